@@ -0,0 +1,71 @@
+pragma circom 2.1.0;
+
+include "./node_modules/circomlib/circuits/poseidon.circom";
+include "./lib/range_proof.circom";
+
+// ============================================================================
+// WHISTLE PROTOCOL - NOTE AMOUNT REVEAL CIRCUIT
+// ============================================================================
+//
+// Selective disclosure: prove the amount held in a specific note without
+// spending it and without revealing the secret or nullifier.
+//
+// Example flow:
+// 1. Exchange holds a note worth 5.7 SOL
+// 2. Regulator asks for proof that the note holds >= 5 SOL
+// 3. Exchange proves commitment = H(secret, H(nullifier, 5.7 SOL))
+// 4. Pool emits NoteAmountRevealed addressed to the auditor the proof names
+//
+// Security properties:
+// - Commitment opening proven in ZK (secret/nullifier stay private)
+// - Amount is range-checked to u64
+// - Auditor is a public input, so a disclosure cannot be re-addressed
+// - Nullifier hash is NOT revealed, so the reveal cannot be linked to
+//   a later withdrawal
+//
+// ============================================================================
+
+template AmountReveal() {
+    // ========================================
+    // PUBLIC INPUTS
+    // ========================================
+    signal input commitment;           // Note commitment being disclosed
+    signal input amount;               // Disclosed note amount
+    signal input auditor;              // Auditor's pubkey (first 31 bytes)
+
+    // ========================================
+    // PRIVATE INPUTS
+    // ========================================
+    signal input secret;               // Note secret
+    signal input nullifier;            // Note nullifier
+
+    // ========================================
+    // CONSTRAINT 1: Range check amount
+    // ========================================
+    component amountRange = RangeProof(64);
+    amountRange.in <== amount;
+
+    // ========================================
+    // CONSTRAINT 2: Open the commitment
+    // commitment = Poseidon(secret, Poseidon(nullifier, amount))
+    // ========================================
+    component innerHash = Poseidon(2);
+    innerHash.inputs[0] <== nullifier;
+    innerHash.inputs[1] <== amount;
+
+    component outerHash = Poseidon(2);
+    outerHash.inputs[0] <== secret;
+    outerHash.inputs[1] <== innerHash.out;
+
+    commitment === outerHash.out;
+
+    // Bind the auditor to prevent malleability
+    signal auditorSquare;
+    auditorSquare <== auditor * auditor;
+}
+
+// ============================================================================
+// MAIN COMPONENT
+// ============================================================================
+
+component main {public [commitment, amount, auditor]} = AmountReveal();
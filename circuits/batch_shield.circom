@@ -0,0 +1,71 @@
+pragma circom 2.1.0;
+
+include "./lib/range_proof.circom";
+include "./lib/note_commitment.circom";
+
+// ============================================================================
+// WHISTLE PROTOCOL - BATCH SHIELD CIRCUIT
+// ============================================================================
+//
+// Split one deposit into N notes with a single proof. A plain shield trusts
+// the depositor's commitment; here the program learns that the N notes
+// together hold exactly the deposited amount, without learning the split.
+//
+// This circuit proves, for every note i:
+// 1. commitments[i] = NoteCommitment(secrets[i], nullifiers[i], amounts[i], 0)
+// 2. amounts[i] fits in 64 bits
+//
+// and that sum(amounts) == totalAmount. N amounts below 2^64 sum to far
+// less than the field modulus, so the sum cannot wrap.
+//
+// Only standard notes are created (unlockSlot is fixed at 0).
+//
+// ============================================================================
+
+template BatchShield(n) {
+    // ========================================
+    // PUBLIC INPUTS
+    // ========================================
+    signal input commitments[n];      // Leaves inserted by shield_batch_zk
+    signal input totalAmount;         // Amount the depositor pays in
+
+    // ========================================
+    // PRIVATE INPUTS
+    // ========================================
+    signal input secrets[n];
+    signal input nullifiers[n];
+    signal input amounts[n];
+
+    component noteCommitments[n];
+    component amountRanges[n];
+    signal partialSums[n + 1];
+    partialSums[0] <== 0;
+
+    for (var i = 0; i < n; i++) {
+        noteCommitments[i] = NoteCommitment();
+        noteCommitments[i].secret <== secrets[i];
+        noteCommitments[i].nullifier <== nullifiers[i];
+        noteCommitments[i].amount <== amounts[i];
+        noteCommitments[i].unlockSlot <== 0;
+        commitments[i] === noteCommitments[i].out;
+
+        amountRanges[i] = RangeProof(64);
+        amountRanges[i].in <== amounts[i];
+
+        partialSums[i + 1] <== partialSums[i] + amounts[i];
+    }
+
+    component totalRange = RangeProof(64);
+    totalRange.in <== totalAmount;
+    totalAmount === partialSums[n];
+}
+
+// ============================================================================
+// MAIN COMPONENT
+// ============================================================================
+//
+// Eight notes per proof.
+// Public inputs: [commitments[8], totalAmount]
+// ============================================================================
+
+component main {public [commitments, totalAmount]} = BatchShield(8);
@@ -0,0 +1,111 @@
+pragma circom 2.1.0;
+
+include "./node_modules/circomlib/circuits/poseidon.circom";
+include "./lib/poseidon_merkle.circom";
+include "./lib/range_proof.circom";
+include "./lib/note_commitment.circom";
+
+// ============================================================================
+// WHISTLE PROTOCOL - BATCH WITHDRAWAL CIRCUIT
+// ============================================================================
+//
+// Withdraw N notes to one recipient with a single proof. Each note is
+// checked exactly as withdraw_merkle checks one, against a common root.
+// On-chain this is one pairing check instead of N.
+//
+// This circuit proves, for every note i:
+// 1. Knowledge of (secret, nullifier, amount) for a valid note
+// 2. The note commitment exists in the Merkle tree at merkleRoot
+// 3. nullifierHashes[i] = Poseidon(nullifier, 0)
+// 4. The note holds at least amounts[i]
+//
+// and that relayerFee fits in 64 bits and is bound to the recipient. The
+// fee comes out of the total; the program caps it and checks that the
+// nullifier hashes are distinct.
+//
+// Only standard notes can be batched (unlockSlot is fixed at 0): a
+// time-locked note goes through withdraw_merkle or unshield_change.
+//
+// ============================================================================
+
+template BatchWithdraw(levels, n) {
+    // ========================================
+    // PUBLIC INPUTS
+    // ========================================
+    signal input merkleRoot;          // Root every note is proven against
+    signal input nullifierHashes[n];  // H(nullifier, 0) of each note
+    signal input amounts[n];          // Withdrawal amount of each note
+    signal input recipient;           // Withdrawal destination (truncated to 31 bytes)
+    signal input relayerFee;          // Fee for relayer, taken from the total
+
+    // ========================================
+    // PRIVATE INPUTS
+    // ========================================
+    signal input secrets[n];
+    signal input nullifiers[n];
+    signal input noteAmounts[n];
+    signal input pathElements[n][levels];
+    signal input pathIndices[n][levels];
+
+    component commitments[n];
+    component merkleVerifiers[n];
+    component nullifierHashers[n];
+    component noteAmountRanges[n];
+    component amountRanges[n];
+    component valueChecks[n];
+
+    for (var i = 0; i < n; i++) {
+        // Note commitment (standard notes only)
+        commitments[i] = NoteCommitment();
+        commitments[i].secret <== secrets[i];
+        commitments[i].nullifier <== nullifiers[i];
+        commitments[i].amount <== noteAmounts[i];
+        commitments[i].unlockSlot <== 0;
+
+        // Merkle membership against the common root
+        merkleVerifiers[i] = MerkleProofVerifier(levels);
+        merkleVerifiers[i].leaf <== commitments[i].out;
+        for (var j = 0; j < levels; j++) {
+            merkleVerifiers[i].pathElements[j] <== pathElements[i][j];
+            merkleVerifiers[i].pathIndices[j] <== pathIndices[i][j];
+        }
+        merkleRoot === merkleVerifiers[i].root;
+
+        // Nullifier hash
+        nullifierHashers[i] = Poseidon(2);
+        nullifierHashers[i].inputs[0] <== nullifiers[i];
+        nullifierHashers[i].inputs[1] <== 0;
+        nullifierHashes[i] === nullifierHashers[i].out;
+
+        // noteAmount >= amount, both u64
+        noteAmountRanges[i] = RangeProof(64);
+        noteAmountRanges[i].in <== noteAmounts[i];
+        amountRanges[i] = RangeProof(64);
+        amountRanges[i].in <== amounts[i];
+        valueChecks[i] = AssertGreaterEqThan(64);
+        valueChecks[i].a <== noteAmounts[i];
+        valueChecks[i].b <== amounts[i];
+    }
+
+    component feeRange = RangeProof(64);
+    feeRange.in <== relayerFee;
+
+    // ========================================
+    // Bind recipient and fee to the proof
+    // Prevents front-running attacks
+    // ========================================
+    signal recipientSquare;
+    recipientSquare <== recipient * recipient;
+    signal feeSquare;
+    feeSquare <== relayerFee * relayerFee;
+}
+
+// ============================================================================
+// MAIN COMPONENT
+// ============================================================================
+//
+// Four notes per proof; 7 levels to match the on-chain devnet tree.
+// Public inputs: [merkleRoot, nullifierHashes[4], amounts[4], recipient, relayerFee]
+// ============================================================================
+
+component main {public [merkleRoot, nullifierHashes, amounts, recipient, relayerFee]} = BatchWithdraw(7, 4);
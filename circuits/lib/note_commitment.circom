@@ -0,0 +1,45 @@
+pragma circom 2.1.0;
+
+include "../node_modules/circomlib/circuits/poseidon.circom";
+include "../node_modules/circomlib/circuits/comparators.circom";
+
+// ============================================================================
+// WHISTLE PROTOCOL - NOTE COMMITMENT
+// ============================================================================
+// Standard notes:     Poseidon(secret, Poseidon(nullifier, amount))
+// Time-locked notes:  Poseidon(secret, Poseidon(nullifier, amount), unlockSlot)
+//
+// unlockSlot is a public input of every spend circuit, and the program
+// rejects spends before that slot. Zero selects the standard form, so
+// notes created before time locks keep their commitments.
+// ============================================================================
+
+/**
+ * Compute a note commitment, binding the unlock slot when it is non-zero
+ */
+template NoteCommitment() {
+    signal input secret;
+    signal input nullifier;
+    signal input amount;
+    signal input unlockSlot;
+    signal output out;
+
+    component inner = Poseidon(2);
+    inner.inputs[0] <== nullifier;
+    inner.inputs[1] <== amount;
+
+    component standard = Poseidon(2);
+    standard.inputs[0] <== secret;
+    standard.inputs[1] <== inner.out;
+
+    component locked = Poseidon(3);
+    locked.inputs[0] <== secret;
+    locked.inputs[1] <== inner.out;
+    locked.inputs[2] <== unlockSlot;
+
+    component isStandard = IsZero();
+    isStandard.in <== unlockSlot;
+
+    // out = isStandard ? standard : locked
+    out <== locked.out + isStandard.out * (standard.out - locked.out);
+}
@@ -5,6 +5,7 @@ include "./node_modules/circomlib/circuits/bitify.circom";
 include "./node_modules/circomlib/circuits/comparators.circom";
 include "./lib/poseidon_merkle.circom";
 include "./lib/range_proof.circom";
+include "./lib/note_commitment.circom";
 
 // ============================================================================
 // WHISTLE PROTOCOL - PRIVATE TRANSFER CIRCUIT (2-in-2-out)
@@ -36,6 +37,7 @@ template PrivateTransfer(levels) {
     signal input merkleRoot;                    // Current Merkle tree root
     signal input inputNullifierHashes[2];       // Nullifier hashes for input notes
     signal input outputCommitments[2];          // Commitments for output notes
+    signal input unlockSlots[2];                // Input note unlock slots (0 if not time-locked)
 
     // ========================================
     // PRIVATE INPUTS - Input Note 1
@@ -74,23 +76,19 @@ template PrivateTransfer(levels) {
     // commitment = Poseidon(secret, Poseidon(nullifier, amount))
     // ========================================
     
-    // Input commitment 1
-    component inInner1 = Poseidon(2);
-    inInner1.inputs[0] <== inNullifier1;
-    inInner1.inputs[1] <== inAmount1;
-    
-    component inOuter1 = Poseidon(2);
-    inOuter1.inputs[0] <== inSecret1;
-    inOuter1.inputs[1] <== inInner1.out;
+    // Input commitment 1 (time-locked inputs also bind their unlock slot)
+    component inOuter1 = NoteCommitment();
+    inOuter1.secret <== inSecret1;
+    inOuter1.nullifier <== inNullifier1;
+    inOuter1.amount <== inAmount1;
+    inOuter1.unlockSlot <== unlockSlots[0];
 
     // Input commitment 2
-    component inInner2 = Poseidon(2);
-    inInner2.inputs[0] <== inNullifier2;
-    inInner2.inputs[1] <== inAmount2;
-    
-    component inOuter2 = Poseidon(2);
-    inOuter2.inputs[0] <== inSecret2;
-    inOuter2.inputs[1] <== inInner2.out;
+    component inOuter2 = NoteCommitment();
+    inOuter2.secret <== inSecret2;
+    inOuter2.nullifier <== inNullifier2;
+    inOuter2.amount <== inAmount2;
+    inOuter2.unlockSlot <== unlockSlots[1];
 
     // Output commitment 1
     component outInner1 = Poseidon(2);
@@ -255,4 +253,4 @@ template PrivateTransfer(levels) {
 // Use 20 levels for mainnet
 // ============================================================================
 
-component main {public [merkleRoot, inputNullifierHashes, outputCommitments]} = PrivateTransfer(7);
+component main {public [merkleRoot, inputNullifierHashes, outputCommitments, unlockSlots]} = PrivateTransfer(7);
@@ -14,8 +14,11 @@
  *   node scripts/compile-production.js [circuit-name]
  * 
  * Examples:
- *   node scripts/compile-production.js              # Compile all circuits
+ *   node scripts/compile-production.js              # Compile the default circuits
  *   node scripts/compile-production.js withdraw_merkle  # Compile specific circuit
+ *
+ * Circuits whose instruction is behind a program feature (`feature` below)
+ * are left out of the default run and only compiled by name.
  */
 
 const { execSync } = require('child_process');
@@ -44,6 +47,32 @@ const PRODUCTION_CIRCUITS = [
         file: 'private_transfer.circom',
         description: 'Shielded balance transfers (2-in-2-out)',
         estimatedConstraints: '~60,000-70,000'
+    },
+    {
+        name: 'amount_reveal',
+        file: 'amount_reveal.circom',
+        description: 'Selective disclosure of a note amount to an auditor',
+        estimatedConstraints: '~500'
+    },
+    {
+        name: 'batch_withdraw',
+        file: 'batch_withdraw.circom',
+        description: 'Four withdrawals to one recipient in one proof',
+        estimatedConstraints: '~100,000-110,000',
+        feature: 'batch-withdraw-zk'
+    },
+    {
+        name: 'batch_shield',
+        file: 'batch_shield.circom',
+        description: 'Eight notes from one deposit in one proof',
+        estimatedConstraints: '~3,000',
+        feature: 'batch-shield-zk'
+    },
+    {
+        name: 'unshield_token',
+        file: 'unshield_token.circom',
+        description: 'SPL token withdrawal with change, bound to the mint',
+        estimatedConstraints: '~35,000'
     }
 ];
 
@@ -133,7 +162,7 @@ function main() {
     const specificCircuit = process.argv[2];
     
     // Filter circuits if specific one requested
-    let circuitsToCompile = PRODUCTION_CIRCUITS;
+    let circuitsToCompile = PRODUCTION_CIRCUITS.filter(c => !c.feature);
     if (specificCircuit) {
         circuitsToCompile = PRODUCTION_CIRCUITS.filter(c => c.name === specificCircuit);
         if (circuitsToCompile.length === 0) {
@@ -19,7 +19,10 @@ const path = require('path');
 
 const BUILD_DIR = path.join(__dirname, '..', 'build', 'production');
 
-const CIRCUITS = ['withdraw_merkle', 'unshield_change', 'private_transfer'];
+const CIRCUITS = ['withdraw_merkle', 'unshield_change', 'private_transfer', 'amount_reveal', 'unshield_token'];
+
+// Circuits whose instruction is behind a program feature; converted by name only
+const FEATURE_CIRCUITS = ['batch_withdraw', 'batch_shield'];
 
 /**
  * Convert decimal string to big-endian bytes
@@ -237,9 +240,9 @@ function main() {
     
     let circuitsToConvert = CIRCUITS;
     if (specificCircuit) {
-        if (!CIRCUITS.includes(specificCircuit)) {
+        if (!CIRCUITS.includes(specificCircuit) && !FEATURE_CIRCUITS.includes(specificCircuit)) {
             console.error(`\n❌ Unknown circuit: ${specificCircuit}`);
-            console.log('Available circuits:', CIRCUITS.join(', '));
+            console.log('Available circuits:', [...CIRCUITS, ...FEATURE_CIRCUITS].join(', '));
             process.exit(1);
         }
         circuitsToConvert = [specificCircuit];
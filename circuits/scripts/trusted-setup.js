@@ -38,7 +38,15 @@ const PTAU_URL = `https://hermez.s3-eu-west-1.amazonaws.com/${PTAU_FILE}`;
 const PRODUCTION_CIRCUITS = [
     'withdraw_merkle',
     'unshield_change', 
-    'private_transfer'
+    'private_transfer',
+    'amount_reveal',
+    'unshield_token'
+];
+
+// Circuits whose instruction is behind a program feature; set up by name only
+const FEATURE_CIRCUITS = [
+    'batch_withdraw',
+    'batch_shield'
 ];
 
 function ensureDir(dir) {
@@ -201,9 +209,9 @@ async function main() {
     
     let circuitsToSetup = PRODUCTION_CIRCUITS;
     if (specificCircuit) {
-        if (!PRODUCTION_CIRCUITS.includes(specificCircuit)) {
+        if (!PRODUCTION_CIRCUITS.includes(specificCircuit) && !FEATURE_CIRCUITS.includes(specificCircuit)) {
             console.error(`\n❌ Unknown circuit: ${specificCircuit}`);
-            console.log('Available circuits:', PRODUCTION_CIRCUITS.join(', '));
+            console.log('Available circuits:', [...PRODUCTION_CIRCUITS, ...FEATURE_CIRCUITS].join(', '));
             process.exit(1);
         }
         circuitsToSetup = [specificCircuit];
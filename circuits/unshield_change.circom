@@ -5,6 +5,7 @@ include "./node_modules/circomlib/circuits/bitify.circom";
 include "./node_modules/circomlib/circuits/comparators.circom";
 include "./lib/poseidon_merkle.circom";
 include "./lib/range_proof.circom";
+include "./lib/note_commitment.circom";
 
 // ============================================================================
 // WHISTLE PROTOCOL - UNSHIELD WITH CHANGE CIRCUIT
@@ -40,6 +41,7 @@ template UnshieldChange(levels) {
     signal input withdrawalAmount;     // Fixed denomination being withdrawn
     signal input relayerFee;           // Fee for relayer
     signal input changeCommitment;     // New note commitment for change (0 if no change)
+    signal input unlockSlot;           // Slot the input note unlocks at (0 if not time-locked)
 
     // ========================================
     // PRIVATE INPUTS - Input Note
@@ -60,13 +62,11 @@ template UnshieldChange(levels) {
     // ========================================
     // CONSTRAINT 1: Compute input commitment
     // ========================================
-    component innerHash = Poseidon(2);
-    innerHash.inputs[0] <== nullifier;
-    innerHash.inputs[1] <== noteAmount;
-
-    component inputCommitment = Poseidon(2);
-    inputCommitment.inputs[0] <== secret;
-    inputCommitment.inputs[1] <== innerHash.out;
+    component inputCommitment = NoteCommitment();
+    inputCommitment.secret <== secret;
+    inputCommitment.nullifier <== nullifier;
+    inputCommitment.amount <== noteAmount;
+    inputCommitment.unlockSlot <== unlockSlot;
 
     // ========================================
     // CONSTRAINT 2: Verify Merkle membership
@@ -168,4 +168,4 @@ template UnshieldChange(levels) {
 // Use 20 levels for mainnet (1M deposits)
 // ============================================================================
 
-component main {public [merkleRoot, nullifierHash, recipient, withdrawalAmount, relayerFee, changeCommitment]} = UnshieldChange(7);
+component main {public [merkleRoot, nullifierHash, recipient, withdrawalAmount, relayerFee, changeCommitment, unlockSlot]} = UnshieldChange(7);
@@ -0,0 +1,108 @@
+pragma circom 2.1.0;
+
+include "./node_modules/circomlib/circuits/poseidon.circom";
+include "./node_modules/circomlib/circuits/comparators.circom";
+include "./lib/poseidon_merkle.circom";
+include "./lib/range_proof.circom";
+
+// ============================================================================
+// WHISTLE PROTOCOL - UNSHIELD TOKEN CIRCUIT
+// ============================================================================
+//
+// unshield_change for notes holding an SPL token. A token note commits to
+// its mint:
+//
+//   commitment = Poseidon(secret, nullifier, amount, mint)
+//
+// where mint is the mint's pubkey as a field element (its first 31 bytes,
+// as pubkey_to_field encodes it on-chain). The mint is a public input, so
+// the program only pays a note out of the vault of the mint it was shielded
+// for. SOL notes hash two inputs at the top level, so a SOL note cannot be
+// opened as a token note or the reverse.
+//
+// This circuit proves:
+// 1. The input note, with `mint`, is a leaf under merkleRoot
+// 2. nullifierHash = Poseidon(nullifier, 0)
+// 3. noteAmount = amount + changeAmount, all within 64 bits
+// 4. changeCommitment is a note of the same mint holding changeAmount, or
+//    0 when there is no change
+//
+// ============================================================================
+
+template UnshieldToken(levels) {
+    // ========================================
+    // PUBLIC INPUTS
+    // ========================================
+    signal input merkleRoot;           // Current Merkle tree root
+    signal input nullifierHash;        // Poseidon(nullifier, 0) for input note
+    signal input recipient;            // Owner of the receiving token account
+    signal input amount;               // Token amount withdrawn (base units)
+    signal input mint;                 // Mint of the input and change notes
+    signal input changeCommitment;     // Change note commitment (0 if no change)
+
+    // ========================================
+    // PRIVATE INPUTS
+    // ========================================
+    signal input secret;
+    signal input nullifier;
+    signal input noteAmount;
+    signal input pathElements[levels];
+    signal input pathIndices[levels];
+    signal input changeSecret;
+    signal input changeNullifier;
+    signal input changeAmount;
+
+    // 1. Membership of the input note
+    component inputCommitment = Poseidon(4);
+    inputCommitment.inputs[0] <== secret;
+    inputCommitment.inputs[1] <== nullifier;
+    inputCommitment.inputs[2] <== noteAmount;
+    inputCommitment.inputs[3] <== mint;
+
+    component merkleVerifier = MerkleProofVerifier(levels);
+    merkleVerifier.leaf <== inputCommitment.out;
+    for (var i = 0; i < levels; i++) {
+        merkleVerifier.pathElements[i] <== pathElements[i];
+        merkleVerifier.pathIndices[i] <== pathIndices[i];
+    }
+    merkleRoot === merkleVerifier.root;
+
+    // 2. Nullifier hash
+    component nullifierHasher = Poseidon(2);
+    nullifierHasher.inputs[0] <== nullifier;
+    nullifierHasher.inputs[1] <== 0;
+    nullifierHash === nullifierHasher.out;
+
+    // 3. Value conservation
+    component noteAmountRange = RangeProof(64);
+    noteAmountRange.in <== noteAmount;
+    component amountRange = RangeProof(64);
+    amountRange.in <== amount;
+    component changeAmountRange = RangeProof(64);
+    changeAmountRange.in <== changeAmount;
+    noteAmount === amount + changeAmount;
+
+    // 4. Change note of the same mint, or 0
+    component changeNote = Poseidon(4);
+    changeNote.inputs[0] <== changeSecret;
+    changeNote.inputs[1] <== changeNullifier;
+    changeNote.inputs[2] <== changeAmount;
+    changeNote.inputs[3] <== mint;
+
+    component isChangeZero = IsZero();
+    isChangeZero.in <== changeAmount;
+    changeCommitment === changeNote.out * (1 - isChangeZero.out);
+
+    // Bind the recipient to the proof
+    signal recipientSquare;
+    recipientSquare <== recipient * recipient;
+}
+
+// ============================================================================
+// MAIN COMPONENT
+// ============================================================================
+//
+// Public inputs: [merkleRoot, nullifierHash, recipient, amount, mint, changeCommitment]
+// ============================================================================
+
+component main {public [merkleRoot, nullifierHash, recipient, amount, mint, changeCommitment]} = UnshieldToken(7);
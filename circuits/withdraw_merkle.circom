@@ -5,6 +5,7 @@ include "./node_modules/circomlib/circuits/bitify.circom";
 include "./node_modules/circomlib/circuits/comparators.circom";
 include "./lib/poseidon_merkle.circom";
 include "./lib/range_proof.circom";
+include "./lib/note_commitment.circom";
 
 // ============================================================================
 // WHISTLE PROTOCOL - PRODUCTION WITHDRAWAL CIRCUIT
@@ -36,6 +37,7 @@ template WithdrawMerkle(levels) {
     signal input recipient;       // Withdrawal destination (truncated to 31 bytes)
     signal input amount;          // Withdrawal amount in lamports
     signal input relayerFee;      // Fee for relayer (can be 0)
+    signal input unlockSlot;      // Slot the note unlocks at (0 if not time-locked)
 
     // ========================================
     // PRIVATE INPUTS
@@ -50,18 +52,13 @@ template WithdrawMerkle(levels) {
 
     // ========================================
     // CONSTRAINT 1: Compute note commitment
-    // commitment = Poseidon(secret, Poseidon(nullifier, noteAmount))
+    // commitment = Poseidon(secret, Poseidon(nullifier, noteAmount)[, unlockSlot])
     // ========================================
-    
-    // Inner hash: H(nullifier, noteAmount)
-    component innerHash = Poseidon(2);
-    innerHash.inputs[0] <== nullifier;
-    innerHash.inputs[1] <== noteAmount;
-
-    // Outer hash: commitment = H(secret, innerHash)
-    component commitmentHash = Poseidon(2);
-    commitmentHash.inputs[0] <== secret;
-    commitmentHash.inputs[1] <== innerHash.out;
+    component commitmentHash = NoteCommitment();
+    commitmentHash.secret <== secret;
+    commitmentHash.nullifier <== nullifier;
+    commitmentHash.amount <== noteAmount;
+    commitmentHash.unlockSlot <== unlockSlot;
     
     signal commitment;
     commitment <== commitmentHash.out;
@@ -156,7 +153,7 @@ template WithdrawMerkle(levels) {
 // ============================================================================
 
 // Production: 20 levels for mainnet
-// component main {public [merkleRoot, nullifierHash, recipient, amount, relayerFee]} = WithdrawMerkle(20);
+// component main {public [merkleRoot, nullifierHash, recipient, amount, relayerFee, unlockSlot]} = WithdrawMerkle(20);
 
 // Devnet: 7 levels for testing (matches on-chain config)
-component main {public [merkleRoot, nullifierHash, recipient, amount, relayerFee]} = WithdrawMerkle(7);
+component main {public [merkleRoot, nullifierHash, recipient, amount, relayerFee, unlockSlot]} = WithdrawMerkle(7);
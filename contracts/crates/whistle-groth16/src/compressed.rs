@@ -0,0 +1,170 @@
+// COMPRESSED POINTS
+//
+// A compressed point is a prefix byte followed by its x-coordinate in the
+// syscall layout:
+// - G1: prefix || x (33 bytes)
+// - G2: prefix || x_im || x_re (65 bytes)
+//
+// The prefix is 0x00 for the point at infinity (x all zeroes), otherwise
+// 0x02 | sgn0(y), with sgn0 as in RFC 9380: the parity of y for G1, and for
+// G2 the parity of y_re, or of y_im when y_re is zero.
+//
+// The decompression syscalls recover one of the two square roots; the
+// prefix picks between it and its negation, so the result does not depend
+// on which root the syscall prefers.
+
+use solana_program::alt_bn128::compression::prelude::{alt_bn128_g1_decompress, alt_bn128_g2_decompress};
+
+use crate::{
+    accumulate_input, check_pairing, field_sub, negate_g1, pairs_input, Groth16Error, Groth16Proof, BN254_BASE_MODULUS,
+    G1_SIZE, G2_SIZE,
+};
+
+pub const G1_COMPRESSED_SIZE: usize = 1 + 32;
+pub const G2_COMPRESSED_SIZE: usize = 1 + 64;
+
+const PREFIX_INFINITY: u8 = 0x00;
+const PREFIX_EVEN: u8 = 0x02;
+const PREFIX_ODD: u8 = 0x03;
+
+/// Groth16 verification key with compressed points, half the size of
+/// VerificationKey; each point is decompressed when the key is used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressedVerificationKey<'a> {
+    pub alpha_g1: [u8; G1_COMPRESSED_SIZE],
+    pub beta_g2: [u8; G2_COMPRESSED_SIZE],
+    pub gamma_g2: [u8; G2_COMPRESSED_SIZE],
+    pub delta_g2: [u8; G2_COMPRESSED_SIZE],
+    pub ic: &'a [[u8; G1_COMPRESSED_SIZE]],
+}
+
+impl Groth16Proof {
+    /// Proof with compressed points, A as the prover output it
+    pub fn from_compressed(
+        a: &[u8; G1_COMPRESSED_SIZE],
+        b: &[u8; G2_COMPRESSED_SIZE],
+        c: &[u8; G1_COMPRESSED_SIZE],
+    ) -> Result<Self, Groth16Error> {
+        Groth16Proof::new(&decompress_g1(a)?, &decompress_g2(b)?, &decompress_g1(c)?)
+    }
+}
+
+/// Verify `proof` against a key stored compressed
+pub fn verify_with_compressed_key(
+    vk: &CompressedVerificationKey,
+    proof: &Groth16Proof,
+    public_inputs: &[[u8; 32]],
+) -> Result<(), Groth16Error> {
+    if vk.ic.len() != public_inputs.len() + 1 {
+        return Err(Groth16Error::InvalidPublicInputsLength);
+    }
+    let mut vk_x = decompress_g1(&vk.ic[0])?;
+    for (input, ic) in public_inputs.iter().zip(&vk.ic[1..]) {
+        vk_x = accumulate_input(&vk_x, &decompress_g1(ic)?, input)?;
+    }
+
+    check_pairing(&pairs_input(&[
+        (&proof.neg_a, &proof.b),
+        (&decompress_g1(&vk.alpha_g1)?, &decompress_g2(&vk.beta_g2)?),
+        (&vk_x, &decompress_g2(&vk.gamma_g2)?),
+        (&proof.c, &decompress_g2(&vk.delta_g2)?),
+    ]))
+}
+
+pub fn compress_g1(point: &[u8; G1_SIZE]) -> [u8; G1_COMPRESSED_SIZE] {
+    let mut compressed = [0u8; G1_COMPRESSED_SIZE];
+    if point.iter().all(|b| *b == 0) {
+        return compressed;
+    }
+    compressed[0] = PREFIX_EVEN | (point[63] & 1);
+    compressed[1..].copy_from_slice(&point[..32]);
+    compressed
+}
+
+pub fn compress_g2(point: &[u8; G2_SIZE]) -> [u8; G2_COMPRESSED_SIZE] {
+    let mut compressed = [0u8; G2_COMPRESSED_SIZE];
+    if point.iter().all(|b| *b == 0) {
+        return compressed;
+    }
+    compressed[0] = PREFIX_EVEN | g2_sgn0(point);
+    compressed[1..].copy_from_slice(&point[..64]);
+    compressed
+}
+
+/// Fails with PointDecompressionFailed if the prefix is unknown, x is not
+/// below p, or x is not the x-coordinate of a curve point
+pub fn decompress_g1(compressed: &[u8; G1_COMPRESSED_SIZE]) -> Result<[u8; G1_SIZE], Groth16Error> {
+    let x = &compressed[1..];
+    if !check_prefix(compressed[0], x)? {
+        return Ok([0u8; G1_SIZE]);
+    }
+    if !below_modulus(x) {
+        return Err(Groth16Error::PointDecompressionFailed);
+    }
+
+    let point = alt_bn128_g1_decompress(x).map_err(|_| Groth16Error::PointDecompressionFailed)?;
+    if point[63] & 1 == compressed[0] & 1 {
+        Ok(point)
+    } else {
+        negate_g1(&point)
+    }
+}
+
+/// Fails with PointDecompressionFailed if the prefix is unknown, either
+/// coordinate of x is not below p, or x is not the x-coordinate of a point
+/// on the twist (subgroup membership is left to the pairing syscall)
+pub fn decompress_g2(compressed: &[u8; G2_COMPRESSED_SIZE]) -> Result<[u8; G2_SIZE], Groth16Error> {
+    let x = &compressed[1..];
+    if !check_prefix(compressed[0], x)? {
+        return Ok([0u8; G2_SIZE]);
+    }
+    if !below_modulus(&x[..32]) || !below_modulus(&x[32..]) {
+        return Err(Groth16Error::PointDecompressionFailed);
+    }
+
+    let point = alt_bn128_g2_decompress(x).map_err(|_| Groth16Error::PointDecompressionFailed)?;
+    if g2_sgn0(&point) == compressed[0] & 1 {
+        Ok(point)
+    } else {
+        negate_g2(&point)
+    }
+}
+
+/// -Q = (x, -y), negating both coordinates of y
+pub fn negate_g2(point: &[u8; G2_SIZE]) -> Result<[u8; G2_SIZE], Groth16Error> {
+    let mut result = *point;
+    for coordinate in result[64..].chunks_exact_mut(32) {
+        let mut y = [0u8; 32];
+        y.copy_from_slice(coordinate);
+        if y != [0u8; 32] {
+            coordinate.copy_from_slice(&field_sub(&BN254_BASE_MODULUS, &y)?);
+        }
+    }
+    Ok(result)
+}
+
+/// Whether the point is finite, checking the prefix against x: the
+/// syscalls read an all-zero x as infinity, so a finite point may not have
+/// one (no curve point of either group has x = 0 anyway)
+fn check_prefix(prefix: u8, x: &[u8]) -> Result<bool, Groth16Error> {
+    let zero = x.iter().all(|b| *b == 0);
+    match prefix {
+        PREFIX_INFINITY if zero => Ok(false),
+        PREFIX_EVEN | PREFIX_ODD if !zero => Ok(true),
+        _ => Err(Groth16Error::PointDecompressionFailed),
+    }
+}
+
+fn below_modulus(value: &[u8]) -> bool {
+    value < BN254_BASE_MODULUS.as_slice()
+}
+
+/// sgn0 of y = y_im * u + y_re (layout x_im || x_re || y_im || y_re)
+fn g2_sgn0(point: &[u8; G2_SIZE]) -> u8 {
+    let (y_im, y_re) = (&point[64..96], &point[96..]);
+    if y_re.iter().all(|b| *b == 0) {
+        y_im[31] & 1
+    } else {
+        y_re[31] & 1
+    }
+}
@@ -0,0 +1,86 @@
+// ARKWORKS CONVERSIONS (feature "host")
+//
+// Converts arkworks keys and proofs to the syscall byte layout, so tests and
+// tooling can produce fixtures for `verify` without going through snarkjs.
+
+use alloc::vec::Vec;
+
+use ark_bn254::{Bn254, Fq, Fr, G1Affine, G2Affine};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::{Proof, VerifyingKey};
+
+use crate::{Groth16Proof, VerificationKey, G1_SIZE, G2_SIZE};
+
+fn fq_to_be(value: &Fq) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&value.into_bigint().to_bytes_be());
+    bytes
+}
+
+/// Scalar field element as a big-endian public input
+pub fn fr_to_be(value: &Fr) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&value.into_bigint().to_bytes_be());
+    bytes
+}
+
+pub fn g1_to_bytes(point: &G1Affine) -> [u8; G1_SIZE] {
+    let mut bytes = [0u8; G1_SIZE];
+    if !point.infinity {
+        bytes[..32].copy_from_slice(&fq_to_be(&point.x));
+        bytes[32..].copy_from_slice(&fq_to_be(&point.y));
+    }
+    bytes
+}
+
+/// G2 point with each coordinate as (imaginary, real), as the syscalls take it
+pub fn g2_to_bytes(point: &G2Affine) -> [u8; G2_SIZE] {
+    let mut bytes = [0u8; G2_SIZE];
+    if !point.infinity {
+        for (chunk, coordinate) in bytes.chunks_exact_mut(32).zip([point.x.c1, point.x.c0, point.y.c1, point.y.c0]) {
+            chunk.copy_from_slice(&fq_to_be(&coordinate));
+        }
+    }
+    bytes
+}
+
+/// Verification key that owns its IC points
+pub struct OwnedVerificationKey {
+    pub alpha_g1: [u8; G1_SIZE],
+    pub beta_g2: [u8; G2_SIZE],
+    pub gamma_g2: [u8; G2_SIZE],
+    pub delta_g2: [u8; G2_SIZE],
+    pub ic: Vec<[u8; G1_SIZE]>,
+}
+
+impl OwnedVerificationKey {
+    pub fn key(&self) -> VerificationKey<'_> {
+        VerificationKey {
+            alpha_g1: self.alpha_g1,
+            beta_g2: self.beta_g2,
+            gamma_g2: self.gamma_g2,
+            delta_g2: self.delta_g2,
+            ic: &self.ic,
+        }
+    }
+}
+
+impl From<&VerifyingKey<Bn254>> for OwnedVerificationKey {
+    fn from(vk: &VerifyingKey<Bn254>) -> Self {
+        OwnedVerificationKey {
+            alpha_g1: g1_to_bytes(&vk.alpha_g1),
+            beta_g2: g2_to_bytes(&vk.beta_g2),
+            gamma_g2: g2_to_bytes(&vk.gamma_g2),
+            delta_g2: g2_to_bytes(&vk.delta_g2),
+            ic: vk.gamma_abc_g1.iter().map(g1_to_bytes).collect(),
+        }
+    }
+}
+
+impl From<&Proof<Bn254>> for Groth16Proof {
+    fn from(proof: &Proof<Bn254>) -> Self {
+        // arkworks coordinates are always reduced, so negating A cannot borrow
+        Groth16Proof::new(&g1_to_bytes(&proof.a), &g2_to_bytes(&proof.b), &g1_to_bytes(&proof.c))
+            .expect("arkworks G1 coordinates are below the field modulus")
+    }
+}
@@ -0,0 +1,88 @@
+// PUBLIC INPUT ENCODING
+//
+// Every field element the programs see (commitments, nullifier hashes, roots,
+// Groth16 public inputs) is 32 bytes big-endian, which is what the Poseidon
+// and alt_bn128 syscalls expect. snarkjs prints field elements as decimal
+// strings and circom witness dumps are little-endian, so these conversions
+// must be explicit: a byte-reversed nullifier hash still "verifies" nothing.
+//
+// sdk/src/publicInputs.ts mirrors these encoders for clients.
+
+/// BN254 scalar field modulus r, big-endian
+pub const BN254_SCALAR_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29,
+    0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91,
+    0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+/// Little-endian field element bytes to big-endian
+pub fn field_le_to_be(le: &[u8; 32]) -> [u8; 32] {
+    let mut be = *le;
+    be.reverse();
+    be
+}
+
+/// Big-endian field element bytes to little-endian
+pub fn field_be_to_le(be: &[u8; 32]) -> [u8; 32] {
+    let mut le = *be;
+    le.reverse();
+    le
+}
+
+/// Parse a snarkjs decimal string into big-endian bytes
+/// Returns None for empty or non-decimal input, or values that overflow 256 bits
+pub fn decimal_str_to_be_bytes(decimal: &str) -> Option<[u8; 32]> {
+    if decimal.is_empty() {
+        return None;
+    }
+
+    let mut be = [0u8; 32];
+    for c in decimal.bytes() {
+        let mut carry = match c {
+            b'0'..=b'9' => (c - b'0') as u16,
+            _ => return None,
+        };
+        for byte in be.iter_mut().rev() {
+            let value = *byte as u16 * 10 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some(be)
+}
+
+/// Encode a u64 public input as a big-endian field element
+pub fn u64_to_be_field(value: u64) -> [u8; 32] {
+    let mut field = [0u8; 32];
+    field[24..].copy_from_slice(&value.to_be_bytes());
+    field
+}
+
+/// Decode a big-endian field element as a u64 public input
+///
+/// None unless the high 24 bytes are zero, so a value at or past 2^64 is
+/// never silently truncated.
+pub fn be_field_to_u64(field: &[u8; 32]) -> Option<u64> {
+    if field[..24].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(field[24..].try_into().unwrap()))
+}
+
+/// Whether big-endian `value` is below the field modulus
+pub fn is_canonical_field_element(value: &[u8; 32]) -> bool {
+    *value < BN254_SCALAR_MODULUS
+}
+
+/// Encode a 32-byte account key as the circuits' recipient field element
+///
+/// The first 31 bytes, right-aligned, so the value always fits the field.
+pub fn pubkey_to_field(key: &[u8; 32]) -> [u8; 32] {
+    let mut field = [0u8; 32];
+    field[1..].copy_from_slice(&key[..31]);
+    field
+}
@@ -0,0 +1,323 @@
+// WHISTLE PROTOCOL - SHARED GROTH16 VERIFICATION
+//
+// The one implementation of Groth16 verification used by whistle-pool and
+// whistle-verifier. Everything runs on the alt_bn128 syscalls (which fall
+// back to arkworks off-chain), so the programs only supply verification keys
+// and public inputs.
+//
+// Byte layout (what the syscalls take):
+// - G1: x || y, 32 bytes each, big-endian
+// - G2: x_im || x_re || y_im || y_re, 32 bytes each, big-endian
+// - Scalars / public inputs: 32 bytes big-endian, below the field modulus
+//
+// Pairing check: e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta) = 1
+//
+// Proofs and keys can also arrive compressed (see compressed.rs).
+
+#![no_std]
+
+#[cfg(feature = "host")]
+extern crate alloc;
+
+pub mod compressed;
+#[cfg(feature = "host")]
+pub mod host;
+pub mod inputs;
+
+use solana_program::alt_bn128::prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing};
+
+use inputs::is_canonical_field_element;
+
+pub const G1_SIZE: usize = 64;
+pub const G2_SIZE: usize = 128;
+
+/// Size of one (G1, G2) pair in the pairing syscall input
+pub const PAIR_SIZE: usize = G1_SIZE + G2_SIZE;
+
+/// Size of the four-pair Groth16 pairing input
+pub const PAIRING_INPUT_SIZE: usize = 4 * PAIR_SIZE;
+
+/// BN254 base field modulus p, big-endian
+pub const BN254_BASE_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29,
+    0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d,
+    0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
+
+/// BN254 G1 generator (1, 2)
+pub const G1_GENERATOR: [u8; G1_SIZE] = {
+    let mut point = [0u8; G1_SIZE];
+    point[31] = 1;
+    point[63] = 2;
+    point
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Groth16Error {
+    /// The key's IC length is not the number of public inputs + 1
+    InvalidPublicInputsLength,
+    /// A public input is not below the scalar field modulus
+    NonCanonicalPublicInput,
+    /// Scalar multiplication while preparing inputs failed
+    G1MulFailed,
+    /// Point addition while preparing inputs failed
+    G1AdditionFailed,
+    /// The pairing syscall rejected its input (points off the curve)
+    PairingFailed,
+    /// The pairing equation does not hold
+    ProofVerificationFailed,
+    /// A field subtraction went below zero (a G1 y-coordinate above the
+    /// base field modulus)
+    FieldSubtractBorrow,
+    /// A compressed point has an unknown prefix, a coordinate not below the
+    /// base field modulus, or no point on the curve at its x-coordinate
+    PointDecompressionFailed,
+}
+
+/// Groth16 verification key from a circuit's trusted setup
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerificationKey<'a> {
+    pub alpha_g1: [u8; G1_SIZE],
+    pub beta_g2: [u8; G2_SIZE],
+    pub gamma_g2: [u8; G2_SIZE],
+    pub delta_g2: [u8; G2_SIZE],
+    /// IC[0] + sum(public_input[i] * IC[i + 1])
+    pub ic: &'a [[u8; G1_SIZE]],
+}
+
+impl<'a> VerificationKey<'a> {
+    /// Number of public inputs the circuit takes
+    pub const fn num_public_inputs(&self) -> usize {
+        self.ic.len().saturating_sub(1)
+    }
+
+    /// Lay out the key's constant pairing input once; call it in a const so
+    /// it happens at compile time
+    pub const fn prepare(&self) -> PreparedVerificationKey<'a> {
+        let mut pairing_template = [0u8; PAIRING_INPUT_SIZE];
+        let mut i = 0;
+        while i < G1_SIZE {
+            pairing_template[PAIR_SIZE + i] = self.alpha_g1[i];
+            i += 1;
+        }
+        let mut i = 0;
+        while i < G2_SIZE {
+            pairing_template[PAIR_SIZE + G1_SIZE + i] = self.beta_g2[i];
+            pairing_template[2 * PAIR_SIZE + G1_SIZE + i] = self.gamma_g2[i];
+            pairing_template[3 * PAIR_SIZE + G1_SIZE + i] = self.delta_g2[i];
+            i += 1;
+        }
+        PreparedVerificationKey { key: *self, pairing_template }
+    }
+}
+
+/// Verification key with the pairing input's constant part laid out
+///
+/// The (alpha, beta) pair, gamma and delta sit where the pairing check
+/// reads them, so verifying a proof copies the template once and writes
+/// only -A, B, vk_x and C into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreparedVerificationKey<'a> {
+    pub key: VerificationKey<'a>,
+    /// Pairing input with the proof and vk_x slots left zero
+    pub pairing_template: [u8; PAIRING_INPUT_SIZE],
+}
+
+impl PreparedVerificationKey<'_> {
+    /// Pairing input for `proof` and prepared inputs `vk_x`; the same bytes
+    /// as `pairing_input` builds pair by pair
+    pub fn pairing_input(&self, proof: &Groth16Proof, vk_x: &[u8; G1_SIZE]) -> [u8; PAIRING_INPUT_SIZE] {
+        let mut input = self.pairing_template;
+        input[..G1_SIZE].copy_from_slice(&proof.neg_a);
+        input[G1_SIZE..PAIR_SIZE].copy_from_slice(&proof.b);
+        input[2 * PAIR_SIZE..2 * PAIR_SIZE + G1_SIZE].copy_from_slice(vk_x);
+        input[3 * PAIR_SIZE..3 * PAIR_SIZE + G1_SIZE].copy_from_slice(&proof.c);
+        input
+    }
+}
+
+/// Groth16 proof with A already negated, as the pairing check consumes it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Groth16Proof {
+    pub neg_a: [u8; G1_SIZE],
+    pub b: [u8; G2_SIZE],
+    pub c: [u8; G1_SIZE],
+}
+
+impl Groth16Proof {
+    /// Proof as snarkjs / arkworks produce it
+    pub fn new(a: &[u8; G1_SIZE], b: &[u8; G2_SIZE], c: &[u8; G1_SIZE]) -> Result<Self, Groth16Error> {
+        Ok(Self::from_negated_a(&negate_g1(a)?, b, c))
+    }
+
+    /// Proof whose A the client negated (the whistle-pool instruction format)
+    pub fn from_negated_a(neg_a: &[u8; G1_SIZE], b: &[u8; G2_SIZE], c: &[u8; G1_SIZE]) -> Self {
+        Groth16Proof { neg_a: *neg_a, b: *b, c: *c }
+    }
+}
+
+/// Verify `proof` against `vk` and big-endian `public_inputs`
+pub fn verify(vk: &VerificationKey, proof: &Groth16Proof, public_inputs: &[[u8; 32]]) -> Result<(), Groth16Error> {
+    let vk_x = prepare_inputs(vk, public_inputs)?;
+    check_pairing(&pairing_input(vk, proof, &vk_x))
+}
+
+/// `verify` against a key whose constant pairing input is already laid out
+pub fn verify_prepared(
+    vk: &PreparedVerificationKey,
+    proof: &Groth16Proof,
+    public_inputs: &[[u8; 32]],
+) -> Result<(), Groth16Error> {
+    let vk_x = prepare_inputs(&vk.key, public_inputs)?;
+    check_pairing(&vk.pairing_input(proof, &vk_x))
+}
+
+/// Pairing input e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta),
+/// pair by pair
+pub fn pairing_input(vk: &VerificationKey, proof: &Groth16Proof, vk_x: &[u8; G1_SIZE]) -> [u8; PAIRING_INPUT_SIZE] {
+    pairs_input(&[
+        (&proof.neg_a, &proof.b),
+        (&vk.alpha_g1, &vk.beta_g2),
+        (vk_x, &vk.gamma_g2),
+        (&proof.c, &vk.delta_g2),
+    ])
+}
+
+fn pairs_input(pairs: &[(&[u8; G1_SIZE], &[u8; G2_SIZE]); 4]) -> [u8; PAIRING_INPUT_SIZE] {
+    let mut input = [0u8; PAIRING_INPUT_SIZE];
+    for (pair, (g1, g2)) in input.chunks_exact_mut(PAIR_SIZE).zip(pairs) {
+        pair[..G1_SIZE].copy_from_slice(*g1);
+        pair[G1_SIZE..].copy_from_slice(*g2);
+    }
+    input
+}
+
+/// Fail with ProofVerificationFailed unless the product of the pairings is
+/// the identity
+fn check_pairing(input: &[u8; PAIRING_INPUT_SIZE]) -> Result<(), Groth16Error> {
+    if pairing_is_one(input)? {
+        Ok(())
+    } else {
+        Err(Groth16Error::ProofVerificationFailed)
+    }
+}
+
+/// vk_x = IC[0] + sum(public_input[i] * IC[i + 1])
+pub fn prepare_inputs(vk: &VerificationKey, public_inputs: &[[u8; 32]]) -> Result<[u8; G1_SIZE], Groth16Error> {
+    if vk.ic.len() != public_inputs.len() + 1 {
+        return Err(Groth16Error::InvalidPublicInputsLength);
+    }
+
+    let mut vk_x = vk.ic[0];
+    for (input, ic) in public_inputs.iter().zip(&vk.ic[1..]) {
+        vk_x = accumulate_input(&vk_x, ic, input)?;
+    }
+    Ok(vk_x)
+}
+
+/// vk_x + input * ic
+fn accumulate_input(
+    vk_x: &[u8; G1_SIZE],
+    ic: &[u8; G1_SIZE],
+    input: &[u8; 32],
+) -> Result<[u8; G1_SIZE], Groth16Error> {
+    // A scalar of x + r multiplies like x, so only canonical inputs are
+    // accepted; otherwise one proof would verify for several inputs
+    if !is_canonical_field_element(input) {
+        return Err(Groth16Error::NonCanonicalPublicInput);
+    }
+
+    let mut mul_input = [0u8; G1_SIZE + 32];
+    mul_input[..G1_SIZE].copy_from_slice(ic);
+    mul_input[G1_SIZE..].copy_from_slice(input);
+    let product = alt_bn128_multiplication(&mul_input).map_err(|_| Groth16Error::G1MulFailed)?;
+
+    let mut add_input = [0u8; 2 * G1_SIZE];
+    add_input[..G1_SIZE].copy_from_slice(vk_x);
+    add_input[G1_SIZE..].copy_from_slice(&product);
+    let sum = alt_bn128_addition(&add_input).map_err(|_| Groth16Error::G1AdditionFailed)?;
+
+    let mut result = [0u8; G1_SIZE];
+    result.copy_from_slice(&sum);
+    Ok(result)
+}
+
+/// -P = (x, p - y); the point at infinity (all zeroes) is its own negation
+///
+/// Fails with FieldSubtractBorrow if y is above p.
+pub fn negate_g1(point: &[u8; G1_SIZE]) -> Result<[u8; G1_SIZE], Groth16Error> {
+    let mut result = *point;
+    if point[32..].iter().all(|b| *b == 0) {
+        return Ok(result);
+    }
+
+    let mut y = [0u8; 32];
+    y.copy_from_slice(&point[32..]);
+    result[32..].copy_from_slice(&field_sub(&BN254_BASE_MODULUS, &y)?);
+    Ok(result)
+}
+
+/// a - b of big-endian 32-byte integers
+///
+/// Fails with FieldSubtractBorrow instead of wrapping when b > a.
+pub fn field_sub(a: &[u8; 32], b: &[u8; 32]) -> Result<[u8; 32], Groth16Error> {
+    let mut result = [0u8; 32];
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let diff = a[i] as i16 - b[i] as i16 - borrow;
+        borrow = (diff < 0) as i16;
+        result[i] = (diff + 256 * borrow) as u8;
+    }
+    if borrow != 0 {
+        return Err(Groth16Error::FieldSubtractBorrow);
+    }
+    Ok(result)
+}
+
+/// A G1 point is valid if adding the identity returns it unchanged
+///
+/// The addition syscall rejects points that are not on the curve.
+pub fn is_valid_g1(point: &[u8; G1_SIZE]) -> bool {
+    let mut input = [0u8; 2 * G1_SIZE];
+    input[..G1_SIZE].copy_from_slice(point);
+
+    match alt_bn128_addition(&input) {
+        Ok(sum) => sum.as_slice() == point.as_slice(),
+        Err(_) => false,
+    }
+}
+
+/// A G2 point is valid if e(G, Q) * e(-G, Q) = 1
+///
+/// There is no G2 addition syscall; the pairing syscall rejects G2 points
+/// that are off the curve or outside the prime-order subgroup.
+pub fn is_valid_g2(point: &[u8; G2_SIZE]) -> bool {
+    let mut input = [0u8; 2 * PAIR_SIZE];
+    input[..G1_SIZE].copy_from_slice(&G1_GENERATOR);
+    input[G1_SIZE..PAIR_SIZE].copy_from_slice(point);
+    let Ok(neg_generator) = negate_g1(&G1_GENERATOR) else {
+        return false;
+    };
+    input[PAIR_SIZE..PAIR_SIZE + G1_SIZE].copy_from_slice(&neg_generator);
+    input[PAIR_SIZE + G1_SIZE..].copy_from_slice(point);
+
+    pairing_is_one(&input).unwrap_or(false)
+}
+
+/// Whether every point of `vk` is on its curve
+pub fn is_valid_key(vk: &VerificationKey) -> bool {
+    is_valid_g1(&vk.alpha_g1)
+        && is_valid_g2(&vk.beta_g2)
+        && is_valid_g2(&vk.gamma_g2)
+        && is_valid_g2(&vk.delta_g2)
+        && !vk.ic.is_empty()
+        && vk.ic.iter().all(is_valid_g1)
+}
+
+/// Run the pairing syscall; it returns 1 (32 bytes big-endian) when the
+/// product of the pairings is the identity
+fn pairing_is_one(input: &[u8]) -> Result<bool, Groth16Error> {
+    let result = alt_bn128_pairing(input).map_err(|_| Groth16Error::PairingFailed)?;
+    Ok(result.len() == 32 && result[31] == 1 && result[..31].iter().all(|b| *b == 0))
+}
@@ -0,0 +1,122 @@
+//! Golden vectors for the field element encodings in inputs.rs.
+//!
+//! Each vector pairs the decimal string snarkjs prints with the big-endian
+//! bytes the program expects; the little-endian form is the same bytes
+//! reversed.
+
+use solana_program::poseidon::{hashv, Endianness, Parameters};
+use whistle_groth16::inputs::{
+    be_field_to_u64, decimal_str_to_be_bytes, field_be_to_le, field_le_to_be, is_canonical_field_element,
+    pubkey_to_field, u64_to_be_field, BN254_SCALAR_MODULUS,
+};
+
+/// (decimal, big-endian hex)
+const VECTORS: &[(&str, &str)] = &[
+    ("0", "0000000000000000000000000000000000000000000000000000000000000000"),
+    ("1", "0000000000000000000000000000000000000000000000000000000000000001"),
+    ("18446744073709551615", "000000000000000000000000000000000000000000000000ffffffffffffffff"),
+    // Poseidon(1, 2), as circomlibjs computes it
+    (
+        "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+        "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a",
+    ),
+    // r - 1, the largest field element
+    (
+        "21888242871839275222246405745257275088548364400416034343698204186575808495616",
+        "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000000",
+    ),
+];
+
+fn hex32(hex: &str) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+    }
+    bytes
+}
+
+#[test]
+fn decimal_strings_parse_to_big_endian() {
+    for (decimal, hex) in VECTORS {
+        assert_eq!(decimal_str_to_be_bytes(decimal), Some(hex32(hex)), "{decimal}");
+    }
+}
+
+#[test]
+fn little_and_big_endian_round_trip() {
+    for (decimal, hex) in VECTORS {
+        let be = hex32(hex);
+        let mut le = be;
+        le.reverse();
+
+        assert_eq!(field_be_to_le(&be), le, "{decimal}");
+        assert_eq!(field_le_to_be(&le), be, "{decimal}");
+        assert_eq!(field_le_to_be(&field_be_to_le(&be)), be, "{decimal}");
+    }
+}
+
+#[test]
+fn poseidon_syscall_matches_circomlibjs() {
+    let hash = hashv(
+        Parameters::Bn254X5,
+        Endianness::BigEndian,
+        &[&u64_to_be_field(1), &u64_to_be_field(2)],
+    )
+    .unwrap();
+    assert_eq!(hash.to_bytes(), hex32(VECTORS[3].1));
+}
+
+#[test]
+fn canonical_check_rejects_reversed_values() {
+    for (decimal, hex) in VECTORS {
+        assert!(is_canonical_field_element(&hex32(hex)), "{decimal}");
+    }
+    assert!(!is_canonical_field_element(&BN254_SCALAR_MODULUS));
+
+    // A reversed hash almost always exceeds the modulus...
+    assert!(!is_canonical_field_element(&field_be_to_le(&hex32(VECTORS[3].1))));
+    // ...but not when its low byte happens to be small, as for r - 1
+    assert!(is_canonical_field_element(&field_be_to_le(&hex32(VECTORS[4].1))));
+}
+
+#[test]
+fn malformed_decimal_strings_are_rejected() {
+    assert_eq!(decimal_str_to_be_bytes(""), None);
+    assert_eq!(decimal_str_to_be_bytes("-1"), None);
+    assert_eq!(decimal_str_to_be_bytes("0x10"), None);
+    assert_eq!(decimal_str_to_be_bytes("12 3"), None);
+
+    // 2^256 - 1 fits, 2^256 does not
+    let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+    assert_eq!(decimal_str_to_be_bytes(max), Some([0xff; 32]));
+    assert_eq!(
+        decimal_str_to_be_bytes("115792089237316195423570985008687907853269984665640564039457584007913129639936"),
+        None
+    );
+}
+
+#[test]
+fn u64_inputs_round_trip_and_reject_wider_values() {
+    // u64::MAX is the decimal of VECTORS[2], far below the modulus
+    let max = u64_to_be_field(u64::MAX);
+    assert_eq!(max, hex32(VECTORS[2].1));
+    assert!(is_canonical_field_element(&max));
+    for value in [0, 1, 1_000_000_000, u64::MAX] {
+        assert_eq!(be_field_to_u64(&u64_to_be_field(value)), Some(value), "{value}");
+    }
+
+    // 2^64 and anything wider do not decode
+    let mut two_pow_64 = [0u8; 32];
+    two_pow_64[23] = 1;
+    assert_eq!(be_field_to_u64(&two_pow_64), None);
+    assert_eq!(be_field_to_u64(&hex32(VECTORS[4].1)), None);
+}
+
+#[test]
+fn pubkey_field_keeps_the_first_31_bytes() {
+    let key: [u8; 32] = std::array::from_fn(|i| 0xe0 + i as u8);
+    let field = pubkey_to_field(&key);
+    assert_eq!(field[0], 0);
+    assert_eq!(field[1..], key[..31]);
+    assert!(is_canonical_field_element(&field));
+}
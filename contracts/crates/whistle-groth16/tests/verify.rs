@@ -0,0 +1,271 @@
+//! Verification against arkworks-generated fixtures: a key and proofs for a
+//! small circuit are produced with ark-groth16, converted with the `host`
+//! module, and checked through the syscall path the programs use. Also
+//! covers compressed proofs and keys against known compressed vectors.
+//!
+//! cargo test -p whistle-groth16 --features host
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::Groth16;
+use ark_relations::lc;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_snark::SNARK;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+use whistle_groth16::compressed::{
+    compress_g1, compress_g2, decompress_g1, decompress_g2, negate_g2, verify_with_compressed_key,
+    CompressedVerificationKey,
+};
+use whistle_groth16::host::{fr_to_be, OwnedVerificationKey};
+use whistle_groth16::inputs::{u64_to_be_field, BN254_SCALAR_MODULUS};
+use whistle_groth16::{
+    field_sub, is_valid_g1, is_valid_g2, is_valid_key, negate_g1, pairing_input, prepare_inputs, verify,
+    verify_prepared, Groth16Error, Groth16Proof, BN254_BASE_MODULUS, G1_GENERATOR,
+};
+
+/// Knowledge of a, b with a * b = product and a + b = sum (both public)
+#[derive(Clone)]
+struct ProductSum {
+    a: Option<Fr>,
+    b: Option<Fr>,
+}
+
+impl ConstraintSynthesizer<Fr> for ProductSum {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let product_value = self.a.zip(self.b).map(|(a, b)| a * b);
+        let sum_value = self.a.zip(self.b).map(|(a, b)| a + b);
+
+        let product = cs.new_input_variable(|| product_value.ok_or(SynthesisError::AssignmentMissing))?;
+        let sum = cs.new_input_variable(|| sum_value.ok_or(SynthesisError::AssignmentMissing))?;
+        let a = cs.new_witness_variable(|| self.a.ok_or(SynthesisError::AssignmentMissing))?;
+        let b = cs.new_witness_variable(|| self.b.ok_or(SynthesisError::AssignmentMissing))?;
+
+        cs.enforce_constraint(lc!() + a, lc!() + b, lc!() + product)?;
+        cs.enforce_constraint(lc!() + a + b, lc!() + ark_relations::r1cs::Variable::One, lc!() + sum)?;
+        Ok(())
+    }
+}
+
+struct Fixture {
+    vk: OwnedVerificationKey,
+    proof: Groth16Proof,
+    inputs: [[u8; 32]; 2],
+}
+
+/// Proof that 6 * 7 = 42 and 6 + 7 = 13
+fn fixture() -> Fixture {
+    let mut rng = StdRng::seed_from_u64(7);
+    let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(ProductSum { a: None, b: None }, &mut rng).unwrap();
+    let proof = Groth16::<Bn254>::prove(&pk, ProductSum { a: Some(Fr::from(6u64)), b: Some(Fr::from(7u64)) }, &mut rng)
+        .unwrap();
+
+    Fixture {
+        vk: OwnedVerificationKey::from(&vk),
+        proof: Groth16Proof::from(&proof),
+        inputs: [fr_to_be(&Fr::from(42u64)), fr_to_be(&Fr::from(13u64))],
+    }
+}
+
+#[test]
+fn valid_proof_verifies() {
+    let f = fixture();
+    assert_eq!(verify(&f.vk.key(), &f.proof, &f.inputs), Ok(()));
+    assert_eq!(f.vk.key().num_public_inputs(), 2);
+    assert_eq!(f.inputs[0], u64_to_be_field(42));
+}
+
+#[test]
+fn prepared_key_lays_out_the_same_pairing_input() {
+    let f = fixture();
+    let vk = f.vk.key();
+    let prepared = vk.prepare();
+    let vk_x = prepare_inputs(&vk, &f.inputs).unwrap();
+    assert_eq!(prepared.pairing_input(&f.proof, &vk_x), pairing_input(&vk, &f.proof, &vk_x));
+
+    assert_eq!(verify_prepared(&prepared, &f.proof, &f.inputs), Ok(()));
+    let inputs = [u64_to_be_field(42), u64_to_be_field(14)];
+    assert_eq!(verify_prepared(&prepared, &f.proof, &inputs), Err(Groth16Error::ProofVerificationFailed));
+}
+
+#[test]
+fn wrong_public_input_fails() {
+    let f = fixture();
+    let inputs = [u64_to_be_field(42), u64_to_be_field(14)];
+    assert_eq!(verify(&f.vk.key(), &f.proof, &inputs), Err(Groth16Error::ProofVerificationFailed));
+}
+
+#[test]
+fn tampered_proof_fails() {
+    let f = fixture();
+
+    // Un-negated A
+    let proof = Groth16Proof::from_negated_a(&negate_g1(&f.proof.neg_a).unwrap(), &f.proof.b, &f.proof.c);
+    assert_eq!(verify(&f.vk.key(), &proof, &f.inputs), Err(Groth16Error::ProofVerificationFailed));
+
+    // C replaced by the generator
+    let proof = Groth16Proof { c: G1_GENERATOR, ..f.proof };
+    assert_eq!(verify(&f.vk.key(), &proof, &f.inputs), Err(Groth16Error::ProofVerificationFailed));
+
+    // A off the curve
+    let mut neg_a = f.proof.neg_a;
+    neg_a[63] ^= 1;
+    let proof = Groth16Proof { neg_a, ..f.proof };
+    assert_eq!(verify(&f.vk.key(), &proof, &f.inputs), Err(Groth16Error::PairingFailed));
+}
+
+#[test]
+fn input_count_must_match_key() {
+    let f = fixture();
+    assert_eq!(
+        verify(&f.vk.key(), &f.proof, &f.inputs[..1]),
+        Err(Groth16Error::InvalidPublicInputsLength)
+    );
+}
+
+#[test]
+fn non_canonical_input_is_rejected() {
+    let f = fixture();
+
+    // 13 + r multiplies exactly like 13, so it would otherwise verify
+    let mut shifted = BN254_SCALAR_MODULUS;
+    shifted[31] += 13;
+    let inputs = [f.inputs[0], shifted];
+    assert_eq!(verify(&f.vk.key(), &f.proof, &inputs), Err(Groth16Error::NonCanonicalPublicInput));
+}
+
+#[test]
+fn negation_round_trips() {
+    let neg = negate_g1(&G1_GENERATOR).unwrap();
+    assert_ne!(neg, G1_GENERATOR);
+    assert!(is_valid_g1(&neg));
+    assert_eq!(negate_g1(&neg), Ok(G1_GENERATOR));
+    assert_eq!(negate_g1(&[0u8; 64]), Ok([0u8; 64]));
+}
+
+#[test]
+fn field_sub_rejects_borrow() {
+    // y = p is zero mod p, so p - y = 0 is still a field element
+    assert_eq!(field_sub(&BN254_BASE_MODULUS, &BN254_BASE_MODULUS), Ok([0u8; 32]));
+    assert_eq!(field_sub(&[0u8; 32], &BN254_BASE_MODULUS), Err(Groth16Error::FieldSubtractBorrow));
+
+    // A y-coordinate above p used to wrap into a bogus negation
+    let mut above = G1_GENERATOR;
+    above[32..].copy_from_slice(&BN254_BASE_MODULUS);
+    above[63] += 1;
+    assert_eq!(negate_g1(&above), Err(Groth16Error::FieldSubtractBorrow));
+    assert_eq!(
+        Groth16Proof::new(&above, &[0u8; 128], &G1_GENERATOR),
+        Err(Groth16Error::FieldSubtractBorrow)
+    );
+}
+
+#[test]
+fn key_validation_spots_bad_points() {
+    let f = fixture();
+    assert!(is_valid_key(&f.vk.key()));
+
+    let mut off_curve = G1_GENERATOR;
+    off_curve[63] = 3;
+    assert!(!is_valid_g1(&off_curve));
+
+    let mut beta = f.vk.beta_g2;
+    beta[127] ^= 1;
+    assert!(!is_valid_g2(&beta));
+
+    let mut key = f.vk.key();
+    key.beta_g2 = beta;
+    assert!(!is_valid_key(&key));
+}
+
+/// BN254 G2 generator (x_im || x_re || y_im || y_re)
+const G2_GENERATOR: &str = "198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c2\
+1800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed\
+090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b\
+12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa";
+
+fn hex<const N: usize>(hex: &str) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+    }
+    bytes
+}
+
+#[test]
+fn compressed_proof_and_key_verify() {
+    let f = fixture();
+    let a = negate_g1(&f.proof.neg_a).unwrap();
+    let proof = Groth16Proof::from_compressed(&compress_g1(&a), &compress_g2(&f.proof.b), &compress_g1(&f.proof.c));
+    assert_eq!(proof, Ok(f.proof));
+
+    let ic: Vec<_> = f.vk.ic.iter().map(compress_g1).collect();
+    let vk = CompressedVerificationKey {
+        alpha_g1: compress_g1(&f.vk.alpha_g1),
+        beta_g2: compress_g2(&f.vk.beta_g2),
+        gamma_g2: compress_g2(&f.vk.gamma_g2),
+        delta_g2: compress_g2(&f.vk.delta_g2),
+        ic: &ic,
+    };
+    assert_eq!(verify_with_compressed_key(&vk, &f.proof, &f.inputs), Ok(()));
+    let inputs = [u64_to_be_field(42), u64_to_be_field(14)];
+    assert_eq!(verify_with_compressed_key(&vk, &f.proof, &inputs), Err(Groth16Error::ProofVerificationFailed));
+}
+
+#[test]
+fn known_compressed_vectors() {
+    // G1: (1, 2) has an even y; (1, p - 2) is its negation
+    let mut compressed = [0u8; 33];
+    compressed[0] = 0x02;
+    compressed[32] = 1;
+    assert_eq!(compress_g1(&G1_GENERATOR), compressed);
+    assert_eq!(decompress_g1(&compressed), Ok(G1_GENERATOR));
+    compressed[0] = 0x03;
+    assert_eq!(decompress_g1(&compressed), Ok(negate_g1(&G1_GENERATOR).unwrap()));
+
+    // G2: the generator's y_re ends in 0xaa, so sgn0 is 0
+    let generator: [u8; 128] = hex(G2_GENERATOR);
+    let mut compressed = [0u8; 65];
+    compressed[0] = 0x02;
+    compressed[1..].copy_from_slice(&generator[..64]);
+    assert_eq!(compress_g2(&generator), compressed);
+    assert_eq!(decompress_g2(&compressed), Ok(generator));
+    compressed[0] = 0x03;
+    let negated = negate_g2(&generator).unwrap();
+    assert_eq!(decompress_g2(&compressed), Ok(negated));
+    assert!(is_valid_g2(&negated));
+
+    // The point at infinity
+    assert_eq!(compress_g1(&[0u8; 64]), [0u8; 33]);
+    assert_eq!(decompress_g1(&[0u8; 33]), Ok([0u8; 64]));
+    assert_eq!(decompress_g2(&[0u8; 65]), Ok([0u8; 128]));
+}
+
+#[test]
+fn decompression_rejects_bad_points() {
+    let g1 = |prefix: u8, x: [u8; 32]| {
+        let mut compressed = [prefix; 33];
+        compressed[1..].copy_from_slice(&x);
+        decompress_g1(&compressed)
+    };
+    let failed = Err(Groth16Error::PointDecompressionFailed);
+
+    // 4^3 + 3 has no square root mod p
+    assert_eq!(g1(0x02, u64_to_be_field(4)), failed);
+    // x must be below p, and x = 0 is only the point at infinity
+    assert_eq!(g1(0x02, BN254_BASE_MODULUS), failed);
+    assert_eq!(g1(0x02, [0u8; 32]), failed);
+    assert_eq!(g1(0x00, u64_to_be_field(1)), failed);
+    assert_eq!(g1(0x04, u64_to_be_field(1)), failed);
+
+    // A G2 x_re at p
+    let mut compressed = compress_g2(&hex(G2_GENERATOR));
+    compressed[33..].copy_from_slice(&BN254_BASE_MODULUS);
+    assert_eq!(decompress_g2(&compressed), Err(Groth16Error::PointDecompressionFailed));
+
+    // A compressed proof fails as a whole
+    let f = fixture();
+    let mut c = compress_g1(&f.proof.c);
+    c[1..].copy_from_slice(&u64_to_be_field(4));
+    let a = compress_g1(&negate_g1(&f.proof.neg_a).unwrap());
+    assert_eq!(Groth16Proof::from_compressed(&a, &compress_g2(&f.proof.b), &c), Err(Groth16Error::PointDecompressionFailed));
+}
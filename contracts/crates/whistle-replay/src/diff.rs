@@ -0,0 +1,161 @@
+// Field-by-field comparison of a replayed model with the live accounts
+
+use std::fmt;
+
+use anchor_lang::{AccountDeserialize, Discriminator};
+use whistle_pool::{MerkleTree, NullifierMarker, NullifierSet, PoolState, RootsHistory, RootsRing};
+
+use crate::model::PoolModel;
+use crate::{hex, ReplayError};
+
+/// The state PoolModel rebuilds, read from the pool, merkle_tree,
+/// roots_history and nullifiers accounts and the nullifier markers
+pub struct LiveState {
+    pub merkle_levels: u8,
+    pub next_index: u64,
+    pub current_root: [u8; 32],
+    /// Stored leaves, indexes 0..next_index
+    pub leaves: Vec<[u8; 32]>,
+    /// Non-zero roots history entries
+    pub roots_history: Vec<[u8; 32]>,
+    /// Spent nullifiers with their spend slots: the legacy set's in spend
+    /// order, then the markers' by slot and hash (markers do not record
+    /// their order within a slot)
+    pub nullifiers: Vec<([u8; 32], u64)>,
+    /// How many of `nullifiers` come from the legacy set
+    pub legacy_nullifiers: usize,
+}
+
+impl LiveState {
+    /// Decode the four accounts' data; `markers` are the NullifierMarker
+    /// accounts
+    pub fn from_accounts(
+        pool: &[u8],
+        merkle_tree: &[u8],
+        roots_history: &[u8],
+        nullifiers: &[u8],
+        markers: &[NullifierMarker],
+    ) -> Result<Self, ReplayError> {
+        let pool = PoolState::try_deserialize(&mut &pool[..]).map_err(|_| ReplayError::InvalidAccount("pool"))?;
+
+        let tree = MerkleTree::try_load_versioned(merkle_tree).map_err(|_| ReplayError::InvalidAccount("merkle_tree"))?;
+        let leaf_offset = (1usize << pool.merkle_levels.min(13)) - 1;
+        let leaves = (0..pool.next_index as usize)
+            .map(|i| tree.nodes.get(leaf_offset + i).copied().unwrap_or([0u8; 32]))
+            .collect();
+
+        if roots_history.len() < 8 || roots_history[..8] != RootsHistory::DISCRIMINATOR {
+            return Err(ReplayError::InvalidAccount("roots_history"));
+        }
+        let roots = RootsRing::new(roots_history).map_err(|_| ReplayError::InvalidAccount("roots_history"))?;
+        let roots_history = (0..roots.capacity()).map(|i| roots.root(i)).filter(|root| *root != [0u8; 32]).collect();
+
+        let set: NullifierSet = zero_copy(nullifiers).ok_or(ReplayError::InvalidAccount("nullifiers"))?;
+        let count = (set.count as usize).min(set.nullifiers.len());
+        let mut nullifiers: Vec<_> = (0..count).map(|i| (set.nullifiers[i], set.spent_slots[i])).collect();
+        let legacy_nullifiers = nullifiers.len();
+
+        nullifiers.extend(markers.iter().map(|marker| (marker.nullifier_hash, marker.spent_slot)));
+        nullifiers[legacy_nullifiers..].sort_by_key(|(hash, slot)| (*slot, *hash));
+
+        Ok(Self {
+            merkle_levels: pool.merkle_levels,
+            next_index: pool.next_index,
+            current_root: pool.current_root,
+            leaves,
+            roots_history,
+            nullifiers,
+            legacy_nullifiers,
+        })
+    }
+}
+
+// Copy of a zero-copy account's body (account data may be unaligned)
+fn zero_copy<T: bytemuck::Pod + Discriminator>(data: &[u8]) -> Option<T> {
+    let size = std::mem::size_of::<T>();
+    if data.len() < 8 + size || data[..8] != T::DISCRIMINATOR {
+        return None;
+    }
+    bytemuck::try_pod_read_unaligned(&data[8..8 + size]).ok()
+}
+
+/// One field where the replay and the chain disagree
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    MerkleLevels { model: u8, chain: u8 },
+    NextIndex { model: u64, chain: u64 },
+    CurrentRoot { model: [u8; 32], chain: [u8; 32] },
+    Leaf { index: u64, model: Option<[u8; 32]>, chain: [u8; 32] },
+    /// A roots history entry no replayed state produced
+    UnknownRoot { root: [u8; 32] },
+    NullifierCount { model: usize, chain: usize },
+    Nullifier { position: usize, model: ([u8; 32], u64), chain: ([u8; 32], u64) },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MerkleLevels { model, chain } => write!(f, "merkle_levels: replay {model}, chain {chain}"),
+            Self::NextIndex { model, chain } => write!(f, "next_index: replay {model}, chain {chain}"),
+            Self::CurrentRoot { model, chain } => {
+                write!(f, "current_root: replay {}, chain {}", hex(model), hex(chain))
+            }
+            Self::Leaf { index, model, chain } => match model {
+                Some(model) => write!(f, "leaf {index}: replay {}, chain {}", hex(model), hex(chain)),
+                None => write!(f, "leaf {index}: not replayed, chain {}", hex(chain)),
+            },
+            Self::UnknownRoot { root } => write!(f, "roots_history: {} was never produced by the replay", hex(root)),
+            Self::NullifierCount { model, chain } => write!(f, "nullifier count: replay {model}, chain {chain}"),
+            Self::Nullifier { position, model, chain } => write!(
+                f,
+                "nullifier {position}: replay {} at slot {}, chain {} at slot {}",
+                hex(&model.0),
+                model.1,
+                hex(&chain.0),
+                chain.1
+            ),
+        }
+    }
+}
+
+/// Every field where `model` and `live` disagree; empty when they match
+pub fn diff(model: &PoolModel, live: &LiveState) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+
+    if model.merkle_levels() != live.merkle_levels {
+        mismatches.push(Mismatch::MerkleLevels { model: model.merkle_levels(), chain: live.merkle_levels });
+    }
+    if model.next_index() != live.next_index {
+        mismatches.push(Mismatch::NextIndex { model: model.next_index(), chain: live.next_index });
+    }
+    if model.current_root() != live.current_root {
+        mismatches.push(Mismatch::CurrentRoot { model: model.current_root(), chain: live.current_root });
+    }
+    for (index, chain) in live.leaves.iter().enumerate() {
+        let index = index as u64;
+        let replayed = model.leaf(index);
+        if replayed != Some(*chain) {
+            mismatches.push(Mismatch::Leaf { index, model: replayed, chain: *chain });
+        }
+    }
+    for root in &live.roots_history {
+        if !model.published_root(root) {
+            mismatches.push(Mismatch::UnknownRoot { root: *root });
+        }
+    }
+
+    // Spends after the legacy set are compared in the markers' order
+    let mut replayed = model.nullifiers().to_vec();
+    let split = live.legacy_nullifiers.min(replayed.len());
+    replayed[split..].sort_by_key(|(hash, slot)| (*slot, *hash));
+    if replayed.len() != live.nullifiers.len() {
+        mismatches.push(Mismatch::NullifierCount { model: replayed.len(), chain: live.nullifiers.len() });
+    }
+    for (position, (model, chain)) in replayed.iter().zip(&live.nullifiers).enumerate() {
+        if model != chain {
+            mismatches.push(Mismatch::Nullifier { position, model: *model, chain: *chain });
+        }
+    }
+
+    mismatches
+}
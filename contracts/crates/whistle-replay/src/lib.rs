@@ -0,0 +1,102 @@
+// WHISTLE PROTOCOL - DETERMINISTIC STATE REPLAY
+//
+// Rebuilds the pool's commitment tree and nullifier set from genesis using
+// only the events the program emits, then compares the result field by
+// field with the live accounts.
+//
+// Every state change the replay models is announced by an event:
+// - Leaves: Shielded, ChangeCreated, NoteCreated, TokenShielded (with their
+//   leaf index)
+// - Nullifiers: Unshielded, TokenUnshielded, WithdrawnZk, SchnorrWithdrawn,
+//   BatchWithdrawn, PrivateTransferCompleted (with the slot they were spent
+//   at)
+// - batch_shield / shield_batch_zk batches: BatchShielded (the commitments
+//   are read from the instruction data the transaction carries)
+// - devnet_seed_pool batches: DevnetPoolSeeded (the commitments are
+//   regenerated, so replaying them needs the `devnet` feature)
+//
+// Hashing goes through whistle_pool::poseidon_hash and the tree is updated
+// exactly as MerkleTree::insert_leaf does, so a replayed root only differs
+// from the chain's if the history or the program disagree.
+
+pub mod diff;
+pub mod model;
+pub mod source;
+
+use std::fmt;
+
+pub use diff::{diff, LiveState, Mismatch};
+pub use model::{decode_batch_commitments, decode_event, PoolEvent, PoolModel};
+pub use source::LoggedTransaction;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// A leaf event skipped or repeated a leaf index
+    LeafOutOfOrder { expected: u64, got: u64 },
+    /// More leaves than a tree of the pool's depth holds
+    TreeFull { leaf_index: u64 },
+    /// A nullifier was spent twice
+    DuplicateNullifier { nullifier_hash: [u8; 32], slot: u64 },
+    /// A devnet_seed_pool batch in a build without the `devnet` feature
+    DevnetSeedUnsupported { slot: u64 },
+    /// A BatchShielded event without a matching batch instruction in the
+    /// transaction, e.g. one recorded before transactions carried them
+    BatchCommitmentsMissing { first_leaf_index: u64 },
+    /// The validator truncated a transaction's logs, so events may be missing
+    LogsTruncated { slot: u64 },
+    /// A state-changing pool event that does not decode, e.g. a
+    /// PrivateTransferCompleted from before it carried its nullifier hashes
+    UndecodableEvent { slot: u64 },
+    /// An account is missing or not the layout the program writes
+    InvalidAccount(&'static str),
+    Rpc(String),
+    Fixture(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LeafOutOfOrder { expected, got } => write!(f, "leaf {got} emitted where leaf {expected} was expected"),
+            Self::TreeFull { leaf_index } => write!(f, "leaf {leaf_index} does not fit in the tree"),
+            Self::DuplicateNullifier { nullifier_hash, slot } => {
+                write!(f, "nullifier {} spent again at slot {slot}", hex(nullifier_hash))
+            }
+            Self::DevnetSeedUnsupported { slot } => {
+                write!(f, "devnet_seed_pool batch at slot {slot}; rebuild with --features devnet")
+            }
+            Self::BatchCommitmentsMissing { first_leaf_index } => {
+                write!(f, "batch shield from leaf {first_leaf_index} without its instruction data; record it again")
+            }
+            Self::LogsTruncated { slot } => write!(f, "logs of a transaction at slot {slot} were truncated"),
+            Self::UndecodableEvent { slot } => write!(f, "undecodable pool event at slot {slot}"),
+            Self::InvalidAccount(name) => write!(f, "{name} account is missing or invalid"),
+            Self::Rpc(message) => write!(f, "rpc: {message}"),
+            Self::Fixture(message) => write!(f, "fixture: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Apply the pool events of `transactions` (oldest first) to `model`,
+/// stopping after the last transaction at or before `until_slot`
+pub fn replay(
+    model: &mut PoolModel,
+    transactions: &[LoggedTransaction],
+    until_slot: Option<u64>,
+) -> Result<(), ReplayError> {
+    for transaction in transactions {
+        if until_slot.is_some_and(|until| transaction.slot > until) {
+            break;
+        }
+        for event in transaction.pool_events()? {
+            model.apply(event)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lowercase hex of `bytes`
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,156 @@
+// whistle-replay: rebuild the pool from its event history and diff it
+// against the live accounts
+//
+//   whistle-replay --rpc <URL> [--transactions <FILE>] [--record <FILE>] [--until-slot <SLOT>]
+//
+// --transactions  replay a JSON-lines file (as written by --record) instead
+//                 of fetching the pool's history
+// --record        write the replayed transactions to a JSON-lines file
+// --until-slot    stop after this slot and print the replayed state instead
+//                 of diffing (the live accounts are later than that slot)
+//
+// Exits 1 when the replay and the chain disagree.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::process::ExitCode;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, Discriminator};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::commitment_config::CommitmentConfig;
+use whistle_replay::source::{fetch_transactions, read_transactions, write_transactions};
+use whistle_pool::NullifierMarker;
+use whistle_replay::{diff, hex, replay, LiveState, PoolModel, ReplayError};
+
+struct Args {
+    rpc: String,
+    transactions: Option<String>,
+    record: Option<String>,
+    until_slot: Option<u64>,
+}
+
+const USAGE: &str =
+    "usage: whistle-replay --rpc <URL> [--transactions <FILE>] [--record <FILE>] [--until-slot <SLOT>]";
+
+fn parse_args() -> Option<Args> {
+    let mut rpc = None;
+    let mut transactions = None;
+    let mut record = None;
+    let mut until_slot = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next()?;
+        match flag.as_str() {
+            "--rpc" => rpc = Some(value),
+            "--transactions" => transactions = Some(value),
+            "--record" => record = Some(value),
+            "--until-slot" => until_slot = Some(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some(Args { rpc: rpc?, transactions, record, until_slot })
+}
+
+fn main() -> ExitCode {
+    let Some(args) = parse_args() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    match run(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Whether the replay matches the chain
+fn run(args: Args) -> Result<bool, ReplayError> {
+    let client = RpcClient::new_with_commitment(args.rpc, CommitmentConfig::confirmed());
+    let pda = |seed: &[u8]| Pubkey::find_program_address(&[seed], &whistle_pool::ID).0;
+    let addresses = [pda(b"pool"), pda(b"merkle_tree"), pda(b"roots_history"), pda(b"nullifiers")];
+
+    // Read the accounts before the history, so every transaction they
+    // reflect is in the history; later ones are left out of the replay
+    let response = client
+        .get_multiple_accounts_with_commitment(&addresses, CommitmentConfig::confirmed())
+        .map_err(|e| ReplayError::Rpc(e.to_string()))?;
+    let accounts_slot = response.context.slot;
+    let data: Vec<Vec<u8>> = response
+        .value
+        .into_iter()
+        .zip(["pool", "merkle_tree", "roots_history", "nullifiers"])
+        .map(|(account, name)| account.map(|account| account.data).ok_or(ReplayError::InvalidAccount(name)))
+        .collect::<Result<_, _>>()?;
+    let markers = fetch_nullifier_markers(&client, accounts_slot)?;
+    let live = LiveState::from_accounts(&data[0], &data[1], &data[2], &data[3], &markers)?;
+
+    let transactions = match &args.transactions {
+        Some(path) => {
+            let file = File::open(path).map_err(|e| ReplayError::Fixture(format!("{path}: {e}")))?;
+            read_transactions(BufReader::new(file))?
+        }
+        None => fetch_transactions(&client, &addresses[0])?,
+    };
+    if let Some(path) = &args.record {
+        let file = File::create(path).map_err(|e| ReplayError::Fixture(format!("{path}: {e}")))?;
+        write_transactions(file, &transactions)?;
+    }
+
+    let mut model = PoolModel::new(live.merkle_levels);
+    replay(&mut model, &transactions, Some(args.until_slot.unwrap_or(accounts_slot)))?;
+
+    if let Some(until_slot) = args.until_slot {
+        println!("replayed to slot {until_slot}");
+        println!("next_index:   {}", model.next_index());
+        println!("current_root: {}", hex(&model.current_root()));
+        println!("nullifiers:   {}", model.nullifiers().len());
+        return Ok(true);
+    }
+
+    let mismatches = diff(&model, &live);
+    for mismatch in &mismatches {
+        println!("{mismatch}");
+    }
+    println!(
+        "replayed {} transactions to slot {accounts_slot}: {} mismatches",
+        transactions.len(),
+        mismatches.len()
+    );
+    Ok(mismatches.is_empty())
+}
+
+/// Every NullifierMarker spent by `accounts_slot`; markers are read after
+/// the other accounts, so later spends are dropped to match them
+fn fetch_nullifier_markers(client: &RpcClient, accounts_slot: u64) -> Result<Vec<NullifierMarker>, ReplayError> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(NullifierMarker::SIZE as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, &NullifierMarker::DISCRIMINATOR)),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let accounts = client
+        .get_program_accounts_with_config(&whistle_pool::ID, config)
+        .map_err(|e| ReplayError::Rpc(e.to_string()))?;
+
+    let mut markers = Vec::with_capacity(accounts.len());
+    for (_, account) in accounts {
+        let marker = NullifierMarker::try_deserialize(&mut &account.data[..])
+            .map_err(|_| ReplayError::InvalidAccount("nullifier marker"))?;
+        if marker.spent_slot <= accounts_slot {
+            markers.push(marker);
+        }
+    }
+    Ok(markers)
+}
@@ -0,0 +1,266 @@
+// Pure-Rust model of the pool state the events describe
+
+use std::collections::HashSet;
+
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use whistle_pool::{
+    empty_tree_root, instruction, poseidon_hash, BatchShielded, BatchWithdrawn, ChangeCreated, DevnetPoolSeeded,
+    NoteCreated, PoolMigrated, PrivateTransferCompleted, SchnorrWithdrawn, Shielded, TokenShielded, TokenUnshielded,
+    Unshielded, WithdrawnZk, MERKLE_TREE_NODE_CAPACITY, ZERO_SUBTREE,
+};
+
+use crate::ReplayError;
+
+/// A state change announced by a pool event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PoolEvent {
+    LeafInserted { commitment: [u8; 32], leaf_index: u64 },
+    NullifierSpent { nullifier_hash: [u8; 32], slot: u64 },
+    DevnetSeeded { first_leaf_index: u64, count: u8, seed: u64, slot: u64 },
+    /// A batch_shield or shield_batch_zk batch; LoggedTransaction::pool_events
+    /// turns it into leaves from the instruction's commitments
+    BatchShielded { first_leaf_index: u64, count: u8 },
+    TreeDeepened { new_levels: u8 },
+}
+
+/// State changes announced by the event in `data` (discriminator included)
+///
+/// Events that change neither the tree nor the nullifier set decode to no
+/// changes. None means a state-changing event that could not be decoded,
+/// e.g. a PrivateTransferCompleted emitted before it carried its
+/// nullifier hashes.
+pub fn decode_event(data: &[u8]) -> Option<Vec<PoolEvent>> {
+    if data.len() < 8 {
+        return Some(Vec::new());
+    }
+    let (discriminator, mut body) = data.split_at(8);
+    let leaf = |commitment, leaf_index| PoolEvent::LeafInserted { commitment, leaf_index };
+    let spent = |nullifier_hash, slot| PoolEvent::NullifierSpent { nullifier_hash, slot };
+
+    let events = match <[u8; 8]>::try_from(discriminator).ok()? {
+        Shielded::DISCRIMINATOR => {
+            let event = Shielded::deserialize(&mut body).ok()?;
+            vec![leaf(event.commitment, event.leaf_index)]
+        }
+        ChangeCreated::DISCRIMINATOR => {
+            let event = ChangeCreated::deserialize(&mut body).ok()?;
+            vec![leaf(event.commitment, event.leaf_index)]
+        }
+        NoteCreated::DISCRIMINATOR => {
+            let event = NoteCreated::deserialize(&mut body).ok()?;
+            vec![leaf(event.commitment, event.leaf_index)]
+        }
+        TokenShielded::DISCRIMINATOR => {
+            let event = TokenShielded::deserialize(&mut body).ok()?;
+            vec![leaf(event.commitment, event.leaf_index)]
+        }
+        Unshielded::DISCRIMINATOR => {
+            let event = Unshielded::deserialize(&mut body).ok()?;
+            vec![spent(event.nullifier_hash, event.slot)]
+        }
+        WithdrawnZk::DISCRIMINATOR => {
+            let event = WithdrawnZk::deserialize(&mut body).ok()?;
+            vec![spent(event.nullifier_hash, event.slot)]
+        }
+        TokenUnshielded::DISCRIMINATOR => {
+            let event = TokenUnshielded::deserialize(&mut body).ok()?;
+            vec![spent(event.nullifier_hash, event.slot)]
+        }
+        SchnorrWithdrawn::DISCRIMINATOR => {
+            let event = SchnorrWithdrawn::deserialize(&mut body).ok()?;
+            vec![spent(event.nullifier_hash, event.slot)]
+        }
+        BatchWithdrawn::DISCRIMINATOR => {
+            // batch_withdraw_zk marks all four nullifiers
+            let event = BatchWithdrawn::deserialize(&mut body).ok()?;
+            event.nullifier_hashes.into_iter().map(|hash| spent(hash, event.slot)).collect()
+        }
+        PrivateTransferCompleted::DISCRIMINATOR => {
+            // A zero entry is an unused input slot and was not marked
+            let event = PrivateTransferCompleted::deserialize(&mut body).ok()?;
+            event
+                .nullifier_hashes
+                .into_iter()
+                .filter(|hash| *hash != [0u8; 32])
+                .map(|hash| spent(hash, event.slot))
+                .collect()
+        }
+        DevnetPoolSeeded::DISCRIMINATOR => {
+            let event = DevnetPoolSeeded::deserialize(&mut body).ok()?;
+            vec![PoolEvent::DevnetSeeded {
+                first_leaf_index: event.first_leaf_index,
+                count: event.count,
+                seed: event.seed,
+                slot: event.slot,
+            }]
+        }
+        BatchShielded::DISCRIMINATOR => {
+            let event = BatchShielded::deserialize(&mut body).ok()?;
+            vec![PoolEvent::BatchShielded { first_leaf_index: event.first_leaf_index, count: event.count }]
+        }
+        PoolMigrated::DISCRIMINATOR => {
+            let event = PoolMigrated::deserialize(&mut body).ok()?;
+            vec![PoolEvent::TreeDeepened { new_levels: event.new_levels }]
+        }
+        _ => Vec::new(),
+    };
+    Some(events)
+}
+
+/// Commitments of the batch_shield or shield_batch_zk instruction in
+/// `data` (discriminator included); None for any other instruction
+pub fn decode_batch_commitments(data: &[u8]) -> Option<Vec<[u8; 32]>> {
+    if data.len() < 8 {
+        return None;
+    }
+    let (discriminator, mut body) = data.split_at(8);
+    match <[u8; 8]>::try_from(discriminator).ok()? {
+        instruction::BatchShield::DISCRIMINATOR => {
+            Some(instruction::BatchShield::deserialize(&mut body).ok()?.commitments)
+        }
+        instruction::ShieldBatchZk::DISCRIMINATOR => {
+            Some(instruction::ShieldBatchZk::deserialize(&mut body).ok()?.commitments.to_vec())
+        }
+        _ => None,
+    }
+}
+
+/// Commitment tree and nullifier set rebuilt from events
+pub struct PoolModel {
+    merkle_levels: u8,
+    nodes: Vec<[u8; 32]>,
+    next_index: u64,
+    current_root: [u8; 32],
+    // Root after every leaf; the pool only records the root after each
+    // instruction, so its roots history is a subset of these
+    roots: HashSet<[u8; 32]>,
+    nullifiers: Vec<([u8; 32], u64)>,
+    spent: HashSet<[u8; 32]>,
+}
+
+impl PoolModel {
+    /// Empty pool of depth `merkle_levels`, as initialize_pool leaves it
+    pub fn new(merkle_levels: u8) -> Self {
+        let current_root = empty_tree_root(merkle_levels);
+        Self {
+            merkle_levels,
+            nodes: vec![[0u8; 32]; MERKLE_TREE_NODE_CAPACITY],
+            next_index: 0,
+            current_root,
+            roots: HashSet::from([current_root]),
+            nullifiers: Vec::new(),
+            spent: HashSet::new(),
+        }
+    }
+
+    pub fn apply(&mut self, event: PoolEvent) -> Result<(), ReplayError> {
+        match event {
+            PoolEvent::LeafInserted { commitment, leaf_index } => self.insert_leaf(commitment, leaf_index),
+            PoolEvent::NullifierSpent { nullifier_hash, slot } => {
+                if !self.spent.insert(nullifier_hash) {
+                    return Err(ReplayError::DuplicateNullifier { nullifier_hash, slot });
+                }
+                self.nullifiers.push((nullifier_hash, slot));
+                Ok(())
+            }
+            PoolEvent::DevnetSeeded { first_leaf_index, count, seed, slot } => self.seed(first_leaf_index, count, seed, slot),
+            PoolEvent::BatchShielded { first_leaf_index, .. } => {
+                Err(ReplayError::BatchCommitmentsMissing { first_leaf_index })
+            }
+            PoolEvent::TreeDeepened { new_levels } => self.deepen(new_levels),
+        }
+    }
+
+    // finalize_migration: the same leaves in a tree of `new_levels` levels,
+    // whose nodes match the ones continue_migration rebuilds
+    fn deepen(&mut self, new_levels: u8) -> Result<(), ReplayError> {
+        let leaves: Vec<[u8; 32]> = (0..self.next_index).filter_map(|leaf_index| self.leaf(leaf_index)).collect();
+        self.merkle_levels = new_levels;
+        self.nodes = vec![[0u8; 32]; MERKLE_TREE_NODE_CAPACITY];
+        self.next_index = 0;
+        for (leaf_index, leaf) in leaves.into_iter().enumerate() {
+            self.insert_leaf(leaf, leaf_index as u64)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "devnet")]
+    fn seed(&mut self, first_leaf_index: u64, count: u8, seed: u64, _slot: u64) -> Result<(), ReplayError> {
+        for i in 0..count {
+            let commitment = whistle_pool::devnet::seeded_note_commitment(seed, i);
+            self.insert_leaf(commitment, first_leaf_index + i as u64)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "devnet"))]
+    fn seed(&mut self, _first_leaf_index: u64, _count: u8, _seed: u64, slot: u64) -> Result<(), ReplayError> {
+        Err(ReplayError::DevnetSeedUnsupported { slot })
+    }
+
+    // Same node layout and hashing as MerkleTree::insert_leaf
+    fn insert_leaf(&mut self, commitment: [u8; 32], leaf_index: u64) -> Result<(), ReplayError> {
+        if leaf_index != self.next_index {
+            return Err(ReplayError::LeafOutOfOrder { expected: self.next_index, got: leaf_index });
+        }
+        if leaf_index >= 1u64 << self.merkle_levels {
+            return Err(ReplayError::TreeFull { leaf_index });
+        }
+
+        let leaf_pos = self.leaf_offset() + leaf_index as usize;
+        if leaf_pos < self.nodes.len() {
+            self.nodes[leaf_pos] = commitment;
+            let mut current = leaf_pos;
+            let mut height = 0;
+            while current > 0 {
+                if current % 2 == 1 {
+                    self.nodes[current + 1] = ZERO_SUBTREE[height];
+                }
+                let parent = (current - 1) / 2;
+                let (left, right) = (self.nodes[2 * parent + 1], self.nodes[2 * parent + 2]);
+                self.nodes[parent] = poseidon_hash(&[&left, &right]);
+                current = parent;
+                height += 1;
+            }
+        }
+
+        self.next_index += 1;
+        self.current_root = self.nodes[0];
+        self.roots.insert(self.current_root);
+        Ok(())
+    }
+
+    fn leaf_offset(&self) -> usize {
+        (1usize << self.merkle_levels.min(13)) - 1
+    }
+
+    pub fn merkle_levels(&self) -> u8 {
+        self.merkle_levels
+    }
+
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    pub fn current_root(&self) -> [u8; 32] {
+        self.current_root
+    }
+
+    /// Leaf `leaf_index` as stored in the tree account, if it is stored
+    pub fn leaf(&self, leaf_index: u64) -> Option<[u8; 32]> {
+        if leaf_index >= self.next_index {
+            return None;
+        }
+        self.nodes.get(self.leaf_offset() + leaf_index as usize).copied()
+    }
+
+    /// Whether the pool published `root` at some point of the replay
+    pub fn published_root(&self, root: &[u8; 32]) -> bool {
+        self.roots.contains(root)
+    }
+
+    /// Spent nullifiers with their spend slots, in spend order
+    pub fn nullifiers(&self) -> &[([u8; 32], u64)] {
+        &self.nullifiers
+    }
+}
@@ -0,0 +1,214 @@
+// Transaction sources: the RPC history of the pool, or a JSON-lines file of
+// transactions (one LoggedTransaction per line) recorded from it
+
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::bs58;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{UiInstruction, UiTransactionEncoding, UiTransactionStatusMeta};
+
+use crate::model::{decode_batch_commitments, decode_event, PoolEvent};
+use crate::ReplayError;
+
+// getSignaturesForAddress page size (the RPC maximum)
+const SIGNATURE_PAGE: usize = 1000;
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+const LOG_TRUNCATED: &str = "Log truncated";
+
+/// A successful transaction's slot, log messages and pool instructions
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoggedTransaction {
+    #[serde(default)]
+    pub signature: String,
+    pub slot: u64,
+    pub logs: Vec<String>,
+    /// Data of the pool program's instructions in execution order, base64;
+    /// BatchShielded leaves up to the batch instructions' commitments
+    #[serde(default)]
+    pub instructions: Vec<String>,
+}
+
+impl LoggedTransaction {
+    /// State changes announced by events the pool program emitted (events
+    /// logged by other programs in the same transaction are ignored)
+    pub fn pool_events(&self) -> Result<Vec<PoolEvent>, ReplayError> {
+        let pool_program = whistle_pool::ID.to_string();
+        let mut invocations: Vec<&str> = Vec::new();
+        let mut events = Vec::new();
+        // The k-th BatchShielded comes from the k-th batch instruction
+        let mut batches = self.instructions.iter().filter_map(|data| {
+            let data = base64::engine::general_purpose::STANDARD.decode(data).ok()?;
+            decode_batch_commitments(&data)
+        });
+
+        for log in &self.logs {
+            if log.starts_with(LOG_TRUNCATED) {
+                return Err(ReplayError::LogsTruncated { slot: self.slot });
+            }
+            if let Some(data) = log.strip_prefix(PROGRAM_DATA_PREFIX) {
+                if invocations.last() != Some(&pool_program.as_str()) {
+                    continue;
+                }
+                let decoded = base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .ok()
+                    .and_then(|data| decode_event(&data));
+                for event in decoded.ok_or(ReplayError::UndecodableEvent { slot: self.slot })? {
+                    let PoolEvent::BatchShielded { first_leaf_index, count } = event else {
+                        events.push(event);
+                        continue;
+                    };
+                    let commitments = batches
+                        .next()
+                        .filter(|commitments| commitments.len() == count as usize)
+                        .ok_or(ReplayError::BatchCommitmentsMissing { first_leaf_index })?;
+                    events.extend(
+                        commitments
+                            .into_iter()
+                            .zip(first_leaf_index..)
+                            .map(|(commitment, leaf_index)| PoolEvent::LeafInserted { commitment, leaf_index }),
+                    );
+                }
+                continue;
+            }
+
+            // "Program <id> invoke [depth]" / "Program <id> success" / "Program <id> failed: ..."
+            // ("Program log: ..." and "Program return: ..." have a colon where the id goes)
+            let mut words = log.split(' ');
+            if words.next() != Some("Program") {
+                continue;
+            }
+            match (words.next(), words.next()) {
+                (Some(program), Some("invoke")) if !program.ends_with(':') => invocations.push(program),
+                (Some(program), Some("success" | "failed:")) if !program.ends_with(':') => {
+                    invocations.pop();
+                }
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Read a JSON-lines file of transactions, oldest first
+pub fn read_transactions(reader: impl BufRead) -> Result<Vec<LoggedTransaction>, ReplayError> {
+    let mut transactions = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| ReplayError::Fixture(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let transaction = serde_json::from_str(&line)
+            .map_err(|e| ReplayError::Fixture(format!("line {}: {e}", number + 1)))?;
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+/// Write `transactions` in the format read_transactions reads
+pub fn write_transactions(mut writer: impl Write, transactions: &[LoggedTransaction]) -> Result<(), ReplayError> {
+    for transaction in transactions {
+        let line = serde_json::to_string(transaction).map_err(|e| ReplayError::Fixture(e.to_string()))?;
+        writeln!(writer, "{line}").map_err(|e| ReplayError::Fixture(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Every successful transaction that touched `address`, oldest first
+pub fn fetch_transactions(client: &RpcClient, address: &Pubkey) -> Result<Vec<LoggedTransaction>, ReplayError> {
+    let rpc = |e: solana_client::client_error::ClientError| ReplayError::Rpc(e.to_string());
+    let commitment = Some(CommitmentConfig::confirmed());
+
+    // Signatures come newest first, a page at a time
+    let mut statuses = Vec::new();
+    let mut before = None;
+    loop {
+        let page = client
+            .get_signatures_for_address_with_config(
+                address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(SIGNATURE_PAGE),
+                    commitment,
+                },
+            )
+            .map_err(rpc)?;
+        let Some(last) = page.last() else { break };
+        before = Some(Signature::from_str(&last.signature).map_err(|e| ReplayError::Rpc(e.to_string()))?);
+        let full = page.len() == SIGNATURE_PAGE;
+        statuses.extend(page);
+        if !full {
+            break;
+        }
+    }
+
+    let mut transactions = Vec::new();
+    for status in statuses.into_iter().rev().filter(|status| status.err.is_none()) {
+        let signature = Signature::from_str(&status.signature).map_err(|e| ReplayError::Rpc(e.to_string()))?;
+        let transaction = client
+            .get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment,
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .map_err(rpc)?;
+        let meta = transaction.transaction.meta;
+        let instructions = transaction
+            .transaction
+            .transaction
+            .decode()
+            .map(|decoded| pool_instructions(&decoded, meta.as_ref()))
+            .unwrap_or_default();
+        let logs = meta.and_then(|meta| Option::<Vec<String>>::from(meta.log_messages)).unwrap_or_default();
+        let slot = transaction.slot;
+        transactions.push(LoggedTransaction { signature: status.signature, slot, logs, instructions });
+    }
+    Ok(transactions)
+}
+
+// Data of the pool program's instructions, base64: each top-level
+// instruction followed by the instructions it invoked
+fn pool_instructions(transaction: &VersionedTransaction, meta: Option<&UiTransactionStatusMeta>) -> Vec<String> {
+    // Instructions index the static keys, then the lookup tables' writable
+    // and readonly addresses
+    let mut keys = transaction.message.static_account_keys().to_vec();
+    if let Some(OptionSerializer::Some(loaded)) = meta.map(|meta| &meta.loaded_addresses) {
+        let loaded = loaded.writable.iter().chain(&loaded.readonly);
+        keys.extend(loaded.map(|key| Pubkey::from_str(key).unwrap_or_default()));
+    }
+    let is_pool = |index: u8| keys.get(index as usize) == Some(&whistle_pool::ID);
+    let inner = match meta.map(|meta| &meta.inner_instructions) {
+        Some(OptionSerializer::Some(inner)) => inner.as_slice(),
+        _ => &[],
+    };
+
+    let mut data = Vec::new();
+    for (index, instruction) in transaction.message.instructions().iter().enumerate() {
+        if is_pool(instruction.program_id_index) {
+            data.push(instruction.data.clone());
+        }
+        let invoked = inner.iter().filter(|inner| inner.index as usize == index).flat_map(|inner| &inner.instructions);
+        for invoked in invoked {
+            if let UiInstruction::Compiled(invoked) = invoked {
+                if is_pool(invoked.program_id_index) {
+                    data.extend(bs58::decode(&invoked.data).into_vec().ok());
+                }
+            }
+        }
+    }
+    data.iter().map(|data| base64::engine::general_purpose::STANDARD.encode(data)).collect()
+}
@@ -0,0 +1,416 @@
+// Replay of recorded transaction fixtures against accounts built with the
+// program's own MerkleTree code
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountSerialize, AnchorSerialize, Discriminator, Event, InstructionData};
+use base64::Engine;
+use whistle_pool::{
+    empty_tree_root, instruction, BatchShielded, BatchWithdrawn, ChangeCreated, MerkleTree, NoteCreated, NullifierMarker, NullifierSet, PoolState,
+    PrivateTransferCompleted, RootsHistory, RootsRing, Shielded, TokenShielded, TokenUnshielded, Unshielded,
+    MERKLE_TREE_NODE_CAPACITY, MERKLE_TREE_VERSION, ROOTS_HISTORY_CAPACITY,
+};
+use whistle_replay::source::{read_transactions, write_transactions};
+use whistle_replay::{diff, replay, LiveState, LoggedTransaction, Mismatch, PoolModel, ReplayError};
+
+const LEVELS: u8 = 7;
+
+fn commitment(n: u8) -> [u8; 32] {
+    let mut value = [0u8; 32];
+    value[31] = n;
+    value
+}
+
+fn nullifier(n: u8) -> [u8; 32] {
+    let mut value = [0u8; 32];
+    value[1] = 0xaa;
+    value[31] = n;
+    value
+}
+
+/// Logs of a successful top-level call to `program` that emitted `events`
+fn logs(program: &Pubkey, events: &[Vec<u8>]) -> Vec<String> {
+    let mut logs = vec![format!("Program {program} invoke [1]"), "Program log: Instruction: Fixture".to_string()];
+    logs.extend(
+        events
+            .iter()
+            .map(|event| format!("Program data: {}", base64::engine::general_purpose::STANDARD.encode(event))),
+    );
+    logs.push(format!("Program {program} consumed 1000 of 200000 compute units"));
+    logs.push(format!("Program {program} success"));
+    logs
+}
+
+/// The pool accounts, updated the way the program updates them, and the
+/// transactions that updated them
+struct Chain {
+    pool: PoolState,
+    tree: Vec<u32>,
+    /// roots_history account data, discriminator included
+    roots: Vec<u8>,
+    spent: Vec<([u8; 32], u64)>,
+    /// How many of `spent` are in the legacy set; the rest are markers
+    legacy_spends: usize,
+    transactions: Vec<LoggedTransaction>,
+}
+
+impl Chain {
+    fn new() -> Self {
+        let mut chain = Self {
+            pool: PoolState {
+                merkle_levels: LEVELS,
+                next_index: 0,
+                current_root: empty_tree_root(LEVELS),
+                total_deposits: 0,
+                total_shielded: 0,
+                total_fees_collected: 0,
+                bump: 0,
+                curve: 0,
+                allow_schnorr_for_small: false,
+                max_total_deposits_per_address: 0,
+                max_total_pool_shielded: 0,
+                vault_bump: 0,
+                merkle_tree_bump: 0,
+                roots_history_bump: 0,
+                nullifiers_bump: 0,
+            },
+            tree: vec![0u32; std::mem::size_of::<MerkleTree>() / 4],
+            roots: [&RootsHistory::DISCRIMINATOR[..], &[0u8; RootsHistory::space(ROOTS_HISTORY_CAPACITY) - 8]].concat(),
+            spent: Vec::new(),
+            legacy_spends: usize::MAX,
+            transactions: Vec::new(),
+        };
+        let tree = chain.tree();
+        tree.version = MERKLE_TREE_VERSION;
+        tree.levels_used = LEVELS;
+        tree.node_capacity = MERKLE_TREE_NODE_CAPACITY as u32;
+        chain
+    }
+
+    fn tree(&mut self) -> &mut MerkleTree {
+        bytemuck::from_bytes_mut(bytemuck::cast_slice_mut(&mut self.tree))
+    }
+
+    fn insert(&mut self, commitment: [u8; 32]) -> u64 {
+        let leaf_index = self.pool.next_index;
+        self.tree().insert_leaf(commitment, leaf_index, LEVELS);
+        self.pool.next_index += 1;
+        self.pool.current_root = self.tree().get_root(LEVELS);
+        leaf_index
+    }
+
+    /// Record a transaction at `slot`, and the new root if it inserted leaves
+    fn commit(&mut self, slot: u64, root_before: [u8; 32], events: Vec<Vec<u8>>) {
+        if self.pool.current_root != root_before {
+            RootsRing::new(&mut self.roots[..]).unwrap().push(self.pool.current_root, slot);
+        }
+        self.transactions.push(LoggedTransaction {
+            signature: String::new(),
+            slot,
+            logs: logs(&whistle_pool::ID, &events),
+            instructions: Vec::new(),
+        });
+    }
+
+    fn shield(&mut self, slot: u64, commitment: [u8; 32]) {
+        let root_before = self.pool.current_root;
+        let leaf_index = self.insert(commitment);
+        let event = Shielded { commitment, leaf_index, amount: 0, protocol_fee: 0, slot, timestamp: 0 };
+        self.commit(slot, root_before, vec![event.data()]);
+    }
+
+    fn unshield(&mut self, slot: u64, nullifier_hash: [u8; 32], change: Option<[u8; 32]>) {
+        let root_before = self.pool.current_root;
+        self.spent.push((nullifier_hash, slot));
+        let mut events = Vec::new();
+        if let Some(commitment) = change {
+            let leaf_index = self.insert(commitment);
+            events.push(ChangeCreated { commitment, leaf_index, slot, timestamp: 0 }.data());
+        }
+        let event = Unshielded {
+            nullifier_hash,
+            withdrawal_amount: 0,
+            protocol_fee: 0,
+            has_change: change.is_some(),
+            recipient_is_pda: false,
+            self_relayed: false,
+            receipt_hash: [0u8; 32],
+            slot,
+            timestamp: 0,
+        };
+        events.push(event.data());
+        self.commit(slot, root_before, events);
+    }
+
+    fn private_transfer(&mut self, slot: u64, inputs: [[u8; 32]; 2], outputs: [[u8; 32]; 2]) {
+        let root_before = self.pool.current_root;
+        self.spent.extend(inputs.iter().filter(|hash| **hash != [0u8; 32]).map(|hash| (*hash, slot)));
+        let mut events = Vec::new();
+        for commitment in outputs {
+            let leaf_index = self.insert(commitment);
+            events.push(NoteCreated { commitment, leaf_index, slot, timestamp: 0 }.data());
+        }
+        let event = PrivateTransferCompleted {
+            nullifiers_spent: 2,
+            notes_created: 2,
+            slot,
+            timestamp: 0,
+            nullifier_hashes: inputs,
+        };
+        events.push(event.data());
+        self.commit(slot, root_before, events);
+    }
+
+    fn batch_shield(&mut self, slot: u64, commitments: Vec<[u8; 32]>) {
+        let root_before = self.pool.current_root;
+        let first_leaf_index = self.pool.next_index;
+        for (i, commitment) in commitments.iter().enumerate() {
+            self.insert(*commitment);
+            // The root after each leaf is recorded; commit records the last
+            if i + 1 < commitments.len() {
+                RootsRing::new(&mut self.roots[..]).unwrap().push(self.pool.current_root, slot);
+            }
+        }
+        let event = BatchShielded {
+            count: commitments.len() as u8,
+            total_amount: 0,
+            first_leaf_index,
+            last_leaf_index: self.pool.next_index - 1,
+            timestamp: 0,
+        };
+        self.commit(slot, root_before, vec![event.data()]);
+        let amounts = vec![0; commitments.len()];
+        let data = instruction::BatchShield { commitments, amounts }.data();
+        let transaction = self.transactions.last_mut().unwrap();
+        transaction.instructions.push(base64::engine::general_purpose::STANDARD.encode(data));
+    }
+
+    fn batch_withdraw(&mut self, slot: u64, nullifier_hashes: [[u8; 32]; 4]) {
+        let root_before = self.pool.current_root;
+        self.spent.extend(nullifier_hashes.iter().map(|hash| (*hash, slot)));
+        let event = BatchWithdrawn {
+            nullifier_hashes,
+            amounts: [0; 4],
+            protocol_fee: 0,
+            recipient_is_pda: false,
+            slot,
+            timestamp: 0,
+        };
+        self.commit(slot, root_before, vec![event.data()]);
+    }
+
+    fn live(&self) -> LiveState {
+        let mut pool = Vec::new();
+        self.pool.try_serialize(&mut pool).unwrap();
+
+        let tree = [&MerkleTree::DISCRIMINATOR[..], bytemuck::cast_slice(&self.tree)].concat();
+
+        let (legacy, markers) = self.spent.split_at(self.legacy_spends.min(self.spent.len()));
+        let mut set = vec![0u64; std::mem::size_of::<NullifierSet>() / 8];
+        let nullifiers: &mut NullifierSet = bytemuck::from_bytes_mut(bytemuck::cast_slice_mut(&mut set));
+        for (i, (hash, slot)) in legacy.iter().enumerate() {
+            nullifiers.nullifiers[i] = *hash;
+            nullifiers.spent_slots[i] = *slot;
+        }
+        nullifiers.count = legacy.len() as u64;
+        let nullifiers = [&NullifierSet::DISCRIMINATOR[..], bytemuck::cast_slice(&set)].concat();
+
+        // getProgramAccounts returns markers in no particular order
+        let markers: Vec<_> = markers
+            .iter()
+            .rev()
+            .map(|(hash, slot)| NullifierMarker { nullifier_hash: *hash, spent_slot: *slot, bump: 0 })
+            .collect();
+
+        LiveState::from_accounts(&pool, &tree, &self.roots, &nullifiers, &markers).unwrap()
+    }
+}
+
+/// Shields, an unshield with change, a one-input private transfer, a batch
+/// withdrawal and a batch shield, one slot apart
+fn history() -> Chain {
+    let mut chain = Chain::new();
+    chain.shield(1, commitment(1));
+    chain.shield(2, commitment(2));
+    chain.unshield(3, nullifier(1), Some(commitment(3)));
+    chain.private_transfer(4, [nullifier(2), [0u8; 32]], [commitment(4), commitment(5)]);
+    chain.batch_withdraw(5, [nullifier(3), nullifier(4), nullifier(5), nullifier(6)]);
+    chain.batch_shield(6, vec![commitment(6), commitment(7), commitment(8)]);
+    chain
+}
+
+#[test]
+fn replay_of_recorded_history_matches_the_accounts() {
+    let chain = history();
+
+    // Through the fixture format, as --record writes it and --transactions reads it
+    let mut fixture = Vec::new();
+    write_transactions(&mut fixture, &chain.transactions).unwrap();
+    let transactions = read_transactions(fixture.as_slice()).unwrap();
+    assert_eq!(transactions.len(), chain.transactions.len());
+
+    let mut model = PoolModel::new(LEVELS);
+    replay(&mut model, &transactions, None).unwrap();
+
+    assert_eq!(model.next_index(), 8);
+    assert_eq!(model.current_root(), chain.pool.current_root);
+    assert_eq!(model.nullifiers(), chain.spent.as_slice());
+    assert_eq!(diff(&model, &chain.live()), Vec::new());
+}
+
+#[test]
+fn missing_transaction_shows_up_field_by_field() {
+    let chain = history();
+    let mut transactions = chain.transactions.clone();
+    transactions.remove(4); // batch withdrawal
+
+    let mut model = PoolModel::new(LEVELS);
+    replay(&mut model, &transactions, None).unwrap();
+    assert_eq!(diff(&model, &chain.live()), vec![Mismatch::NullifierCount { model: 2, chain: 6 }]);
+
+    // Without the unshield's change leaf, later leaf indexes leave a gap
+    transactions.remove(2);
+    let mut model = PoolModel::new(LEVELS);
+    assert!(matches!(
+        replay(&mut model, &transactions, None),
+        Err(ReplayError::LeafOutOfOrder { expected: 2, got: 3 })
+    ));
+}
+
+#[test]
+fn tampered_leaf_and_spend_slot_are_reported() {
+    let mut chain = history();
+    let mut model = PoolModel::new(LEVELS);
+    replay(&mut model, &chain.transactions, None).unwrap();
+
+    chain.spent[0].1 = 99;
+    chain.tree().nodes[(1 << LEVELS) - 1 + 4] = commitment(9);
+    let mismatches = diff(&model, &chain.live());
+
+    assert!(mismatches.contains(&Mismatch::Leaf { index: 4, model: Some(commitment(5)), chain: commitment(9) }));
+    assert!(mismatches.contains(&Mismatch::Nullifier {
+        position: 0,
+        model: (nullifier(1), 3),
+        chain: (nullifier(1), 99),
+    }));
+    assert_eq!(mismatches.len(), 2);
+}
+
+#[test]
+fn spends_after_the_legacy_set_are_read_from_markers() {
+    let mut chain = history();
+    chain.legacy_spends = 2;
+    let mut model = PoolModel::new(LEVELS);
+    replay(&mut model, &chain.transactions, None).unwrap();
+
+    // The batch withdrawal's four markers share a slot; their order is lost
+    assert_eq!(diff(&model, &chain.live()), Vec::new());
+
+    chain.spent.pop();
+    assert_eq!(diff(&model, &chain.live()), vec![Mismatch::NullifierCount { model: 6, chain: 5 }]);
+}
+
+#[test]
+fn replay_halts_at_slot() {
+    let mut chain = Chain::new();
+    chain.shield(1, commitment(1));
+    chain.shield(2, commitment(2));
+    let root_at_2 = chain.pool.current_root;
+    chain.unshield(3, nullifier(1), Some(commitment(3)));
+
+    let mut model = PoolModel::new(LEVELS);
+    replay(&mut model, &chain.transactions, Some(2)).unwrap();
+
+    assert_eq!(model.next_index(), 2);
+    assert_eq!(model.current_root(), root_at_2);
+    assert!(model.nullifiers().is_empty());
+}
+
+#[test]
+fn only_pool_events_change_the_model() {
+    let shielded = Shielded { commitment: commitment(1), leaf_index: 0, amount: 0, protocol_fee: 0, slot: 1, timestamp: 0 };
+    let transaction = LoggedTransaction {
+        signature: String::new(),
+        slot: 1,
+        logs: logs(&Pubkey::new_unique(), &[shielded.data()]),
+        instructions: Vec::new(),
+    };
+    assert_eq!(transaction.pool_events().unwrap(), Vec::new());
+
+    let transfer = PrivateTransferCompleted {
+        nullifiers_spent: 2,
+        notes_created: 2,
+        slot: 4,
+        timestamp: 0,
+        nullifier_hashes: [[0u8; 32], nullifier(7)],
+    };
+    let events = whistle_replay::decode_event(&transfer.data()).unwrap();
+    assert_eq!(events, vec![whistle_replay::PoolEvent::NullifierSpent { nullifier_hash: nullifier(7), slot: 4 }]);
+}
+
+#[test]
+fn token_notes_share_the_tree_and_nullifier_set() {
+    let mint = Pubkey::new_unique();
+    let shielded = TokenShielded { mint, commitment: commitment(1), leaf_index: 3, amount: 5, slot: 1, timestamp: 0 };
+    let events = whistle_replay::decode_event(&shielded.data()).unwrap();
+    assert_eq!(events, vec![whistle_replay::PoolEvent::LeafInserted { commitment: commitment(1), leaf_index: 3 }]);
+
+    let unshielded = TokenUnshielded {
+        mint,
+        nullifier_hash: nullifier(1),
+        amount: 5,
+        has_change: false,
+        slot: 2,
+        timestamp: 0,
+    };
+    let events = whistle_replay::decode_event(&unshielded.data()).unwrap();
+    assert_eq!(events, vec![whistle_replay::PoolEvent::NullifierSpent { nullifier_hash: nullifier(1), slot: 2 }]);
+}
+
+#[test]
+fn truncated_logs_and_legacy_transfer_events_are_rejected() {
+    let mut transaction = LoggedTransaction {
+        signature: String::new(),
+        slot: 8,
+        logs: logs(&whistle_pool::ID, &[]),
+        instructions: Vec::new(),
+    };
+    transaction.logs.insert(2, "Log truncated".to_string());
+    assert!(matches!(transaction.pool_events(), Err(ReplayError::LogsTruncated { slot: 8 })));
+
+    // PrivateTransferCompleted before it carried its nullifier hashes
+    let mut legacy = PrivateTransferCompleted::DISCRIMINATOR.to_vec();
+    (2u8, 2u8, 8u64, 0i64).serialize(&mut legacy).unwrap();
+    let transaction = LoggedTransaction {
+        signature: String::new(),
+        slot: 8,
+        logs: logs(&whistle_pool::ID, &[legacy]),
+        instructions: Vec::new(),
+    };
+    assert!(matches!(transaction.pool_events(), Err(ReplayError::UndecodableEvent { slot: 8 })));
+}
+
+#[test]
+fn batch_shield_leaves_come_from_the_instruction() {
+    let chain = history();
+    let batch = chain.transactions.last().unwrap();
+    let leaves: Vec<_> = (5..8)
+        .zip(6..)
+        .map(|(leaf_index, n)| whistle_replay::PoolEvent::LeafInserted { commitment: commitment(n), leaf_index })
+        .collect();
+    assert_eq!(batch.pool_events().unwrap(), leaves);
+
+    // Every intermediate root was published
+    let mut model = PoolModel::new(LEVELS);
+    replay(&mut model, &chain.transactions, Some(5)).unwrap();
+    let mut roots = Vec::new();
+    for event in leaves {
+        model.apply(event).unwrap();
+        roots.push(model.current_root());
+    }
+    assert!(roots.iter().all(|root| RootsRing::new(&chain.roots[..]).unwrap().contains(root)));
+
+    // A recording without the instruction cannot place the leaves
+    let mut stripped = batch.clone();
+    stripped.instructions.clear();
+    assert!(matches!(stripped.pool_events(), Err(ReplayError::BatchCommitmentsMissing { first_leaf_index: 5 })));
+}
@@ -0,0 +1,411 @@
+// WHISTLE PROTOCOL - SEALED-BID NFT AUCTIONS
+//
+// The seller escrows an NFT and commits to a hidden reserve price. Bidders
+// escrow a public `max_bid` alongside a sealed bid:
+// - bid commitment     = keccak("whistle-auction-bid" || bid_amount || randomness)
+// - reserve commitment = keccak("whistle-auction-reserve" || reserve || randomness)
+//
+// After the deadline the seller reveals the reserve, and during the settle
+// window bidders who beat it open their bids. The highest opened bid wins.
+// Everyone else reclaims their escrow as a shielded note (change_commitment),
+// so a losing bid is never revealed.
+//
+// At finalization the winner's bid goes to the seller, the rest of their
+// escrow is shielded as their change note, and the NFT goes to the address
+// the winner named. Refund notes are shielded through the same book_shield
+// as `shield` and pay the normal protocol fee. The escrow is counted
+// against the bidder's deposit caps when it is bid, so a refund can never
+// be refused for them.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::AccountsClose;
+
+use crate::{
+    book_shield, record_deposit, split_protocol_fee, AuctionFinalized, AuctionOpened, BidOpened, BidReclaimed,
+    BidSubmitted, ChangeCreated, DepositHistogram, FinalizeAuction, LeafAccounts, OpenAuction, PoolState, PoolStats,
+    ReserveRevealed, RevealReserve, SettleBid, SubmitBid, WhistleError, CURVE_BN254, MIN_DEPOSIT,
+};
+
+/// Slots after the deadline during which bids can be opened (~10 minutes)
+pub const AUCTION_SETTLE_SLOTS: u64 = 1_500;
+
+/// Domain separators for the sealed commitments
+pub const AUCTION_BID_DOMAIN: &[u8] = b"whistle-auction-bid";
+pub const AUCTION_RESERVE_DOMAIN: &[u8] = b"whistle-auction-reserve";
+
+/// Sealed bid commitment for `bid_amount`
+pub fn bid_commitment(bid_amount: u64, randomness: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[AUCTION_BID_DOMAIN, &bid_amount.to_le_bytes(), randomness]).to_bytes()
+}
+
+/// Reserve price commitment for `reserve`
+pub fn reserve_commitment(reserve: u64, randomness: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[AUCTION_RESERVE_DOMAIN, &reserve.to_le_bytes(), randomness]).to_bytes()
+}
+
+// ============================================================================
+// INSTRUCTIONS
+// ============================================================================
+
+pub fn open_auction(
+    ctx: Context<OpenAuction>,
+    nft_mint: Pubkey,
+    reserve_commitment: [u8; 32],
+    deadline_slot: u64,
+) -> Result<()> {
+    require!(deadline_slot > Clock::get()?.slot, WhistleError::InvalidAuctionDeadline);
+
+    let auction_key = ctx.accounts.auction.key();
+    let escrow = unpack_token_account(&ctx.accounts.nft_escrow)?;
+    require!(
+        escrow.mint == nft_mint && escrow.owner == auction_key,
+        WhistleError::InvalidTokenAccount
+    );
+
+    // Move the NFT into escrow under the auction PDA
+    invoke(
+        &spl_token::instruction::transfer(
+            &spl_token::id(),
+            ctx.accounts.seller_nft_account.key,
+            ctx.accounts.nft_escrow.key,
+            ctx.accounts.seller.key,
+            &[],
+            1,
+        )?,
+        &[
+            ctx.accounts.seller_nft_account.to_account_info(),
+            ctx.accounts.nft_escrow.to_account_info(),
+            ctx.accounts.seller.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        ],
+    )?;
+
+    let auction = &mut ctx.accounts.auction;
+    auction.seller = ctx.accounts.seller.key();
+    auction.nft_mint = nft_mint;
+    auction.nft_escrow = ctx.accounts.nft_escrow.key();
+    auction.reserve_commitment = reserve_commitment;
+    auction.deadline_slot = deadline_slot;
+    auction.reserve = 0;
+    auction.reserve_revealed = false;
+    auction.bid_count = 0;
+    auction.leading_bid = Pubkey::default();
+    auction.leading_amount = 0;
+    auction.leading_change_commitment = [0u8; 32];
+    auction.nft_recipient = Pubkey::default();
+    auction.finalized = false;
+    auction.bump = ctx.bumps.auction;
+
+    emit!(AuctionOpened {
+        auction: auction_key,
+        nft_mint,
+        deadline_slot,
+    });
+
+    Ok(())
+}
+
+pub fn submit_bid(ctx: Context<SubmitBid>, bid_commitment: [u8; 32], max_bid: u64) -> Result<()> {
+    require!(
+        Clock::get()?.slot < ctx.accounts.auction.deadline_slot,
+        WhistleError::AuctionClosed
+    );
+    require!(max_bid >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+
+    // Whatever the escrow's outcome, at most max_bid of it is shielded
+    let (_, net_amount) = split_protocol_fee(max_bid)?;
+    record_deposit(&ctx.accounts.pool, &mut ctx.accounts.deposit_record, ctx.accounts.bidder.key(), net_amount)?;
+
+    // The bid PDA holds the escrow on top of its rent
+    anchor_lang::system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.bidder.to_account_info(),
+                to: ctx.accounts.bid.to_account_info(),
+            },
+        ),
+        max_bid,
+    )?;
+
+    let bid = &mut ctx.accounts.bid;
+    bid.auction = ctx.accounts.auction.key();
+    bid.bidder = ctx.accounts.bidder.key();
+    bid.bid_commitment = bid_commitment;
+    bid.max_bid = max_bid;
+    bid.bump = ctx.bumps.bid;
+
+    let auction = &mut ctx.accounts.auction;
+    auction.bid_count = auction.bid_count.checked_add(1)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+
+    emit!(BidSubmitted {
+        auction: auction.key(),
+        bid_commitment,
+        max_bid,
+    });
+
+    Ok(())
+}
+
+pub fn reveal_reserve(ctx: Context<RevealReserve>, reserve: u64, randomness: [u8; 32]) -> Result<()> {
+    let auction = &mut ctx.accounts.auction;
+    require!(Clock::get()?.slot >= auction.deadline_slot, WhistleError::AuctionNotEnded);
+    require!(!auction.reserve_revealed, WhistleError::ReserveAlreadyRevealed);
+    require!(
+        reserve_commitment(reserve, &randomness) == auction.reserve_commitment,
+        WhistleError::CommitmentOpeningMismatch
+    );
+
+    auction.reserve = reserve;
+    auction.reserve_revealed = true;
+
+    emit!(ReserveRevealed {
+        auction: auction.key(),
+        reserve,
+    });
+
+    Ok(())
+}
+
+/// Settle a bid
+///
+/// Winner claim (`is_winner`): opens the sealed bid during the settle window.
+/// It must meet the reserve and beat the current leader; funds stay in escrow
+/// until finalization. `change_commitment` will receive the escrow left over
+/// after paying the bid, and `nft_recipient` the NFT.
+///
+/// Reclaim (`!is_winner`): shields the whole escrow as `change_commitment`
+/// without opening the bid. Not available to the current leader.
+pub fn settle_bid(
+    ctx: Context<SettleBid>,
+    bid_amount: u64,
+    randomness: [u8; 32],
+    is_winner: bool,
+    change_commitment: [u8; 32],
+    nft_recipient: Pubkey,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let auction = &mut ctx.accounts.auction;
+    let bid_key = ctx.accounts.bid.key();
+    let max_bid = ctx.accounts.bid.max_bid;
+
+    require!(slot >= auction.deadline_slot, WhistleError::AuctionNotEnded);
+
+    if is_winner {
+        require!(
+            slot < auction.deadline_slot.saturating_add(AUCTION_SETTLE_SLOTS),
+            WhistleError::SettleWindowClosed
+        );
+        require!(auction.reserve_revealed, WhistleError::ReserveNotRevealed);
+        require!(
+            bid_commitment(bid_amount, &randomness) == ctx.accounts.bid.bid_commitment,
+            WhistleError::CommitmentOpeningMismatch
+        );
+        require!(bid_amount <= max_bid, WhistleError::BidExceedsEscrow);
+        require!(bid_amount >= auction.reserve, WhistleError::BidBelowReserve);
+        require!(
+            auction.leading_bid == Pubkey::default() || bid_amount > auction.leading_amount,
+            WhistleError::BidNotHighest
+        );
+        // Leftover escrow must either be zero or go to a change note
+        require!(
+            (bid_amount == max_bid) == (change_commitment == [0u8; 32]),
+            WhistleError::InvalidCommitment
+        );
+        require!(nft_recipient != Pubkey::default(), WhistleError::InvalidRecipient);
+
+        // The previous leader becomes a loser and can now reclaim
+        auction.leading_bid = bid_key;
+        auction.leading_amount = bid_amount;
+        auction.leading_change_commitment = change_commitment;
+        auction.nft_recipient = nft_recipient;
+
+        emit!(BidOpened {
+            auction: auction.key(),
+            bid: bid_key,
+            bid_amount,
+        });
+
+        // The bid account stays open: finalization pays out of it
+        return Ok(());
+    }
+
+    require!(auction.leading_bid != bid_key, WhistleError::LeadingBidCannotReclaim);
+    require!(change_commitment != [0u8; 32], WhistleError::InvalidCommitment);
+
+    let bid_info = ctx.accounts.bid.to_account_info();
+    shield_from_escrow(
+        &bid_info,
+        &mut ctx.accounts.pool,
+        LeafAccounts {
+            merkle_tree: &ctx.accounts.merkle_tree,
+            roots_history: &ctx.accounts.roots_history,
+            leaf_page: Some(&ctx.accounts.leaf_page),
+            commitment_marker: &ctx.accounts.commitment_marker,
+            payer: ctx.accounts.bidder.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        },
+        &ctx.accounts.pool_stats,
+        &mut ctx.accounts.deposit_histogram,
+        &ctx.accounts.pool_vault,
+        &ctx.accounts.fee_vault,
+        change_commitment,
+        max_bid,
+    )?;
+
+    // Return the rent to the bidder
+    ctx.accounts.bid.close(ctx.accounts.bidder.to_account_info())?;
+
+    emit!(BidReclaimed {
+        auction: auction.key(),
+        bid: bid_key,
+    });
+
+    Ok(())
+}
+
+/// Finalize after the settle window: pay the seller and deliver the NFT,
+/// or return the NFT to the seller if nobody won
+pub fn finalize_auction(ctx: Context<FinalizeAuction>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+    require!(!auction.finalized, WhistleError::AuctionAlreadyFinalized);
+    require!(
+        Clock::get()?.slot >= auction.deadline_slot.saturating_add(AUCTION_SETTLE_SLOTS),
+        WhistleError::SettleWindowOpen
+    );
+
+    let has_winner = auction.leading_bid != Pubkey::default();
+    let nft_owner = if has_winner { auction.nft_recipient } else { auction.seller };
+    let destination = unpack_token_account(&ctx.accounts.nft_destination)?;
+    require!(
+        destination.mint == auction.nft_mint && destination.owner == nft_owner,
+        WhistleError::InvalidTokenAccount
+    );
+
+    if has_winner {
+        let bid = ctx.accounts.leading_bid.as_ref().ok_or(WhistleError::InvalidAuctionBid)?;
+        require!(bid.key() == auction.leading_bid, WhistleError::InvalidAuctionBid);
+        let bidder = ctx.accounts.leading_bidder.as_ref().ok_or(WhistleError::InvalidAuctionBid)?;
+        require!(bidder.key() == bid.bidder, WhistleError::InvalidAuctionBid);
+
+        let bid_info = bid.to_account_info();
+        let amount = auction.leading_amount;
+        let change = bid.max_bid.checked_sub(amount)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+
+        move_lamports(&bid_info, &ctx.accounts.seller.to_account_info(), amount)?;
+
+        if change > 0 {
+            shield_from_escrow(
+                &bid_info,
+                &mut ctx.accounts.pool,
+                LeafAccounts {
+                    merkle_tree: &ctx.accounts.merkle_tree,
+                    roots_history: &ctx.accounts.roots_history,
+                    leaf_page: Some(&ctx.accounts.leaf_page),
+                    commitment_marker: &ctx.accounts.commitment_marker,
+                    payer: ctx.accounts.payer.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                },
+                &ctx.accounts.pool_stats,
+                &mut ctx.accounts.deposit_histogram,
+                &ctx.accounts.pool_vault,
+                &ctx.accounts.fee_vault,
+                auction.leading_change_commitment,
+                change,
+            )?;
+        }
+
+        // Return the rent to the winning bidder
+        bid.close(bidder.to_account_info())?;
+    }
+
+    let auction_seeds: &[&[u8]] = &[b"auction", auction.nft_mint.as_ref(), &[auction.bump]];
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            &spl_token::id(),
+            ctx.accounts.nft_escrow.key,
+            ctx.accounts.nft_destination.key,
+            &ctx.accounts.auction.key(),
+            &[],
+            1,
+        )?,
+        &[
+            ctx.accounts.nft_escrow.to_account_info(),
+            ctx.accounts.nft_destination.to_account_info(),
+            ctx.accounts.auction.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        ],
+        &[auction_seeds],
+    )?;
+
+    let auction = &mut ctx.accounts.auction;
+    auction.finalized = true;
+
+    let clock = Clock::get()?;
+    emit!(AuctionFinalized {
+        auction: auction.key(),
+        winning_amount: if has_winner { auction.leading_amount } else { 0 },
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// ============================================================================
+// HELPERS
+// ============================================================================
+
+fn unpack_token_account(info: &AccountInfo) -> Result<spl_token::state::Account> {
+    require!(*info.owner == spl_token::id(), WhistleError::InvalidTokenAccount);
+    spl_token::state::Account::unpack(&info.try_borrow_data()?)
+        .map_err(|_| error!(WhistleError::InvalidTokenAccount))
+}
+
+/// Debit a program-owned escrow account directly
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    **from.try_borrow_mut_lamports()? = from.lamports().checked_sub(amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    **to.try_borrow_mut_lamports()? = to.lamports().checked_add(amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    Ok(())
+}
+
+/// Shield `amount` from a bid escrow into the pool as `commitment`,
+/// charging the same protocol fee as `shield`
+fn shield_from_escrow<'info>(
+    escrow: &AccountInfo<'info>,
+    pool: &mut Account<'info, PoolState>,
+    leaf: LeafAccounts<'_, 'info>,
+    pool_stats: &AccountLoader<'info, PoolStats>,
+    deposit_histogram: &mut DepositHistogram,
+    pool_vault: &SystemAccount<'info>,
+    fee_vault: &SystemAccount<'info>,
+    commitment: [u8; 32],
+    amount: u64,
+) -> Result<()> {
+    require!(pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    let (protocol_fee, net_amount) = split_protocol_fee(amount)?;
+
+    move_lamports(escrow, &pool_vault.to_account_info(), net_amount)?;
+    if protocol_fee > 0 {
+        move_lamports(escrow, &fee_vault.to_account_info(), protocol_fee)?;
+    }
+
+    let leaf_index = book_shield(pool, leaf, pool_stats, deposit_histogram, commitment, amount, protocol_fee)?;
+
+    let clock = Clock::get()?;
+    emit!(ChangeCreated {
+        commitment,
+        leaf_index,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
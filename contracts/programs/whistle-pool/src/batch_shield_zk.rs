@@ -0,0 +1,105 @@
+// WHISTLE PROTOCOL - ZK BATCH SHIELD
+//
+// shield_batch_zk splits one deposit into eight notes under a single
+// batch_shield proof, which shows that every commitment is a standard note
+// and that the note amounts sum to the public total. The pool only sees the
+// total, so the split stays private.
+//
+// Only compiled with the `batch-shield-zk` feature, which needs the
+// batch_shield verification key pasted into groth16.rs.
+
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+
+#[cfg(not(feature = "test-harness"))]
+use crate::groth16::{vk_is_generated, BATCH_SHIELD_VK_ALPHA_G1};
+use crate::public_inputs::require_canonical_field_element;
+use crate::{
+    insert_batch, record_batch_commitments, record_deposit, verify_batch_shield_proof, Shield, WhistleError,
+    BPS_DENOMINATOR, CURVE_BN254, MIN_DEPOSIT, PROTOCOL_FEE_BPS,
+};
+
+// The test proof backend stands in for the key in test-harness builds
+#[cfg(not(feature = "test-harness"))]
+const _: () = assert!(
+    vk_is_generated(&BATCH_SHIELD_VK_ALPHA_G1),
+    "the batch-shield-zk feature needs the batch_shield verification key in groth16.rs"
+);
+
+pub fn shield_batch_zk<'info>(
+    ctx: Context<'_, '_, '_, 'info, Shield<'info>>,
+    proof_a: [u8; 64],
+    proof_b: [u8; 128],
+    proof_c: [u8; 64],
+    commitments: [[u8; 32]; 8],
+    total_amount: u64,
+) -> Result<()> {
+    require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    require!(total_amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+    for commitment in &commitments {
+        require!(*commitment != [0u8; 32], WhistleError::ZeroCommitment);
+        require_canonical_field_element(commitment)?;
+    }
+    
+    let proof_valid = verify_batch_shield_proof(&proof_a, &proof_b, &proof_c, &commitments, total_amount)?;
+    require!(proof_valid, WhistleError::InvalidProof);
+    
+    let accounts = ctx.accounts;
+    let pool = &mut accounts.pool;
+    let max_leaves = 1u64 << pool.merkle_levels;
+    let end_index = pool.next_index.checked_add(commitments.len() as u64)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    require!(end_index <= max_leaves, WhistleError::TreeFull);
+    record_batch_commitments(
+        &accounts.commitment_marker,
+        ctx.remaining_accounts,
+        &accounts.depositor.to_account_info(),
+        &accounts.system_program.to_account_info(),
+        &commitments,
+        pool.next_index,
+    )?;
+    
+    let protocol_fee = total_amount.checked_mul(PROTOCOL_FEE_BPS)
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    let net_amount = total_amount.checked_sub(protocol_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    
+    record_deposit(pool, &mut accounts.deposit_record, accounts.depositor.key(), net_amount)?;
+    
+    system_program::transfer(
+        CpiContext::new(
+            accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: accounts.depositor.to_account_info(),
+                to: accounts.pool_vault.to_account_info(),
+            },
+        ),
+        net_amount,
+    )?;
+    if protocol_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: accounts.depositor.to_account_info(),
+                    to: accounts.fee_vault.to_account_info(),
+                },
+            ),
+            protocol_fee,
+        )?;
+        pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+    }
+    
+    insert_batch(pool, &accounts.merkle_tree, &accounts.roots_history, &accounts.leaf_page, &commitments, net_amount)?;
+    pool.total_deposits = pool.total_deposits.checked_add(net_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    pool.total_shielded = pool.total_shielded.checked_add(net_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    
+    accounts.pool_stats.load_mut()?.record_shield(Clock::get()?.slot, net_amount);
+    accounts.deposit_histogram.record(total_amount);
+    Ok(())
+}
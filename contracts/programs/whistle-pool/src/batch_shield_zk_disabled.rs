@@ -0,0 +1,20 @@
+// WHISTLE PROTOCOL - ZK BATCH SHIELD (DISABLED)
+//
+// Stand-in for batch_shield_zk.rs when the `batch-shield-zk` feature is
+// off. The instruction stays in the program interface but always fails
+// with VerifyingKeyNotGenerated.
+
+use anchor_lang::prelude::*;
+
+use crate::{Shield, WhistleError};
+
+pub fn shield_batch_zk<'info>(
+    _ctx: Context<'_, '_, '_, 'info, Shield<'info>>,
+    _proof_a: [u8; 64],
+    _proof_b: [u8; 128],
+    _proof_c: [u8; 64],
+    _commitments: [[u8; 32]; 8],
+    _total_amount: u64,
+) -> Result<()> {
+    err!(WhistleError::VerifyingKeyNotGenerated)
+}
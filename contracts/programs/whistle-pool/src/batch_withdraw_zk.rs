@@ -0,0 +1,182 @@
+// WHISTLE PROTOCOL - ZK BATCH WITHDRAW
+//
+// batch_withdraw_zk spends four standard notes to one recipient under a
+// single batch_withdraw proof, which checks each note as withdraw_merkle
+// does against one common root.
+//
+// Only compiled with the `batch-withdraw-zk` feature, which needs the
+// batch_withdraw verification key pasted into groth16.rs.
+
+use anchor_lang::prelude::*;
+
+#[cfg(not(feature = "test-harness"))]
+use crate::groth16::{vk_is_generated, BATCH_WITHDRAW_VK_ALPHA_G1};
+use crate::public_inputs::{pubkey_to_field, require_canonical_field_element};
+use crate::{
+    check_relayer, is_program_address, verify_batch_withdraw_proof, BatchWithdrawZk, BatchWithdrawn, NullifierMarker,
+    RootsHistory, WhistleError, BPS_DENOMINATOR, CURVE_BN254, PROTOCOL_FEE_BPS,
+};
+
+// The test proof backend stands in for the key in test-harness builds
+#[cfg(not(feature = "test-harness"))]
+const _: () = assert!(
+    vk_is_generated(&BATCH_WITHDRAW_VK_ALPHA_G1),
+    "the batch-withdraw-zk feature needs the batch_withdraw verification key in groth16.rs"
+);
+
+pub fn batch_withdraw_zk(
+    ctx: Context<BatchWithdrawZk>,
+    proof_a: [u8; 64],
+    proof_b: [u8; 128],
+    proof_c: [u8; 64],
+    nullifiers: [[u8; 32]; 4],
+    amounts: [u64; 4],
+    recipient: Pubkey,
+    relayer_fee: u64,
+    merkle_root: [u8; 32],
+) -> Result<()> {
+    require!(ctx.accounts.unshield.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    let batch = ctx.accounts;
+    let accounts = &mut batch.unshield;
+    require!(recipient == accounts.recipient.key(), WhistleError::InvalidRecipient);
+
+    // max_relayer_fee also rejects amounts that are not denominations
+    let mut total_amount = 0u64;
+    let mut max_fee = 0u64;
+    for amount in amounts {
+        max_fee = max_fee.checked_add(accounts.denomination_config.max_relayer_fee(amount)?)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        total_amount = total_amount.checked_add(amount)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+    }
+    require!(relayer_fee <= max_fee, WhistleError::FeeTooHigh);
+    check_relayer(accounts.relayer.as_ref(), relayer_fee, false)?;
+
+    for nullifier_hash in &nullifiers {
+        require!(*nullifier_hash != [0u8; 32], WhistleError::ZeroNullifierHash);
+        require_canonical_field_element(nullifier_hash)?;
+    }
+
+    let pool = &mut accounts.pool;
+    let nullifier_set = accounts.nullifiers.load()?;
+    let markers = [
+        &accounts.nullifier_marker,
+        &batch.nullifier_marker_1,
+        &batch.nullifier_marker_2,
+        &batch.nullifier_marker_3,
+    ];
+
+    for (nullifier_hash, marker) in nullifiers.iter().zip(markers) {
+        NullifierMarker::require_unspent(&nullifier_set, marker, nullifier_hash)?;
+    }
+
+    require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+    require!(pool.next_index > 0, WhistleError::TreeEmpty);
+    RootsHistory::ring(&accounts.roots_history)?.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
+
+    // Prepare recipient as field element (truncate to 31 bytes to fit BN254 field)
+    let recipient_field = pubkey_to_field(&recipient.to_bytes());
+
+    let proof_valid = verify_batch_withdraw_proof(
+        &proof_a,
+        &proof_b,
+        &proof_c,
+        &merkle_root,
+        &nullifiers,
+        &amounts,
+        &recipient_field,
+        relayer_fee,
+    )?;
+
+    require!(proof_valid, WhistleError::InvalidProof);
+
+    // Create the markers; a note filling two slots of the batch finds
+    // its marker already created by the first
+    let payer = accounts.payer.to_account_info();
+    let system_program = accounts.system_program.to_account_info();
+    for (nullifier_hash, marker) in nullifiers.iter().zip(markers) {
+        NullifierMarker::spend(&nullifier_set, marker, &payer, &system_program, nullifier_hash)?;
+    }
+    drop(nullifier_set);
+    accounts.congestion.record_withdrawals(Clock::get()?.slot, nullifiers.len() as u32);
+
+    let vault_balance = accounts.pool_vault.lamports();
+    require!(vault_balance >= total_amount, WhistleError::InsufficientVaultBalance);
+
+    let protocol_fee = total_amount.checked_mul(PROTOCOL_FEE_BPS)
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    let withdrawal_net = total_amount
+        .checked_sub(relayer_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        .checked_sub(protocol_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    let vault_bump = pool.vault_bump;
+    let vault_seeds: &[&[u8]] = &[b"vault", &[vault_bump]];
+
+    anchor_lang::solana_program::program::invoke_signed(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            accounts.pool_vault.key,
+            accounts.recipient.key,
+            withdrawal_net,
+        ),
+        &[
+            accounts.pool_vault.to_account_info(),
+            accounts.recipient.to_account_info(),
+            accounts.system_program.to_account_info(),
+        ],
+        &[vault_seeds],
+    )?;
+
+    if let (true, Some(relayer)) = (relayer_fee > 0, &accounts.relayer) {
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                accounts.pool_vault.key,
+                relayer.key,
+                relayer_fee,
+            ),
+            &[
+                accounts.pool_vault.to_account_info(),
+                relayer.to_account_info(),
+                accounts.system_program.to_account_info(),
+            ],
+            &[vault_seeds],
+        )?;
+    }
+
+    if protocol_fee > 0 {
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                accounts.pool_vault.key,
+                accounts.fee_vault.key,
+                protocol_fee,
+            ),
+            &[
+                accounts.pool_vault.to_account_info(),
+                accounts.fee_vault.to_account_info(),
+                accounts.system_program.to_account_info(),
+            ],
+            &[vault_seeds],
+        )?;
+
+        pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+    }
+
+    pool.total_shielded = pool.total_shielded
+        .checked_sub(total_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+
+    let clock = Clock::get()?;
+    emit!(BatchWithdrawn {
+        nullifier_hashes: nullifiers,
+        amounts,
+        protocol_fee,
+        recipient_is_pda: is_program_address(&recipient),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
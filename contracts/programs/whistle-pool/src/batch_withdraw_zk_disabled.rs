@@ -0,0 +1,23 @@
+// WHISTLE PROTOCOL - ZK BATCH WITHDRAW (DISABLED)
+//
+// Stand-in for batch_withdraw_zk.rs when the `batch-withdraw-zk` feature
+// is off. The instruction stays in the program interface but always fails
+// with VerifyingKeyNotGenerated.
+
+use anchor_lang::prelude::*;
+
+use crate::{BatchWithdrawZk, WhistleError};
+
+pub fn batch_withdraw_zk(
+    _ctx: Context<BatchWithdrawZk>,
+    _proof_a: [u8; 64],
+    _proof_b: [u8; 128],
+    _proof_c: [u8; 64],
+    _nullifiers: [[u8; 32]; 4],
+    _amounts: [u64; 4],
+    _recipient: Pubkey,
+    _relayer_fee: u64,
+    _merkle_root: [u8; 32],
+) -> Result<()> {
+    err!(WhistleError::VerifyingKeyNotGenerated)
+}
@@ -0,0 +1,121 @@
+// WHISTLE PROTOCOL - DEVNET POOL SEEDING
+//
+// Fills a devnet pool with notes whose secrets anyone can recompute, so QA
+// can build realistic anonymity sets in one instruction and later spend the
+// seeded notes from tests. For note `i` of a batch seeded with `seed`:
+// - secret    = sha256("whistle-devnet-secret" || seed || i) with byte 0 cleared
+// - nullifier = sha256("whistle-devnet-nullifier" || seed || i) with byte 0 cleared
+// - amount    = DEVNET_SEED_AMOUNTS[i % 3]
+// - commitment = Poseidon(secret, Poseidon(nullifier, amount))
+//
+// The caller picks `seed`, so it can derive the commitments, and pass their
+// markers, before sending: the first note's marker is the Shield
+// commitment_marker, the others follow as remaining accounts. Reusing a
+// seed fails with DuplicateCommitment. The SDK's fixtures module implements
+// the same derivation.
+//
+// INSECURE: the notes are spendable by anyone. Only compiled with the
+// `insecure-devnet` feature, which must never be enabled for mainnet.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::system_program;
+
+use crate::public_inputs::u64_to_be_field;
+use crate::{
+    book_shield, merkle_hash, DevnetPoolSeeded, LeafAccounts, Shield, WhistleError, DENOM_001_SOL, DENOM_005_SOL,
+    DENOM_01_SOL, CURVE_BN254,
+};
+
+/// Most notes per call (each insertion costs a full Merkle path of Poseidon
+/// hashes, and each note's marker is an account of the transaction)
+pub const DEVNET_SEED_MAX_NOTES: u8 = 16;
+
+/// Seeded note amounts, cycled by index
+pub const DEVNET_SEED_AMOUNTS: [u64; 3] = [DENOM_001_SOL, DENOM_005_SOL, DENOM_01_SOL];
+
+pub const DEVNET_SECRET_DOMAIN: &[u8] = b"whistle-devnet-secret";
+pub const DEVNET_NULLIFIER_DOMAIN: &[u8] = b"whistle-devnet-nullifier";
+
+/// Deterministic field element for note `index` of a batch seeded with `seed`
+pub fn derive_seed_field(domain: &[u8], seed: u64, index: u8) -> [u8; 32] {
+    let mut field = hashv(&[domain, &seed.to_le_bytes(), &[index]]).to_bytes();
+    // Clear the top byte so the value is below the BN254 field modulus
+    field[0] = 0;
+    field
+}
+
+/// Commitment of seeded note `index`
+pub fn seeded_note_commitment(seed: u64, index: u8) -> [u8; 32] {
+    let secret = derive_seed_field(DEVNET_SECRET_DOMAIN, seed, index);
+    let nullifier = derive_seed_field(DEVNET_NULLIFIER_DOMAIN, seed, index);
+
+    let amount = u64_to_be_field(DEVNET_SEED_AMOUNTS[index as usize % 3]);
+
+    merkle_hash(&secret, &merkle_hash(&nullifier, &amount))
+}
+
+pub fn devnet_seed_pool<'info>(ctx: Context<'_, '_, '_, 'info, Shield<'info>>, count: u8, seed: u64) -> Result<()> {
+    require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    require!(
+        count > 0 && count <= DEVNET_SEED_MAX_NOTES,
+        WhistleError::InvalidSeedCount
+    );
+    require!(ctx.remaining_accounts.len() + 1 == count as usize, WhistleError::InvalidCommitmentMarker);
+
+    let slot = Clock::get()?.slot;
+    let accounts = ctx.accounts;
+    let pool = &mut accounts.pool;
+
+    let max_leaves = 1u64 << pool.merkle_levels;
+    require!(
+        pool.next_index + count as u64 <= max_leaves,
+        WhistleError::TreeFull
+    );
+
+    let total: u64 = (0..count).map(|i| DEVNET_SEED_AMOUNTS[i as usize % 3]).sum();
+
+    // Seeded notes are backed by the caller's lamports; no protocol fee on devnet
+    system_program::transfer(
+        CpiContext::new(
+            accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: accounts.depositor.to_account_info(),
+                to: accounts.pool_vault.to_account_info(),
+            },
+        ),
+        total,
+    )?;
+
+    let first_leaf_index = pool.next_index;
+    let markers = std::iter::once(&accounts.commitment_marker).chain(ctx.remaining_accounts);
+    for (i, marker) in (0..count).zip(markers) {
+        let amount = DEVNET_SEED_AMOUNTS[i as usize % 3];
+        book_shield(
+            pool,
+            LeafAccounts {
+                merkle_tree: &accounts.merkle_tree,
+                roots_history: &accounts.roots_history,
+                leaf_page: Some(&accounts.leaf_page),
+                commitment_marker: marker,
+                payer: accounts.depositor.to_account_info(),
+                system_program: accounts.system_program.to_account_info(),
+            },
+            &accounts.pool_stats,
+            &mut accounts.deposit_histogram,
+            seeded_note_commitment(seed, i),
+            amount,
+            0,
+        )?;
+    }
+
+    emit!(DevnetPoolSeeded {
+        slot,
+        seed,
+        first_leaf_index,
+        count,
+        total_amount: total,
+    });
+
+    Ok(())
+}
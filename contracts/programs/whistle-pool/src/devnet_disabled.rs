@@ -0,0 +1,12 @@
+// WHISTLE PROTOCOL - DEVNET POOL SEEDING (DISABLED)
+//
+// Stand-in for devnet.rs when the `insecure-devnet` feature is off. The
+// instruction stays in the program interface but always fails with DevnetOnly.
+
+use anchor_lang::prelude::*;
+
+use crate::{Shield, WhistleError};
+
+pub fn devnet_seed_pool<'info>(_ctx: Context<'_, '_, '_, 'info, Shield<'info>>, _count: u8, _seed: u64) -> Result<()> {
+    err!(WhistleError::DevnetOnly)
+}
@@ -0,0 +1,60 @@
+// WHISTLE PROTOCOL - SELECTIVE AMOUNT DISCLOSURE
+//
+// reveal_note_amount proves the amount held in an unspent note to an
+// auditor: the amount_reveal circuit opens
+// commitment = Poseidon(secret, Poseidon(nullifier, amount)) with the
+// commitment, amount and auditor public. The nullifier hash stays private,
+// so the disclosure cannot be linked to the note's eventual withdrawal.
+//
+// Only compiled with the `amount-reveal` feature, which needs the
+// amount_reveal verification key pasted into groth16.rs.
+
+use anchor_lang::prelude::*;
+
+use crate::groth16::{get_amount_reveal_vk, verify_amount_reveal_proof, vk_is_generated, AMOUNT_REVEAL_VK_ALPHA_G1};
+use crate::{NoteAmountRevealed, RevealNoteAmount, WhistleError};
+
+const _: () = assert!(
+    vk_is_generated(&AMOUNT_REVEAL_VK_ALPHA_G1),
+    "the amount-reveal feature needs the amount_reveal verification key in groth16.rs"
+);
+
+pub fn reveal_note_amount(
+    ctx: Context<RevealNoteAmount>,
+    proof_a: [u8; 64],
+    proof_b: [u8; 128],
+    proof_c: [u8; 64],
+    commitment: [u8; 32],
+    amount: u64,
+    auditor_pubkey: [u8; 32],
+) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    
+    // Only notes that are actually in the pool can be disclosed
+    let in_tree = {
+        let merkle_tree = ctx.accounts.merkle_tree.load()?;
+        merkle_tree.contains_leaf(&commitment, pool.next_index, pool.merkle_levels)
+    };
+    require!(in_tree, WhistleError::CommitmentNotFound);
+    
+    let proof_valid = verify_amount_reveal_proof(
+        &get_amount_reveal_vk(),
+        &proof_a,
+        &proof_b,
+        &proof_c,
+        &commitment,
+        amount,
+        &auditor_pubkey,
+    )?;
+    
+    require!(proof_valid, WhistleError::InvalidProof);
+    
+    emit!(NoteAmountRevealed {
+        commitment,
+        amount,
+        auditor_pubkey,
+        slot: Clock::get()?.slot,
+    });
+    
+    Ok(())
+}
@@ -0,0 +1,21 @@
+// WHISTLE PROTOCOL - SELECTIVE AMOUNT DISCLOSURE (DISABLED)
+//
+// Stand-in for disclosure.rs when the `amount-reveal` feature is off. The
+// instruction stays in the program interface but always fails with
+// VerifyingKeyNotGenerated.
+
+use anchor_lang::prelude::*;
+
+use crate::{RevealNoteAmount, WhistleError};
+
+pub fn reveal_note_amount(
+    _ctx: Context<RevealNoteAmount>,
+    _proof_a: [u8; 64],
+    _proof_b: [u8; 128],
+    _proof_c: [u8; 64],
+    _commitment: [u8; 32],
+    _amount: u64,
+    _auditor_pubkey: [u8; 32],
+) -> Result<()> {
+    err!(WhistleError::VerifyingKeyNotGenerated)
+}
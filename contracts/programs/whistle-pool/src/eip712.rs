@@ -0,0 +1,92 @@
+// WHISTLE PROTOCOL - EIP-712 AUTHORIZATION
+//
+// Typed-data hashes an Ethereum wallet (e.g. a Ledger on its Ethereum app,
+// which cannot sign Solana transactions) signs to authorize an unshield,
+// and secp256k1 recovery of the signing address. The SDK's
+// computeEip712Hash must produce the same bytes.
+//
+// Domain: EIP712Domain(string name,string version,uint256 chainId,bytes32 salt)
+// with the program id as salt, so a signature is bound to one deployment
+// and one cluster.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::secp256k1_recover::secp256k1_recover;
+
+use crate::public_inputs::u64_to_be_field;
+use crate::UnshieldArgs;
+
+pub const EIP712_DOMAIN_NAME: &str = "Whistle Protocol";
+pub const EIP712_DOMAIN_VERSION: &str = "1";
+
+/// Chain id of the domain: the Solana token-list ids, 101 for mainnet-beta
+/// and 103 for devnet
+#[cfg(not(feature = "insecure-devnet"))]
+pub const EIP712_CHAIN_ID: u64 = 101;
+#[cfg(feature = "insecure-devnet")]
+pub const EIP712_CHAIN_ID: u64 = 103;
+
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId,bytes32 salt)";
+const UNSHIELD_TYPE: &str = "Unshield(bytes32 merkleRoot,bytes32 nullifierHash,bytes32 recipient,\
+uint256 withdrawalAmount,uint256 relayerFee,bytes32 changeCommitment,uint256 unlockSlot)";
+const REGISTER_TYPE: &str = "RegisterEthAddress(bytes32 authorized,uint256 nonce)";
+
+fn domain_separator(chain_id: u64) -> [u8; 32] {
+    keccak::hashv(&[
+        &keccak::hash(DOMAIN_TYPE.as_bytes()).to_bytes(),
+        &keccak::hash(EIP712_DOMAIN_NAME.as_bytes()).to_bytes(),
+        &keccak::hash(EIP712_DOMAIN_VERSION.as_bytes()).to_bytes(),
+        &u64_to_be_field(chain_id),
+        crate::ID.as_ref(),
+    ])
+    .to_bytes()
+}
+
+/// keccak256("\x19\x01" || domainSeparator || hashStruct)
+fn typed_data_hash(chain_id: u64, struct_hash: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[b"\x19\x01", &domain_separator(chain_id), struct_hash]).to_bytes()
+}
+
+/// Hash an Ethereum wallet signs to authorize `args`, committing to every
+/// public input of the unshield proof
+pub fn unshield_hash(args: &UnshieldArgs, chain_id: u64) -> [u8; 32] {
+    let struct_hash = keccak::hashv(&[
+        &keccak::hash(UNSHIELD_TYPE.as_bytes()).to_bytes(),
+        &args.merkle_root,
+        &args.nullifier_hash,
+        args.recipient.as_ref(),
+        &u64_to_be_field(args.withdrawal_amount),
+        &u64_to_be_field(args.relayer_fee),
+        &args.change_commitment,
+        &u64_to_be_field(args.unlock_slot),
+    ])
+    .to_bytes();
+    typed_data_hash(chain_id, &struct_hash)
+}
+
+/// Hash an Ethereum wallet signs to map its address to `authorized`;
+/// `nonce` is the mapping's registration count, so an old registration
+/// cannot be replayed over a newer one
+pub fn register_hash(authorized: &Pubkey, nonce: u64, chain_id: u64) -> [u8; 32] {
+    let struct_hash = keccak::hashv(&[
+        &keccak::hash(REGISTER_TYPE.as_bytes()).to_bytes(),
+        authorized.as_ref(),
+        &u64_to_be_field(nonce),
+    ])
+    .to_bytes();
+    typed_data_hash(chain_id, &struct_hash)
+}
+
+/// Address that produced `signature` (r || s || v, v either 0/1 or 27/28)
+/// over `hash`, or None if it does not recover
+pub fn recover_eth_address(hash: &[u8; 32], signature: &[u8; 65]) -> Option<[u8; 20]> {
+    let recovery_id = match signature[64] {
+        v @ (0 | 1) => v,
+        v @ (27 | 28) => v - 27,
+        _ => return None,
+    };
+    let pubkey = secp256k1_recover(hash, recovery_id, &signature[..64]).ok()?;
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&keccak::hash(&pubkey.to_bytes()).to_bytes()[12..]);
+    Some(address)
+}
@@ -5,13 +5,51 @@
 // - withdraw_merkle: Production withdrawal with full Merkle proof
 // - unshield_change: Withdrawal with automatic change re-shielding
 // - private_transfer: Shielded balance transfers
+// - batch_withdraw: Four withdrawals to one recipient in one proof
+// - batch_shield: Eight new notes from one deposit in one proof
+// - unshield_token: SPL token withdrawal with change, bound to the mint
 //
 // Generated verification keys use:
 // - Big-endian byte encoding
 // - Swapped G2 coordinates for Solana alt_bn128 syscalls
+//
+// Verification itself lives in the shared whistle-groth16 crate; this file
+// only holds the keys and lays out each circuit's public inputs.
+
+use whistle_groth16::{Groth16Proof, VerificationKey};
+
+use crate::public_inputs::{pubkey_to_field, u64_to_be_field};
+
+/// Verify a proof in the instruction format (proof_a negated by the client)
+fn verify_proof(
+    vk: &VerificationKey,
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    public_inputs: &[[u8; 32]],
+) -> anchor_lang::Result<bool> {
+    whistle_groth16::verify(vk, &Groth16Proof::from_negated_a(proof_a, proof_b, proof_c), public_inputs)
+        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
+    Ok(true)
+}
 
-use anchor_lang::prelude::*;
-use groth16_solana::groth16::{Groth16Verifier, Groth16Verifyingkey};
+/// Append the unlockSlot public inputs when `vk`'s circuit exposes them
+///
+/// Keys generated before time-locked notes have no unlockSlot inputs. Their
+/// circuits only open standard notes, so they accept only unlock slot zero.
+fn with_unlock_slots(
+    vk: &VerificationKey,
+    public_inputs: &[[u8; 32]],
+    unlock_slots: &[u64],
+) -> Option<Vec<[u8; 32]>> {
+    let mut inputs = public_inputs.to_vec();
+    if vk.ic.len() == public_inputs.len() + unlock_slots.len() + 1 {
+        inputs.extend(unlock_slots.iter().map(|slot| u64_to_be_field(*slot)));
+    } else if unlock_slots.iter().any(|slot| *slot != 0) {
+        return None;
+    }
+    Some(inputs)
+}
 
 // ============================================================================
 // WITHDRAW_SIMPLE (Legacy - for backward compatibility)
@@ -101,16 +139,15 @@ pub const IC_5: [u8; 64] = [
     0xa9, 0x06, 0x3c, 0x5b, 0x97, 0xdb, 0xb4, 0xfc, 0x32, 0x0d, 0xd3, 0x15, 0xb8, 0x66, 0x9e, 0x40,
 ];
 
-pub fn get_withdraw_verifying_key() -> Groth16Verifyingkey<'static> {
+pub fn get_withdraw_verifying_key() -> VerificationKey<'static> {
     static VK_IC: [[u8; 64]; 6] = [IC_0, IC_1, IC_2, IC_3, IC_4, IC_5];
     
-    Groth16Verifyingkey {
-        nr_pubinputs: NUM_PUBLIC_INPUTS,
-        vk_alpha_g1: VK_ALPHA_G1,
-        vk_beta_g2: VK_BETA_G2,
-        vk_gamme_g2: VK_GAMMA_G2,
-        vk_delta_g2: VK_DELTA_G2,
-        vk_ic: &VK_IC,
+    VerificationKey {
+        alpha_g1: VK_ALPHA_G1,
+        beta_g2: VK_BETA_G2,
+        gamma_g2: VK_GAMMA_G2,
+        delta_g2: VK_DELTA_G2,
+        ic: &VK_IC,
     }
 }
 
@@ -126,11 +163,9 @@ pub fn verify_withdraw_proof_groth16(
     relayer_fee: u64,
 ) -> anchor_lang::Result<bool> {
     
-    let mut amount_bytes = [0u8; 32];
-    amount_bytes[24..].copy_from_slice(&amount.to_be_bytes());
+    let amount_bytes = u64_to_be_field(amount);
     
-    let mut fee_bytes = [0u8; 32];
-    fee_bytes[24..].copy_from_slice(&relayer_fee.to_be_bytes());
+    let fee_bytes = u64_to_be_field(relayer_fee);
     
     let public_inputs: [[u8; 32]; NUM_PUBLIC_INPUTS] = [
         *commitment,
@@ -142,17 +177,7 @@ pub fn verify_withdraw_proof_groth16(
     
     let vk = get_withdraw_verifying_key();
     
-    let mut verifier = Groth16Verifier::<NUM_PUBLIC_INPUTS>::new(
-        proof_a, proof_b, proof_c, &public_inputs, &vk,
-    ).map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    verifier.prepare_inputs()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    verifier.verify()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    Ok(true)
+    verify_proof(&vk, proof_a, proof_b, proof_c, &public_inputs)
 }
 
 // ============================================================================
@@ -244,28 +269,65 @@ pub const WITHDRAW_MERKLE_IC_5: [u8; 64] = [
     0xff, 0x70, 0x32, 0x3e, 0x51, 0xe6, 0x2d, 0xfb, 0x0c, 0x3e, 0xd1, 0xd6, 0x79, 0xab, 0x31, 0x70,
 ];
 
-pub fn get_withdraw_merkle_vk() -> Groth16Verifyingkey<'static> {
-    static VK_IC: [[u8; 64]; 6] = [
+const WITHDRAW_MERKLE_VK: VerificationKey<'static> = VerificationKey {
+    alpha_g1: WITHDRAW_MERKLE_VK_ALPHA_G1,
+    beta_g2: WITHDRAW_MERKLE_VK_BETA_G2,
+    gamma_g2: WITHDRAW_MERKLE_VK_GAMMA_G2,
+    delta_g2: WITHDRAW_MERKLE_VK_DELTA_G2,
+    ic: &[
         WITHDRAW_MERKLE_IC_0,
         WITHDRAW_MERKLE_IC_1,
         WITHDRAW_MERKLE_IC_2,
         WITHDRAW_MERKLE_IC_3,
         WITHDRAW_MERKLE_IC_4,
         WITHDRAW_MERKLE_IC_5
-    ];
-    
-    Groth16Verifyingkey {
-        nr_pubinputs: WITHDRAW_MERKLE_NUM_PUBLIC_INPUTS,
-        vk_alpha_g1: WITHDRAW_MERKLE_VK_ALPHA_G1,
-        vk_beta_g2: WITHDRAW_MERKLE_VK_BETA_G2,
-        vk_gamme_g2: WITHDRAW_MERKLE_VK_GAMMA_G2,
-        vk_delta_g2: WITHDRAW_MERKLE_VK_DELTA_G2,
-        vk_ic: &VK_IC,
-    }
+    ],
+};
+
+pub fn get_withdraw_merkle_vk() -> VerificationKey<'static> {
+    WITHDRAW_MERKLE_VK
+}
+
+/// Verification key for one withdrawal denomination
+pub struct VkEntry {
+    pub denomination: u64,
+    pub vk: VerificationKey<'static>,
+}
+
+/// Per-denomination withdraw circuits
+pub struct CircuitVkRegistry {
+    pub entries: &'static [VkEntry],
+}
+
+// All denominations share the withdraw_merkle ceremony output until
+// denomination-specific circuits are set up; swap an entry's VK to move
+// that denomination onto its own circuit.
+pub static WITHDRAW_VK_REGISTRY: CircuitVkRegistry = CircuitVkRegistry {
+    entries: &[
+        VkEntry { denomination: crate::DENOM_001_SOL, vk: WITHDRAW_MERKLE_VK },
+        VkEntry { denomination: crate::DENOM_005_SOL, vk: WITHDRAW_MERKLE_VK },
+        VkEntry { denomination: crate::DENOM_01_SOL, vk: WITHDRAW_MERKLE_VK },
+        VkEntry { denomination: crate::DENOM_1_SOL, vk: WITHDRAW_MERKLE_VK },
+        VkEntry { denomination: crate::DENOM_10_SOL, vk: WITHDRAW_MERKLE_VK },
+        VkEntry { denomination: crate::DENOM_100_SOL, vk: WITHDRAW_MERKLE_VK },
+    ],
+};
+
+/// Pick the withdraw circuit VK registered for `denomination`
+pub fn select_vk_for_denomination(
+    denomination: u64,
+    vk_registry: &CircuitVkRegistry,
+) -> anchor_lang::Result<&VkEntry> {
+    vk_registry
+        .entries
+        .iter()
+        .find(|entry| entry.denomination == denomination)
+        .ok_or_else(|| anchor_lang::error!(crate::WhistleError::NoVkForDenomination))
 }
 
 /// Production verification for withdraw_merkle circuit
-/// Public inputs: [merkleRoot, nullifierHash, recipient, amount, relayerFee]
+/// The VK is chosen by denomination (`amount`) from WITHDRAW_VK_REGISTRY
+/// Public inputs: [merkleRoot, nullifierHash, recipient, amount, relayerFee, unlockSlot]
 pub fn verify_withdraw_merkle_proof(
     proof_a: &[u8; 64],
     proof_b: &[u8; 128],
@@ -275,13 +337,12 @@ pub fn verify_withdraw_merkle_proof(
     recipient: &[u8; 32],
     amount: u64,
     relayer_fee: u64,
+    unlock_slot: u64,
 ) -> anchor_lang::Result<bool> {
     
-    let mut amount_bytes = [0u8; 32];
-    amount_bytes[24..].copy_from_slice(&amount.to_be_bytes());
+    let amount_bytes = u64_to_be_field(amount);
     
-    let mut fee_bytes = [0u8; 32];
-    fee_bytes[24..].copy_from_slice(&relayer_fee.to_be_bytes());
+    let fee_bytes = u64_to_be_field(relayer_fee);
     
     let public_inputs: [[u8; 32]; WITHDRAW_MERKLE_NUM_PUBLIC_INPUTS] = [
         *merkle_root,
@@ -291,19 +352,12 @@ pub fn verify_withdraw_merkle_proof(
         fee_bytes,
     ];
     
-    let vk = get_withdraw_merkle_vk();
+    let vk = &select_vk_for_denomination(amount, &WITHDRAW_VK_REGISTRY)?.vk;
+    let Some(public_inputs) = with_unlock_slots(vk, &public_inputs, &[unlock_slot]) else {
+        return Ok(false);
+    };
     
-    let mut verifier = Groth16Verifier::<WITHDRAW_MERKLE_NUM_PUBLIC_INPUTS>::new(
-        proof_a, proof_b, proof_c, &public_inputs, &vk,
-    ).map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    verifier.prepare_inputs()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    verifier.verify()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    Ok(true)
+    verify_proof(vk, proof_a, proof_b, proof_c, &public_inputs)
 }
 
 // ============================================================================
@@ -401,7 +455,7 @@ pub const UNSHIELD_CHANGE_IC_6: [u8; 64] = [
     0xfd, 0xff, 0x20, 0xa9, 0xe7, 0x2d, 0x95, 0x20, 0x19, 0x4d, 0x4c, 0xc5, 0xb4, 0xbe, 0xec, 0xa7,
 ];
 
-pub fn get_unshield_change_vk() -> Groth16Verifyingkey<'static> {
+pub fn get_unshield_change_vk() -> VerificationKey<'static> {
     static VK_IC: [[u8; 64]; 7] = [
         UNSHIELD_CHANGE_IC_0,
         UNSHIELD_CHANGE_IC_1,
@@ -412,18 +466,17 @@ pub fn get_unshield_change_vk() -> Groth16Verifyingkey<'static> {
         UNSHIELD_CHANGE_IC_6
     ];
     
-    Groth16Verifyingkey {
-        nr_pubinputs: UNSHIELD_CHANGE_NUM_PUBLIC_INPUTS,
-        vk_alpha_g1: UNSHIELD_CHANGE_VK_ALPHA_G1,
-        vk_beta_g2: UNSHIELD_CHANGE_VK_BETA_G2,
-        vk_gamme_g2: UNSHIELD_CHANGE_VK_GAMMA_G2,
-        vk_delta_g2: UNSHIELD_CHANGE_VK_DELTA_G2,
-        vk_ic: &VK_IC,
+    VerificationKey {
+        alpha_g1: UNSHIELD_CHANGE_VK_ALPHA_G1,
+        beta_g2: UNSHIELD_CHANGE_VK_BETA_G2,
+        gamma_g2: UNSHIELD_CHANGE_VK_GAMMA_G2,
+        delta_g2: UNSHIELD_CHANGE_VK_DELTA_G2,
+        ic: &VK_IC,
     }
 }
 
 /// Production verification for unshield_change circuit
-/// Public inputs: [merkleRoot, nullifierHash, recipient, withdrawalAmount, relayerFee, changeCommitment]
+/// Public inputs: [merkleRoot, nullifierHash, recipient, withdrawalAmount, relayerFee, changeCommitment, unlockSlot]
 pub fn verify_unshield_change_proof(
     proof_a: &[u8; 64],
     proof_b: &[u8; 128],
@@ -434,13 +487,12 @@ pub fn verify_unshield_change_proof(
     withdrawal_amount: u64,
     relayer_fee: u64,
     change_commitment: &[u8; 32],
+    unlock_slot: u64,
 ) -> anchor_lang::Result<bool> {
     
-    let mut amount_bytes = [0u8; 32];
-    amount_bytes[24..].copy_from_slice(&withdrawal_amount.to_be_bytes());
+    let amount_bytes = u64_to_be_field(withdrawal_amount);
     
-    let mut fee_bytes = [0u8; 32];
-    fee_bytes[24..].copy_from_slice(&relayer_fee.to_be_bytes());
+    let fee_bytes = u64_to_be_field(relayer_fee);
     
     let public_inputs: [[u8; 32]; UNSHIELD_CHANGE_NUM_PUBLIC_INPUTS] = [
         *merkle_root,
@@ -452,18 +504,11 @@ pub fn verify_unshield_change_proof(
     ];
     
     let vk = get_unshield_change_vk();
+    let Some(public_inputs) = with_unlock_slots(&vk, &public_inputs, &[unlock_slot]) else {
+        return Ok(false);
+    };
     
-    let mut verifier = Groth16Verifier::<UNSHIELD_CHANGE_NUM_PUBLIC_INPUTS>::new(
-        proof_a, proof_b, proof_c, &public_inputs, &vk,
-    ).map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    verifier.prepare_inputs()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    verifier.verify()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
-    
-    Ok(true)
+    verify_proof(&vk, proof_a, proof_b, proof_c, &public_inputs)
 }
 
 // ============================================================================
@@ -554,7 +599,7 @@ pub const PRIVATE_TRANSFER_IC_5: [u8; 64] = [
     0x6a, 0x39, 0xb0, 0xa0, 0x04, 0xe6, 0x4b, 0x86, 0x6c, 0xa2, 0x45, 0x20, 0xfb, 0x04, 0x9c, 0x2f,
 ];
 
-pub fn get_private_transfer_vk() -> Groth16Verifyingkey<'static> {
+pub fn get_private_transfer_vk() -> VerificationKey<'static> {
     static VK_IC: [[u8; 64]; 6] = [
         PRIVATE_TRANSFER_IC_0,
         PRIVATE_TRANSFER_IC_1,
@@ -564,18 +609,18 @@ pub fn get_private_transfer_vk() -> Groth16Verifyingkey<'static> {
         PRIVATE_TRANSFER_IC_5,
     ];
     
-    Groth16Verifyingkey {
-        nr_pubinputs: PRIVATE_TRANSFER_NUM_PUBLIC_INPUTS,
-        vk_alpha_g1: PRIVATE_TRANSFER_VK_ALPHA_G1,
-        vk_beta_g2: PRIVATE_TRANSFER_VK_BETA_G2,
-        vk_gamme_g2: PRIVATE_TRANSFER_VK_GAMMA_G2,
-        vk_delta_g2: PRIVATE_TRANSFER_VK_DELTA_G2,
-        vk_ic: &VK_IC,
+    VerificationKey {
+        alpha_g1: PRIVATE_TRANSFER_VK_ALPHA_G1,
+        beta_g2: PRIVATE_TRANSFER_VK_BETA_G2,
+        gamma_g2: PRIVATE_TRANSFER_VK_GAMMA_G2,
+        delta_g2: PRIVATE_TRANSFER_VK_DELTA_G2,
+        ic: &VK_IC,
     }
 }
 
 /// Production verification for private_transfer circuit
-/// Public inputs: [merkleRoot, inputNullifierHash1, inputNullifierHash2, outputCommitment1, outputCommitment2]
+/// Public inputs: [merkleRoot, inputNullifierHash1, inputNullifierHash2, outputCommitment1, outputCommitment2,
+/// unlockSlot1, unlockSlot2]
 /// Note: The public inputs are packed as a single merkleRoot followed by arrays
 pub fn verify_private_transfer_proof(
     proof_a: &[u8; 64],
@@ -584,6 +629,7 @@ pub fn verify_private_transfer_proof(
     merkle_root: &[u8; 32],
     input_nullifier_hashes: &[[u8; 32]; 2],
     output_commitments: &[[u8; 32]; 2],
+    unlock_slots: &[u64; 2],
 ) -> anchor_lang::Result<bool> {
     
     // Pack public inputs: merkleRoot, nullHash1, nullHash2, outComm1, outComm2
@@ -598,16 +644,252 @@ pub fn verify_private_transfer_proof(
     ];
     
     let vk = get_private_transfer_vk();
+    let Some(public_inputs) = with_unlock_slots(&vk, &public_inputs, unlock_slots) else {
+        return Ok(false);
+    };
     
-    let mut verifier = Groth16Verifier::<PRIVATE_TRANSFER_NUM_PUBLIC_INPUTS>::new(
-        proof_a, proof_b, proof_c, &public_inputs, &vk,
-    ).map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
+    verify_proof(&vk, proof_a, proof_b, proof_c, &public_inputs)
+}
+
+// ============================================================================
+// AMOUNT_REVEAL (Selective disclosure - note is not spent)
+// ============================================================================
+//
+// The verification key is produced by the amount_reveal trusted setup
+// (`npm run build:all` in circuits/) and pasted here. Until then the key is
+// all zeroes, and the `amount-reveal` feature that enables
+// reveal_note_amount refuses to build.
+
+pub const AMOUNT_REVEAL_NUM_PUBLIC_INPUTS: usize = 3;
+
+pub const AMOUNT_REVEAL_VK_ALPHA_G1: [u8; 64] = [0u8; 64];
+pub const AMOUNT_REVEAL_VK_BETA_G2: [u8; 128] = [0u8; 128];
+pub const AMOUNT_REVEAL_VK_GAMMA_G2: [u8; 128] = [0u8; 128];
+pub const AMOUNT_REVEAL_VK_DELTA_G2: [u8; 128] = [0u8; 128];
+pub const AMOUNT_REVEAL_IC_0: [u8; 64] = [0u8; 64];
+pub const AMOUNT_REVEAL_IC_1: [u8; 64] = [0u8; 64];
+pub const AMOUNT_REVEAL_IC_2: [u8; 64] = [0u8; 64];
+pub const AMOUNT_REVEAL_IC_3: [u8; 64] = [0u8; 64];
+
+/// A verification key whose alpha point is all zeroes has not been generated yet
+pub const fn vk_is_generated(vk_alpha_g1: &[u8; 64]) -> bool {
+    let mut i = 0;
+    while i < vk_alpha_g1.len() {
+        if vk_alpha_g1[i] != 0 {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+pub fn get_amount_reveal_vk() -> VerificationKey<'static> {
+    static VK_IC: [[u8; 64]; AMOUNT_REVEAL_NUM_PUBLIC_INPUTS + 1] = [
+        AMOUNT_REVEAL_IC_0,
+        AMOUNT_REVEAL_IC_1,
+        AMOUNT_REVEAL_IC_2,
+        AMOUNT_REVEAL_IC_3,
+    ];
     
-    verifier.prepare_inputs()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
+    VerificationKey {
+        alpha_g1: AMOUNT_REVEAL_VK_ALPHA_G1,
+        beta_g2: AMOUNT_REVEAL_VK_BETA_G2,
+        gamma_g2: AMOUNT_REVEAL_VK_GAMMA_G2,
+        delta_g2: AMOUNT_REVEAL_VK_DELTA_G2,
+        ic: &VK_IC,
+    }
+}
+
+/// Verification for amount_reveal circuit against `vk`
+/// Public inputs: [commitment, amount, auditor]
+/// 
+/// The auditor enters like a recipient (pubkey_to_field), so a disclosure
+/// addressed to one auditor cannot be replayed as addressed to another.
+pub fn verify_amount_reveal_proof(
+    vk: &VerificationKey,
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    commitment: &[u8; 32],
+    amount: u64,
+    auditor_pubkey: &[u8; 32],
+) -> anchor_lang::Result<bool> {
+    let public_inputs: [[u8; 32]; AMOUNT_REVEAL_NUM_PUBLIC_INPUTS] = [
+        *commitment,
+        u64_to_be_field(amount),
+        pubkey_to_field(auditor_pubkey),
+    ];
     
-    verifier.verify()
-        .map_err(|_| anchor_lang::error!(crate::WhistleError::InvalidProof))?;
+    verify_proof(vk, proof_a, proof_b, proof_c, &public_inputs)
+}
+
+// ============================================================================
+// BATCH_WITHDRAW (Four withdrawals, one proof)
+// ============================================================================
+//
+// One pairing check (four pairings) covers four notes, against sixteen for
+// four withdraw_merkle proofs; the public input MSM grows to 11 points. The
+// key is pasted here from the batch_withdraw trusted setup; verification
+// fails closed with VerifyingKeyNotGenerated until then.
+
+pub const BATCH_WITHDRAW_NOTES: usize = 4;
+
+pub const BATCH_WITHDRAW_NUM_PUBLIC_INPUTS: usize = 2 * BATCH_WITHDRAW_NOTES + 3;
+
+pub const BATCH_WITHDRAW_VK_ALPHA_G1: [u8; 64] = [0u8; 64];
+pub const BATCH_WITHDRAW_VK_BETA_G2: [u8; 128] = [0u8; 128];
+pub const BATCH_WITHDRAW_VK_GAMMA_G2: [u8; 128] = [0u8; 128];
+pub const BATCH_WITHDRAW_VK_DELTA_G2: [u8; 128] = [0u8; 128];
+pub const BATCH_WITHDRAW_IC: [[u8; 64]; BATCH_WITHDRAW_NUM_PUBLIC_INPUTS + 1] =
+    [[0u8; 64]; BATCH_WITHDRAW_NUM_PUBLIC_INPUTS + 1];
+
+pub fn get_batch_withdraw_vk() -> VerificationKey<'static> {
+    VerificationKey {
+        alpha_g1: BATCH_WITHDRAW_VK_ALPHA_G1,
+        beta_g2: BATCH_WITHDRAW_VK_BETA_G2,
+        gamma_g2: BATCH_WITHDRAW_VK_GAMMA_G2,
+        delta_g2: BATCH_WITHDRAW_VK_DELTA_G2,
+        ic: &BATCH_WITHDRAW_IC,
+    }
+}
+
+/// Verification for batch_withdraw circuit
+/// Public inputs: [merkleRoot, nullifierHash1..4, amount1..4, recipient, relayerFee]
+pub fn verify_batch_withdraw_proof(
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hashes: &[[u8; 32]; BATCH_WITHDRAW_NOTES],
+    amounts: &[u64; BATCH_WITHDRAW_NOTES],
+    recipient: &[u8; 32],
+    relayer_fee: u64,
+) -> anchor_lang::Result<bool> {
+    if !vk_is_generated(&BATCH_WITHDRAW_VK_ALPHA_G1) {
+        return Err(anchor_lang::error!(crate::WhistleError::VerifyingKeyNotGenerated));
+    }
     
-    Ok(true)
+    let mut public_inputs = [[0u8; 32]; BATCH_WITHDRAW_NUM_PUBLIC_INPUTS];
+    public_inputs[0] = *merkle_root;
+    public_inputs[1..=BATCH_WITHDRAW_NOTES].copy_from_slice(nullifier_hashes);
+    for (input, amount) in public_inputs[BATCH_WITHDRAW_NOTES + 1..].iter_mut().zip(amounts) {
+        *input = u64_to_be_field(*amount);
+    }
+    public_inputs[2 * BATCH_WITHDRAW_NOTES + 1] = *recipient;
+    public_inputs[2 * BATCH_WITHDRAW_NOTES + 2] = u64_to_be_field(relayer_fee);
+    
+    let vk = get_batch_withdraw_vk();
+    
+    verify_proof(&vk, proof_a, proof_b, proof_c, &public_inputs)
+}
+
+// ============================================================================
+// BATCH_SHIELD (Eight notes from one deposit, one proof)
+// ============================================================================
+//
+// Proves that eight commitments are well-formed standard notes whose
+// amounts sum to the deposit. Pasted here from the batch_shield trusted
+// setup; fails closed with VerifyingKeyNotGenerated until then.
+
+pub const BATCH_SHIELD_NOTES: usize = 8;
+
+pub const BATCH_SHIELD_NUM_PUBLIC_INPUTS: usize = BATCH_SHIELD_NOTES + 1;
+
+pub const BATCH_SHIELD_VK_ALPHA_G1: [u8; 64] = [0u8; 64];
+pub const BATCH_SHIELD_VK_BETA_G2: [u8; 128] = [0u8; 128];
+pub const BATCH_SHIELD_VK_GAMMA_G2: [u8; 128] = [0u8; 128];
+pub const BATCH_SHIELD_VK_DELTA_G2: [u8; 128] = [0u8; 128];
+pub const BATCH_SHIELD_IC: [[u8; 64]; BATCH_SHIELD_NUM_PUBLIC_INPUTS + 1] =
+    [[0u8; 64]; BATCH_SHIELD_NUM_PUBLIC_INPUTS + 1];
+
+pub fn get_batch_shield_vk() -> VerificationKey<'static> {
+    VerificationKey {
+        alpha_g1: BATCH_SHIELD_VK_ALPHA_G1,
+        beta_g2: BATCH_SHIELD_VK_BETA_G2,
+        gamma_g2: BATCH_SHIELD_VK_GAMMA_G2,
+        delta_g2: BATCH_SHIELD_VK_DELTA_G2,
+        ic: &BATCH_SHIELD_IC,
+    }
+}
+
+/// Verification for batch_shield circuit
+/// Public inputs: [commitment1..8, totalAmount]
+pub fn verify_batch_shield_proof(
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    commitments: &[[u8; 32]; BATCH_SHIELD_NOTES],
+    total_amount: u64,
+) -> anchor_lang::Result<bool> {
+    if !vk_is_generated(&BATCH_SHIELD_VK_ALPHA_G1) {
+        return Err(anchor_lang::error!(crate::WhistleError::VerifyingKeyNotGenerated));
+    }
+    
+    let mut public_inputs = [[0u8; 32]; BATCH_SHIELD_NUM_PUBLIC_INPUTS];
+    public_inputs[..BATCH_SHIELD_NOTES].copy_from_slice(commitments);
+    public_inputs[BATCH_SHIELD_NOTES] = u64_to_be_field(total_amount);
+    
+    let vk = get_batch_shield_vk();
+    
+    verify_proof(&vk, proof_a, proof_b, proof_c, &public_inputs)
+}
+
+// ============================================================================
+// UNSHIELD_TOKEN (SPL token withdrawal with change)
+// ============================================================================
+//
+// unshield_change for notes holding an SPL token. The note commitment is
+// Poseidon(secret, nullifier, amount, mint), with the mint as a public
+// input, so a note can only be spent for the mint it was created with.
+// Pasted here from the unshield_token trusted setup; fails closed with
+// VerifyingKeyNotGenerated until then.
+
+pub const UNSHIELD_TOKEN_NUM_PUBLIC_INPUTS: usize = 6;
+
+pub const UNSHIELD_TOKEN_VK_ALPHA_G1: [u8; 64] = [0u8; 64];
+pub const UNSHIELD_TOKEN_VK_BETA_G2: [u8; 128] = [0u8; 128];
+pub const UNSHIELD_TOKEN_VK_GAMMA_G2: [u8; 128] = [0u8; 128];
+pub const UNSHIELD_TOKEN_VK_DELTA_G2: [u8; 128] = [0u8; 128];
+pub const UNSHIELD_TOKEN_IC: [[u8; 64]; UNSHIELD_TOKEN_NUM_PUBLIC_INPUTS + 1] =
+    [[0u8; 64]; UNSHIELD_TOKEN_NUM_PUBLIC_INPUTS + 1];
+
+pub fn get_unshield_token_vk() -> VerificationKey<'static> {
+    VerificationKey {
+        alpha_g1: UNSHIELD_TOKEN_VK_ALPHA_G1,
+        beta_g2: UNSHIELD_TOKEN_VK_BETA_G2,
+        gamma_g2: UNSHIELD_TOKEN_VK_GAMMA_G2,
+        delta_g2: UNSHIELD_TOKEN_VK_DELTA_G2,
+        ic: &UNSHIELD_TOKEN_IC,
+    }
+}
+
+/// Verification for unshield_token circuit
+/// Public inputs: [merkleRoot, nullifierHash, recipient, amount, mint, changeCommitment]
+pub fn verify_unshield_token_proof(
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+    recipient: &[u8; 32],
+    amount: u64,
+    mint: &[u8; 32],
+    change_commitment: &[u8; 32],
+) -> anchor_lang::Result<bool> {
+    if !vk_is_generated(&UNSHIELD_TOKEN_VK_ALPHA_G1) {
+        return Err(anchor_lang::error!(crate::WhistleError::VerifyingKeyNotGenerated));
+    }
+    
+    let public_inputs: [[u8; 32]; UNSHIELD_TOKEN_NUM_PUBLIC_INPUTS] = [
+        *merkle_root,
+        *nullifier_hash,
+        *recipient,
+        u64_to_be_field(amount),
+        *mint,
+        *change_commitment,
+    ];
+    
+    let vk = get_unshield_token_vk();
+    
+    verify_proof(&vk, proof_a, proof_b, proof_c, &public_inputs)
 }
@@ -0,0 +1,259 @@
+// WHISTLE PROTOCOL - PROPERTY TEST HARNESS
+//
+// Support for driving the pool from program-test with arbitrary operation
+// sequences (see tests/invariants.rs). Compiled only with the `test-harness`
+// feature, which refuses to build in release mode.
+//
+// - Test proof backend: unshield, withdraw, private_transfer,
+//   batch_withdraw_zk, shield_batch_zk and unshield_token accept a "proof"
+//   whose proof_a is test_proof(public inputs) instead of a Groth16 proof,
+//   so tests can create and spend notes without the circuits.
+// - assert_invariants: checks the pool's global invariants and returns a
+//   bitmap of the violated ones (0 when all hold).
+// - import_state_chunk: writes raw bytes exported by export_state_chunk
+//   back into a pool state account, to clone a pool for load testing.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+
+use crate::groth16::{BATCH_SHIELD_NOTES, BATCH_WITHDRAW_NOTES};
+use crate::public_inputs::u64_to_be_field;
+use crate::{check_state_account, empty_tree_root, AssertInvariants, ImportStateChunk, RootsHistory, WhistleError};
+
+#[cfg(not(debug_assertions))]
+compile_error!("the test-harness feature must never be enabled in release builds");
+
+pub const TEST_PROOF_DOMAIN: &[u8] = b"whistle-test-proof";
+
+/// Vault lamports are below total_shielded
+pub const VAULT_BELOW_SHIELDED: u32 = 1 << 0;
+/// total_shielded exceeds total_deposits
+pub const SHIELDED_ABOVE_DEPOSITS: u32 = 1 << 1;
+/// next_index is beyond the tree's leaf capacity
+pub const NEXT_INDEX_OUT_OF_RANGE: u32 = 1 << 2;
+/// A leaf at or after next_index is set
+pub const LEAF_PAST_NEXT_INDEX: u32 = 1 << 3;
+/// current_root is not the root of the stored tree
+pub const ROOT_MISMATCH: u32 = 1 << 4;
+/// The latest roots history entry is not current_root
+pub const ROOT_NOT_IN_HISTORY: u32 = 1 << 5;
+/// The nullifier count is out of range or a nullifier is recorded twice
+pub const NULLIFIER_SET_CORRUPT: u32 = 1 << 6;
+
+/// Encode a u64 public input as a big-endian field element (as the Groth16 backend does)
+pub fn field_u64(value: u64) -> [u8; 32] {
+    u64_to_be_field(value)
+}
+
+/// The proof_a the test backend accepts for `public_inputs`
+pub fn test_proof(public_inputs: &[[u8; 32]]) -> [u8; 64] {
+    let mut parts: Vec<&[u8]> = vec![TEST_PROOF_DOMAIN];
+    parts.extend(public_inputs.iter().map(|input| input.as_slice()));
+
+    let mut proof_a = [0u8; 64];
+    proof_a[..32].copy_from_slice(&keccak::hashv(&parts).to_bytes());
+    proof_a
+}
+
+/// Test backend for the withdraw_merkle circuit
+/// Public inputs: [merkleRoot, nullifierHash, recipient, amount, relayerFee, unlockSlot]
+pub fn verify_withdraw_merkle_proof(
+    proof_a: &[u8; 64],
+    _proof_b: &[u8; 128],
+    _proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+    recipient: &[u8; 32],
+    amount: u64,
+    relayer_fee: u64,
+    unlock_slot: u64,
+) -> Result<bool> {
+    Ok(*proof_a == test_proof(&[
+        *merkle_root,
+        *nullifier_hash,
+        *recipient,
+        field_u64(amount),
+        field_u64(relayer_fee),
+            field_u64(unlock_slot),
+    ]))
+}
+
+/// Test backend for the unshield_change circuit
+/// Public inputs: [merkleRoot, nullifierHash, recipient, withdrawalAmount, relayerFee, changeCommitment, unlockSlot]
+pub fn verify_unshield_change_proof(
+    proof_a: &[u8; 64],
+    _proof_b: &[u8; 128],
+    _proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+    recipient: &[u8; 32],
+    withdrawal_amount: u64,
+    relayer_fee: u64,
+    change_commitment: &[u8; 32],
+    unlock_slot: u64,
+) -> Result<bool> {
+    Ok(*proof_a == test_proof(&[
+        *merkle_root,
+        *nullifier_hash,
+        *recipient,
+        field_u64(withdrawal_amount),
+        field_u64(relayer_fee),
+        *change_commitment,
+            field_u64(unlock_slot),
+    ]))
+}
+
+/// Test backend for the private_transfer circuit
+/// Public inputs: [merkleRoot, inputNullifierHash1, inputNullifierHash2, outputCommitment1, outputCommitment2,
+/// unlockSlot1, unlockSlot2]
+pub fn verify_private_transfer_proof(
+    proof_a: &[u8; 64],
+    _proof_b: &[u8; 128],
+    _proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    input_nullifier_hashes: &[[u8; 32]; 2],
+    output_commitments: &[[u8; 32]; 2],
+    unlock_slots: &[u64; 2],
+) -> Result<bool> {
+    Ok(*proof_a == test_proof(&[
+        *merkle_root,
+        input_nullifier_hashes[0],
+        input_nullifier_hashes[1],
+        output_commitments[0],
+        output_commitments[1],
+            field_u64(unlock_slots[0]),
+            field_u64(unlock_slots[1]),
+    ]))
+}
+
+/// Test backend for the batch_withdraw circuit
+/// Public inputs: [merkleRoot, nullifierHash1..4, amount1..4, recipient, relayerFee]
+pub fn verify_batch_withdraw_proof(
+    proof_a: &[u8; 64],
+    _proof_b: &[u8; 128],
+    _proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hashes: &[[u8; 32]; BATCH_WITHDRAW_NOTES],
+    amounts: &[u64; BATCH_WITHDRAW_NOTES],
+    recipient: &[u8; 32],
+    relayer_fee: u64,
+) -> Result<bool> {
+    let mut public_inputs = vec![*merkle_root];
+    public_inputs.extend_from_slice(nullifier_hashes);
+    public_inputs.extend(amounts.iter().map(|amount| field_u64(*amount)));
+    public_inputs.push(*recipient);
+    public_inputs.push(field_u64(relayer_fee));
+    Ok(*proof_a == test_proof(&public_inputs))
+}
+
+/// Test backend for the batch_shield circuit
+/// Public inputs: [commitment1..8, totalAmount]
+pub fn verify_batch_shield_proof(
+    proof_a: &[u8; 64],
+    _proof_b: &[u8; 128],
+    _proof_c: &[u8; 64],
+    commitments: &[[u8; 32]; BATCH_SHIELD_NOTES],
+    total_amount: u64,
+) -> Result<bool> {
+    let mut public_inputs = commitments.to_vec();
+    public_inputs.push(field_u64(total_amount));
+    Ok(*proof_a == test_proof(&public_inputs))
+}
+
+/// Test backend for the unshield_token circuit
+/// Public inputs: [merkleRoot, nullifierHash, recipient, amount, mint, changeCommitment]
+pub fn verify_unshield_token_proof(
+    proof_a: &[u8; 64],
+    _proof_b: &[u8; 128],
+    _proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+    recipient: &[u8; 32],
+    amount: u64,
+    mint: &[u8; 32],
+    change_commitment: &[u8; 32],
+) -> Result<bool> {
+    Ok(*proof_a == test_proof(&[
+        *merkle_root,
+        *nullifier_hash,
+        *recipient,
+        field_u64(amount),
+        *mint,
+        *change_commitment,
+    ]))
+}
+
+pub fn assert_invariants(ctx: Context<AssertInvariants>) -> Result<u32> {
+    let pool = &ctx.accounts.pool;
+    let tree = ctx.accounts.merkle_tree.load()?;
+    let roots = RootsHistory::ring(&ctx.accounts.roots_history)?;
+    let nullifiers = ctx.accounts.nullifiers.load()?;
+    let mut violations = 0;
+
+    // Funds: every shielded lamport is in the vault, and came from a deposit
+    if ctx.accounts.pool_vault.lamports() < pool.total_shielded {
+        violations |= VAULT_BELOW_SHIELDED;
+    }
+    if pool.total_shielded > pool.total_deposits {
+        violations |= SHIELDED_ABOVE_DEPOSITS;
+    }
+
+    // Tree: exactly the first next_index leaves can be set
+    let levels = pool.merkle_levels.min(13);
+    let max_leaves = 1usize << levels;
+    let leaf_offset = max_leaves - 1;
+    if pool.next_index > max_leaves as u64 {
+        violations |= NEXT_INDEX_OUT_OF_RANGE;
+    }
+    let leaves_end = (leaf_offset + max_leaves).min(tree.nodes.len());
+    let first_unused = (leaf_offset + (pool.next_index as usize).min(max_leaves)).min(leaves_end);
+    if tree.nodes[first_unused..leaves_end].iter().any(|leaf| *leaf != [0u8; 32]) {
+        violations |= LEAF_PAST_NEXT_INDEX;
+    }
+
+    // Roots: current_root matches the tree and is the latest history entry
+    let tree_root = if pool.next_index == 0 {
+        empty_tree_root(pool.merkle_levels)
+    } else {
+        tree.get_root(pool.merkle_levels)
+    };
+    if pool.current_root != tree_root {
+        violations |= ROOT_MISMATCH;
+    }
+    if pool.next_index > 0 && roots.latest() != pool.current_root {
+        violations |= ROOT_NOT_IN_HISTORY;
+    }
+
+    // Nullifiers: no double spends recorded
+    let count = nullifiers.count as usize;
+    if count > nullifiers.nullifiers.len() {
+        violations |= NULLIFIER_SET_CORRUPT;
+    } else {
+        let mut spent = nullifiers.nullifiers[..count].to_vec();
+        spent.sort_unstable();
+        if spent.windows(2).any(|pair| pair[0] == pair[1]) {
+            violations |= NULLIFIER_SET_CORRUPT;
+        }
+    }
+
+    Ok(violations)
+}
+
+pub fn import_state_chunk(
+    ctx: Context<ImportStateChunk>,
+    account_kind: u8,
+    offset: u32,
+    bytes: Vec<u8>,
+) -> Result<()> {
+    let account = ctx.accounts.account.to_account_info();
+    check_state_account(&account, account_kind)?;
+
+    let mut data = account.try_borrow_mut_data()?;
+    let start = offset as usize;
+    let end = start
+        .checked_add(bytes.len())
+        .filter(|end| *end <= data.len())
+        .ok_or(WhistleError::InvalidStateChunk)?;
+    data[start..end].copy_from_slice(&bytes);
+    Ok(())
+}
@@ -0,0 +1,22 @@
+// WHISTLE PROTOCOL - PROPERTY TEST HARNESS (DISABLED)
+//
+// Stand-in for harness.rs when the `test-harness` feature is off. The
+// instructions stay in the program interface but always fail with
+// TestHarnessOnly, and proofs go through the Groth16 backend.
+
+use anchor_lang::prelude::*;
+
+use crate::{AssertInvariants, ImportStateChunk, WhistleError};
+
+pub fn assert_invariants(_ctx: Context<AssertInvariants>) -> Result<u32> {
+    err!(WhistleError::TestHarnessOnly)
+}
+
+pub fn import_state_chunk(
+    _ctx: Context<ImportStateChunk>,
+    _account_kind: u8,
+    _offset: u32,
+    _bytes: Vec<u8>,
+) -> Result<()> {
+    err!(WhistleError::TestHarnessOnly)
+}
@@ -0,0 +1,340 @@
+// WHISTLE PROTOCOL - JUBJUB-STYLE PEDERSEN COMMITMENTS
+//
+// Alternative commitment scheme for PLONK-oriented circuits that commit on an
+// embedded curve instead of hashing into BN254. Solana has no Jubjub
+// (BLS12-381) syscalls, so Ristretto255 - which the runtime supports natively
+// through the curve25519 syscalls - stands in as the embedded group.
+//
+// Commitment to message m with randomness p:
+// - r = m*G + p*H   (Pedersen commitment)
+// - s = p*G         (randomness commitment)
+//
+// G is the Ristretto basepoint, H is hash_from_bytes::<Sha3_512>(G) - the
+// same generators the zk-token-sdk Pedersen scheme uses.
+//
+// Unshielding reveals the opening, so the leaf also commits to an Ed25519
+// spend key whose signature over (commitment, recipient, relayer_fee) must
+// accompany the unshield: a copied opening cannot redirect the payout.
+//
+// Only compiled with the `jubjub` feature.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::system_program;
+use solana_zk_token_sdk::curve25519::ristretto::{
+    multiscalar_multiply_ristretto, multiply_ristretto, validate_ristretto, PodRistrettoPoint,
+};
+use solana_zk_token_sdk::curve25519::scalar::PodScalar;
+
+use crate::{
+    book_shield, check_relayer, empty_tree_root, is_program_address, receipt_hash, record_deposit, split_protocol_fee, verify_ed25519_instruction, InitializePool,
+    LeafAccounts, NullifierMarker, PoolInitialized, Shield, Shielded, UnshieldJubjub, Unshielded, WhistleError, BPS_DENOMINATOR, CURVE_JUBJUB, MAX_RELAYER_FEE_CAP_BPS,
+    MIN_DEPOSIT,
+};
+
+/// Ristretto basepoint G (compressed)
+pub const PEDERSEN_G: [u8; 32] = [
+    0xe2, 0xf2, 0xae, 0x0a, 0x6a, 0xbc, 0x4e, 0x71, 0xa8, 0x84, 0xa9, 0x61, 0xc5, 0x00, 0x51, 0x5f,
+    0x58, 0xe3, 0x0b, 0x6a, 0xa5, 0x82, 0xdd, 0x8d, 0xb6, 0xa6, 0x59, 0x45, 0xe0, 0x8d, 0x2d, 0x76,
+];
+
+/// Second generator H = hash_from_bytes::<Sha3_512>(G) (compressed)
+pub const PEDERSEN_H: [u8; 32] = [
+    0x8c, 0x92, 0x40, 0xb4, 0x56, 0xa9, 0xe6, 0xdc, 0x65, 0xc3, 0x77, 0xa1, 0x04, 0x8d, 0x74, 0x5f,
+    0x94, 0xa0, 0x8c, 0xdb, 0x7f, 0x44, 0xcb, 0xcd, 0x7b, 0x46, 0xf3, 0x40, 0x48, 0x87, 0x11, 0x34,
+];
+
+/// Domain separator for Jubjub note nullifiers
+pub const JUBJUB_NULLIFIER_DOMAIN: &[u8] = b"whistle-jubjub-nullifier";
+
+/// Domain separator for the spend key's unshield signature
+pub const JUBJUB_WITHDRAW_DOMAIN: &[u8] = b"whistle-jubjub-withdraw";
+
+/// Pedersen commitment on the embedded curve
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JubjubCommitment {
+    /// Pedersen commitment point m*G + p*H (compressed)
+    pub r: [u8; 32],
+    /// Randomness commitment point p*G (compressed)
+    pub s: [u8; 32],
+}
+
+impl JubjubCommitment {
+    /// Both points must be valid group elements
+    pub fn is_valid(&self) -> bool {
+        validate_ristretto(&PodRistrettoPoint(self.r)) && validate_ristretto(&PodRistrettoPoint(self.s))
+    }
+
+    /// Merkle leaf for this commitment, bound to the deposited amount and
+    /// the spend key
+    /// 
+    /// keccak(r || s || amount || spend_pubkey) with the top byte cleared so
+    /// the leaf is a valid BN254 field element for the Poseidon tree.
+    pub fn leaf(&self, amount: u64, spend_pubkey: &[u8; 32]) -> [u8; 32] {
+        let mut leaf = keccak::hashv(&[&self.r, &self.s, &amount.to_le_bytes(), spend_pubkey]).to_bytes();
+        leaf[0] = 0;
+        leaf
+    }
+
+    /// Nullifier hash marking this commitment as spent
+    pub fn nullifier_hash(&self) -> [u8; 32] {
+        keccak::hashv(&[JUBJUB_NULLIFIER_DOMAIN, &self.r]).to_bytes()
+    }
+
+    /// Message the spend key signs to unshield this commitment
+    /// 
+    /// JUBJUB_WITHDRAW_DOMAIN || r || s || recipient || relayer_fee
+    pub fn withdraw_message(&self, recipient: &Pubkey, relayer_fee: u64) -> Vec<u8> {
+        [JUBJUB_WITHDRAW_DOMAIN, &self.r, &self.s, recipient.as_ref(), &relayer_fee.to_le_bytes()].concat()
+    }
+}
+
+/// Encode an amount as a little-endian Ristretto scalar
+pub fn amount_to_scalar(amount: u64) -> [u8; 32] {
+    let mut scalar = [0u8; 32];
+    scalar[..8].copy_from_slice(&amount.to_le_bytes());
+    scalar
+}
+
+/// Commitment to `message` with `randomness`, as a client computes it
+/// before shielding
+/// 
+/// r = message*G + randomness*H and s = randomness*G
+pub fn commit_jubjub(message: &[u8; 32], randomness: &[u8; 32]) -> Option<JubjubCommitment> {
+    let g = PodRistrettoPoint(PEDERSEN_G);
+    let h = PodRistrettoPoint(PEDERSEN_H);
+
+    let r = multiscalar_multiply_ristretto(
+        &[PodScalar(*message), PodScalar(*randomness)],
+        &[g, h],
+    )?;
+    let s = multiply_ristretto(&PodScalar(*randomness), &g)?;
+    Some(JubjubCommitment { r: r.0, s: s.0 })
+}
+
+/// Check that `commitment` opens to `message` with `randomness`
+pub fn verify_jubjub_commitment(
+    commitment: &JubjubCommitment,
+    message: &[u8; 32],
+    randomness: &[u8; 32],
+) -> bool {
+    commit_jubjub(message, randomness).as_ref() == Some(commitment)
+}
+
+// ============================================================================
+// INSTRUCTION PROCESSORS
+// ============================================================================
+
+/// Initialize pool state for Jubjub-style commitments (step 1, alternative)
+pub fn initialize_jubjub(ctx: Context<InitializePool>, merkle_levels: u8) -> Result<()> {
+    require!((7..=13).contains(&merkle_levels), WhistleError::InvalidMerkleLevels);
+
+    let pool = &mut ctx.accounts.pool;
+    pool.merkle_levels = merkle_levels;
+    pool.next_index = 0;
+    pool.current_root = empty_tree_root(merkle_levels);
+    pool.total_deposits = 0;
+    pool.total_shielded = 0;
+    pool.total_fees_collected = 0;
+    pool.bump = ctx.bumps.pool;
+    pool.curve = CURVE_JUBJUB;
+    pool.allow_schnorr_for_small = false;
+    pool.max_total_deposits_per_address = 0;
+    pool.max_total_pool_shielded = 0;
+    pool.store_pda_bumps();
+
+    let clock = Clock::get()?;
+    emit!(PoolInitialized {
+        pool: ctx.accounts.pool.key(),
+        merkle_levels,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Shield SOL into a Jubjub-style Pedersen commitment
+/// 
+/// The leaf binds the commitment to the deposited amount and to
+/// `spend_pubkey`, so it can only be unshielded for exactly that amount
+/// and with that key's signature.
+pub fn shield_jubjub(ctx: Context<Shield>, commitment: JubjubCommitment, spend_pubkey: [u8; 32], amount: u64) -> Result<()> {
+    require!(ctx.accounts.pool.curve == CURVE_JUBJUB, WhistleError::UnsupportedCurve);
+    require!(amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+    require!(commitment.is_valid(), WhistleError::InvalidCommitment);
+
+    let pool = &mut ctx.accounts.pool;
+    let (protocol_fee, net_amount) = split_protocol_fee(amount)?;
+
+    record_deposit(pool, &mut ctx.accounts.deposit_record, ctx.accounts.depositor.key(), net_amount)?;
+
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.depositor.to_account_info(),
+                to: ctx.accounts.pool_vault.to_account_info(),
+            },
+        ),
+        net_amount,
+    )?;
+
+    if protocol_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.depositor.to_account_info(),
+                    to: ctx.accounts.fee_vault.to_account_info(),
+                },
+            ),
+            protocol_fee,
+        )?;
+    }
+
+    let leaf = commitment.leaf(net_amount, &spend_pubkey);
+    let leaf_index = book_shield(
+        pool,
+        LeafAccounts {
+            merkle_tree: &ctx.accounts.merkle_tree,
+            roots_history: &ctx.accounts.roots_history,
+            leaf_page: Some(&ctx.accounts.leaf_page),
+            commitment_marker: &ctx.accounts.commitment_marker,
+            payer: ctx.accounts.depositor.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        },
+        &ctx.accounts.pool_stats,
+        &mut ctx.accounts.deposit_histogram,
+        leaf,
+        amount,
+        protocol_fee,
+    )?;
+
+    let clock = Clock::get()?;
+    emit!(Shielded {
+        commitment: leaf,
+        leaf_index,
+        amount: net_amount,
+        protocol_fee,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Unshield a Jubjub-style commitment by opening it
+/// 
+/// The opening (amount, randomness) is revealed on-chain: this path trades
+/// unlinkability for compatibility with embedded-curve commitments. The
+/// Ed25519 signature over commitment.withdraw_message(recipient,
+/// relayer_fee) is checked by the Ed25519 precompile in the instruction
+/// just before this one, and its key must be the one the leaf commits to.
+pub fn unshield_jubjub(
+    ctx: Context<UnshieldJubjub>,
+    commitment: JubjubCommitment,
+    randomness: [u8; 32],
+    amount: u64,
+    recipient: Pubkey,
+    relayer_fee: u64,
+    signature: [u8; 64],
+) -> Result<()> {
+    let instructions = ctx.accounts.instructions.to_account_info();
+    let accounts = &mut ctx.accounts.unshield;
+    require!(accounts.pool.curve == CURVE_JUBJUB, WhistleError::UnsupportedCurve);
+    require!(recipient == accounts.recipient.key(), WhistleError::InvalidRecipient);
+    let max_relayer_fee = amount.checked_mul(MAX_RELAYER_FEE_CAP_BPS.into())
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        / BPS_DENOMINATOR;
+    require!(relayer_fee <= max_relayer_fee, WhistleError::FeeTooHigh);
+    check_relayer(accounts.relayer.as_ref(), relayer_fee, false)?;
+
+    // Commitment must open to exactly this amount
+    require!(
+        verify_jubjub_commitment(&commitment, &amount_to_scalar(amount), &randomness),
+        WhistleError::InvalidCommitment
+    );
+
+    let spend_pubkey = verify_ed25519_instruction(
+        &instructions,
+        &signature,
+        &commitment.withdraw_message(&recipient, relayer_fee),
+    )?;
+
+    let pool = &mut accounts.pool;
+    let in_tree = {
+        let merkle_tree = accounts.merkle_tree.load()?;
+        merkle_tree.contains_leaf(&commitment.leaf(amount, &spend_pubkey), pool.next_index, pool.merkle_levels)
+    };
+    require!(in_tree, WhistleError::CommitmentNotFound);
+
+    let nullifier_hash = commitment.nullifier_hash();
+    {
+        let nullifiers = accounts.nullifiers.load()?;
+        NullifierMarker::spend(
+            &nullifiers,
+            &accounts.nullifier_marker,
+            &accounts.payer.to_account_info(),
+            &accounts.system_program.to_account_info(),
+            &nullifier_hash,
+        )?;
+    }
+    accounts.congestion.record_withdrawals(Clock::get()?.slot, 1);
+
+    let vault_balance = accounts.pool_vault.lamports();
+    require!(vault_balance >= amount, WhistleError::InsufficientVaultBalance);
+
+    let vault_bump = pool.vault_bump;
+    let vault_seeds: &[&[u8]] = &[b"vault", &[vault_bump]];
+
+    anchor_lang::solana_program::program::invoke_signed(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            accounts.pool_vault.key,
+            accounts.recipient.key,
+            amount - relayer_fee,
+        ),
+        &[
+            accounts.pool_vault.to_account_info(),
+            accounts.recipient.to_account_info(),
+            accounts.system_program.to_account_info(),
+        ],
+        &[vault_seeds],
+    )?;
+
+    // check_relayer guarantees the account
+    if let (true, Some(relayer)) = (relayer_fee > 0, &accounts.relayer) {
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                accounts.pool_vault.key,
+                relayer.key,
+                relayer_fee,
+            ),
+            &[
+                accounts.pool_vault.to_account_info(),
+                relayer.to_account_info(),
+                accounts.system_program.to_account_info(),
+            ],
+            &[vault_seeds],
+        )?;
+    }
+
+    pool.total_shielded = pool.total_shielded
+        .checked_sub(amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+
+    let clock = Clock::get()?;
+    emit!(Unshielded {
+        nullifier_hash,
+        withdrawal_amount: amount,
+        protocol_fee: 0,
+        has_change: false,
+        recipient_is_pda: is_program_address(&recipient),
+        self_relayed: false,
+        receipt_hash: receipt_hash(&nullifier_hash, &recipient, amount, clock.slot),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// WHISTLE PROTOCOL - JUBJUB-STYLE COMMITMENTS (DISABLED)
+//
+// Stand-in for jubjub.rs when the `jubjub` feature is off. The instructions
+// stay in the program interface but always fail with UnsupportedCurve.
+
+use anchor_lang::prelude::*;
+
+use crate::{InitializePool, Shield, UnshieldJubjub, WhistleError};
+
+/// Pedersen commitment on the embedded curve
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JubjubCommitment {
+    /// Pedersen commitment point m*G + p*H (compressed)
+    pub r: [u8; 32],
+    /// Randomness commitment point p*G (compressed)
+    pub s: [u8; 32],
+}
+
+pub fn initialize_jubjub(_ctx: Context<InitializePool>, _merkle_levels: u8) -> Result<()> {
+    err!(WhistleError::UnsupportedCurve)
+}
+
+pub fn shield_jubjub(
+    _ctx: Context<Shield>,
+    _commitment: JubjubCommitment,
+    _spend_pubkey: [u8; 32],
+    _amount: u64,
+) -> Result<()> {
+    err!(WhistleError::UnsupportedCurve)
+}
+
+pub fn unshield_jubjub(
+    _ctx: Context<UnshieldJubjub>,
+    _commitment: JubjubCommitment,
+    _randomness: [u8; 32],
+    _amount: u64,
+    _recipient: Pubkey,
+    _relayer_fee: u64,
+    _signature: [u8; 64],
+) -> Result<()> {
+    err!(WhistleError::UnsupportedCurve)
+}
@@ -1,15 +1,89 @@
+#![allow(clippy::too_many_arguments)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
 use anchor_lang::solana_program::poseidon::{hashv as poseidon_hashv, Endianness as PoseidonEndianness, Parameters as PoseidonParameters};
-// Note: alt_bn128 operations are handled by groth16_solana in groth16.rs
+use std::cell::{Ref, RefMut};
+use std::ops::{Deref, DerefMut};
+use whistle_merkle::MerklePath;
+// Note: alt_bn128 operations are handled by whistle-groth16 (see groth16.rs)
 
+pub mod auction;
+pub mod eip712;
 pub mod groth16;
+pub mod public_inputs;
+#[cfg(feature = "batch-shield-zk")]
+pub mod batch_shield_zk;
+#[cfg(not(feature = "batch-shield-zk"))]
+#[path = "batch_shield_zk_disabled.rs"]
+pub mod batch_shield_zk;
+#[cfg(feature = "batch-withdraw-zk")]
+pub mod batch_withdraw_zk;
+#[cfg(not(feature = "batch-withdraw-zk"))]
+#[path = "batch_withdraw_zk_disabled.rs"]
+pub mod batch_withdraw_zk;
+#[cfg(feature = "spl-tokens")]
+pub mod token;
+#[cfg(not(feature = "spl-tokens"))]
+#[path = "token_disabled.rs"]
+pub mod token;
+#[cfg(feature = "jubjub")]
+pub mod jubjub;
+#[cfg(not(feature = "jubjub"))]
+#[path = "jubjub_disabled.rs"]
+pub mod jubjub;
+use jubjub::JubjubCommitment;
+use public_inputs::{pubkey_to_field, require_canonical_field_element, u64_to_be_field, BN254_SCALAR_MODULUS};
+#[cfg(feature = "amount-reveal")]
+pub mod disclosure;
+#[cfg(not(feature = "amount-reveal"))]
+#[path = "disclosure_disabled.rs"]
+pub mod disclosure;
+#[cfg(feature = "insecure-devnet")]
+pub mod devnet;
+#[cfg(not(feature = "insecure-devnet"))]
+#[path = "devnet_disabled.rs"]
+pub mod devnet;
+#[cfg(feature = "test-harness")]
+pub mod harness;
+#[cfg(not(feature = "test-harness"))]
+#[path = "harness_disabled.rs"]
+pub mod harness;
+#[cfg(feature = "profiling")]
+#[macro_use]
+pub mod profiling;
+#[cfg(not(feature = "profiling"))]
+#[path = "profiling_disabled.rs"]
+#[macro_use]
+pub mod profiling;
 use groth16::{
     verify_withdraw_proof_groth16,       // Legacy (withdraw_simple)
+};
+#[cfg(not(feature = "test-harness"))]
+use groth16::{
     verify_withdraw_merkle_proof,         // Production (full Merkle proof)
     verify_unshield_change_proof,         // Production (withdrawal with change)
     verify_private_transfer_proof,        // Production (shielded transfers)
 };
+#[cfg(all(feature = "batch-withdraw-zk", not(feature = "test-harness")))]
+use groth16::verify_batch_withdraw_proof; // Four withdrawals, one proof
+#[cfg(all(feature = "batch-shield-zk", not(feature = "test-harness")))]
+use groth16::verify_batch_shield_proof;   // Eight notes, one deposit
+#[cfg(all(feature = "spl-tokens", not(feature = "test-harness")))]
+use groth16::verify_unshield_token_proof;  // SPL token withdrawal with change
+// test-harness builds swap in the test proof backend (see harness.rs)
+#[cfg(feature = "test-harness")]
+use harness::{
+    verify_withdraw_merkle_proof,
+    verify_unshield_change_proof,
+    verify_private_transfer_proof,
+    verify_batch_withdraw_proof,
+    verify_batch_shield_proof,
+    verify_unshield_token_proof,
+};
 
 declare_id!("AMtxCTW99zCBfhukVdN8YvA3AsdSJ7nsgnUdHpth7QTD");
 
@@ -33,6 +107,8 @@ declare_id!("AMtxCTW99zCBfhukVdN8YvA3AsdSJ7nsgnUdHpth7QTD");
 
 // Fixed withdrawal denominations for maximum anonymity
 // Devnet testing denominations (smaller for testing)
+// The protocol header above is an outer doc comment and lands on this constant
+#[allow(clippy::empty_line_after_doc_comments, clippy::doc_lazy_continuation)]
 pub const DENOM_001_SOL: u64 = 10_000_000;    // 0.01 SOL
 pub const DENOM_005_SOL: u64 = 50_000_000;    // 0.05 SOL
 pub const DENOM_01_SOL: u64 = 100_000_000;    // 0.1 SOL
@@ -41,6 +117,31 @@ pub const DENOM_1_SOL: u64 = 1_000_000_000;   // 1 SOL
 pub const DENOM_10_SOL: u64 = 10_000_000_000; // 10 SOL
 pub const DENOM_100_SOL: u64 = 100_000_000_000; // 100 SOL
 
+// All withdrawal denominations, smallest first. Indexes into the fee cap table.
+pub const DENOMINATIONS: [u64; 6] = [
+    DENOM_001_SOL,
+    DENOM_005_SOL,
+    DENOM_01_SOL,
+    DENOM_1_SOL,
+    DENOM_10_SOL,
+    DENOM_100_SOL,
+];
+
+// Default relayer fee caps per denomination (basis points), used at genesis.
+// Small notes need a higher percentage to be worth relaying; large notes must
+// not be able to pay out abusive absolute fees.
+pub const DEFAULT_RELAYER_FEE_CAPS_BPS: [u16; 6] = [
+    1000, // 0.01 SOL: 10%
+    1000, // 0.05 SOL: 10%
+    500,  // 0.1 SOL:  5%
+    300,  // 1 SOL:    3%
+    100,  // 10 SOL:   1%
+    100,  // 100 SOL:  1%
+];
+
+// Hard ceiling for any configured relayer fee cap: 10%
+pub const MAX_RELAYER_FEE_CAP_BPS: u16 = 1000;
+
 // Minimum deposit to prevent dust spam
 pub const MIN_DEPOSIT: u64 = 10_000_000; // 0.01 SOL
 
@@ -49,507 +150,1726 @@ pub const MIN_DEPOSIT: u64 = 10_000_000; // 0.01 SOL
 pub const PROTOCOL_FEE_BPS: u64 = 4;
 pub const BPS_DENOMINATOR: u64 = 10000;
 
+// Anonymity metrics: shield counts per epoch of STATS_EPOCH_SLOTS slots,
+// kept for the trailing STATS_RING_EPOCHS epochs (100k slots)
+pub const STATS_EPOCH_SLOTS: u64 = 1_000;
+pub const STATS_RING_EPOCHS: usize = 100;
+
+// Amount bands for anonymity metrics, by upper bound (exclusive):
+// [< 0.1 SOL, < 1 SOL, < 10 SOL, >= 10 SOL]
+pub const STATS_AMOUNT_BANDS: usize = 4;
+pub const STATS_BAND_UPPER_BOUNDS: [u64; STATS_AMOUNT_BANDS - 1] = [DENOM_01_SOL, DENOM_1_SOL, DENOM_10_SOL];
+
+// Congestion stats: withdrawals and relayer approvals are counted per
+// window of CONGESTION_WINDOW_SLOTS slots (~1 minute). CONGESTION_WINDOW_TARGET
+// is the per-window withdrawal load at which the published fee curve
+// (sdk suggestFee) reaches the relayer fee cap; nothing is throttled.
+pub const CONGESTION_WINDOW_SLOTS: u64 = 150;
+pub const CONGESTION_WINDOW_TARGET: u32 = 32;
+
+// Deposit histogram buckets, by lower bound (inclusive):
+// [0.01, 0.05, 0.1, 0.5, 1, 5, 10, 100] SOL
+pub const DEPOSIT_HISTOGRAM_BUCKETS: usize = 8;
+pub const DEPOSIT_BUCKET_LOWER_BOUNDS: [u64; DEPOSIT_HISTOGRAM_BUCKETS] = [
+    DENOM_001_SOL,
+    DENOM_005_SOL,
+    DENOM_01_SOL,
+    500_000_000,
+    DENOM_1_SOL,
+    5_000_000_000,
+    DENOM_10_SOL,
+    DENOM_100_SOL,
+];
+
+// Staged proof payloads: total capacity, largest chunk per instruction (keeps
+// each staging transaction well under the 1232-byte packet limit) and slots
+// until an unused session can be closed by anyone (~20 minutes)
+pub const PROOF_STAGING_CAPACITY: usize = 1024;
+pub const MAX_PROOF_CHUNK_SIZE: usize = 900;
+pub const PROOF_STAGING_EXPIRY_SLOTS: u64 = 3_000;
+
+// Schnorr withdraw path for small notes: domain separators for the note
+// commitment, its nullifier and the signed withdrawal message
+pub const SCHNORR_NOTE_DOMAIN: &[u8] = b"whistle-schnorr-note";
+pub const SCHNORR_NULLIFIER_DOMAIN: &[u8] = b"whistle-schnorr-nullifier";
+pub const SCHNORR_WITHDRAW_DOMAIN: &[u8] = b"whistle-schnorr-withdraw";
+
+// Largest page returned by get_spent_nullifiers (fits in 1KB of return data)
+pub const MAX_NULLIFIER_PAGE: u8 = 16;
+
+// Hashes checked per batch_nullifier_status call (one bit each in the u16 result)
+pub const NULLIFIER_STATUS_BATCH: usize = 16;
+
+// Most notes one batch_shield inserts (one Poseidon path and one duplicate
+// scan of the tree each)
+pub const MAX_BATCH_SHIELD: usize = 8;
+
+// Most notes one batch_unshield withdraws (one Groth16 verification each)
+pub const MAX_BATCH_UNSHIELD: usize = 4;
+
+// Largest ciphertext store_encrypted_note keeps: room for a note's secret,
+// nullifier and amount plus the sealing overhead
+pub const MAX_NOTE_CIPHERTEXT: usize = 256;
+
+// Layout version of ReserveSnapshot; bump whenever its fields change
+pub const RESERVE_SNAPSHOT_VERSION: u8 = 1;
+
+// Pre-committed shields: stake held until the reveal, and how many slots
+// after `deposit_slot` the reveal may land
+pub const PRE_COMMIT_STAKE: u64 = 10_000_000; // 0.01 SOL
+pub const PRE_COMMIT_REVEAL_SLOTS: u64 = 5;
+
+// Relayer bonds: what one inactivity proof takes from a bond (also the
+// smallest bond that can take withdrawals), and how many slots an approved
+// withdrawal may stay unsubmitted
+pub const SLASH_AMOUNT: u64 = 50_000_000; // 0.05 SOL
+pub const RELAYER_EXECUTION_TIMEOUT_SLOTS: u64 = 300;
+
+// How long a relayer node's intent lock on a nullifier hash holds off other
+// nodes: enough to prove and land one withdrawal (~30s)
+pub const INTENT_LOCK_TTL_SLOTS: u64 = 75;
+
+// Tree root disputes: smallest bond a challenge posts, how many slots a
+// responder has to defend the disputed range, and the range size (one leaf
+// page; resolve_dispute hashes all of it, ~250k CU on a 13-level tree)
+pub const MIN_DISPUTE_BOND: u64 = 100_000_000; // 0.1 SOL
+pub const DISPUTE_RESPONSE_SLOTS: u64 = 3_000;
+pub const DISPUTE_RANGE_LEVELS: u8 = 8;
+pub const DISPUTE_RANGE_LEAVES: u64 = 1 << DISPUTE_RANGE_LEVELS;
+
+// Tree capacity warnings: CapacityWarning is emitted once next_index
+// reaches each of these fractions of the tree's leaves (basis points)
+pub const CAPACITY_WARNING_BPS: [u16; 2] = [9_000, 9_900];
+
+// Oldest roots history entry a spend may prove against (~1 day); the pool's
+// current root is accepted at any age
+pub const MAX_ROOT_AGE_SLOTS: u64 = 216_000;
+
+// Roots history entries: the capacity init_roots creates (and the one an
+// account without a stored capacity has), and the most resize_roots_history
+// grows it to. Solana grows an account by at most 10 KiB per instruction,
+// so one resize adds at most MAX_ROOTS_HISTORY_GROWTH entries.
+pub const ROOTS_HISTORY_CAPACITY: usize = 100;
+pub const MAX_ROOTS_HISTORY_CAPACITY: usize = 1024;
+pub const MAX_ROOTS_HISTORY_GROWTH: usize = 256;
+
+// Commitment curve a pool is configured for
+pub const CURVE_BN254: u8 = 0;
+pub const CURVE_JUBJUB: u8 = 1;
+
+// Pool state accounts exported by export_state_chunk, and the largest chunk
+// one call returns (the 4-byte length prefix keeps it under 1KB of return data)
+pub const STATE_POOL: u8 = 0;
+pub const STATE_MERKLE_TREE: u8 = 1;
+pub const STATE_ROOTS_HISTORY: u8 = 2;
+pub const STATE_NULLIFIERS: u8 = 3;
+pub const STATE_CHUNK_SIZE: usize = 1000;
+
 #[program]
 pub mod whistle_pool {
     use super::*;
 
+    
     /// Initialize pool state only (step 1)
-    pub fn initialize(ctx: Context<InitializePool>, merkle_levels: u8) -> Result<()> {
+    /// 
+    /// `allow_schnorr_for_small` enables verify_schnorr_withdraw for notes
+    /// below 0.1 SOL. It is fixed for the life of the pool.
+    /// 
+    /// `max_total_deposits_per_address` caps what one depositor can shield
+    /// in total, so no single entity dominates the anonymity set, and
+    /// `max_total_pool_shielded` caps the pool's shielded balance. Both are
+    /// in lamports, 0 means unlimited, and both are fixed at genesis.
+    /// Only the program's upgrade authority may initialize, so these
+    /// parameters cannot be front-run between deploy and initialization.
+    pub fn initialize(
+        ctx: Context<InitializePool>,
+        merkle_levels: u8,
+        allow_schnorr_for_small: bool,
+        max_total_deposits_per_address: u64,
+        max_total_pool_shielded: u64,
+    ) -> Result<()> {
         // Match circuit tree depth (7 for devnet, 13 for mainnet)
-        require!(merkle_levels >= 7 && merkle_levels <= 13, WhistleError::InvalidMerkleLevels);
+        require!((7..=13).contains(&merkle_levels), WhistleError::InvalidMerkleLevels);
         
         let pool = &mut ctx.accounts.pool;
         pool.merkle_levels = merkle_levels;
         pool.next_index = 0;
-        pool.current_root = [0u8; 32];
+        pool.current_root = empty_tree_root(merkle_levels);
         pool.total_deposits = 0;
         pool.total_shielded = 0;
         pool.total_fees_collected = 0;
         pool.bump = ctx.bumps.pool;
+        pool.curve = CURVE_BN254;
+        pool.allow_schnorr_for_small = allow_schnorr_for_small;
+        pool.max_total_deposits_per_address = max_total_deposits_per_address;
+        pool.max_total_pool_shielded = max_total_pool_shielded;
+        pool.store_pda_bumps();
         
+        let clock = Clock::get()?;
         emit!(PoolInitialized {
             pool: ctx.accounts.pool.key(),
             merkle_levels,
-            timestamp: Clock::get()?.unix_timestamp,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
         
         Ok(())
     }
     
+    /// Initialize pool state for Jubjub-style commitments (step 1, alternative)
+    /// 
+    /// Requires the `jubjub` feature; fails with UnsupportedCurve otherwise.
+    pub fn initialize_jubjub(ctx: Context<InitializePool>, merkle_levels: u8) -> Result<()> {
+        jubjub::initialize_jubjub(ctx, merkle_levels)
+    }
+    
     /// Initialize merkle tree (step 2)
     pub fn init_merkle(ctx: Context<InitMerkle>) -> Result<()> {
         let merkle_tree = &mut ctx.accounts.merkle_tree.load_init()?;
+        merkle_tree.version = MERKLE_TREE_VERSION;
         merkle_tree.levels_used = ctx.accounts.pool.merkle_levels;
+        merkle_tree.node_capacity = MERKLE_TREE_NODE_CAPACITY as u32;
         Ok(())
     }
     
-    /// Initialize roots history (step 3)
-    pub fn init_roots(ctx: Context<InitRoots>) -> Result<()> {
-        let roots = &mut ctx.accounts.roots_history.load_init()?;
-        roots.current_index = 0;
+    /// Migrate a v0 merkle tree account (no version field) to the v1 layout
+    /// 
+    /// Permissionless and one-shot. The v1 header is built from the v0 one
+    /// (levels_used kept, node_capacity the nodes the account holds) and
+    /// replaces it; the nodes are left untouched. The v1 tree cannot be a
+    /// new account with the v0 one closed: every client derives the tree at
+    /// its fixed PDA, and at 512KB it is far above the 10KB an account can
+    /// be created with from a program.
+    pub fn migrate_merkle_tree_v0_to_v1(ctx: Context<MigrateMerkleTree>) -> Result<()> {
+        let account = ctx.accounts.merkle_tree.to_account_info();
+        let mut data = account.try_borrow_mut_data()?;
+        
+        let v0 = MerkleTree::try_load_versioned(&data)?;
+        require!(v0.version == 0, WhistleError::MerkleTreeAlreadyMigrated);
+        let levels_used = v0.levels_used;
+        let node_capacity = v0.node_capacity;
+        
+        let mut header = [0u8; MERKLE_TREE_HEADER_SIZE];
+        header[0] = MERKLE_TREE_VERSION;
+        header[1] = levels_used;
+        header[4..8].copy_from_slice(&node_capacity.to_le_bytes());
+        data[8..8 + MERKLE_TREE_HEADER_SIZE].copy_from_slice(&header);
+        
+        emit!(MerkleTreeMigrated {
+            from_version: 0,
+            to_version: MERKLE_TREE_VERSION,
+            levels_used,
+        });
+        
         Ok(())
     }
     
-    /// Initialize nullifier set (step 4)
-    pub fn init_nullifiers(ctx: Context<InitNullifiers>) -> Result<()> {
-        let nullifiers = &mut ctx.accounts.nullifiers.load_init()?;
-        nullifiers.count = 0;
+    /// Record the PDA bumps in a pool created before PoolState stored them
+    /// 
+    /// Permissionless and one-shot: every context that checks a vault or
+    /// data PDA against the stored bump fails until this has run.
+    pub fn migrate_pool_bumps(ctx: Context<MigratePoolBumps>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.has_pda_bumps(), WhistleError::PoolBumpsAlreadyStored);
+        pool.store_pda_bumps();
         Ok(())
     }
-
-    /// Shield SOL - Deposit ANY amount into a shielded note
+    
+    /// Grow a roots history account created before root_slots to the
+    /// current layout
     /// 
-    /// Creates a note commitment: hash(secret, nullifier, amount)
-    /// The amount is hidden inside the note, only the depositor knows it.
-    /// Protocol fee (0.04%) is collected and sent to fee vault.
-    pub fn shield(ctx: Context<Shield>, commitment: [u8; 32], amount: u64) -> Result<()> {
-        require!(amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+    /// Permissionless; `payer` funds the extra rent. The roots already
+    /// recorded are stamped with the current slot, so proofs built against
+    /// them stay valid for MAX_ROOT_AGE_SLOTS.
+    pub fn migrate_roots_history(ctx: Context<MigrateRootsHistory>) -> Result<()> {
+        let account = ctx.accounts.roots_history.to_account_info();
+        let size = RootsHistory::space(ROOTS_HISTORY_CAPACITY);
+        {
+            let data = account.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == <RootsHistory as anchor_lang::Discriminator>::DISCRIMINATOR,
+                WhistleError::InvalidStateAccount
+            );
+            require!(data.len() < size, WhistleError::RootsHistoryAlreadyMigrated);
+        }
         
-        let pool = &mut ctx.accounts.pool;
-        let merkle_tree = &mut ctx.accounts.merkle_tree.load_mut()?;
+        let top_up = Rent::get()?.minimum_balance(size).saturating_sub(account.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: account.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        account.realloc(size, true)?;
         
-        let max_leaves = 1u64 << pool.merkle_levels;
-        require!(pool.next_index < max_leaves, WhistleError::TreeFull);
+        let slot = Clock::get()?.slot;
+        let mut roots = RootsRing::new(RefMut::map(account.try_borrow_mut_data()?, |data| &mut **data))?;
+        for i in 0..roots.capacity() {
+            let root = roots.root(i);
+            if root != [0u8; 32] {
+                roots.set(i, root, slot);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Grow the roots history to hold `capacity` roots
+    /// 
+    /// Permissionless; `payer` funds the extra rent. A larger history keeps
+    /// a proof's root valid through more later insertions (still bounded by
+    /// MAX_ROOT_AGE_SLOTS). `capacity` must exceed the current one by at
+    /// most MAX_ROOTS_HISTORY_GROWTH, up to MAX_ROOTS_HISTORY_CAPACITY.
+    /// Recorded roots and their slots are kept. An export of a grown
+    /// history imports only into one grown to the same capacity.
+    pub fn resize_roots_history(ctx: Context<ResizeRootsHistory>, capacity: u16) -> Result<()> {
+        // Checked before the realloc, which the runtime would fail first
+        require!(
+            ctx.accounts.roots_history.load()?.can_grow_to(capacity as usize),
+            WhistleError::InvalidRootsHistoryCapacity
+        );
         
-        // Calculate protocol fee (0.04%)
-        let protocol_fee = amount.checked_mul(PROTOCOL_FEE_BPS)
-            .ok_or(WhistleError::ArithmeticOverflow)?
-            .checked_div(BPS_DENOMINATOR)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
-        let net_amount = amount.checked_sub(protocol_fee)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
+        let account = ctx.accounts.roots_history.to_account_info();
+        let size = RootsHistory::space(capacity as usize);
+        let top_up = Rent::get()?.minimum_balance(size).saturating_sub(account.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: account.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        account.realloc(size, false)?;
         
-        // Transfer net amount to main vault
-        let cpi_context = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.depositor.to_account_info(),
-                to: ctx.accounts.pool_vault.to_account_info(),
-            },
+        RootsHistory::ring_mut(&ctx.accounts.roots_history)?.grow(capacity as usize);
+        Ok(())
+    }
+    
+    /// Start moving a full tree to `new_levels` levels (step 1 of 3)
+    /// 
+    /// Only a full pool can migrate: before that nothing is stranded, and
+    /// spends at the new depth need circuits and verifying keys of that
+    /// depth, which a program upgrade must ship first. The tree is deepened
+    /// in place, up to the 13 levels MERKLE_TREE_NODE_CAPACITY holds. Until
+    /// finalize_migration, every instruction that reads the stored nodes
+    /// fails with PoolMigrationInProgress, and inserts still fail with
+    /// TreeFull since the pool keeps its old depth. A withdrawal with no
+    /// change inserts nothing and still verifies against the roots history,
+    /// which the migration leaves alone.
+    pub fn begin_pool_migration(ctx: Context<BeginMigration>, new_levels: u8) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(
+            new_levels > pool.merkle_levels && new_levels <= 13,
+            WhistleError::InvalidMerkleLevels
         );
-        system_program::transfer(cpi_context, net_amount)?;
+        require!(pool.next_index >= 1u64 << pool.merkle_levels, WhistleError::TreeNotFull);
         
-        // Transfer protocol fee to fee vault
-        if protocol_fee > 0 {
-            let fee_cpi = CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::Transfer {
-                    from: ctx.accounts.depositor.to_account_info(),
-                    to: ctx.accounts.fee_vault.to_account_info(),
-                },
-            );
-            system_program::transfer(fee_cpi, protocol_fee)?;
-            
-            pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
-                .ok_or(WhistleError::ArithmeticOverflow)?;
-        }
+        let mut merkle_tree = ctx.accounts.merkle_tree.load_mut()?;
+        require!(merkle_tree.version == MERKLE_TREE_VERSION, WhistleError::UnsupportedMerkleTreeVersion);
+        merkle_tree.check_root(pool)?;
+        merkle_tree.levels_used = new_levels;
         
-        // Add commitment to Merkle tree
-        let leaf_index = pool.next_index;
-        merkle_tree.insert_leaf(commitment, leaf_index, pool.merkle_levels);
+        let migration = &mut ctx.accounts.migration;
+        migration.old_levels = pool.merkle_levels;
+        migration.new_levels = new_levels;
+        migration.migrated_leaves = 0;
+        migration.next_node = (1u32 << new_levels) - 1;
+        migration.completed = false;
+        migration.bump = ctx.bumps.migration;
+        Ok(())
+    }
+    
+    /// Advance a migration by `batch_size` steps (step 2 of 3)
+    /// 
+    /// Permissionless. Copies leaves to the deeper tree's leaf level first,
+    /// then rebuilds its internal nodes from the highest index down, as
+    /// rebuild_root does. `batch_size` is 1 to MAX_REBUILD_NODES; once
+    /// nothing is left, calls change nothing. The new leaf level starts past
+    /// every node of the old tree, so no copy overwrites a leaf still to
+    /// copy.
+    pub fn continue_migration(ctx: Context<ContinueMigration>, batch_size: u32) -> Result<()> {
+        require!(
+            batch_size > 0 && batch_size <= u32::from(MAX_REBUILD_NODES),
+            WhistleError::InvalidMigrationBatch
+        );
+        let pool = &ctx.accounts.pool;
+        let migration = &mut ctx.accounts.migration;
+        let mut merkle_tree = ctx.accounts.merkle_tree.load_mut()?;
         
-        pool.current_root = merkle_tree.get_root(pool.merkle_levels);
-        pool.next_index = pool.next_index.checked_add(1)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
-        pool.total_deposits = pool.total_deposits.checked_add(net_amount)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
-        pool.total_shielded = pool.total_shielded.checked_add(net_amount)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
+        if migration.migrated_leaves < pool.next_index {
+            let old_offset = (1usize << migration.old_levels) - 1;
+            let new_offset = (1usize << migration.new_levels) - 1;
+            let start = migration.migrated_leaves as usize;
+            let end = (start + batch_size as usize).min(pool.next_index as usize);
+            merkle_tree.nodes.copy_within(old_offset + start..old_offset + end, new_offset + start);
+            migration.migrated_leaves = end as u64;
+        } else {
+            let end = migration.next_node;
+            let start = end.saturating_sub(batch_size);
+            merkle_tree.rebuild_nodes(start as usize, end as usize, pool.next_index, migration.new_levels);
+            migration.next_node = start;
+        }
+        Ok(())
+    }
+    
+    /// Publish the deeper tree's root and adopt its depth (step 3 of 3)
+    /// 
+    /// Requires every leaf copied and every internal node rebuilt. Shields
+    /// resume with room for 2^new_levels leaves; the roots recorded before
+    /// stay in the history and age out as usual.
+    pub fn finalize_migration(ctx: Context<FinalizeMigration>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let migration = &mut ctx.accounts.migration;
+        require!(
+            migration.migrated_leaves == pool.next_index && migration.next_node == 0,
+            WhistleError::MigrationIncomplete
+        );
         
-        // Store root in history
-        let roots = &mut ctx.accounts.roots_history.load_mut()?;
-        let idx = roots.current_index as usize;
-        roots.roots[idx] = pool.current_root;
-        roots.current_index = ((roots.current_index as usize + 1) % 100) as u8;
+        let root = ctx.accounts.merkle_tree.load()?.get_root(migration.new_levels);
+        pool.merkle_levels = migration.new_levels;
+        pool.current_root = root;
+        migration.completed = true;
+        RootsHistory::ring_mut(&ctx.accounts.roots_history)?.push(root, Clock::get()?.slot);
         
-        emit!(Shielded {
-            commitment,
-            leaf_index,
-            amount: net_amount,
-            protocol_fee,
-            timestamp: Clock::get()?.unix_timestamp,
+        emit!(PoolMigrated {
+            old_levels: migration.old_levels,
+            new_levels: migration.new_levels,
+            leaf_count: pool.next_index,
+            root,
         });
-        
         Ok(())
     }
-
-    /// Unshield SOL - Withdraw in FIXED denomination + re-shield change
+    
+    /// Recompute the tree's internal nodes from its stored leaves
     /// 
-    /// ZK Proof verifies:
-    /// 1. User knows secret/nullifier for a note in the tree
-    /// 2. Note value >= withdrawal amount
-    /// 3. Change commitment is correctly computed
+    /// Permissionless repair for a tree whose root no longer matches pool
+    /// state (TreeStateDesync), e.g. after a partial import_state_chunk.
+    /// Rebuilds `count` (at most MAX_REBUILD_NODES) nodes below `end_node`;
+    /// call with `end_node` stepping down from 2^levels - 1 so children are
+    /// rebuilt before their parents. The call that reaches node 0 publishes
+    /// the rebuilt root. On a consistent tree every node comes out unchanged.
+    pub fn rebuild_root(ctx: Context<RebuildRoot>, end_node: u32, count: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let leaf_offset = (1u32 << pool.merkle_levels.min(13)) - 1;
+        require!(
+            end_node <= leaf_offset && count <= MAX_REBUILD_NODES && u32::from(count) <= end_node,
+            WhistleError::InvalidRebuildRange
+        );
+        let start = end_node - u32::from(count);
+        
+        let mut merkle_tree = ctx.accounts.merkle_tree.load_mut()?;
+        merkle_tree.require_not_migrating(pool)?;
+        merkle_tree.rebuild_nodes(start as usize, end_node as usize, pool.next_index, pool.merkle_levels);
+        if start > 0 {
+            return Ok(());
+        }
+        
+        let root = if pool.next_index == 0 {
+            empty_tree_root(pool.merkle_levels)
+        } else {
+            merkle_tree.get_root(pool.merkle_levels)
+        };
+        drop(merkle_tree);
+        
+        if root != pool.current_root {
+            pool.current_root = root;
+            let mut roots = RootsHistory::ring_mut(&ctx.accounts.roots_history)?;
+            roots.push(root, Clock::get()?.slot);
+        }
+        
+        emit!(TreeRootRebuilt {
+            root,
+            leaf_count: pool.next_index,
+        });
+        Ok(())
+    }
+    
+    /// Dispute the published root, posting `dispute_bond`
     /// 
-    /// Privacy: All withdrawals of same denomination look identical!
-    pub fn unshield(
-        ctx: Context<Unshield>,
-        proof_a: [u8; 64],
-        proof_b: [u8; 128],
-        proof_c: [u8; 64],
-        nullifier_hash: [u8; 32],
-        recipient: Pubkey,
-        withdrawal_amount: u64,   // Must be 1, 10, or 100 SOL
-        relayer_fee: u64,
-        merkle_root: [u8; 32],
-        change_commitment: [u8; 32], // New note for leftover balance
+    /// Claims that the leaves in the DISPUTE_RANGE_LEAVES-aligned range at
+    /// `disputed_range_start` do not hash to the current root (a hashing bug
+    /// or corrupted nodes), and that `claimed_correct_root` is the right one.
+    /// The bond sits in the TreeDispute account until resolve_dispute or
+    /// expire_tree_dispute closes it. One open dispute per challenger.
+    pub fn challenge_tree_root(
+        ctx: Context<ChallengeTreeRoot>,
+        claimed_correct_root: [u8; 32],
+        disputed_range_start: u64,
+        dispute_bond: u64,
     ) -> Result<()> {
-        // Withdrawal must be fixed denomination
+        let pool = &ctx.accounts.pool;
+        require!(dispute_bond >= MIN_DISPUTE_BOND, WhistleError::DisputeBondTooLow);
         require!(
-            withdrawal_amount == DENOM_001_SOL ||
-            withdrawal_amount == DENOM_005_SOL ||
-            withdrawal_amount == DENOM_01_SOL ||
-            withdrawal_amount == DENOM_1_SOL ||
-            withdrawal_amount == DENOM_10_SOL ||
-            withdrawal_amount == DENOM_100_SOL,
-            WhistleError::InvalidWithdrawDenomination
+            disputed_range_start.is_multiple_of(DISPUTE_RANGE_LEAVES) && disputed_range_start < pool.next_index,
+            WhistleError::InvalidDisputeRange
         );
+        require!(claimed_correct_root != pool.current_root, WhistleError::DisputeClaimsPublishedRoot);
 
-        require!(relayer_fee <= withdrawal_amount / 10, WhistleError::FeeTooHigh); // Max 10% fee
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.challenger.to_account_info(),
+                to: ctx.accounts.tree_dispute.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, dispute_bond)?;
 
-        let pool = &mut ctx.accounts.pool;
-        let nullifiers = &mut ctx.accounts.nullifiers.load_mut()?;
+        let dispute = &mut ctx.accounts.tree_dispute;
+        dispute.challenger = ctx.accounts.challenger.key();
+        dispute.claimed_root = claimed_correct_root;
+        dispute.disputed_root = pool.current_root;
+        dispute.range_start = disputed_range_start;
+        dispute.bond = dispute_bond;
+        dispute.opened_slot = Clock::get()?.slot;
+        dispute.bump = ctx.bumps.tree_dispute;
 
-        // Check nullifier not spent
+        emit!(TreeRootChallenged {
+            challenger: dispute.challenger,
+            claimed_root: claimed_correct_root,
+            disputed_root: dispute.disputed_root,
+            range_start: disputed_range_start,
+            bond: dispute_bond,
+        });
+        Ok(())
+    }
+
+    /// Defend the disputed root, taking the challenger's bond
+    /// 
+    /// Anyone can respond within DISPUTE_RESPONSE_SLOTS (the pool has no
+    /// operator). The program rehashes the disputed range from the leaves
+    /// stored in the tree and climbs to the root through the stored
+    /// siblings; the response succeeds only if that reproduces the disputed
+    /// root, so it must land before the tree moves on. The account's rent
+    /// goes back to the challenger.
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>) -> Result<()> {
+        let dispute = &ctx.accounts.tree_dispute;
         require!(
-            !nullifiers.is_spent(&nullifier_hash),
-            WhistleError::NullifierAlreadyUsed
+            Clock::get()?.slot <= dispute.opened_slot.saturating_add(DISPUTE_RESPONSE_SLOTS),
+            WhistleError::DisputeResponseClosed
         );
 
-        // Check root is valid (current or in history)
-        // Use a separate scope to drop the immutable borrow before potential mutable borrow
-        let root_valid = {
-            let roots = ctx.accounts.roots_history.load()?;
-            merkle_root == pool.current_root || roots.contains(&merkle_root)
-        };
-        require!(root_valid, WhistleError::InvalidMerkleRoot);
+        let pool = &ctx.accounts.pool;
+        let merkle_tree = ctx.accounts.merkle_tree.load()?;
+        merkle_tree.check_root(pool)?;
+        require!(
+            merkle_tree.range_root(dispute.range_start, pool.next_index, pool.merkle_levels) == dispute.disputed_root,
+            WhistleError::DisputedRangeMismatch
+        );
 
-        // Prepare recipient as field element (truncate to 31 bytes to fit BN254 field)
-        let recipient_bytes = recipient.to_bytes();
-        let mut recipient_field = [0u8; 32];
-        recipient_field[1..].copy_from_slice(&recipient_bytes[..31]);
+        **dispute.to_account_info().try_borrow_mut_lamports()? -= dispute.bond;
+        **ctx.accounts.responder.try_borrow_mut_lamports()? += dispute.bond;
 
-        // Verify Groth16 ZK proof
-        let proof_valid = verify_unshield_proof(
-            &proof_a,
-            &proof_b,
-            &proof_c,
-            &merkle_root,
-            &nullifier_hash,
-            &recipient_field,
-            withdrawal_amount,
-            relayer_fee,
-            &change_commitment,
-        )?;
+        emit!(TreeDisputeResolved {
+            challenger: dispute.challenger,
+            responder: ctx.accounts.responder.key(),
+            range_start: dispute.range_start,
+            bond: dispute.bond,
+        });
+        Ok(())
+    }
 
-        require!(proof_valid, WhistleError::InvalidProof);
+    /// Close an undefended dispute, returning the bond to the challenger
+    /// 
+    /// Once DISPUTE_RESPONSE_SLOTS pass without a successful
+    /// resolve_dispute, the challenge stands and TreeDisputeUpheld records
+    /// the claimed root. The pool's root is not replaced with it: rebuild_root
+    /// recomputes the tree from its leaves, which anyone can run.
+    pub fn expire_tree_dispute(ctx: Context<ExpireTreeDispute>) -> Result<()> {
+        let dispute = &ctx.accounts.tree_dispute;
+        require!(
+            Clock::get()?.slot > dispute.opened_slot.saturating_add(DISPUTE_RESPONSE_SLOTS),
+            WhistleError::DisputeResponseOpen
+        );
 
-        // Mark nullifier as spent (prevents double-spend)
-        nullifiers.mark_spent(&nullifier_hash)?;
-        
-        // Drop nullifiers borrow before accessing other accounts
-        drop(nullifiers);
+        emit!(TreeDisputeUpheld {
+            challenger: dispute.challenger,
+            claimed_root: dispute.claimed_root,
+            disputed_root: dispute.disputed_root,
+            range_start: dispute.range_start,
+        });
+        Ok(())
+    }
+    
+    /// Initialize roots history (step 3)
+    pub fn init_roots(ctx: Context<InitRoots>) -> Result<()> {
+        let roots = &mut ctx.accounts.roots_history.load_init()?;
+        roots.current_index = 0;
+        roots.capacity = ROOTS_HISTORY_CAPACITY as u16;
+        Ok(())
+    }
+    
+    /// Initialize nullifier set (step 4)
+    pub fn init_nullifiers(ctx: Context<InitNullifiers>) -> Result<()> {
+        let nullifiers = &mut ctx.accounts.nullifiers.load_init()?;
+        nullifiers.count = 0;
+        Ok(())
+    }
 
-        // If there's change, add it to the tree as a new note
-        let has_change = change_commitment != [0u8; 32];
-        if has_change {
-            let merkle_tree = &mut ctx.accounts.merkle_tree.load_mut()?;
-            let max_leaves = 1u64 << pool.merkle_levels;
-            require!(pool.next_index < max_leaves, WhistleError::TreeFull);
-            
-            let change_index = pool.next_index;
-            merkle_tree.insert_leaf(change_commitment, change_index, pool.merkle_levels);
-            pool.current_root = merkle_tree.get_root(pool.merkle_levels);
-            pool.next_index = pool.next_index.checked_add(1)
-                .ok_or(WhistleError::ArithmeticOverflow)?;
-            
-            // Drop merkle_tree borrow before accessing roots_history
-            drop(merkle_tree);
-            
-            // Update roots history
-            let mut roots = ctx.accounts.roots_history.load_mut()?;
-            let idx = roots.current_index as usize;
-            roots.roots[idx] = pool.current_root;
-            roots.current_index = ((roots.current_index as usize + 1) % 100) as u8;
-            
-            emit!(ChangeCreated {
-                commitment: change_commitment,
-                leaf_index: change_index,
-                timestamp: Clock::get()?.unix_timestamp,
-            });
+    /// Initialize per-denomination relayer fee caps (step 5)
+    ///
+    /// The table is fixed at genesis: there is no instruction to change it.
+    /// Only the program's upgrade authority may set it, so the deployer's
+    /// caps cannot be front-run between deploy and initialization.
+    pub fn init_denominations(
+        ctx: Context<InitDenominations>,
+        relayer_fee_caps_bps: [u16; 6],
+    ) -> Result<()> {
+        for cap in relayer_fee_caps_bps.iter() {
+            require!(*cap <= MAX_RELAYER_FEE_CAP_BPS, WhistleError::InvalidFeeCap);
         }
 
-        // Verify vault has sufficient balance
-        let vault_balance = ctx.accounts.pool_vault.lamports();
-        require!(vault_balance >= withdrawal_amount, WhistleError::InsufficientVaultBalance);
+        let config = &mut ctx.accounts.denomination_config;
+        config.denominations = DENOMINATIONS;
+        config.relayer_fee_caps_bps = relayer_fee_caps_bps;
+        config.bump = ctx.bumps.denomination_config;
+        Ok(())
+    }
 
-        // Calculate protocol fee (0.04%)
-        let protocol_fee = withdrawal_amount.checked_mul(PROTOCOL_FEE_BPS)
-            .ok_or(WhistleError::ArithmeticOverflow)?
-            .checked_div(BPS_DENOMINATOR)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
-        
-        // Transfer SOL from vault to recipient (minus relayer fee and protocol fee)
-        let withdrawal_net = withdrawal_amount
-            .checked_sub(relayer_fee)
-            .ok_or(WhistleError::ArithmeticOverflow)?
-            .checked_sub(protocol_fee)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
-        let vault_bump = ctx.bumps.pool_vault;
-        let vault_seeds: &[&[u8]] = &[b"vault", &[vault_bump]];
-        
-        // Transfer to recipient
-        anchor_lang::solana_program::program::invoke_signed(
-            &anchor_lang::solana_program::system_instruction::transfer(
-                ctx.accounts.pool_vault.key,
-                ctx.accounts.recipient.key,
-                withdrawal_net,
-            ),
-            &[
-                ctx.accounts.pool_vault.to_account_info(),
-                ctx.accounts.recipient.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            &[vault_seeds],
-        )?;
-        
-        // Pay relayer fee if any
-        if relayer_fee > 0 {
-            anchor_lang::solana_program::program::invoke_signed(
-                &anchor_lang::solana_program::system_instruction::transfer(
-                    ctx.accounts.pool_vault.key,
-                    ctx.accounts.relayer.key,
-                    relayer_fee,
-                ),
-                &[
-                    ctx.accounts.pool_vault.to_account_info(),
-                    ctx.accounts.relayer.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-                &[vault_seeds],
-            )?;
-        }
-        
-        // Transfer protocol fee to fee vault
-        if protocol_fee > 0 {
-            anchor_lang::solana_program::program::invoke_signed(
-                &anchor_lang::solana_program::system_instruction::transfer(
-                    ctx.accounts.pool_vault.key,
-                    ctx.accounts.fee_vault.key,
-                    protocol_fee,
-                ),
-                &[
-                    ctx.accounts.pool_vault.to_account_info(),
-                    ctx.accounts.fee_vault.to_account_info(),
-                    ctx.accounts.system_program.to_account_info(),
-                ],
-                &[vault_seeds],
-            )?;
-            
-            pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
-                .ok_or(WhistleError::ArithmeticOverflow)?;
-        }
-
-        // SECURITY FIX: Use checked_sub to prevent underflow
-        pool.total_shielded = pool.total_shielded
-            .checked_sub(withdrawal_amount)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
+    /// Initialize anonymity metrics (step 6)
+    pub fn init_pool_stats(ctx: Context<InitPoolStats>) -> Result<()> {
+        let stats = &mut ctx.accounts.pool_stats.load_init()?;
+        stats.current_epoch = Clock::get()?.slot / STATS_EPOCH_SLOTS;
+        stats.head = 0;
+        Ok(())
+    }
 
-        emit!(Unshielded {
-            nullifier_hash,
-            withdrawal_amount,
-            protocol_fee,
-            has_change,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+    /// Initialize the deposit histogram (step 7)
+    pub fn init_deposit_histogram(ctx: Context<InitDepositHistogram>) -> Result<()> {
+        ctx.accounts.deposit_histogram.buckets = [0; DEPOSIT_HISTOGRAM_BUCKETS];
+        ctx.accounts.deposit_histogram.bump = ctx.bumps.deposit_histogram;
+        Ok(())
+    }
 
+    /// Initialize congestion stats (step 8)
+    pub fn init_congestion_stats(ctx: Context<InitCongestionStats>) -> Result<()> {
+        let congestion = &mut ctx.accounts.congestion;
+        congestion.window = Clock::get()?.slot / CONGESTION_WINDOW_SLOTS;
+        congestion.bump = ctx.bumps.congestion;
         Ok(())
     }
 
-    /// Private Transfer - Move shielded balance without revealing amount
+    /// Get deposit counts per amount bucket (view, via return data)
+    pub fn get_deposit_histogram(ctx: Context<GetDepositHistogram>) -> Result<DepositHistogram> {
+        Ok((*ctx.accounts.deposit_histogram).clone())
+    }
+
+    /// Get age-bucketed anonymity metrics (view, via return data)
     /// 
-    /// Spends old notes, creates new notes with same total value.
-    /// Can split/merge balances privately.
-    pub fn private_transfer(
-        ctx: Context<PrivateTransfer>,
-        proof_a: [u8; 64],
-        proof_b: [u8; 128],
-        proof_c: [u8; 64],
-        input_nullifier_hashes: [[u8; 32]; 2],  // Spend up to 2 notes
-        output_commitments: [[u8; 32]; 2],      // Create up to 2 new notes
-        merkle_root: [u8; 32],
-    ) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
-        let mut nullifiers = ctx.accounts.nullifiers.load_mut()?;
+    /// Shield counts per amount band over the trailing 1k, 10k and 100k
+    /// slots, and per withdrawal denomination the number of deposits since
+    /// genesis large enough to have funded it.
+    pub fn get_anonymity_metrics(ctx: Context<GetAnonymityMetrics>) -> Result<AnonymityMetrics> {
+        let stats = ctx.accounts.pool_stats.load()?;
+        let mut metrics = stats.metrics(Clock::get()?.slot);
+        metrics.denomination_specific_set_sizes =
+            DENOMINATIONS.map(|denomination| compute_weighted_anonymity_set(denomination, &ctx.accounts.deposit_histogram));
+        Ok(metrics)
+    }
 
-        // Check root validity (use separate scope to release borrow)
-        let root_valid = {
-            let roots = ctx.accounts.roots_history.load()?;
-            merkle_root == pool.current_root || roots.contains(&merkle_root)
-        };
-        require!(root_valid, WhistleError::InvalidMerkleRoot);
+    /// Whether a client's Poseidon matches the program's (view, via return
+    /// data)
+    /// 
+    /// `test_vectors` are the client's hashes of the
+    /// COMMITMENT_COMPATIBILITY_INPUTS pairs, in order. Any mismatch (byte
+    /// order, arity, parameters) means the client computes commitments the
+    /// pool will never find.
+    pub fn verify_commitment_compatibility(
+        _ctx: Context<VerifyCommitmentCompatibility>,
+        test_vectors: [[u8; 32]; 3],
+    ) -> Result<bool> {
+        Ok(COMMITMENT_COMPATIBILITY_INPUTS
+            .iter()
+            .zip(test_vectors.iter())
+            .all(|((left, right), expected)| merkle_hash(left, right) == *expected))
+    }
 
-        // Check nullifiers not spent and mark them
-        for nullifier_hash in &input_nullifier_hashes {
-            if *nullifier_hash != [0u8; 32] {
-                require!(
-                    !nullifiers.is_spent(nullifier_hash),
-                    WhistleError::NullifierAlreadyUsed
-                );
-            }
-        }
+    /// Withdrawal and relayer approval rates for fee quotes (view, via
+    /// return data)
+    pub fn get_congestion_info(ctx: Context<GetCongestionInfo>) -> Result<CongestionInfo> {
+        Ok(ctx.accounts.congestion.info(Clock::get()?.slot))
+    }
 
-        // Verify the transfer proof
-        let proof_valid = verify_transfer_proof(
-            &proof_a,
-            &proof_b,
-            &proof_c,
-            &input_nullifier_hashes,
-            &output_commitments,
-            &merkle_root,
-        )?;
+    /// Page through spent nullifiers for audit exports (view, via return data)
+    /// 
+    /// Returns up to MAX_NULLIFIER_PAGE entries in spend order from `start`;
+    /// an empty page means the end of the ledger. Covers the legacy
+    /// NullifierSet only: later spends are the NullifierMarker accounts,
+    /// which getProgramAccounts lists.
+    pub fn get_spent_nullifiers(
+        ctx: Context<GetSpentNullifiers>,
+        start: u64,
+        count: u8,
+    ) -> Result<Vec<SpentNullifier>> {
+        require!(count <= MAX_NULLIFIER_PAGE, WhistleError::InvalidPageSize);
+        let nullifiers = ctx.accounts.nullifiers.load()?;
+        Ok(nullifiers.page(start, count))
+    }
 
-        require!(proof_valid, WhistleError::InvalidProof);
+    /// Read leaves of one leaf page (view, via return data)
+    /// 
+    /// Returns up to MAX_LEAF_READ commitments from offset `start` within page
+    /// `page_index`, covering leaves `page_index * LEAF_PAGE_SIZE + start`
+    /// onwards. A short page means the rest has not been inserted or synced.
+    pub fn get_leaves_page(
+        ctx: Context<GetLeavesPage>,
+        _page_index: u32,
+        start: u16,
+        count: u8,
+    ) -> Result<Vec<[u8; 32]>> {
+        require!(count <= MAX_LEAF_READ, WhistleError::InvalidPageSize);
+        let page = ctx.accounts.leaf_page.load()?;
+        Ok(page.read(start, count))
+    }
 
-        // Mark nullifiers as spent
-        for nullifier_hash in &input_nullifier_hashes {
-            if *nullifier_hash != [0u8; 32] {
-                nullifiers.mark_spent(nullifier_hash)?;
-            }
-        }
+    /// Copy leaves inserted outside `shield` into leaf page `page_index`,
+    /// creating the page if needed
+    /// 
+    /// Permissionless; the only call that loads the full MerkleTree account
+    /// for the leaf index.
+    pub fn sync_leaf_page(ctx: Context<SyncLeafPage>, page_index: u32) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(
+            (page_index as u64) * (LEAF_PAGE_SIZE as u64) < 1u64 << pool.merkle_levels,
+            WhistleError::InvalidLeafPage
+        );
+        let tree = ctx.accounts.merkle_tree.load()?;
+        tree.require_not_migrating(pool)?;
+        let mut page = MerkleTreeLeafPage::load_or_init(&ctx.accounts.leaf_page, page_index)?;
+        page.sync(&tree, pool.next_index, pool.merkle_levels);
+        Ok(())
+    }
+
+    /// Sibling path of leaf `leaf_index` (view, via return data)
+    /// 
+    /// Read from the stored nodes, so the path hashes to the pool's current
+    /// root and clients need not fetch the MerkleTree account to build it.
+    /// Feed it to whistle-merkle's verify_merkle_proof or a withdrawal
+    /// proof.
+    pub fn generate_merkle_proof(ctx: Context<GenerateMerkleProof>, leaf_index: u64) -> Result<MerklePath> {
+        let pool = &ctx.accounts.pool;
+        require!(leaf_index < pool.next_index, WhistleError::LeafNotInserted);
+        let tree = ctx.accounts.merkle_tree.load()?;
+        tree.check_root(pool)?;
+        Ok(whistle_merkle::generate_merkle_path(&tree.nodes, leaf_index, pool.merkle_levels.min(13)))
+    }
+
+    /// Store an encrypted copy of leaf `leaf_index`'s note for wallet recovery
+    /// 
+    /// Optional: shields never require it. `encrypted_data` (1 to
+    /// MAX_NOTE_CIPHERTEXT bytes) is opaque to the program; clients seal
+    /// the note's secret, nullifier and amount to the owner's key. The
+    /// account ties `owner` to the leaf, and one exists per leaf, so send
+    /// this in the shield's transaction to leave nobody a window to claim
+    /// the leaf's address first.
+    pub fn store_encrypted_note(ctx: Context<StoreNote>, leaf_index: u64, encrypted_data: Vec<u8>) -> Result<()> {
+        require!(leaf_index < ctx.accounts.pool.next_index, WhistleError::LeafNotInserted);
+        require!(
+            !encrypted_data.is_empty() && encrypted_data.len() <= MAX_NOTE_CIPHERTEXT,
+            WhistleError::InvalidNoteCiphertext
+        );
         
-        // Drop nullifiers borrow
-        drop(nullifiers);
+        let note = &mut ctx.accounts.note;
+        note.owner = ctx.accounts.owner.key();
+        note.leaf_index = leaf_index;
+        note.ciphertext = encrypted_data;
+        note.stored_at = Clock::get()?.unix_timestamp;
+        note.bump = ctx.bumps.note;
+        Ok(())
+    }
+    
+    /// Encrypted note stored for leaf `leaf_index` (view, via return data)
+    pub fn read_encrypted_note(ctx: Context<ReadEncryptedNote>, _leaf_index: u64) -> Result<EncryptedNote> {
+        Ok((*ctx.accounts.note).clone())
+    }
+    
+    /// Close a stored note, e.g. once it is spent, refunding its owner
+    /// 
+    /// Closing right after a spend hints at which leaf the spend used.
+    pub fn delete_encrypted_note(_ctx: Context<DeleteEncryptedNote>) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Publish `owner`'s view key, for selective disclosure to auditors
+    /// 
+    /// Clients derive it with derive_view_key from their spending key and
+    /// seal stored notes to it. The program cannot check the derivation:
+    /// the record only states which key the owner discloses under. One
+    /// record per owner; revoke it to register another key.
+    pub fn register_view_key(ctx: Context<RegisterViewKey>, view_key: [u8; 32]) -> Result<()> {
+        let record = &mut ctx.accounts.view_key_record;
+        record.owner = ctx.accounts.owner.key();
+        record.view_key = view_key;
+        record.registered_at = Clock::get()?.unix_timestamp;
+        record.bump = ctx.bumps.view_key_record;
+        
+        emit!(ViewKeyRegistered {
+            owner: record.owner,
+            view_key,
+            registered_at: record.registered_at,
+        });
+        Ok(())
+    }
+    
+    /// Close `owner`'s view key record, refunding its rent
+    /// 
+    /// Notes already disclosed stay disclosed; revocation only stops
+    /// auditors from checking new disclosures against the key.
+    pub fn revoke_view_key(ctx: Context<RevokeViewKey>) -> Result<()> {
+        let record = &ctx.accounts.view_key_record;
+        emit!(ViewKeyRevoked {
+            owner: record.owner,
+            view_key: record.view_key,
+            revoked_at: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+    
+    /// Whether `claimed_note`, decrypted under the registered `view_key`,
+    /// opens `commitment` (view, via return data)
+    /// 
+    /// False when `view_key` is not the record's key or the note hashes to
+    /// another commitment. `claimed_note` holds the note's spending secrets,
+    /// so simulate this rather than send it; reveal_note_amount discloses an
+    /// amount without them.
+    pub fn verify_view_key_decryption(
+        ctx: Context<VerifyDecryption>,
+        commitment: [u8; 32],
+        view_key: [u8; 32],
+        claimed_note: NoteData,
+    ) -> Result<bool> {
+        require_canonical_field_element(&claimed_note.secret)?;
+        require_canonical_field_element(&claimed_note.nullifier)?;
+        Ok(ctx.accounts.view_key_record.view_key == view_key && claimed_note.commitment() == commitment)
+    }
 
-        // Add new commitments to tree
-        let mut merkle_tree = ctx.accounts.merkle_tree.load_mut()?;
-        for commitment in &output_commitments {
-            if *commitment != [0u8; 32] {
-                let max_leaves = 1u64 << pool.merkle_levels;
-                require!(pool.next_index < max_leaves, WhistleError::TreeFull);
-                
-                let leaf_index = pool.next_index;
-                merkle_tree.insert_leaf(*commitment, leaf_index, pool.merkle_levels);
-                pool.next_index = pool.next_index.checked_add(1)
-                    .ok_or(WhistleError::ArithmeticOverflow)?;
-                
-                emit!(NoteCreated {
-                    commitment: *commitment,
-                    leaf_index,
-                    timestamp: Clock::get()?.unix_timestamp,
-                });
+    /// Spent status of a batch of nullifier hashes (view, via return data)
+    /// 
+    /// Bit i of the result is set when nullifier_hashes[i] is spent. Pad
+    /// short batches with zero hashes, which are never spent. The remaining
+    /// accounts are the hashes' nullifier markers, in the same order.
+    pub fn batch_nullifier_status(
+        ctx: Context<BatchNullifierStatus>,
+        nullifier_hashes: [[u8; 32]; NULLIFIER_STATUS_BATCH],
+    ) -> Result<u16> {
+        require!(
+            ctx.remaining_accounts.len() == NULLIFIER_STATUS_BATCH,
+            WhistleError::InvalidNullifierMarker
+        );
+        let nullifiers = ctx.accounts.nullifiers.load()?;
+        let mut mask = nullifiers.spent_mask(&nullifier_hashes);
+        for (i, (hash, marker)) in nullifier_hashes.iter().zip(ctx.remaining_accounts).enumerate() {
+            if NullifierMarker::spent_slot(&nullifiers, marker, hash)?.is_some() {
+                mask |= 1 << i;
             }
         }
+        Ok(mask)
+    }
 
-        pool.current_root = merkle_tree.get_root(pool.merkle_levels);
+    /// Slot a nullifier hash was spent at, or None if unspent (view, via
+    /// return data)
+    /// 
+    /// Answered from the hash's NullifierMarker, or for nullifiers spent
+    /// before markers, from the spend slots NullifierSet keeps beside each
+    /// hash.
+    pub fn query_nullifier_history(
+        ctx: Context<QueryNullifierHistory>,
+        nullifier_hash: [u8; 32],
+    ) -> Result<Option<u64>> {
+        let nullifiers = ctx.accounts.nullifiers.load()?;
+        NullifierMarker::spent_slot(&nullifiers, &ctx.accounts.nullifier_marker, &nullifier_hash)
+    }
+
+    /// Whether a spend could prove against `merkle_root` now (view, via
+    /// return data)
+    /// 
+    /// True for the current root and the roots history entries recorded
+    /// within MAX_ROOT_AGE_SLOTS, the same check every spend makes. The zero
+    /// root is never valid.
+    pub fn check_root(ctx: Context<CheckRoot>, merkle_root: [u8; 32]) -> Result<bool> {
+        let pool = &ctx.accounts.pool;
+        let roots = RootsHistory::ring(&ctx.accounts.roots_history)?;
+        Ok(merkle_root != [0u8; 32]
+            && (merkle_root == pool.current_root || roots.is_recent(&merkle_root, Clock::get()?.slot)))
+    }
+
+    /// Proof-of-reserve snapshot (view, via return data)
+    /// 
+    /// Vault balances, pool accounting and tree state read in one call, all
+    /// at the returned slot.
+    pub fn get_reserve_snapshot(ctx: Context<GetReserveSnapshot>) -> Result<ReserveSnapshot> {
+        let pool = &ctx.accounts.pool;
+        let vault_lamports = ctx.accounts.pool_vault.lamports();
+        Ok(ReserveSnapshot {
+            version: RESERVE_SNAPSHOT_VERSION,
+            slot: Clock::get()?.slot,
+            vault_lamports,
+            fee_vault_lamports: ctx.accounts.fee_vault.lamports(),
+            total_deposits: pool.total_deposits,
+            total_shielded: pool.total_shielded,
+            total_withdrawn: pool.total_deposits.saturating_sub(pool.total_shielded),
+            total_fees_collected: pool.total_fees_collected,
+            surplus: (vault_lamports as i128 - pool.total_shielded as i128) as i64,
+            leaf_count: pool.next_index,
+            current_root: pool.current_root,
+        })
+    }
+    
+    /// Check the pool's accounting against its accounts (view, via return data)
+    /// 
+    /// Solvent when the vault holds at least total_shielded. The leaf count
+    /// matches when the tree's leaf layer holds exactly next_index
+    /// commitments, and the nullifier count is valid when the frozen
+    /// NullifierSet records no more spends than there are notes. Failing
+    /// checks are reported, not errors; ReservesVerified leaves a record of
+    /// each run for indexers watching for drift.
+    pub fn verify_reserves(ctx: Context<VerifyReserves>) -> Result<ReservesReport> {
+        let pool = &ctx.accounts.pool;
+        let tree = ctx.accounts.merkle_tree.load()?;
+        tree.require_not_migrating(pool)?;
         
-        // Drop merkle_tree borrow before accessing roots_history
-        drop(merkle_tree);
+        let vault_balance = ctx.accounts.pool_vault.lamports();
+        let report = ReservesReport {
+            vault_balance,
+            shielded_balance: pool.total_shielded,
+            is_solvent: vault_balance >= pool.total_shielded,
+            leaf_count_matches: tree.leaf_count(pool.merkle_levels) == pool.next_index,
+            nullifier_count_valid: ctx.accounts.nullifiers.load()?.count <= pool.next_index,
+        };
         
-        // Update roots history
-        let mut roots = ctx.accounts.roots_history.load_mut()?;
-        let idx = roots.current_index as usize;
-        roots.roots[idx] = pool.current_root;
-        roots.current_index = ((roots.current_index as usize + 1) % 100) as u8;
-
-        emit!(PrivateTransferCompleted {
-            nullifiers_spent: 2,
-            notes_created: 2,
+        emit!(ReservesVerified {
+            is_solvent: report.is_solvent,
             timestamp: Clock::get()?.unix_timestamp,
         });
+        Ok(report)
+    }
+
+    /// Record whether this program can still be upgraded
+    /// 
+    /// Permissionless. Reads the program's ProgramData account and stores
+    /// whether its upgrade authority is burned. Re-attesting refreshes the
+    /// record; immutable_since_slot keeps the first attestation that saw no
+    /// authority, so wallets can show "immutable since slot N".
+    pub fn attest_finality(ctx: Context<AttestFinality>) -> Result<()> {
+        let program_data = &ctx.accounts.program_data;
+        let immutable = program_data.upgrade_authority_address.is_none();
+        let slot = Clock::get()?.slot;
 
+        let attestation = &mut ctx.accounts.finality_attestation;
+        attestation.immutable_since_slot = match (immutable, attestation.immutable) {
+            (false, _) => 0,
+            (true, false) => slot,
+            (true, true) => attestation.immutable_since_slot,
+        };
+        attestation.immutable = immutable;
+        attestation.deployed_slot = program_data.slot;
+        attestation.attested_slot = slot;
+        attestation.bump = ctx.bumps.finality_attestation;
+
+        emit!(FinalityAttested {
+            immutable,
+            immutable_since_slot: attestation.immutable_since_slot,
+            slot,
+        });
         Ok(())
     }
 
-    // =========================================================================
-    // LEGACY FUNCTIONS (for backward compatibility during hackathon)
-    // =========================================================================
+    /// Get the latest finality attestation (view, via return data)
+    pub fn get_finality_attestation(ctx: Context<GetFinalityAttestation>) -> Result<FinalityAttestation> {
+        Ok((*ctx.accounts.finality_attestation).clone())
+    }
 
-    /// Legacy deposit (fixed amounts) - maps to shield
-    /// Deposit (alias for shield) - accepts ANY amount >= 0.1 SOL
-    /// Withdrawals must be in fixed denominations (1, 10, 100 SOL) for privacy
-    pub fn deposit(ctx: Context<Shield>, commitment: [u8; 32], amount: u64) -> Result<()> {
-        require!(amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+    /// Raw contents of a pool state account (view, via return data)
+    /// 
+    /// Returns up to STATE_CHUNK_SIZE bytes of `account_kind` (STATE_*)
+    /// from `offset`, discriminator included; an empty chunk means the end
+    /// of the account. Used to clone a pool onto a test validator.
+    pub fn export_state_chunk(ctx: Context<ExportStateChunk>, account_kind: u8, offset: u32) -> Result<Vec<u8>> {
+        let account = ctx.accounts.account.to_account_info();
+        check_state_account(&account, account_kind)?;
+        let data = account.try_borrow_data()?;
+        let start = (offset as usize).min(data.len());
+        let end = (start + STATE_CHUNK_SIZE).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    /// Shield SOL - Deposit ANY amount into a shielded note
+    /// 
+    /// Creates a note commitment: hash(secret, nullifier, amount)
+    /// The amount is hidden inside the note, only the depositor knows it.
+    /// Protocol fee (0.04%) is collected and sent to fee vault.
+    pub fn shield(ctx: Context<Shield>, commitment: [u8; 32], amount: u64) -> Result<()> {
+        process_shield(ctx.accounts, commitment, amount)
+    }
+
+    /// Shield `amount` forwarded by a deposit router under `commitment`
+    /// 
+    /// Called through CPI: `forwarder` is a system-owned PDA of the router,
+    /// signed with invoke_signed, and funds the deposit. Nothing derived from
+    /// an account is stored or emitted: no deposit record is kept and the
+    /// Shielded event is the one a direct shield emits. Without a record the
+    /// per-address cap cannot be enforced, so forwarded shields fail with
+    /// ForwardedShieldWithAddressCap while one is set.
+    pub fn shield_forwarded(ctx: Context<ShieldForwarded>, commitment: [u8; 32], amount: u64) -> Result<()> {
+        process_shield_forwarded(ctx.accounts, commitment, amount)
+    }
+
+    /// Shield `total_amount` as eight notes with a single Groth16 proof
+    /// 
+    /// The batch_shield circuit proves that every commitment is a standard
+    /// note and that the eight note amounts sum to `total_amount`, so one
+    /// deposit can fund many small notes without revealing how it was
+    /// split. Fees, caps and pool statistics treat the batch as one shield
+    /// of `total_amount`. The leaves are inserted and announced as in
+    /// batch_shield; leaves past the end of the leaf page are left to
+    /// sync_leaf_page. The remaining accounts are the commitment markers of
+    /// commitments[1..], in order.
+    /// 
+    /// Requires the `batch-shield-zk` feature; fails with
+    /// VerifyingKeyNotGenerated otherwise.
+    pub fn shield_batch_zk<'info>(
+        ctx: Context<'_, '_, '_, 'info, Shield<'info>>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        commitments: [[u8; 32]; 8],
+        total_amount: u64,
+    ) -> Result<()> {
+        batch_shield_zk::shield_batch_zk(ctx, proof_a, proof_b, proof_c, commitments, total_amount)
+    }
+
+    /// Shield several notes with public amounts in one transaction
+    /// 
+    /// Each note is a shield of `amounts[i]` under `commitments[i]`: the
+    /// amount must reach MIN_DEPOSIT and pays its own protocol fee, and the
+    /// pool statistics count it as a separate deposit. The lamports move in
+    /// one transfer to each vault, and the deposit caps apply to the total.
+    /// Up to MAX_BATCH_SHIELD notes; a zero, repeated or already inserted
+    /// commitment rejects the whole batch. The root after each leaf enters
+    /// roots history, and the batch is announced by a single BatchShielded
+    /// instead of a NoteCreated per leaf. The remaining accounts are the
+    /// commitment markers of commitments[1..], in order.
+    pub fn batch_shield<'info>(
+        ctx: Context<'_, '_, '_, 'info, Shield<'info>>,
+        commitments: Vec<[u8; 32]>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+        require!(
+            !commitments.is_empty() && commitments.len() <= MAX_BATCH_SHIELD && commitments.len() == amounts.len(),
+            WhistleError::InvalidBatchSize
+        );
         
-        // Delegate to shield
-        let pool = &mut ctx.accounts.pool;
-        let merkle_tree = &mut ctx.accounts.merkle_tree.load_mut()?;
+        let mut protocol_fee = 0u64;
+        let mut net_amounts = Vec::with_capacity(amounts.len());
+        for (i, (commitment, amount)) in commitments.iter().zip(&amounts).enumerate() {
+            require!(*amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+            require!(*commitment != [0u8; 32], WhistleError::ZeroCommitment);
+            require_canonical_field_element(commitment)?;
+            require!(!commitments[..i].contains(commitment), WhistleError::DuplicateCommitment);
+            
+            let fee = amount.checked_mul(PROTOCOL_FEE_BPS)
+                .ok_or(WhistleError::ArithmeticOverflow)?
+                .checked_div(BPS_DENOMINATOR)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+            protocol_fee = protocol_fee.checked_add(fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+            net_amounts.push(amount.checked_sub(fee).ok_or(WhistleError::ArithmeticOverflow)?);
+        }
+        let net_amount = net_amounts.iter().try_fold(0u64, |total, net| total.checked_add(*net))
+            .ok_or(WhistleError::ArithmeticOverflow)?;
         
+        let accounts = ctx.accounts;
+        let pool = &mut accounts.pool;
         let max_leaves = 1u64 << pool.merkle_levels;
-        require!(pool.next_index < max_leaves, WhistleError::TreeFull);
+        let end_index = pool.next_index.checked_add(commitments.len() as u64)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        require!(end_index <= max_leaves, WhistleError::TreeFull);
+        record_batch_commitments(
+            &accounts.commitment_marker,
+            ctx.remaining_accounts,
+            &accounts.depositor.to_account_info(),
+            &accounts.system_program.to_account_info(),
+            &commitments,
+            pool.next_index,
+        )?;
         
-        let cpi_context = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.depositor.to_account_info(),
-                to: ctx.accounts.pool_vault.to_account_info(),
-            },
-        );
-        system_program::transfer(cpi_context, amount)?;
+        record_deposit(pool, &mut accounts.deposit_record, accounts.depositor.key(), net_amount)?;
         
-        let leaf_index = pool.next_index;
-        merkle_tree.insert_leaf(commitment, leaf_index, pool.merkle_levels);
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: accounts.depositor.to_account_info(),
+                    to: accounts.pool_vault.to_account_info(),
+                },
+            ),
+            net_amount,
+        )?;
+        if protocol_fee > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: accounts.depositor.to_account_info(),
+                        to: accounts.fee_vault.to_account_info(),
+                    },
+                ),
+                protocol_fee,
+            )?;
+            pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+        }
         
-        pool.current_root = merkle_tree.get_root(pool.merkle_levels);
-        pool.next_index = pool.next_index.checked_add(1)
-            .ok_or(WhistleError::ArithmeticOverflow)?;
-        pool.total_deposits = pool.total_deposits.checked_add(amount)
+        {
+            let merkle_tree = accounts.merkle_tree.load()?;
+            for commitment in &commitments {
+                require!(
+                    !merkle_tree.contains_leaf(commitment, pool.next_index, pool.merkle_levels),
+                    WhistleError::DuplicateCommitment
+                );
+            }
+        }
+        insert_batch(pool, &accounts.merkle_tree, &accounts.roots_history, &accounts.leaf_page, &commitments, net_amount)?;
+        pool.total_deposits = pool.total_deposits.checked_add(net_amount)
             .ok_or(WhistleError::ArithmeticOverflow)?;
-        pool.total_shielded = pool.total_shielded.checked_add(amount)
+        pool.total_shielded = pool.total_shielded.checked_add(net_amount)
             .ok_or(WhistleError::ArithmeticOverflow)?;
         
-        let roots = &mut ctx.accounts.roots_history.load_mut()?;
-        let idx = roots.current_index as usize;
-        roots.roots[idx] = pool.current_root;
-        roots.current_index = ((roots.current_index as usize + 1) % 100) as u8;
-        
-        emit!(Shielded {
-            commitment,
-            leaf_index,
-            amount,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
-        
+        let slot = Clock::get()?.slot;
+        let mut pool_stats = accounts.pool_stats.load_mut()?;
+        for (amount, net) in amounts.iter().zip(net_amounts) {
+            pool_stats.record_shield(slot, net);
+            accounts.deposit_histogram.record(*amount);
+        }
         Ok(())
     }
 
-    /// Legacy withdraw (no change) - maps to unshield with zero change
-    pub fn withdraw(
-        ctx: Context<Unshield>,
-        proof_a: [u8; 64],
-        proof_b: [u8; 128],
-        proof_c: [u8; 64],
-        nullifier_hash: [u8; 32],
-        recipient: Pubkey,
-        amount: u64,
-        relayer_fee: u64,
-        merkle_root: [u8; 32],
+    /// Stake PRE_COMMIT_STAKE lamports behind `sha256(commitment)` ahead of a
+    /// large shield
+    /// 
+    /// The commitment itself is only published by `reveal_shield`, which must
+    /// land within PRE_COMMIT_REVEAL_SLOTS of `deposit_slot`.
+    pub fn pre_commit_shield(
+        ctx: Context<PreCommitShield>,
+        commitment_hash: [u8; 32],
+        deposit_slot: u64,
     ) -> Result<()> {
+        let slot = Clock::get()?.slot;
         require!(
-            amount == DENOM_001_SOL || amount == DENOM_005_SOL || amount == DENOM_01_SOL ||
-            amount == DENOM_1_SOL || amount == DENOM_10_SOL || amount == DENOM_100_SOL,
-            WhistleError::InvalidWithdrawDenomination
+            deposit_slot <= slot && slot - deposit_slot <= PRE_COMMIT_REVEAL_SLOTS,
+            WhistleError::InvalidPreCommitSlot
         );
 
-        require!(relayer_fee <= amount / 10, WhistleError::FeeTooHigh);
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: ctx.accounts.pre_commit.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, PRE_COMMIT_STAKE)?;
 
-        let pool = &mut ctx.accounts.pool;
-        let nullifiers = &mut ctx.accounts.nullifiers.load_mut()?;
-        let roots = &ctx.accounts.roots_history.load()?;
+        let pre_commit = &mut ctx.accounts.pre_commit;
+        pre_commit.payer = ctx.accounts.payer.key();
+        pre_commit.commitment_hash = commitment_hash;
+        pre_commit.deposit_slot = deposit_slot;
+        pre_commit.bump = ctx.bumps.pre_commit;
 
-        require!(
-            !nullifiers.is_spent(&nullifier_hash),
-            WhistleError::NullifierAlreadyUsed
+        emit!(PreCommitted {
+            payer: pre_commit.payer,
+            commitment_hash,
+            deposit_slot,
+        });
+        Ok(())
+    }
+
+    /// Shield a pre-committed note
+    /// 
+    /// Closes the pre-commit account to its payer, refunding the stake.
+    pub fn reveal_shield(ctx: Context<RevealShield>, commitment: [u8; 32], amount: u64) -> Result<()> {
+        let pre_commit = &ctx.accounts.pre_commit;
+        require!(
+            hash(&commitment).to_bytes() == pre_commit.commitment_hash,
+            WhistleError::PreCommitHashMismatch
+        );
+        require!(
+            Clock::get()?.slot <= pre_commit.deposit_slot.saturating_add(PRE_COMMIT_REVEAL_SLOTS),
+            WhistleError::PreCommitExpired
+        );
+
+        process_shield(&mut ctx.accounts.shield, commitment, amount)
+    }
+
+    /// Take the stake of a pre-commit that was never revealed
+    /// 
+    /// Anyone can claim once the reveal window has passed; the account's
+    /// rent goes back to the payer.
+    pub fn claim_expired_pre_commit(ctx: Context<ClaimExpiredPreCommit>) -> Result<()> {
+        let pre_commit = &ctx.accounts.pre_commit;
+        require!(
+            Clock::get()?.slot > pre_commit.deposit_slot.saturating_add(PRE_COMMIT_REVEAL_SLOTS),
+            WhistleError::PreCommitNotExpired
+        );
+
+        let pre_commit_info = pre_commit.to_account_info();
+        **pre_commit_info.try_borrow_mut_lamports()? -= PRE_COMMIT_STAKE;
+        **ctx.accounts.claimer.try_borrow_mut_lamports()? += PRE_COMMIT_STAKE;
+
+        emit!(PreCommitClaimed {
+            payer: pre_commit.payer,
+            commitment_hash: pre_commit.commitment_hash,
+            claimer: ctx.accounts.claimer.key(),
+        });
+        Ok(())
+    }
+
+    /// Unshield SOL - Withdraw in FIXED denomination + re-shield change
+    /// 
+    /// ZK Proof verifies:
+    /// 1. User knows secret/nullifier for a note in the tree
+    /// 2. Note value >= withdrawal amount
+    /// 3. Change commitment is correctly computed
+    /// 
+    /// Privacy: All withdrawals of same denomination look identical!
+    pub fn unshield(
+        ctx: Context<Unshield>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        nullifier_hash: [u8; 32],
+        recipient: Pubkey,
+        withdrawal_amount: u64,   // Must be 1, 10, or 100 SOL
+        relayer_fee: u64,
+        merkle_root: [u8; 32],
+        change_commitment: [u8; 32], // New note for leftover balance
+        unlock_slot: u64,            // Zero unless the note is time-locked
+    ) -> Result<()> {
+        process_unshield(
+            ctx.accounts,
+            UnshieldArgs {
+                proof_a,
+                proof_b,
+                proof_c,
+                nullifier_hash,
+                recipient,
+                withdrawal_amount,
+                relayer_fee,
+                merkle_root,
+                change_commitment,
+                unlock_slot,
+            },
+            false,
+        )
+    }
+
+    /// Unshield without a relayer, submitted by the user themselves
+    /// 
+    /// The censorship-resistance fallback when every relayer refuses a
+    /// recipient. The relayer account is omitted and the relayer fee is
+    /// fixed at zero: the proof must be generated with relayerFee = 0, the
+    /// public input that selects this path (the circuits bind no relayer
+    /// address). Unshielded reports self_relayed so monitoring can measure
+    /// how often relayers are bypassed.
+    pub fn self_unshield(
+        ctx: Context<Unshield>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        nullifier_hash: [u8; 32],
+        recipient: Pubkey,
+        withdrawal_amount: u64,
+        merkle_root: [u8; 32],
+        change_commitment: [u8; 32],
+        unlock_slot: u64,
+    ) -> Result<()> {
+        process_unshield(
+            ctx.accounts,
+            UnshieldArgs {
+                proof_a,
+                proof_b,
+                proof_c,
+                nullifier_hash,
+                recipient,
+                withdrawal_amount,
+                relayer_fee: 0,
+                merkle_root,
+                change_commitment,
+                unlock_slot,
+            },
+            true,
+        )
+    }
+
+    /// Map an Ethereum address to the Solana account its authorized
+    /// unshields must pay
+    /// 
+    /// The Ethereum wallet signs eip712::register_hash(authorized, nonce);
+    /// anyone can submit it and pay the mapping's rent. Registering again
+    /// with the next nonce moves the mapping to a new account.
+    pub fn register_eth_address(
+        ctx: Context<RegisterEthAddress>,
+        eth_address: [u8; 20],
+        authorized: Pubkey,
+        eth_signature: [u8; 65],
+    ) -> Result<()> {
+        let mapping = &mut ctx.accounts.eth_mapping;
+        let hash = eip712::register_hash(&authorized, mapping.nonce, eip712::EIP712_CHAIN_ID);
+        require!(
+            eip712::recover_eth_address(&hash, &eth_signature) == Some(eth_address),
+            WhistleError::InvalidEthSignature
+        );
+
+        mapping.eth_address = eth_address;
+        mapping.authorized = authorized;
+        mapping.nonce = mapping.nonce.checked_add(1)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        mapping.bump = ctx.bumps.eth_mapping;
+
+        emit!(EthAddressRegistered { eth_address, authorized, nonce: mapping.nonce });
+        Ok(())
+    }
+
+    /// Unshield authorized by an Ethereum wallet's EIP-712 signature
+    /// 
+    /// For users whose hardware wallet only signs Ethereum data: the
+    /// wallet signs eip712::unshield_hash over every public input of the
+    /// proof, a relayer submits it, and the recipient must be the account
+    /// `eth_address` is mapped to. Otherwise identical to unshield.
+    pub fn unshield_eip712(
+        ctx: Context<UnshieldEip712>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        nullifier_hash: [u8; 32],
+        recipient: Pubkey,
+        withdrawal_amount: u64,
+        relayer_fee: u64,
+        merkle_root: [u8; 32],
+        change_commitment: [u8; 32],
+        unlock_slot: u64,
+        eth_signature: [u8; 65],
+        eth_address: [u8; 20],
+    ) -> Result<()> {
+        let args = UnshieldArgs {
+            proof_a,
+            proof_b,
+            proof_c,
+            nullifier_hash,
+            recipient,
+            withdrawal_amount,
+            relayer_fee,
+            merkle_root,
+            change_commitment,
+            unlock_slot,
+        };
+        let hash = eip712::unshield_hash(&args, eip712::EIP712_CHAIN_ID);
+        require!(
+            eip712::recover_eth_address(&hash, &eth_signature) == Some(eth_address),
+            WhistleError::InvalidEthSignature
+        );
+        require!(
+            recipient == ctx.accounts.eth_mapping.authorized
+                && ctx.accounts.unshield.recipient.key() == recipient,
+            WhistleError::EthAddressNotAuthorized
+        );
+
+        process_unshield(&mut ctx.accounts.unshield, args, false)
+    }
+
+    /// Stage part of an oversized instruction payload (proof + public inputs)
+    /// 
+    /// Chunks are appended in order to a ProofStaging PDA derived from the
+    /// creator and session id; only the creator can write to it.
+    pub fn stage_proof_chunk(
+        ctx: Context<StageProofChunk>,
+        session_id: u64,
+        offset: u16,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        require!(bytes.len() <= MAX_PROOF_CHUNK_SIZE, WhistleError::ProofChunkTooLarge);
+
+        let staging = &mut ctx.accounts.staging;
+        if staging.creator == Pubkey::default() {
+            staging.creator = ctx.accounts.creator.key();
+            staging.session_id = session_id;
+            staging.created_slot = Clock::get()?.slot;
+            staging.len = 0;
+            staging.bump = ctx.bumps.staging;
+        }
+
+        // Append-only: a chunk can never overwrite staged bytes
+        require!(offset == staging.len, WhistleError::InvalidProofChunkOffset);
+        let end = offset as usize + bytes.len();
+        require!(end <= PROOF_STAGING_CAPACITY, WhistleError::ProofChunkTooLarge);
+
+        staging.data[offset as usize..end].copy_from_slice(&bytes);
+        staging.len = end as u16;
+        Ok(())
+    }
+
+    /// Unshield using a payload uploaded with stage_proof_chunk
+    /// 
+    /// The staged bytes are the borsh-encoded UnshieldArgs. The staging
+    /// account is closed to its creator, so a session can only be used once.
+    pub fn unshield_from_staged(ctx: Context<UnshieldFromStaged>, _session_id: u64) -> Result<()> {
+        let staging = &ctx.accounts.staging;
+        require!(
+            Clock::get()?.slot < staging.created_slot.saturating_add(PROOF_STAGING_EXPIRY_SLOTS),
+            WhistleError::ProofStagingExpired
+        );
+
+        let args = UnshieldArgs::try_from_slice(&staging.data[..staging.len as usize])
+            .map_err(|_| error!(WhistleError::InvalidStagedPayload))?;
+
+        process_unshield(&mut ctx.accounts.unshield, args, false)
+    }
+
+    /// Close a staging account and return its rent to the creator
+    /// 
+    /// The creator can close at any time; anyone can once it has expired.
+    pub fn close_proof_staging(ctx: Context<CloseProofStaging>, _session_id: u64) -> Result<()> {
+        let staging = &ctx.accounts.staging;
+        let expired = Clock::get()?.slot
+            >= staging.created_slot.saturating_add(PROOF_STAGING_EXPIRY_SLOTS);
+        require!(
+            expired || ctx.accounts.closer.key() == staging.creator,
+            WhistleError::ProofStagingNotExpired
+        );
+        Ok(())
+    }
+
+    /// Post or top up a relayer's bond, held by its relayer_bond PDA
+    pub fn register_relayer(ctx: Context<RegisterRelayer>, amount: u64) -> Result<()> {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.relayer.to_account_info(),
+                to: ctx.accounts.relayer_bond.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let relayer_bond = &mut ctx.accounts.relayer_bond;
+        relayer_bond.relayer = ctx.accounts.relayer.key();
+        relayer_bond.bond = relayer_bond.bond.checked_add(amount)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        relayer_bond.bump = ctx.bumps.relayer_bond;
+        require!(relayer_bond.bond >= SLASH_AMOUNT, WhistleError::RelayerBondTooLow);
+
+        emit!(RelayerBonded {
+            relayer: relayer_bond.relayer,
+            bond: relayer_bond.bond,
+        });
+        Ok(())
+    }
+
+    /// Record that a bonded relayer accepted a withdrawal
+    /// 
+    /// Signed by both the user and the relayer, so the relayer cannot deny
+    /// taking the job. Only the nullifier hash goes on-chain; the proof stays
+    /// with the relayer. Once the note is spent, the user's wallet is linked
+    /// to that withdrawal, so sign with one unconnected to the deposit.
+    pub fn approve_relayer_withdrawal(
+        ctx: Context<ApproveRelayerWithdrawal>,
+        nullifier_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(ctx.accounts.relayer_bond.bond >= SLASH_AMOUNT, WhistleError::RelayerBondTooLow);
+        NullifierMarker::require_unspent(
+            &*ctx.accounts.nullifiers.load()?,
+            &ctx.accounts.nullifier_marker,
+            &nullifier_hash,
+        )?;
+
+        let pending = &mut ctx.accounts.pending_withdrawal;
+        pending.user = ctx.accounts.user.key();
+        pending.relayer = ctx.accounts.relayer.key();
+        pending.nullifier_hash = nullifier_hash;
+        pending.approved_slot = Clock::get()?.slot;
+        pending.bump = ctx.bumps.pending_withdrawal;
+        ctx.accounts.congestion.record_approval(pending.approved_slot);
+        Ok(())
+    }
+
+    /// Slash a relayer that left an approved withdrawal unsubmitted
+    /// 
+    /// Once RELAYER_EXECUTION_TIMEOUT_SLOTS have passed with the nullifier
+    /// still unspent, SLASH_AMOUNT (or what is left of the bond) goes to the
+    /// user and the pending withdrawal is closed, so the user can approve
+    /// another relayer for the same note.
+    pub fn prove_relayer_inactivity(ctx: Context<ProveRelayerInactivity>, relayer_pubkey: Pubkey) -> Result<()> {
+        let pending = &ctx.accounts.pending_withdrawal;
+        require!(
+            Clock::get()?.slot > pending.approved_slot.saturating_add(RELAYER_EXECUTION_TIMEOUT_SLOTS),
+            WhistleError::RelayerNotInactive
+        );
+        // A spent nullifier means the withdrawal went through, whoever sent it
+        NullifierMarker::require_unspent(
+            &*ctx.accounts.nullifiers.load()?,
+            &ctx.accounts.nullifier_marker,
+            &pending.nullifier_hash,
+        )?;
+
+        let relayer_bond = &mut ctx.accounts.relayer_bond;
+        let slash_amount = SLASH_AMOUNT.min(relayer_bond.bond);
+        relayer_bond.bond -= slash_amount;
+        **relayer_bond.to_account_info().try_borrow_mut_lamports()? -= slash_amount;
+        **ctx.accounts.user.try_borrow_mut_lamports()? += slash_amount;
+
+        emit!(RelayerSlashed {
+            relayer: relayer_pubkey,
+            reason: "inactivity".to_string(),
+            slash_amount,
+        });
+        Ok(())
+    }
+
+    /// Claim a nullifier hash for one submitter node before sending its
+    /// withdrawal
+    /// 
+    /// Coordination only: withdrawals never check the lock. Nodes racing the
+    /// same withdrawal take the lock first, and the loser fails here
+    /// (IntentLocked) instead of paying for a withdrawal that fails at the
+    /// nullifier check. The lock lapses INTENT_LOCK_TTL_SLOTS after it was
+    /// taken, so a crashed node holds nothing up; its holder may renew it.
+    pub fn take_intent_lock(ctx: Context<TakeIntentLock>, nullifier_hash: [u8; 32]) -> Result<()> {
+        NullifierMarker::require_unspent(
+            &*ctx.accounts.nullifiers.load()?,
+            &ctx.accounts.nullifier_marker,
+            &nullifier_hash,
+        )?;
+
+        let slot = Clock::get()?.slot;
+        let lock = &mut ctx.accounts.intent_lock;
+        let holder = ctx.accounts.holder.key();
+        if lock.holder == Pubkey::default() {
+            lock.nullifier_hash = nullifier_hash;
+            lock.rent_payer = holder;
+            lock.bump = ctx.bumps.intent_lock;
+        } else {
+            require!(lock.holder == holder || slot >= lock.expires_slot, WhistleError::IntentLocked);
+        }
+        lock.holder = holder;
+        lock.expires_slot = slot.saturating_add(INTENT_LOCK_TTL_SLOTS);
+        Ok(())
+    }
+
+    /// Close an intent lock, returning its rent to whoever created it
+    /// 
+    /// The holder can release at any time, e.g. once its withdrawal lands or
+    /// it gives up; anyone can once the lock has lapsed.
+    pub fn release_intent_lock(ctx: Context<ReleaseIntentLock>) -> Result<()> {
+        let lock = &ctx.accounts.intent_lock;
+        require!(
+            lock.holder == ctx.accounts.closer.key() || Clock::get()?.slot >= lock.expires_slot,
+            WhistleError::IntentLockHeld
+        );
+        Ok(())
+    }
+
+    /// Private Transfer - Move shielded balance without revealing amount
+    /// 
+    /// Spends old notes, creates new notes with same total value.
+    /// Can split/merge balances privately.
+    /// 
+    /// Several transfers can share a transaction. Each proves against a
+    /// root that stays in the roots history, so none depends on another's
+    /// insertions; outputs take leaf indices in instruction order (reported
+    /// by NoteCreated). A failing transfer rolls back the whole transaction,
+    /// including nullifiers marked by the ones before it.
+    pub fn private_transfer(
+        ctx: Context<PrivateTransfer>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        input_nullifier_hashes: [[u8; 32]; 2],  // Spend up to 2 notes
+        output_commitments: [[u8; 32]; 2],      // Create up to 2 new notes
+        merkle_root: [u8; 32],
+        unlock_slots: [u64; 2], // Per input note, zero unless time-locked
+    ) -> Result<()> {
+        let leg = TransferLeg {
+            proof_a,
+            proof_b,
+            proof_c,
+            input_nullifier_hashes,
+            output_commitments,
+            unlock_slots,
+        };
+        let markers = [ctx.accounts.input_marker_0.clone(), ctx.accounts.input_marker_1.clone()];
+        let output_markers = [ctx.accounts.output_marker_0.clone(), ctx.accounts.output_marker_1.clone()];
+        process_private_transfer(ctx.accounts, markers, output_markers, leg, merkle_root)
+    }
+
+    /// Swap notes of two denominations between two parties, atomically
+    /// 
+    /// The maker's off-chain signed intent names the note it gives,
+    /// `from_commitment` (worth `from_denomination`), and the note it wants,
+    /// `to_commitment` (worth `to_denomination`). Each commitment is built
+    /// from secrets its receiver generated. The maker's leg is a
+    /// private_transfer creating `from_commitment`, the taker's leg one
+    /// creating `to_commitment`; both must prove against `merkle_root`, and
+    /// either failing rolls back the other.
+    /// 
+    /// The proofs hide amounts, so the program only checks that the
+    /// denominations are real and differ; each party checks that the
+    /// commitment it receives opens to the agreed amount. Each leg is also
+    /// a valid private_transfer alone, so the party assembling the swap must
+    /// be trusted not to land only the leg that pays it.
+    pub fn execute_denomination_swap(
+        ctx: Context<DenominationSwap>,
+        intent: SwapIntent,
+        maker_leg: TransferLeg,
+        taker_leg: TransferLeg,
+        merkle_root: [u8; 32],
+    ) -> Result<()> {
+        require!(
+            DENOMINATIONS.contains(&intent.from_denomination)
+                && DENOMINATIONS.contains(&intent.to_denomination)
+                && intent.from_denomination != intent.to_denomination,
+            WhistleError::InvalidSwapIntent
+        );
+        require!(
+            intent.from_commitment != [0u8; 32] && maker_leg.output_commitments.contains(&intent.from_commitment),
+            WhistleError::SwapLegMismatch
+        );
+        require!(
+            intent.to_commitment != [0u8; 32] && taker_leg.output_commitments.contains(&intent.to_commitment),
+            WhistleError::SwapLegMismatch
+        );
+
+        let swap = ctx.accounts;
+        let maker_markers = [swap.transfer.input_marker_0.clone(), swap.transfer.input_marker_1.clone()];
+        let maker_outputs = [swap.transfer.output_marker_0.clone(), swap.transfer.output_marker_1.clone()];
+        process_private_transfer(&mut swap.transfer, maker_markers, maker_outputs, maker_leg, merkle_root)?;
+        let taker_markers = [swap.taker_marker_0.clone(), swap.taker_marker_1.clone()];
+        let taker_outputs = [swap.taker_output_marker_0.clone(), swap.taker_output_marker_1.clone()];
+        process_private_transfer(&mut swap.transfer, taker_markers, taker_outputs, taker_leg, merkle_root)?;
+
+        let clock = Clock::get()?;
+        emit!(DenominationSwapped {
+            from_commitment: intent.from_commitment,
+            from_denomination: intent.from_denomination,
+            to_commitment: intent.to_commitment,
+            to_denomination: intent.to_denomination,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    /// Reveal Note Amount - Prove a note's amount to an auditor without spending it
+    /// 
+    /// ZK Proof verifies commitment == Poseidon(secret, Poseidon(nullifier, amount))
+    /// and binds the disclosure to `auditor_pubkey`. The nullifier hash is never
+    /// revealed, so the disclosure cannot be linked to the note's eventual
+    /// withdrawal.
+    /// 
+    /// Requires the `amount-reveal` feature; fails with VerifyingKeyNotGenerated
+    /// otherwise.
+    pub fn reveal_note_amount(
+        ctx: Context<RevealNoteAmount>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        commitment: [u8; 32],
+        amount: u64,
+        auditor_pubkey: [u8; 32],
+    ) -> Result<()> {
+        disclosure::reveal_note_amount(ctx, proof_a, proof_b, proof_c, commitment, amount, auditor_pubkey)
+    }
+
+    /// Shield SOL into a Jubjub-style Pedersen commitment spendable by
+    /// `spend_pubkey`
+    /// 
+    /// Requires the `jubjub` feature; fails with UnsupportedCurve otherwise.
+    pub fn shield_jubjub(
+        ctx: Context<Shield>,
+        commitment: JubjubCommitment,
+        spend_pubkey: [u8; 32],
+        amount: u64,
+    ) -> Result<()> {
+        jubjub::shield_jubjub(ctx, commitment, spend_pubkey, amount)
+    }
+
+    /// Unshield a Jubjub-style commitment by opening it, signed by its
+    /// spend key through the Ed25519 precompile
+    /// 
+    /// Requires the `jubjub` feature; fails with UnsupportedCurve otherwise.
+    pub fn unshield_jubjub(
+        ctx: Context<UnshieldJubjub>,
+        commitment: JubjubCommitment,
+        randomness: [u8; 32],
+        amount: u64,
+        recipient: Pubkey,
+        relayer_fee: u64,
+        signature: [u8; 64],
+    ) -> Result<()> {
+        jubjub::unshield_jubjub(ctx, commitment, randomness, amount, recipient, relayer_fee, signature)
+    }
+
+    // =========================================================================
+    // LEGACY FUNCTIONS (for backward compatibility during hackathon)
+    // =========================================================================
+
+    /// Legacy deposit - shield under its old name
+    /// 
+    /// Runs process_shield, so fees, caps, accounting and the Shielded
+    /// event are the same as for shield.
+    pub fn deposit(ctx: Context<Shield>, commitment: [u8; 32], amount: u64) -> Result<()> {
+        process_shield(ctx.accounts, commitment, amount)
+    }
+
+    /// Legacy withdraw (no change) - maps to unshield with zero change
+    pub fn withdraw(
+        ctx: Context<Unshield>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        nullifier_hash: [u8; 32],
+        recipient: Pubkey,
+        amount: u64,
+        relayer_fee: u64,
+        merkle_root: [u8; 32],
+        unlock_slot: u64,
+    ) -> Result<()> {
+        require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+        require!(
+            amount == DENOM_001_SOL || amount == DENOM_005_SOL || amount == DENOM_01_SOL ||
+            amount == DENOM_1_SOL || amount == DENOM_10_SOL || amount == DENOM_100_SOL,
+            WhistleError::InvalidWithdrawDenomination
         );
 
         require!(
-            merkle_root == pool.current_root || roots.contains(&merkle_root),
-            WhistleError::InvalidMerkleRoot
+            relayer_fee <= ctx.accounts.denomination_config.max_relayer_fee(amount)?,
+            WhistleError::FeeTooHigh
         );
+        check_relayer(ctx.accounts.relayer.as_ref(), relayer_fee, false)?;
+
+        require!(nullifier_hash != [0u8; 32], WhistleError::ZeroNullifierHash);
+        require_canonical_field_element(&nullifier_hash)?;
+        require_unlocked(unlock_slot)?;
+
+        let pool = &mut ctx.accounts.pool;
+        let nullifiers = &ctx.accounts.nullifiers.load()?;
+        let roots = &RootsHistory::ring(&ctx.accounts.roots_history)?;
+
+        NullifierMarker::require_unspent(nullifiers, &ctx.accounts.nullifier_marker, &nullifier_hash)?;
+
+        require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+        require!(pool.next_index > 0, WhistleError::TreeEmpty);
+        roots.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
 
         // Prepare recipient as field element (truncate to 31 bytes to fit BN254 field)
-        let recipient_bytes = recipient.to_bytes();
-        let mut recipient_field = [0u8; 32];
-        recipient_field[1..].copy_from_slice(&recipient_bytes[..31]);
+        let recipient_field = pubkey_to_field(&recipient.to_bytes());
 
         // Verify Groth16 proof
         let proof_valid = verify_withdraw_proof(
@@ -561,11 +1881,19 @@ pub mod whistle_pool {
             &recipient_field,
             amount,
             relayer_fee,
+            unlock_slot,
         )?;
 
         require!(proof_valid, WhistleError::InvalidProof);
 
-        nullifiers.mark_spent(&nullifier_hash)?;
+        NullifierMarker::spend(
+            nullifiers,
+            &ctx.accounts.nullifier_marker,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &nullifier_hash,
+        )?;
+        ctx.accounts.congestion.record_withdrawals(Clock::get()?.slot, 1);
 
         // SECURITY FIX: Verify vault has sufficient balance
         let vault_balance = ctx.accounts.pool_vault.lamports();
@@ -573,7 +1901,7 @@ pub mod whistle_pool {
 
         let withdrawal_net = amount.checked_sub(relayer_fee)
             .ok_or(WhistleError::ArithmeticOverflow)?;
-        let vault_bump = ctx.bumps.pool_vault;
+        let vault_bump = pool.vault_bump;
         let vault_seeds: &[&[u8]] = &[b"vault", &[vault_bump]];
         
         // Transfer to recipient
@@ -591,16 +1919,16 @@ pub mod whistle_pool {
             &[vault_seeds],
         )?;
         
-        if relayer_fee > 0 {
+        if let (true, Some(relayer)) = (relayer_fee > 0, &ctx.accounts.relayer) {
             anchor_lang::solana_program::program::invoke_signed(
                 &anchor_lang::solana_program::system_instruction::transfer(
                     ctx.accounts.pool_vault.key,
-                    ctx.accounts.relayer.key,
+                    relayer.key,
                     relayer_fee,
                 ),
                 &[
                     ctx.accounts.pool_vault.to_account_info(),
-                    ctx.accounts.relayer.to_account_info(),
+                    relayer.to_account_info(),
                     ctx.accounts.system_program.to_account_info(),
                 ],
                 &[vault_seeds],
@@ -612,11 +1940,17 @@ pub mod whistle_pool {
             .checked_sub(amount)
             .ok_or(WhistleError::ArithmeticOverflow)?;
 
+        let clock = Clock::get()?;
         emit!(Unshielded {
             nullifier_hash,
             withdrawal_amount: amount,
+            protocol_fee: 0,
             has_change: false,
-            timestamp: Clock::get()?.unix_timestamp,
+            recipient_is_pda: is_program_address(ctx.accounts.recipient.key),
+            self_relayed: false,
+            receipt_hash: receipt_hash(&nullifier_hash, ctx.accounts.recipient.key, withdrawal_net, clock.slot),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         Ok(())
@@ -644,6 +1978,7 @@ pub mod whistle_pool {
         relayer_fee: u64,
         merkle_root: [u8; 32],  // SECURITY FIX: Added Merkle root validation
     ) -> Result<()> {
+        require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
         // Validate denomination
         require!(
             amount == DENOM_001_SOL || amount == DENOM_005_SOL || amount == DENOM_01_SOL ||
@@ -651,32 +1986,33 @@ pub mod whistle_pool {
             WhistleError::InvalidWithdrawDenomination
         );
 
-        require!(relayer_fee <= amount / 10, WhistleError::FeeTooHigh);
+        require!(
+            relayer_fee <= ctx.accounts.denomination_config.max_relayer_fee(amount)?,
+            WhistleError::FeeTooHigh
+        );
+
+        require!(nullifier_hash != [0u8; 32], WhistleError::ZeroNullifierHash);
+        require_canonical_field_element(&commitment)?;
+        require_canonical_field_element(&nullifier_hash)?;
 
         let pool = &mut ctx.accounts.pool;
-        let nullifiers = &mut ctx.accounts.nullifiers.load_mut()?;
-        let roots = &ctx.accounts.roots_history.load()?;
+        let nullifiers = &ctx.accounts.nullifiers.load()?;
+        let roots = &RootsHistory::ring(&ctx.accounts.roots_history)?;
 
         // SECURITY FIX: Validate Merkle root exists in history
-        require!(
-            merkle_root == pool.current_root || roots.contains(&merkle_root),
-            WhistleError::InvalidMerkleRoot
-        );
+        require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+        require!(pool.next_index > 0, WhistleError::TreeEmpty);
+        roots.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
 
         // Check nullifier not already spent (prevents double-spend)
-        require!(
-            !nullifiers.is_spent(&nullifier_hash),
-            WhistleError::NullifierAlreadyUsed
-        );
+        NullifierMarker::require_unspent(nullifiers, &ctx.accounts.nullifier_marker, &nullifier_hash)?;
 
         // Verify vault has sufficient balance
         let vault_balance = ctx.accounts.pool_vault.lamports();
         require!(vault_balance >= amount, WhistleError::InsufficientVaultBalance);
 
         // Prepare recipient as field element (truncate to 31 bytes to fit BN254 field)
-        let recipient_bytes = recipient.to_bytes();
-        let mut recipient_field = [0u8; 32];
-        recipient_field[1..].copy_from_slice(&recipient_bytes[..31]);
+        let recipient_field = pubkey_to_field(&recipient.to_bytes());
         
         // Verify the Groth16 ZK proof
         let proof_valid = verify_withdraw_proof_groth16(
@@ -693,12 +2029,19 @@ pub mod whistle_pool {
         require!(proof_valid, WhistleError::InvalidProof);
 
         // Mark nullifier as spent
-        nullifiers.mark_spent(&nullifier_hash)?;
+        NullifierMarker::spend(
+            nullifiers,
+            &ctx.accounts.nullifier_marker,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &nullifier_hash,
+        )?;
+        ctx.accounts.congestion.record_withdrawals(Clock::get()?.slot, 1);
 
         // Transfer SOL
         let withdrawal_net = amount.checked_sub(relayer_fee)
             .ok_or(WhistleError::ArithmeticOverflow)?;
-        let vault_bump = ctx.bumps.pool_vault;
+        let vault_bump = pool.vault_bump;
         let vault_seeds: &[&[u8]] = &[b"vault", &[vault_bump]];
         
         anchor_lang::solana_program::program::invoke_signed(
@@ -737,17 +2080,540 @@ pub mod whistle_pool {
             .checked_sub(amount)
             .ok_or(WhistleError::ArithmeticOverflow)?;
 
+        let clock = Clock::get()?;
         emit!(WithdrawnZk {
             nullifier_hash,
             commitment,
             amount,
-            timestamp: Clock::get()?.unix_timestamp,
+            recipient_is_pda: is_program_address(&recipient),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         Ok(())
     }
 
-    // REMOVED: demo_withdraw function was a security vulnerability
+    /// Withdraw four notes to one recipient with a single Groth16 proof
+    /// 
+    /// The batch_withdraw circuit checks each note as withdraw_merkle does,
+    /// against one common root, so the four withdrawals cost one pairing
+    /// check instead of four. Each amount must be a withdrawal denomination.
+    /// The relayer fee is taken once from the total, capped at the sum of
+    /// the four denominations' caps, and the protocol fee is charged on the
+    /// total as in unshield. Only standard notes can be batched; the
+    /// circuit fixes every unlock slot at zero.
+    /// 
+    /// Requires the `batch-withdraw-zk` feature; fails with
+    /// VerifyingKeyNotGenerated otherwise.
+    pub fn batch_withdraw_zk(
+        ctx: Context<BatchWithdrawZk>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        nullifiers: [[u8; 32]; 4],
+        amounts: [u64; 4],
+        recipient: Pubkey,
+        relayer_fee: u64,
+        merkle_root: [u8; 32],
+    ) -> Result<()> {
+        batch_withdraw_zk::batch_withdraw_zk(ctx, proof_a, proof_b, proof_c, nullifiers, amounts, recipient, relayer_fee, merkle_root)
+    }
+
+    /// Withdraw up to MAX_BATCH_UNSHIELD notes to one recipient, each with
+    /// its own unshield proof
+    /// 
+    /// Each note is checked as in unshield, against one common root. Only
+    /// standard notes can be batched: every proof is checked with a zero
+    /// unlock slot. All checks and proofs run before the first nullifier is
+    /// spent, and any failure rejects the whole batch. The vault then pays
+    /// the recipient, the relayer and the fee vault once each. Each note is
+    /// announced by Unshielded and the batch by BatchUnshielded; only the
+    /// final root enters roots history.
+    /// 
+    /// The first note uses the unshield's nullifier and change markers. The
+    /// remaining accounts are the nullifier markers of proofs[1..], then
+    /// their change markers (the zero hash's without change), in order.
+    /// 
+    /// MAX_BATCH_UNSHIELD is the compute budget's limit. At 336 bytes of
+    /// arguments per note, a transaction only fits two notes, and then only
+    /// with the pool accounts in an address lookup table.
+    pub fn batch_unshield<'info>(
+        ctx: Context<'_, '_, '_, 'info, Unshield<'info>>,
+        proofs: Vec<UnshieldParams>,
+        merkle_root: [u8; 32],
+    ) -> Result<()> {
+        require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+        require!(
+            !proofs.is_empty() && proofs.len() <= MAX_BATCH_UNSHIELD,
+            WhistleError::InvalidBatchSize
+        );
+        require!(
+            ctx.remaining_accounts.len() == 2 * (proofs.len() - 1),
+            WhistleError::InvalidNullifierMarker
+        );
+        let (rest_nullifiers, rest_changes) = ctx.remaining_accounts.split_at(proofs.len() - 1);
+        let accounts = ctx.accounts;
+        let nullifier_markers: Vec<_> = std::iter::once(&accounts.nullifier_marker).chain(rest_nullifiers).collect();
+        let change_markers: Vec<_> = std::iter::once(&accounts.change_marker).chain(rest_changes).collect();
+
+        // max_relayer_fee also rejects amounts that are not denominations
+        let mut total_amount = 0u64;
+        let mut total_relayer_fee = 0u64;
+        for (i, note) in proofs.iter().enumerate() {
+            require!(
+                note.relayer_fee <= accounts.denomination_config.max_relayer_fee(note.withdrawal_amount)?,
+                WhistleError::FeeTooHigh
+            );
+            require!(note.nullifier_hash != [0u8; 32], WhistleError::ZeroNullifierHash);
+            require_canonical_field_element(&note.nullifier_hash)?;
+            require_canonical_field_element(&note.change_commitment)?;
+            require!(
+                !is_weak_change_commitment(&note.change_commitment, &note.nullifier_hash),
+                WhistleError::WeakChangeCommitment
+            );
+
+            // No marker exists yet to catch a note or change repeated
+            // within the batch
+            let earlier = &proofs[..i];
+            require!(
+                earlier.iter().all(|e| e.nullifier_hash != note.nullifier_hash),
+                WhistleError::NullifierAlreadyUsed
+            );
+            require!(
+                note.change_commitment == [0u8; 32]
+                    || earlier.iter().all(|e| e.change_commitment != note.change_commitment),
+                WhistleError::DuplicateCommitment
+            );
+
+            total_amount = total_amount.checked_add(note.withdrawal_amount)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+            total_relayer_fee = total_relayer_fee.checked_add(note.relayer_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+        }
+        check_relayer(accounts.relayer.as_ref(), total_relayer_fee, false)?;
+
+        let pool = &mut accounts.pool;
+        let nullifier_set = accounts.nullifiers.load()?;
+        for (note, marker) in proofs.iter().zip(&nullifier_markers) {
+            NullifierMarker::require_unspent(&nullifier_set, marker, &note.nullifier_hash)?;
+        }
+
+        require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+        require!(pool.next_index > 0, WhistleError::TreeEmpty);
+        RootsHistory::ring(&accounts.roots_history)?.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
+
+        let recipient = accounts.recipient.key();
+        let recipient_field = pubkey_to_field(&recipient.to_bytes());
+        for note in &proofs {
+            let proof_valid = verify_unshield_change_proof(
+                &note.proof_a,
+                &note.proof_b,
+                &note.proof_c,
+                &merkle_root,
+                &note.nullifier_hash,
+                &recipient_field,
+                note.withdrawal_amount,
+                note.relayer_fee,
+                &note.change_commitment,
+                0,
+            )?;
+            require!(proof_valid, WhistleError::InvalidProof);
+        }
+
+        // Every proof holds: spend the notes, then insert their change
+        let payer = accounts.payer.to_account_info();
+        let system_program = accounts.system_program.to_account_info();
+        for (note, marker) in proofs.iter().zip(&nullifier_markers) {
+            NullifierMarker::spend(&nullifier_set, marker, &payer, &system_program, &note.nullifier_hash)?;
+        }
+        drop(nullifier_set);
+        accounts.congestion.record_withdrawals(Clock::get()?.slot, proofs.len() as u32);
+
+        let clock = Clock::get()?;
+        let changes: Vec<_> = proofs.iter()
+            .zip(&change_markers)
+            .filter(|(note, _)| note.change_commitment != [0u8; 32])
+            .collect();
+        if !changes.is_empty() {
+            let end_index = pool.next_index.checked_add(changes.len() as u64)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+            require!(end_index <= 1u64 << pool.merkle_levels, WhistleError::TreeFull);
+
+            let first_change_index = pool.next_index;
+            let mut merkle_tree = accounts.merkle_tree.load_mut()?;
+            merkle_tree.check_root(pool)?;
+            for (note, marker) in changes {
+                let change_index = pool.next_index;
+                CommitmentMarker::record(marker, &payer, &system_program, &note.change_commitment, change_index)?;
+                merkle_tree.insert_leaf(note.change_commitment, change_index, pool.merkle_levels);
+                pool.next_index = change_index + 1;
+
+                emit!(ChangeCreated {
+                    commitment: note.change_commitment,
+                    leaf_index: change_index,
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            pool.current_root = merkle_tree.get_root(pool.merkle_levels);
+            drop(merkle_tree);
+            pool.warn_capacity(first_change_index)?;
+            RootsHistory::ring_mut(&accounts.roots_history)?.push(pool.current_root, clock.slot);
+        }
+
+        let vault_balance = accounts.pool_vault.lamports();
+        require!(vault_balance >= total_amount, WhistleError::InsufficientVaultBalance);
+
+        // Fees are charged per note, as each would pay alone: (protocol
+        // fee, amount the recipient receives) of every note
+        let mut payouts = Vec::with_capacity(proofs.len());
+        for note in &proofs {
+            let note_protocol_fee = note.withdrawal_amount.checked_mul(PROTOCOL_FEE_BPS)
+                .ok_or(WhistleError::ArithmeticOverflow)?
+                .checked_div(BPS_DENOMINATOR)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+            let note_net = note.withdrawal_amount
+                .checked_sub(note.relayer_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?
+                .checked_sub(note_protocol_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+            payouts.push((note_protocol_fee, note_net));
+        }
+        let protocol_fee: u64 = payouts.iter().map(|(fee, _)| fee).sum();
+        let withdrawal_net: u64 = payouts.iter().map(|(_, net)| net).sum();
+        let vault_bump = pool.vault_bump;
+        let vault_seeds: &[&[u8]] = &[b"vault", &[vault_bump]];
+
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                accounts.pool_vault.key,
+                accounts.recipient.key,
+                withdrawal_net,
+            ),
+            &[
+                accounts.pool_vault.to_account_info(),
+                accounts.recipient.to_account_info(),
+                accounts.system_program.to_account_info(),
+            ],
+            &[vault_seeds],
+        )?;
+
+        if let (true, Some(relayer)) = (total_relayer_fee > 0, &accounts.relayer) {
+            anchor_lang::solana_program::program::invoke_signed(
+                &anchor_lang::solana_program::system_instruction::transfer(
+                    accounts.pool_vault.key,
+                    relayer.key,
+                    total_relayer_fee,
+                ),
+                &[
+                    accounts.pool_vault.to_account_info(),
+                    relayer.to_account_info(),
+                    accounts.system_program.to_account_info(),
+                ],
+                &[vault_seeds],
+            )?;
+        }
+
+        if protocol_fee > 0 {
+            anchor_lang::solana_program::program::invoke_signed(
+                &anchor_lang::solana_program::system_instruction::transfer(
+                    accounts.pool_vault.key,
+                    accounts.fee_vault.key,
+                    protocol_fee,
+                ),
+                &[
+                    accounts.pool_vault.to_account_info(),
+                    accounts.fee_vault.to_account_info(),
+                    accounts.system_program.to_account_info(),
+                ],
+                &[vault_seeds],
+            )?;
+
+            pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+        }
+
+        // Change never leaves the vault, so only the withdrawals come off
+        pool.total_shielded = pool.total_shielded
+            .checked_sub(total_amount)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+
+        let recipient_is_pda = is_program_address(&recipient);
+        for (note, (note_protocol_fee, note_net)) in proofs.iter().zip(payouts) {
+            emit!(Unshielded {
+                nullifier_hash: note.nullifier_hash,
+                withdrawal_amount: note.withdrawal_amount,
+                protocol_fee: note_protocol_fee,
+                has_change: note.change_commitment != [0u8; 32],
+                recipient_is_pda,
+                self_relayed: false,
+                receipt_hash: receipt_hash(&note.nullifier_hash, &recipient, note_net, clock.slot),
+                slot: clock.slot,
+                timestamp: clock.unix_timestamp,
+            });
+        }
+        emit!(BatchUnshielded {
+            count: proofs.len() as u8,
+            total_withdrawn: total_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw a small note with a Schnorr signature instead of a Groth16 proof
+    /// 
+    /// For notes below 0.1 SOL, where a Groth16 verification costs about as
+    /// much as the note. The note commitment is
+    /// keccak(SCHNORR_NOTE_DOMAIN || spend_pubkey || amount) and is revealed,
+    /// so the withdrawal is linkable to its deposit: weaker privacy than the
+    /// ZK paths. Use a fresh spend key per note.
+    /// 
+    /// The Ed25519 (Schnorr) signature over
+    /// (SCHNORR_WITHDRAW_DOMAIN, nullifier_hash, recipient, amount) is checked
+    /// by the Ed25519 precompile in the instruction just before this one.
+    pub fn verify_schnorr_withdraw(
+        ctx: Context<SchnorrWithdraw>,
+        signature: [u8; 64],
+        commitment: [u8; 32],
+        nullifier_hash: [u8; 32],
+        recipient: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.allow_schnorr_for_small, WhistleError::SchnorrPathDisabled);
+        require!(
+            amount == DENOM_001_SOL || amount == DENOM_005_SOL,
+            WhistleError::InvalidWithdrawDenomination
+        );
+        require!(
+            nullifier_hash == schnorr_nullifier_hash(&commitment),
+            WhistleError::InvalidSchnorrNullifier
+        );
+        
+        let mut message = Vec::with_capacity(SCHNORR_WITHDRAW_DOMAIN.len() + 72);
+        message.extend_from_slice(SCHNORR_WITHDRAW_DOMAIN);
+        message.extend_from_slice(&nullifier_hash);
+        message.extend_from_slice(recipient.as_ref());
+        message.extend_from_slice(&amount.to_le_bytes());
+        
+        let spend_pubkey = verify_ed25519_instruction(
+            &ctx.accounts.instructions.to_account_info(),
+            &signature,
+            &message,
+        )?;
+        require!(
+            commitment == schnorr_note_commitment(&spend_pubkey, amount),
+            WhistleError::InvalidCommitment
+        );
+        
+        {
+            let merkle_tree = ctx.accounts.merkle_tree.load()?;
+            require!(
+                merkle_tree.contains_leaf(&commitment, pool.next_index, pool.merkle_levels),
+                WhistleError::CommitmentNotFound
+            );
+        }
+        
+        let nullifiers = ctx.accounts.nullifiers.load()?;
+        NullifierMarker::spend(
+            &nullifiers,
+            &ctx.accounts.nullifier_marker,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &nullifier_hash,
+        )?;
+        drop(nullifiers);
+        ctx.accounts.congestion.record_withdrawals(Clock::get()?.slot, 1);
+        
+        require!(
+            ctx.accounts.pool_vault.lamports() >= amount,
+            WhistleError::InsufficientVaultBalance
+        );
+        
+        let protocol_fee = amount.checked_mul(PROTOCOL_FEE_BPS)
+            .ok_or(WhistleError::ArithmeticOverflow)?
+            .checked_div(BPS_DENOMINATOR)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        let withdrawal_net = amount.checked_sub(protocol_fee)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        
+        let vault_seeds: &[&[u8]] = &[b"vault", &[pool.vault_bump]];
+        
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                ctx.accounts.pool_vault.key,
+                ctx.accounts.recipient.key,
+                withdrawal_net,
+            ),
+            &[
+                ctx.accounts.pool_vault.to_account_info(),
+                ctx.accounts.recipient.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            &[vault_seeds],
+        )?;
+        
+        if protocol_fee > 0 {
+            anchor_lang::solana_program::program::invoke_signed(
+                &anchor_lang::solana_program::system_instruction::transfer(
+                    ctx.accounts.pool_vault.key,
+                    ctx.accounts.fee_vault.key,
+                    protocol_fee,
+                ),
+                &[
+                    ctx.accounts.pool_vault.to_account_info(),
+                    ctx.accounts.fee_vault.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                &[vault_seeds],
+            )?;
+            
+            pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+        }
+        
+        pool.total_shielded = pool.total_shielded
+            .checked_sub(amount)
+            .ok_or(WhistleError::ArithmeticOverflow)?;
+        
+        let clock = Clock::get()?;
+        emit!(SchnorrWithdrawn {
+            nullifier_hash,
+            commitment,
+            amount,
+            protocol_fee,
+            recipient_is_pda: is_program_address(ctx.accounts.recipient.key),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Open a sealed-bid auction for an NFT (see auction.rs)
+    /// 
+    /// Escrows the NFT under the auction PDA. The reserve stays hidden
+    /// behind `reserve_commitment` until after `deadline_slot`.
+    pub fn open_auction(
+        ctx: Context<OpenAuction>,
+        nft_mint: Pubkey,
+        reserve_commitment: [u8; 32],
+        deadline_slot: u64,
+    ) -> Result<()> {
+        auction::open_auction(ctx, nft_mint, reserve_commitment, deadline_slot)
+    }
+
+    /// Submit a sealed bid, escrowing `max_bid` SOL
+    pub fn submit_bid(ctx: Context<SubmitBid>, bid_commitment: [u8; 32], max_bid: u64) -> Result<()> {
+        auction::submit_bid(ctx, bid_commitment, max_bid)
+    }
+
+    /// Seller opens the reserve commitment after the deadline
+    pub fn reveal_reserve(ctx: Context<RevealReserve>, reserve: u64, randomness: [u8; 32]) -> Result<()> {
+        auction::reveal_reserve(ctx, reserve, randomness)
+    }
+
+    /// Open a winning bid, or reclaim a losing one as a shielded note
+    pub fn settle_bid(
+        ctx: Context<SettleBid>,
+        bid_amount: u64,
+        randomness: [u8; 32],
+        is_winner: bool,
+        change_commitment: [u8; 32],
+        nft_recipient: Pubkey,
+    ) -> Result<()> {
+        auction::settle_bid(ctx, bid_amount, randomness, is_winner, change_commitment, nft_recipient)
+    }
+
+    /// Pay the seller and deliver the NFT once the settle window has closed
+    pub fn finalize_auction(ctx: Context<FinalizeAuction>) -> Result<()> {
+        auction::finalize_auction(ctx)
+    }
+
+    /// Shield `amount` of an SPL token under `commitment` (see token.rs)
+    /// 
+    /// The commitment is Poseidon(secret, nullifier, amount, mint), so the
+    /// note can only be spent for this mint. Creates the mint's vault on
+    /// first use, at the depositor's expense.
+    /// 
+    /// Requires the `spl-tokens` feature; fails with VerifyingKeyNotGenerated
+    /// otherwise, since a token note could not be withdrawn.
+    pub fn shield_token(ctx: Context<ShieldToken>, commitment: [u8; 32], amount: u64) -> Result<()> {
+        token::shield_token(ctx, commitment, amount)
+    }
+
+    /// Withdraw `amount` whole units of an SPL token note to `recipient`'s
+    /// token account, re-shielding the change as `change_commitment`
+    /// 
+    /// The unshield_token proof binds the mint, so a note shielded for
+    /// another mint fails with InvalidProof; a recipient token account of
+    /// another mint fails with MintMismatch.
+    /// 
+    /// Requires the `spl-tokens` feature; fails with VerifyingKeyNotGenerated
+    /// otherwise.
+    pub fn unshield_token(
+        ctx: Context<UnshieldToken>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        nullifier_hash: [u8; 32],
+        recipient: Pubkey,
+        amount: u64,
+        merkle_root: [u8; 32],
+        change_commitment: [u8; 32],
+    ) -> Result<()> {
+        token::unshield_token(
+            ctx,
+            proof_a,
+            proof_b,
+            proof_c,
+            nullifier_hash,
+            recipient,
+            amount,
+            merkle_root,
+            change_commitment,
+        )
+    }
+
+    /// Seed a devnet pool with `count` notes whose secrets are recomputable
+    /// from `seed`
+    /// 
+    /// Requires the `insecure-devnet` feature; fails with DevnetOnly otherwise.
+    /// See devnet.rs for the note derivation. The remaining accounts are the
+    /// commitment markers of notes 1.., in order.
+    pub fn devnet_seed_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, Shield<'info>>,
+        count: u8,
+        seed: u64,
+    ) -> Result<()> {
+        devnet::devnet_seed_pool(ctx, count, seed)
+    }
+
+    /// Check the pool's global invariants (view, via return data)
+    /// 
+    /// Returns a bitmap of violated invariants, 0 when all hold. Requires the
+    /// `test-harness` feature; fails with TestHarnessOnly otherwise. See
+    /// harness.rs for the invariants.
+    pub fn assert_invariants(ctx: Context<AssertInvariants>) -> Result<u32> {
+        harness::assert_invariants(ctx)
+    }
+
+    /// Overwrite raw bytes of a pool state account from an export
+    /// 
+    /// Restores what export_state_chunk returned, roots and counters
+    /// included. Requires the `test-harness` feature; fails with
+    /// TestHarnessOnly otherwise.
+    pub fn import_state_chunk(
+        ctx: Context<ImportStateChunk>,
+        account_kind: u8,
+        offset: u32,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        harness::import_state_chunk(ctx, account_kind, offset, bytes)
+    }
+
+    // REMOVED: demo_withdraw function was a security vulnerability
     // It allowed anyone to drain funds without proof verification
     // DO NOT RE-ADD THIS FUNCTION
 }
@@ -775,34 +2641,916 @@ pub mod whistle_pool {
 // See circuits/PRODUCTION_CIRCUITS.md for detailed specifications.
 // ============================================================================
 
-/// Verify unshield proof (with change support)
-/// 
-/// Uses dedicated unshield_change.circom circuit that verifies:
-/// - Input note exists in Merkle tree
-/// - Change commitment = Poseidon(changeSecret, Poseidon(changeNullifier, changeAmount))
-/// - Value conservation: inputAmount = withdrawalAmount + relayerFee + changeAmount
-fn verify_unshield_proof(
-    proof_a: &[u8; 64],
-    proof_b: &[u8; 128],
-    proof_c: &[u8; 64],
-    merkle_root: &[u8; 32],
-    nullifier_hash: &[u8; 32],
-    recipient: &[u8; 32],
-    withdrawal_amount: u64,
-    relayer_fee: u64,
-    change_commitment: &[u8; 32],
-) -> Result<bool> {
-    // PRODUCTION: Uses dedicated unshield_change circuit
-    verify_unshield_change_proof(
+/// Arguments of `unshield`, also the layout of a staged unshield payload
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UnshieldArgs {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub nullifier_hash: [u8; 32],
+    pub recipient: Pubkey,
+    pub withdrawal_amount: u64,
+    pub relayer_fee: u64,
+    pub merkle_root: [u8; 32],
+    pub change_commitment: [u8; 32],
+    pub unlock_slot: u64,
+}
+
+/// One note of a batch_unshield; the recipient and root are the batch's
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UnshieldParams {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub nullifier_hash: [u8; 32],
+    pub withdrawal_amount: u64,
+    pub relayer_fee: u64,
+    pub change_commitment: [u8; 32],
+}
+
+/// One party's private_transfer inside a denomination swap
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TransferLeg {
+    pub proof_a: [u8; 64],
+    pub proof_b: [u8; 128],
+    pub proof_c: [u8; 64],
+    pub input_nullifier_hashes: [[u8; 32]; 2],
+    pub output_commitments: [[u8; 32]; 2],
+    pub unlock_slots: [u64; 2],
+}
+
+/// A maker's offer to give `from_commitment` for `to_commitment`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapIntent {
+    pub from_commitment: [u8; 32],
+    pub from_denomination: u64,
+    pub to_denomination: u64,
+    pub to_commitment: [u8; 32],
+}
+
+// Borsh size of UnshieldArgs
+pub const UNSHIELD_ARGS_SIZE: usize = 64 + 128 + 64 + 32 + 32 + 8 + 8 + 32 + 32 + 8;
+const _: () = assert!(UNSHIELD_ARGS_SIZE <= PROOF_STAGING_CAPACITY);
+
+/// Every error the withdrawal flows can fail with, per instruction: the
+/// guards in the handler's body followed by those of the helpers it calls
+/// (check_relayer, require_canonical_field_element, require_unlocked,
+/// MerkleTree::check_root, RootsHistory::require_spendable_root,
+/// NullifierMarker, CommitmentMarker, validate_spl_denominations)
+///
+/// tests/guards.rs sends a minimally-invalid transaction for each entry and
+/// fails when a handler body names a WhistleError that is neither here nor
+/// in UNTESTABLE_WITHDRAWAL_GUARDS, so a new guard needs its negative test.
+pub const WITHDRAWAL_GUARDS: &[(&str, &[WhistleError])] = &[
+    ("withdraw", &[
+        WhistleError::InvalidWithdrawDenomination,
+        WhistleError::FeeTooHigh,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::InvalidProof,
+        WhistleError::InsufficientVaultBalance,
+        WhistleError::ArithmeticOverflow,
+        WhistleError::MissingRelayer,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::NoteStillLocked,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::UnsupportedCurve,
+    ]),
+    ("withdraw_zk", &[
+        WhistleError::InvalidWithdrawDenomination,
+        WhistleError::FeeTooHigh,
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::InsufficientVaultBalance,
+        WhistleError::InvalidProof,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::UnsupportedCurve,
+    ]),
+    ("unshield", &[
+        WhistleError::InvalidWithdrawDenomination,
+        WhistleError::FeeTooHigh,
+        WhistleError::WeakChangeCommitment,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::InvalidProof,
+        WhistleError::TreeFull,
+        WhistleError::InsufficientVaultBalance,
+        WhistleError::ArithmeticOverflow,
+        WhistleError::MissingRelayer,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::NoteStillLocked,
+        WhistleError::TreeStateDesync,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::PoolMigrationInProgress,
+        WhistleError::DuplicateCommitment,
+        WhistleError::InvalidCommitmentMarker,
+        WhistleError::UnsupportedCurve,
+    ]),
+    ("unshield_eip712", &[
+        WhistleError::InvalidEthSignature,
+        WhistleError::EthAddressNotAuthorized,
+        WhistleError::InvalidWithdrawDenomination,
+        WhistleError::FeeTooHigh,
+        WhistleError::WeakChangeCommitment,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::InvalidProof,
+        WhistleError::TreeFull,
+        WhistleError::InsufficientVaultBalance,
+        WhistleError::ArithmeticOverflow,
+        WhistleError::MissingRelayer,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::NoteStillLocked,
+        WhistleError::TreeStateDesync,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::PoolMigrationInProgress,
+        WhistleError::DuplicateCommitment,
+        WhistleError::InvalidCommitmentMarker,
+        WhistleError::UnsupportedCurve,
+    ]),
+    ("unshield_token", &[
+        WhistleError::MintMismatch,
+        WhistleError::InvalidTokenAccount,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::WeakChangeCommitment,
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidProof,
+        WhistleError::TreeFull,
+        WhistleError::InsufficientVaultBalance,
+        WhistleError::InvalidSplDenomination,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::TreeStateDesync,
+        WhistleError::PoolMigrationInProgress,
+        WhistleError::DuplicateCommitment,
+        WhistleError::InvalidCommitmentMarker,
+        WhistleError::UnsupportedCurve,
+    ]),
+    ("batch_unshield", &[
+        WhistleError::InvalidBatchSize,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::FeeTooHigh,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::WeakChangeCommitment,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::DuplicateCommitment,
+        WhistleError::ArithmeticOverflow,
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidProof,
+        WhistleError::TreeFull,
+        WhistleError::InsufficientVaultBalance,
+        WhistleError::InvalidWithdrawDenomination,
+        WhistleError::MissingRelayer,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::TreeStateDesync,
+        WhistleError::PoolMigrationInProgress,
+        WhistleError::InvalidCommitmentMarker,
+        WhistleError::UnsupportedCurve,
+    ]),
+    ("private_transfer", &[
+        WhistleError::ZeroMerkleRoot,
+        WhistleError::TreeEmpty,
+        WhistleError::InvalidMerkleRoot,
+        WhistleError::StaleMerkleRoot,
+        WhistleError::NullifierAlreadyUsed,
+        WhistleError::InvalidNullifierMarker,
+        WhistleError::InvalidProof,
+        WhistleError::TreeFull,
+        WhistleError::NonCanonicalFieldElement,
+        WhistleError::NoteStillLocked,
+        WhistleError::TreeStateDesync,
+        WhistleError::ZeroNullifierHash,
+        WhistleError::PoolMigrationInProgress,
+        WhistleError::DuplicateCommitment,
+        WhistleError::InvalidCommitmentMarker,
+        WhistleError::UnsupportedCurve,
+    ]),
+];
+
+/// Guards of the withdrawal flows no test transaction can reach
+///
+/// withdraw_zk's only guard past its proof check is the overflow check,
+/// and withdraw_zk has no test proof backend. private_transfer and
+/// unshield_token overflow only past u64::MAX leaves.
+pub const UNTESTABLE_WITHDRAWAL_GUARDS: &[(&str, WhistleError)] = &[
+    ("withdraw_zk", WhistleError::ArithmeticOverflow),
+    ("private_transfer", WhistleError::ArithmeticOverflow),
+    ("unshield_token", WhistleError::ArithmeticOverflow),
+];
+
+/// Shared body of `private_transfer` and both legs of `execute_denomination_swap`;
+/// `markers` are the markers of the leg's input nullifiers, `output_markers`
+/// those of its output commitments
+fn process_private_transfer<'info>(
+    accounts: &mut PrivateTransfer<'info>,
+    markers: [AccountInfo<'info>; 2],
+    output_markers: [AccountInfo<'info>; 2],
+    leg: TransferLeg,
+    merkle_root: [u8; 32],
+) -> Result<()> {
+    profile_begin!(profile);
+    require!(accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    let TransferLeg {
         proof_a,
         proof_b,
         proof_c,
-        merkle_root,
-        nullifier_hash,
+        input_nullifier_hashes,
+        output_commitments,
+        unlock_slots,
+    } = leg;
+    // A zero input or output is an unused slot, but a transfer must spend
+    // something
+    require!(
+        input_nullifier_hashes.iter().any(|hash| *hash != [0u8; 32]),
+        WhistleError::ZeroNullifierHash
+    );
+    for value in input_nullifier_hashes.iter().chain(&output_commitments) {
+        require_canonical_field_element(value)?;
+    }
+    for unlock_slot in unlock_slots {
+        require_unlocked(unlock_slot)?;
+    }
+
+    let pool = &mut accounts.pool;
+    let nullifiers = accounts.nullifiers.load()?;
+
+    // The zero root never belongs to a real tree (empty roots history slots are zero)
+    require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+    require!(pool.next_index > 0, WhistleError::TreeEmpty);
+
+    // Check root validity (use separate scope to release borrow)
+    RootsHistory::ring(&accounts.roots_history)?.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
+
+    // Check nullifiers not spent
+    for (nullifier_hash, marker) in input_nullifier_hashes.iter().zip(&markers) {
+        if *nullifier_hash != [0u8; 32] {
+            NullifierMarker::require_unspent(&nullifiers, marker, nullifier_hash)?;
+        }
+    }
+
+    // Verify the transfer proof
+    let proof_valid = profile_section!(profile, PROOF_VERIFY, {
+        verify_transfer_proof(
+            &proof_a,
+            &proof_b,
+            &proof_c,
+            &input_nullifier_hashes,
+            &output_commitments,
+            &merkle_root,
+            &unlock_slots,
+        )
+    })?;
+
+    require!(proof_valid, WhistleError::InvalidProof);
+
+    // Create the markers; a note filling both input slots finds its
+    // marker already created by the first
+    let payer = accounts.payer.to_account_info();
+    let system_program = accounts.system_program.to_account_info();
+    for (nullifier_hash, marker) in input_nullifier_hashes.iter().zip(&markers) {
+        if *nullifier_hash != [0u8; 32] {
+            NullifierMarker::spend(&nullifiers, marker, &payer, &system_program, nullifier_hash)?;
+        }
+    }
+
+    // Drop nullifiers borrow
+    drop(nullifiers);
+
+    // Add new commitments to tree
+    profile_section!(profile, TREE_INSERT, {
+        let mut merkle_tree = accounts.merkle_tree.load_mut()?;
+        merkle_tree.check_root(pool)?;
+        for (commitment, marker) in output_commitments.iter().zip(&output_markers) {
+            if *commitment != [0u8; 32] {
+                let max_leaves = 1u64 << pool.merkle_levels;
+                require!(pool.next_index < max_leaves, WhistleError::TreeFull);
+
+                let leaf_index = pool.next_index;
+                CommitmentMarker::record(marker, &payer, &system_program, commitment, leaf_index)?;
+                merkle_tree.insert_leaf(*commitment, leaf_index, pool.merkle_levels);
+                pool.next_index = pool.next_index.checked_add(1)
+                    .ok_or(WhistleError::ArithmeticOverflow)?;
+                pool.warn_capacity(leaf_index)?;
+
+                let clock = Clock::get()?;
+                emit!(NoteCreated {
+                    commitment: *commitment,
+                    leaf_index,
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+        }
+
+        pool.current_root = merkle_tree.get_root(pool.merkle_levels);
+
+        // Drop merkle_tree borrow before accessing roots_history
+        drop(merkle_tree);
+
+        // Update roots history
+        let mut roots = RootsHistory::ring_mut(&accounts.roots_history)?;
+        roots.push(pool.current_root, Clock::get()?.slot);
+    });
+
+    let clock = Clock::get()?;
+    emit!(PrivateTransferCompleted {
+        nullifiers_spent: 2,
+        notes_created: 2,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+        nullifier_hashes: input_nullifier_hashes,
+    });
+
+    profile_emit!(profile);
+    Ok(())
+}
+
+/// Shared body of `shield` and `reveal_shield`
+fn process_shield(accounts: &mut Shield, commitment: [u8; 32], amount: u64) -> Result<()> {
+    profile_begin!(profile);
+    require!(accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    require!(amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+    
+    let pool = &mut accounts.pool;
+    let (protocol_fee, net_amount) = split_protocol_fee(amount)?;
+    
+    record_deposit(pool, &mut accounts.deposit_record, accounts.depositor.key(), net_amount)?;
+    
+    profile_section!(profile, TRANSFERS, {
+        // Transfer net amount to main vault
+        let cpi_context = CpiContext::new(
+            accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: accounts.depositor.to_account_info(),
+                to: accounts.pool_vault.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, net_amount)?;
+        
+        // Transfer protocol fee to fee vault
+        if protocol_fee > 0 {
+            let fee_cpi = CpiContext::new(
+                accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: accounts.depositor.to_account_info(),
+                    to: accounts.fee_vault.to_account_info(),
+                },
+            );
+            system_program::transfer(fee_cpi, protocol_fee)?;
+        }
+    });
+    
+    let leaf_index = profile_section!(profile, TREE_INSERT, {
+        book_shield(
+            pool,
+            LeafAccounts {
+                merkle_tree: &accounts.merkle_tree,
+                roots_history: &accounts.roots_history,
+                leaf_page: Some(&accounts.leaf_page),
+                commitment_marker: &accounts.commitment_marker,
+                payer: accounts.depositor.to_account_info(),
+                system_program: accounts.system_program.to_account_info(),
+            },
+            &accounts.pool_stats,
+            &mut accounts.deposit_histogram,
+            commitment,
+            amount,
+            protocol_fee,
+        )?
+    });
+    
+    let clock = Clock::get()?;
+    emit!(Shielded {
+        commitment,
+        leaf_index,
+        amount: net_amount,
+        protocol_fee,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    profile_emit!(profile);
+    Ok(())
+}
+
+/// Body of `shield_forwarded`
+/// 
+/// Mirrors process_shield without the deposit record. Audit note: the only
+/// account read here is `forwarder`, as the source of the transfers and the
+/// payer of the commitment marker; its key reaches neither pool state nor
+/// the event.
+fn process_shield_forwarded(accounts: &mut ShieldForwarded, commitment: [u8; 32], amount: u64) -> Result<()> {
+    require!(accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    require!(amount >= MIN_DEPOSIT, WhistleError::AmountTooSmall);
+    
+    let pool = &mut accounts.pool;
+    require!(pool.max_total_deposits_per_address == 0, WhistleError::ForwardedShieldWithAddressCap);
+    
+    let (protocol_fee, net_amount) = split_protocol_fee(amount)?;
+    require_pool_capacity(pool, net_amount)?;
+    
+    system_program::transfer(
+        CpiContext::new(
+            accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: accounts.forwarder.to_account_info(),
+                to: accounts.pool_vault.to_account_info(),
+            },
+        ),
+        net_amount,
+    )?;
+    if protocol_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: accounts.forwarder.to_account_info(),
+                    to: accounts.fee_vault.to_account_info(),
+                },
+            ),
+            protocol_fee,
+        )?;
+    }
+    
+    let leaf_index = book_shield(
+        pool,
+        LeafAccounts {
+            merkle_tree: &accounts.merkle_tree,
+            roots_history: &accounts.roots_history,
+            leaf_page: Some(&accounts.leaf_page),
+            commitment_marker: &accounts.commitment_marker,
+            payer: accounts.forwarder.to_account_info(),
+            system_program: accounts.system_program.to_account_info(),
+        },
+        &accounts.pool_stats,
+        &mut accounts.deposit_histogram,
+        commitment,
+        amount,
+        protocol_fee,
+    )?;
+    
+    let clock = Clock::get()?;
+    emit!(Shielded {
+        commitment,
+        leaf_index,
+        amount: net_amount,
+        protocol_fee,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Split a shield of `amount` into its protocol fee (0.04%) and the net
+/// amount shielded
+pub(crate) fn split_protocol_fee(amount: u64) -> Result<(u64, u64)> {
+    let protocol_fee = amount.checked_mul(PROTOCOL_FEE_BPS)
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    let net_amount = amount.checked_sub(protocol_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    Ok((protocol_fee, net_amount))
+}
+
+/// Insert a shield's note once its `amount` is in the vaults,
+/// `protocol_fee` of it in the fee vault, and count it in the pool totals,
+/// pool statistics and deposit histogram; returns the note's leaf index
+pub(crate) fn book_shield<'info>(
+    pool: &mut PoolState,
+    leaf: LeafAccounts<'_, 'info>,
+    pool_stats: &AccountLoader<'info, PoolStats>,
+    deposit_histogram: &mut DepositHistogram,
+    commitment: [u8; 32],
+    amount: u64,
+    protocol_fee: u64,
+) -> Result<u64> {
+    let net_amount = amount.checked_sub(protocol_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    let leaf_index = insert_commitment(pool, leaf, commitment)?;
+    
+    pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    pool.total_deposits = pool.total_deposits.checked_add(net_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    pool.total_shielded = pool.total_shielded.checked_add(net_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    
+    pool_stats.load_mut()?.record_shield(Clock::get()?.slot, net_amount);
+    deposit_histogram.record(amount);
+    Ok(leaf_index)
+}
+
+/// Accounts a new leaf is written to
+pub(crate) struct LeafAccounts<'a, 'info> {
+    pub merkle_tree: &'a AccountLoader<'info, MerkleTree>,
+    pub roots_history: &'a AccountLoader<'info, RootsHistory>,
+    /// Page of the pool's next leaf when the instruction takes it; leaves
+    /// inserted without one reach their page through sync_leaf_page
+    pub leaf_page: Option<&'a AccountLoader<'info, MerkleTreeLeafPage>>,
+    pub commitment_marker: &'a AccountInfo<'info>,
+    /// Pays the commitment marker's rent
+    pub payer: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
+
+/// Insert `commitment` as the pool's next leaf, returning its index
+///
+/// Every shield, change note and refund note goes through here: the
+/// commitment must be a nonzero canonical field element, its marker is
+/// created (so it cannot be inserted twice), the leaf is appended to its
+/// page and the new root is pushed to the roots history.
+pub(crate) fn insert_commitment(pool: &mut PoolState, accounts: LeafAccounts, commitment: [u8; 32]) -> Result<u64> {
+    require!(commitment != [0u8; 32], WhistleError::ZeroCommitment);
+    require_canonical_field_element(&commitment)?;
+    require!(pool.next_index < 1u64 << pool.merkle_levels, WhistleError::TreeFull);
+    
+    let leaf_index = pool.next_index;
+    let mut merkle_tree = accounts.merkle_tree.load_mut()?;
+    merkle_tree.check_root(pool)?;
+    CommitmentMarker::record(
+        accounts.commitment_marker,
+        &accounts.payer,
+        &accounts.system_program,
+        &commitment,
+        leaf_index,
+    )?;
+    merkle_tree.insert_leaf(commitment, leaf_index, pool.merkle_levels);
+    if let Some(leaf_page) = accounts.leaf_page {
+        MerkleTreeLeafPage::load_or_init(leaf_page, MerkleTreeLeafPage::page_of(leaf_index))?
+            .append(leaf_index, commitment);
+    }
+    pool.current_root = merkle_tree.get_root(pool.merkle_levels);
+    drop(merkle_tree);
+    
+    pool.next_index = leaf_index.checked_add(1)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    pool.warn_capacity(leaf_index)?;
+    
+    let roots = &mut RootsHistory::ring_mut(accounts.roots_history)?;
+    roots.push(pool.current_root, Clock::get()?.slot);
+    Ok(leaf_index)
+}
+
+/// Create the commitment markers of a batch shield's leaves, from
+/// `first_leaf_index` on: `first` is the first commitment's marker and
+/// `rest` the others', in order
+fn record_batch_commitments<'info>(
+    first: &AccountInfo<'info>,
+    rest: &[AccountInfo<'info>],
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    commitments: &[[u8; 32]],
+    first_leaf_index: u64,
+) -> Result<()> {
+    require!(rest.len() + 1 == commitments.len(), WhistleError::InvalidCommitmentMarker);
+    let markers = std::iter::once(first).chain(rest);
+    for ((commitment, marker), leaf_index) in commitments.iter().zip(markers).zip(first_leaf_index..) {
+        CommitmentMarker::record(marker, payer, system_program, commitment, leaf_index)?;
+    }
+    Ok(())
+}
+
+/// Insert a batch shield's commitments as the pool's next leaves and emit
+/// BatchShielded for `total_amount`
+/// 
+/// The root after each leaf enters roots history, so a proof made against
+/// any intermediate root stays valid. The caller creates the commitment
+/// markers.
+pub(crate) fn insert_batch(
+    pool: &mut PoolState,
+    merkle_tree: &AccountLoader<MerkleTree>,
+    roots_history: &AccountLoader<RootsHistory>,
+    leaf_page: &AccountLoader<MerkleTreeLeafPage>,
+    commitments: &[[u8; 32]],
+    total_amount: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let first_leaf_index = pool.next_index;
+    let mut merkle_tree = merkle_tree.load_mut()?;
+    merkle_tree.check_root(pool)?;
+    let mut leaf_page = MerkleTreeLeafPage::load_or_init(leaf_page, MerkleTreeLeafPage::page_of(first_leaf_index))?;
+    let mut roots = RootsHistory::ring_mut(roots_history)?;
+    for commitment in commitments {
+        let leaf_index = pool.next_index;
+        merkle_tree.insert_leaf(*commitment, leaf_index, pool.merkle_levels);
+        leaf_page.append(leaf_index, *commitment);
+        pool.next_index = leaf_index + 1;
+        pool.current_root = merkle_tree.get_root(pool.merkle_levels);
+        roots.push(pool.current_root, clock.slot);
+    }
+    pool.warn_capacity(first_leaf_index)?;
+    
+    emit!(BatchShielded {
+        count: commitments.len() as u8,
+        total_amount,
+        first_leaf_index,
+        last_leaf_index: pool.next_index - 1,
+        timestamp: clock.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Count a shield of `net_amount` against the per-address and pool caps
+/// 
+/// Records only grow: withdrawals cannot be linked back to a depositor, so
+/// the per-address cap bounds lifetime deposits, not the current balance.
+fn record_deposit(pool: &PoolState, record: &mut DepositRecord, depositor: Pubkey, net_amount: u64) -> Result<()> {
+    record.depositor = depositor;
+    record.total_deposited = record.total_deposited.checked_add(net_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    require!(
+        pool.max_total_deposits_per_address == 0
+            || record.total_deposited <= pool.max_total_deposits_per_address,
+        WhistleError::DepositCapExceeded
+    );
+    
+    require_pool_capacity(pool, net_amount)
+}
+
+/// Fail with PoolShieldedCapExceeded unless `net_amount` more fits under the
+/// pool's total shielded cap
+fn require_pool_capacity(pool: &PoolState, net_amount: u64) -> Result<()> {
+    let shielded = pool.total_shielded.checked_add(net_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    require!(
+        pool.max_total_pool_shielded == 0 || shielded <= pool.max_total_pool_shielded,
+        WhistleError::PoolShieldedCapExceeded
+    );
+    Ok(())
+}
+
+/// Shared body of `unshield`, `self_unshield` and `unshield_from_staged`
+fn process_unshield(accounts: &mut Unshield, args: UnshieldArgs, self_relayed: bool) -> Result<()> {
+    profile_begin!(profile);
+    require!(accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    let UnshieldArgs {
+        proof_a,
+        proof_b,
+        proof_c,
+        nullifier_hash,
+        recipient,
+        withdrawal_amount,
+        relayer_fee,
+        merkle_root,
+        change_commitment,
+        unlock_slot,
+    } = args;
+
+    // Withdrawal must be fixed denomination
+    require!(
+        withdrawal_amount == DENOM_001_SOL ||
+        withdrawal_amount == DENOM_005_SOL ||
+        withdrawal_amount == DENOM_01_SOL ||
+        withdrawal_amount == DENOM_1_SOL ||
+        withdrawal_amount == DENOM_10_SOL ||
+        withdrawal_amount == DENOM_100_SOL,
+        WhistleError::InvalidWithdrawDenomination
+    );
+
+    require!(
+        relayer_fee <= accounts.denomination_config.max_relayer_fee(withdrawal_amount)?,
+        WhistleError::FeeTooHigh
+    );
+    check_relayer(accounts.relayer.as_ref(), relayer_fee, self_relayed)?;
+
+    require!(nullifier_hash != [0u8; 32], WhistleError::ZeroNullifierHash);
+    require_canonical_field_element(&nullifier_hash)?;
+    require_canonical_field_element(&change_commitment)?;
+    require!(
+        !is_weak_change_commitment(&change_commitment, &nullifier_hash),
+        WhistleError::WeakChangeCommitment
+    );
+    require_unlocked(unlock_slot)?;
+
+    let pool = &mut accounts.pool;
+    let nullifiers = accounts.nullifiers.load()?;
+
+    // Check nullifier not spent
+    NullifierMarker::require_unspent(&nullifiers, &accounts.nullifier_marker, &nullifier_hash)?;
+
+    // The zero root never belongs to a real tree (empty roots history slots are zero)
+    require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+    require!(pool.next_index > 0, WhistleError::TreeEmpty);
+
+    // Check root is valid (current or in history)
+    // Use a separate scope to drop the immutable borrow before potential mutable borrow
+    RootsHistory::ring(&accounts.roots_history)?.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
+
+    // Prepare recipient as field element (truncate to 31 bytes to fit BN254 field)
+    let recipient_field = pubkey_to_field(&recipient.to_bytes());
+
+    // Verify Groth16 ZK proof
+    let proof_valid = profile_section!(profile, PROOF_VERIFY, {
+        verify_unshield_proof(
+            &proof_a,
+            &proof_b,
+            &proof_c,
+            &merkle_root,
+            &nullifier_hash,
+            &recipient_field,
+            withdrawal_amount,
+            relayer_fee,
+            &change_commitment,
+            unlock_slot,
+        )
+    })?;
+
+    require!(proof_valid, WhistleError::InvalidProof);
+
+    // Create the nullifier's marker (prevents double-spend)
+    NullifierMarker::spend(
+        &nullifiers,
+        &accounts.nullifier_marker,
+        &accounts.payer.to_account_info(),
+        &accounts.system_program.to_account_info(),
+        &nullifier_hash,
+    )?;
+    accounts.congestion.record_withdrawals(Clock::get()?.slot, 1);
+    
+    // Drop nullifiers borrow before accessing other accounts
+    drop(nullifiers);
+
+    // If there's change, add it to the tree as a new note
+    let has_change = change_commitment != [0u8; 32];
+    if has_change {
+        let change_index = profile_section!(profile, TREE_INSERT, {
+            insert_commitment(
+                pool,
+                LeafAccounts {
+                    merkle_tree: &accounts.merkle_tree,
+                    roots_history: &accounts.roots_history,
+                    leaf_page: None,
+                    commitment_marker: &accounts.change_marker,
+                    payer: accounts.payer.to_account_info(),
+                    system_program: accounts.system_program.to_account_info(),
+                },
+                change_commitment,
+            )?
+        });
+        
+        let clock = Clock::get()?;
+        emit!(ChangeCreated {
+            commitment: change_commitment,
+            leaf_index: change_index,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+
+    // Verify vault has sufficient balance
+    let vault_balance = accounts.pool_vault.lamports();
+    require!(vault_balance >= withdrawal_amount, WhistleError::InsufficientVaultBalance);
+
+    // Calculate protocol fee (0.04%)
+    let protocol_fee = withdrawal_amount.checked_mul(PROTOCOL_FEE_BPS)
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    
+    // Transfer SOL from vault to recipient (minus relayer fee and protocol fee)
+    let withdrawal_net = withdrawal_amount
+        .checked_sub(relayer_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?
+        .checked_sub(protocol_fee)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+    profile_section!(profile, TRANSFERS, {
+        let vault_seeds: &[&[u8]] = &[b"vault", &[pool.vault_bump]];
+        
+        // Transfer to recipient
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                accounts.pool_vault.key,
+                accounts.recipient.key,
+                withdrawal_net,
+            ),
+            &[
+                accounts.pool_vault.to_account_info(),
+                accounts.recipient.to_account_info(),
+                accounts.system_program.to_account_info(),
+            ],
+            &[vault_seeds],
+        )?;
+        
+        // Pay relayer fee if any (check_relayer guarantees the account)
+        if let (true, Some(relayer)) = (relayer_fee > 0, &accounts.relayer) {
+            anchor_lang::solana_program::program::invoke_signed(
+                &anchor_lang::solana_program::system_instruction::transfer(
+                    accounts.pool_vault.key,
+                    relayer.key,
+                    relayer_fee,
+                ),
+                &[
+                    accounts.pool_vault.to_account_info(),
+                    relayer.to_account_info(),
+                    accounts.system_program.to_account_info(),
+                ],
+                &[vault_seeds],
+            )?;
+        }
+        
+        // Transfer protocol fee to fee vault
+        if protocol_fee > 0 {
+            anchor_lang::solana_program::program::invoke_signed(
+                &anchor_lang::solana_program::system_instruction::transfer(
+                    accounts.pool_vault.key,
+                    accounts.fee_vault.key,
+                    protocol_fee,
+                ),
+                &[
+                    accounts.pool_vault.to_account_info(),
+                    accounts.fee_vault.to_account_info(),
+                    accounts.system_program.to_account_info(),
+                ],
+                &[vault_seeds],
+            )?;
+            
+            pool.total_fees_collected = pool.total_fees_collected.checked_add(protocol_fee)
+                .ok_or(WhistleError::ArithmeticOverflow)?;
+        }
+    });
+
+    // The vault paid out exactly withdrawal_amount (recipient, relayer fee
+    // and protocol fee together). The change note's value never left the
+    // vault, so it stays in total_shielded and only withdrawal_amount comes off.
+    // SECURITY FIX: Use checked_sub to prevent underflow
+    pool.total_shielded = pool.total_shielded
+        .checked_sub(withdrawal_amount)
+        .ok_or(WhistleError::ArithmeticOverflow)?;
+
+    let clock = Clock::get()?;
+    emit!(Unshielded {
+        nullifier_hash,
+        withdrawal_amount,
+        protocol_fee,
+        has_change,
+        recipient_is_pda: is_program_address(accounts.recipient.key),
+        self_relayed,
+        receipt_hash: receipt_hash(&nullifier_hash, accounts.recipient.key, withdrawal_net, clock.slot),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    profile_emit!(profile);
+    Ok(())
+}
+
+/// Verify unshield proof (with change support)
+/// 
+/// Uses dedicated unshield_change.circom circuit that verifies:
+/// - Input note exists in Merkle tree
+/// - Change commitment = Poseidon(changeSecret, Poseidon(changeNullifier, changeAmount))
+/// - Value conservation: inputAmount = withdrawalAmount + relayerFee + changeAmount
+/// - unlock_slot is the one the input note was committed with
+fn verify_unshield_proof(
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    merkle_root: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+    recipient: &[u8; 32],
+    withdrawal_amount: u64,
+    relayer_fee: u64,
+    change_commitment: &[u8; 32],
+    unlock_slot: u64,
+) -> Result<bool> {
+    // PRODUCTION: Uses dedicated unshield_change circuit
+    verify_unshield_change_proof(
+        proof_a,
+        proof_b,
+        proof_c,
+        merkle_root,
+        nullifier_hash,
         recipient,
         withdrawal_amount,
         relayer_fee,
         change_commitment,
+        unlock_slot,
     )
 }
 
@@ -812,6 +3560,9 @@ fn verify_unshield_proof(
 /// - Input note exists in Merkle tree (Merkle proof)
 /// - Nullifier hash is correctly computed
 /// - Recipient is bound to proof (prevents front-running)
+/// - unlock_slot is the one the note was committed with
+/// 
+/// The circuit's VK is selected by denomination (see select_vk_for_denomination).
 fn verify_withdraw_proof(
     proof_a: &[u8; 64],
     proof_b: &[u8; 128],
@@ -821,6 +3572,7 @@ fn verify_withdraw_proof(
     recipient: &[u8; 32],
     amount: u64,
     relayer_fee: u64,
+    unlock_slot: u64,
 ) -> Result<bool> {
     // PRODUCTION: Uses dedicated withdraw_merkle circuit
     verify_withdraw_merkle_proof(
@@ -832,6 +3584,7 @@ fn verify_withdraw_proof(
         recipient,
         amount,
         relayer_fee,
+        unlock_slot,
     )
 }
 
@@ -842,6 +3595,7 @@ fn verify_withdraw_proof(
 /// - Both output commitments are correctly computed  
 /// - Value conservation: sum(input amounts) == sum(output amounts)
 /// - All amounts are in valid range (no overflow attacks)
+/// - Each unlock slot is the one its input note was committed with
 fn verify_transfer_proof(
     proof_a: &[u8; 64],
     proof_b: &[u8; 128],
@@ -849,6 +3603,7 @@ fn verify_transfer_proof(
     input_nullifiers: &[[u8; 32]; 2],
     output_commitments: &[[u8; 32]; 2],
     merkle_root: &[u8; 32],
+    unlock_slots: &[u64; 2],
 ) -> Result<bool> {
     // PRODUCTION: Uses dedicated private_transfer circuit
     verify_private_transfer_proof(
@@ -858,39 +3613,248 @@ fn verify_transfer_proof(
         merkle_root,
         input_nullifiers,
         output_commitments,
+        unlock_slots,
     )
 }
 
-// SECURITY FIX: Removed incomplete groth16_verify function
-// All verification now uses verify_withdraw_proof_groth16 from groth16.rs
-// which properly implements full Groth16 verification via groth16_solana library
+/// Reject spending a time-locked note before its unlock slot
+///
+/// The slot is a public input of every spend proof, bound to the note's
+/// commitment, so a spender cannot understate it. Standard notes use zero.
+fn require_unlocked(unlock_slot: u64) -> Result<()> {
+    require!(Clock::get()?.slot >= unlock_slot, WhistleError::NoteStillLocked);
+    Ok(())
+}
 
-// SECURITY FIX: Removed unused EC helper functions (scalar_mul_g1, point_add_g1, negate_g1)
-// All EC operations are now handled by the groth16_solana library in groth16.rs
+/// Change notes derived straight from the spent note's nullifier hash
+/// 
+/// Catches the two degenerate wallet constructions, `nullifier_hash` itself
+/// and `Poseidon(nullifier_hash, nullifier_hash)`; anything subtler is only
+/// visible off-chain. A zero commitment means no change and is never weak.
+pub fn is_weak_change_commitment(change_commitment: &[u8; 32], nullifier_hash: &[u8; 32]) -> bool {
+    *change_commitment != [0u8; 32]
+        && (change_commitment == nullifier_hash
+            || *change_commitment == merkle_hash(nullifier_hash, nullifier_hash))
+}
 
-// ============================================================================
-// VERIFICATION KEYS
-// ============================================================================
+/// Relayer account rules for a withdrawal paying `relayer_fee`
+/// 
+/// "No relayer" and "relayer fee zero" are distinct: a relayed withdrawal
+/// may waive its fee, but a self-relayed one takes neither a fee nor a
+/// relayer account, and a fee always needs an account to pay.
+pub(crate) fn check_relayer(relayer: Option<&AccountInfo>, relayer_fee: u64, self_relayed: bool) -> Result<()> {
+    if self_relayed {
+        require!(relayer_fee == 0 && relayer.is_none(), WhistleError::SelfRelayedWithRelayer);
+    }
+    require!(relayer_fee == 0 || relayer.is_some(), WhistleError::MissingRelayer);
+    Ok(())
+}
 
-// SECURITY FIX: Removed Groth16VK struct and get_*_vk functions
-// All verification now uses the proper groth16_solana library via groth16.rs
-// which handles verification keys internally
+/// Whether `key` is off the ed25519 curve, i.e. a program derived address
+/// 
+/// Withdrawals to PDAs (a DeFi vault, a multisig) need no special transfer:
+/// the system program credits any writable account, signer or not. The
+/// vault is system-owned, so the pool could not debit it directly anyway.
+/// The flag is only reported in withdrawal events.
+pub fn is_program_address(key: &Pubkey) -> bool {
+    #[cfg(target_os = "solana")]
+    {
+        const CURVE25519_EDWARDS: u64 = 0;
+        let mut unused = 0u8;
+        // SAFETY: the syscall reads 32 bytes from the key and writes nothing
+        let status = unsafe {
+            anchor_lang::solana_program::syscalls::sol_curve_validate_point(
+                CURVE25519_EDWARDS,
+                key.as_ref().as_ptr(),
+                &mut unused,
+            )
+        };
+        status != 0
+    }
+    #[cfg(not(target_os = "solana"))]
+    {
+        !key.is_on_curve()
+    }
+}
+
+/// Check that `account` is the pool's state PDA for `account_kind` (STATE_*)
+pub fn check_state_account(account: &AccountInfo, account_kind: u8) -> Result<()> {
+    let seed: &[u8] = match account_kind {
+        STATE_POOL => b"pool",
+        STATE_MERKLE_TREE => b"merkle_tree",
+        STATE_ROOTS_HISTORY => b"roots_history",
+        STATE_NULLIFIERS => b"nullifiers",
+        _ => return err!(WhistleError::InvalidStateAccount),
+    };
+    let (expected, _) = Pubkey::find_program_address(&[seed], &crate::ID);
+    require!(
+        *account.key == expected && *account.owner == crate::ID,
+        WhistleError::InvalidStateAccount
+    );
+    Ok(())
+}
 
 // ============================================================================
-// MERKLE TREE (Poseidon BN254 X5 based)
+// SCHNORR WITHDRAW PATH (small denominations)
 // ============================================================================
 
-fn merkle_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
-    // Poseidon(2) hash using Solana syscall (BN254 X5, big-endian)
-    poseidon_hashv(
-        PoseidonParameters::Bn254X5,
-        PoseidonEndianness::BigEndian,
-        &[left, right],
-    )
-    .expect("Poseidon syscall should succeed")
+/// Commitment of a Schnorr-path note, with the top byte cleared like any leaf
+pub fn schnorr_note_commitment(spend_pubkey: &[u8; 32], amount: u64) -> [u8; 32] {
+    let mut commitment = keccak::hashv(&[SCHNORR_NOTE_DOMAIN, spend_pubkey, &amount.to_le_bytes()]).to_bytes();
+    commitment[0] = 0;
+    commitment
+}
+
+/// Nullifier of a Schnorr-path note
+pub fn schnorr_nullifier_hash(commitment: &[u8; 32]) -> [u8; 32] {
+    keccak::hashv(&[SCHNORR_NULLIFIER_DOMAIN, commitment]).to_bytes()
+}
+
+// Ed25519 precompile instruction layout: a 2-byte header, then one 14-byte
+// offsets entry per signature
+const ED25519_HEADER_SIZE: usize = 2;
+const ED25519_OFFSETS_SIZE: usize = 14;
+
+/// Check that the previous instruction is an Ed25519 precompile call verifying
+/// `signature` over `message`, and return the signer's public key
+/// 
+/// The precompile has already rejected the transaction if the signature is
+/// invalid; this only checks that it verified what we expect.
+fn verify_ed25519_instruction(
+    instructions: &AccountInfo,
+    signature: &[u8; 64],
+    message: &[u8],
+) -> Result<[u8; 32]> {
+    let current = load_current_index_checked(instructions)?;
+    require!(current > 0, WhistleError::InvalidSchnorrSignature);
+    let ix = load_instruction_at_checked(current as usize - 1, instructions)?;
+    require!(
+        ix.program_id == anchor_lang::solana_program::ed25519_program::ID,
+        WhistleError::InvalidSchnorrSignature
+    );
+    
+    let data = &ix.data;
+    require!(
+        data.len() >= ED25519_HEADER_SIZE + ED25519_OFFSETS_SIZE && data[0] == 1,
+        WhistleError::InvalidSchnorrSignature
+    );
+    
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let offsets = ED25519_HEADER_SIZE;
+    let signature_offset = read_u16(offsets) as usize;
+    let signature_ix = read_u16(offsets + 2);
+    let pubkey_offset = read_u16(offsets + 4) as usize;
+    let pubkey_ix = read_u16(offsets + 6);
+    let message_offset = read_u16(offsets + 8) as usize;
+    let message_size = read_u16(offsets + 10) as usize;
+    let message_ix = read_u16(offsets + 12);
+    
+    // All data must come from the precompile instruction itself
+    require!(
+        signature_ix == u16::MAX && pubkey_ix == u16::MAX && message_ix == u16::MAX,
+        WhistleError::InvalidSchnorrSignature
+    );
+    
+    let signed = data.get(signature_offset..signature_offset + 64);
+    let pubkey = data.get(pubkey_offset..pubkey_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size);
+    require!(
+        signed == Some(signature.as_slice()) && signed_message == Some(message),
+        WhistleError::InvalidSchnorrSignature
+    );
+    
+    let mut spend_pubkey = [0u8; 32];
+    spend_pubkey.copy_from_slice(pubkey.ok_or(WhistleError::InvalidSchnorrSignature)?);
+    Ok(spend_pubkey)
+}
+
+// SECURITY FIX: Removed incomplete groth16_verify function
+// All verification now goes through groth16.rs, which calls the shared
+// whistle-groth16 crate
+
+// SECURITY FIX: Removed unused EC helper functions (scalar_mul_g1, point_add_g1, negate_g1)
+// All EC operations are now handled by whistle-groth16
+
+// ============================================================================
+// VERIFICATION KEYS
+// ============================================================================
+
+// SECURITY FIX: Removed Groth16VK struct and get_*_vk functions
+// Verification keys live in groth16.rs as whistle_groth16::VerificationKey
+
+// ============================================================================
+// MERKLE TREE (Poseidon BN254 X5 based)
+// ============================================================================
+
+/// Heights ZERO_SUBTREE covers: every level of the deepest (13-level) pool
+/// tree and its root
+pub const ZERO_SUBTREE_LEVELS: usize = 14;
+
+/// Root of an empty subtree of each height: ZERO_SUBTREE[0] is the zero
+/// leaf and ZERO_SUBTREE[h] = merkle_hash(ZERO_SUBTREE[h - 1], ZERO_SUBTREE[h - 1]).
+/// 
+/// The first levels of whistle_merkle::ZERO_VALUES, so the pool and the
+/// standalone tree program pad with the same Poseidon constants.
+pub const ZERO_SUBTREE: [[u8; 32]; ZERO_SUBTREE_LEVELS] = {
+    let mut table = [[0u8; 32]; ZERO_SUBTREE_LEVELS];
+    let mut level = 0;
+    while level < ZERO_SUBTREE_LEVELS {
+        table[level] = whistle_merkle::ZERO_VALUES[level];
+        level += 1;
+    }
+    table
+};
+
+/// Root of an empty tree of `levels` levels: Poseidon zero-subtree hash
+pub fn empty_tree_root(levels: u8) -> [u8; 32] {
+    let top = ZERO_SUBTREE.len() - 1;
+    let mut node = ZERO_SUBTREE[(levels as usize).min(top)];
+    for _ in top..levels as usize {
+        node = merkle_hash(&node, &node);
+    }
+    node
+}
+
+/// Pairs verify_commitment_compatibility hashes: both orders of an
+/// asymmetric pair catch swapped inputs, and r - 1 catches a client that
+/// reduces or reverses bytes
+pub const COMMITMENT_COMPATIBILITY_INPUTS: [([u8; 32], [u8; 32]); 3] = {
+    let mut r_minus_1 = BN254_SCALAR_MODULUS;
+    r_minus_1[31] -= 1;
+    [([0; 32], [1; 32]), ([1; 32], [0; 32]), (r_minus_1, r_minus_1)]
+};
+
+fn merkle_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    poseidon_hash(&[left, right])
+}
+
+/// Poseidon hash using Solana syscall (BN254 X5, big-endian), as in the circuits
+/// 
+/// Every input must be a canonical field element.
+pub fn poseidon_hash(inputs: &[&[u8]]) -> [u8; 32] {
+    poseidon_hashv(
+        PoseidonParameters::Bn254X5,
+        PoseidonEndianness::BigEndian,
+        inputs,
+    )
+    .expect("Poseidon syscall should succeed")
     .to_bytes()
 }
 
+/// Payment receipt for a withdrawal: Poseidon(nullifier_hash, recipient, amount, slot)
+/// 
+/// `amount` is what the recipient received. The nullifier hash and the
+/// recipient enter as their first 31 bytes (like pubkey_to_field), so the
+/// inputs are field elements on every withdrawal path.
+pub fn receipt_hash(nullifier_hash: &[u8; 32], recipient: &Pubkey, amount: u64, slot: u64) -> [u8; 32] {
+    poseidon_hash(&[
+        &pubkey_to_field(nullifier_hash),
+        &pubkey_to_field(&recipient.to_bytes()),
+        &public_inputs::u64_to_be_field(amount),
+        &public_inputs::u64_to_be_field(slot),
+    ])
+}
+
 // ============================================================================
 // ACCOUNT STRUCTURES
 // ============================================================================
@@ -904,19 +3868,178 @@ pub struct PoolState {
     pub total_shielded: u64,  // Currently shielded balance
     pub total_fees_collected: u64, // Protocol fees for point holder rewards
     pub bump: u8,
+    pub curve: u8, // CURVE_BN254 or CURVE_JUBJUB
+    pub allow_schnorr_for_small: bool, // verify_schnorr_withdraw enabled
+    pub max_total_deposits_per_address: u64, // 0 = unlimited
+    pub max_total_pool_shielded: u64, // 0 = unlimited
+    // Canonical bumps of the vault and data PDAs, so contexts verify them
+    // with create_program_address instead of searching with
+    // find_program_address. Appended last: they fill what was struct padding
+    // in pools created before them, which read zero until migrate_pool_bumps.
+    pub vault_bump: u8,
+    pub merkle_tree_bump: u8,
+    pub roots_history_bump: u8,
+    pub nullifiers_bump: u8,
+}
+
+impl PoolState {
+    /// Record the canonical bumps of the vault and data PDAs
+    pub fn store_pda_bumps(&mut self) {
+        let bump = |seed: &[u8]| Pubkey::find_program_address(&[seed], &crate::ID).1;
+        self.vault_bump = bump(b"vault");
+        self.merkle_tree_bump = bump(b"merkle_tree");
+        self.roots_history_bump = bump(b"roots_history");
+        self.nullifiers_bump = bump(b"nullifiers");
+    }
+
+    /// Whether the PDA bumps were recorded (a canonical bump is never zero
+    /// in practice, so all four zero means a pool from before they existed)
+    pub fn has_pda_bumps(&self) -> bool {
+        self.vault_bump != 0 || self.merkle_tree_bump != 0 || self.roots_history_bump != 0 || self.nullifiers_bump != 0
+    }
+
+    /// Emit CapacityWarning for each CAPACITY_WARNING_BPS threshold that
+    /// next_index reached since `previous_next_index`
+    /// 
+    /// next_index only grows, so each threshold is crossed, and announced,
+    /// exactly once without recording which warnings went out. Every path
+    /// that inserts leaves calls this after advancing next_index.
+    pub fn warn_capacity(&self, previous_next_index: u64) -> Result<()> {
+        let capacity = 1u64 << self.merkle_levels;
+        for threshold_bps in CAPACITY_WARNING_BPS {
+            let threshold = (capacity * threshold_bps as u64).div_ceil(BPS_DENOMINATOR);
+            if previous_next_index < threshold && self.next_index >= threshold {
+                emit!(CapacityWarning {
+                    threshold_bps,
+                    next_index: self.next_index,
+                    capacity,
+                    remaining: capacity.saturating_sub(self.next_index),
+                    slot: Clock::get()?.slot,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Relayer fee caps per withdrawal denomination, written once at genesis
+#[account]
+pub struct DenominationConfig {
+    pub denominations: [u64; 6],
+    pub relayer_fee_caps_bps: [u16; 6],
+    pub bump: u8,
+}
+
+impl DenominationConfig {
+    /// Maximum relayer fee (lamports) allowed for a withdrawal of `amount`
+    pub fn max_relayer_fee(&self, amount: u64) -> Result<u64> {
+        let idx = self.denominations.iter()
+            .position(|d| *d == amount)
+            .ok_or(WhistleError::InvalidWithdrawDenomination)?;
+        let cap = (amount as u128)
+            .checked_mul(self.relayer_fee_caps_bps[idx] as u128)
+            .ok_or(WhistleError::ArithmeticOverflow)?
+            / BPS_DENOMINATOR as u128;
+        Ok(cap as u64)
+    }
+}
+
+/// Check that SPL denominations are whole token units
+/// 
+/// Each must be a nonzero multiple of 10^mint_decimals; anything else is
+/// unusable in the circuits. unshield_token checks its withdrawal amount
+/// with this.
+pub fn validate_spl_denominations(mint_decimals: u8, denoms: &[u64]) -> Result<()> {
+    let unit = 10u64.checked_pow(mint_decimals.into());
+    for &denomination in denoms {
+        let whole_units = unit.is_some_and(|unit| denomination % unit == 0);
+        if denomination == 0 || !whole_units {
+            // Logged with the error when it is returned
+            return Err(error!(WhistleError::InvalidSplDenomination).with_values((denomination, mint_decimals)));
+        }
+    }
+    Ok(())
 }
 
 // MAINNET: 13 levels => 8192 leaves (deposits), 16384 total nodes
 // ~512KB account size - requires larger account allocation
+//
+// Leaves stay here even though MerkleTreeLeafPage also holds them: the
+// pages are a read index for get_leaves_page, and inserts, spends,
+// contains_leaf, rebuild_root and the migration still load this account
+// (see docs/ARCHITECTURE.md)
+//
+// Layout versions (after the 8-byte discriminator):
+// - v0: levels_used: u8, _padding: [u8; 7], nodes
+// - v1: version: u8, levels_used: u8, _padding: [u8; 2], node_capacity: u32, nodes
+pub const MERKLE_TREE_VERSION: u8 = 1;
+pub const MERKLE_TREE_NODE_CAPACITY: usize = 16384;
+pub const MERKLE_TREE_HEADER_SIZE: usize = 8;
+
+// Internal nodes rebuild_root recomputes per call (one Poseidon hash each)
+pub const MAX_REBUILD_NODES: u16 = 128;
+
+// Leaf commitments per MerkleTreeLeafPage (8KB of leaves)
+pub const LEAF_PAGE_SIZE: usize = 256;
+
+// Largest slice returned by get_leaves_page (fits in 1KB of return data)
+pub const MAX_LEAF_READ: u8 = 24;
+
 #[account(zero_copy)]
 #[repr(C)]
 pub struct MerkleTree {
+    pub version: u8,
     pub levels_used: u8,
-    pub _padding: [u8; 7],
-    pub nodes: [[u8; 32]; 16384],
+    pub _padding: [u8; 2],
+    pub node_capacity: u32,
+    pub nodes: [[u8; 32]; MERKLE_TREE_NODE_CAPACITY],
+}
+
+/// Version-independent view of a merkle tree account
+pub struct VersionedMerkleTree<'a> {
+    pub version: u8,
+    pub levels_used: u8,
+    pub node_capacity: u32,
+    pub nodes: &'a [[u8; 32]],
 }
 
 impl MerkleTree {
+    /// Parse raw account data (including discriminator) of either layout
+    /// 
+    /// v0 starts with levels_used (7-13); v1 starts with its version byte.
+    pub fn try_load_versioned(data: &[u8]) -> Result<VersionedMerkleTree<'_>> {
+        require!(
+            data.len() >= 8 + MERKLE_TREE_HEADER_SIZE && data[..8] == <MerkleTree as anchor_lang::Discriminator>::DISCRIMINATOR,
+            WhistleError::InvalidMerkleTreeAccount
+        );
+        let header = &data[8..8 + MERKLE_TREE_HEADER_SIZE];
+        let nodes: &[[u8; 32]] = bytemuck::try_cast_slice(&data[8 + MERKLE_TREE_HEADER_SIZE..])
+            .map_err(|_| error!(WhistleError::InvalidMerkleTreeAccount))?;
+        
+        match header[0] {
+            MERKLE_TREE_VERSION => Ok(VersionedMerkleTree {
+                version: MERKLE_TREE_VERSION,
+                levels_used: header[1],
+                node_capacity: u32::from_le_bytes([header[4], header[5], header[6], header[7]]),
+                nodes,
+            }),
+            7..=13 => Ok(VersionedMerkleTree {
+                version: 0,
+                levels_used: header[0],
+                node_capacity: nodes.len() as u32,
+                nodes,
+            }),
+            _ => err!(WhistleError::UnsupportedMerkleTreeVersion),
+        }
+    }
+    
+    /// Store `leaf` at `index` and rehash its path up to the root
+    /// 
+    /// A tree of `levels` levels occupies the first 2^(levels + 1) - 1 nodes
+    /// of the heap at any depth. Leaves are inserted in index order, so the
+    /// right sibling of each left child on the path has no leaf yet: it is
+    /// stored as the ZERO_SUBTREE entry of its height, as the circuits pad
+    /// empty subtrees.
     pub fn insert_leaf(&mut self, leaf: [u8; 32], index: u64, levels: u8) {
         let levels = levels.min(13); // 13 levels max for mainnet (8192 leaves)
         let leaf_offset = (1u64 << levels) - 1;
@@ -926,183 +4049,2690 @@ impl MerkleTree {
             self.nodes[leaf_pos] = leaf;
             
             let mut current = leaf_pos;
+            let mut height = 0;
             while current > 0 {
+                if current % 2 == 1 {
+                    self.nodes[current + 1] = ZERO_SUBTREE[height];
+                }
                 let parent = (current - 1) / 2;
-                let left_child = 2 * parent + 1;
-                let right_child = 2 * parent + 2;
-                
-                let left = if left_child < self.nodes.len() { self.nodes[left_child] } else { [0u8; 32] };
-                let right = if right_child < self.nodes.len() { self.nodes[right_child] } else { [0u8; 32] };
-                
-                self.nodes[parent] = merkle_hash(&left, &right);
+                self.nodes[parent] = merkle_hash(&self.nodes[2 * parent + 1], &self.nodes[2 * parent + 2]);
                 current = parent;
+                height += 1;
             }
         }
     }
     
+    /// Stored root: nodes[0] is the root at every depth, so `levels` only
+    /// matters to callers of the other methods
     pub fn get_root(&self, _levels: u8) -> [u8; 32] {
         self.nodes[0]
     }
+    
+    /// Fail with TreeStateDesync unless the stored root is the one `pool`
+    /// last published
+    /// 
+    /// An empty tree stores no root (the pool publishes empty_tree_root), so
+    /// there is nothing to compare until the first insert.
+    pub fn check_root(&self, pool: &PoolState) -> Result<()> {
+        self.require_not_migrating(pool)?;
+        require!(
+            pool.next_index == 0 || self.nodes[0] == pool.current_root,
+            WhistleError::TreeStateDesync
+        );
+        Ok(())
+    }
+    
+    /// Fail with PoolMigrationInProgress while a migration deepens the tree
+    /// 
+    /// begin_pool_migration records the new depth in levels_used, and the
+    /// pool only takes it at finalize_migration; in between the nodes are
+    /// neither the old tree nor the new one.
+    pub fn require_not_migrating(&self, pool: &PoolState) -> Result<()> {
+        require!(self.levels_used <= pool.merkle_levels, WhistleError::PoolMigrationInProgress);
+        Ok(())
+    }
+    
+    /// Recompute internal nodes `start..end` from their children, highest
+    /// index first
+    /// 
+    /// Matches insert_leaf: a node with a leaf before `next_index` hashes
+    /// its children, an empty right child of such a node is the ZERO_SUBTREE
+    /// entry of its height, and every other empty node stays zero. A tree
+    /// written before insert_leaf padded empty siblings comes out padded.
+    pub fn rebuild_nodes(&mut self, start: usize, end: usize, next_index: u64, levels: u8) {
+        let leaf_offset = (1usize << levels.min(13)) - 1;
+        for node in (start..end).rev() {
+            let (first_leaf, height) = leftmost_leaf(node, leaf_offset);
+            self.nodes[node] = if first_leaf < next_index {
+                merkle_hash(&self.nodes[2 * node + 1], &self.nodes[2 * node + 2])
+            } else if node % 2 == 0 && node > 0 && leftmost_leaf(node - 1, leaf_offset).0 < next_index {
+                ZERO_SUBTREE[height]
+            } else {
+                [0u8; 32]
+            };
+        }
+    }
+    
+    /// Root obtained by hashing the DISPUTE_RANGE_LEAVES leaves from `start`
+    /// and climbing through the stored siblings
+    /// 
+    /// Follows insert_leaf: a node with no leaf before `next_index` is the
+    /// ZERO_SUBTREE entry of its height. Trees smaller than the range are
+    /// hashed whole. `start` must be below `next_index`, so every node above
+    /// the range is hashed.
+    pub fn range_root(&self, start: u64, next_index: u64, levels: u8) -> [u8; 32] {
+        let levels = levels.min(13);
+        let range_levels = levels.min(DISPUTE_RANGE_LEVELS);
+        let leaf_offset = (1usize << levels) - 1;
+        let first_leaf = leaf_offset + start as usize;
+        let mut layer = self.nodes[first_leaf..first_leaf + (1usize << range_levels)].to_vec();
+        
+        // `first` indexes layer[0] within its level; `span` leaves sit below each node
+        let mut first = start;
+        let mut span = 1u64;
+        let mut height = 0;
+        while layer.len() > 1 {
+            first /= 2;
+            span *= 2;
+            height += 1;
+            layer = layer
+                .chunks(2)
+                .enumerate()
+                .map(|(i, pair)| {
+                    if (first + i as u64) * span < next_index {
+                        merkle_hash(&pair[0], &pair[1])
+                    } else {
+                        ZERO_SUBTREE[height]
+                    }
+                })
+                .collect();
+        }
+        
+        let mut node = (1usize << (levels - range_levels)) - 1 + first as usize;
+        let mut hash = layer[0];
+        while node > 0 {
+            hash = if node % 2 == 1 {
+                merkle_hash(&hash, &self.nodes[node + 1])
+            } else {
+                merkle_hash(&self.nodes[node - 1], &hash)
+            };
+            node = (node - 1) / 2;
+        }
+        hash
+    }
+    
+    /// Check whether `leaf` is one of the first `count` inserted leaves
+    pub fn contains_leaf(&self, leaf: &[u8; 32], count: u64, levels: u8) -> bool {
+        let levels = levels.min(13);
+        let leaf_offset = ((1u64 << levels) - 1) as usize;
+        let end = (leaf_offset + count as usize).min(self.nodes.len());
+        self.nodes[leaf_offset..end].iter().any(|n| n == leaf)
+    }
+    
+    /// Non-zero leaves in the leaf layer of a tree of `levels` levels
+    pub fn leaf_count(&self, levels: u8) -> u64 {
+        let levels = levels.min(13);
+        let leaf_offset = ((1u64 << levels) - 1) as usize;
+        let end = (2 * leaf_offset + 1).min(self.nodes.len());
+        self.nodes[leaf_offset..end].iter().filter(|n| **n != [0u8; 32]).count() as u64
+    }
+}
+
+/// Index of the leftmost leaf below heap node `node` and the node's height
+/// above the leaves, in a tree whose leaves start at `leaf_offset`
+fn leftmost_leaf(node: usize, leaf_offset: usize) -> (u64, usize) {
+    let (mut leftmost, mut height) = (node, 0);
+    while leftmost < leaf_offset {
+        leftmost = 2 * leftmost + 1;
+        height += 1;
+    }
+    ((leftmost - leaf_offset) as u64, height)
+}
+
+/// Page `page_index` of the tree's leaves, readable without loading the
+/// MerkleTree account
+/// 
+/// Holds the first `count` leaves of the page. Shields append as they insert;
+/// leaves inserted by other paths (batched shields, change notes, auction
+/// settlements) are copied in by sync_leaf_page.
+#[account(zero_copy)]
+#[repr(C)]
+pub struct MerkleTreeLeafPage {
+    pub page_index: u32,
+    pub count: u32,
+    pub leaves: [[u8; 32]; LEAF_PAGE_SIZE],
+}
+
+impl MerkleTreeLeafPage {
+    pub fn page_of(leaf_index: u64) -> u32 {
+        (leaf_index / LEAF_PAGE_SIZE as u64) as u32
+    }
+    
+    /// Borrow `loader` for writing, setting up page `page_index` if the
+    /// account was created by this instruction
+    pub fn load_or_init<'a>(
+        loader: &'a AccountLoader<'_, MerkleTreeLeafPage>,
+        page_index: u32,
+    ) -> Result<std::cell::RefMut<'a, MerkleTreeLeafPage>> {
+        if let Ok(page) = loader.load_mut() {
+            return Ok(page);
+        }
+        let mut page = loader.load_init()?;
+        // A page created earlier in this instruction already has its index
+        if page.count == 0 {
+            page.page_index = page_index;
+        }
+        Ok(page)
+    }
+    
+    fn first_leaf(&self) -> u64 {
+        self.page_index as u64 * LEAF_PAGE_SIZE as u64
+    }
+    
+    /// Record `leaf` at `leaf_index` when it is the next leaf of this page;
+    /// anything else is left for sync_leaf_page
+    pub fn append(&mut self, leaf_index: u64, leaf: [u8; 32]) {
+        if leaf_index == self.first_leaf() + self.count as u64 && (self.count as usize) < LEAF_PAGE_SIZE {
+            self.leaves[self.count as usize] = leaf;
+            self.count += 1;
+        }
+    }
+    
+    /// Copy the page's leaves below `next_index` that it is missing from `tree`
+    pub fn sync(&mut self, tree: &MerkleTree, next_index: u64, levels: u8) {
+        let leaf_offset = (1u64 << levels.min(13)) - 1;
+        let end = next_index.min(self.first_leaf() + LEAF_PAGE_SIZE as u64);
+        for leaf_index in self.first_leaf() + self.count as u64..end {
+            self.leaves[self.count as usize] = tree.nodes[(leaf_offset + leaf_index) as usize];
+            self.count += 1;
+        }
+    }
+    
+    /// Up to `count` filled leaves from offset `start` within the page
+    pub fn read(&self, start: u16, count: u8) -> Vec<[u8; 32]> {
+        let filled = self.count as usize;
+        let start = (start as usize).min(filled);
+        let end = filled.min(start + count as usize);
+        self.leaves[start..end].to_vec()
+    }
 }
 
-// MAINNET: 100 roots history for better reliability
+// Roots history account: this header, then `capacity` roots, then
+// `capacity` little-endian u64 slots, root_slots[i] being the slot roots[i]
+// was recorded at. Read and written through RootsRing, since the ring's
+// length is only known at runtime.
+// 
+// Accounts created before the capacity was stored read it as zero and hold
+// ROOTS_HISTORY_CAPACITY entries, in the same layout. Accounts created
+// before root_slots are grown by migrate_roots_history, which stamps their
+// roots with the migration slot.
 #[account(zero_copy)]
 #[repr(C)]
 pub struct RootsHistory {
-    pub current_index: u8,
-    pub _padding: [u8; 31],
-    pub roots: [[u8; 32]; 100], // 100 roots history for mainnet
+    pub current_index: u16, // was a u8 followed by zero padding
+    pub capacity: u16,
+    pub _padding: [u8; 28],
 }
 
 impl RootsHistory {
+    /// Account size holding `capacity` roots, discriminator included
+    pub const fn space(capacity: usize) -> usize {
+        ROOTS_OFFSET + capacity * (32 + 8)
+    }
+    
+    pub fn capacity(&self) -> usize {
+        match self.capacity {
+            0 => ROOTS_HISTORY_CAPACITY,
+            capacity => capacity as usize,
+        }
+    }
+    
+    /// Whether resize_roots_history may grow this history to `capacity`
+    pub fn can_grow_to(&self, capacity: usize) -> bool {
+        capacity > self.capacity()
+            && capacity - self.capacity() <= MAX_ROOTS_HISTORY_GROWTH
+            && capacity <= MAX_ROOTS_HISTORY_CAPACITY
+    }
+    
+    pub fn ring<'a>(account: &'a AccountLoader<RootsHistory>) -> Result<RootsRing<Ref<'a, [u8]>>> {
+        let data = account.as_ref().try_borrow_data()?;
+        RootsRing::new(Ref::map(data, |data| &**data))
+    }
+    
+    pub fn ring_mut<'a>(account: &'a AccountLoader<RootsHistory>) -> Result<RootsRing<RefMut<'a, [u8]>>> {
+        let data = account.as_ref().try_borrow_mut_data()?;
+        RootsRing::new(RefMut::map(data, |data| &mut **data))
+    }
+}
+
+// Offset of roots[0] in a roots history account
+const ROOTS_OFFSET: usize = 8 + std::mem::size_of::<RootsHistory>();
+
+/// A roots history account's ring, over the account's whole data
+pub struct RootsRing<D> {
+    data: D,
+}
+
+impl<D: Deref<Target = [u8]>> RootsRing<D> {
+    pub fn new(data: D) -> Result<Self> {
+        let ring = Self { data };
+        require!(
+            ring.data.len() >= ROOTS_OFFSET && ring.data.len() >= RootsHistory::space(ring.capacity()),
+            WhistleError::InvalidStateAccount
+        );
+        Ok(ring)
+    }
+    
+    fn header(&self) -> RootsHistory {
+        bytemuck::pod_read_unaligned(&self.data[8..ROOTS_OFFSET])
+    }
+    
+    pub fn capacity(&self) -> usize {
+        self.header().capacity()
+    }
+    
+    /// Entry the next push overwrites
+    pub fn current_index(&self) -> usize {
+        self.header().current_index as usize
+    }
+    
+    pub fn root(&self, index: usize) -> [u8; 32] {
+        let offset = ROOTS_OFFSET + index * 32;
+        self.data[offset..offset + 32].try_into().unwrap()
+    }
+    
+    pub fn root_slot(&self, index: usize) -> u64 {
+        let offset = self.slots_offset() + index * 8;
+        u64::from_le_bytes(self.data[offset..offset + 8].try_into().unwrap())
+    }
+    
+    /// The most recently pushed root (zero while nothing was pushed)
+    pub fn latest(&self) -> [u8; 32] {
+        let capacity = self.capacity();
+        self.root((self.current_index() + capacity - 1) % capacity)
+    }
+    
+    /// Whether `root` was recorded; unfilled slots are zero and never match
     pub fn contains(&self, root: &[u8; 32]) -> bool {
-        self.roots.iter().any(|r| r == root)
+        *root != [0u8; 32] && (0..self.capacity()).any(|i| self.root(i) == *root)
     }
     
     pub fn is_valid_root(&self, root: &[u8; 32]) -> bool {
         self.contains(root)
     }
+    
+    /// Whether `root` was recorded no more than MAX_ROOT_AGE_SLOTS before `slot`
+    pub fn is_recent(&self, root: &[u8; 32], slot: u64) -> bool {
+        *root != [0u8; 32]
+            && (0..self.capacity()).any(|i| {
+                self.root(i) == *root && slot.saturating_sub(self.root_slot(i)) <= MAX_ROOT_AGE_SLOTS
+            })
+    }
+    
+    /// Fail unless a spend may prove against `root`: the pool's current root,
+    /// or a recent roots history entry
+    /// 
+    /// A recorded root past MAX_ROOT_AGE_SLOTS fails with StaleMerkleRoot,
+    /// so the client knows to rebuild its path against the current root.
+    pub fn require_spendable_root(&self, root: &[u8; 32], pool: &PoolState, slot: u64) -> Result<()> {
+        if *root == pool.current_root {
+            return Ok(());
+        }
+        require!(self.contains(root), WhistleError::InvalidMerkleRoot);
+        require!(self.is_recent(root, slot), WhistleError::StaleMerkleRoot);
+        Ok(())
+    }
+    
+    fn slots_offset(&self) -> usize {
+        ROOTS_OFFSET + self.capacity() * 32
+    }
+}
+
+impl<D: DerefMut<Target = [u8]>> RootsRing<D> {
+    /// Record `root`, published at `slot`, over the oldest entry
+    pub fn push(&mut self, root: [u8; 32], slot: u64) {
+        let index = self.current_index();
+        self.set(index, root, slot);
+        let mut header = self.header();
+        header.current_index = ((index + 1) % self.capacity()) as u16;
+        self.set_header(header);
+    }
+    
+    /// Overwrite entry `index` without moving the ring
+    pub fn set(&mut self, index: usize, root: [u8; 32], slot: u64) {
+        let offset = ROOTS_OFFSET + index * 32;
+        self.data[offset..offset + 32].copy_from_slice(&root);
+        let offset = self.slots_offset() + index * 8;
+        self.data[offset..offset + 8].copy_from_slice(&slot.to_le_bytes());
+    }
+    
+    /// Re-lay the ring out for `capacity` entries once the account data has
+    /// grown to RootsHistory::space(capacity)
+    /// 
+    /// Every recorded root keeps its slot. A full ring is unrolled oldest
+    /// first, so the new entries fill before any recorded root is
+    /// overwritten.
+    pub fn grow(&mut self, capacity: usize) {
+        let old = self.capacity();
+        let index = self.current_index();
+        let full = self.root(index) != [0u8; 32];
+        let old_slots = self.slots_offset();
+        let new_slots = ROOTS_OFFSET + capacity * 32;
+        
+        self.data.copy_within(old_slots..old_slots + old * 8, new_slots);
+        self.data[old_slots..new_slots].fill(0);
+        self.data[new_slots + old * 8..new_slots + capacity * 8].fill(0);
+        let mut header = self.header();
+        if full {
+            self.data[ROOTS_OFFSET..old_slots].rotate_left(index * 32);
+            self.data[new_slots..new_slots + old * 8].rotate_left(index * 8);
+            header.current_index = old as u16;
+        }
+        header.capacity = capacity as u16;
+        self.set_header(header);
+    }
+    
+    fn set_header(&mut self, header: RootsHistory) {
+        self.data[8..ROOTS_OFFSET].copy_from_slice(bytemuck::bytes_of(&header));
+    }
 }
 
-// MAINNET: 4096 nullifiers = ~128KB (supports 4096 withdrawals)
+/// Ring of per-epoch shield counts, bucketed by amount band
+/// 
+/// Rolled forward lazily: the first shield in a new epoch advances the ring
+/// and clears the epochs that were skipped.
 #[account(zero_copy)]
 #[repr(C)]
-pub struct NullifierSet {
-    pub count: u64,
-    pub nullifiers: [[u8; 32]; 4096], // 4096 nullifiers for mainnet
+pub struct PoolStats {
+    pub current_epoch: u64,
+    pub head: u64, // ring slot holding current_epoch
+    pub counts: [[u32; STATS_AMOUNT_BANDS]; STATS_RING_EPOCHS],
 }
 
-impl NullifierSet {
-    pub fn is_spent(&self, nullifier: &[u8; 32]) -> bool {
-        for i in 0..self.count as usize {
-            if i < 4096 && self.nullifiers[i] == *nullifier {
-                return true;
+impl PoolStats {
+    pub fn amount_band(amount: u64) -> usize {
+        STATS_BAND_UPPER_BOUNDS.iter()
+            .position(|upper| amount < *upper)
+            .unwrap_or(STATS_AMOUNT_BANDS - 1)
+    }
+    
+    /// Advance the ring to the epoch containing `slot`
+    pub fn roll_forward(&mut self, slot: u64) {
+        let epoch = slot / STATS_EPOCH_SLOTS;
+        if epoch <= self.current_epoch {
+            return;
+        }
+        
+        let steps = (epoch - self.current_epoch).min(STATS_RING_EPOCHS as u64);
+        for _ in 0..steps {
+            self.head = (self.head + 1) % STATS_RING_EPOCHS as u64;
+            self.counts[self.head as usize] = [0; STATS_AMOUNT_BANDS];
+        }
+        self.current_epoch = epoch;
+    }
+    
+    pub fn record_shield(&mut self, slot: u64, amount: u64) {
+        self.roll_forward(slot);
+        let count = &mut self.counts[self.head as usize][Self::amount_band(amount)];
+        *count = count.saturating_add(1);
+    }
+    
+    /// Shield counts per band over the trailing `epochs` epochs as seen at `slot`
+    /// 
+    /// Read-only: epochs the ring has not rolled past yet are treated as empty.
+    pub fn window(&self, slot: u64, epochs: usize) -> [u32; STATS_AMOUNT_BANDS] {
+        let lag = (slot / STATS_EPOCH_SLOTS).saturating_sub(self.current_epoch) as usize;
+        let mut totals = [0u32; STATS_AMOUNT_BANDS];
+        
+        for age in 0..epochs.min(STATS_RING_EPOCHS).saturating_sub(lag) {
+            let idx = (self.head as usize + STATS_RING_EPOCHS - age) % STATS_RING_EPOCHS;
+            for (total, count) in totals.iter_mut().zip(self.counts[idx].iter()) {
+                *total = total.saturating_add(*count);
             }
         }
-        false
+        totals
+    }
+    
+    pub fn metrics(&self, slot: u64) -> AnonymityMetrics {
+        AnonymityMetrics {
+            slot,
+            last_1k_slots: self.window(slot, 1),
+            last_10k_slots: self.window(slot, 10),
+            last_100k_slots: self.window(slot, STATS_RING_EPOCHS),
+            denomination_specific_set_sizes: [0; DENOMINATIONS.len()],
+        }
+    }
+}
+
+/// Snapshot of shield counts per amount band over trailing windows
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AnonymityMetrics {
+    pub slot: u64,
+    pub last_1k_slots: [u32; STATS_AMOUNT_BANDS],
+    pub last_10k_slots: [u32; STATS_AMOUNT_BANDS],
+    pub last_100k_slots: [u32; STATS_AMOUNT_BANDS],
+    /// compute_weighted_anonymity_set of each entry of DENOMINATIONS
+    pub denomination_specific_set_sizes: [u64; DENOMINATIONS.len()],
+}
+
+impl AnonymityMetrics {
+    /// Borsh size; return data drops trailing zero bytes, so decoders pad to this
+    pub const SIZE: usize = 8 + 3 * 4 * STATS_AMOUNT_BANDS + 8 * DENOMINATIONS.len();
+}
+
+/// Everything a proof-of-reserve report needs, read at `slot`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ReserveSnapshot {
+    /// RESERVE_SNAPSHOT_VERSION
+    pub version: u8,
+    pub slot: u64,
+    pub vault_lamports: u64,
+    pub fee_vault_lamports: u64,
+    pub total_deposits: u64,
+    pub total_shielded: u64,
+    /// Shielded value that has left the pool (total_deposits - total_shielded)
+    pub total_withdrawn: u64,
+    pub total_fees_collected: u64,
+    /// vault_lamports - total_shielded; negative means the vault is short
+    pub surplus: i64,
+    pub leaf_count: u64,
+    pub current_root: [u8; 32],
+}
+
+impl ReserveSnapshot {
+    /// Borsh size; return data drops trailing zero bytes, so decoders pad to this
+    pub const SIZE: usize = 1 + 8 * 9 + 32;
+}
+
+/// Result of verify_reserves
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReservesReport {
+    pub vault_balance: u64,
+    pub shielded_balance: u64,
+    /// vault_balance >= shielded_balance
+    pub is_solvent: bool,
+    /// The tree holds exactly pool.next_index leaves
+    pub leaf_count_matches: bool,
+    /// The NullifierSet count does not exceed pool.next_index
+    pub nullifier_count_valid: bool,
+}
+
+impl ReservesReport {
+    /// Borsh size; return data drops trailing zero bytes, so decoders pad to this
+    pub const SIZE: usize = 8 * 2 + 3;
+}
+
+/// Deposit counts per amount bucket, for every shield since genesis
+#[account]
+pub struct DepositHistogram {
+    pub buckets: [u32; DEPOSIT_HISTOGRAM_BUCKETS],
+    pub bump: u8,
+}
+
+impl DepositHistogram {
+    pub const SIZE: usize = 8 + 4 * DEPOSIT_HISTOGRAM_BUCKETS + 1;
+    
+    pub fn bucket_index(amount: u64) -> usize {
+        DEPOSIT_BUCKET_LOWER_BOUNDS.iter()
+            .rposition(|lower| amount >= *lower)
+            .unwrap_or(0)
+    }
+    
+    /// Count a deposit, emitting HistogramUpdated when the bucket reaches 1, 10, 100...
+    pub fn record(&mut self, amount: u64) {
+        let bucket_index = Self::bucket_index(amount);
+        let count = &mut self.buckets[bucket_index];
+        *count = count.saturating_add(1);
+        
+        if *count == 10u32.pow(count.ilog10()) {
+            emit!(HistogramUpdated {
+                bucket_index: bucket_index as u8,
+            });
+        }
     }
+}
+
+/// Deposits that could have funded a withdrawal of `denomination`: those of
+/// at least that amount
+/// 
+/// A pool's leaf count overstates the anonymity of a large withdrawal, since
+/// smaller deposits cannot have paid for it. Counts whole histogram buckets
+/// with a lower bound of at least `denomination`, so it is exact for the
+/// withdrawal denominations (all bucket bounds) and an undercount between
+/// bounds.
+pub fn compute_weighted_anonymity_set(denomination: u64, deposit_histogram: &DepositHistogram) -> u64 {
+    DEPOSIT_BUCKET_LOWER_BOUNDS
+        .iter()
+        .zip(deposit_histogram.buckets.iter())
+        .filter(|(lower, _)| **lower >= denomination)
+        .map(|(_, count)| *count as u64)
+        .sum()
+}
+
+/// Withdrawal and relayer approval counts for the current and previous
+/// congestion window
+/// 
+/// Rolled forward lazily like PoolStats: the first event in a new window
+/// moves the current counts to the `last_*` fields, or clears both when a
+/// whole window went by without events.
+#[account]
+pub struct CongestionStats {
+    pub window: u64, // slot / CONGESTION_WINDOW_SLOTS of the current counts
+    pub withdrawals: u32,
+    pub approvals: u32,
+    pub last_withdrawals: u32,
+    pub last_approvals: u32,
+    pub bump: u8,
+}
+
+impl CongestionStats {
+    pub const SIZE: usize = 8 + 8 + 4 * 4 + 1;
+    
+    /// Advance to the window containing `slot`
+    pub fn roll_forward(&mut self, slot: u64) {
+        let window = slot / CONGESTION_WINDOW_SLOTS;
+        if window <= self.window {
+            return;
+        }
+        
+        if window == self.window + 1 {
+            self.last_withdrawals = self.withdrawals;
+            self.last_approvals = self.approvals;
+        } else {
+            self.last_withdrawals = 0;
+            self.last_approvals = 0;
+        }
+        self.withdrawals = 0;
+        self.approvals = 0;
+        self.window = window;
+    }
+    
+    pub fn record_withdrawals(&mut self, slot: u64, count: u32) {
+        self.roll_forward(slot);
+        self.withdrawals = self.withdrawals.saturating_add(count);
+    }
+    
+    pub fn record_approval(&mut self, slot: u64) {
+        self.roll_forward(slot);
+        self.approvals = self.approvals.saturating_add(1);
+    }
+    
+    /// Counts as seen at `slot`
+    /// 
+    /// Read-only: windows the account has not rolled past yet are treated as
+    /// empty.
+    pub fn info(&self, slot: u64) -> CongestionInfo {
+        let mut rolled = self.clone();
+        rolled.roll_forward(slot);
+        CongestionInfo {
+            slot,
+            withdrawals_this_window: rolled.withdrawals,
+            withdrawals_last_window: rolled.last_withdrawals,
+            approvals_this_window: rolled.approvals,
+            approvals_last_window: rolled.last_approvals,
+            window_slots: CONGESTION_WINDOW_SLOTS,
+            window_target: CONGESTION_WINDOW_TARGET,
+        }
+    }
+}
+
+/// Congestion counts read at `slot`, for relayer fee quotes
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CongestionInfo {
+    pub slot: u64,
+    pub withdrawals_this_window: u32,
+    pub withdrawals_last_window: u32,
+    /// Relayer withdrawals approved; each is a job a relayer still has to
+    /// submit or has just submitted
+    pub approvals_this_window: u32,
+    pub approvals_last_window: u32,
+    /// CONGESTION_WINDOW_SLOTS
+    pub window_slots: u64,
+    /// CONGESTION_WINDOW_TARGET
+    pub window_target: u32,
+}
+
+/// Chunked upload buffer for payloads too large for one transaction
+#[account]
+pub struct ProofStaging {
+    pub creator: Pubkey,
+    pub session_id: u64,
+    pub created_slot: u64,
+    pub len: u16,
+    pub data: [u8; PROOF_STAGING_CAPACITY],
+    pub bump: u8,
+}
+
+/// A note's secrets, sealed to its owner's key, stored for wallet recovery
+#[account]
+pub struct EncryptedNote {
+    pub owner: Pubkey,
+    pub leaf_index: u64,
+    pub ciphertext: Vec<u8>,
+    pub stored_at: i64,
+    pub bump: u8,
+}
+
+impl EncryptedNote {
+    /// Account size holding a `ciphertext_len`-byte ciphertext
+    pub const fn space(ciphertext_len: usize) -> usize {
+        8 + 32 + 8 + 4 + ciphertext_len + 8 + 1
+    }
+}
+
+/// Progress of a move to a deeper tree, from begin_pool_migration to
+/// finalize_migration
+#[account]
+pub struct MigrationState {
+    pub old_levels: u8,
+    pub new_levels: u8,
+    pub migrated_leaves: u64, // Leaves copied to the new leaf level
+    pub next_node: u32, // Internal nodes below this index are still to rebuild
+    pub completed: bool,
+    pub bump: u8,
+}
+
+impl MigrationState {
+    pub const SIZE: usize = 8 + 1 + 1 + 8 + 4 + 1 + 1;
+}
+
+/// View key an owner discloses notes under
+#[account]
+pub struct ViewKeyRecord {
+    pub owner: Pubkey,
+    pub view_key: [u8; 32],
+    pub registered_at: i64,
+    pub bump: u8,
+}
+
+impl ViewKeyRecord {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 1;
+}
+
+/// Opening of a note commitment, as the circuits' NoteCommitment hashes it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NoteData {
+    pub secret: [u8; 32],
+    pub nullifier: [u8; 32],
+    pub amount: u64,
+    pub unlock_slot: u64, // Zero unless the note is time-locked
+}
+
+impl NoteData {
+    /// Poseidon(secret, Poseidon(nullifier, amount)), with unlock_slot as a
+    /// third input of the outer hash when non-zero
+    /// 
+    /// secret and nullifier must be canonical field elements.
+    pub fn commitment(&self) -> [u8; 32] {
+        let inner = merkle_hash(&self.nullifier, &u64_to_be_field(self.amount));
+        if self.unlock_slot == 0 {
+            merkle_hash(&self.secret, &inner)
+        } else {
+            poseidon_hash(&[&self.secret, &inner, &u64_to_be_field(self.unlock_slot)])
+        }
+    }
+}
+
+/// Domain input hashed with the spending key into its view key
+pub const VIEW_KEY_DOMAIN: [u8; 32] = [1u8; 32];
+
+/// View key of `spending_key`: Poseidon(spending_key, VIEW_KEY_DOMAIN)
+/// 
+/// One-way, so holding the view key reveals nothing that derives
+/// nullifiers from the spending key.
+pub fn derive_view_key(spending_key: &[u8; 32]) -> [u8; 32] {
+    merkle_hash(spending_key, &VIEW_KEY_DOMAIN)
+}
+
+/// Pending shield hidden behind the hash of its commitment
+#[account]
+pub struct PreCommit {
+    pub payer: Pubkey,
+    pub commitment_hash: [u8; 32],
+    pub deposit_slot: u64,
+    pub bump: u8,
+}
+
+/// Lamports a relayer has bonded against inactivity slashing
+#[account]
+pub struct RelayerBond {
+    pub relayer: Pubkey,
+    pub bond: u64,
+    pub bump: u8,
+}
+
+/// A submitter node's short claim on a withdrawal, keyed by its nullifier hash
+#[account]
+pub struct IntentLock {
+    pub holder: Pubkey,
+    pub rent_payer: Pubkey, // first holder, refunded on release
+    pub nullifier_hash: [u8; 32],
+    pub expires_slot: u64,
+    pub bump: u8,
+}
+
+impl IntentLock {
+    pub const SIZE: usize = 8 + 32 + 32 + 32 + 8 + 1;
+}
+
+/// A withdrawal a relayer agreed to submit, keyed by its nullifier hash
+#[account]
+pub struct PendingWithdrawal {
+    pub user: Pubkey,
+    pub relayer: Pubkey,
+    pub nullifier_hash: [u8; 32],
+    pub approved_slot: u64,
+    pub bump: u8,
+}
+
+/// Whether the program's upgrade authority was burned, as last attested
+#[account]
+pub struct FinalityAttestation {
+    pub immutable: bool,
+    pub deployed_slot: u64,        // ProgramData slot of the last deploy
+    pub immutable_since_slot: u64, // First attestation that saw no authority (0 while upgradeable)
+    pub attested_slot: u64,
+    pub bump: u8,
+}
+
+impl FinalityAttestation {
+    pub const SIZE: usize = 8 + 1 + 8 + 8 + 8 + 1;
+}
+
+/// An open challenge of the published root, holding the challenger's bond
+#[account]
+pub struct TreeDispute {
+    pub challenger: Pubkey,
+    pub claimed_root: [u8; 32],
+    pub disputed_root: [u8; 32], // pool.current_root when the challenge was opened
+    pub range_start: u64,
+    pub bond: u64,
+    pub opened_slot: u64,
+    pub bump: u8,
+}
+
+impl TreeDispute {
+    pub const SIZE: usize = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 1;
+}
+
+/// The Solana account an Ethereum address authorizes unshields to
+#[account]
+pub struct EthAddressMapping {
+    pub eth_address: [u8; 20],
+    pub authorized: Pubkey,
+    pub nonce: u64, // Registrations so far; the next one must sign this value
+    pub bump: u8,
+}
+
+impl EthAddressMapping {
+    pub const SIZE: usize = 8 + 20 + 32 + 8 + 1;
+}
+
+/// Running total shielded by one depositor, for the per-address cap
+#[account]
+pub struct DepositRecord {
+    pub depositor: Pubkey,
+    pub total_deposited: u64,
+}
+
+/// Sealed-bid NFT auction (see auction.rs)
+#[account]
+pub struct AuctionState {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub nft_escrow: Pubkey,
+    pub reserve_commitment: [u8; 32],
+    pub deadline_slot: u64,
+    pub reserve: u64,
+    pub reserve_revealed: bool,
+    pub bid_count: u32,
+    /// Bid account of the highest opened bid (default if none)
+    pub leading_bid: Pubkey,
+    pub leading_amount: u64,
+    pub leading_change_commitment: [u8; 32],
+    pub nft_recipient: Pubkey,
+    pub finalized: bool,
+    pub bump: u8,
+}
+
+#[account]
+pub struct AuctionBid {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub bid_commitment: [u8; 32],
+    pub max_bid: u64,
+    pub bump: u8,
+}
+
+/// Nullifiers spent before NullifierMarker existed
+/// 
+/// Frozen: spends now create markers instead, but every spend still checks
+/// this set, so a note spent here can never be spent again.
+#[account(zero_copy)]
+#[repr(C)]
+pub struct NullifierSet {
+    pub count: u64,
+    pub nullifiers: [[u8; 32]; 4096], // 4096 nullifiers for mainnet
+    pub spent_slots: [u64; 4096],     // Slot each nullifier was spent at
+}
+
+impl NullifierSet {
+    pub fn is_spent(&self, nullifier: &[u8; 32]) -> bool {
+        for i in 0..self.count as usize {
+            if i < 4096 && self.nullifiers[i] == *nullifier {
+                return true;
+            }
+        }
+        false
+    }
+    
+    /// Bitmask of which `hashes` are spent, in a single pass over the set
+    pub fn spent_mask(&self, hashes: &[[u8; 32]; NULLIFIER_STATUS_BATCH]) -> u16 {
+        let count = (self.count as usize).min(self.nullifiers.len());
+        let mut mask = 0u16;
+        for spent in &self.nullifiers[..count] {
+            for (i, hash) in hashes.iter().enumerate() {
+                if spent == hash {
+                    mask |= 1 << i;
+                }
+            }
+        }
+        mask
+    }
+    
+    /// Slot `nullifier` was spent at, if it is in the set
+    pub fn spent_slot(&self, nullifier: &[u8; 32]) -> Option<u64> {
+        let count = (self.count as usize).min(self.nullifiers.len());
+        self.nullifiers[..count]
+            .iter()
+            .position(|spent| spent == nullifier)
+            .map(|i| self.spent_slots[i])
+    }
+    
+    /// Spent nullifiers from position `start` (in spend order), at most `count`
+    pub fn page(&self, start: u64, count: u8) -> Vec<SpentNullifier> {
+        let end = self.count.min(4096).min(start.saturating_add(count as u64));
+        (start..end)
+            .map(|seq| SpentNullifier {
+                nullifier: self.nullifiers[seq as usize],
+                slot: self.spent_slots[seq as usize],
+                seq,
+            })
+            .collect()
+    }
+}
+
+/// Marks one nullifier spent; lives at [b"nullifier", nullifier_hash]
+/// 
+/// Creating the marker is the spend, so a second spend of the same note
+/// finds the account and fails, however many notes the pool has spent.
+/// The relayer or recipient submitting the spend pays its rent.
+#[account]
+pub struct NullifierMarker {
+    pub nullifier_hash: [u8; 32],
+    pub spent_slot: u64,
+    pub bump: u8,
+}
+
+impl NullifierMarker {
+    pub const SIZE: usize = 8 + 32 + 8 + 1;
+    
+    pub fn address(nullifier_hash: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"nullifier", nullifier_hash.as_ref()], &crate::ID)
+    }
+    
+    /// Slot `nullifier_hash` was spent at, if `legacy` or `marker` records
+    /// it; fails if `marker` is not the hash's marker address
+    pub fn spent_slot(legacy: &NullifierSet, marker: &AccountInfo, nullifier_hash: &[u8; 32]) -> Result<Option<u64>> {
+        require_keys_eq!(marker.key(), Self::address(nullifier_hash).0, WhistleError::InvalidNullifierMarker);
+        if let Some(slot) = legacy.spent_slot(nullifier_hash) {
+            return Ok(Some(slot));
+        }
+        if marker.owner != &crate::ID {
+            return Ok(None);
+        }
+        let data = marker.try_borrow_data()?;
+        Ok(Some(NullifierMarker::try_deserialize(&mut &data[..])?.spent_slot))
+    }
+    
+    pub fn require_unspent(legacy: &NullifierSet, marker: &AccountInfo, nullifier_hash: &[u8; 32]) -> Result<()> {
+        require!(
+            Self::spent_slot(legacy, marker, nullifier_hash)?.is_none(),
+            WhistleError::NullifierAlreadyUsed
+        );
+        Ok(())
+    }
+    
+    /// Spend `nullifier_hash` by creating its marker, funded by `payer`
+    /// 
+    /// Fails with NullifierAlreadyUsed if it is already spent, including
+    /// by an earlier marker of the same instruction. Lamports sent to the
+    /// address beforehand are kept, as Anchor's `init` does.
+    pub fn spend<'info>(
+        legacy: &NullifierSet,
+        marker: &AccountInfo<'info>,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        nullifier_hash: &[u8; 32],
+    ) -> Result<()> {
+        let (address, bump) = Self::address(nullifier_hash);
+        require_keys_eq!(marker.key(), address, WhistleError::InvalidNullifierMarker);
+        require!(
+            legacy.spent_slot(nullifier_hash).is_none() && marker.owner != &crate::ID,
+            WhistleError::NullifierAlreadyUsed
+        );
+        
+        let seeds: &[&[u8]] = &[b"nullifier", nullifier_hash.as_ref(), &[bump]];
+        create_marker_account(marker, payer, system_program, seeds, Self::SIZE)?;
+        
+        let record = NullifierMarker {
+            nullifier_hash: *nullifier_hash,
+            spent_slot: Clock::get()?.slot,
+            bump,
+        };
+        record.try_serialize(&mut &mut marker.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+}
+
+/// Create the program-owned account `marker` at its PDA (`seeds`), `size`
+/// bytes, funded by `payer`
+/// 
+/// Lamports sent to the address beforehand are kept, as Anchor's `init`
+/// does, so pre-funding an address cannot block its marker.
+fn create_marker_account<'info>(
+    marker: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    size: usize,
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(size);
+    let lamports = marker.lamports();
+    if lamports == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::CreateAccount { from: payer.clone(), to: marker.clone() },
+                &[seeds],
+            ),
+            rent,
+            size as u64,
+            &crate::ID,
+        )?;
+    } else {
+        if lamports < rent {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    system_program::Transfer { from: payer.clone(), to: marker.clone() },
+                ),
+                rent - lamports,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Allocate { account_to_allocate: marker.clone() },
+                &[seeds],
+            ),
+            size as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Assign { account_to_assign: marker.clone() },
+                &[seeds],
+            ),
+            &crate::ID,
+        )?;
+    }
+    
+    Ok(())
+}
+
+/// Records one commitment's leaf; lives at [b"commitment", commitment]
+/// 
+/// Created with the leaf, so a commitment already in the tree cannot be
+/// inserted again, and wallets can look up a note's leaf index by
+/// commitment without scanning the tree. The depositor, or the relayer or
+/// recipient submitting a spend, pays its rent.
+#[account]
+pub struct CommitmentMarker {
+    pub commitment: [u8; 32],
+    pub leaf_index: u64,
+    pub bump: u8,
+}
+
+impl CommitmentMarker {
+    pub const SIZE: usize = 8 + 32 + 8 + 1;
+    
+    pub fn address(commitment: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"commitment", commitment.as_ref()], &crate::ID)
+    }
+    
+    /// Record `commitment` at `leaf_index` by creating its marker, funded
+    /// by `payer`
+    /// 
+    /// Fails with DuplicateCommitment if the commitment already has a
+    /// leaf, including one inserted earlier in the same instruction.
+    pub fn record<'info>(
+        marker: &AccountInfo<'info>,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        commitment: &[u8; 32],
+        leaf_index: u64,
+    ) -> Result<()> {
+        let (address, bump) = Self::address(commitment);
+        require_keys_eq!(marker.key(), address, WhistleError::InvalidCommitmentMarker);
+        require!(marker.owner != &crate::ID, WhistleError::DuplicateCommitment);
+        
+        let seeds: &[&[u8]] = &[b"commitment", commitment.as_ref(), &[bump]];
+        create_marker_account(marker, payer, system_program, seeds, Self::SIZE)?;
+        
+        let record = CommitmentMarker { commitment: *commitment, leaf_index, bump };
+        record.try_serialize(&mut &mut marker.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+}
+
+/// One entry of the spent-nullifier ledger
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SpentNullifier {
+    pub nullifier: [u8; 32],
+    pub slot: u64,
+    /// Position in spend order
+    pub seq: u64,
+}
+
+// ============================================================================
+// INSTRUCTION CONTEXTS
+// ============================================================================
+
+// Split initialization into separate instructions to avoid stack overflow
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<PoolState>(),
+        seeds = [b"pool"],
+        bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    /// This program's ProgramData, naming the upgrade authority
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority.key()) @ WhistleError::NotUpgradeAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitMerkle<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<MerkleTree>(),
+        seeds = [b"merkle_tree"],
+        bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BeginMigration<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(mut, seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = MigrationState::SIZE,
+        seeds = [b"migration"],
+        bump
+    )]
+    pub migration: Account<'info, MigrationState>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContinueMigration<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(mut, seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        mut,
+        seeds = [b"migration"],
+        bump = migration.bump,
+        constraint = !migration.completed @ WhistleError::NoMigrationInProgress
+    )]
+    pub migration: Account<'info, MigrationState>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeMigration<'info> {
+    #[account(mut, seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(mut, seeds = [b"roots_history"], bump = pool.roots_history_bump)]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"migration"],
+        bump = migration.bump,
+        constraint = !migration.completed @ WhistleError::NoMigrationInProgress
+    )]
+    pub migration: Account<'info, MigrationState>,
+}
+
+#[derive(Accounts)]
+pub struct RebuildRoot<'info> {
+    #[account(mut, seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(mut, seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(mut, seeds = [b"roots_history"], bump = pool.roots_history_bump)]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+}
+
+#[derive(Accounts)]
+pub struct ChallengeTreeRoot<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = challenger,
+        space = TreeDispute::SIZE,
+        seeds = [b"tree_dispute", challenger.key().as_ref()],
+        bump
+    )]
+    pub tree_dispute: Account<'info, TreeDispute>,
+    
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        mut,
+        seeds = [b"tree_dispute", challenger.key().as_ref()],
+        bump = tree_dispute.bump,
+        has_one = challenger,
+        close = challenger
+    )]
+    pub tree_dispute: Account<'info, TreeDispute>,
+    
+    /// CHECK: Receives the rent; pinned by the dispute account
+    #[account(mut)]
+    pub challenger: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub responder: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireTreeDispute<'info> {
+    #[account(
+        mut,
+        seeds = [b"tree_dispute", challenger.key().as_ref()],
+        bump = tree_dispute.bump,
+        has_one = challenger,
+        close = challenger
+    )]
+    pub tree_dispute: Account<'info, TreeDispute>,
+    
+    /// CHECK: Receives the bond and rent; pinned by the dispute account
+    #[account(mut)]
+    pub challenger: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(page_index: u32)]
+pub struct SyncLeafPage<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &page_index.to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(leaf_index: u64, encrypted_data: Vec<u8>)]
+pub struct StoreNote<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = EncryptedNote::space(encrypted_data.len()),
+        seeds = [b"note", leaf_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub note: Account<'info, EncryptedNote>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(leaf_index: u64)]
+pub struct ReadEncryptedNote<'info> {
+    #[account(seeds = [b"note", leaf_index.to_le_bytes().as_ref()], bump = note.bump)]
+    pub note: Account<'info, EncryptedNote>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteEncryptedNote<'info> {
+    #[account(
+        mut,
+        seeds = [b"note", note.leaf_index.to_le_bytes().as_ref()],
+        bump = note.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub note: Account<'info, EncryptedNote>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GenerateMerkleProof<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+}
+
+#[derive(Accounts)]
+#[instruction(page_index: u32)]
+pub struct GetLeavesPage<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"leaf_page", pool.key().as_ref(), &page_index.to_le_bytes()], bump)]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateMerkleTree<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    /// CHECK: Raw v0 or v1 merkle tree, validated by try_load_versioned
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePoolBumps<'info> {
+    #[account(mut, seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateRootsHistory<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    /// CHECK: Roots history of either layout; the handler checks its discriminator and size
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResizeRootsHistory<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(mut, seeds = [b"roots_history"], bump = pool.roots_history_bump)]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitRoots<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = RootsHistory::space(ROOTS_HISTORY_CAPACITY),
+        seeds = [b"roots_history"],
+        bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitNullifiers<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<NullifierSet>(),
+        seeds = [b"nullifiers"],
+        bump
+    )]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitDenominations<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<DenominationConfig>(),
+        seeds = [b"denomination_config"],
+        bump
+    )]
+    pub denomination_config: Account<'info, DenominationConfig>,
+    
+    /// This program's ProgramData, naming the upgrade authority
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(authority.key()) @ WhistleError::NotUpgradeAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitPoolStats<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<PoolStats>(),
+        seeds = [b"pool_stats"],
+        bump
+    )]
+    pub pool_stats: AccountLoader<'info, PoolStats>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitDepositHistogram<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = DepositHistogram::SIZE,
+        seeds = [b"deposit_histogram"],
+        bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitCongestionStats<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = CongestionStats::SIZE,
+        seeds = [b"congestion"],
+        bump
+    )]
+    pub congestion: Account<'info, CongestionStats>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetCongestionInfo<'info> {
+    #[account(
+        seeds = [b"congestion"],
+        bump = congestion.bump
+    )]
+    pub congestion: Account<'info, CongestionStats>,
+}
+
+#[derive(Accounts)]
+pub struct GetDepositHistogram<'info> {
+    #[account(
+        seeds = [b"deposit_histogram"],
+        bump = deposit_histogram.bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+}
+
+#[derive(Accounts)]
+pub struct GetAnonymityMetrics<'info> {
+    #[account(
+        seeds = [b"pool_stats"],
+        bump
+    )]
+    pub pool_stats: AccountLoader<'info, PoolStats>,
+    
+    #[account(
+        seeds = [b"deposit_histogram"],
+        bump = deposit_histogram.bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+}
+
+#[derive(Accounts)]
+pub struct AssertInvariants<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(seeds = [b"roots_history"], bump = pool.roots_history_bump)]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(seeds = [b"nullifiers"], bump = pool.nullifiers_bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    #[account(seeds = [b"vault"], bump = pool.vault_bump)]
+    pub pool_vault: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetSpentNullifiers<'info> {
+    #[account(seeds = [b"nullifiers"], bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+}
+
+#[derive(Accounts)]
+pub struct GetReserveSnapshot<'info> {
+    #[account(
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        seeds = [b"vault"],
+        bump = pool.vault_bump
+    )]
+    pub pool_vault: SystemAccount<'info>,
+    
+    #[account(
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyReserves<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(seeds = [b"vault"], bump = pool.vault_bump)]
+    pub pool_vault: SystemAccount<'info>,
+    
+    #[account(seeds = [b"merkle_tree"], bump = pool.merkle_tree_bump)]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(seeds = [b"nullifiers"], bump = pool.nullifiers_bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+}
+
+#[derive(Accounts)]
+pub struct AttestFinality<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        init_if_needed,
+        payer = attester,
+        space = FinalityAttestation::SIZE,
+        seeds = [b"finality_attestation", pool.key().as_ref()],
+        bump
+    )]
+    pub finality_attestation: Account<'info, FinalityAttestation>,
+    
+    /// This program's ProgramData, at its upgradeable loader address
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = anchor_lang::solana_program::bpf_loader_upgradeable::ID
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    
+    #[account(mut)]
+    pub attester: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetFinalityAttestation<'info> {
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        seeds = [b"finality_attestation", pool.key().as_ref()],
+        bump = finality_attestation.bump
+    )]
+    pub finality_attestation: Account<'info, FinalityAttestation>,
+}
+
+#[derive(Accounts)]
+pub struct ExportStateChunk<'info> {
+    /// CHECK: Pool state PDA, matched to `account_kind` by check_state_account
+    pub account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ImportStateChunk<'info> {
+    /// CHECK: Pool state PDA, matched to `account_kind` by check_state_account
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BatchNullifierStatus<'info> {
+    #[account(seeds = [b"nullifiers"], bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyCommitmentCompatibility {}
+
+#[derive(Accounts)]
+pub struct QueryNullifierHistory<'info> {
+    #[account(seeds = [b"nullifiers"], bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    /// CHECK: Marker of the nullifier; NullifierMarker checks its address
+    pub nullifier_marker: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CheckRoot<'info> {
+    #[account(
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+}
+
+#[derive(Accounts)]
+pub struct Shield<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        mut,
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_stats"],
+        bump
+    )]
+    pub pool_stats: AccountLoader<'info, PoolStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"deposit_histogram"],
+        bump = deposit_histogram.bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+    
+    /// CHECK: Vault PDA for shielded funds
+    #[account(
+        mut,
+        seeds = [b"vault"],
+        bump = pool.vault_bump
+    )]
+    pub pool_vault: SystemAccount<'info>,
+    
+    /// CHECK: Fee vault PDA for protocol fees (point holder rewards)
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: SystemAccount<'info>,
+    
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = 8 + 32 + 8,
+        seeds = [b"deposit_record", depositor.key().as_ref()],
+        bump
+    )]
+    pub deposit_record: Account<'info, DepositRecord>,
+    
+    /// Leaf page receiving the next leaf; the depositor pays its rent when
+    /// the shield opens a new page
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &MerkleTreeLeafPage::page_of(pool.next_index).to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+    
+    /// CHECK: Marker of the shielded commitment, created here; batch shields
+    /// pass their first commitment's here and the rest as remaining
+    /// accounts. CommitmentMarker checks its address
+    #[account(mut)]
+    pub commitment_marker: AccountInfo<'info>,
+}
+
+/// Shield minus the deposit record, funded by a router's PDA
+#[derive(Accounts)]
+pub struct ShieldForwarded<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        mut,
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_stats"],
+        bump
+    )]
+    pub pool_stats: AccountLoader<'info, PoolStats>,
+    
+    #[account(
+        mut,
+        seeds = [b"deposit_histogram"],
+        bump = deposit_histogram.bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+    
+    /// CHECK: Vault PDA for shielded funds
+    #[account(
+        mut,
+        seeds = [b"vault"],
+        bump = pool.vault_bump
+    )]
+    pub pool_vault: SystemAccount<'info>,
+    
+    /// CHECK: Fee vault PDA for protocol fees (point holder rewards)
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: SystemAccount<'info>,
+    
+    /// The router's PDA holding the forwarded lamports
+    #[account(mut)]
+    pub forwarder: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+    
+    /// Leaf page receiving the next leaf; the forwarder pays its rent when
+    /// the shield opens a new page
+    #[account(
+        init_if_needed,
+        payer = forwarder,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &MerkleTreeLeafPage::page_of(pool.next_index).to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+    
+    /// CHECK: Marker of the shielded commitment, created here at the
+    /// forwarder's expense; CommitmentMarker checks its address
+    #[account(mut)]
+    pub commitment_marker: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Unshield<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        seeds = [b"nullifiers"],
+        bump = pool.nullifiers_bump
+    )]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    #[account(
+        mut,
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(
+        seeds = [b"denomination_config"],
+        bump = denomination_config.bump
+    )]
+    pub denomination_config: Account<'info, DenominationConfig>,
+    
+    /// CHECK: Vault PDA for shielded funds
+    #[account(
+        mut,
+        seeds = [b"vault"],
+        bump = pool.vault_bump
+    )]
+    pub pool_vault: SystemAccount<'info>,
+    
+    /// CHECK: Fee vault PDA for protocol fees (point holder rewards)
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: SystemAccount<'info>,
+    
+    /// CHECK: Recipient receives SOL
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+    
+    /// CHECK: Relayer receives fee; omitted for self-relayed withdrawals
+    #[account(mut)]
+    pub relayer: Option<AccountInfo<'info>>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"congestion"],
+        bump = congestion.bump
+    )]
+    pub congestion: Account<'info, CongestionStats>,    
+    /// CHECK: Marker of the nullifier spent, created here; NullifierMarker checks its address
+    #[account(mut)]
+    pub nullifier_marker: AccountInfo<'info>,
+    
+    /// Relayer or recipient submitting the spend; pays the markers' rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    /// CHECK: Marker of the change commitment (of the zero hash without
+    /// change), created here; CommitmentMarker checks its address
+    #[account(mut)]
+    pub change_marker: AccountInfo<'info>,
+}
+
+/// Writes the pool-wide accounts every spend and shield also writes, plus
+/// one nullifier marker per input and one commitment marker per output.
+/// The runtime takes all of a transaction's account locks before running
+/// it, so transfers serialize on these accounts but cannot deadlock,
+/// whatever order they are packed in.
+#[derive(Accounts)]
+pub struct PrivateTransfer<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        mut,
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        seeds = [b"nullifiers"],
+        bump = pool.nullifiers_bump
+    )]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    #[account(
+        mut,
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    /// CHECK: Marker of the first input's nullifier (of the zero hash for an
+    /// unused slot); NullifierMarker checks its address
+    #[account(mut)]
+    pub input_marker_0: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the second input's nullifier, as above
+    #[account(mut)]
+    pub input_marker_1: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the first output commitment (of the zero hash for
+    /// an unused slot); CommitmentMarker checks its address
+    #[account(mut)]
+    pub output_marker_0: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the second output commitment, as above
+    #[account(mut)]
+    pub output_marker_1: AccountInfo<'info>,
+    
+    /// Relayer or owner submitting the transfer; pays the markers' rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// A swap spends and creates up to four notes each: the maker's leg uses
+/// the transfer's markers, the taker's these
+#[derive(Accounts)]
+pub struct DenominationSwap<'info> {
+    pub transfer: PrivateTransfer<'info>,
+    
+    /// CHECK: Marker of the taker's first input nullifier
+    #[account(mut)]
+    pub taker_marker_0: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the taker's second input nullifier
+    #[account(mut)]
+    pub taker_marker_1: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the taker's first output commitment
+    #[account(mut)]
+    pub taker_output_marker_0: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the taker's second output commitment
+    #[account(mut)]
+    pub taker_output_marker_1: AccountInfo<'info>,
+}
+
+/// batch_withdraw_zk spends four notes: the first uses the unshield's
+/// marker, the other three these
+#[derive(Accounts)]
+pub struct BatchWithdrawZk<'info> {
+    pub unshield: Unshield<'info>,
+    
+    /// CHECK: Marker of the second note's nullifier
+    #[account(mut)]
+    pub nullifier_marker_1: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the third note's nullifier
+    #[account(mut)]
+    pub nullifier_marker_2: AccountInfo<'info>,
+    
+    /// CHECK: Marker of the fourth note's nullifier
+    #[account(mut)]
+    pub nullifier_marker_3: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SchnorrWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    
+    #[account(
+        seeds = [b"nullifiers"],
+        bump = pool.nullifiers_bump
+    )]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    /// CHECK: Vault PDA for shielded funds
+    #[account(
+        mut,
+        seeds = [b"vault"],
+        bump = pool.vault_bump
+    )]
+    pub pool_vault: SystemAccount<'info>,
+    
+    /// CHECK: Fee vault PDA for protocol fees (point holder rewards)
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: SystemAccount<'info>,
+    
+    /// CHECK: Recipient receives SOL
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+    
+    /// CHECK: Instructions sysvar, for the Ed25519 precompile check
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"congestion"],
+        bump = congestion.bump
+    )]
+    pub congestion: Account<'info, CongestionStats>,    
+    /// CHECK: Marker of the nullifier spent, created here; NullifierMarker checks its address
+    #[account(mut)]
+    pub nullifier_marker: AccountInfo<'info>,
+    
+    /// Relayer or recipient submitting the spend; pays the marker's rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevealNoteAmount<'info> {
+    #[account(
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+}
+
+// SECURITY FIX: DemoWithdraw context REMOVED - it was a security vulnerability
+// that allowed anyone to drain all funds without proof verification
+
+#[derive(Accounts)]
+pub struct WithdrawZk<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool"],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, PoolState>,
+    
+    #[account(
+        seeds = [b"nullifiers"],
+        bump = pool.nullifiers_bump
+    )]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    // SECURITY FIX: Added roots_history for Merkle root validation
+    #[account(
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
+    )]
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+    
+    #[account(
+        seeds = [b"denomination_config"],
+        bump = denomination_config.bump
+    )]
+    pub denomination_config: Account<'info, DenominationConfig>,
+    
+    /// CHECK: Vault PDA
+    #[account(
+        mut,
+        seeds = [b"vault"],
+        bump = pool.vault_bump
+    )]
+    pub pool_vault: SystemAccount<'info>,
+    
+    /// CHECK: Recipient receives SOL
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+    
+    /// CHECK: Relayer receives fee
+    #[account(mut)]
+    pub relayer: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"congestion"],
+        bump = congestion.bump
+    )]
+    pub congestion: Account<'info, CongestionStats>,    
+    /// CHECK: Marker of the nullifier spent, created here; NullifierMarker checks its address
+    #[account(mut)]
+    pub nullifier_marker: AccountInfo<'info>,
+    
+    /// Relayer or recipient submitting the spend; pays the marker's rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+// Alias for backward compatibility
+pub type Deposit<'info> = Shield<'info>;
+pub type Withdraw<'info> = Unshield<'info>;
+
+#[derive(Accounts)]
+#[instruction(session_id: u64)]
+pub struct StageProofChunk<'info> {
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = 8 + 32 + 8 + 8 + 2 + PROOF_STAGING_CAPACITY + 1,
+        seeds = [b"proof_staging", creator.key().as_ref(), &session_id.to_le_bytes()],
+        bump
+    )]
+    pub staging: Account<'info, ProofStaging>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(session_id: u64)]
+pub struct UnshieldFromStaged<'info> {
+    #[account(
+        mut,
+        seeds = [b"proof_staging", creator.key().as_ref(), &session_id.to_le_bytes()],
+        bump = staging.bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub staging: Account<'info, ProofStaging>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub unshield: Unshield<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterViewKey<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = ViewKeyRecord::SIZE,
+        seeds = [b"view_key", owner.key().as_ref()],
+        bump
+    )]
+    pub view_key_record: Account<'info, ViewKeyRecord>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeViewKey<'info> {
+    #[account(
+        mut,
+        seeds = [b"view_key", owner.key().as_ref()],
+        bump = view_key_record.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub view_key_record: Account<'info, ViewKeyRecord>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyDecryption<'info> {
+    #[account(seeds = [b"view_key", view_key_record.owner.as_ref()], bump = view_key_record.bump)]
+    pub view_key_record: Account<'info, ViewKeyRecord>,
+}
+
+#[derive(Accounts)]
+#[instruction(eth_address: [u8; 20])]
+pub struct RegisterEthAddress<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = EthAddressMapping::SIZE,
+        seeds = [b"eth_address", eth_address.as_ref()],
+        bump
+    )]
+    pub eth_mapping: Account<'info, EthAddressMapping>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    proof_a: [u8; 64],
+    proof_b: [u8; 128],
+    proof_c: [u8; 64],
+    nullifier_hash: [u8; 32],
+    recipient: Pubkey,
+    withdrawal_amount: u64,
+    relayer_fee: u64,
+    merkle_root: [u8; 32],
+    change_commitment: [u8; 32],
+    unlock_slot: u64,
+    eth_signature: [u8; 65],
+    eth_address: [u8; 20],
+)]
+pub struct UnshieldEip712<'info> {
+    #[account(
+        seeds = [b"eth_address", eth_address.as_ref()],
+        bump = eth_mapping.bump
+    )]
+    pub eth_mapping: Account<'info, EthAddressMapping>,
+    
+    pub unshield: Unshield<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UnshieldJubjub<'info> {
+    pub unshield: Unshield<'info>,
+    
+    /// CHECK: Instructions sysvar, for the Ed25519 precompile check
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(session_id: u64)]
+pub struct CloseProofStaging<'info> {
+    #[account(
+        mut,
+        seeds = [b"proof_staging", creator.key().as_ref(), &session_id.to_le_bytes()],
+        bump = staging.bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub staging: Account<'info, ProofStaging>,
+    
+    /// CHECK: Receives the rent; pinned by the staging account
+    #[account(mut)]
+    pub creator: UncheckedAccount<'info>,
+    
+    pub closer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(commitment_hash: [u8; 32])]
+pub struct PreCommitShield<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [b"pre_commit", payer.key().as_ref(), commitment_hash.as_ref()],
+        bump
+    )]
+    pub pre_commit: Account<'info, PreCommit>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealShield<'info> {
+    #[account(
+        mut,
+        seeds = [b"pre_commit", payer.key().as_ref(), pre_commit.commitment_hash.as_ref()],
+        bump = pre_commit.bump,
+        has_one = payer,
+        close = payer
+    )]
+    pub pre_commit: Account<'info, PreCommit>,
     
-    pub fn mark_spent(&mut self, nullifier: &[u8; 32]) -> Result<()> {
-        require!((self.count as usize) < 4096, WhistleError::NullifierSetFull);
-        self.nullifiers[self.count as usize] = *nullifier;
-        self.count += 1;
-        Ok(())
-    }
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub shield: Shield<'info>,
 }
 
-// ============================================================================
-// INSTRUCTION CONTEXTS
-// ============================================================================
+#[derive(Accounts)]
+pub struct ClaimExpiredPreCommit<'info> {
+    #[account(
+        mut,
+        seeds = [b"pre_commit", payer.key().as_ref(), pre_commit.commitment_hash.as_ref()],
+        bump = pre_commit.bump,
+        has_one = payer,
+        close = payer
+    )]
+    pub pre_commit: Account<'info, PreCommit>,
+    
+    /// CHECK: Receives the rent; pinned by the pre-commit account
+    #[account(mut)]
+    pub payer: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub claimer: Signer<'info>,
+}
 
-// Split initialization into separate instructions to avoid stack overflow
 #[derive(Accounts)]
-pub struct InitializePool<'info> {
+pub struct RegisterRelayer<'info> {
     #[account(
-        init,
-        payer = authority,
-        space = 8 + std::mem::size_of::<PoolState>(),
-        seeds = [b"pool"],
+        init_if_needed,
+        payer = relayer,
+        space = 8 + 32 + 8 + 1,
+        seeds = [b"relayer_bond", relayer.key().as_ref()],
         bump
     )]
-    pub pool: Account<'info, PoolState>,
+    pub relayer_bond: Account<'info, RelayerBond>,
     
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub relayer: Signer<'info>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitMerkle<'info> {
-    #[account(seeds = [b"pool"], bump = pool.bump)]
-    pub pool: Account<'info, PoolState>,
-    
+#[instruction(nullifier_hash: [u8; 32])]
+pub struct TakeIntentLock<'info> {
     #[account(
-        init,
-        payer = authority,
-        space = 8 + std::mem::size_of::<MerkleTree>(),
-        seeds = [b"merkle_tree"],
+        init_if_needed,
+        payer = holder,
+        space = IntentLock::SIZE,
+        seeds = [b"intent_lock", nullifier_hash.as_ref()],
         bump
     )]
-    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+    pub intent_lock: Account<'info, IntentLock>,
+    
+    #[account(seeds = [b"nullifiers"], bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    /// CHECK: Marker of the nullifier; NullifierMarker checks its address
+    pub nullifier_marker: AccountInfo<'info>,
     
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub holder: Signer<'info>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitRoots<'info> {
-    #[account(seeds = [b"pool"], bump = pool.bump)]
-    pub pool: Account<'info, PoolState>,
+pub struct ReleaseIntentLock<'info> {
+    #[account(
+        mut,
+        seeds = [b"intent_lock", intent_lock.nullifier_hash.as_ref()],
+        bump = intent_lock.bump,
+        has_one = rent_payer,
+        close = rent_payer
+    )]
+    pub intent_lock: Account<'info, IntentLock>,
+    
+    /// CHECK: Receives the rent; pinned by the lock account
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
     
+    pub closer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(nullifier_hash: [u8; 32])]
+pub struct ApproveRelayerWithdrawal<'info> {
     #[account(
         init,
-        payer = authority,
-        space = 8 + std::mem::size_of::<RootsHistory>(),
-        seeds = [b"roots_history"],
+        payer = user,
+        space = 8 + 32 + 32 + 32 + 8 + 1,
+        seeds = [b"pending_withdrawal", nullifier_hash.as_ref()],
         bump
     )]
-    pub roots_history: AccountLoader<'info, RootsHistory>,
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    
+    #[account(
+        seeds = [b"relayer_bond", relayer.key().as_ref()],
+        bump = relayer_bond.bump
+    )]
+    pub relayer_bond: Account<'info, RelayerBond>,
+    
+    #[account(seeds = [b"nullifiers"], bump)]
+    pub nullifiers: AccountLoader<'info, NullifierSet>,
+    
+    /// CHECK: Marker of the nullifier; NullifierMarker checks its address
+    pub nullifier_marker: AccountInfo<'info>,
+    
+    pub relayer: Signer<'info>,
     
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub user: Signer<'info>,
     
     pub system_program: Program<'info, System>,
+    
+    #[account(
+        mut,
+        seeds = [b"congestion"],
+        bump = congestion.bump
+    )]
+    pub congestion: Account<'info, CongestionStats>,
 }
 
 #[derive(Accounts)]
-pub struct InitNullifiers<'info> {
-    #[account(seeds = [b"pool"], bump = pool.bump)]
-    pub pool: Account<'info, PoolState>,
+#[instruction(relayer_pubkey: Pubkey)]
+pub struct ProveRelayerInactivity<'info> {
+    #[account(
+        mut,
+        seeds = [b"pending_withdrawal", pending_withdrawal.nullifier_hash.as_ref()],
+        bump = pending_withdrawal.bump,
+        has_one = user,
+        constraint = pending_withdrawal.relayer == relayer_pubkey @ WhistleError::InvalidRelayerBond,
+        close = user
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
     
     #[account(
-        init,
-        payer = authority,
-        space = 8 + std::mem::size_of::<NullifierSet>(),
-        seeds = [b"nullifiers"],
-        bump
+        mut,
+        seeds = [b"relayer_bond", relayer_pubkey.as_ref()],
+        bump = relayer_bond.bump
     )]
+    pub relayer_bond: Account<'info, RelayerBond>,
+    
+    #[account(seeds = [b"nullifiers"], bump)]
     pub nullifiers: AccountLoader<'info, NullifierSet>,
     
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    /// CHECK: Marker of the nullifier; NullifierMarker checks its address
+    pub nullifier_marker: AccountInfo<'info>,
     
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(nft_mint: Pubkey)]
+pub struct OpenAuction<'info> {
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + 32 * 4 + 8 + 8 + 1 + 4 + 32 + 8 + 32 + 32 + 1 + 1,
+        seeds = [b"auction", nft_mint.as_ref()],
+        bump
+    )]
+    pub auction: Account<'info, AuctionState>,
+
+    /// CHECK: Seller's token account for the NFT; the token program checks it
+    #[account(mut)]
+    pub seller_nft_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token account for the NFT owned by the auction PDA; checked in the handler
+    #[account(mut)]
+    pub nft_escrow: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::id())]
+    pub token_program: UncheckedAccount<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Shield<'info> {
+#[instruction(bid_commitment: [u8; 32])]
+pub struct SubmitBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.nft_mint.as_ref()],
+        bump = auction.bump
+    )]
+    pub auction: Account<'info, AuctionState>,
+
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + 32 + 32 + 32 + 8 + 1,
+        seeds = [b"auction_bid", auction.key().as_ref(), bid_commitment.as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, AuctionBid>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(seeds = [b"pool"], bump = pool.bump)]
+    pub pool: Account<'info, PoolState>,
+
+    /// The bidder's deposit record; the escrow counts against their cap
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + 32 + 8,
+        seeds = [b"deposit_record", bidder.key().as_ref()],
+        bump
+    )]
+    pub deposit_record: Account<'info, DepositRecord>,
+}
+
+#[derive(Accounts)]
+pub struct RevealReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.nft_mint.as_ref()],
+        bump = auction.bump,
+        has_one = seller
+    )]
+    pub auction: Account<'info, AuctionState>,
+
+    pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBid<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.nft_mint.as_ref()],
+        bump = auction.bump,
+        constraint = !auction.finalized @ WhistleError::AuctionAlreadyFinalized
+    )]
+    pub auction: Account<'info, AuctionState>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_bid", auction.key().as_ref(), bid.bid_commitment.as_ref()],
+        bump = bid.bump,
+        has_one = auction,
+        has_one = bidder
+    )]
+    pub bid: Account<'info, AuctionBid>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
     #[account(
         mut,
         seeds = [b"pool"],
         bump = pool.bump
     )]
     pub pool: Account<'info, PoolState>,
-    
+
     #[account(
         mut,
         seeds = [b"merkle_tree"],
-        bump
+        bump = pool.merkle_tree_bump
     )]
     pub merkle_tree: AccountLoader<'info, MerkleTree>,
-    
+
     #[account(
         mut,
         seeds = [b"roots_history"],
-        bump
+        bump = pool.roots_history_bump
     )]
     pub roots_history: AccountLoader<'info, RootsHistory>,
-    
+
+    #[account(
+        mut,
+        seeds = [b"pool_stats"],
+        bump
+    )]
+    pub pool_stats: AccountLoader<'info, PoolStats>,
+
     /// CHECK: Vault PDA for shielded funds
     #[account(
         mut,
         seeds = [b"vault"],
-        bump
+        bump = pool.vault_bump
     )]
     pub pool_vault: SystemAccount<'info>,
-    
+
     /// CHECK: Fee vault PDA for protocol fees (point holder rewards)
     #[account(
         mut,
@@ -1110,51 +6740,100 @@ pub struct Shield<'info> {
         bump
     )]
     pub fee_vault: SystemAccount<'info>,
-    
-    #[account(mut)]
-    pub depositor: Signer<'info>,
-    
+
+    #[account(
+        mut,
+        seeds = [b"deposit_histogram"],
+        bump = deposit_histogram.bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+
     pub system_program: Program<'info, System>,
+
+    /// Leaf page receiving the refund note; the bidder pays its rent when
+    /// the refund opens a new page
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &MerkleTreeLeafPage::page_of(pool.next_index).to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+
+    /// CHECK: Marker of the refund note's commitment, created on a reclaim;
+    /// CommitmentMarker checks its address
+    #[account(mut)]
+    pub commitment_marker: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct Unshield<'info> {
+pub struct FinalizeAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", auction.nft_mint.as_ref()],
+        bump = auction.bump,
+        has_one = seller,
+        has_one = nft_escrow
+    )]
+    pub auction: Account<'info, AuctionState>,
+
+    /// Winning bid account; omit if no bid was opened
+    #[account(mut)]
+    pub leading_bid: Option<Account<'info, AuctionBid>>,
+
+    /// CHECK: Receives the winning bid account's rent; checked against the bid
+    #[account(mut)]
+    pub leading_bidder: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Receives the winning bid
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: NFT escrow, pinned by the auction
+    #[account(mut)]
+    pub nft_escrow: UncheckedAccount<'info>,
+
+    /// CHECK: Winner's (or seller's) token account for the NFT; checked in the handler
+    #[account(mut)]
+    pub nft_destination: UncheckedAccount<'info>,
+
     #[account(
         mut,
         seeds = [b"pool"],
         bump = pool.bump
     )]
     pub pool: Account<'info, PoolState>,
-    
+
     #[account(
         mut,
         seeds = [b"merkle_tree"],
-        bump
+        bump = pool.merkle_tree_bump
     )]
     pub merkle_tree: AccountLoader<'info, MerkleTree>,
-    
+
     #[account(
         mut,
-        seeds = [b"nullifiers"],
-        bump
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
     )]
-    pub nullifiers: AccountLoader<'info, NullifierSet>,
-    
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+
     #[account(
         mut,
-        seeds = [b"roots_history"],
+        seeds = [b"pool_stats"],
         bump
     )]
-    pub roots_history: AccountLoader<'info, RootsHistory>,
-    
+    pub pool_stats: AccountLoader<'info, PoolStats>,
+
     /// CHECK: Vault PDA for shielded funds
     #[account(
         mut,
         seeds = [b"vault"],
-        bump
+        bump = pool.vault_bump
     )]
     pub pool_vault: SystemAccount<'info>,
-    
+
     /// CHECK: Fee vault PDA for protocol fees (point holder rewards)
     #[account(
         mut,
@@ -1162,97 +6841,180 @@ pub struct Unshield<'info> {
         bump
     )]
     pub fee_vault: SystemAccount<'info>,
-    
-    /// CHECK: Recipient receives SOL
-    #[account(mut)]
-    pub recipient: AccountInfo<'info>,
-    
-    /// CHECK: Relayer receives fee
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::id())]
+    pub token_program: UncheckedAccount<'info>,
+
+    /// Anyone finalizing; pays the rent of the winner's change note
     #[account(mut)]
-    pub relayer: AccountInfo<'info>,
-    
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"deposit_histogram"],
+        bump = deposit_histogram.bump
+    )]
+    pub deposit_histogram: Account<'info, DepositHistogram>,
+
     pub system_program: Program<'info, System>,
+
+    /// Leaf page receiving the refund note; the payer pays its rent when
+    /// the refund opens a new page
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &MerkleTreeLeafPage::page_of(pool.next_index).to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+
+    /// CHECK: Marker of the refund note's commitment, created when the
+    /// winner has change; CommitmentMarker checks its address
+    #[account(mut)]
+    pub commitment_marker: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct PrivateTransfer<'info> {
+pub struct ShieldToken<'info> {
     #[account(
         mut,
         seeds = [b"pool"],
         bump = pool.bump
     )]
     pub pool: Account<'info, PoolState>,
-    
+
     #[account(
         mut,
         seeds = [b"merkle_tree"],
-        bump
+        bump = pool.merkle_tree_bump
     )]
     pub merkle_tree: AccountLoader<'info, MerkleTree>,
-    
+
     #[account(
         mut,
-        seeds = [b"nullifiers"],
-        bump
+        seeds = [b"roots_history"],
+        bump = pool.roots_history_bump
     )]
-    pub nullifiers: AccountLoader<'info, NullifierSet>,
-    
+    pub roots_history: AccountLoader<'info, RootsHistory>,
+
+    /// CHECK: Mint of the shielded token; unpacked in the handler
+    #[account(owner = spl_token::id())]
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: The mint's vault, a token account owned by itself; created by the first shield
     #[account(
         mut,
-        seeds = [b"roots_history"],
+        seeds = [b"token_vault", mint.key().as_ref()],
         bump
     )]
-    pub roots_history: AccountLoader<'info, RootsHistory>,
-}
+    pub token_vault: UncheckedAccount<'info>,
 
-// SECURITY FIX: DemoWithdraw context REMOVED - it was a security vulnerability
-// that allowed anyone to drain all funds without proof verification
+    /// CHECK: Depositor's token account for the mint; checked in the handler
+    #[account(mut)]
+    pub depositor_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::id())]
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Leaf page receiving the next leaf; the depositor pays its rent when
+    /// the shield opens a new page
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &MerkleTreeLeafPage::page_of(pool.next_index).to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+
+    /// CHECK: Marker of the shielded commitment, created here; CommitmentMarker checks its address
+    #[account(mut)]
+    pub commitment_marker: AccountInfo<'info>,
+}
 
 #[derive(Accounts)]
-pub struct WithdrawZk<'info> {
+pub struct UnshieldToken<'info> {
     #[account(
         mut,
         seeds = [b"pool"],
         bump = pool.bump
     )]
     pub pool: Account<'info, PoolState>,
-    
+
     #[account(
         mut,
+        seeds = [b"merkle_tree"],
+        bump = pool.merkle_tree_bump
+    )]
+    pub merkle_tree: AccountLoader<'info, MerkleTree>,
+
+    #[account(
         seeds = [b"nullifiers"],
-        bump
+        bump = pool.nullifiers_bump
     )]
     pub nullifiers: AccountLoader<'info, NullifierSet>,
-    
-    // SECURITY FIX: Added roots_history for Merkle root validation
+
     #[account(
+        mut,
         seeds = [b"roots_history"],
-        bump
+        bump = pool.roots_history_bump
     )]
     pub roots_history: AccountLoader<'info, RootsHistory>,
-    
-    /// CHECK: Vault PDA
+
+    /// CHECK: Mint of the note; unpacked in the handler and bound by the proof
+    #[account(owner = spl_token::id())]
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: The mint's vault; checked in the handler
     #[account(
         mut,
-        seeds = [b"vault"],
+        seeds = [b"token_vault", mint.key().as_ref()],
         bump
     )]
-    pub pool_vault: SystemAccount<'info>,
-    
-    /// CHECK: Recipient receives SOL
-    #[account(mut)]
-    pub recipient: AccountInfo<'info>,
-    
-    /// CHECK: Relayer receives fee
+    pub token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Recipient's token account for the mint; checked in the handler
     #[account(mut)]
-    pub relayer: AccountInfo<'info>,
-    
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: SPL Token program
+    #[account(address = spl_token::id())]
+    pub token_program: UncheckedAccount<'info>,
+
     pub system_program: Program<'info, System>,
-}
 
-// Alias for backward compatibility
-pub type Deposit<'info> = Shield<'info>;
-pub type Withdraw<'info> = Unshield<'info>;
+    /// CHECK: Marker of the nullifier spent, created here; NullifierMarker checks its address
+    #[account(mut)]
+    pub nullifier_marker: AccountInfo<'info>,
+
+    /// Recipient or anyone submitting the spend for them; pays the markers'
+    /// rent, and the leaf page's when the change note opens a new page
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Leaf page receiving the change note
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + std::mem::size_of::<MerkleTreeLeafPage>(),
+        seeds = [b"leaf_page", pool.key().as_ref(), &MerkleTreeLeafPage::page_of(pool.next_index).to_le_bytes()],
+        bump
+    )]
+    pub leaf_page: AccountLoader<'info, MerkleTreeLeafPage>,
+
+    /// CHECK: Marker of the change commitment, created when there is
+    /// change; CommitmentMarker checks its address
+    #[account(mut)]
+    pub change_marker: AccountInfo<'info>,
+}
 
 // ============================================================================
 // EVENTS
@@ -1262,6 +7024,7 @@ pub type Withdraw<'info> = Unshield<'info>;
 pub struct PoolInitialized {
     pub pool: Pubkey,
     pub merkle_levels: u8,
+    pub slot: u64,
     pub timestamp: i64,
 }
 
@@ -1271,15 +7034,37 @@ pub struct Shielded {
     pub leaf_index: u64,
     pub amount: u64,
     pub protocol_fee: u64,
+    pub slot: u64,
     pub timestamp: i64,
 }
 
+/// next_index reached `threshold_bps` of the tree's capacity; `remaining`
+/// leaves are left before shields and change notes fail with TreeFull
+#[event]
+pub struct CapacityWarning {
+    pub threshold_bps: u16,
+    pub next_index: u64,
+    pub capacity: u64,
+    pub remaining: u64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct HistogramUpdated {
+    pub bucket_index: u8,
+}
+
 #[event]
 pub struct Unshielded {
     pub nullifier_hash: [u8; 32],
     pub withdrawal_amount: u64,
     pub protocol_fee: u64,
     pub has_change: bool,
+    pub recipient_is_pda: bool,
+    pub self_relayed: bool,
+    /// receipt_hash over what the recipient received at `slot`
+    pub receipt_hash: [u8; 32],
+    pub slot: u64,
     pub timestamp: i64,
 }
 
@@ -1288,6 +7073,25 @@ pub struct WithdrawnZk {
     pub nullifier_hash: [u8; 32],
     pub commitment: [u8; 32],
     pub amount: u64,
+    pub recipient_is_pda: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchWithdrawn {
+    pub nullifier_hashes: [[u8; 32]; 4],
+    pub amounts: [u64; 4],
+    pub protocol_fee: u64,
+    pub recipient_is_pda: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchUnshielded {
+    pub count: u8,
+    pub total_withdrawn: u64,
     pub timestamp: i64,
 }
 
@@ -1295,6 +7099,19 @@ pub struct WithdrawnZk {
 pub struct ChangeCreated {
     pub commitment: [u8; 32],
     pub leaf_index: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/// A batch_shield or shield_batch_zk batch; the commitments of leaves
+/// first_leaf_index..=last_leaf_index are in the instruction data
+#[event]
+pub struct BatchShielded {
+    pub count: u8,
+    /// Net amount the notes hold, after protocol fees
+    pub total_amount: u64,
+    pub first_leaf_index: u64,
+    pub last_leaf_index: u64,
     pub timestamp: i64,
 }
 
@@ -1302,6 +7119,7 @@ pub struct ChangeCreated {
 pub struct NoteCreated {
     pub commitment: [u8; 32],
     pub leaf_index: u64,
+    pub slot: u64,
     pub timestamp: i64,
 }
 
@@ -1309,6 +7127,216 @@ pub struct NoteCreated {
 pub struct PrivateTransferCompleted {
     pub nullifiers_spent: u8,
     pub notes_created: u8,
+    pub slot: u64,
+    pub timestamp: i64,
+    /// Input nullifiers marked spent; a zero entry is an unused input slot.
+    /// Appended last so readers of the earlier fields are unaffected.
+    pub nullifier_hashes: [[u8; 32]; 2],
+}
+
+#[event]
+pub struct DenominationSwapped {
+    pub from_commitment: [u8; 32],
+    pub from_denomination: u64,
+    pub to_commitment: [u8; 32],
+    pub to_denomination: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SchnorrWithdrawn {
+    pub nullifier_hash: [u8; 32],
+    pub commitment: [u8; 32],
+    pub amount: u64,
+    pub protocol_fee: u64,
+    pub recipient_is_pda: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NoteAmountRevealed {
+    pub commitment: [u8; 32],
+    pub amount: u64,
+    pub auditor_pubkey: [u8; 32],
+    pub slot: u64,
+}
+
+#[event]
+pub struct MerkleTreeMigrated {
+    pub from_version: u8,
+    pub to_version: u8,
+    pub levels_used: u8,
+}
+
+#[event]
+pub struct PoolMigrated {
+    pub old_levels: u8,
+    pub new_levels: u8,
+    pub leaf_count: u64,
+    pub root: [u8; 32],
+}
+
+#[event]
+pub struct TreeRootRebuilt {
+    pub root: [u8; 32],
+    pub leaf_count: u64,
+}
+
+#[event]
+pub struct TreeRootChallenged {
+    pub challenger: Pubkey,
+    pub claimed_root: [u8; 32],
+    pub disputed_root: [u8; 32],
+    pub range_start: u64,
+    pub bond: u64,
+}
+
+#[event]
+pub struct TreeDisputeResolved {
+    pub challenger: Pubkey,
+    pub responder: Pubkey,
+    pub range_start: u64,
+    pub bond: u64,
+}
+
+#[event]
+pub struct TreeDisputeUpheld {
+    pub challenger: Pubkey,
+    pub claimed_root: [u8; 32],
+    pub disputed_root: [u8; 32],
+    pub range_start: u64,
+}
+
+#[event]
+pub struct DevnetPoolSeeded {
+    pub slot: u64,
+    pub seed: u64,
+    pub first_leaf_index: u64,
+    pub count: u8,
+    pub total_amount: u64,
+}
+
+#[event]
+pub struct PreCommitted {
+    pub payer: Pubkey,
+    pub commitment_hash: [u8; 32],
+    pub deposit_slot: u64,
+}
+
+#[event]
+pub struct PreCommitClaimed {
+    pub payer: Pubkey,
+    pub commitment_hash: [u8; 32],
+    pub claimer: Pubkey,
+}
+
+#[event]
+pub struct EthAddressRegistered {
+    pub eth_address: [u8; 20],
+    pub authorized: Pubkey,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct ViewKeyRegistered {
+    pub owner: Pubkey,
+    pub view_key: [u8; 32],
+    pub registered_at: i64,
+}
+
+#[event]
+pub struct ViewKeyRevoked {
+    pub owner: Pubkey,
+    pub view_key: [u8; 32],
+    pub revoked_at: i64,
+}
+
+#[event]
+pub struct RelayerBonded {
+    pub relayer: Pubkey,
+    pub bond: u64,
+}
+
+#[event]
+pub struct RelayerSlashed {
+    pub relayer: Pubkey,
+    pub reason: String,
+    pub slash_amount: u64,
+}
+
+#[event]
+pub struct FinalityAttested {
+    pub immutable: bool,
+    pub immutable_since_slot: u64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct ReservesVerified {
+    pub is_solvent: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionOpened {
+    pub auction: Pubkey,
+    pub nft_mint: Pubkey,
+    pub deadline_slot: u64,
+}
+
+#[event]
+pub struct BidSubmitted {
+    pub auction: Pubkey,
+    pub bid_commitment: [u8; 32],
+    pub max_bid: u64,
+}
+
+#[event]
+pub struct ReserveRevealed {
+    pub auction: Pubkey,
+    pub reserve: u64,
+}
+
+#[event]
+pub struct BidOpened {
+    pub auction: Pubkey,
+    pub bid: Pubkey,
+    pub bid_amount: u64,
+}
+
+#[event]
+pub struct BidReclaimed {
+    pub auction: Pubkey,
+    pub bid: Pubkey,
+}
+
+#[event]
+pub struct AuctionFinalized {
+    pub auction: Pubkey,
+    pub winning_amount: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokenShielded {
+    pub mint: Pubkey,
+    pub commitment: [u8; 32],
+    pub leaf_index: u64,
+    pub amount: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokenUnshielded {
+    pub mint: Pubkey,
+    pub nullifier_hash: [u8; 32],
+    pub amount: u64,
+    pub has_change: bool,
+    pub slot: u64,
     pub timestamp: i64,
 }
 
@@ -1339,9 +7367,10 @@ pub enum WhistleError {
     #[msg("Invalid ZK proof")]
     InvalidProof,
     
-    #[msg("Relayer fee too high (max 10%)")]
+    #[msg("Relayer fee exceeds the cap for this denomination")]
     FeeTooHigh,
     
+    // No longer raised: spends create NullifierMarker accounts instead
     #[msg("Nullifier set is full")]
     NullifierSetFull,
     
@@ -1365,4 +7394,277 @@ pub enum WhistleError {
     
     #[msg("Arithmetic overflow or underflow")]
     ArithmeticOverflow,
+    
+    #[msg("Relayer fee cap exceeds the 10% ceiling")]
+    InvalidFeeCap,
+    
+    #[msg("Commitment not found in Merkle tree")]
+    CommitmentNotFound,
+    
+    #[msg("Verification key for this circuit has not been generated")]
+    VerifyingKeyNotGenerated,
+    
+    #[msg("Commitment curve not supported by this pool")]
+    UnsupportedCurve,
+    
+    #[msg("Invalid commitment")]
+    InvalidCommitment,
+    
+    #[msg("Recipient account does not match recipient argument")]
+    InvalidRecipient,
+    
+    #[msg("Invalid merkle tree account data")]
+    InvalidMerkleTreeAccount,
+    
+    #[msg("Unsupported merkle tree account version")]
+    UnsupportedMerkleTreeVersion,
+    
+    #[msg("Merkle tree account is already at the current version")]
+    MerkleTreeAlreadyMigrated,
+    
+    #[msg("Merkle root must not be zero")]
+    ZeroMerkleRoot,
+    
+    #[msg("Auction deadline must be in the future")]
+    InvalidAuctionDeadline,
+    
+    #[msg("Invalid token account for this auction")]
+    InvalidTokenAccount,
+    
+    #[msg("Auction is closed for bids")]
+    AuctionClosed,
+    
+    #[msg("Auction has not reached its deadline")]
+    AuctionNotEnded,
+    
+    #[msg("Reserve already revealed")]
+    ReserveAlreadyRevealed,
+    
+    #[msg("Reserve not revealed yet")]
+    ReserveNotRevealed,
+    
+    #[msg("Commitment opening does not match")]
+    CommitmentOpeningMismatch,
+    
+    #[msg("Settle window has closed")]
+    SettleWindowClosed,
+    
+    #[msg("Settle window is still open")]
+    SettleWindowOpen,
+    
+    #[msg("Bid exceeds escrowed amount")]
+    BidExceedsEscrow,
+    
+    #[msg("Bid is below the reserve")]
+    BidBelowReserve,
+    
+    #[msg("Bid does not beat the leading bid")]
+    BidNotHighest,
+    
+    #[msg("Leading bid cannot be reclaimed")]
+    LeadingBidCannotReclaim,
+    
+    #[msg("Auction already finalized")]
+    AuctionAlreadyFinalized,
+    
+    #[msg("Invalid auction bid account")]
+    InvalidAuctionBid,
+    
+    #[msg("Proof chunk exceeds the staging limits")]
+    ProofChunkTooLarge,
+    
+    #[msg("Proof chunk must start where the staged bytes end")]
+    InvalidProofChunkOffset,
+    
+    #[msg("Staged payload is malformed")]
+    InvalidStagedPayload,
+    
+    #[msg("Proof staging session has expired")]
+    ProofStagingExpired,
+    
+    #[msg("Proof staging session has not expired")]
+    ProofStagingNotExpired,
+    
+    #[msg("No verification key registered for this denomination")]
+    NoVkForDenomination,
+    
+    #[msg("Only available in insecure-devnet builds")]
+    DevnetOnly,
+    
+    #[msg("Invalid number of notes to seed")]
+    InvalidSeedCount,
+    
+    #[msg("Schnorr withdrawals are disabled for this pool")]
+    SchnorrPathDisabled,
+    
+    #[msg("Missing or mismatched Ed25519 signature instruction")]
+    InvalidSchnorrSignature,
+    
+    #[msg("Nullifier hash does not match the note commitment")]
+    InvalidSchnorrNullifier,
+    
+    #[msg("Page size exceeds the maximum")]
+    InvalidPageSize,
+    
+    #[msg("Only available in test-harness builds")]
+    TestHarnessOnly,
+    
+    #[msg("Commitment or nullifier hash is not a canonical field element")]
+    NonCanonicalFieldElement,
+    
+    #[msg("Pre-commit deposit slot is in the future or too old")]
+    InvalidPreCommitSlot,
+    
+    #[msg("Commitment does not match the pre-commit hash")]
+    PreCommitHashMismatch,
+    
+    #[msg("Pre-commit reveal window has passed")]
+    PreCommitExpired,
+    
+    #[msg("Pre-commit reveal window is still open")]
+    PreCommitNotExpired,
+    
+    #[msg("Change commitment is derived from the spent nullifier hash")]
+    WeakChangeCommitment,
+    
+    #[msg("Self-relayed withdrawals take no relayer account or relayer fee")]
+    SelfRelayedWithRelayer,
+    
+    #[msg("A relayer fee needs a relayer account")]
+    MissingRelayer,
+    
+    #[msg("Shield exceeds the depositor's total deposit cap")]
+    DepositCapExceeded,
+    
+    #[msg("Shield exceeds the pool's total shielded cap")]
+    PoolShieldedCapExceeded,
+    
+    #[msg("Account is not the pool state account of this kind")]
+    InvalidStateAccount,
+    
+    #[msg("State chunk does not fit in the account")]
+    InvalidStateChunk,
+    
+    #[msg("Relayer bond is below the slash amount")]
+    RelayerBondTooLow,
+    
+    #[msg("Relayer bond does not belong to the approved relayer")]
+    InvalidRelayerBond,
+    
+    #[msg("Relayer execution timeout has not passed")]
+    RelayerNotInactive,
+    
+    #[msg("Merkle tree root does not match pool state; run rebuild_root")]
+    TreeStateDesync,
+    
+    #[msg("Invalid rebuild_root node range")]
+    InvalidRebuildRange,
+    
+    #[msg("SPL denomination is not a whole number of token units")]
+    InvalidSplDenomination,
+    
+    #[msg("Leaf page is beyond the tree's capacity")]
+    InvalidLeafPage,
+
+    #[msg("Note is time-locked until a later slot")]
+    NoteStillLocked,
+
+    #[msg("Swap denominations must be two different withdrawal denominations")]
+    InvalidSwapIntent,
+
+    #[msg("Swap leg does not create the intent's commitment")]
+    SwapLegMismatch,
+
+    #[msg("Dispute bond is below the minimum")]
+    DisputeBondTooLow,
+
+    #[msg("Disputed range must start at a multiple of DISPUTE_RANGE_LEAVES below the leaf count")]
+    InvalidDisputeRange,
+
+    #[msg("Dispute claims the root the pool already publishes")]
+    DisputeClaimsPublishedRoot,
+
+    #[msg("Dispute response window has closed")]
+    DisputeResponseClosed,
+
+    #[msg("Dispute response window is still open")]
+    DisputeResponseOpen,
+
+    #[msg("Disputed range does not hash to the disputed root")]
+    DisputedRangeMismatch,
+
+    #[msg("Pool already stores its PDA bumps")]
+    PoolBumpsAlreadyStored,
+
+    #[msg("Another node holds the intent lock for this nullifier")]
+    IntentLocked,
+
+    #[msg("Intent lock is held and has not lapsed")]
+    IntentLockHeld,
+
+    #[msg("Forwarded shields are disabled while a per-address deposit cap is set")]
+    ForwardedShieldWithAddressCap,
+
+    #[msg("Ethereum signature does not recover to the given address")]
+    InvalidEthSignature,
+
+    #[msg("Recipient is not the account the Ethereum address is mapped to")]
+    EthAddressNotAuthorized,
+
+    #[msg("Account is not the nullifier marker of this nullifier hash")]
+    InvalidNullifierMarker,
+
+    #[msg("Nullifier hash is zero, the unused-input sentinel")]
+    ZeroNullifierHash,
+
+    #[msg("Commitment is zero, the empty-leaf value")]
+    ZeroCommitment,
+
+    #[msg("Token account does not hold this note's mint")]
+    MintMismatch,
+
+    #[msg("No note has been inserted into the tree yet")]
+    TreeEmpty,
+
+    #[msg("Batch needs 1 to MAX_BATCH_SHIELD commitments and one amount per commitment")]
+    InvalidBatchSize,
+
+    #[msg("Commitment is already in the tree or repeated in the batch")]
+    DuplicateCommitment,
+
+    #[msg("Merkle root is older than MAX_ROOT_AGE_SLOTS; prove against the current root")]
+    StaleMerkleRoot,
+
+    #[msg("Roots history account already has the current layout")]
+    RootsHistoryAlreadyMigrated,
+
+    #[msg("Roots history capacity must grow, by at most MAX_ROOTS_HISTORY_GROWTH, up to MAX_ROOTS_HISTORY_CAPACITY")]
+    InvalidRootsHistoryCapacity,
+
+    #[msg("No leaf has been inserted at this index")]
+    LeafNotInserted,
+
+    #[msg("Encrypted note must be 1 to MAX_NOTE_CIPHERTEXT bytes")]
+    InvalidNoteCiphertext,
+    
+    #[msg("The tree is being migrated to a deeper one; finalize_migration ends it")]
+    PoolMigrationInProgress,
+    
+    #[msg("Only a full tree can migrate to a deeper one")]
+    TreeNotFull,
+    
+    #[msg("Migration batch must be 1 to MAX_REBUILD_NODES")]
+    InvalidMigrationBatch,
+    
+    #[msg("No pool migration is in progress")]
+    NoMigrationInProgress,
+    
+    #[msg("Migration still has leaves to copy or nodes to rebuild")]
+    MigrationIncomplete,
+    
+    #[msg("Commitment marker account is not the commitment's marker address")]
+    InvalidCommitmentMarker,
+    
+    #[msg("Signer is not the program's upgrade authority")]
+    NotUpgradeAuthority,
 }
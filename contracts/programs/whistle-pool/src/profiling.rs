@@ -0,0 +1,91 @@
+// WHISTLE PROTOCOL - INSTRUCTION PROFILING
+//
+// Devnet builds only (`profiling` feature). Hot paths wrap their expensive
+// sections in profile_section!, which charges the compute units the section
+// consumed (the drop in sol_remaining_compute_units) to a section tag, and
+// profile_emit! logs the totals as one Profile event at the end of the
+// instruction. profiling_disabled.rs swaps in macros that expand to
+// nothing, so other builds carry no instrumentation.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::compute_units::sol_remaining_compute_units;
+
+/// Groth16 proof verification
+pub const PROOF_VERIFY: u8 = 1;
+/// Merkle tree inserts, root recomputation and roots history updates
+pub const TREE_INSERT: u8 = 2;
+/// Lamport transfers out of or into the vaults
+pub const TRANSFERS: u8 = 3;
+
+/// Distinct section tags an instruction can report
+pub const PROFILE_SECTIONS: usize = 3;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SectionCost {
+    /// Section tag (PROOF_VERIFY, TREE_INSERT, TRANSFERS); zero marks an unused slot
+    pub tag: u8,
+    /// Compute units consumed inside the section, summed over its entries
+    pub cu: u64,
+}
+
+/// Per-section compute costs of one shield, unshield or private_transfer
+#[event]
+pub struct Profile {
+    /// Sections in the order they were first entered
+    pub section_costs: [SectionCost; PROFILE_SECTIONS],
+    /// sol_remaining_compute_units when the event was emitted, so the
+    /// transaction's total can be reconciled with the runtime's count
+    pub remaining_cu: u64,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    section_costs: [SectionCost; PROFILE_SECTIONS],
+}
+
+impl Profiler {
+    /// Meter reading to pass back to `record` when the section ends
+    pub fn start(&self) -> u64 {
+        sol_remaining_compute_units()
+    }
+
+    /// Charge the units consumed since `start` to `tag`
+    pub fn record(&mut self, tag: u8, start: u64) {
+        let cu = start.saturating_sub(sol_remaining_compute_units());
+        if let Some(slot) = self.section_costs.iter_mut().find(|slot| slot.tag == tag || slot.tag == 0) {
+            slot.tag = tag;
+            slot.cu += cu;
+        }
+    }
+
+    pub fn emit(&self) {
+        emit!(Profile {
+            section_costs: self.section_costs,
+            remaining_cu: sol_remaining_compute_units(),
+        });
+    }
+}
+
+/// Start profiling the current instruction into `$profiler`
+macro_rules! profile_begin {
+    ($profiler:ident) => {
+        let mut $profiler = $crate::profiling::Profiler::default();
+    };
+}
+
+/// Evaluate `$body`, charging its compute units to section `$tag`
+macro_rules! profile_section {
+    ($profiler:ident, $tag:ident, $body:expr) => {{
+        let start = $profiler.start();
+        let result = $body;
+        $profiler.record($crate::profiling::$tag, start);
+        result
+    }};
+}
+
+/// Emit the Profile event for `$profiler`
+macro_rules! profile_emit {
+    ($profiler:ident) => {
+        $profiler.emit();
+    };
+}
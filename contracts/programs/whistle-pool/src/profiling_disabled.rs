@@ -0,0 +1,19 @@
+// WHISTLE PROTOCOL - INSTRUCTION PROFILING (DISABLED)
+//
+// Stand-in for profiling.rs when the `profiling` feature is off: the
+// macros expand to nothing (profile_section! to its body alone), so
+// instrumented code compiles exactly as if it were not instrumented.
+
+macro_rules! profile_begin {
+    ($profiler:ident) => {};
+}
+
+macro_rules! profile_section {
+    ($profiler:ident, $tag:ident, $body:expr) => {
+        $body
+    };
+}
+
+macro_rules! profile_emit {
+    ($profiler:ident) => {};
+}
@@ -0,0 +1,22 @@
+// WHISTLE PROTOCOL - PUBLIC INPUT ENCODING
+//
+// Every field element the program sees (commitments, nullifier hashes, roots,
+// Groth16 public inputs) is 32 bytes big-endian. The encoders are shared
+// with whistle-verifier through whistle-groth16; this module adds the
+// program's error for non-canonical values.
+
+use anchor_lang::prelude::*;
+
+pub use whistle_groth16::inputs::*;
+
+use crate::WhistleError;
+
+/// Reject commitments and nullifier hashes that are not canonical field
+/// elements. Byte-reversed values almost always exceed the modulus.
+pub fn require_canonical_field_element(value: &[u8; 32]) -> Result<()> {
+    require!(
+        is_canonical_field_element(value),
+        WhistleError::NonCanonicalFieldElement
+    );
+    Ok(())
+}
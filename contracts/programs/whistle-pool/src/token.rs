@@ -0,0 +1,320 @@
+// WHISTLE PROTOCOL - SPL TOKEN NOTES
+//
+// Shielded notes holding an SPL token instead of SOL. Token notes share the
+// pool's Merkle tree, nullifier markers and roots history with SOL notes;
+// only the value moves differently:
+// - shield_token moves tokens from the depositor's token account into the
+//   mint's vault at [b"token_vault", mint], creating the vault on first use
+// - unshield_token pays out of that vault with transfer_checked, signed by
+//   the vault itself, and re-shields the change like unshield
+//
+// A token note commits to its mint: Poseidon(secret, nullifier, amount,
+// mint), with the mint as a field element (pubkey_to_field). The mint is a
+// public input of the unshield_token circuit, so a note shielded for one
+// mint cannot be spent from another mint's vault, and a SOL note (a
+// two-input Poseidon) cannot be spent as a token note or the reverse.
+//
+// Token balances are not part of total_shielded, the deposit caps or the
+// pool statistics, which all count lamports; each vault's token balance is
+// the pool's reserve of that mint. No protocol fee is taken, since the fee
+// vault holds SOL, and there is no relayer fee yet: the payer signs and
+// pays the nullifier marker's rent.
+//
+// Only compiled with the `spl-tokens` feature, which needs the
+// unshield_token verification key pasted into groth16.rs: without it a
+// shielded token note could never be withdrawn.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::system_program;
+
+#[cfg(not(feature = "test-harness"))]
+use crate::groth16::{vk_is_generated, UNSHIELD_TOKEN_VK_ALPHA_G1};
+use crate::public_inputs::{pubkey_to_field, require_canonical_field_element};
+use crate::{
+    insert_commitment, is_weak_change_commitment, validate_spl_denominations, verify_unshield_token_proof,
+    ChangeCreated, LeafAccounts, NullifierMarker, RootsHistory, ShieldToken, TokenShielded, TokenUnshielded,
+    UnshieldToken, WhistleError, CURVE_BN254,
+};
+
+// The test proof backend stands in for the key in test-harness builds
+#[cfg(not(feature = "test-harness"))]
+const _: () = assert!(
+    vk_is_generated(&UNSHIELD_TOKEN_VK_ALPHA_G1),
+    "the spl-tokens feature needs the unshield_token verification key in groth16.rs"
+);
+
+// ============================================================================
+// INSTRUCTIONS
+// ============================================================================
+
+pub fn shield_token(ctx: Context<ShieldToken>, commitment: [u8; 32], amount: u64) -> Result<()> {
+    require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    require!(amount > 0, WhistleError::AmountTooSmall);
+
+    let accounts = ctx.accounts;
+    let mint = accounts.mint.key();
+    let decimals = unpack_mint(&accounts.mint)?.decimals;
+    let source = unpack_token_account(&accounts.depositor_token_account)?;
+    require_keys_eq!(source.mint, mint, WhistleError::MintMismatch);
+
+    if accounts.token_vault.owner != &spl_token::id() {
+        create_token_vault(
+            &accounts.token_vault,
+            &accounts.mint,
+            &accounts.depositor.to_account_info(),
+            &accounts.system_program.to_account_info(),
+            ctx.bumps.token_vault,
+        )?;
+    }
+
+    invoke(
+        &spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            accounts.depositor_token_account.key,
+            accounts.mint.key,
+            accounts.token_vault.key,
+            accounts.depositor.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            accounts.depositor_token_account.to_account_info(),
+            accounts.mint.to_account_info(),
+            accounts.token_vault.to_account_info(),
+            accounts.depositor.to_account_info(),
+            accounts.token_program.to_account_info(),
+        ],
+    )?;
+
+    let leaf_index = insert_commitment(
+        &mut accounts.pool,
+        LeafAccounts {
+            merkle_tree: &accounts.merkle_tree,
+            roots_history: &accounts.roots_history,
+            leaf_page: Some(&accounts.leaf_page),
+            commitment_marker: &accounts.commitment_marker,
+            payer: accounts.depositor.to_account_info(),
+            system_program: accounts.system_program.to_account_info(),
+        },
+        commitment,
+    )?;
+
+    let clock = Clock::get()?;
+    emit!(TokenShielded {
+        mint,
+        commitment,
+        leaf_index,
+        amount,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn unshield_token(
+    ctx: Context<UnshieldToken>,
+    proof_a: [u8; 64],
+    proof_b: [u8; 128],
+    proof_c: [u8; 64],
+    nullifier_hash: [u8; 32],
+    recipient: Pubkey,
+    amount: u64,
+    merkle_root: [u8; 32],
+    change_commitment: [u8; 32],
+) -> Result<()> {
+    require!(ctx.accounts.pool.curve == CURVE_BN254, WhistleError::UnsupportedCurve);
+    let accounts = ctx.accounts;
+    let mint = accounts.mint.key();
+    let decimals = unpack_mint(&accounts.mint)?.decimals;
+
+    // Whole token units only: the SPL counterpart of fixed denominations
+    validate_spl_denominations(decimals, &[amount])?;
+
+    let destination = unpack_token_account(&accounts.recipient_token_account)?;
+    require_keys_eq!(destination.mint, mint, WhistleError::MintMismatch);
+    require_keys_eq!(destination.owner, recipient, WhistleError::InvalidTokenAccount);
+
+    require!(nullifier_hash != [0u8; 32], WhistleError::ZeroNullifierHash);
+    require_canonical_field_element(&nullifier_hash)?;
+    require_canonical_field_element(&change_commitment)?;
+    require!(
+        !is_weak_change_commitment(&change_commitment, &nullifier_hash),
+        WhistleError::WeakChangeCommitment
+    );
+
+    let pool = &mut accounts.pool;
+    let nullifiers = accounts.nullifiers.load()?;
+    NullifierMarker::require_unspent(&nullifiers, &accounts.nullifier_marker, &nullifier_hash)?;
+
+    require!(merkle_root != [0u8; 32], WhistleError::ZeroMerkleRoot);
+    require!(pool.next_index > 0, WhistleError::TreeEmpty);
+    RootsHistory::ring(&accounts.roots_history)?.require_spendable_root(&merkle_root, pool, Clock::get()?.slot)?;
+
+    let proof_valid = verify_unshield_token_proof(
+        &proof_a,
+        &proof_b,
+        &proof_c,
+        &merkle_root,
+        &nullifier_hash,
+        &pubkey_to_field(&recipient.to_bytes()),
+        amount,
+        &pubkey_to_field(&mint.to_bytes()),
+        &change_commitment,
+    )?;
+    require!(proof_valid, WhistleError::InvalidProof);
+
+    NullifierMarker::spend(
+        &nullifiers,
+        &accounts.nullifier_marker,
+        &accounts.payer.to_account_info(),
+        &accounts.system_program.to_account_info(),
+        &nullifier_hash,
+    )?;
+    drop(nullifiers);
+
+    let has_change = change_commitment != [0u8; 32];
+    if has_change {
+        let change_index = insert_commitment(
+            pool,
+            LeafAccounts {
+                merkle_tree: &accounts.merkle_tree,
+                roots_history: &accounts.roots_history,
+                leaf_page: Some(&accounts.leaf_page),
+                commitment_marker: &accounts.change_marker,
+                payer: accounts.payer.to_account_info(),
+                system_program: accounts.system_program.to_account_info(),
+            },
+            change_commitment,
+        )?;
+
+        let clock = Clock::get()?;
+        emit!(ChangeCreated {
+            commitment: change_commitment,
+            leaf_index: change_index,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+    }
+
+    // Only shield_token creates the vault, so an uncreated one holds nothing
+    require!(
+        accounts.token_vault.owner == &spl_token::id()
+            && unpack_token_account(&accounts.token_vault)?.amount >= amount,
+        WhistleError::InsufficientVaultBalance
+    );
+
+    let vault_seeds: &[&[u8]] = &[b"token_vault", mint.as_ref(), &[ctx.bumps.token_vault]];
+    invoke_signed(
+        &spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            accounts.token_vault.key,
+            accounts.mint.key,
+            accounts.recipient_token_account.key,
+            accounts.token_vault.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            accounts.token_vault.to_account_info(),
+            accounts.mint.to_account_info(),
+            accounts.recipient_token_account.to_account_info(),
+            accounts.token_program.to_account_info(),
+        ],
+        &[vault_seeds],
+    )?;
+
+    let clock = Clock::get()?;
+    emit!(TokenUnshielded {
+        mint,
+        nullifier_hash,
+        amount,
+        has_change,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// ============================================================================
+// HELPERS
+// ============================================================================
+
+fn unpack_mint(info: &AccountInfo) -> Result<spl_token::state::Mint> {
+    require!(*info.owner == spl_token::id(), WhistleError::InvalidTokenAccount);
+    spl_token::state::Mint::unpack(&info.try_borrow_data()?)
+        .map_err(|_| error!(WhistleError::InvalidTokenAccount))
+}
+
+fn unpack_token_account(info: &AccountInfo) -> Result<spl_token::state::Account> {
+    require!(*info.owner == spl_token::id(), WhistleError::InvalidTokenAccount);
+    spl_token::state::Account::unpack(&info.try_borrow_data()?)
+        .map_err(|_| error!(WhistleError::InvalidTokenAccount))
+}
+
+/// Create `mint`'s vault: a token account at its PDA, owned by itself
+///
+/// Lamports sent to the address beforehand are kept, as NullifierMarker
+/// does, so nobody can block a mint by pre-funding its vault.
+fn create_token_vault<'info>(
+    vault: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    bump: u8,
+) -> Result<()> {
+    let mint_key = mint.key();
+    let seeds: &[&[u8]] = &[b"token_vault", mint_key.as_ref(), &[bump]];
+    let size = spl_token::state::Account::LEN;
+    let rent = Rent::get()?.minimum_balance(size);
+    let lamports = vault.lamports();
+    if lamports == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::CreateAccount { from: payer.clone(), to: vault.clone() },
+                &[seeds],
+            ),
+            rent,
+            size as u64,
+            &spl_token::id(),
+        )?;
+    } else {
+        if lamports < rent {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    system_program::Transfer { from: payer.clone(), to: vault.clone() },
+                ),
+                rent - lamports,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Allocate { account_to_allocate: vault.clone() },
+                &[seeds],
+            ),
+            size as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Assign { account_to_assign: vault.clone() },
+                &[seeds],
+            ),
+            &spl_token::id(),
+        )?;
+    }
+
+    invoke(
+        &spl_token::instruction::initialize_account3(&spl_token::id(), vault.key, mint.key, vault.key)?,
+        &[vault.clone(), mint.clone()],
+    )?;
+    Ok(())
+}
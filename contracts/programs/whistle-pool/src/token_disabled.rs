@@ -0,0 +1,28 @@
+// WHISTLE PROTOCOL - SPL TOKEN NOTES (DISABLED)
+//
+// Stand-in for token.rs when the `spl-tokens` feature is off. The
+// instructions stay in the program interface but always fail with
+// VerifyingKeyNotGenerated: shield_token too, since a token note shielded
+// before the unshield_token key exists could never be withdrawn.
+
+use anchor_lang::prelude::*;
+
+use crate::{ShieldToken, UnshieldToken, WhistleError};
+
+pub fn shield_token(_ctx: Context<ShieldToken>, _commitment: [u8; 32], _amount: u64) -> Result<()> {
+    err!(WhistleError::VerifyingKeyNotGenerated)
+}
+
+pub fn unshield_token(
+    _ctx: Context<UnshieldToken>,
+    _proof_a: [u8; 64],
+    _proof_b: [u8; 128],
+    _proof_c: [u8; 64],
+    _nullifier_hash: [u8; 32],
+    _recipient: Pubkey,
+    _amount: u64,
+    _merkle_root: [u8; 32],
+    _change_commitment: [u8; 32],
+) -> Result<()> {
+    err!(WhistleError::VerifyingKeyNotGenerated)
+}
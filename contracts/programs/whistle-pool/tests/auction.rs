@@ -0,0 +1,337 @@
+//! Sealed-bid NFT auctions run in order: open_auction escrowing the NFT,
+//! submit_bid before the deadline only, reveal_reserve after it only and
+//! once, settle_bid opening bids only after the reserve and only while
+//! they beat it and the current leader, the outbid bidder reclaiming a
+//! change note while the leader cannot, and finalize_auction only after
+//! the settle window, paying the seller, shielding the winner's change and
+//! delivering the NFT exactly once.
+//!
+//! cargo test -p whistle-pool --features test-harness --test auction
+
+mod common;
+
+use anchor_client::solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::{
+    instruction::Instruction, keccak, program_pack::Pack, system_instruction, system_program,
+};
+use solana_program_test::BanksClientError;
+
+use common::{commitment_marker, create_mint, create_token_account, deposit_record, leaf_page, pda, TestPool};
+use whistle_pool::auction::{bid_commitment, reserve_commitment, AUCTION_SETTLE_SLOTS};
+use whistle_pool::{accounts, instruction, MerkleTreeLeafPage, WhistleError, BPS_DENOMINATOR, PROTOCOL_FEE_BPS};
+
+const MERKLE_LEVELS: u8 = 7;
+const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+const BIDDING_SLOTS: u64 = 20;
+
+fn custom_error(err: BanksClientError) -> u32 {
+    let BanksClientError::TransactionError(TransactionError::InstructionError(0, InstructionError::Custom(code))) = err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    code
+}
+
+fn field(domain: &[u8]) -> [u8; 32] {
+    let mut value = keccak::hash(domain).to_bytes();
+    value[0] = 0;
+    value
+}
+
+fn auction_pda(nft_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"auction", nft_mint.as_ref()], &whistle_pool::ID).0
+}
+
+/// A bidder with a sealed bid and the note their leftover escrow goes to
+struct Bidder {
+    key: Keypair,
+    amount: u64,
+    max_bid: u64,
+    randomness: [u8; 32],
+    change_commitment: [u8; 32],
+}
+
+impl Bidder {
+    async fn new(pool: &mut TestPool, seed: &[u8], amount: u64, max_bid: u64) -> Self {
+        let key = Keypair::new();
+        let fund = system_instruction::transfer(&pool.payer.pubkey(), &key.pubkey(), max_bid + LAMPORTS_PER_SOL);
+        pool.send(fund).await.unwrap();
+        Self {
+            key,
+            amount,
+            max_bid,
+            randomness: keccak::hashv(&[b"randomness", seed]).to_bytes(),
+            change_commitment: field(&[b"change ", seed].concat()),
+        }
+    }
+
+    fn commitment(&self) -> [u8; 32] {
+        bid_commitment(self.amount, &self.randomness)
+    }
+
+    fn bid(&self, auction: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"auction_bid", auction.as_ref(), &self.commitment()], &whistle_pool::ID).0
+    }
+}
+
+/// An open auction of a freshly minted NFT
+struct Auction {
+    seller: Keypair,
+    nft_mint: Pubkey,
+    address: Pubkey,
+    escrow: Pubkey,
+    reserve_randomness: [u8; 32],
+    deadline_slot: u64,
+}
+
+impl Auction {
+    async fn open(pool: &mut TestPool, reserve: u64) -> Self {
+        let seller = Keypair::new();
+        let fund = system_instruction::transfer(&pool.payer.pubkey(), &seller.pubkey(), LAMPORTS_PER_SOL);
+        pool.send(fund).await.unwrap();
+        let nft_mint = create_mint(pool, 0).await;
+        let seller_nft_account = create_token_account(pool, nft_mint, seller.pubkey(), 1).await;
+        let address = auction_pda(&nft_mint);
+        let escrow = create_token_account(pool, nft_mint, address, 0).await;
+        let reserve_randomness = keccak::hash(b"reserve randomness").to_bytes();
+        let deadline_slot = pool.slot().await + BIDDING_SLOTS;
+
+        let ix = pool.ix(
+            accounts::OpenAuction {
+                auction: address,
+                seller_nft_account,
+                nft_escrow: escrow,
+                seller: seller.pubkey(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            },
+            instruction::OpenAuction {
+                nft_mint,
+                reserve_commitment: reserve_commitment(reserve, &reserve_randomness),
+                deadline_slot,
+            },
+        );
+        pool.send_signed(ix, &[&seller]).await.unwrap();
+        Self { seller, nft_mint, address, escrow, reserve_randomness, deadline_slot }
+    }
+
+    async fn submit_bid(&self, pool: &mut TestPool, bidder: &Bidder) -> Result<(), BanksClientError> {
+        let ix = pool.ix(
+            accounts::SubmitBid {
+                auction: self.address,
+                bid: bidder.bid(&self.address),
+                bidder: bidder.key.pubkey(),
+                system_program: system_program::ID,
+                pool: pda(b"pool"),
+                deposit_record: deposit_record(&bidder.key.pubkey()),
+            },
+            instruction::SubmitBid { bid_commitment: bidder.commitment(), max_bid: bidder.max_bid },
+        );
+        pool.send_signed(ix, &[&bidder.key]).await
+    }
+
+    async fn reveal_reserve(&self, pool: &mut TestPool, reserve: u64) -> Result<(), BanksClientError> {
+        let ix = pool.ix(
+            accounts::RevealReserve { auction: self.address, seller: self.seller.pubkey() },
+            instruction::RevealReserve { reserve, randomness: self.reserve_randomness },
+        );
+        pool.send_signed(ix, &[&self.seller]).await
+    }
+
+    /// Open `bidder`'s bid as a winner claim, the NFT going to themselves
+    async fn open_bid(&self, pool: &mut TestPool, bidder: &Bidder) -> Result<(), BanksClientError> {
+        let change_commitment = if bidder.amount == bidder.max_bid { [0u8; 32] } else { bidder.change_commitment };
+        let next_index = pool.pool_state().await.next_index;
+        let ix = self.settle_ix(pool, bidder, next_index, true, change_commitment);
+        pool.send_signed(ix, &[&bidder.key]).await
+    }
+
+    /// Reclaim `bidder`'s escrow as their change note without opening the bid
+    async fn reclaim(&self, pool: &mut TestPool, bidder: &Bidder) -> Result<(), BanksClientError> {
+        let next_index = pool.pool_state().await.next_index;
+        let ix = self.settle_ix(pool, bidder, next_index, false, bidder.change_commitment);
+        pool.send_signed(ix, &[&bidder.key]).await
+    }
+
+    fn settle_ix(
+        &self,
+        pool: &TestPool,
+        bidder: &Bidder,
+        next_index: u64,
+        is_winner: bool,
+        change_commitment: [u8; 32],
+    ) -> Instruction {
+        pool.ix(
+            accounts::SettleBid {
+                auction: self.address,
+                bid: bidder.bid(&self.address),
+                bidder: bidder.key.pubkey(),
+                pool: pda(b"pool"),
+                merkle_tree: pda(b"merkle_tree"),
+                roots_history: pda(b"roots_history"),
+                pool_stats: pda(b"pool_stats"),
+                pool_vault: pda(b"vault"),
+                fee_vault: pda(b"fee_vault"),
+                deposit_histogram: pda(b"deposit_histogram"),
+                system_program: system_program::ID,
+                leaf_page: leaf_page(MerkleTreeLeafPage::page_of(next_index)),
+                commitment_marker: commitment_marker(&change_commitment),
+            },
+            instruction::SettleBid {
+                bid_amount: bidder.amount,
+                randomness: bidder.randomness,
+                is_winner,
+                change_commitment,
+                nft_recipient: bidder.key.pubkey(),
+            },
+        )
+    }
+
+    async fn finalize(
+        &self,
+        pool: &mut TestPool,
+        winner: Option<&Bidder>,
+        nft_destination: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let next_index = pool.pool_state().await.next_index;
+        let change_commitment = winner.map(|bidder| bidder.change_commitment).unwrap_or_default();
+        let ix = pool.ix(
+            accounts::FinalizeAuction {
+                auction: self.address,
+                leading_bid: winner.map(|bidder| bidder.bid(&self.address)),
+                leading_bidder: winner.map(|bidder| bidder.key.pubkey()),
+                seller: self.seller.pubkey(),
+                nft_escrow: self.escrow,
+                nft_destination,
+                pool: pda(b"pool"),
+                merkle_tree: pda(b"merkle_tree"),
+                roots_history: pda(b"roots_history"),
+                pool_stats: pda(b"pool_stats"),
+                pool_vault: pda(b"vault"),
+                fee_vault: pda(b"fee_vault"),
+                token_program: spl_token::id(),
+                payer: pool.payer.pubkey(),
+                deposit_histogram: pda(b"deposit_histogram"),
+                system_program: system_program::ID,
+                leaf_page: leaf_page(MerkleTreeLeafPage::page_of(next_index)),
+                commitment_marker: commitment_marker(&change_commitment),
+            },
+            instruction::FinalizeAuction {},
+        );
+        pool.send_result(ix).await
+    }
+}
+
+async fn token_balance(pool: &mut TestPool, account: Pubkey) -> u64 {
+    let data = pool.banks.get_account(account).await.unwrap().unwrap().data;
+    spl_token::state::Account::unpack(&data).unwrap().amount
+}
+
+/// Advance the clock to `slot`
+async fn warp_to(pool: &mut TestPool, slot: u64) {
+    let current = pool.slot().await;
+    pool.warp_slots(slot - current).await;
+}
+
+#[tokio::test]
+async fn auction_steps_run_only_in_order() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let reserve = LAMPORTS_PER_SOL;
+    let auction = Auction::open(&mut pool, reserve).await;
+    assert_eq!(token_balance(&mut pool, auction.escrow).await, 1);
+
+    let low = Bidder::new(&mut pool, b"low", reserve / 2, reserve).await;
+    let outbid = Bidder::new(&mut pool, b"outbid", 2 * LAMPORTS_PER_SOL, 3 * LAMPORTS_PER_SOL).await;
+    let winner = Bidder::new(&mut pool, b"winner", 3 * LAMPORTS_PER_SOL, 4 * LAMPORTS_PER_SOL).await;
+    for bidder in [&low, &outbid, &winner] {
+        auction.submit_bid(&mut pool, bidder).await.unwrap();
+    }
+
+    // Nothing is revealed or settled while bidding is open
+    let not_ended = u32::from(WhistleError::AuctionNotEnded);
+    let err = auction.reveal_reserve(&mut pool, reserve).await.unwrap_err();
+    assert_eq!(custom_error(err), not_ended);
+    let err = auction.open_bid(&mut pool, &winner).await.unwrap_err();
+    assert_eq!(custom_error(err), not_ended);
+    let err = auction.reclaim(&mut pool, &low).await.unwrap_err();
+    assert_eq!(custom_error(err), not_ended);
+
+    // After the deadline no more bids are taken
+    warp_to(&mut pool, auction.deadline_slot).await;
+    let late = Bidder::new(&mut pool, b"late", 5 * LAMPORTS_PER_SOL, 5 * LAMPORTS_PER_SOL).await;
+    let err = auction.submit_bid(&mut pool, &late).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::AuctionClosed));
+
+    // Bids open only against a revealed reserve
+    let err = auction.open_bid(&mut pool, &winner).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::ReserveNotRevealed));
+
+    // The reserve opens to its committed value, once
+    let err = auction.reveal_reserve(&mut pool, reserve - 1).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::CommitmentOpeningMismatch));
+    auction.reveal_reserve(&mut pool, reserve).await.unwrap();
+    let err = auction.reveal_reserve(&mut pool, reserve).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::ReserveAlreadyRevealed));
+
+    // A bid under the reserve cannot win
+    let err = auction.open_bid(&mut pool, &low).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::BidBelowReserve));
+
+    // Each opened bid must beat the leader; the leader cannot reclaim
+    auction.open_bid(&mut pool, &outbid).await.unwrap();
+    let err = auction.reclaim(&mut pool, &outbid).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::LeadingBidCannotReclaim));
+    auction.open_bid(&mut pool, &winner).await.unwrap();
+    let err = auction.open_bid(&mut pool, &outbid).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::BidNotHighest));
+    let err = auction.reclaim(&mut pool, &winner).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::LeadingBidCannotReclaim));
+
+    // Losers reclaim their escrow as change notes without opening their
+    // bids; each note takes its commitment marker like a shield
+    for bidder in [&low, &outbid] {
+        let next_index = pool.pool_state().await.next_index;
+        auction.reclaim(&mut pool, bidder).await.unwrap();
+        let state = pool.pool_state().await;
+        assert_eq!(state.next_index, next_index + 1);
+        assert!(pool.banks.get_account(bidder.bid(&auction.address)).await.unwrap().is_none());
+        assert!(pool.banks.get_account(commitment_marker(&bidder.change_commitment)).await.unwrap().is_some());
+    }
+
+    // Nothing finalizes while the settle window is open
+    let nft_destination = create_token_account(&mut pool, auction.nft_mint, winner.key.pubkey(), 0).await;
+    let err = auction.finalize(&mut pool, Some(&winner), nft_destination).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::SettleWindowOpen));
+
+    // Once it closes no bid can be opened
+    warp_to(&mut pool, auction.deadline_slot + AUCTION_SETTLE_SLOTS).await;
+    let err = auction.open_bid(&mut pool, &winner).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::SettleWindowClosed));
+
+    // The winner pays their bid to the seller, the rest of their escrow
+    // lands in the tree as their change note, and the NFT is theirs
+    let seller_before = pool.balance(auction.seller.pubkey()).await;
+    let next_index = pool.pool_state().await.next_index;
+    auction.finalize(&mut pool, Some(&winner), nft_destination).await.unwrap();
+    assert_eq!(pool.balance(auction.seller.pubkey()).await, seller_before + winner.amount);
+    let state = pool.pool_state().await;
+    assert_eq!(state.next_index, next_index + 1);
+    let change = winner.max_bid - winner.amount;
+    let shielded: u64 = [low.max_bid, outbid.max_bid, change]
+        .iter()
+        .map(|amount| amount - amount * PROTOCOL_FEE_BPS / BPS_DENOMINATOR)
+        .sum();
+    assert_eq!(state.total_shielded, shielded);
+    assert!(pool.banks.get_account(commitment_marker(&winner.change_commitment)).await.unwrap().is_some());
+    assert_eq!(token_balance(&mut pool, nft_destination).await, 1);
+    assert_eq!(token_balance(&mut pool, auction.escrow).await, 0);
+
+    // The NFT is delivered once
+    let err = auction.finalize(&mut pool, None, nft_destination).await.unwrap_err();
+    assert_eq!(custom_error(err), u32::from(WhistleError::AuctionAlreadyFinalized));
+}
@@ -0,0 +1,758 @@
+//! program-test setup shared by the integration tests: a native-processor
+//! pool with every PDA initialized, and whistle-merkle next to it.
+
+// Each test binary uses a different subset of these helpers
+#![allow(dead_code)]
+
+use anchor_client::solana_sdk::{
+    account::Account,
+    signature::{keypair_from_seed, Keypair, Signature, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    bpf_loader_upgradeable,
+    ed25519_program,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    keccak,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    system_instruction, system_program,
+};
+use anchor_lang::{AccountDeserialize, AccountSerialize, Discriminator, InstructionData, ToAccountMetas};
+use solana_program_test::{processor, BanksClient, BanksTransactionResultWithMetadata, ProgramTest, ProgramTestContext};
+
+use std::collections::HashSet;
+use std::sync::{Once, OnceLock};
+
+use whistle_pool::DEFAULT_RELAYER_FEE_CAPS_BPS;
+
+// Genesis balance of the vault and fee vault: keeps them rent-exempt when
+// notes are drained or the first protocol fees arrive
+pub const VAULT_GENESIS_LAMPORTS: u64 = 1_000_000_000;
+
+fn entry<'info>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'info>],
+    data: &[u8],
+) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+    // processor! cannot name the lifetimes Anchor's entry requires
+    let accounts: &'info [AccountInfo<'info>] = unsafe { std::mem::transmute(accounts) };
+    whistle_pool::entry(program_id, accounts, data)
+}
+
+fn merkle_entry<'info>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'info>],
+    data: &[u8],
+) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+    let accounts: &'info [AccountInfo<'info>] = unsafe { std::mem::transmute(accounts) };
+    whistle_merkle::entry(program_id, accounts, data)
+}
+
+// program-test's syscall stubs, behind EventLogStubs
+static PROGRAM_TEST_STUBS: OnceLock<Box<dyn SyscallStubs>> = OnceLock::new();
+
+/// program-test's stubs, except that events reach the transaction logs
+///
+/// Native programs emit through sol_log_data, which program-test leaves to
+/// the default stub printing to stdout. This logs each event through
+/// sol_log instead, as "Program log: Program data: <base64>"; event_data
+/// reads both that and the SBF form.
+struct EventLogStubs;
+
+impl EventLogStubs {
+    /// Install once, after program-test has set its own stubs
+    fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let program_test = set_syscall_stubs(Box::new(EventLogStubs));
+            let _ = PROGRAM_TEST_STUBS.set(program_test);
+        });
+    }
+
+    fn inner(&self) -> &dyn SyscallStubs {
+        PROGRAM_TEST_STUBS.get().expect("installed with program-test's stubs").as_ref()
+    }
+}
+
+impl SyscallStubs for EventLogStubs {
+    fn sol_log(&self, message: &str) {
+        self.inner().sol_log(message)
+    }
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        use base64::Engine as _;
+        let fields: Vec<_> = fields.iter().map(|field| base64::engine::general_purpose::STANDARD.encode(field)).collect();
+        self.inner().sol_log(&format!("Program data: {}", fields.join(" ")))
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+        self.inner().sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_clock_sysvar(var_addr)
+    }
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_epoch_schedule_sysvar(var_addr)
+    }
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_epoch_rewards_sysvar(var_addr)
+    }
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_fees_sysvar(var_addr)
+    }
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_rent_sysvar(var_addr)
+    }
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_last_restart_slot(var_addr)
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.inner().sol_get_return_data()
+    }
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.inner().sol_set_return_data(data)
+    }
+    fn sol_get_stack_height(&self) -> u64 {
+        self.inner().sol_get_stack_height()
+    }
+}
+
+/// Base64 payload of an event log line, from the SBF program or from
+/// EventLogStubs
+pub fn event_data(log: &str) -> Option<&str> {
+    log.strip_prefix("Program data: ").or_else(|| log.strip_prefix("Program log: Program data: "))
+}
+
+/// A program-owned zero-copy account holding `header` followed by zeroes
+///
+/// The merkle tree and nullifier set exceed the 10KB an account can be
+/// created with from a CPI, so the tests create them in their initialized
+/// state instead of calling init_merkle / init_nullifiers.
+pub fn zero_copy_account<T: Discriminator>(header: &[u8]) -> Account {
+    let mut data = vec![0u8; 8 + std::mem::size_of::<T>()];
+    data[..8].copy_from_slice(&T::DISCRIMINATOR);
+    data[8..8 + header.len()].copy_from_slice(header);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: whistle_pool::ID,
+        ..Default::default()
+    }
+}
+
+/// Example deposit router for the shield_forwarded tests
+pub const ROUTER_ID: Pubkey = Pubkey::new_from_array([0x52; 32]);
+
+/// The router's PDA, which receives users' lamports and forwards them to
+/// the pool (funded at genesis so it can pay for new leaf pages and
+/// commitment markers)
+pub fn router_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"router"], &ROUTER_ID)
+}
+
+/// Router instruction `commitment || amount`: shield_forwarded from the
+/// router PDA, passing the caller's accounts through (ShieldForwarded
+/// order, then the pool program)
+fn router_entry(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+    let (forwarder, bump) = router_pda();
+    let commitment: [u8; 32] = data[..32].try_into().unwrap();
+    let amount = u64::from_le_bytes(data[32..40].try_into().unwrap());
+    let ix = Instruction {
+        program_id: whistle_pool::ID,
+        accounts: accounts
+            .iter()
+            .filter(|account| *account.key != whistle_pool::ID)
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer || *account.key == forwarder,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: whistle_pool::instruction::ShieldForwarded { commitment, amount }.data(),
+    };
+    invoke_signed(&ix, accounts, &[&[b"router", &[bump]]])
+}
+
+pub fn pda(seed: &[u8]) -> Pubkey {
+    Pubkey::find_program_address(&[seed], &whistle_pool::ID).0
+}
+
+/// Marker a spend of `nullifier_hash` creates
+pub fn nullifier_marker(nullifier_hash: &[u8; 32]) -> Pubkey {
+    whistle_pool::NullifierMarker::address(nullifier_hash).0
+}
+
+/// Marker the insertion of `commitment` creates
+pub fn commitment_marker(commitment: &[u8; 32]) -> Pubkey {
+    whistle_pool::CommitmentMarker::address(commitment).0
+}
+
+pub fn deposit_record(depositor: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"deposit_record", depositor.as_ref()], &whistle_pool::ID).0
+}
+
+pub fn leaf_page(page_index: u32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"leaf_page", pda(b"pool").as_ref(), &page_index.to_le_bytes()],
+        &whistle_pool::ID,
+    )
+    .0
+}
+
+/// Recipient pubkey as the circuits' field element (first 31 bytes)
+pub fn recipient_field(recipient: &Pubkey) -> [u8; 32] {
+    let mut field = [0u8; 32];
+    field[1..].copy_from_slice(&recipient.to_bytes()[..31]);
+    field
+}
+
+/// An Ethereum key and its address
+pub fn eth_key(seed: u8) -> (libsecp256k1::SecretKey, [u8; 20]) {
+    let secret = libsecp256k1::SecretKey::parse(&[seed; 32]).unwrap();
+    let public = libsecp256k1::PublicKey::from_secret_key(&secret).serialize();
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&keccak::hash(&public[1..]).to_bytes()[12..]);
+    (secret, address)
+}
+
+/// r || s || v with v = 27 / 28, as Ethereum wallets return it
+pub fn eth_sign(secret: &libsecp256k1::SecretKey, hash: &[u8; 32]) -> [u8; 65] {
+    let (signature, recovery_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(hash), secret);
+    let mut signed = [0u8; 65];
+    signed[..64].copy_from_slice(&signature.serialize());
+    signed[64] = 27 + recovery_id.serialize();
+    signed
+}
+
+/// The pool's vault for `mint`
+pub fn token_vault(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"token_vault", mint.as_ref()], &whistle_pool::ID).0
+}
+
+/// Wait out the account locks of the last transaction sent
+/// 
+/// Banks reports a queued transaction's status before its batch releases
+/// the account locks. Simulations and process_transaction_with_metadata
+/// run on the bank directly, so one sent right after can fail with
+/// AccountInUse under load.
+async fn settle_account_locks() {
+    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+}
+
+/// Ed25519 precompile instruction verifying `signer`'s signature over
+/// `message`, laid out as solana_sdk's new_ed25519_instruction does: the
+/// offsets, then the public key, the signature and the message
+pub fn ed25519_ix(signer: &Keypair, message: &[u8]) -> (Instruction, [u8; 64]) {
+    const PUBKEY_OFFSET: u16 = 16;
+    const SIGNATURE_OFFSET: u16 = PUBKEY_OFFSET + 32;
+    const MESSAGE_OFFSET: u16 = SIGNATURE_OFFSET + 64;
+    let signature: [u8; 64] = signer.sign_message(message).as_ref().try_into().unwrap();
+
+    let mut data = vec![1, 0];
+    for offset in [
+        SIGNATURE_OFFSET,
+        u16::MAX,
+        PUBKEY_OFFSET,
+        u16::MAX,
+        MESSAGE_OFFSET,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend(offset.to_le_bytes());
+    }
+    data.extend(signer.pubkey().to_bytes());
+    data.extend(signature);
+    data.extend(message);
+    let ix = Instruction { program_id: ed25519_program::ID, accounts: vec![], data };
+    (ix, signature)
+}
+
+/// Upgrade authority named by the test pool's ProgramData, the only signer
+/// initialize and init_denominations accept
+pub fn upgrade_authority() -> Keypair {
+    keypair_from_seed(&[0x55; 32]).unwrap()
+}
+
+/// whistle_pool's ProgramData address under the upgradeable loader
+pub fn program_data_address() -> Pubkey {
+    Pubkey::find_program_address(&[whistle_pool::ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+/// whistle_pool's ProgramData as the upgradeable loader lays it out:
+/// UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address }
+pub fn program_data_account(deployed_slot: u64, upgrade_authority: Option<Pubkey>) -> Account {
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend(deployed_slot.to_le_bytes());
+    match upgrade_authority {
+        Some(authority) => {
+            data.push(1);
+            data.extend(authority.to_bytes());
+        }
+        None => data.extend([0u8; 33]),
+    }
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: bpf_loader_upgradeable::ID,
+        ..Default::default()
+    }
+}
+
+/// shield_token of `amount` from the payer's `depositor_token_account`
+pub fn shield_token_ix(
+    pool: &TestPool,
+    next_index: u64,
+    mint: Pubkey,
+    depositor_token_account: Pubkey,
+    commitment: [u8; 32],
+    amount: u64,
+) -> Instruction {
+    pool.ix(
+        whistle_pool::accounts::ShieldToken {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            roots_history: pda(b"roots_history"),
+            mint,
+            token_vault: token_vault(&mint),
+            depositor_token_account,
+            depositor: pool.payer.pubkey(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+            leaf_page: leaf_page(whistle_pool::MerkleTreeLeafPage::page_of(next_index)),
+            commitment_marker: commitment_marker(&commitment),
+        },
+        whistle_pool::instruction::ShieldToken { commitment, amount },
+    )
+}
+
+pub struct TestPool {
+    pub banks: BanksClient,
+    pub payer: Keypair,
+    context: ProgramTestContext,
+    /// Signatures of every transaction sent, see `transaction`
+    sent: HashSet<Signature>,
+}
+
+impl TestPool {
+    /// Start a validator with an initialized pool of `merkle_levels`
+    pub async fn start(merkle_levels: u8) -> Self {
+        Self::start_with_deposit_caps(merkle_levels, 0, 0).await
+    }
+
+    /// Like `start`, with the pool's per-address and total shielded caps
+    pub async fn start_with_deposit_caps(merkle_levels: u8, per_address: u64, pool_shielded: u64) -> Self {
+        Self::launch(merkle_levels, per_address, pool_shielded, Vec::new(), false).await
+    }
+
+    /// Like `start`, with `accounts` created at genesis
+    /// 
+    /// Lamports of accounts created later with set_account are missing from
+    /// the bank's capitalization, so warp_slots panics after it.
+    pub async fn start_with_accounts(merkle_levels: u8, accounts: Vec<(Pubkey, Account)>) -> Self {
+        Self::launch(merkle_levels, 0, 0, accounts, false).await
+    }
+
+    /// Like `start`, running the built SBF program (target/deploy/whistle_pool.so)
+    /// instead of the native processor, for tests that need the compute meter
+    pub async fn start_sbf(merkle_levels: u8) -> Self {
+        Self::launch(merkle_levels, 0, 0, Vec::new(), true).await
+    }
+
+    async fn launch(
+        merkle_levels: u8,
+        per_address: u64,
+        pool_shielded: u64,
+        accounts: Vec<(Pubkey, Account)>,
+        sbf: bool,
+    ) -> Self {
+        let mut program_test = ProgramTest::new("whistle_pool", whistle_pool::ID, processor!(entry));
+        program_test.prefer_bpf(sbf);
+        if !sbf {
+            program_test.add_program("forwarding_router", ROUTER_ID, processor!(router_entry));
+            program_test.add_program("whistle_merkle", whistle_merkle::ID, processor!(merkle_entry));
+            program_test.add_account(
+                router_pda().0,
+                Account {
+                    lamports: VAULT_GENESIS_LAMPORTS,
+                    owner: system_program::ID,
+                    ..Default::default()
+                },
+            );
+        }
+        for vault in [pda(b"vault"), pda(b"fee_vault")] {
+            program_test.add_account(
+                vault,
+                Account {
+                    lamports: VAULT_GENESIS_LAMPORTS,
+                    owner: system_program::ID,
+                    ..Default::default()
+                },
+            );
+        }
+        let mut tree_header = [0u8; whistle_pool::MERKLE_TREE_HEADER_SIZE];
+        tree_header[0] = whistle_pool::MERKLE_TREE_VERSION;
+        tree_header[1] = merkle_levels;
+        tree_header[4..].copy_from_slice(&(whistle_pool::MERKLE_TREE_NODE_CAPACITY as u32).to_le_bytes());
+        program_test.add_account(pda(b"merkle_tree"), zero_copy_account::<whistle_pool::MerkleTree>(&tree_header));
+        program_test.add_account(pda(b"nullifiers"), zero_copy_account::<whistle_pool::NullifierSet>(&[]));
+        program_test.add_account(program_data_address(), program_data_account(0, Some(upgrade_authority().pubkey())));
+        program_test.add_account(
+            upgrade_authority().pubkey(),
+            Account { lamports: VAULT_GENESIS_LAMPORTS, owner: system_program::ID, ..Default::default() },
+        );
+        for (address, account) in accounts {
+            program_test.add_account(address, account);
+        }
+        let context = program_test.start_with_context().await;
+        if !sbf {
+            EventLogStubs::install();
+        }
+        let banks = context.banks_client.clone();
+        let payer = context.payer.insecure_clone();
+
+        let mut pool = Self { banks, payer, context, sent: HashSet::new() };
+        pool.initialize(merkle_levels, per_address, pool_shielded).await;
+        pool
+    }
+
+    pub async fn send(&mut self, ix: Instruction) -> std::result::Result<(), String> {
+        self.send_result(ix).await.map_err(|e| e.to_string())
+    }
+
+    /// Like `send`, keeping the transaction error for matching
+    pub async fn send_result(&mut self, ix: Instruction) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        self.send_signed(ix, &[]).await
+    }
+
+    /// Send `ixs` as a single transaction
+    pub async fn send_all(&mut self, ixs: &[Instruction]) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let tx = self.transaction(ixs, &[]).await;
+        self.banks.process_transaction(tx).await
+    }
+
+    /// Send `ix`, returning the logs and compute units alongside the result
+    pub async fn send_with_metadata(&mut self, ix: Instruction) -> BanksTransactionResultWithMetadata {
+        let tx = self.transaction(&[ix], &[]).await;
+        loop {
+            let sent = self.banks.process_transaction_with_metadata(tx.clone()).await.unwrap();
+            if !matches!(sent.result, Err(TransactionError::AccountInUse)) {
+                return sent;
+            }
+            settle_account_locks().await;
+        }
+    }
+
+    /// Send with `signers` co-signing alongside the payer
+    pub async fn send_signed(
+        &mut self,
+        ix: Instruction,
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let tx = self.transaction(&[ix], signers).await;
+        self.banks.process_transaction(tx).await
+    }
+
+    /// `ixs` signed by the payer and `signers`
+    /// 
+    /// Banks answers a transaction it already processed with the first
+    /// result instead of running it again, so a repeat of an earlier
+    /// transaction waits for the next blockhash.
+    async fn transaction(&mut self, ixs: &[Instruction], signers: &[&Keypair]) -> Transaction {
+        let signers = [&[&self.payer], signers].concat();
+        let mut blockhash = self.banks.get_latest_blockhash().await.unwrap();
+        loop {
+            let tx = Transaction::new_signed_with_payer(ixs, Some(&self.payer.pubkey()), &signers, blockhash);
+            if self.sent.insert(tx.signatures[0]) {
+                return tx;
+            }
+            blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        }
+    }
+
+    pub async fn slot(&mut self) -> u64 {
+        self.banks.get_sysvar::<Clock>().await.unwrap().slot
+    }
+
+    /// Advance the clock by `slots`
+    pub async fn warp_slots(&mut self, slots: u64) {
+        let slot = self.slot().await;
+        self.context.warp_to_slot(slot + slots).unwrap();
+    }
+
+    /// Create or overwrite `address` with `account`
+    pub fn set_account(&mut self, address: Pubkey, account: Account) {
+        self.context.set_account(&address, &account.into());
+    }
+
+    /// Simulate a view instruction and return its return data
+    pub async fn view(&mut self, ix: Instruction) -> Vec<u8> {
+        // A fresh signature: simulating one already sent reports
+        // AlreadyProcessed instead of running it
+        let tx = self.transaction(&[ix], &[]).await;
+        let simulation = loop {
+            let simulation = self.banks.simulate_transaction(tx.clone()).await.unwrap();
+            if !matches!(simulation.result, Some(Err(TransactionError::AccountInUse))) {
+                break simulation;
+            }
+            settle_account_locks().await;
+        };
+        simulation.result.unwrap().expect("view instruction failed");
+
+        // RPC nodes trim trailing zero bytes from return data; banks
+        // simulation does not, so trim here to read views as clients do
+        let mut data = simulation.simulation_details.unwrap().return_data.map_or_else(Vec::new, |r| r.data);
+        data.truncate(data.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1));
+        data
+    }
+
+    pub fn ix(&self, accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: whistle_pool::ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    async fn initialize(&mut self, merkle_levels: u8, per_address: u64, pool_shielded: u64) {
+        use whistle_pool::{accounts, instruction};
+        let authority = self.payer.pubkey();
+        let system_program = system_program::ID;
+        let pool = pda(b"pool");
+
+        let steps = [
+            self.ix(
+                accounts::InitializePool {
+                    pool,
+                    program_data: program_data_address(),
+                    authority: upgrade_authority().pubkey(),
+                    system_program,
+                },
+                instruction::Initialize {
+                    merkle_levels,
+                    allow_schnorr_for_small: false,
+                    max_total_deposits_per_address: per_address,
+                    max_total_pool_shielded: pool_shielded,
+                },
+            ),
+            self.ix(
+                accounts::InitRoots { pool, roots_history: pda(b"roots_history"), authority, system_program },
+                instruction::InitRoots {},
+            ),
+            self.ix(
+                accounts::InitDenominations {
+                    pool,
+                    denomination_config: pda(b"denomination_config"),
+                    program_data: program_data_address(),
+                    authority: upgrade_authority().pubkey(),
+                    system_program,
+                },
+                instruction::InitDenominations { relayer_fee_caps_bps: DEFAULT_RELAYER_FEE_CAPS_BPS },
+            ),
+            self.ix(
+                accounts::InitPoolStats { pool, pool_stats: pda(b"pool_stats"), authority, system_program },
+                instruction::InitPoolStats {},
+            ),
+            self.ix(
+                accounts::InitDepositHistogram {
+                    pool,
+                    deposit_histogram: pda(b"deposit_histogram"),
+                    authority,
+                    system_program,
+                },
+                instruction::InitDepositHistogram {},
+            ),
+            self.ix(
+                accounts::InitCongestionStats { pool, congestion: pda(b"congestion"), authority, system_program },
+                instruction::InitCongestionStats {},
+            ),
+        ];
+        for ix in steps {
+            // initialize and init_denominations are signed by the upgrade authority
+            let signer = ix.accounts.iter().any(|meta| meta.pubkey == upgrade_authority().pubkey() && meta.is_signer);
+            let signers: &[&Keypair] = if signer { &[&upgrade_authority()] } else { &[] };
+            self.send_signed(ix, signers).await.expect("pool setup failed");
+        }
+    }
+
+    pub async fn pool_state(&mut self) -> whistle_pool::PoolState {
+        let account = self.banks.get_account(pda(b"pool")).await.unwrap().unwrap();
+        whistle_pool::PoolState::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    /// Overwrite the pool's state in place
+    pub async fn set_pool_state(&mut self, state: &whistle_pool::PoolState) {
+        let mut account = self.banks.get_account(pda(b"pool")).await.unwrap().unwrap();
+        let mut data = Vec::new();
+        state.try_serialize(&mut data).unwrap();
+        account.data[..data.len()].copy_from_slice(&data);
+        self.set_account(pda(b"pool"), account);
+    }
+
+    pub async fn current_root(&mut self) -> [u8; 32] {
+        self.pool_state().await.current_root
+    }
+
+    pub async fn balance(&mut self, account: Pubkey) -> u64 {
+        self.banks.get_balance(account).await.unwrap()
+    }
+
+    /// Shield accounts with the payer as depositor, for a shield landing at
+    /// leaf `next_index`
+    pub fn shield_accounts(&self, next_index: u64, commitment: &[u8; 32]) -> whistle_pool::accounts::Shield {
+        whistle_pool::accounts::Shield {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            roots_history: pda(b"roots_history"),
+            pool_stats: pda(b"pool_stats"),
+            deposit_histogram: pda(b"deposit_histogram"),
+            pool_vault: pda(b"vault"),
+            fee_vault: pda(b"fee_vault"),
+            depositor: self.payer.pubkey(),
+            system_program: system_program::ID,
+            deposit_record: deposit_record(&self.payer.pubkey()),
+            leaf_page: leaf_page(whistle_pool::MerkleTreeLeafPage::page_of(next_index)),
+            commitment_marker: commitment_marker(commitment),
+        }
+    }
+
+    /// Batch shield of `commitments` from `next_index`: the first
+    /// commitment's marker goes in the Shield accounts, the others' follow
+    /// as remaining accounts
+    pub fn batch_shield_ix(&self, next_index: u64, commitments: &[[u8; 32]], data: impl InstructionData) -> Instruction {
+        let first = commitments.first().copied().unwrap_or_default();
+        let mut ix = self.ix(self.shield_accounts(next_index, &first), data);
+        ix.accounts.extend(commitments.iter().skip(1).map(|c| AccountMeta::new(commitment_marker(c), false)));
+        ix
+    }
+
+    /// Shield `amount` from the payer under `commitment`
+    pub async fn shield(&mut self, commitment: [u8; 32], amount: u64) -> std::result::Result<(), String> {
+        let next_index = self.pool_state().await.next_index;
+        let ix = self.ix(self.shield_accounts(next_index, &commitment), whistle_pool::instruction::Shield { commitment, amount });
+        self.send(ix).await
+    }
+
+    /// Route a shield of `amount` under `commitment` through the example
+    /// router: the payer funds the router PDA, which calls shield_forwarded
+    pub async fn shield_through_router(&mut self, commitment: [u8; 32], amount: u64) -> BanksTransactionResultWithMetadata {
+        let (forwarder, _) = router_pda();
+        self.send(system_instruction::transfer(&self.payer.pubkey(), &forwarder, amount)).await.unwrap();
+
+        let next_index = self.pool_state().await.next_index;
+        let shield = self.shield_accounts(next_index, &commitment);
+        let accounts = whistle_pool::accounts::ShieldForwarded {
+            pool: shield.pool,
+            merkle_tree: shield.merkle_tree,
+            roots_history: shield.roots_history,
+            pool_stats: shield.pool_stats,
+            deposit_histogram: shield.deposit_histogram,
+            pool_vault: shield.pool_vault,
+            fee_vault: shield.fee_vault,
+            forwarder,
+            system_program: shield.system_program,
+            leaf_page: shield.leaf_page,
+            commitment_marker: shield.commitment_marker,
+        };
+        let mut metas = accounts.to_account_metas(None);
+        for meta in &mut metas {
+            meta.is_signer = false;
+        }
+        metas.push(AccountMeta::new_readonly(whistle_pool::ID, false));
+        let data = [&commitment[..], &amount.to_le_bytes()].concat();
+        self.send_with_metadata(Instruction { program_id: ROUTER_ID, accounts: metas, data }).await
+    }
+
+    /// Unshield accounts spending `nullifier_hash` into `change_commitment`
+    /// (zero without change) with the given recipient and relayer; the test
+    /// payer funds the markers
+    pub fn unshield_accounts(
+        &self,
+        nullifier_hash: &[u8; 32],
+        change_commitment: &[u8; 32],
+        recipient: Pubkey,
+        relayer: Pubkey,
+    ) -> whistle_pool::accounts::Unshield {
+        whistle_pool::accounts::Unshield {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            nullifiers: pda(b"nullifiers"),
+            roots_history: pda(b"roots_history"),
+            denomination_config: pda(b"denomination_config"),
+            pool_vault: pda(b"vault"),
+            fee_vault: pda(b"fee_vault"),
+            recipient,
+            relayer: Some(relayer),
+            system_program: system_program::ID,
+            congestion: pda(b"congestion"),
+            nullifier_marker: nullifier_marker(nullifier_hash),
+            payer: self.payer.pubkey(),
+            change_marker: commitment_marker(change_commitment),
+        }
+    }
+
+    /// private_transfer accounts spending `input_nullifier_hashes` into
+    /// `output_commitments`
+    pub fn transfer_accounts(
+        &self,
+        input_nullifier_hashes: &[[u8; 32]; 2],
+        output_commitments: &[[u8; 32]; 2],
+    ) -> whistle_pool::accounts::PrivateTransfer {
+        whistle_pool::accounts::PrivateTransfer {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            nullifiers: pda(b"nullifiers"),
+            roots_history: pda(b"roots_history"),
+            input_marker_0: nullifier_marker(&input_nullifier_hashes[0]),
+            input_marker_1: nullifier_marker(&input_nullifier_hashes[1]),
+            output_marker_0: commitment_marker(&output_commitments[0]),
+            output_marker_1: commitment_marker(&output_commitments[1]),
+            payer: self.payer.pubkey(),
+            system_program: system_program::ID,
+        }
+    }
+}
+
+/// A new SPL mint with `decimals`, the payer as mint authority
+pub async fn create_mint(pool: &mut TestPool, decimals: u8) -> Pubkey {
+    let mint = Keypair::new();
+    let payer = pool.payer.pubkey();
+    let rent = pool.banks.get_rent().await.unwrap().minimum_balance(spl_token::state::Mint::LEN);
+    let create =
+        system_instruction::create_account(&payer, &mint.pubkey(), rent, spl_token::state::Mint::LEN as u64, &spl_token::id());
+    pool.send_signed(create, &[&mint]).await.unwrap();
+    let init = spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer, None, decimals).unwrap();
+    pool.send(init).await.unwrap();
+    mint.pubkey()
+}
+
+/// A new token account of `mint` for `owner`, holding `amount`
+pub async fn create_token_account(pool: &mut TestPool, mint: Pubkey, owner: Pubkey, amount: u64) -> Pubkey {
+    let account = Keypair::new();
+    let payer = pool.payer.pubkey();
+    let rent = pool.banks.get_rent().await.unwrap().minimum_balance(spl_token::state::Account::LEN);
+    let create = system_instruction::create_account(
+        &payer,
+        &account.pubkey(),
+        rent,
+        spl_token::state::Account::LEN as u64,
+        &spl_token::id(),
+    );
+    pool.send_signed(create, &[&account]).await.unwrap();
+    let init = spl_token::instruction::initialize_account3(&spl_token::id(), &account.pubkey(), &mint, &owner).unwrap();
+    pool.send(init).await.unwrap();
+    if amount > 0 {
+        let mint_to = spl_token::instruction::mint_to(&spl_token::id(), &mint, &account.pubkey(), &payer, &[], amount).unwrap();
+        pool.send(mint_to).await.unwrap();
+    }
+    account.pubkey()
+}
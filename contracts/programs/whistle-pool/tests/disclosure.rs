@@ -0,0 +1,130 @@
+//! reveal_note_amount's verifier against a real Groth16 proof: a key and
+//! proof are generated with arkworks for a circuit with amount_reveal's
+//! public inputs [commitment, amount, auditor], then checked through
+//! verify_amount_reveal_proof as the instruction calls it. A disclosure
+//! verifies only for the amount and auditor it was proven for.
+//!
+//! The amount_reveal key itself comes from the circom trusted setup, so the
+//! circuit here opens a stand-in commitment secret * (nullifier + amount)
+//! instead of the Poseidon one; the verifier only sees the public inputs.
+//!
+//! cargo test -p whistle-pool --test disclosure
+
+use ark_bn254::{Bn254, Fr};
+use ark_ff::PrimeField;
+use ark_groth16::Groth16;
+use ark_relations::lc;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_snark::SNARK;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+use ark_std::UniformRand;
+
+use anchor_lang::prelude::Pubkey;
+use whistle_groth16::host::{fr_to_be, OwnedVerificationKey};
+use whistle_groth16::Groth16Proof;
+use whistle_pool::groth16::{get_amount_reveal_vk, verify_amount_reveal_proof, AMOUNT_REVEAL_NUM_PUBLIC_INPUTS};
+use whistle_pool::public_inputs::pubkey_to_field;
+use whistle_pool::WhistleError;
+
+const AMOUNT: u64 = 5_700_000_000; // 5.7 SOL
+
+/// amount_reveal's public inputs over a stand-in commitment, with the
+/// auditor bound by squaring it as the circom circuit does
+#[derive(Clone)]
+struct AmountReveal {
+    commitment: Option<Fr>,
+    amount: Option<Fr>,
+    auditor: Option<Fr>,
+    secret: Option<Fr>,
+    nullifier: Option<Fr>,
+}
+
+impl ConstraintSynthesizer<Fr> for AmountReveal {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let missing = || SynthesisError::AssignmentMissing;
+        let commitment = cs.new_input_variable(|| self.commitment.ok_or_else(missing))?;
+        let amount = cs.new_input_variable(|| self.amount.ok_or_else(missing))?;
+        let auditor = cs.new_input_variable(|| self.auditor.ok_or_else(missing))?;
+        let secret = cs.new_witness_variable(|| self.secret.ok_or_else(missing))?;
+        let nullifier = cs.new_witness_variable(|| self.nullifier.ok_or_else(missing))?;
+        let auditor_square = cs.new_witness_variable(|| self.auditor.map(|a| a * a).ok_or_else(missing))?;
+
+        cs.enforce_constraint(lc!() + secret, lc!() + nullifier + amount, lc!() + commitment)?;
+        cs.enforce_constraint(lc!() + auditor, lc!() + auditor, lc!() + auditor_square)?;
+        Ok(())
+    }
+}
+
+struct Disclosure {
+    vk: OwnedVerificationKey,
+    proof: Groth16Proof,
+    commitment: [u8; 32],
+    auditor: Pubkey,
+}
+
+/// A proof that the note holds AMOUNT, addressed to a fresh auditor
+fn disclosure() -> Disclosure {
+    let mut rng = StdRng::seed_from_u64(716);
+    let empty = AmountReveal { commitment: None, amount: None, auditor: None, secret: None, nullifier: None };
+    let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(empty, &mut rng).unwrap();
+
+    let auditor = Pubkey::new_unique();
+    let (secret, nullifier, amount) = (Fr::rand(&mut rng), Fr::rand(&mut rng), Fr::from(AMOUNT));
+    let commitment = secret * (nullifier + amount);
+    let circuit = AmountReveal {
+        commitment: Some(commitment),
+        amount: Some(amount),
+        auditor: Some(Fr::from_be_bytes_mod_order(&pubkey_to_field(&auditor.to_bytes()))),
+        secret: Some(secret),
+        nullifier: Some(nullifier),
+    };
+    let proof = Groth16::<Bn254>::prove(&pk, circuit, &mut rng).unwrap();
+
+    Disclosure {
+        vk: OwnedVerificationKey::from(&vk),
+        proof: Groth16Proof::from(&proof),
+        commitment: fr_to_be(&commitment),
+        auditor,
+    }
+}
+
+impl Disclosure {
+    fn verify(&self, amount: u64, auditor: &Pubkey) -> anchor_lang::Result<bool> {
+        verify_amount_reveal_proof(
+            &self.vk.key(),
+            &self.proof.neg_a,
+            &self.proof.b,
+            &self.proof.c,
+            &self.commitment,
+            amount,
+            &auditor.to_bytes(),
+        )
+    }
+}
+
+#[test]
+fn disclosure_verifies_for_its_amount_and_auditor() {
+    let d = disclosure();
+    assert_eq!(d.vk.key().num_public_inputs(), AMOUNT_REVEAL_NUM_PUBLIC_INPUTS);
+    assert!(d.verify(AMOUNT, &d.auditor).unwrap());
+}
+
+#[test]
+fn disclosure_is_bound_to_the_amount() {
+    let d = disclosure();
+    for amount in [AMOUNT - 1, AMOUNT + 1, 5_000_000_000] {
+        assert_eq!(d.verify(amount, &d.auditor).unwrap_err(), WhistleError::InvalidProof.into());
+    }
+}
+
+#[test]
+fn disclosure_is_bound_to_the_auditor() {
+    let d = disclosure();
+    let other = Pubkey::new_unique();
+    assert_eq!(d.verify(AMOUNT, &other).unwrap_err(), WhistleError::InvalidProof.into());
+}
+
+#[test]
+fn embedded_key_matches_the_circuit_inputs() {
+    assert_eq!(get_amount_reveal_vk().ic.len(), AMOUNT_REVEAL_NUM_PUBLIC_INPUTS + 1);
+}
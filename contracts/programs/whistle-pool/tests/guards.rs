@@ -0,0 +1,779 @@
+//! Negative paths of the withdrawal flows: for every entry of
+//! WITHDRAWAL_GUARDS, send withdraw / withdraw_zk / unshield /
+//! unshield_eip712 / unshield_token / batch_unshield / private_transfer
+//! with the one input or account that trips that guard and check the exact
+//! error code. A second test parses the handlers out of lib.rs and token.rs
+//! and fails when one names a WhistleError the list lacks, so a new
+//! require! cannot land without its case here.
+//!
+//! cargo test -p whistle-pool --features test-harness --test guards
+
+mod common;
+
+use anchor_client::solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    keccak, system_program,
+};
+use solana_program_test::BanksClientError;
+
+use common::{
+    commitment_marker, create_mint, create_token_account, eth_key, eth_sign, leaf_page, nullifier_marker, pda,
+    recipient_field, shield_token_ix, token_vault, TestPool,
+};
+use whistle_pool::eip712::{register_hash, unshield_hash, EIP712_CHAIN_ID};
+use whistle_pool::harness::{field_u64, test_proof};
+use whistle_pool::{
+    accounts, instruction, UnshieldArgs, UnshieldParams, WhistleError, CURVE_JUBJUB, DENOM_005_SOL, DENOM_10_SOL,
+    DENOM_1_SOL, MAX_ROOT_AGE_SLOTS, UNTESTABLE_WITHDRAWAL_GUARDS, WITHDRAWAL_GUARDS,
+};
+
+const MERKLE_LEVELS: u8 = 7;
+const SHIELD_AMOUNT: u64 = 100_000_000; // 0.1 SOL
+// Mapped to the spend's recipient for unshield_eip712
+const ETH_KEY_SEED: u8 = 7;
+const OTHER_ETH_KEY_SEED: u8 = 8;
+
+fn field(domain: &[u8]) -> [u8; 32] {
+    let mut value = keccak::hash(domain).to_bytes();
+    value[0] = 0;
+    value
+}
+
+fn error_code(err: BanksClientError) -> u32 {
+    let BanksClientError::TransactionError(TransactionError::InstructionError(0, InstructionError::Custom(code))) = err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    code
+}
+
+/// Pool state a case needs beyond one shielded note
+#[derive(Clone, Copy, PartialEq)]
+enum Setup {
+    Shielded,
+    /// Nothing was shielded; the spend names the empty tree's root
+    EmptyTree,
+    /// The spend's nullifier was already withdrawn
+    SpentNullifier,
+    /// next_index is at the tree's capacity
+    FullTree,
+    /// The stored tree root differs from the pool's
+    DesyncedTree,
+    /// The spend's root was replaced more than MAX_ROOT_AGE_SLOTS ago
+    StaleRoot,
+    /// The tree's header records a deeper migration in progress
+    MigratingTree,
+    /// The pool holds Jubjub-style notes
+    JubjubPool,
+}
+
+/// What batch_unshield sends besides the spend itself
+#[derive(Clone, Copy)]
+enum Batch {
+    /// The spend alone
+    Single,
+    /// No notes at all
+    Empty,
+    /// A second note with the spend's nullifier
+    RepeatedNullifier,
+    /// A second note with the spend's change commitment
+    RepeatedChange,
+    /// A second note whose markers are missing from the remaining accounts
+    MissingMarkers,
+}
+
+/// The mint an unshield_token spend withdraws, with the recipient's token
+/// account for it and one for another mint
+#[derive(Clone, Copy)]
+struct TokenAccounts {
+    mint: Pubkey,
+    recipient_account: Pubkey,
+    other_mint_account: Pubkey,
+}
+
+/// The inputs of one spend, valid until a case changes one of them
+#[derive(Clone)]
+struct Spend {
+    recipient: Pubkey,
+    amount: u64,
+    relayer_fee: u64,
+    relayer: Option<Pubkey>,
+    nullifier_hash: [u8; 32],
+    merkle_root: [u8; 32],
+    change_commitment: [u8; 32],
+    unlock_slot: u64,
+    forge_proof: bool,
+    /// Pass another nullifier's marker account
+    wrong_marker: bool,
+    /// Pass another commitment's marker account for the change (first output)
+    wrong_change_marker: bool,
+    /// Sign the EIP-712 hash with a key other than the mapped address's
+    wrong_eth_signer: bool,
+    /// Pay the recipient's token account of another mint
+    wrong_mint_account: bool,
+    batch: Batch,
+    /// Set for unshield_token spends
+    token: Option<TokenAccounts>,
+    /// The pool's next leaf, whose page unshield_token appends the change to
+    next_index: u64,
+}
+
+struct Case {
+    handler: &'static str,
+    error: WhistleError,
+    setup: Setup,
+    mutate: fn(&mut Spend),
+}
+
+fn case(handler: &'static str, error: WhistleError, setup: Setup, mutate: fn(&mut Spend)) -> Case {
+    Case { handler, error, setup, mutate }
+}
+
+/// The table: at least one minimally-invalid spend per (handler, guard)
+///
+/// withdraw_zk verifies against the real withdraw_simple key, so every
+/// withdraw_zk proof here is invalid and its InvalidProof case is the
+/// baseline spend itself.
+fn cases() -> Vec<Case> {
+    use Setup::*;
+    use WhistleError::*;
+
+    let invalid_denomination: fn(&mut Spend) = |spend| spend.amount = DENOM_005_SOL + 1;
+    let fee_too_high: fn(&mut Spend) = |spend| spend.relayer_fee = spend.amount;
+    let zero_root: fn(&mut Spend) = |spend| spend.merkle_root = [0u8; 32];
+    let unknown_root: fn(&mut Spend) = |spend| spend.merkle_root = field(b"unknown root");
+    let forged_proof: fn(&mut Spend) = |spend| spend.forge_proof = true;
+    // 10 SOL is more than the vault holds
+    let drains_vault: fn(&mut Spend) = |spend| spend.amount = DENOM_10_SOL;
+    // The vault covers 1 SOL, but total_shielded (0.1 SOL) does not
+    let exceeds_shielded: fn(&mut Spend) = |spend| spend.amount = DENOM_1_SOL;
+    let no_relayer: fn(&mut Spend) = |spend| {
+        spend.relayer = None;
+        spend.relayer_fee = 1;
+    };
+    let non_canonical: fn(&mut Spend) = |spend| spend.nullifier_hash = [0xff; 32];
+    let locked: fn(&mut Spend) = |spend| spend.unlock_slot = u64::MAX;
+    let wrong_marker: fn(&mut Spend) = |spend| spend.wrong_marker = true;
+    // The change re-inserts the note prepare() shielded
+    let duplicate_change: fn(&mut Spend) = |spend| spend.change_commitment = field(b"commitment");
+    let wrong_change_marker: fn(&mut Spend) = |spend| spend.wrong_change_marker = true;
+    let zero_nullifier: fn(&mut Spend) = |spend| spend.nullifier_hash = [0u8; 32];
+    // Neither mapped to the Ethereum address nor the owner of the token accounts
+    let other_recipient: fn(&mut Spend) = |spend| spend.recipient = Pubkey::new_unique();
+    let unchanged: fn(&mut Spend) = |_| {};
+
+    vec![
+        case("withdraw", InvalidWithdrawDenomination, Shielded, invalid_denomination),
+        case("withdraw", FeeTooHigh, Shielded, fee_too_high),
+        case("withdraw", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("withdraw", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("withdraw", ZeroMerkleRoot, Shielded, zero_root),
+        case("withdraw", TreeEmpty, EmptyTree, unchanged),
+        case("withdraw", InvalidMerkleRoot, Shielded, unknown_root),
+        case("withdraw", StaleMerkleRoot, StaleRoot, unchanged),
+        case("withdraw", InvalidProof, Shielded, forged_proof),
+        case("withdraw", InsufficientVaultBalance, Shielded, drains_vault),
+        case("withdraw", ArithmeticOverflow, Shielded, exceeds_shielded),
+        case("withdraw", MissingRelayer, Shielded, no_relayer),
+        case("withdraw", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("withdraw", NoteStillLocked, Shielded, locked),
+        case("withdraw", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("withdraw", UnsupportedCurve, JubjubPool, unchanged),
+        case("withdraw_zk", InvalidWithdrawDenomination, Shielded, invalid_denomination),
+        case("withdraw_zk", FeeTooHigh, Shielded, fee_too_high),
+        case("withdraw_zk", ZeroMerkleRoot, Shielded, zero_root),
+        case("withdraw_zk", TreeEmpty, EmptyTree, unchanged),
+        case("withdraw_zk", InvalidMerkleRoot, Shielded, unknown_root),
+        case("withdraw_zk", StaleMerkleRoot, StaleRoot, unchanged),
+        case("withdraw_zk", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("withdraw_zk", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("withdraw_zk", InsufficientVaultBalance, Shielded, drains_vault),
+        case("withdraw_zk", InvalidProof, Shielded, unchanged),
+        case("withdraw_zk", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("withdraw_zk", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("withdraw_zk", UnsupportedCurve, JubjubPool, unchanged),
+        case("unshield", InvalidWithdrawDenomination, Shielded, invalid_denomination),
+        case("unshield", FeeTooHigh, Shielded, fee_too_high),
+        case("unshield", WeakChangeCommitment, Shielded, |spend| spend.change_commitment = spend.nullifier_hash),
+        case("unshield", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("unshield", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("unshield", ZeroMerkleRoot, Shielded, zero_root),
+        case("unshield", TreeEmpty, EmptyTree, unchanged),
+        case("unshield", InvalidMerkleRoot, Shielded, unknown_root),
+        case("unshield", StaleMerkleRoot, StaleRoot, unchanged),
+        case("unshield", InvalidProof, Shielded, forged_proof),
+        case("unshield", TreeFull, FullTree, unchanged),
+        case("unshield", InsufficientVaultBalance, Shielded, drains_vault),
+        case("unshield", ArithmeticOverflow, Shielded, exceeds_shielded),
+        case("unshield", MissingRelayer, Shielded, no_relayer),
+        case("unshield", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("unshield", NoteStillLocked, Shielded, locked),
+        case("unshield", TreeStateDesync, DesyncedTree, unchanged),
+        case("unshield", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("unshield", PoolMigrationInProgress, MigratingTree, unchanged),
+        case("unshield", DuplicateCommitment, Shielded, duplicate_change),
+        case("unshield", InvalidCommitmentMarker, Shielded, wrong_change_marker),
+        case("unshield", UnsupportedCurve, JubjubPool, unchanged),
+        case("unshield_eip712", InvalidEthSignature, Shielded, |spend| spend.wrong_eth_signer = true),
+        case("unshield_eip712", EthAddressNotAuthorized, Shielded, other_recipient),
+        case("unshield_eip712", InvalidWithdrawDenomination, Shielded, invalid_denomination),
+        case("unshield_eip712", FeeTooHigh, Shielded, fee_too_high),
+        case("unshield_eip712", WeakChangeCommitment, Shielded, |spend| spend.change_commitment = spend.nullifier_hash),
+        case("unshield_eip712", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("unshield_eip712", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("unshield_eip712", ZeroMerkleRoot, Shielded, zero_root),
+        case("unshield_eip712", TreeEmpty, EmptyTree, unchanged),
+        case("unshield_eip712", InvalidMerkleRoot, Shielded, unknown_root),
+        case("unshield_eip712", StaleMerkleRoot, StaleRoot, unchanged),
+        case("unshield_eip712", InvalidProof, Shielded, forged_proof),
+        case("unshield_eip712", TreeFull, FullTree, unchanged),
+        case("unshield_eip712", InsufficientVaultBalance, Shielded, drains_vault),
+        case("unshield_eip712", ArithmeticOverflow, Shielded, exceeds_shielded),
+        case("unshield_eip712", MissingRelayer, Shielded, no_relayer),
+        case("unshield_eip712", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("unshield_eip712", NoteStillLocked, Shielded, locked),
+        case("unshield_eip712", TreeStateDesync, DesyncedTree, unchanged),
+        case("unshield_eip712", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("unshield_eip712", PoolMigrationInProgress, MigratingTree, unchanged),
+        case("unshield_eip712", DuplicateCommitment, Shielded, duplicate_change),
+        case("unshield_eip712", InvalidCommitmentMarker, Shielded, wrong_change_marker),
+        case("unshield_eip712", UnsupportedCurve, JubjubPool, unchanged),
+        case("unshield_token", MintMismatch, Shielded, |spend| spend.wrong_mint_account = true),
+        case("unshield_token", InvalidTokenAccount, Shielded, other_recipient),
+        case("unshield_token", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("unshield_token", WeakChangeCommitment, Shielded, |spend| spend.change_commitment = spend.nullifier_hash),
+        case("unshield_token", ZeroMerkleRoot, Shielded, zero_root),
+        case("unshield_token", TreeEmpty, EmptyTree, unchanged),
+        case("unshield_token", InvalidProof, Shielded, forged_proof),
+        case("unshield_token", TreeFull, FullTree, unchanged),
+        case("unshield_token", InsufficientVaultBalance, Shielded, drains_vault),
+        case("unshield_token", InvalidSplDenomination, Shielded, invalid_denomination),
+        case("unshield_token", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("unshield_token", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("unshield_token", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("unshield_token", InvalidMerkleRoot, Shielded, unknown_root),
+        case("unshield_token", StaleMerkleRoot, StaleRoot, unchanged),
+        case("unshield_token", TreeStateDesync, DesyncedTree, unchanged),
+        case("unshield_token", PoolMigrationInProgress, MigratingTree, unchanged),
+        case("unshield_token", DuplicateCommitment, Shielded, duplicate_change),
+        case("unshield_token", InvalidCommitmentMarker, Shielded, wrong_change_marker),
+        case("unshield_token", UnsupportedCurve, JubjubPool, unchanged),
+        case("batch_unshield", InvalidBatchSize, Shielded, |spend| spend.batch = Batch::Empty),
+        case("batch_unshield", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("batch_unshield", InvalidNullifierMarker, Shielded, |spend| spend.batch = Batch::MissingMarkers),
+        case("batch_unshield", FeeTooHigh, Shielded, fee_too_high),
+        case("batch_unshield", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("batch_unshield", WeakChangeCommitment, Shielded, |spend| spend.change_commitment = spend.nullifier_hash),
+        case("batch_unshield", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("batch_unshield", NullifierAlreadyUsed, Shielded, |spend| spend.batch = Batch::RepeatedNullifier),
+        case("batch_unshield", DuplicateCommitment, Shielded, duplicate_change),
+        case("batch_unshield", DuplicateCommitment, Shielded, |spend| spend.batch = Batch::RepeatedChange),
+        case("batch_unshield", ArithmeticOverflow, Shielded, exceeds_shielded),
+        case("batch_unshield", ZeroMerkleRoot, Shielded, zero_root),
+        case("batch_unshield", TreeEmpty, EmptyTree, unchanged),
+        case("batch_unshield", InvalidProof, Shielded, forged_proof),
+        case("batch_unshield", TreeFull, FullTree, unchanged),
+        case("batch_unshield", InsufficientVaultBalance, Shielded, drains_vault),
+        case("batch_unshield", InvalidWithdrawDenomination, Shielded, invalid_denomination),
+        case("batch_unshield", MissingRelayer, Shielded, no_relayer),
+        case("batch_unshield", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("batch_unshield", InvalidMerkleRoot, Shielded, unknown_root),
+        case("batch_unshield", StaleMerkleRoot, StaleRoot, unchanged),
+        case("batch_unshield", TreeStateDesync, DesyncedTree, unchanged),
+        case("batch_unshield", PoolMigrationInProgress, MigratingTree, unchanged),
+        case("batch_unshield", InvalidCommitmentMarker, Shielded, wrong_change_marker),
+        case("batch_unshield", UnsupportedCurve, JubjubPool, unchanged),
+        case("private_transfer", ZeroMerkleRoot, Shielded, zero_root),
+        case("private_transfer", TreeEmpty, EmptyTree, unchanged),
+        case("private_transfer", InvalidMerkleRoot, Shielded, unknown_root),
+        case("private_transfer", StaleMerkleRoot, StaleRoot, unchanged),
+        case("private_transfer", NullifierAlreadyUsed, SpentNullifier, unchanged),
+        case("private_transfer", InvalidNullifierMarker, Shielded, wrong_marker),
+        case("private_transfer", InvalidProof, Shielded, forged_proof),
+        case("private_transfer", TreeFull, FullTree, unchanged),
+        case("private_transfer", NonCanonicalFieldElement, Shielded, non_canonical),
+        case("private_transfer", NoteStillLocked, Shielded, locked),
+        case("private_transfer", TreeStateDesync, DesyncedTree, unchanged),
+        case("private_transfer", ZeroNullifierHash, Shielded, zero_nullifier),
+        case("private_transfer", PoolMigrationInProgress, MigratingTree, unchanged),
+        case("private_transfer", DuplicateCommitment, Shielded, duplicate_change),
+        case("private_transfer", InvalidCommitmentMarker, Shielded, wrong_change_marker),
+        case("private_transfer", UnsupportedCurve, JubjubPool, unchanged),
+    ]
+}
+
+fn proof(spend: &Spend, public_inputs: &[[u8; 32]]) -> [u8; 64] {
+    if spend.forge_proof {
+        [0u8; 64]
+    } else {
+        test_proof(public_inputs)
+    }
+}
+
+/// unshield's arguments for `spend`, also what unshield_eip712 signs
+fn unshield_args(spend: &Spend) -> UnshieldArgs {
+    UnshieldArgs {
+        proof_a: proof(spend, &[
+            spend.merkle_root,
+            spend.nullifier_hash,
+            recipient_field(&spend.recipient),
+            field_u64(spend.amount),
+            field_u64(spend.relayer_fee),
+            spend.change_commitment,
+            field_u64(spend.unlock_slot),
+        ]),
+        proof_b: [0u8; 128],
+        proof_c: [0u8; 64],
+        nullifier_hash: spend.nullifier_hash,
+        recipient: spend.recipient,
+        withdrawal_amount: spend.amount,
+        relayer_fee: spend.relayer_fee,
+        merkle_root: spend.merkle_root,
+        change_commitment: spend.change_commitment,
+        unlock_slot: spend.unlock_slot,
+    }
+}
+
+fn eth_mapping(eth_address: &[u8; 20]) -> Pubkey {
+    Pubkey::find_program_address(&[b"eth_address".as_ref(), eth_address.as_ref()], &whistle_pool::ID).0
+}
+
+fn spend_ix(pool: &TestPool, handler: &str, spend: &Spend) -> Instruction {
+    let recipient = spend.recipient;
+    let marker = if spend.wrong_marker {
+        nullifier_marker(&field(b"other nullifier"))
+    } else {
+        nullifier_marker(&spend.nullifier_hash)
+    };
+    let change_marker = if spend.wrong_change_marker {
+        commitment_marker(&field(b"other commitment"))
+    } else {
+        commitment_marker(&spend.change_commitment)
+    };
+    let mut unshield_accounts = pool.unshield_accounts(&spend.nullifier_hash, &spend.change_commitment, recipient, recipient);
+    unshield_accounts.relayer = spend.relayer;
+    unshield_accounts.nullifier_marker = marker;
+    unshield_accounts.change_marker = change_marker;
+
+    match handler {
+        "withdraw" => {
+            let proof_a = proof(spend, &[
+                spend.merkle_root,
+                spend.nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(spend.amount),
+                field_u64(spend.relayer_fee),
+                field_u64(spend.unlock_slot),
+            ]);
+            pool.ix(
+                unshield_accounts,
+                instruction::Withdraw {
+                    proof_a,
+                    proof_b: [0u8; 128],
+                    proof_c: [0u8; 64],
+                    nullifier_hash: spend.nullifier_hash,
+                    recipient,
+                    amount: spend.amount,
+                    relayer_fee: spend.relayer_fee,
+                    merkle_root: spend.merkle_root,
+                    unlock_slot: spend.unlock_slot,
+                },
+            )
+        }
+        "withdraw_zk" => pool.ix(
+            accounts::WithdrawZk {
+                pool: pda(b"pool"),
+                nullifiers: pda(b"nullifiers"),
+                roots_history: pda(b"roots_history"),
+                denomination_config: pda(b"denomination_config"),
+                pool_vault: pda(b"vault"),
+                recipient,
+                relayer: spend.relayer.unwrap_or(recipient),
+                system_program: system_program::ID,
+                congestion: pda(b"congestion"),
+                nullifier_marker: marker,
+                payer: pool.payer.pubkey(),
+            },
+            instruction::WithdrawZk {
+                proof_a: [0u8; 64],
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                commitment: field(b"commitment"),
+                nullifier_hash: spend.nullifier_hash,
+                recipient,
+                amount: spend.amount,
+                relayer_fee: spend.relayer_fee,
+                merkle_root: spend.merkle_root,
+            },
+        ),
+        "unshield" => {
+            let args = unshield_args(spend);
+            pool.ix(
+                unshield_accounts,
+                instruction::Unshield {
+                    proof_a: args.proof_a,
+                    proof_b: args.proof_b,
+                    proof_c: args.proof_c,
+                    nullifier_hash: args.nullifier_hash,
+                    recipient: args.recipient,
+                    withdrawal_amount: args.withdrawal_amount,
+                    relayer_fee: args.relayer_fee,
+                    merkle_root: args.merkle_root,
+                    change_commitment: args.change_commitment,
+                    unlock_slot: args.unlock_slot,
+                },
+            )
+        }
+        "unshield_eip712" => {
+            let args = unshield_args(spend);
+            let eth_address = eth_key(ETH_KEY_SEED).1;
+            let (signer, _) = eth_key(if spend.wrong_eth_signer { OTHER_ETH_KEY_SEED } else { ETH_KEY_SEED });
+            let eth_signature = eth_sign(&signer, &unshield_hash(&args, EIP712_CHAIN_ID));
+            pool.ix(
+                accounts::UnshieldEip712 { eth_mapping: eth_mapping(&eth_address), unshield: unshield_accounts },
+                instruction::UnshieldEip712 {
+                    proof_a: args.proof_a,
+                    proof_b: args.proof_b,
+                    proof_c: args.proof_c,
+                    nullifier_hash: args.nullifier_hash,
+                    recipient: args.recipient,
+                    withdrawal_amount: args.withdrawal_amount,
+                    relayer_fee: args.relayer_fee,
+                    merkle_root: args.merkle_root,
+                    change_commitment: args.change_commitment,
+                    unlock_slot: args.unlock_slot,
+                    eth_signature,
+                    eth_address,
+                },
+            )
+        }
+        "unshield_token" => {
+            let token = spend.token.expect("prepare() creates the token accounts for unshield_token");
+            let proof_a = proof(spend, &[
+                spend.merkle_root,
+                spend.nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(spend.amount),
+                recipient_field(&token.mint),
+                spend.change_commitment,
+            ]);
+            pool.ix(
+                accounts::UnshieldToken {
+                    pool: pda(b"pool"),
+                    merkle_tree: pda(b"merkle_tree"),
+                    nullifiers: pda(b"nullifiers"),
+                    roots_history: pda(b"roots_history"),
+                    mint: token.mint,
+                    token_vault: token_vault(&token.mint),
+                    recipient_token_account: if spend.wrong_mint_account {
+                        token.other_mint_account
+                    } else {
+                        token.recipient_account
+                    },
+                    token_program: spl_token::id(),
+                    system_program: system_program::ID,
+                    nullifier_marker: marker,
+                    payer: pool.payer.pubkey(),
+                    leaf_page: leaf_page(whistle_pool::MerkleTreeLeafPage::page_of(spend.next_index)),
+                    change_marker,
+                },
+                instruction::UnshieldToken {
+                    proof_a,
+                    proof_b: [0u8; 128],
+                    proof_c: [0u8; 64],
+                    nullifier_hash: spend.nullifier_hash,
+                    recipient,
+                    amount: spend.amount,
+                    merkle_root: spend.merkle_root,
+                    change_commitment: spend.change_commitment,
+                },
+            )
+        }
+        "batch_unshield" => {
+            let note = |nullifier_hash: [u8; 32], change_commitment: [u8; 32]| UnshieldParams {
+                proof_a: proof(spend, &[
+                    spend.merkle_root,
+                    nullifier_hash,
+                    recipient_field(&recipient),
+                    field_u64(spend.amount),
+                    field_u64(spend.relayer_fee),
+                    change_commitment,
+                    field_u64(0),
+                ]),
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                nullifier_hash,
+                withdrawal_amount: spend.amount,
+                relayer_fee: spend.relayer_fee,
+                change_commitment,
+            };
+            let first = note(spend.nullifier_hash, spend.change_commitment);
+            let notes = match spend.batch {
+                Batch::Single => vec![first],
+                Batch::Empty => vec![],
+                Batch::RepeatedNullifier => vec![first.clone(), first],
+                Batch::RepeatedChange => vec![first, note(field(b"second nullifier"), spend.change_commitment)],
+                Batch::MissingMarkers => vec![first, note(field(b"second nullifier"), [0u8; 32])],
+            };
+            let mut ix = pool.ix(
+                unshield_accounts,
+                instruction::BatchUnshield { proofs: notes.clone(), merkle_root: spend.merkle_root },
+            );
+            if !matches!(spend.batch, Batch::MissingMarkers) {
+                let rest = notes.iter().skip(1);
+                ix.accounts.extend(rest.clone().map(|n| AccountMeta::new(nullifier_marker(&n.nullifier_hash), false)));
+                ix.accounts.extend(rest.map(|n| AccountMeta::new(commitment_marker(&n.change_commitment), false)));
+            }
+            ix
+        }
+        "private_transfer" => {
+            // A zero first input leaves both slots unused, since a lone zero
+            // input is just an empty slot
+            let second_nullifier = if spend.nullifier_hash == [0u8; 32] {
+                [0u8; 32]
+            } else {
+                field(b"second nullifier")
+            };
+            let input_nullifier_hashes = [spend.nullifier_hash, second_nullifier];
+            let output_commitments = [spend.change_commitment, field(b"second output")];
+            let unlock_slots = [spend.unlock_slot, 0];
+            let proof_a = proof(spend, &[
+                spend.merkle_root,
+                input_nullifier_hashes[0],
+                input_nullifier_hashes[1],
+                output_commitments[0],
+                output_commitments[1],
+                field_u64(unlock_slots[0]),
+                field_u64(unlock_slots[1]),
+            ]);
+            let mut transfer_accounts = pool.transfer_accounts(&input_nullifier_hashes, &output_commitments);
+            transfer_accounts.input_marker_0 = marker;
+            transfer_accounts.output_marker_0 = change_marker;
+            pool.ix(
+                transfer_accounts,
+                instruction::PrivateTransfer {
+                    proof_a,
+                    proof_b: [0u8; 128],
+                    proof_c: [0u8; 64],
+                    input_nullifier_hashes,
+                    output_commitments,
+                    merkle_root: spend.merkle_root,
+                    unlock_slots,
+                },
+            )
+        }
+        _ => panic!("no instruction builder for {handler}"),
+    }
+}
+
+/// A new 6-decimal mint with 0.1 SOL worth of units shielded (unless
+/// `shield` is false), and `recipient`'s token accounts
+async fn prepare_token(pool: &mut TestPool, recipient: Pubkey, shield: bool) -> TokenAccounts {
+    let payer = pool.payer.pubkey();
+    let mint = create_mint(pool, 6).await;
+    let other_mint = create_mint(pool, 6).await;
+    if shield {
+        let source = create_token_account(pool, mint, payer, SHIELD_AMOUNT).await;
+        let next_index = pool.pool_state().await.next_index;
+        let ix = shield_token_ix(pool, next_index, mint, source, field(b"token commitment"), SHIELD_AMOUNT);
+        pool.send(ix).await.unwrap();
+    }
+    TokenAccounts {
+        mint,
+        recipient_account: create_token_account(pool, mint, recipient, 0).await,
+        other_mint_account: create_token_account(pool, other_mint, recipient, 0).await,
+    }
+}
+
+/// A fresh pool holding one 0.1 SOL note (none for EmptyTree), prepared for
+/// `handler` and `setup`, and the spend that withdraws 0.05 SOL of that
+/// note (0.05 SOL worth of units of a token note for unshield_token)
+async fn prepare(handler: &str, setup: Setup) -> (TestPool, Spend) {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let shielded = !matches!(setup, Setup::EmptyTree);
+    if shielded {
+        pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    }
+    let recipient = Keypair::new().pubkey();
+    let token = match handler {
+        "unshield_token" => Some(prepare_token(&mut pool, recipient, shielded).await),
+        _ => None,
+    };
+    if handler == "unshield_eip712" {
+        let (secret, eth_address) = eth_key(ETH_KEY_SEED);
+        let register = pool.ix(
+            accounts::RegisterEthAddress {
+                eth_mapping: eth_mapping(&eth_address),
+                payer: pool.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::RegisterEthAddress {
+                eth_address,
+                authorized: recipient,
+                eth_signature: eth_sign(&secret, &register_hash(&recipient, 0, EIP712_CHAIN_ID)),
+            },
+        );
+        pool.send(register).await.unwrap();
+    }
+
+    let mut spend = Spend {
+        recipient,
+        amount: DENOM_005_SOL,
+        relayer_fee: 0,
+        relayer: Some(Keypair::new().pubkey()),
+        nullifier_hash: field(b"nullifier"),
+        merkle_root: pool.current_root().await,
+        change_commitment: field(b"change"),
+        unlock_slot: 0,
+        forge_proof: false,
+        wrong_marker: false,
+        wrong_change_marker: false,
+        wrong_eth_signer: false,
+        wrong_mint_account: false,
+        batch: Batch::Single,
+        token,
+        next_index: 0,
+    };
+
+    match setup {
+        Setup::Shielded | Setup::EmptyTree => {}
+        Setup::SpentNullifier => {
+            // Paid elsewhere, so the case's own withdraw is not the same transaction
+            spend.recipient = Keypair::new().pubkey();
+            let withdraw = spend_ix(&pool, "withdraw", &spend);
+            pool.send(withdraw).await.unwrap();
+            spend.recipient = recipient;
+        }
+        Setup::FullTree => {
+            let mut state = pool.pool_state().await;
+            state.next_index = 1 << MERKLE_LEVELS;
+            pool.set_pool_state(&state).await;
+        }
+        Setup::JubjubPool => {
+            let mut state = pool.pool_state().await;
+            state.curve = CURVE_JUBJUB;
+            pool.set_pool_state(&state).await;
+        }
+        Setup::StaleRoot => {
+            pool.warp_slots(MAX_ROOT_AGE_SLOTS + 1).await;
+            pool.shield(field(b"newer commitment"), SHIELD_AMOUNT).await.unwrap();
+        }
+        Setup::DesyncedTree => {
+            let mut account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+            let root = 8 + whistle_pool::MERKLE_TREE_HEADER_SIZE;
+            account.data[root..root + 32].copy_from_slice(&field(b"desynced root"));
+            pool.set_account(pda(b"merkle_tree"), account);
+        }
+        Setup::MigratingTree => {
+            let mut account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+            account.data[8 + 1] = MERKLE_LEVELS + 1; // levels_used
+            pool.set_account(pda(b"merkle_tree"), account);
+        }
+    }
+    spend.next_index = pool.pool_state().await.next_index;
+    (pool, spend)
+}
+
+#[tokio::test]
+async fn every_withdrawal_guard_rejects_its_case() {
+    let cases = cases();
+    for (handler, errors) in WITHDRAWAL_GUARDS {
+        for error in *errors {
+            let code = u32::from(*error);
+            assert!(
+                cases.iter().any(|case| case.handler == *handler && u32::from(case.error) == code),
+                "no negative test for {handler} / {error:?}"
+            );
+        }
+
+        // A rejected spend leaves the pool as it was, so the cases of one
+        // setup share a pool (program-test never frees a bank's threads)
+        let mut setups: Vec<Setup> = Vec::new();
+        for case in cases.iter().filter(|case| case.handler == *handler) {
+            if !setups.contains(&case.setup) {
+                setups.push(case.setup);
+            }
+        }
+        for setup in setups {
+            let (mut pool, prepared) = prepare(handler, setup).await;
+            for case in cases.iter().filter(|case| case.handler == *handler && case.setup == setup) {
+                let error = case.error;
+                let mut spend = prepared.clone();
+                (case.mutate)(&mut spend);
+                let ix = spend_ix(&pool, handler, &spend);
+                let err = pool.send_result(ix).await.err().unwrap_or_else(|| panic!("{handler} / {error:?} succeeded"));
+                assert_eq!(error_code(err), u32::from(error), "{handler} / {error:?}");
+            }
+        }
+    }
+
+    // ...and the table has no case for a guard the list dropped
+    for case in &cases {
+        let listed = WITHDRAWAL_GUARDS
+            .iter()
+            .any(|(handler, errors)| *handler == case.handler && errors.iter().any(|e| u32::from(*e) == u32::from(case.error)));
+        assert!(listed, "{} / {:?} is not in WITHDRAWAL_GUARDS", case.handler, case.error);
+    }
+}
+
+/// Body of the function whose definition starts with `signature`
+fn function_body<'a>(source: &'a str, signature: &str) -> &'a str {
+    let start = source.find(signature).unwrap_or_else(|| panic!("{signature} not found"));
+    let open = start + source[start..].find('{').unwrap();
+    let mut depth = 0;
+    for (offset, c) in source[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &source[open..=open + offset];
+                }
+            }
+            _ => {}
+        }
+    }
+    panic!("unbalanced braces after {signature}");
+}
+
+#[test]
+fn guard_list_covers_every_handler_error() {
+    let lib = include_str!("../src/lib.rs");
+    let token = include_str!("../src/token.rs");
+    let bodies = |handler: &str| match handler {
+        "withdraw" => vec![(lib, "pub fn withdraw(")],
+        "withdraw_zk" => vec![(lib, "pub fn withdraw_zk(")],
+        "unshield" => vec![(lib, "pub fn unshield("), (lib, "fn process_unshield(")],
+        "unshield_eip712" => vec![(lib, "pub fn unshield_eip712("), (lib, "fn process_unshield(")],
+        "unshield_token" => vec![(token, "pub fn unshield_token(")],
+        "batch_unshield" => vec![(lib, "pub fn batch_unshield<'info>(")],
+        "private_transfer" => vec![(lib, "pub fn private_transfer("), (lib, "fn process_private_transfer<'info>(")],
+        _ => panic!("no handler body for {handler}"),
+    };
+
+    for (handler, errors) in WITHDRAWAL_GUARDS {
+        let mut known: Vec<String> = errors.iter().map(|error| format!("{error:?}")).collect();
+        known.extend(
+            UNTESTABLE_WITHDRAWAL_GUARDS
+                .iter()
+                .filter(|(untestable, _)| untestable == handler)
+                .map(|(_, error)| format!("{error:?}")),
+        );
+
+        for (source, signature) in bodies(handler) {
+            for name in function_body(source, signature).split("WhistleError::").skip(1) {
+                let name: String = name.chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+                assert!(
+                    known.contains(&name),
+                    "{handler} can fail with WhistleError::{name}, which WITHDRAWAL_GUARDS does not list"
+                );
+            }
+        }
+    }
+}
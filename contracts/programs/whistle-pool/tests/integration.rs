@@ -0,0 +1,3716 @@
+//! Deposit-to-withdrawal round trip through the real PDAs: shield a note,
+//! find it in the Merkle tree, withdraw it with a test-backend proof, then
+//! check the payouts, the nullifier set and its spend-slot history, and
+//! double-spend rejection. Also checks per-denomination anonymity set
+//! sizes, per-band shield counts rolling forward across epochs, the
+//! nullifier markers spends create, batch_nullifier_status setting the bit
+//! of each spent hash, the frozen legacy nullifier set still rejecting its
+//! hashes, spends to a marker address pre-funded by someone else, the
+//! reserve snapshot against pool state after a mixed workload,
+//! verify_reserves flagging a short vault, a gap in the leaf layer and an
+//! overcounted nullifier set, the pre-commit / reveal / expiry paths for
+//! large shields, rejection of change notes derived from the spent
+//! nullifier hash, commitment markers rejecting a commitment shielded twice
+//! or reused as change, rejection of the zero commitment and of
+//! non-canonical commitments by every shield path, deposit matching shield,
+//! nullifier spend slots and root validity read through simulation, history
+//! roots expiring after MAX_ROOT_AGE_SLOTS, the roots history migration and
+//! resize, the v0 to v1 merkle tree migration, the roots ring wrapping
+//! around, the zero root never matching an unfilled roots history slot,
+//! withdrawals to a program-owned PDA, the fee-free self-relayed path,
+//! unshields authorized by an Ethereum wallet's EIP-712 signature, the
+//! deposit caps, shields forwarded through a router program, and ordering /
+//! rollback of private transfers packed into one transaction,
+//! total_shielded accounting for an unshield with change, cloning a pool
+//! through export_state_chunk / import_state_chunk, slashing relayers that
+//! leave an approved withdrawal unsubmitted, intent locks contended, lapsed
+//! and taken over, the client Poseidon compatibility check, congestion
+//! counts of approvals and withdrawals per window, TreeStateDesync
+//! detection with rebuild_root repair, whistle-merkle verifying Merkle
+//! paths of the pool's tree and its precomputed zero values, withdraw
+//! verification keys selected by denomination, SPL denomination validation,
+//! Unshielded receipt hashes for payment confirmation, leaf pages written
+//! by shields and filled in by sync_leaf_page, Merkle paths served by
+//! generate_merkle_proof, encrypted notes stored for wallet recovery, view
+//! keys checking disclosed notes until revoked, time-locked notes in every
+//! spend path, finality attestations for both upgrade authority states,
+//! atomic denomination swaps between two parties, tree root disputes
+//! defended against a consistent tree and upheld against a corrupted one,
+//! four-note batch withdrawals, batch unshields paying out every note or
+//! none, eight-note batch shields, batch shields of public amounts, relayer
+//! fees at, below and above each denomination's cap and the cap table set
+//! only by the upgrade authority, pool initialization restricted to the
+//! upgrade authority, capacity warnings as the tree fills, full pools
+//! migrating to a deeper tree, SPL token notes kept apart by mint, stored
+//! PDA bumps with their migration and imposter rejection, the tree event
+//! layouts the SDK decodes, and the pool's roots at depths 7 and 13 against
+//! a padded full tree.
+//!
+//! cargo test -p whistle-pool --features test-harness --test integration
+
+mod common;
+
+use anchor_client::solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use anchor_lang::prelude::{Pubkey, Rent};
+use anchor_lang::solana_program::poseidon::{hashv as poseidon_hashv, Endianness, Parameters};
+use anchor_lang::solana_program::{
+    hash::hash, instruction::{AccountMeta, Instruction}, keccak, program_pack::Pack, system_instruction,
+    system_program,
+};
+use anchor_lang::{AccountDeserialize, AccountSerialize, AnchorDeserialize, AnchorSerialize, InstructionData};
+use solana_program_test::BanksClientError;
+
+use common::{
+    commitment_marker, create_mint, create_token_account, deposit_record, eth_key, eth_sign, event_data, leaf_page,
+    nullifier_marker, pda, program_data_account, program_data_address, recipient_field, router_pda, shield_token_ix,
+    token_vault, upgrade_authority, zero_copy_account, TestPool, ROUTER_ID, VAULT_GENESIS_LAMPORTS,
+};
+use whistle_merkle::MerklePath;
+use whistle_pool::harness::{field_u64, test_proof};
+use whistle_pool::{
+    accounts, instruction, AnonymityMetrics, CapacityWarning, CommitmentMarker, CongestionInfo, EncryptedNote, FinalityAttestation, ReserveSnapshot, ReservesReport, ReservesVerified, RootsHistory, RootsRing, SwapIntent, TransferLeg, TreeDispute, UnshieldParams, WhistleError, DEFAULT_RELAYER_FEE_CAPS_BPS, DENOMINATIONS,
+    BPS_DENOMINATOR, DISPUTE_RANGE_LEAVES, DISPUTE_RESPONSE_SLOTS, MAX_LEAF_READ, MIN_DISPUTE_BOND, PRE_COMMIT_REVEAL_SLOTS,
+    MAX_BATCH_SHIELD, MAX_NOTE_CIPHERTEXT, MAX_ROOT_AGE_SLOTS, MIN_DEPOSIT, PRE_COMMIT_STAKE, PROTOCOL_FEE_BPS, RESERVE_SNAPSHOT_VERSION,
+    MAX_ROOTS_HISTORY_CAPACITY, MAX_ROOTS_HISTORY_GROWTH, ROOTS_HISTORY_CAPACITY, PoolStats, STATS_EPOCH_SLOTS, STATS_RING_EPOCHS,
+};
+
+const MERKLE_LEVELS: u8 = 7;
+const SHIELD_AMOUNT: u64 = 100_000_000; // 0.1 SOL
+
+// The note holds 0.1 SOL less the 0.04% shield fee, so 0.05 SOL is the
+// largest denomination it covers
+const WITHDRAW_AMOUNT: u64 = whistle_pool::DENOM_005_SOL;
+
+fn field(domain: &[u8]) -> [u8; 32] {
+    let mut value = keccak::hash(domain).to_bytes();
+    value[0] = 0;
+    value
+}
+
+/// Index of the failing instruction and its custom error code
+fn instruction_error(err: BanksClientError) -> (u8, u32) {
+    let BanksClientError::TransactionError(TransactionError::InstructionError(index, InstructionError::Custom(code))) =
+        err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    (index, code)
+}
+
+fn error_code(err: BanksClientError) -> u32 {
+    let (index, code) = instruction_error(err);
+    assert_eq!(index, 0);
+    code
+}
+
+/// batch_nullifier_status for `nullifier_hashes`, with their markers
+fn nullifier_status_ix(pool: &TestPool, nullifier_hashes: [[u8; 32]; whistle_pool::NULLIFIER_STATUS_BATCH]) -> Instruction {
+    let mut status = pool.ix(
+        accounts::BatchNullifierStatus { nullifiers: pda(b"nullifiers") },
+        instruction::BatchNullifierStatus { nullifier_hashes },
+    );
+    status.accounts.extend(nullifier_hashes.iter().map(|hash| AccountMeta::new_readonly(nullifier_marker(hash), false)));
+    status
+}
+
+async fn is_spent(pool: &mut TestPool, nullifier_hash: [u8; 32]) -> bool {
+    let mut hashes = [[0u8; 32]; whistle_pool::NULLIFIER_STATUS_BATCH];
+    hashes[0] = nullifier_hash;
+    let status = nullifier_status_ix(pool, hashes);
+    let mask = pool.view(status).await;
+    mask.first().is_some_and(|bits| bits & 1 == 1)
+}
+
+#[tokio::test]
+async fn shield_withdraw_round_trip() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let commitment = field(b"commitment");
+    let nullifier_hash = field(b"nullifier");
+
+    // Shield
+    pool.shield(commitment, SHIELD_AMOUNT).await.unwrap();
+
+    // The commitment is leaf 0 and the tree root is the pool's root
+    let state = pool.pool_state().await;
+    assert_eq!(state.next_index, 1);
+    let tree_account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let tree = whistle_pool::MerkleTree::try_load_versioned(&tree_account.data).unwrap();
+    let leaf_offset = (1usize << MERKLE_LEVELS) - 1;
+    assert_eq!(tree.nodes[leaf_offset], commitment);
+    assert_eq!(tree.nodes[0], state.current_root);
+
+    // Withdraw to a fresh recipient through a separate relayer, at the
+    // denomination's maximum relayer fee
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let denom_idx = DENOMINATIONS.iter().position(|d| *d == WITHDRAW_AMOUNT).unwrap();
+    let relayer_fee = WITHDRAW_AMOUNT * DEFAULT_RELAYER_FEE_CAPS_BPS[denom_idx] as u64 / 10_000;
+
+    let merkle_root = state.current_root;
+    let proof_a = test_proof(&[
+        merkle_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(WITHDRAW_AMOUNT),
+        field_u64(relayer_fee),
+        field_u64(0),
+    ]);
+    let withdraw = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, relayer),
+        instruction::Withdraw {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            amount: WITHDRAW_AMOUNT,
+            relayer_fee,
+            merkle_root,
+            unlock_slot: 0,
+        },
+    );
+    pool.send(withdraw.clone()).await.unwrap();
+
+    // 0.05 SOL less the 10% relayer fee
+    assert_eq!(pool.balance(recipient).await, WITHDRAW_AMOUNT - relayer_fee);
+    assert_eq!(pool.balance(relayer).await, relayer_fee);
+
+    // The nullifier is spent
+    let mut hashes = [[0u8; 32]; whistle_pool::NULLIFIER_STATUS_BATCH];
+    hashes[0] = nullifier_hash;
+    let status = nullifier_status_ix(&pool, hashes);
+    let mut mask = pool.view(status).await;
+    mask.resize(2, 0);
+    assert_eq!(u16::from_le_bytes([mask[0], mask[1]]), 1);
+
+    // ...at the withdrawal's slot, while an unspent hash has no history
+    let spent_slot = pool.slot().await;
+    let [spent, unspent] = [nullifier_hash, field(b"unspent")].map(|nullifier_hash| pool.ix(
+        accounts::QueryNullifierHistory { nullifiers: pda(b"nullifiers"), nullifier_marker: nullifier_marker(&nullifier_hash) },
+        instruction::QueryNullifierHistory { nullifier_hash },
+    ));
+    let mut spent = pool.view(spent).await;
+    spent.resize(9, 0);
+    assert_eq!(Option::<u64>::try_from_slice(&spent).unwrap(), Some(spent_slot));
+    let unspent = pool.view(unspent).await;
+    assert!(unspent.is_empty());
+
+    // Replaying the withdrawal is rejected
+    let err = pool.send_result(withdraw).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+}
+
+#[tokio::test]
+async fn spends_create_nullifier_markers_and_honor_the_legacy_set() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..3u8 {
+        pool.shield(field(&[b"commitment".as_ref(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let merkle_root = pool.current_root().await;
+
+    // A spend creates the hash's marker at its slot and leaves the legacy
+    // set untouched
+    let spent = field(b"spent");
+    pool.send(withdraw_ix(&pool, merkle_root, spent, Keypair::new().pubkey())).await.unwrap();
+    let spent_slot = pool.slot().await;
+    let account = pool.banks.get_account(nullifier_marker(&spent)).await.unwrap().unwrap();
+    assert_eq!(account.owner, whistle_pool::ID);
+    let marker = whistle_pool::NullifierMarker::try_deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!((marker.nullifier_hash, marker.spent_slot), (spent, spent_slot));
+    let legacy = pool.banks.get_account(pda(b"nullifiers")).await.unwrap().unwrap();
+    assert_eq!(legacy.data[8..16], [0u8; 8]);
+
+    // Once the marker exists the hash cannot be spent again, to any recipient
+    let err = pool.send_result(withdraw_ix(&pool, merkle_root, spent, Keypair::new().pubkey())).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+
+    // A hash recorded only in the frozen legacy set is spent too
+    let legacy_spent = field(b"legacy spent");
+    let mut legacy = legacy;
+    legacy.data[8..16].copy_from_slice(&1u64.to_le_bytes());
+    legacy.data[16..48].copy_from_slice(&legacy_spent);
+    pool.set_account(pda(b"nullifiers"), legacy);
+    assert!(is_spent(&mut pool, legacy_spent).await);
+    let err = pool.send_result(withdraw_ix(&pool, merkle_root, legacy_spent, Keypair::new().pubkey())).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+
+    // Lamports sent to a marker address beforehand cannot block the spend
+    let griefed = field(b"griefed");
+    let prefund = Rent::default().minimum_balance(0);
+    pool.send(system_instruction::transfer(&pool.payer.pubkey(), &nullifier_marker(&griefed), prefund)).await.unwrap();
+    assert!(!is_spent(&mut pool, griefed).await);
+    pool.send(withdraw_ix(&pool, merkle_root, griefed, Keypair::new().pubkey())).await.unwrap();
+    assert!(is_spent(&mut pool, griefed).await);
+    let account = pool.banks.get_account(nullifier_marker(&griefed)).await.unwrap().unwrap();
+    assert_eq!(account.owner, whistle_pool::ID);
+}
+
+#[tokio::test]
+async fn batch_nullifier_status_sets_the_bit_of_each_spent_hash() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"status"), 4 * SHIELD_AMOUNT).await.unwrap();
+    let merkle_root = pool.current_root().await;
+
+    let hashes: [[u8; 32]; whistle_pool::NULLIFIER_STATUS_BATCH] = std::array::from_fn(|i| field(&[b'n', i as u8]));
+    for i in [0, 7, 8] {
+        pool.send(withdraw_ix(&pool, merkle_root, hashes[i], Keypair::new().pubkey())).await.unwrap();
+    }
+    // The last one spent before markers existed, in the frozen legacy set
+    let mut legacy = pool.banks.get_account(pda(b"nullifiers")).await.unwrap().unwrap();
+    legacy.data[8..16].copy_from_slice(&1u64.to_le_bytes());
+    legacy.data[16..48].copy_from_slice(&hashes[15]);
+    pool.set_account(pda(b"nullifiers"), legacy);
+
+    // Return data loses its trailing zero bytes, as over RPC
+    let mut mask = pool.view(nullifier_status_ix(&pool, hashes)).await;
+    mask.resize(2, 0);
+    assert_eq!(u16::from_le_bytes([mask[0], mask[1]]), 1 << 0 | 1 << 7 | 1 << 8 | 1 << 15);
+
+    // Every hash needs its marker account
+    let mut short = nullifier_status_ix(&pool, hashes);
+    short.accounts.pop();
+    let err = pool.send_result(short).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidNullifierMarker));
+}
+
+async fn reserve_snapshot(pool: &mut TestPool) -> ReserveSnapshot {
+    let ix = pool.ix(
+        accounts::GetReserveSnapshot {
+            pool: pda(b"pool"),
+            pool_vault: pda(b"vault"),
+            fee_vault: pda(b"fee_vault"),
+        },
+        instruction::GetReserveSnapshot {},
+    );
+    let mut data = pool.view(ix).await;
+    data.resize(ReserveSnapshot::SIZE, 0);
+    ReserveSnapshot::try_from_slice(&data).unwrap()
+}
+
+#[tokio::test]
+async fn reserve_snapshot_is_consistent_after_busy_workload() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let shields = [100_000_000, 250_000_000, 1_000_000_000, 50_000_000, 2_000_000_000, 300_000_000];
+
+    let mut deposited = 0;
+    for (i, amount) in shields.into_iter().enumerate() {
+        pool.shield(field(&[b"commitment".as_slice(), &[i as u8]].concat()), amount).await.unwrap();
+        deposited += amount - amount * whistle_pool::PROTOCOL_FEE_BPS / whistle_pool::BPS_DENOMINATOR;
+    }
+
+    // Unshield 0.05 SOL from three notes, re-shielding the change
+    let recipient = Keypair::new().pubkey();
+    for i in 0..3u8 {
+        let nullifier_hash = field(&[b"nullifier".as_slice(), &[i]].concat());
+        let change_commitment = field(&[b"change".as_slice(), &[i]].concat());
+        let merkle_root = pool.current_root().await;
+        let proof_a = test_proof(&[
+            merkle_root,
+            nullifier_hash,
+            recipient_field(&recipient),
+            field_u64(WITHDRAW_AMOUNT),
+            field_u64(0),
+            change_commitment,
+            field_u64(0),
+        ]);
+        let ix = pool.ix(
+            pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient),
+            instruction::Unshield {
+                proof_a,
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                nullifier_hash,
+                recipient,
+                withdrawal_amount: WITHDRAW_AMOUNT,
+                relayer_fee: 0,
+                merkle_root,
+                change_commitment,
+                unlock_slot: 0,
+            },
+        );
+        pool.send(ix).await.unwrap();
+    }
+
+    let snapshot = reserve_snapshot(&mut pool).await;
+    let state = pool.pool_state().await;
+
+    assert_eq!(snapshot.version, RESERVE_SNAPSHOT_VERSION);
+    assert_eq!(snapshot.vault_lamports, pool.balance(pda(b"vault")).await);
+    assert_eq!(snapshot.fee_vault_lamports, pool.balance(pda(b"fee_vault")).await);
+    assert_eq!(snapshot.total_deposits, deposited);
+    assert_eq!(snapshot.total_withdrawn, 3 * WITHDRAW_AMOUNT);
+    assert_eq!(snapshot.total_deposits - snapshot.total_withdrawn, snapshot.total_shielded);
+    assert_eq!(snapshot.surplus, snapshot.vault_lamports as i64 - snapshot.total_shielded as i64);
+    // Every withdrawn lamport left the vault, so only the genesis balance is surplus
+    assert_eq!(snapshot.surplus, VAULT_GENESIS_LAMPORTS as i64);
+    assert_eq!(snapshot.fee_vault_lamports, VAULT_GENESIS_LAMPORTS + snapshot.total_fees_collected);
+    assert_eq!(snapshot.leaf_count, 9);
+    assert_eq!(snapshot.leaf_count, state.next_index);
+    assert_eq!(snapshot.current_root, state.current_root);
+}
+
+/// Pins the snapshot layout. If this fails after changing ReserveSnapshot,
+/// bump RESERVE_SNAPSHOT_VERSION and update the expected bytes.
+#[test]
+fn reserve_snapshot_layout_matches_version() {
+    let snapshot = ReserveSnapshot {
+        version: RESERVE_SNAPSHOT_VERSION,
+        slot: 1,
+        vault_lamports: 2,
+        fee_vault_lamports: 3,
+        total_deposits: 4,
+        total_shielded: 5,
+        total_withdrawn: 6,
+        total_fees_collected: 7,
+        surplus: -8,
+        leaf_count: 9,
+        current_root: [10; 32],
+    };
+    let bytes = snapshot.try_to_vec().unwrap();
+    assert_eq!(bytes.len(), ReserveSnapshot::SIZE);
+
+    let mut expected = vec![1u8];
+    for value in [1u64, 2, 3, 4, 5, 6, 7] {
+        expected.extend_from_slice(&value.to_le_bytes());
+    }
+    expected.extend_from_slice(&(-8i64).to_le_bytes());
+    expected.extend_from_slice(&9u64.to_le_bytes());
+    expected.extend_from_slice(&[10; 32]);
+    assert_eq!((RESERVE_SNAPSHOT_VERSION, bytes), (1, expected));
+}
+
+async fn verify_reserves(pool: &mut TestPool) -> ReservesReport {
+    let ix = pool.ix(
+        accounts::VerifyReserves {
+            pool: pda(b"pool"),
+            pool_vault: pda(b"vault"),
+            merkle_tree: pda(b"merkle_tree"),
+            nullifiers: pda(b"nullifiers"),
+        },
+        instruction::VerifyReserves {},
+    );
+    let mut data = pool.view(ix).await;
+    data.resize(ReservesReport::SIZE, 0);
+    ReservesReport::try_from_slice(&data).unwrap()
+}
+
+#[tokio::test]
+async fn verify_reserves_flags_accounting_drift() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..3u8 {
+        pool.shield(field(&[b"reserve".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let vault_balance = pool.balance(pda(b"vault")).await;
+    let state = pool.pool_state().await;
+    let healthy = ReservesReport {
+        vault_balance,
+        shielded_balance: state.total_shielded,
+        is_solvent: true,
+        leaf_count_matches: true,
+        nullifier_count_valid: true,
+    };
+    assert_eq!(verify_reserves(&mut pool).await, healthy);
+
+    let ix = pool.ix(
+        accounts::VerifyReserves {
+            pool: pda(b"pool"),
+            pool_vault: pda(b"vault"),
+            merkle_tree: pda(b"merkle_tree"),
+            nullifiers: pda(b"nullifiers"),
+        },
+        instruction::VerifyReserves {},
+    );
+    let sent = pool.send_with_metadata(ix).await;
+    sent.result.unwrap();
+    let events = emitted_events(&sent.metadata.unwrap().log_messages);
+    assert!(ReservesVerified::try_from_slice(&events[0][8..]).unwrap().is_solvent);
+
+    // A vault short of total_shielded is insolvent
+    let mut short = state.clone();
+    short.total_shielded = vault_balance + 1;
+    let mut account = pool.banks.get_account(pda(b"pool")).await.unwrap().unwrap();
+    let original_pool = account.clone();
+    let mut data = Vec::new();
+    short.try_serialize(&mut data).unwrap();
+    account.data[..data.len()].copy_from_slice(&data);
+    pool.set_account(pda(b"pool"), account);
+    let report = verify_reserves(&mut pool).await;
+    assert_eq!((report.shielded_balance, report.is_solvent), (vault_balance + 1, false));
+    assert!(report.leaf_count_matches && report.nullifier_count_valid);
+    pool.set_account(pda(b"pool"), original_pool);
+
+    // A leaf missing from the tree no longer matches next_index
+    let mut tree = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let original_tree = tree.clone();
+    let leaf = 8 + whistle_pool::MERKLE_TREE_HEADER_SIZE + ((1usize << MERKLE_LEVELS) - 1 + 1) * 32;
+    tree.data[leaf..leaf + 32].copy_from_slice(&[0u8; 32]);
+    pool.set_account(pda(b"merkle_tree"), tree);
+    assert_eq!(verify_reserves(&mut pool).await, ReservesReport { leaf_count_matches: false, ..healthy.clone() });
+    pool.set_account(pda(b"merkle_tree"), original_tree);
+
+    // More recorded spends than notes
+    let mut nullifiers = pool.banks.get_account(pda(b"nullifiers")).await.unwrap().unwrap();
+    nullifiers.data[8..16].copy_from_slice(&4u64.to_le_bytes());
+    pool.set_account(pda(b"nullifiers"), nullifiers);
+    assert_eq!(verify_reserves(&mut pool).await, ReservesReport { nullifier_count_valid: false, ..healthy });
+}
+
+#[test]
+fn withdraw_vks_are_selected_by_denomination() {
+    use whistle_pool::groth16::{
+        get_amount_reveal_vk, get_withdraw_merkle_vk, select_vk_for_denomination, CircuitVkRegistry, VkEntry,
+        WITHDRAW_VK_REGISTRY,
+    };
+
+    // Every withdrawal denomination has a circuit
+    for denomination in DENOMINATIONS {
+        let entry = select_vk_for_denomination(denomination, &WITHDRAW_VK_REGISTRY).unwrap();
+        assert_eq!(entry.denomination, denomination);
+    }
+
+    // Each denomination gets its own entry's key, and one without an
+    // entry gets none
+    let registry = CircuitVkRegistry {
+        entries: Box::leak(Box::new([
+            VkEntry { denomination: whistle_pool::DENOM_001_SOL, vk: get_withdraw_merkle_vk() },
+            VkEntry { denomination: whistle_pool::DENOM_1_SOL, vk: get_amount_reveal_vk() },
+        ])),
+    };
+    let small = select_vk_for_denomination(whistle_pool::DENOM_001_SOL, &registry).unwrap();
+    assert_eq!(small.vk.alpha_g1, get_withdraw_merkle_vk().alpha_g1);
+    assert_eq!(small.vk.ic.len(), get_withdraw_merkle_vk().ic.len());
+    let large = select_vk_for_denomination(whistle_pool::DENOM_1_SOL, &registry).unwrap();
+    assert_eq!(large.vk.ic.len(), get_amount_reveal_vk().ic.len());
+    for denomination in [whistle_pool::DENOM_01_SOL, WITHDRAW_AMOUNT + 1, 0] {
+        let err = select_vk_for_denomination(denomination, &registry).err().unwrap();
+        assert_eq!(err, WhistleError::NoVkForDenomination.into());
+    }
+}
+
+#[test]
+fn spl_denominations_must_be_whole_units() {
+    // USDC: 6 decimals
+    assert!(whistle_pool::validate_spl_denominations(6, &[1_000_000, 10_000_000]).is_ok());
+    for denoms in [&[999_999u64][..], &[1_000_000, 1_500_001], &[0]] {
+        let err = whistle_pool::validate_spl_denominations(6, denoms).unwrap_err();
+        assert_eq!(err, WhistleError::InvalidSplDenomination.into());
+    }
+    // 10^20 overflows u64, so no denomination is a whole unit
+    assert!(whistle_pool::validate_spl_denominations(20, &[u64::MAX]).is_err());
+}
+
+async fn anonymity_metrics(pool: &mut TestPool) -> AnonymityMetrics {
+    let metrics = pool.ix(
+        accounts::GetAnonymityMetrics { pool_stats: pda(b"pool_stats"), deposit_histogram: pda(b"deposit_histogram") },
+        instruction::GetAnonymityMetrics {},
+    );
+    let mut data = pool.view(metrics).await;
+    data.resize(AnonymityMetrics::SIZE, 0);
+    AnonymityMetrics::try_from_slice(&data).unwrap()
+}
+
+async fn pool_stats(pool: &mut TestPool) -> PoolStats {
+    let account = pool.banks.get_account(pda(b"pool_stats")).await.unwrap().unwrap();
+    bytemuck::pod_read_unaligned(&account.data[8..])
+}
+
+/// Shield a fresh note in amount band `band`: < 0.1, < 1, < 10 or >= 10 SOL
+async fn shield_in_band(pool: &mut TestPool, band: usize) {
+    let amount = [50_000_000, 500_000_000, 5_000_000_000, 20_000_000_000][band];
+    let next_index = pool.pool_state().await.next_index;
+    let commitment = field(&[b"band commitment".as_ref(), &next_index.to_le_bytes()].concat());
+    pool.shield(commitment, amount).await.unwrap();
+}
+
+/// Per-band counts over the trailing 1k, 10k and 100k slots
+fn windows(metrics: &AnonymityMetrics) -> [[u32; 4]; 3] {
+    [metrics.last_1k_slots, metrics.last_10k_slots, metrics.last_100k_slots]
+}
+
+#[tokio::test]
+async fn anonymity_metrics_count_deposits_per_denomination() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for (i, amount) in [SHIELD_AMOUNT, SHIELD_AMOUNT, whistle_pool::DENOM_1_SOL].into_iter().enumerate() {
+        pool.shield(field(&[b"commitment".as_ref(), &[i as u8]].concat()), amount).await.unwrap();
+    }
+
+    let metrics = anonymity_metrics(&mut pool).await;
+
+    // Three notes could have paid 0.1 SOL, only the 1 SOL note 1 SOL
+    assert_eq!(metrics.denomination_specific_set_sizes, [3, 3, 3, 1, 0, 0]);
+}
+
+#[tokio::test]
+async fn anonymity_metrics_roll_forward_across_epochs() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+
+    // Start a little past an epoch boundary, so nothing below crosses one
+    // unless it warps
+    let slot = pool.slot().await;
+    let start_epoch = slot / STATS_EPOCH_SLOTS + 1;
+    pool.warp_slots(start_epoch * STATS_EPOCH_SLOTS + 10 - slot).await;
+
+    shield_in_band(&mut pool, 0).await;
+    shield_in_band(&mut pool, 0).await;
+    shield_in_band(&mut pool, 1).await;
+    assert_eq!(windows(&anonymity_metrics(&mut pool).await), [[2, 1, 0, 0]; 3]);
+    let stats = pool_stats(&mut pool).await;
+    assert_eq!(stats.current_epoch, start_epoch);
+    let start_head = stats.head as usize;
+    assert_eq!(stats.counts[start_head], [2, 1, 0, 0]);
+
+    // Next epoch: the getter already leaves the old epoch out of the last
+    // 1k slots before any shield rolls the ring
+    pool.warp_slots(STATS_EPOCH_SLOTS).await;
+    assert_eq!(windows(&anonymity_metrics(&mut pool).await), [[0; 4], [2, 1, 0, 0], [2, 1, 0, 0]]);
+    assert_eq!(pool_stats(&mut pool).await.current_epoch, start_epoch);
+    shield_in_band(&mut pool, 2).await;
+    assert_eq!(windows(&anonymity_metrics(&mut pool).await), [[0, 0, 1, 0], [2, 1, 1, 0], [2, 1, 1, 0]]);
+    let stats = pool_stats(&mut pool).await;
+    assert_eq!(stats.current_epoch, start_epoch + 1);
+    assert_eq!(stats.head as usize, (start_head + 1) % STATS_RING_EPOCHS);
+    assert_eq!(stats.counts[start_head], [2, 1, 0, 0]);
+
+    // Nine epochs later the first epoch has left the last 10k slots, and
+    // the skipped epochs are empty
+    pool.warp_slots(9 * STATS_EPOCH_SLOTS).await;
+    shield_in_band(&mut pool, 3).await;
+    assert_eq!(windows(&anonymity_metrics(&mut pool).await), [[0, 0, 0, 1], [0, 0, 1, 1], [2, 1, 1, 1]]);
+    let stats = pool_stats(&mut pool).await;
+    assert_eq!(stats.current_epoch, start_epoch + 10);
+    assert_eq!(stats.head as usize, (start_head + 10) % STATS_RING_EPOCHS);
+    for skipped in 2..10 {
+        assert_eq!(stats.counts[(start_head + skipped) % STATS_RING_EPOCHS], [0; 4]);
+    }
+
+    // Past the whole ring: every window reads empty before the next shield,
+    // which clears the ring and counts only itself
+    pool.warp_slots(STATS_RING_EPOCHS as u64 * STATS_EPOCH_SLOTS).await;
+    assert_eq!(windows(&anonymity_metrics(&mut pool).await), [[0; 4]; 3]);
+    shield_in_band(&mut pool, 0).await;
+    assert_eq!(windows(&anonymity_metrics(&mut pool).await), [[1, 0, 0, 0]; 3]);
+    let stats = pool_stats(&mut pool).await;
+    assert_eq!(stats.current_epoch, start_epoch + 10 + STATS_RING_EPOCHS as u64);
+    let total: u32 = stats.counts.iter().flatten().sum();
+    assert_eq!(total, 1);
+}
+
+const LARGE_SHIELD: u64 = 2_000_000_000; // 2 SOL
+
+fn pre_commit_address(payer: &Pubkey, commitment_hash: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"pre_commit", payer.as_ref(), commitment_hash], &whistle_pool::ID).0
+}
+
+async fn pre_commit(pool: &mut TestPool, commitment: &[u8; 32]) -> Pubkey {
+    let commitment_hash = hash(commitment).to_bytes();
+    let payer = pool.payer.pubkey();
+    let address = pre_commit_address(&payer, &commitment_hash);
+    let deposit_slot = pool.slot().await;
+    let ix = pool.ix(
+        accounts::PreCommitShield { pre_commit: address, payer, system_program: system_program::ID },
+        instruction::PreCommitShield { commitment_hash, deposit_slot },
+    );
+    pool.send(ix).await.unwrap();
+    address
+}
+
+fn reveal(pool: &TestPool, pre_commit: Pubkey, commitment: [u8; 32]) -> Instruction {
+    pool.ix(
+        accounts::RevealShield { pre_commit, payer: pool.payer.pubkey(), shield: pool.shield_accounts(0, &commitment) },
+        instruction::RevealShield { commitment, amount: LARGE_SHIELD },
+    )
+}
+
+#[tokio::test]
+async fn pre_committed_shield_refunds_stake_on_reveal() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let commitment = field(b"large commitment");
+    let address = pre_commit(&mut pool, &commitment).await;
+
+    let rent = Rent::default().minimum_balance(8 + 32 + 32 + 8 + 1);
+    assert_eq!(pool.balance(address).await, rent + PRE_COMMIT_STAKE);
+
+    // Revealing a different commitment is rejected
+    let err = pool.send_result(reveal(&pool, address, field(b"other commitment"))).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::PreCommitHashMismatch));
+
+    pool.warp_slots(PRE_COMMIT_REVEAL_SLOTS - 1).await;
+    let vault_before = pool.balance(pda(b"vault")).await;
+    pool.send(reveal(&pool, address, commitment)).await.unwrap();
+
+    // The note is shielded and the pre-commit closed back to the payer
+    let state = pool.pool_state().await;
+    assert_eq!(state.next_index, 1);
+    let fee = LARGE_SHIELD * whistle_pool::PROTOCOL_FEE_BPS / whistle_pool::BPS_DENOMINATOR;
+    assert_eq!(pool.balance(pda(b"vault")).await - vault_before, LARGE_SHIELD - fee);
+    assert!(pool.banks.get_account(address).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn expired_pre_commit_stake_goes_to_claimer() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let commitment = field(b"large commitment");
+    let address = pre_commit(&mut pool, &commitment).await;
+
+    let claimer = Keypair::new();
+    let claim = pool.ix(
+        accounts::ClaimExpiredPreCommit { pre_commit: address, payer: pool.payer.pubkey(), claimer: claimer.pubkey() },
+        instruction::ClaimExpiredPreCommit {},
+    );
+
+    // Not claimable while the reveal window is open
+    let err = pool.send_signed(claim.clone(), &[&claimer]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::PreCommitNotExpired));
+
+    pool.warp_slots(PRE_COMMIT_REVEAL_SLOTS + 1).await;
+
+    // Too late to reveal
+    let err = pool.send_result(reveal(&pool, address, commitment)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::PreCommitExpired));
+
+    pool.send_signed(claim, &[&claimer]).await.unwrap();
+    assert_eq!(pool.balance(claimer.pubkey()).await, PRE_COMMIT_STAKE);
+    assert!(pool.banks.get_account(address).await.unwrap().is_none());
+    assert_eq!(pool.pool_state().await.next_index, 0);
+}
+
+#[tokio::test]
+async fn nullifier_history_and_root_checks_answer_through_simulation() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let nullifier_hash = field(b"nullifier");
+    let spent_slot = |pool: &TestPool| {
+        pool.ix(
+            accounts::QueryNullifierHistory { nullifiers: pda(b"nullifiers"), nullifier_marker: nullifier_marker(&nullifier_hash) },
+            instruction::QueryNullifierHistory { nullifier_hash },
+        )
+    };
+    let check_root = |pool: &TestPool, merkle_root: [u8; 32]| {
+        pool.ix(
+            accounts::CheckRoot { pool: pda(b"pool"), roots_history: pda(b"roots_history") },
+            instruction::CheckRoot { merkle_root },
+        )
+    };
+
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    let first_root = pool.current_root().await;
+    pool.shield(field(b"second"), SHIELD_AMOUNT).await.unwrap();
+    let root = pool.current_root().await;
+
+    // Trailing zero bytes are trimmed from return data, so false and None
+    // come back empty
+    for (merkle_root, valid) in [(root, true), (first_root, true), (field(b"unknown root"), false), ([0u8; 32], false)] {
+        let data = pool.view(check_root(&pool, merkle_root)).await;
+        assert_eq!(data.first() == Some(&1), valid);
+    }
+    assert!(pool.view(spent_slot(&pool)).await.is_empty());
+
+    let recipient = Keypair::new().pubkey();
+    let proof_a = test_proof(&[
+        root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(WITHDRAW_AMOUNT),
+        field_u64(0),
+        [0u8; 32],
+        field_u64(0),
+    ]);
+    let unshield = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, recipient),
+        instruction::Unshield {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root: root,
+            change_commitment: [0u8; 32],
+            unlock_slot: 0,
+        },
+    );
+    pool.send(unshield).await.unwrap();
+    let slot = pool.slot().await;
+
+    let mut data = pool.view(spent_slot(&pool)).await;
+    data.resize(9, 0);
+    assert_eq!(Option::<u64>::try_from_slice(&data).unwrap(), Some(slot));
+}
+
+async fn roots_history(pool: &mut TestPool) -> RootsRing<Vec<u8>> {
+    let account = pool.banks.get_account(pda(b"roots_history")).await.unwrap().unwrap();
+    RootsRing::new(account.data).unwrap()
+}
+
+#[tokio::test]
+async fn history_roots_expire_after_the_max_age() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let check_root = |pool: &TestPool, merkle_root: [u8; 32]| {
+        pool.ix(
+            accounts::CheckRoot { pool: pda(b"pool"), roots_history: pda(b"roots_history") },
+            instruction::CheckRoot { merkle_root },
+        )
+    };
+    let unshield = |pool: &TestPool, merkle_root: [u8; 32], nullifier_hash: [u8; 32]| {
+        let recipient = Keypair::new().pubkey();
+        let proof_a = test_proof(&[
+            merkle_root,
+            nullifier_hash,
+            recipient_field(&recipient),
+            field_u64(WITHDRAW_AMOUNT),
+            field_u64(0),
+            [0u8; 32],
+            field_u64(0),
+        ]);
+        pool.ix(
+            pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, recipient),
+            instruction::Unshield {
+                proof_a,
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                nullifier_hash,
+                recipient,
+                withdrawal_amount: WITHDRAW_AMOUNT,
+                relayer_fee: 0,
+                merkle_root,
+                change_commitment: [0u8; 32],
+                unlock_slot: 0,
+            },
+        )
+    };
+
+    pool.shield(field(b"first"), SHIELD_AMOUNT).await.unwrap();
+    let old_root = pool.current_root().await;
+    let recorded = roots_history(&mut pool).await.root_slot(0);
+    assert_eq!(recorded, pool.slot().await);
+    pool.shield(field(b"second"), SHIELD_AMOUNT).await.unwrap();
+    let current_root = pool.current_root().await;
+
+    // Still spendable at exactly MAX_ROOT_AGE_SLOTS...
+    let slot = pool.slot().await;
+    pool.warp_slots(recorded + MAX_ROOT_AGE_SLOTS - slot).await;
+    assert_eq!(pool.view(check_root(&pool, old_root)).await, [1]);
+    pool.send(unshield(&pool, old_root, field(b"fresh nullifier"))).await.unwrap();
+
+    // ...and not one slot later, while the current root never expires
+    pool.warp_slots(1).await;
+    assert!(pool.view(check_root(&pool, old_root)).await.is_empty());
+    let err = pool.send_result(unshield(&pool, old_root, field(b"stale nullifier"))).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::StaleMerkleRoot));
+    assert_eq!(pool.current_root().await, current_root);
+    pool.send(unshield(&pool, current_root, field(b"stale nullifier"))).await.unwrap();
+}
+
+#[tokio::test]
+async fn migrate_roots_history_grows_legacy_accounts() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    let root = pool.current_root().await;
+
+    // A roots history from before root_slots: the roots and nothing after
+    // them (lamports unchanged, or warp_slots panics on the bank's
+    // capitalization)
+    let legacy_size = RootsHistory::space(ROOTS_HISTORY_CAPACITY) - ROOTS_HISTORY_CAPACITY * 8;
+    let mut account = pool.banks.get_account(pda(b"roots_history")).await.unwrap().unwrap();
+    account.data.truncate(legacy_size);
+    pool.set_account(pda(b"roots_history"), account);
+
+    pool.warp_slots(10).await;
+    let migrate = pool.ix(
+        accounts::MigrateRootsHistory {
+            pool: pda(b"pool"),
+            roots_history: pda(b"roots_history"),
+            payer: pool.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::MigrateRootsHistory {},
+    );
+    pool.send(migrate.clone()).await.unwrap();
+
+    let slot = pool.slot().await;
+    let roots = roots_history(&mut pool).await;
+    assert_eq!((roots.capacity(), roots.root(0), roots.root_slot(0)), (ROOTS_HISTORY_CAPACITY, root, slot));
+    assert!((1..roots.capacity()).all(|i| roots.root_slot(i) == 0));
+    pool.shield(field(b"after migration"), SHIELD_AMOUNT).await.unwrap();
+
+    pool.warp_slots(1).await;
+    let err = pool.send_result(migrate).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::RootsHistoryAlreadyMigrated));
+}
+
+#[tokio::test]
+async fn migrate_merkle_tree_v0_to_v1_keeps_every_field() {
+    use anchor_lang::Discriminator;
+    use whistle_pool::{MerkleTree, MerkleTreeMigrated, MERKLE_TREE_HEADER_SIZE, MERKLE_TREE_NODE_CAPACITY, MERKLE_TREE_VERSION};
+
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..3u8 {
+        pool.shield(field(&[b"before migration".as_ref(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+
+    // The v0 layout: levels_used, then padding, then the same nodes
+    let mut v0 = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    v0.data[8..8 + MERKLE_TREE_HEADER_SIZE].copy_from_slice(&[MERKLE_LEVELS, 0, 0, 0, 0, 0, 0, 0]);
+    pool.set_account(pda(b"merkle_tree"), v0.clone());
+    let before = MerkleTree::try_load_versioned(&v0.data).unwrap();
+    assert_eq!((before.version, before.levels_used), (0, MERKLE_LEVELS));
+
+    let migrate = pool.ix(
+        accounts::MigrateMerkleTree { pool: pda(b"pool"), merkle_tree: pda(b"merkle_tree") },
+        instruction::MigrateMerkleTreeV0ToV1 {},
+    );
+    let sent = pool.send_with_metadata(migrate.clone()).await;
+    sent.result.unwrap();
+    let events = emitted_events(&sent.metadata.unwrap().log_messages);
+    let event = events.iter().find(|event| event[..8] == MerkleTreeMigrated::DISCRIMINATOR).unwrap();
+    let event = MerkleTreeMigrated::try_from_slice(&event[8..]).unwrap();
+    assert_eq!((event.from_version, event.to_version, event.levels_used), (0, MERKLE_TREE_VERSION, MERKLE_LEVELS));
+
+    let v1 = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let after = MerkleTree::try_load_versioned(&v1.data).unwrap();
+    assert_eq!(after.version, MERKLE_TREE_VERSION);
+    assert_eq!(after.levels_used, before.levels_used);
+    assert_eq!(after.node_capacity, MERKLE_TREE_NODE_CAPACITY as u32);
+    assert_eq!(after.nodes, before.nodes);
+    assert_eq!((v1.lamports, v1.owner, v1.data.len()), (v0.lamports, v0.owner, v0.data.len()));
+    assert_eq!(v1.data[..8], v0.data[..8]);
+
+    // The migrated tree is the pool's tree: the next shield lands on it
+    pool.shield(field(b"after migration"), SHIELD_AMOUNT).await.unwrap();
+    assert_eq!(pool.pool_state().await.next_index, 4);
+
+    let err = pool.send_result(migrate).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::MerkleTreeAlreadyMigrated));
+}
+
+#[tokio::test]
+async fn resize_roots_history_keeps_recorded_roots() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let check_root = |pool: &TestPool, merkle_root: [u8; 32]| {
+        pool.ix(
+            accounts::CheckRoot { pool: pda(b"pool"), roots_history: pda(b"roots_history") },
+            instruction::CheckRoot { merkle_root },
+        )
+    };
+    let resize = |pool: &TestPool, capacity: u16| {
+        pool.ix(
+            accounts::ResizeRootsHistory {
+                pool: pda(b"pool"),
+                roots_history: pda(b"roots_history"),
+                payer: pool.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::ResizeRootsHistory { capacity },
+        )
+    };
+
+    // A spend proven before the resize lands after it
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    let proof_root = pool.current_root().await;
+    pool.shield(field(b"second"), SHIELD_AMOUNT).await.unwrap();
+    pool.send(resize(&pool, 200)).await.unwrap();
+    let roots = roots_history(&mut pool).await;
+    assert_eq!((roots.capacity(), roots.current_index()), (200, 2));
+    assert_eq!(pool.balance(pda(b"roots_history")).await, Rent::default().minimum_balance(RootsHistory::space(200)));
+
+    let nullifier_hash = field(b"nullifier");
+    let recipient = Keypair::new().pubkey();
+    let proof_a = test_proof(&[
+        proof_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(WITHDRAW_AMOUNT),
+        field_u64(0),
+        [0u8; 32],
+        field_u64(0),
+    ]);
+    pool.send(pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, recipient),
+        instruction::Unshield {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root: proof_root,
+            change_commitment: [0u8; 32],
+            unlock_slot: 0,
+        },
+    ))
+    .await
+    .unwrap();
+
+    // A wrapped ring is unrolled oldest first: the new entries are filled
+    // before any recorded root is overwritten
+    let slot = pool.slot().await;
+    let mut account = pool.banks.get_account(pda(b"roots_history")).await.unwrap().unwrap();
+    let mut roots = RootsRing::new(&mut account.data[..]).unwrap();
+    for i in 0..235 {
+        roots.push(field_u64(1_000 + i), slot);
+    }
+    assert_eq!(roots.current_index(), 37);
+    pool.set_account(pda(b"roots_history"), account);
+
+    pool.send(resize(&pool, 456)).await.unwrap();
+    let roots = roots_history(&mut pool).await;
+    assert_eq!((roots.capacity(), roots.current_index()), (456, 200));
+    assert_eq!((roots.root(0), roots.root(199)), (field_u64(1_035), field_u64(1_234)));
+    for i in [35, 100, 234] {
+        assert_eq!(pool.view(check_root(&pool, field_u64(1_000 + i))).await, [1]);
+    }
+    pool.shield(field(b"after resize"), SHIELD_AMOUNT).await.unwrap();
+    let roots = roots_history(&mut pool).await;
+    assert_eq!((roots.root(0), roots.root(200)), (field_u64(1_035), pool.current_root().await));
+
+    // Capacity only grows, by at most MAX_ROOTS_HISTORY_GROWTH per call,
+    // up to MAX_ROOTS_HISTORY_CAPACITY
+    let growth = MAX_ROOTS_HISTORY_GROWTH as u16;
+    for capacity in [456, 300, 456 + growth + 1] {
+        let err = pool.send_result(resize(&pool, capacity)).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(WhistleError::InvalidRootsHistoryCapacity));
+    }
+    for capacity in [456 + growth, 456 + 2 * growth, MAX_ROOTS_HISTORY_CAPACITY as u16] {
+        pool.send(resize(&pool, capacity)).await.unwrap();
+    }
+    let err = pool.send_result(resize(&pool, MAX_ROOTS_HISTORY_CAPACITY as u16 + 1)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidRootsHistoryCapacity));
+    assert_eq!(roots_history(&mut pool).await.root(0), field_u64(1_035));
+}
+
+/// Every root write goes through RootsRing::push, which overwrites the
+/// oldest entry once the ring is full
+#[test]
+fn roots_ring_push_wraps_around() {
+    let mut data = vec![0u8; RootsHistory::space(ROOTS_HISTORY_CAPACITY)];
+    let mut roots = RootsRing::new(&mut data[..]).unwrap();
+    let total = ROOTS_HISTORY_CAPACITY as u64 + 3;
+    for i in 0..total {
+        roots.push(field_u64(1_000 + i), i);
+        assert_eq!(roots.latest(), field_u64(1_000 + i));
+    }
+    assert_eq!(roots.current_index(), 3);
+    for i in 0..ROOTS_HISTORY_CAPACITY {
+        let pushed = if i < 3 { ROOTS_HISTORY_CAPACITY as u64 + i as u64 } else { i as u64 };
+        assert_eq!((roots.root(i), roots.root_slot(i)), (field_u64(1_000 + pushed), pushed));
+    }
+    assert!((0..3).all(|i| !roots.contains(&field_u64(1_000 + i))));
+}
+
+/// Unfilled roots history slots are zero; the zero root must not match them
+#[test]
+fn roots_history_never_contains_the_zero_root() {
+    let mut data = vec![0u8; RootsHistory::space(ROOTS_HISTORY_CAPACITY)];
+    let mut roots = RootsRing::new(&mut data[..]).unwrap();
+    assert!(!roots.contains(&[0u8; 32]));
+    roots.set(0, field(b"root"), 0);
+    assert!(roots.contains(&field(b"root")));
+    assert!(!roots.contains(&[0u8; 32]));
+}
+
+#[tokio::test]
+async fn unshield_rejects_change_derived_from_nullifier_hash() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+
+    let recipient = Keypair::new().pubkey();
+    let nullifier_hash = field(b"nullifier");
+    let merkle_root = pool.current_root().await;
+    let unshield = |change_commitment: [u8; 32]| {
+        let proof_a = test_proof(&[
+            merkle_root,
+            nullifier_hash,
+            recipient_field(&recipient),
+            field_u64(WITHDRAW_AMOUNT),
+            field_u64(0),
+            change_commitment,
+            field_u64(0),
+        ]);
+        pool.ix(
+            pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient),
+            instruction::Unshield {
+                proof_a,
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                nullifier_hash,
+                recipient,
+                withdrawal_amount: WITHDRAW_AMOUNT,
+                relayer_fee: 0,
+                merkle_root,
+                change_commitment,
+                unlock_slot: 0,
+            },
+        )
+    };
+
+    let self_hash = poseidon_hashv(Parameters::Bn254X5, Endianness::BigEndian, &[&nullifier_hash, &nullifier_hash])
+        .unwrap()
+        .to_bytes();
+    let weak = [unshield(nullifier_hash), unshield(self_hash)];
+    let independent = unshield(field(b"change"));
+
+    for ix in weak {
+        let err = pool.send_result(ix).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(WhistleError::WeakChangeCommitment));
+    }
+    pool.send(independent).await.unwrap();
+    assert_eq!(pool.pool_state().await.next_index, 2);
+}
+
+#[tokio::test]
+async fn commitment_markers_reject_reinserted_commitments() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let note = field(b"note");
+    pool.shield(note, SHIELD_AMOUNT).await.unwrap();
+    pool.shield(field(b"other note"), SHIELD_AMOUNT).await.unwrap();
+
+    // The marker maps the commitment to its leaf
+    let account = pool.banks.get_account(commitment_marker(&note)).await.unwrap().unwrap();
+    assert_eq!(account.owner, whistle_pool::ID);
+    let marker = CommitmentMarker::try_deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!((marker.commitment, marker.leaf_index), (note, 0));
+
+    // The same commitment cannot be shielded twice, nor a new one past
+    // another commitment's marker
+    let again = pool.ix(pool.shield_accounts(2, &note), instruction::Shield { commitment: note, amount: SHIELD_AMOUNT });
+    let err = pool.send_result(again).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DuplicateCommitment));
+    let fresh = instruction::Shield { commitment: field(b"fresh"), amount: SHIELD_AMOUNT };
+    let err = pool.send_result(pool.ix(pool.shield_accounts(2, &note), fresh)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidCommitmentMarker));
+
+    // Change colliding with a leaf fails the whole unshield
+    let recipient = Keypair::new().pubkey();
+    let nullifier_hash = field(b"nullifier");
+    let merkle_root = pool.current_root().await;
+    let unshield = |change_commitment: [u8; 32]| {
+        let proof_a = test_proof(&[
+            merkle_root,
+            nullifier_hash,
+            recipient_field(&recipient),
+            field_u64(WITHDRAW_AMOUNT),
+            field_u64(0),
+            change_commitment,
+            field_u64(0),
+        ]);
+        pool.ix(
+            pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient),
+            instruction::Unshield {
+                proof_a,
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                nullifier_hash,
+                recipient,
+                withdrawal_amount: WITHDRAW_AMOUNT,
+                relayer_fee: 0,
+                merkle_root,
+                change_commitment,
+                unlock_slot: 0,
+            },
+        )
+    };
+    let change = field(b"change");
+    let (colliding, fresh_change) = (unshield(field(b"other note")), unshield(change));
+    let err = pool.send_result(colliding).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DuplicateCommitment));
+    assert!(!is_spent(&mut pool, nullifier_hash).await);
+
+    pool.send(fresh_change).await.unwrap();
+    let account = pool.banks.get_account(commitment_marker(&change)).await.unwrap().unwrap();
+    assert_eq!(CommitmentMarker::try_deserialize(&mut &account.data[..]).unwrap().leaf_index, 2);
+
+    // Batch shields check each commitment's marker too
+    let commitments = vec![field(b"batch"), change];
+    let batch = instruction::BatchShield { commitments: commitments.clone(), amounts: vec![SHIELD_AMOUNT; 2] };
+    let err = pool.send_result(pool.batch_shield_ix(3, &commitments, batch)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DuplicateCommitment));
+    assert_eq!(pool.pool_state().await.next_index, 3);
+}
+
+#[tokio::test]
+async fn every_shield_path_rejects_the_zero_commitment() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let code = u32::from(WhistleError::ZeroCommitment);
+
+    let shield = pool.ix(pool.shield_accounts(0, &[0u8; 32]), instruction::Shield { commitment: [0u8; 32], amount: SHIELD_AMOUNT });
+    let deposit = pool.ix(pool.shield_accounts(0, &[0u8; 32]), instruction::Deposit { commitment: [0u8; 32], amount: SHIELD_AMOUNT });
+    let mut commitments: [[u8; 32]; 8] = std::array::from_fn(|i| field(&[b'z', i as u8]));
+    commitments[7] = [0u8; 32];
+    let batch = shield_batch_ix(&pool, 0, commitments, 8 * SHIELD_AMOUNT, 8 * SHIELD_AMOUNT);
+    for ix in [shield, deposit, batch] {
+        let err = pool.send_result(ix).await.unwrap_err();
+        assert_eq!(error_code(err), code);
+    }
+    let routed = pool.shield_through_router([0u8; 32], SHIELD_AMOUNT).await;
+    assert_eq!(routed.result, Err(TransactionError::InstructionError(0, InstructionError::Custom(code))));
+    assert_eq!(pool.pool_state().await.next_index, 0);
+}
+
+#[tokio::test]
+async fn every_shield_path_rejects_non_canonical_commitments() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let code = u32::from(WhistleError::NonCanonicalFieldElement);
+
+    // The scalar field modulus itself, the smallest value that is not a
+    // field element
+    let non_canonical = whistle_pool::public_inputs::BN254_SCALAR_MODULUS;
+
+    let shield = pool.ix(pool.shield_accounts(0, &non_canonical), instruction::Shield { commitment: non_canonical, amount: SHIELD_AMOUNT });
+    let deposit = pool.ix(pool.shield_accounts(0, &non_canonical), instruction::Deposit { commitment: non_canonical, amount: SHIELD_AMOUNT });
+    let mut commitments: [[u8; 32]; 8] = std::array::from_fn(|i| field(&[b'v', i as u8]));
+    commitments[3] = non_canonical;
+    let batch = shield_batch_ix(&pool, 0, commitments, 8 * SHIELD_AMOUNT, 8 * SHIELD_AMOUNT);
+    let public_batch = pool.batch_shield_ix(
+        0,
+        &[field(b"canonical"), non_canonical],
+        instruction::BatchShield { commitments: vec![field(b"canonical"), non_canonical], amounts: vec![SHIELD_AMOUNT; 2] },
+    );
+    let mint = create_mint(&mut pool, 0).await;
+    let owner = pool.payer.pubkey();
+    let source = create_token_account(&mut pool, mint, owner, 10).await;
+    let shield_token = shield_token_ix(&pool, 0, mint, source, non_canonical, 10);
+    for ix in [shield, deposit, batch, public_batch, shield_token] {
+        let err = pool.send_result(ix).await.unwrap_err();
+        assert_eq!(error_code(err), code);
+    }
+    let routed = pool.shield_through_router(non_canonical, SHIELD_AMOUNT).await;
+    assert_eq!(routed.result, Err(TransactionError::InstructionError(0, InstructionError::Custom(code))));
+    assert_eq!(pool.pool_state().await.next_index, 0);
+}
+
+/// deposit and shield are one code path: the same notes through either
+/// leave the same pool state and events, and hit the same caps
+#[tokio::test]
+async fn deposit_matches_shield() {
+    use anchor_lang::Discriminator;
+
+    let net = SHIELD_AMOUNT - SHIELD_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    let mut shielded = TestPool::start_with_deposit_caps(MERKLE_LEVELS, 2 * net, 0).await;
+    let mut deposited = TestPool::start_with_deposit_caps(MERKLE_LEVELS, 2 * net, 0).await;
+
+    let mut states = Vec::new();
+    for (pool, deposit) in [(&mut shielded, false), (&mut deposited, true)] {
+        let mut events = Vec::new();
+        for (next_index, commitment) in [(0, field(b"first")), (1, field(b"second")), (2, field(b"over cap"))] {
+            let accounts = pool.shield_accounts(next_index, &commitment);
+            let ix = if deposit {
+                pool.ix(accounts, instruction::Deposit { commitment, amount: SHIELD_AMOUNT })
+            } else {
+                pool.ix(accounts, instruction::Shield { commitment, amount: SHIELD_AMOUNT })
+            };
+            let sent = pool.send_with_metadata(ix).await;
+            if next_index == 2 {
+                let code = u32::from(WhistleError::DepositCapExceeded);
+                assert_eq!(sent.result, Err(TransactionError::InstructionError(0, InstructionError::Custom(code))));
+                continue;
+            }
+            sent.result.unwrap();
+            for event in emitted_events(&sent.metadata.unwrap().log_messages) {
+                if event[..8] != whistle_pool::Shielded::DISCRIMINATOR {
+                    continue;
+                }
+                let event = whistle_pool::Shielded::try_from_slice(&event[8..]).unwrap();
+                events.push((event.commitment, event.leaf_index, event.amount, event.protocol_fee));
+            }
+        }
+
+        let mut state = Vec::new();
+        pool.pool_state().await.try_serialize(&mut state).unwrap();
+        let record = pool.banks.get_account(deposit_record(&pool.payer.pubkey())).await.unwrap().unwrap();
+        let record = whistle_pool::DepositRecord::try_deserialize(&mut &record.data[..]).unwrap();
+        states.push((
+            state,
+            events,
+            read_leaves(pool, 0, 2).await,
+            pool.balance(pda(b"vault")).await,
+            pool.balance(pda(b"fee_vault")).await,
+            record.total_deposited,
+        ));
+    }
+    assert_eq!(states[0], states[1]);
+    assert_eq!(states[0].1, vec![(field(b"first"), 0, net, SHIELD_AMOUNT - net), (field(b"second"), 1, net, SHIELD_AMOUNT - net)]);
+}
+
+#[tokio::test]
+async fn unshield_eip712_pays_only_the_mapped_account() {
+    use whistle_pool::eip712::{register_hash, unshield_hash, EIP712_CHAIN_ID};
+
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    let (secret, eth_address) = eth_key(7);
+    let (other_secret, _) = eth_key(8);
+    let mapping = Pubkey::find_program_address(&[b"eth_address".as_ref(), eth_address.as_ref()], &whistle_pool::ID).0;
+
+    let register = |pool: &TestPool, authorized: Pubkey, signer: &libsecp256k1::SecretKey, nonce: u64| {
+        pool.ix(
+            accounts::RegisterEthAddress { eth_mapping: mapping, payer: pool.payer.pubkey(), system_program: system_program::ID },
+            instruction::RegisterEthAddress {
+                eth_address,
+                authorized,
+                eth_signature: eth_sign(signer, &register_hash(&authorized, nonce, EIP712_CHAIN_ID)),
+            },
+        )
+    };
+
+    // Only the address's own key can map it
+    let recipient = Keypair::new().pubkey();
+    let err = pool.send_result(register(&pool, recipient, &other_secret, 0)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidEthSignature));
+    pool.send(register(&pool, recipient, &secret, 0)).await.unwrap();
+
+    let merkle_root = pool.current_root().await;
+    let nullifier_hash = field(b"nullifier");
+    let unshield = |pool: &TestPool, recipient: Pubkey, signer: &libsecp256k1::SecretKey| {
+        let args = whistle_pool::UnshieldArgs {
+            proof_a: test_proof(&[
+                merkle_root,
+                nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(WITHDRAW_AMOUNT),
+                field_u64(0),
+                [0u8; 32],
+                field_u64(0),
+            ]),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root,
+            change_commitment: [0u8; 32],
+            unlock_slot: 0,
+        };
+        let eth_signature = eth_sign(signer, &unshield_hash(&args, EIP712_CHAIN_ID));
+        pool.ix(
+            accounts::UnshieldEip712 {
+                eth_mapping: mapping,
+                unshield: pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, recipient),
+            },
+            instruction::UnshieldEip712 {
+                proof_a: args.proof_a,
+                proof_b: args.proof_b,
+                proof_c: args.proof_c,
+                nullifier_hash,
+                recipient,
+                withdrawal_amount: WITHDRAW_AMOUNT,
+                relayer_fee: 0,
+                merkle_root,
+                change_commitment: [0u8; 32],
+                unlock_slot: 0,
+                eth_signature,
+                eth_address,
+            },
+        )
+    };
+
+    // A signature by another key, or a valid one paying an unmapped account
+    let err = pool.send_result(unshield(&pool, recipient, &other_secret)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidEthSignature));
+    let err = pool.send_result(unshield(&pool, Keypair::new().pubkey(), &secret)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::EthAddressNotAuthorized));
+
+    let protocol_fee = WITHDRAW_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    pool.send(unshield(&pool, recipient, &secret)).await.unwrap();
+    assert_eq!(pool.balance(recipient).await, WITHDRAW_AMOUNT - protocol_fee);
+
+    // Re-registering signs the next nonce; the first registration cannot be replayed
+    let moved = Keypair::new().pubkey();
+    pool.send(register(&pool, moved, &secret, 1)).await.unwrap();
+    let err = pool.send_result(register(&pool, recipient, &secret, 0)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidEthSignature));
+    let account = pool.banks.get_account(mapping).await.unwrap().unwrap();
+    let stored = whistle_pool::EthAddressMapping::try_deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!((stored.authorized, stored.nonce), (moved, 2));
+}
+
+#[tokio::test]
+async fn withdraw_pays_program_owned_pda() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+
+    // A DeFi vault's state account: off-curve, owned by its program, with data
+    let vault_program = Pubkey::new_unique();
+    let recipient = Pubkey::find_program_address(&[b"vault"], &vault_program).0;
+    let rent = Rent::default().minimum_balance(64);
+    pool.set_account(
+        recipient,
+        Account { lamports: rent, data: vec![7u8; 64], owner: vault_program, ..Default::default() },
+    );
+    assert!(whistle_pool::is_program_address(&recipient));
+    assert!(!whistle_pool::is_program_address(&Keypair::new().pubkey()));
+
+    let nullifier_hash = field(b"nullifier");
+    let merkle_root = pool.current_root().await;
+    let proof_a = test_proof(&[
+        merkle_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(WITHDRAW_AMOUNT),
+        field_u64(0),
+        field_u64(0),
+    ]);
+    let withdraw = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, recipient),
+        instruction::Withdraw {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root,
+            unlock_slot: 0,
+        },
+    );
+    pool.send(withdraw).await.unwrap();
+
+    let account = pool.banks.get_account(recipient).await.unwrap().unwrap();
+    assert_eq!(account.lamports, rent + WITHDRAW_AMOUNT);
+    assert_eq!(account.owner, vault_program);
+    assert_eq!(account.data, vec![7u8; 64]);
+}
+
+#[tokio::test]
+async fn self_unshield_takes_no_relayer_or_fee() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+
+    let recipient = Keypair::new().pubkey();
+    let nullifier_hash = field(b"nullifier");
+    let merkle_root = pool.current_root().await;
+    let change_commitment = [0u8; 32];
+    // relayerFee is the public input that selects the self-relayed path
+    let proof_for_fee = |relayer_fee: u64| {
+        test_proof(&[
+            merkle_root,
+            nullifier_hash,
+            recipient_field(&recipient),
+            field_u64(WITHDRAW_AMOUNT),
+            field_u64(relayer_fee),
+            change_commitment,
+            field_u64(0),
+        ])
+    };
+    let self_unshield = |proof_a: [u8; 64], relayer: Option<Pubkey>| {
+        pool.ix(
+            accounts::Unshield { relayer, ..pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient) },
+            instruction::SelfUnshield {
+                proof_a,
+                proof_b: [0u8; 128],
+                proof_c: [0u8; 64],
+                nullifier_hash,
+                recipient,
+                withdrawal_amount: WITHDRAW_AMOUNT,
+                merkle_root,
+                change_commitment,
+                unlock_slot: 0,
+            },
+        )
+    };
+
+    let relayer = Keypair::new().pubkey();
+    let with_relayer = self_unshield(proof_for_fee(0), Some(relayer));
+    let proof_with_fee = self_unshield(proof_for_fee(1_000), None);
+    let self_relayed = self_unshield(proof_for_fee(0), None);
+
+    // A fee without a relayer account to pay is rejected on the relayed path
+    let fee_without_relayer = pool.ix(
+        accounts::Unshield { relayer: None, ..pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient) },
+        instruction::Unshield {
+            proof_a: proof_for_fee(1_000),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 1_000,
+            merkle_root,
+            change_commitment,
+            unlock_slot: 0,
+        },
+    );
+
+    let err = pool.send_result(with_relayer).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::SelfRelayedWithRelayer));
+    let err = pool.send_result(proof_with_fee).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidProof));
+    let err = pool.send_result(fee_without_relayer).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::MissingRelayer));
+
+    pool.send(self_relayed).await.unwrap();
+    let protocol_fee = WITHDRAW_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    assert_eq!(pool.balance(recipient).await, WITHDRAW_AMOUNT - protocol_fee);
+    assert_eq!(pool.balance(relayer).await, 0);
+}
+
+#[tokio::test]
+async fn shield_enforces_per_address_and_pool_caps() {
+    let net = SHIELD_AMOUNT - SHIELD_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    let mut pool = TestPool::start_with_deposit_caps(MERKLE_LEVELS, 2 * net, 3 * net).await;
+
+    pool.shield(field(b"a0"), SHIELD_AMOUNT).await.unwrap();
+    pool.shield(field(b"a1"), SHIELD_AMOUNT).await.unwrap();
+    let ix = pool.ix(
+        pool.shield_accounts(2, &field(b"a2")),
+        instruction::Shield { commitment: field(b"a2"), amount: SHIELD_AMOUNT },
+    );
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DepositCapExceeded));
+
+    let record = pool.banks.get_account(deposit_record(&pool.payer.pubkey())).await.unwrap().unwrap();
+    let record = whistle_pool::DepositRecord::try_deserialize(&mut record.data.as_slice()).unwrap();
+    assert_eq!(record.depositor, pool.payer.pubkey());
+    assert_eq!(record.total_deposited, 2 * net);
+
+    // A second depositor has its own allowance, up to the pool ceiling
+    let other = Keypair::new();
+    let fund = system_instruction::transfer(&pool.payer.pubkey(), &other.pubkey(), 1_000_000_000);
+    pool.send(fund).await.unwrap();
+    let other_shield = |commitment: [u8; 32]| {
+        pool.ix(
+            accounts::Shield {
+                depositor: other.pubkey(),
+                deposit_record: deposit_record(&other.pubkey()),
+                ..pool.shield_accounts(2, &commitment)
+            },
+            instruction::Shield { commitment, amount: SHIELD_AMOUNT },
+        )
+    };
+    let (first, second) = (other_shield(field(b"b0")), other_shield(field(b"b1")));
+    pool.send_signed(first, &[&other]).await.unwrap();
+    let err = pool.send_signed(second, &[&other]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::PoolShieldedCapExceeded));
+    assert_eq!(pool.pool_state().await.total_shielded, 3 * net);
+
+    // A forwarded shield has no address to count against the cap
+    let routed = pool.shield_through_router(field(b"r0"), SHIELD_AMOUNT).await;
+    let code = u32::from(WhistleError::ForwardedShieldWithAddressCap);
+    assert_eq!(routed.result, Err(TransactionError::InstructionError(0, InstructionError::Custom(code))));
+}
+
+/// Data of every event in `logs`
+fn emitted_events(logs: &[String]) -> Vec<Vec<u8>> {
+    use base64::Engine as _;
+    logs.iter()
+        .filter_map(|log| event_data(log))
+        .map(|data| base64::engine::general_purpose::STANDARD.decode(data).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn forwarded_shield_emits_what_a_direct_shield_does() {
+    use anchor_lang::Discriminator;
+
+    let net = SHIELD_AMOUNT - SHIELD_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let (forwarder, _) = router_pda();
+
+    let ix = pool.ix(pool.shield_accounts(0, &field(b"direct")), instruction::Shield { commitment: field(b"direct"), amount: SHIELD_AMOUNT });
+    let direct = pool.send_with_metadata(ix).await;
+    direct.result.unwrap();
+    let routed = pool.shield_through_router(field(b"routed"), SHIELD_AMOUNT).await;
+    routed.result.unwrap();
+
+    // One Shielded event each, the same size (the first deposit also
+    // emits HistogramUpdated)
+    let shielded = |logs: &[String]| -> Vec<Vec<u8>> {
+        emitted_events(logs).into_iter().filter(|event| event[..8] == whistle_pool::Shielded::DISCRIMINATOR).collect()
+    };
+    let direct = shielded(&direct.metadata.unwrap().log_messages);
+    let routed = shielded(&routed.metadata.unwrap().log_messages);
+    assert_eq!((direct.len(), routed.len()), (1, 1));
+    assert_eq!(routed[0][..8], direct[0][..8]);
+    assert_eq!(routed[0].len(), direct[0].len());
+
+    let event = whistle_pool::Shielded::try_from_slice(&routed[0][8..]).unwrap();
+    assert_eq!((event.commitment, event.leaf_index, event.amount), (field(b"routed"), 1, net));
+    for key in [ROUTER_ID, forwarder, pool.payer.pubkey()] {
+        assert!(!routed[0].windows(32).any(|window| window == key.as_ref()));
+    }
+
+    // Nothing is recorded about the router or the user behind it
+    assert!(pool.banks.get_account(deposit_record(&forwarder)).await.unwrap().is_none());
+    assert!(pool.banks.get_account(commitment_marker(&field(b"routed"))).await.unwrap().is_some());
+    let replayed = pool.shield_through_router(field(b"routed"), SHIELD_AMOUNT).await;
+    let code = u32::from(WhistleError::DuplicateCommitment);
+    assert_eq!(replayed.result, Err(TransactionError::InstructionError(0, InstructionError::Custom(code))));
+    assert_eq!(pool.pool_state().await.total_shielded, 2 * net);
+    assert_eq!(read_leaves(&mut pool, 0, 2).await, vec![field(b"direct"), field(b"routed")]);
+}
+
+fn private_transfer(
+    pool: &TestPool,
+    merkle_root: [u8; 32],
+    input_nullifier_hashes: [[u8; 32]; 2],
+    output_commitments: [[u8; 32]; 2],
+) -> Instruction {
+    locked_private_transfer(pool, merkle_root, input_nullifier_hashes, output_commitments, [0; 2])
+}
+
+fn locked_private_transfer(
+    pool: &TestPool,
+    merkle_root: [u8; 32],
+    input_nullifier_hashes: [[u8; 32]; 2],
+    output_commitments: [[u8; 32]; 2],
+    unlock_slots: [u64; 2],
+) -> Instruction {
+    let proof_a = test_proof(&[
+        merkle_root,
+        input_nullifier_hashes[0],
+        input_nullifier_hashes[1],
+        output_commitments[0],
+        output_commitments[1],
+        field_u64(unlock_slots[0]),
+        field_u64(unlock_slots[1]),
+    ]);
+    pool.ix(
+        pool.transfer_accounts(&input_nullifier_hashes, &output_commitments),
+        instruction::PrivateTransfer {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            input_nullifier_hashes,
+            output_commitments,
+            merkle_root,
+            unlock_slots,
+        },
+    )
+}
+
+#[tokio::test]
+async fn private_transfers_in_one_transaction_insert_in_order() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..4u8 {
+        pool.shield(field(&[b"note".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+
+    // Both proofs are made against the same root
+    let merkle_root = pool.current_root().await;
+    let nullifier_hashes = [field(b"n0"), field(b"n1"), field(b"n2"), field(b"n3")];
+    let outputs = [field(b"o0"), field(b"o1"), field(b"o2"), field(b"o3")];
+    let first = private_transfer(&pool, merkle_root, [nullifier_hashes[0], nullifier_hashes[1]], [outputs[0], outputs[1]]);
+    let second = private_transfer(&pool, merkle_root, [nullifier_hashes[2], nullifier_hashes[3]], [outputs[2], outputs[3]]);
+    pool.send_all(&[first, second]).await.unwrap();
+
+    // Outputs take leaf indices in instruction order
+    let state = pool.pool_state().await;
+    assert_eq!(state.next_index, 8);
+    let tree_account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let tree = whistle_pool::MerkleTree::try_load_versioned(&tree_account.data).unwrap();
+    let leaf_offset = (1usize << MERKLE_LEVELS) - 1;
+    assert_eq!(tree.nodes[leaf_offset + 4..leaf_offset + 8], outputs);
+    assert_eq!(tree.nodes[0], state.current_root);
+    for nullifier_hash in nullifier_hashes {
+        assert!(is_spent(&mut pool, nullifier_hash).await);
+    }
+}
+
+#[tokio::test]
+async fn failing_private_transfer_rolls_back_the_transaction() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"note0"), SHIELD_AMOUNT).await.unwrap();
+    pool.shield(field(b"note1"), SHIELD_AMOUNT).await.unwrap();
+    let before = pool.pool_state().await;
+
+    // The second transfer double-spends the first's input
+    let merkle_root = before.current_root;
+    let spent = field(b"n0");
+    let first = private_transfer(&pool, merkle_root, [spent, [0u8; 32]], [field(b"o0"), field(b"o1")]);
+    let second = private_transfer(&pool, merkle_root, [spent, field(b"n1")], [field(b"o2"), field(b"o3")]);
+    let err = pool.send_all(&[first, second]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::NullifierAlreadyUsed)));
+
+    // Nothing from the first transfer survives
+    let after = pool.pool_state().await;
+    assert_eq!(after.next_index, before.next_index);
+    assert_eq!(after.current_root, before.current_root);
+    assert!(!is_spent(&mut pool, spent).await);
+
+    // Nor can one transfer fill both input slots with the same note
+    let duplicate = private_transfer(&pool, merkle_root, [spent, spent], [field(b"o0"), field(b"o1")]);
+    let err = pool.send_result(duplicate).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+    assert!(!is_spent(&mut pool, spent).await);
+}
+
+#[tokio::test]
+async fn unshield_with_change_keeps_change_in_total_shielded() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), 3 * whistle_pool::DENOM_1_SOL).await.unwrap();
+    let before = pool.pool_state().await;
+    let vault_before = pool.balance(pda(b"vault")).await;
+
+    // Withdraw 1 SOL through a relayer from a ~3 SOL note, keeping the rest as change
+    let withdrawal_amount = whistle_pool::DENOM_1_SOL;
+    let relayer_fee = withdrawal_amount / 100;
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let nullifier_hash = field(b"nullifier");
+    let change_commitment = field(b"change");
+    let merkle_root = before.current_root;
+    let proof_a = test_proof(&[
+        merkle_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(withdrawal_amount),
+        field_u64(relayer_fee),
+        change_commitment,
+        field_u64(0),
+    ]);
+    let unshield = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, relayer),
+        instruction::Unshield {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount,
+            relayer_fee,
+            merkle_root,
+            change_commitment,
+            unlock_slot: 0,
+        },
+    );
+    pool.send(unshield).await.unwrap();
+
+    // The relayer and protocol fees come out of withdrawal_amount: the vault
+    // pays exactly that, and total_shielded drops by the same amount
+    let after = pool.pool_state().await;
+    let vault_after = pool.balance(pda(b"vault")).await;
+    assert_eq!(vault_before - vault_after, withdrawal_amount);
+    assert_eq!(before.total_shielded - after.total_shielded, withdrawal_amount);
+    assert_eq!(after.next_index, 2);
+
+    // The change is still backed: the surplus is unchanged
+    assert_eq!(
+        vault_after as i128 - after.total_shielded as i128,
+        vault_before as i128 - before.total_shielded as i128
+    );
+}
+
+const STATE_ACCOUNTS: [(u8, &[u8]); 4] = [
+    (whistle_pool::STATE_POOL, b"pool"),
+    (whistle_pool::STATE_MERKLE_TREE, b"merkle_tree"),
+    (whistle_pool::STATE_ROOTS_HISTORY, b"roots_history"),
+    (whistle_pool::STATE_NULLIFIERS, b"nullifiers"),
+];
+
+/// Export one pool state account, chunk by chunk, through export_state_chunk
+async fn export_state(pool: &mut TestPool, account_kind: u8, seed: &[u8]) -> Vec<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut offset = 0u32;
+    loop {
+        let ix = pool.ix(
+            accounts::ExportStateChunk { account: pda(seed) },
+            instruction::ExportStateChunk { account_kind, offset },
+        );
+        // Vec<u8> return data: u32 length, then the bytes, trailing zeros trimmed
+        let mut data = pool.view(ix).await;
+        data.resize(data.len().max(4), 0);
+        let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        if len == 0 {
+            return chunks;
+        }
+        data.resize(4 + len, 0);
+        chunks.push(data.split_off(4));
+        offset += len as u32;
+    }
+}
+
+fn withdraw_ix(pool: &TestPool, merkle_root: [u8; 32], nullifier_hash: [u8; 32], recipient: Pubkey) -> Instruction {
+    relayed_withdraw_ix(pool, merkle_root, nullifier_hash, recipient, recipient, 0, 0)
+}
+
+fn relayed_withdraw_ix(
+    pool: &TestPool,
+    merkle_root: [u8; 32],
+    nullifier_hash: [u8; 32],
+    recipient: Pubkey,
+    relayer: Pubkey,
+    relayer_fee: u64,
+    unlock_slot: u64,
+) -> Instruction {
+    let proof_a = test_proof(&[
+        merkle_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(WITHDRAW_AMOUNT),
+        field_u64(relayer_fee),
+        field_u64(unlock_slot),
+    ]);
+    pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, relayer),
+        instruction::Withdraw {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            amount: WITHDRAW_AMOUNT,
+            relayer_fee,
+            merkle_root,
+            unlock_slot,
+        },
+    )
+}
+
+#[tokio::test]
+async fn relayer_fees_are_capped_per_denomination() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"fee caps"), DENOMINATIONS.iter().sum::<u64>() * 3).await.unwrap();
+    let merkle_root = pool.current_root().await;
+    let relayer = Keypair::new().pubkey();
+
+    for (denom_idx, &amount) in DENOMINATIONS.iter().enumerate() {
+        let cap = amount * u64::from(DEFAULT_RELAYER_FEE_CAPS_BPS[denom_idx]) / BPS_DENOMINATOR;
+        for (relayer_fee, accepted) in [(cap - 1, true), (cap, true), (cap + 1, false)] {
+            let nullifier_hash = field(&[b"fee cap".as_slice(), &[denom_idx as u8], &relayer_fee.to_le_bytes()].concat());
+            let recipient = Keypair::new().pubkey();
+            let proof_a = test_proof(&[
+                merkle_root,
+                nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(amount),
+                field_u64(relayer_fee),
+                field_u64(0),
+            ]);
+            let withdraw = pool.ix(
+                pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, relayer),
+                instruction::Withdraw {
+                    proof_a,
+                    proof_b: [0u8; 128],
+                    proof_c: [0u8; 64],
+                    nullifier_hash,
+                    recipient,
+                    amount,
+                    relayer_fee,
+                    merkle_root,
+                    unlock_slot: 0,
+                },
+            );
+            let result = pool.send_result(withdraw).await;
+            if accepted {
+                result.unwrap_or_else(|err| panic!("{amount} lamports at fee {relayer_fee}: {err}"));
+                assert_eq!(pool.balance(recipient).await, amount - relayer_fee);
+            } else {
+                let err = result.expect_err("fee above the cap accepted");
+                assert_eq!(error_code(err), u32::from(WhistleError::FeeTooHigh), "{amount} lamports");
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn only_the_upgrade_authority_sets_relayer_fee_caps() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    // Reset the config TestPool initialized
+    let denomination_config = pda(b"denomination_config");
+    pool.set_account(denomination_config, Default::default());
+    let init = |pool: &TestPool, authority: Pubkey, relayer_fee_caps_bps: [u16; 6]| pool.ix(
+        accounts::InitDenominations {
+            pool: pda(b"pool"),
+            denomination_config,
+            program_data: program_data_address(),
+            authority,
+            system_program: system_program::ID,
+        },
+        instruction::InitDenominations { relayer_fee_caps_bps },
+    );
+
+    // Anyone but the upgrade authority is refused
+    let authority = upgrade_authority();
+    let intruder = Keypair::new();
+    pool.send(system_instruction::transfer(&pool.payer.pubkey(), &intruder.pubkey(), 1_000_000_000)).await.unwrap();
+    let err = pool.send_signed(init(&pool, intruder.pubkey(), [0; 6]), &[&intruder]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NotUpgradeAuthority));
+
+    // So is a cap above the ceiling, even from the upgrade authority
+    let mut caps = DEFAULT_RELAYER_FEE_CAPS_BPS;
+    caps[5] = whistle_pool::MAX_RELAYER_FEE_CAP_BPS + 1;
+    let err = pool.send_signed(init(&pool, authority.pubkey(), caps), &[&authority]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidFeeCap));
+
+    // The upgrade authority sets the table once
+    pool.send_signed(init(&pool, authority.pubkey(), DEFAULT_RELAYER_FEE_CAPS_BPS), &[&authority]).await.unwrap();
+    let account = pool.banks.get_account(denomination_config).await.unwrap().unwrap();
+    let config = whistle_pool::DenominationConfig::try_deserialize(&mut account.data.as_slice()).unwrap();
+    assert_eq!(config.denominations, DENOMINATIONS);
+    assert_eq!(config.relayer_fee_caps_bps, DEFAULT_RELAYER_FEE_CAPS_BPS);
+    let init_again = init(&pool, authority.pubkey(), DEFAULT_RELAYER_FEE_CAPS_BPS);
+    assert!(pool.send_signed(init_again, &[&authority]).await.is_err());
+}
+
+#[tokio::test]
+async fn only_the_upgrade_authority_initializes_the_pool() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    // Reset the pool TestPool initialized
+    pool.set_account(pda(b"pool"), Default::default());
+    let init = |pool: &TestPool, authority: Pubkey| pool.ix(
+        accounts::InitializePool {
+            pool: pda(b"pool"),
+            program_data: program_data_address(),
+            authority,
+            system_program: system_program::ID,
+        },
+        instruction::Initialize {
+            merkle_levels: MERKLE_LEVELS,
+            allow_schnorr_for_small: true,
+            max_total_deposits_per_address: 1,
+            max_total_pool_shielded: 1,
+        },
+    );
+
+    // A front-runner cannot pick the pool's security parameters
+    let intruder = Keypair::new();
+    pool.send(system_instruction::transfer(&pool.payer.pubkey(), &intruder.pubkey(), 1_000_000_000)).await.unwrap();
+    let err = pool.send_signed(init(&pool, intruder.pubkey()), &[&intruder]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NotUpgradeAuthority));
+
+    // The upgrade authority can, once
+    let authority = upgrade_authority();
+    pool.send_signed(init(&pool, authority.pubkey()), &[&authority]).await.unwrap();
+    let state = pool.pool_state().await;
+    assert!(state.allow_schnorr_for_small);
+    assert_eq!((state.max_total_deposits_per_address, state.max_total_pool_shielded), (1, 1));
+    assert!(pool.send_signed(init(&pool, authority.pubkey()), &[&authority]).await.is_err());
+}
+
+#[tokio::test]
+async fn exported_state_restores_into_a_fresh_pool() {
+    // Populate the source pool and spend one note
+    let mut source = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..3u8 {
+        source.shield(field(&[b"commitment".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let merkle_root = source.current_root().await;
+    let recipient = Keypair::new().pubkey();
+    let spent = field(b"nullifier-spent");
+    source.send(withdraw_ix(&source, merkle_root, spent, recipient)).await.unwrap();
+
+    // Copy every state account into a freshly initialized pool
+    let mut clone = TestPool::start(MERKLE_LEVELS).await;
+    for (account_kind, seed) in STATE_ACCOUNTS {
+        let mut offset = 0u32;
+        for bytes in export_state(&mut source, account_kind, seed).await {
+            let len = bytes.len() as u32;
+            let import = clone.ix(
+                accounts::ImportStateChunk { account: pda(seed) },
+                instruction::ImportStateChunk { account_kind, offset, bytes },
+            );
+            clone.send(import).await.unwrap();
+            offset += len;
+        }
+
+        let original = source.banks.get_account(pda(seed)).await.unwrap().unwrap();
+        let copy = clone.banks.get_account(pda(seed)).await.unwrap().unwrap();
+        assert_eq!(original.data, copy.data);
+    }
+
+    // Nullifier markers are not state chunks; a clone copies them as
+    // ordinary accounts
+    let marker = source.banks.get_account(nullifier_marker(&spent)).await.unwrap().unwrap();
+    clone.set_account(nullifier_marker(&spent), marker);
+
+    // Roots and counters carry over
+    let state = clone.pool_state().await;
+    assert_eq!(state.next_index, 3);
+    assert_eq!(state.current_root, source.current_root().await);
+
+    // A proof against the source's root verifies on the clone, and the
+    // source's spent nullifier stays spent
+    clone.send(withdraw_ix(&clone, merkle_root, field(b"nullifier-fresh"), recipient)).await.unwrap();
+    assert!(is_spent(&mut clone, spent).await);
+    let err = clone.send_result(withdraw_ix(&clone, merkle_root, spent, recipient)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+}
+
+#[tokio::test]
+async fn state_chunks_reject_foreign_accounts_and_overruns() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+
+    // The account must be the PDA named by account_kind
+    let wrong_kind = pool.ix(
+        accounts::ImportStateChunk { account: pda(b"pool") },
+        instruction::ImportStateChunk { account_kind: whistle_pool::STATE_NULLIFIERS, offset: 0, bytes: vec![1] },
+    );
+    let err = pool.send_result(wrong_kind).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidStateAccount));
+
+    // Chunks may not run past the end of the account
+    let len = pool.banks.get_account(pda(b"roots_history")).await.unwrap().unwrap().data.len() as u32;
+    let overrun = pool.ix(
+        accounts::ImportStateChunk { account: pda(b"roots_history") },
+        instruction::ImportStateChunk { account_kind: whistle_pool::STATE_ROOTS_HISTORY, offset: len - 1, bytes: vec![1, 2] },
+    );
+    let err = pool.send_result(overrun).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidStateChunk));
+}
+
+async fn bonded_relayer(pool: &mut TestPool, bond: u64) -> Keypair {
+    let relayer = Keypair::new();
+    // Funded by transfer: lamports from set_account would break the
+    // capitalization check when the test warps
+    let fund = system_instruction::transfer(&pool.payer.pubkey(), &relayer.pubkey(), whistle_pool::DENOM_1_SOL);
+    pool.send(fund).await.unwrap();
+    let register = pool.ix(
+        accounts::RegisterRelayer {
+            relayer_bond: relayer_bond(&relayer.pubkey()),
+            relayer: relayer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::RegisterRelayer { amount: bond },
+    );
+    pool.send_signed(register, &[&relayer]).await.unwrap();
+    relayer
+}
+
+fn relayer_bond(relayer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"relayer_bond", relayer.as_ref()], &whistle_pool::ID).0
+}
+
+fn pending_withdrawal(nullifier_hash: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"pending_withdrawal", nullifier_hash], &whistle_pool::ID).0
+}
+
+async fn approve_relayer(pool: &mut TestPool, relayer: &Keypair, nullifier_hash: [u8; 32]) {
+    let approve = pool.ix(
+        accounts::ApproveRelayerWithdrawal {
+            pending_withdrawal: pending_withdrawal(&nullifier_hash),
+            relayer_bond: relayer_bond(&relayer.pubkey()),
+            nullifiers: pda(b"nullifiers"),
+            nullifier_marker: nullifier_marker(&nullifier_hash),
+            relayer: relayer.pubkey(),
+            user: pool.payer.pubkey(),
+            system_program: system_program::ID,
+            congestion: pda(b"congestion"),
+        },
+        instruction::ApproveRelayerWithdrawal { nullifier_hash },
+    );
+    pool.send_signed(approve, &[relayer]).await.unwrap();
+}
+
+fn prove_inactivity(pool: &TestPool, nullifier_hash: &[u8; 32], relayer_pubkey: Pubkey) -> Instruction {
+    pool.ix(
+        accounts::ProveRelayerInactivity {
+            pending_withdrawal: pending_withdrawal(nullifier_hash),
+            relayer_bond: relayer_bond(&relayer_pubkey),
+            nullifiers: pda(b"nullifiers"),
+            nullifier_marker: nullifier_marker(nullifier_hash),
+            user: pool.payer.pubkey(),
+        },
+        instruction::ProveRelayerInactivity { relayer_pubkey },
+    )
+}
+
+#[tokio::test]
+async fn inactive_relayer_is_slashed_and_can_be_replaced() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    let ghost = bonded_relayer(&mut pool, 2 * whistle_pool::SLASH_AMOUNT).await;
+    let backup = bonded_relayer(&mut pool, whistle_pool::SLASH_AMOUNT).await;
+    let nullifier_hash = field(b"nullifier");
+    approve_relayer(&mut pool, &ghost, nullifier_hash).await;
+
+    // The relayer still has time to submit
+    let err = pool.send_result(prove_inactivity(&pool, &nullifier_hash, ghost.pubkey())).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::RelayerNotInactive));
+
+    // Only the approved relayer can be slashed
+    pool.warp_slots(whistle_pool::RELAYER_EXECUTION_TIMEOUT_SLOTS + 1).await;
+    let err = pool.send_result(prove_inactivity(&pool, &nullifier_hash, backup.pubkey())).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidRelayerBond));
+
+    let bond_before = pool.balance(relayer_bond(&ghost.pubkey())).await;
+    pool.send(prove_inactivity(&pool, &nullifier_hash, ghost.pubkey())).await.unwrap();
+    assert_eq!(bond_before - pool.balance(relayer_bond(&ghost.pubkey())).await, whistle_pool::SLASH_AMOUNT);
+    let bond_account = pool.banks.get_account(relayer_bond(&ghost.pubkey())).await.unwrap().unwrap();
+    let bond = whistle_pool::RelayerBond::try_deserialize(&mut bond_account.data.as_slice()).unwrap();
+    assert_eq!(bond.bond, whistle_pool::SLASH_AMOUNT);
+    assert!(pool.banks.get_account(pending_withdrawal(&nullifier_hash)).await.unwrap().is_none());
+
+    // The note can go to another relayer, which cannot be slashed once the
+    // withdrawal lands
+    approve_relayer(&mut pool, &backup, nullifier_hash).await;
+    let merkle_root = pool.current_root().await;
+    pool.send(withdraw_ix(&pool, merkle_root, nullifier_hash, Keypair::new().pubkey())).await.unwrap();
+    pool.warp_slots(whistle_pool::RELAYER_EXECUTION_TIMEOUT_SLOTS + 1).await;
+    let err = pool.send_result(prove_inactivity(&pool, &nullifier_hash, backup.pubkey())).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+}
+
+#[tokio::test]
+async fn commitment_compatibility_accepts_only_the_program_poseidon() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let vectors = whistle_pool::COMMITMENT_COMPATIBILITY_INPUTS
+        .map(|(left, right)| whistle_pool::poseidon_hash(&[&left, &right]));
+
+    // A client with swapped inputs or little-endian output fails
+    let mut swapped = vectors;
+    swapped.swap(0, 1);
+    let reversed = vectors.map(|mut hash| {
+        hash.reverse();
+        hash
+    });
+    let checks = [vectors, swapped, reversed].map(|test_vectors| {
+        pool.ix(accounts::VerifyCommitmentCompatibility {}, instruction::VerifyCommitmentCompatibility { test_vectors })
+    });
+    let mut results = Vec::new();
+    for check in checks {
+        // Return data is a bool; false is trimmed to nothing
+        results.push(pool.view(check).await == [1]);
+    }
+    assert_eq!(results, [true, false, false]);
+}
+
+async fn congestion_info(pool: &mut TestPool) -> CongestionInfo {
+    let ix = pool.ix(
+        accounts::GetCongestionInfo { congestion: pda(b"congestion") },
+        instruction::GetCongestionInfo {},
+    );
+    let mut data = pool.view(ix).await;
+    data.resize(8 + 4 * 4 + 8 + 4, 0);
+    CongestionInfo::try_from_slice(&data).unwrap()
+}
+
+/// Withdrawals plus approvals seen in the current and previous window
+fn congestion_counts(info: &CongestionInfo) -> [u32; 4] {
+    [
+        info.withdrawals_this_window,
+        info.withdrawals_last_window,
+        info.approvals_this_window,
+        info.approvals_last_window,
+    ]
+}
+
+#[tokio::test]
+async fn congestion_info_follows_approvals_and_withdrawals() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..2u8 {
+        pool.shield(field(&[b"commitment".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let relayer = bonded_relayer(&mut pool, whistle_pool::SLASH_AMOUNT).await;
+
+    // Start from the first slot of a window so everything below lands in it
+    let window_slots = whistle_pool::CONGESTION_WINDOW_SLOTS;
+    let slot = pool.slot().await;
+    pool.warp_slots(window_slots - slot % window_slots).await;
+    let idle = congestion_info(&mut pool).await;
+    assert_eq!(congestion_counts(&idle), [0; 4]);
+    assert_eq!(idle.window_slots, window_slots);
+    assert_eq!(idle.window_target, whistle_pool::CONGESTION_WINDOW_TARGET);
+
+    // Each approval, then each withdrawal, raises the load
+    let nullifier_hashes = [field(b"nullifier 0"), field(b"nullifier 1")];
+    let mut seen = Vec::new();
+    for nullifier_hash in nullifier_hashes {
+        approve_relayer(&mut pool, &relayer, nullifier_hash).await;
+        seen.push(congestion_counts(&congestion_info(&mut pool).await));
+    }
+    let merkle_root = pool.current_root().await;
+    for nullifier_hash in nullifier_hashes {
+        pool.send(withdraw_ix(&pool, merkle_root, nullifier_hash, Keypair::new().pubkey())).await.unwrap();
+        seen.push(congestion_counts(&congestion_info(&mut pool).await));
+    }
+    assert_eq!(seen, [[0, 0, 1, 0], [0, 0, 2, 0], [1, 0, 2, 0], [2, 0, 2, 0]]);
+
+    // The counts move to last_* in the next window, and age out after it
+    pool.warp_slots(window_slots).await;
+    assert_eq!(congestion_counts(&congestion_info(&mut pool).await), [0, 2, 0, 2]);
+    pool.warp_slots(window_slots).await;
+    assert_eq!(congestion_counts(&congestion_info(&mut pool).await), [0; 4]);
+
+    // A withdrawal after an idle window starts afresh
+    pool.send(withdraw_ix(&pool, merkle_root, field(b"nullifier 2"), Keypair::new().pubkey())).await.unwrap();
+    assert_eq!(congestion_counts(&congestion_info(&mut pool).await), [1, 0, 0, 0]);
+}
+
+fn intent_lock(nullifier_hash: &[u8; 32]) -> Pubkey {
+    Pubkey::find_program_address(&[b"intent_lock", nullifier_hash], &whistle_pool::ID).0
+}
+
+fn take_intent_lock(pool: &TestPool, holder: &Pubkey, nullifier_hash: [u8; 32]) -> Instruction {
+    pool.ix(
+        accounts::TakeIntentLock {
+            intent_lock: intent_lock(&nullifier_hash),
+            nullifiers: pda(b"nullifiers"),
+            nullifier_marker: nullifier_marker(&nullifier_hash),
+            holder: *holder,
+            system_program: system_program::ID,
+        },
+        instruction::TakeIntentLock { nullifier_hash },
+    )
+}
+
+fn release_intent_lock(pool: &TestPool, nullifier_hash: &[u8; 32], rent_payer: Pubkey, closer: Pubkey) -> Instruction {
+    pool.ix(
+        accounts::ReleaseIntentLock { intent_lock: intent_lock(nullifier_hash), rent_payer, closer },
+        instruction::ReleaseIntentLock {},
+    )
+}
+
+#[tokio::test]
+async fn intent_locks_keep_one_node_per_withdrawal_until_they_lapse() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    let [crashed, backup] = [Keypair::new(), Keypair::new()];
+    for node in [&crashed, &backup] {
+        let fund = system_instruction::transfer(&pool.payer.pubkey(), &node.pubkey(), whistle_pool::DENOM_01_SOL);
+        pool.send(fund).await.unwrap();
+    }
+    let nullifier_hash = field(b"nullifier");
+
+    // The first node to lock wins; the other aborts before submitting
+    pool.send_signed(take_intent_lock(&pool, &crashed.pubkey(), nullifier_hash), &[&crashed]).await.unwrap();
+    let err = pool
+        .send_signed(take_intent_lock(&pool, &backup.pubkey(), nullifier_hash), &[&backup])
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::IntentLocked));
+    let err = pool
+        .send_signed(release_intent_lock(&pool, &nullifier_hash, crashed.pubkey(), backup.pubkey()), &[&backup])
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::IntentLockHeld));
+
+    // The holder never submits: once the lock lapses the other node takes
+    // it over, and the withdrawal still goes through
+    pool.warp_slots(whistle_pool::INTENT_LOCK_TTL_SLOTS).await;
+    pool.send_signed(take_intent_lock(&pool, &backup.pubkey(), nullifier_hash), &[&backup]).await.unwrap();
+    let err = pool
+        .send_signed(take_intent_lock(&pool, &crashed.pubkey(), nullifier_hash), &[&crashed])
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::IntentLocked));
+
+    let merkle_root = pool.current_root().await;
+    pool.send(withdraw_ix(&pool, merkle_root, nullifier_hash, Keypair::new().pubkey())).await.unwrap();
+    let err = pool
+        .send_signed(take_intent_lock(&pool, &crashed.pubkey(), nullifier_hash), &[&crashed])
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+
+    // Releasing refunds the node that created the lock
+    let rent = pool.balance(intent_lock(&nullifier_hash)).await;
+    let before = pool.balance(crashed.pubkey()).await;
+    pool.send_signed(release_intent_lock(&pool, &nullifier_hash, crashed.pubkey(), backup.pubkey()), &[&backup])
+        .await
+        .unwrap();
+    assert_eq!(pool.balance(crashed.pubkey()).await, before + rent);
+    assert!(pool.banks.get_account(intent_lock(&nullifier_hash)).await.unwrap().is_none());
+}
+
+fn rebuild_root(pool: &TestPool, end_node: u32, count: u16) -> Instruction {
+    pool.ix(
+        accounts::RebuildRoot {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            roots_history: pda(b"roots_history"),
+        },
+        instruction::RebuildRoot { end_node, count },
+    )
+}
+
+/// rebuild_root calls covering every internal node, children first
+fn full_rebuild(pool: &TestPool) -> Vec<Instruction> {
+    let mut end_node = (1u32 << MERKLE_LEVELS) - 1;
+    let mut calls = Vec::new();
+    while end_node > 0 {
+        let count = end_node.min(u32::from(whistle_pool::MAX_REBUILD_NODES)) as u16;
+        calls.push(rebuild_root(pool, end_node, count));
+        end_node -= u32::from(count);
+    }
+    calls
+}
+
+#[tokio::test]
+async fn desynced_tree_is_rejected_until_rebuilt() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..2u8 {
+        pool.shield(field(&[b"commitment".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let stale_tree = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+
+    // Rebuilding a consistent tree changes nothing
+    for ix in full_rebuild(&pool) {
+        pool.send(ix).await.unwrap();
+    }
+    let rebuilt = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    assert_eq!(rebuilt.data, stale_tree.data);
+
+    // Desync: the pool and the third leaf reflect a shield whose path and
+    // root never reached the tree
+    let commitment = field(b"commitment-2");
+    pool.shield(commitment, SHIELD_AMOUNT).await.unwrap();
+    let state = pool.pool_state().await;
+    let mut desynced = stale_tree;
+    let nodes = 8 + whistle_pool::MERKLE_TREE_HEADER_SIZE;
+    let leaf = nodes + ((1usize << MERKLE_LEVELS) - 1 + 2) * 32;
+    desynced.data[leaf..leaf + 32].copy_from_slice(&commitment);
+    pool.set_account(pda(b"merkle_tree"), desynced);
+
+    let shield = pool.ix(pool.shield_accounts(state.next_index, &field(b"commitment-3")), instruction::Shield { commitment: field(b"commitment-3"), amount: SHIELD_AMOUNT });
+    let err = pool.send_result(shield).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::TreeStateDesync));
+
+    // Out-of-range chunks are rejected
+    let err = pool.send_result(rebuild_root(&pool, 1 << MERKLE_LEVELS, 1)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidRebuildRange));
+
+    // Anyone can rebuild from the stored leaves, restoring the pool's root
+    for ix in full_rebuild(&pool) {
+        pool.send(ix).await.unwrap();
+    }
+    assert_eq!(pool.current_root().await, state.current_root);
+    pool.shield(field(b"commitment-3"), SHIELD_AMOUNT).await.unwrap();
+}
+
+#[tokio::test]
+async fn withdrawal_receipts_match_only_the_payment_made() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..3u8 {
+        pool.shield(field(&[b"commitment".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let merkle_root = pool.current_root().await;
+    let merchant = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let relayer_fee = WITHDRAW_AMOUNT / 20; // above the rent-exempt minimum
+    let protocol_fee = WITHDRAW_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+
+    // withdraw without a fee, withdraw through a relayer, unshield with change
+    let nullifiers: Vec<_> = (0..3u8).map(|i| field(&[b"nullifier".as_slice(), &[i]].concat())).collect();
+    let fee_withdraw = relayed_withdraw_ix(&pool, merkle_root, nullifiers[1], merchant, relayer, relayer_fee, 0);
+    let change_commitment = field(b"change");
+    let unshield = pool.ix(
+        pool.unshield_accounts(&nullifiers[2], &change_commitment, merchant, merchant),
+        instruction::Unshield {
+            proof_a: test_proof(&[
+                merkle_root,
+                nullifiers[2],
+                recipient_field(&merchant),
+                field_u64(WITHDRAW_AMOUNT),
+                field_u64(0),
+                change_commitment,
+                field_u64(0),
+            ]),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash: nullifiers[2],
+            recipient: merchant,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root,
+            change_commitment,
+            unlock_slot: 0,
+        },
+    );
+    let payouts = [
+        (withdraw_ix(&pool, merkle_root, nullifiers[0], merchant), WITHDRAW_AMOUNT),
+        (fee_withdraw, WITHDRAW_AMOUNT - relayer_fee),
+        (unshield, WITHDRAW_AMOUNT - protocol_fee),
+    ];
+    // The receipted amounts are exactly what the merchant was paid
+    let receipts_total: u64 = payouts.iter().map(|(_, received)| received).sum();
+
+    // program-test's native processor drops emit! data, so rebuild each
+    // receipt from the withdrawal's slot the way the event computes it
+    let mut receipts = Vec::new();
+    for ((ix, received), nullifier_hash) in payouts.into_iter().zip(&nullifiers) {
+        let slot = pool.slot().await;
+        pool.send(ix).await.unwrap();
+        let receipt = whistle_pool::receipt_hash(nullifier_hash, &merchant, received, slot);
+        receipts.push(receipt);
+
+        // A wrong recipient, amount, slot or nullifier gives another receipt
+        assert_ne!(receipt, whistle_pool::receipt_hash(nullifier_hash, &relayer, received, slot));
+        assert_ne!(receipt, whistle_pool::receipt_hash(nullifier_hash, &merchant, received + 1, slot));
+        assert_ne!(receipt, whistle_pool::receipt_hash(nullifier_hash, &merchant, received, slot + 1));
+        assert_ne!(receipt, whistle_pool::receipt_hash(&field(b"other"), &merchant, received, slot));
+    }
+    assert_eq!(pool.balance(merchant).await, receipts_total);
+    receipts.dedup();
+    assert_eq!(receipts.len(), 3);
+}
+
+/// whistle-merkle's verify_merkle_proof for `leaf` at `leaf_index` of the
+/// pool's tree, with its siblings read from the tree account
+async fn merkle_verify_ix(pool: &mut TestPool, leaf: [u8; 32], leaf_index: usize, root: [u8; 32]) -> Instruction {
+    let tree_account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let tree = whistle_pool::MerkleTree::try_load_versioned(&tree_account.data).unwrap();
+    merkle_verify_path_ix(leaf, whistle_merkle::generate_merkle_path(tree.nodes, leaf_index as u64, MERKLE_LEVELS), root)
+}
+
+fn merkle_verify_path_ix(leaf: [u8; 32], path: MerklePath, root: [u8; 32]) -> Instruction {
+    let MerklePath { path_elements, path_indices, .. } = path;
+    Instruction {
+        program_id: whistle_merkle::ID,
+        accounts: Vec::new(),
+        data: whistle_merkle::instruction::VerifyMerkleProof { leaf, path_elements, path_indices, root }.data(),
+    }
+}
+
+#[tokio::test]
+async fn whistle_merkle_verifies_paths_of_the_pool_tree() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let commitments = [field(b"commitment 0"), field(b"commitment 1")];
+    for commitment in commitments {
+        pool.shield(commitment, SHIELD_AMOUNT).await.unwrap();
+    }
+    let root = pool.current_root().await;
+    assert_eq!(
+        whistle_merkle::compute_poseidon(&commitments[0], &commitments[1]),
+        whistle_pool::poseidon_hash(&[&commitments[0], &commitments[1]]),
+    );
+
+    // Both leaves verify against the pool's root; the return data is the
+    // bool, with a false trimmed to nothing
+    for (leaf_index, leaf) in commitments.into_iter().enumerate() {
+        let verify = merkle_verify_ix(&mut pool, leaf, leaf_index, root).await;
+        assert_eq!(pool.view(verify).await, [1]);
+    }
+
+    // ...and not against another root or at another index
+    let verify = merkle_verify_ix(&mut pool, commitments[0], 0, field(b"other root")).await;
+    assert!(pool.view(verify).await.is_empty());
+    let verify = merkle_verify_ix(&mut pool, commitments[0], 1, root).await;
+    assert!(pool.view(verify).await.is_empty());
+}
+
+#[test]
+fn whistle_merkle_zero_values_chain_by_poseidon() {
+    let zeros = whistle_merkle::ZERO_VALUES;
+    assert_eq!(zeros[0], [0u8; 32]);
+    for level in 1..zeros.len() {
+        let below = zeros[level - 1];
+        assert_eq!(zeros[level], whistle_pool::poseidon_hash(&[&below, &below]), "level {level}");
+        assert_eq!(whistle_merkle::get_zero_value(level), zeros[level]);
+    }
+    assert_eq!(zeros[..whistle_pool::ZERO_SUBTREE.len()], whistle_pool::ZERO_SUBTREE);
+
+    let top = zeros[zeros.len() - 1];
+    assert_eq!(whistle_merkle::get_zero_value(zeros.len()), whistle_pool::poseidon_hash(&[&top, &top]));
+}
+
+#[test]
+fn pool_zero_subtree_matches_whistle_merkle_zero_values() {
+    let levels = whistle_pool::ZERO_SUBTREE.len();
+    assert_eq!(whistle_pool::ZERO_SUBTREE[..], whistle_merkle::ZERO_VALUES[..levels]);
+    for height in 0..whistle_merkle::ZERO_VALUES.len() {
+        assert_eq!(whistle_pool::empty_tree_root(height as u8), whistle_merkle::get_zero_value(height), "height {height}");
+    }
+}
+
+fn get_leaves_page(pool: &TestPool, page_index: u32, start: u16, count: u8) -> Instruction {
+    pool.ix(
+        accounts::GetLeavesPage { pool: pda(b"pool"), leaf_page: leaf_page(page_index) },
+        instruction::GetLeavesPage { _page_index: page_index, start, count },
+    )
+}
+
+async fn read_leaves(pool: &mut TestPool, start: u16, count: u8) -> Vec<[u8; 32]> {
+    let mut data = pool.view(get_leaves_page(pool, 0, start, count)).await;
+    data.resize(data.len().max(4), 0);
+    let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+    data.resize(4 + 32 * len, 0);
+    Vec::<[u8; 32]>::try_from_slice(&data).unwrap()
+}
+
+fn sync_leaf_page(pool: &TestPool, page_index: u32) -> Instruction {
+    pool.ix(
+        accounts::SyncLeafPage {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            leaf_page: leaf_page(page_index),
+            payer: pool.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::SyncLeafPage { page_index },
+    )
+}
+
+#[tokio::test]
+async fn leaf_pages_follow_shields_and_sync_other_inserts() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let commitments: Vec<_> = (0..3u8).map(|i| field(&[b"commitment".as_slice(), &[i]].concat())).collect();
+    for commitment in &commitments {
+        pool.shield(*commitment, SHIELD_AMOUNT).await.unwrap();
+    }
+    assert_eq!(read_leaves(&mut pool, 0, MAX_LEAF_READ).await, commitments);
+
+    // The change note of an unshield is inserted without the leaf page, so
+    // the next shield cannot extend it either
+    let recipient = Keypair::new().pubkey();
+    let nullifier_hash = field(b"nullifier");
+    let merkle_root = pool.current_root().await;
+    let change_commitment = field(b"change");
+    let unshield = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient),
+        instruction::Unshield {
+            proof_a: test_proof(&[
+                merkle_root,
+                nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(WITHDRAW_AMOUNT),
+                field_u64(0),
+                change_commitment,
+                field_u64(0),
+            ]),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root,
+            change_commitment,
+            unlock_slot: 0,
+        },
+    );
+    pool.send(unshield).await.unwrap();
+    let late = field(b"late commitment");
+    pool.shield(late, SHIELD_AMOUNT).await.unwrap();
+    assert_eq!(read_leaves(&mut pool, 0, MAX_LEAF_READ).await, commitments);
+
+    // Syncing copies the missing leaves from the tree, in leaf order
+    pool.send(sync_leaf_page(&pool, 0)).await.unwrap();
+    let leaves = read_leaves(&mut pool, 0, MAX_LEAF_READ).await;
+    assert_eq!(leaves.len(), 5);
+    assert_eq!(leaves[..3], commitments[..]);
+    assert_eq!(read_leaves(&mut pool, 3, 2).await, vec![change_commitment, late]);
+    assert_eq!(read_leaves(&mut pool, 5, 2).await, Vec::<[u8; 32]>::new());
+
+    // A seven-level tree fits in page 0, and reads are capped to fit return data
+    let err = pool.send_result(sync_leaf_page(&pool, 1)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidLeafPage));
+    let err = pool.send_result(get_leaves_page(&pool, 0, 0, MAX_LEAF_READ + 1)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidPageSize));
+}
+
+/// Paths generated on chain verify each leaf against the pool's current
+/// root through whistle-merkle
+#[tokio::test]
+async fn generated_merkle_proofs_verify_against_the_current_root() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let generate = |pool: &TestPool, leaf_index: u64| {
+        pool.ix(
+            accounts::GenerateMerkleProof { pool: pda(b"pool"), merkle_tree: pda(b"merkle_tree") },
+            instruction::GenerateMerkleProof { leaf_index },
+        )
+    };
+    let err = pool.send_result(generate(&pool, 0)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::LeafNotInserted));
+
+    let commitments: Vec<_> = (0..5u8).map(|i| field(&[b"commitment".as_slice(), &[i]].concat())).collect();
+    for commitment in &commitments {
+        pool.shield(*commitment, SHIELD_AMOUNT).await.unwrap();
+    }
+    let root = pool.current_root().await;
+
+    // Trailing zero bytes are trimmed from return data
+    let levels = MERKLE_LEVELS as usize;
+    let path_size = 4 + 32 * levels + 4 + levels + 32 + 8;
+    for (leaf_index, commitment) in commitments.iter().enumerate() {
+        let mut data = pool.view(generate(&pool, leaf_index as u64)).await;
+        data.resize(path_size, 0);
+        let path = MerklePath::try_from_slice(&data).unwrap();
+        assert_eq!((path.root, path.leaf_index, path.path_elements.len()), (root, leaf_index as u64, levels));
+        assert_eq!(path.path_indices[0] as usize, leaf_index % 2);
+        assert_eq!(pool.view(merkle_verify_path_ix(*commitment, path, root)).await, [1]);
+    }
+
+    let err = pool.send_result(generate(&pool, 5)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::LeafNotInserted));
+}
+
+/// Encrypted notes are stored for inserted leaves only, one per leaf,
+/// readable by anyone and closed by their owner alone
+#[tokio::test]
+async fn encrypted_notes_are_stored_read_and_deleted() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let owner = pool.payer.pubkey();
+    let note = |leaf_index: u64| Pubkey::find_program_address(&[b"note", &leaf_index.to_le_bytes()], &whistle_pool::ID).0;
+    let store = |pool: &TestPool, leaf_index: u64, encrypted_data: Vec<u8>| {
+        pool.ix(
+            accounts::StoreNote { pool: pda(b"pool"), note: note(leaf_index), owner, system_program: system_program::ID },
+            instruction::StoreEncryptedNote { leaf_index, encrypted_data },
+        )
+    };
+    let delete = |pool: &TestPool, owner: Pubkey| {
+        pool.ix(accounts::DeleteEncryptedNote { note: note(0), owner }, instruction::DeleteEncryptedNote {})
+    };
+
+    let ciphertext = vec![0xab; MAX_NOTE_CIPHERTEXT];
+    let err = pool.send_result(store(&pool, 0, ciphertext.clone())).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::LeafNotInserted));
+
+    pool.shield(field(b"commitment"), SHIELD_AMOUNT).await.unwrap();
+    for encrypted_data in [Vec::new(), vec![0xab; MAX_NOTE_CIPHERTEXT + 1]] {
+        let err = pool.send_result(store(&pool, 0, encrypted_data)).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(WhistleError::InvalidNoteCiphertext));
+    }
+    pool.send(store(&pool, 0, ciphertext.clone())).await.unwrap();
+    assert!(pool.send_result(store(&pool, 0, vec![0xcd])).await.is_err());
+
+    // Trailing zero bytes are trimmed from return data
+    let read = pool.ix(accounts::ReadEncryptedNote { note: note(0) }, instruction::ReadEncryptedNote { _leaf_index: 0 });
+    let mut data = pool.view(read).await;
+    data.resize(EncryptedNote::space(MAX_NOTE_CIPHERTEXT) - 8, 0);
+    let stored = EncryptedNote::try_from_slice(&data).unwrap();
+    assert_eq!((stored.owner, stored.leaf_index, stored.ciphertext), (owner, 0, ciphertext));
+    assert!(stored.stored_at > 0);
+
+    let other = Keypair::new();
+    let err = pool.send_signed(delete(&pool, other.pubkey()), &[&other]).await.unwrap_err();
+    assert_eq!(error_code(err), anchor_lang::error::ErrorCode::ConstraintHasOne as u32);
+    let rent = pool.balance(note(0)).await;
+    let before = pool.balance(owner).await;
+    pool.send(delete(&pool, owner)).await.unwrap();
+    assert!(pool.banks.get_account(note(0)).await.unwrap().is_none());
+    assert_eq!(pool.balance(owner).await, before + rent - 5_000);
+}
+
+#[tokio::test]
+async fn view_keys_check_disclosed_notes_until_revoked() {
+    use whistle_pool::{derive_view_key, poseidon_hash, NoteData, ViewKeyRegistered, ViewKeyRevoked};
+
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let owner = pool.payer.pubkey();
+    let record = Pubkey::find_program_address(&[b"view_key", owner.as_ref()], &whistle_pool::ID).0;
+    let register = |pool: &TestPool, view_key: [u8; 32]| {
+        pool.ix(
+            accounts::RegisterViewKey { view_key_record: record, owner, system_program: system_program::ID },
+            instruction::RegisterViewKey { view_key },
+        )
+    };
+    let verify = |pool: &TestPool, commitment: [u8; 32], view_key: [u8; 32], claimed_note: NoteData| {
+        pool.ix(
+            accounts::VerifyDecryption { view_key_record: record },
+            instruction::VerifyViewKeyDecryption { commitment, view_key, claimed_note },
+        )
+    };
+
+    let view_key = derive_view_key(&field(b"spending key"));
+    assert_eq!(view_key, poseidon_hash(&[&field(b"spending key"), &[1u8; 32]]));
+    let sent = pool.send_with_metadata(register(&pool, view_key)).await;
+    sent.result.unwrap();
+    let events = emitted_events(&sent.metadata.unwrap().log_messages);
+    let event = ViewKeyRegistered::try_from_slice(&events[0][8..]).unwrap();
+    assert_eq!((event.owner, event.view_key), (owner, view_key));
+    assert!(pool.send_result(register(&pool, field(b"second key"))).await.is_err());
+
+    // Both commitment forms open; a wrong note or key does not. A false is
+    // trimmed from the return data to nothing
+    let note = NoteData { secret: field(b"secret"), nullifier: field(b"nullifier"), amount: SHIELD_AMOUNT, unlock_slot: 0 };
+    let inner = poseidon_hash(&[&note.nullifier, &field_u64(SHIELD_AMOUNT)]);
+    let commitment = poseidon_hash(&[&note.secret, &inner]);
+    let locked = NoteData { unlock_slot: 500, ..note.clone() };
+    let locked_commitment = poseidon_hash(&[&note.secret, &inner, &field_u64(500)]);
+    assert_eq!(pool.view(verify(&pool, commitment, view_key, note.clone())).await, [1]);
+    assert_eq!(pool.view(verify(&pool, locked_commitment, view_key, locked.clone())).await, [1]);
+    assert!(pool.view(verify(&pool, commitment, view_key, locked)).await.is_empty());
+    assert!(pool.view(verify(&pool, commitment, field(b"other key"), note.clone())).await.is_empty());
+    let wrong_amount = NoteData { amount: SHIELD_AMOUNT + 1, ..note.clone() };
+    assert!(pool.view(verify(&pool, commitment, view_key, wrong_amount)).await.is_empty());
+
+    // Revoking closes the record, so nothing verifies against it
+    let revoke = pool.ix(accounts::RevokeViewKey { view_key_record: record, owner }, instruction::RevokeViewKey {});
+    let sent = pool.send_with_metadata(revoke).await;
+    sent.result.unwrap();
+    let events = emitted_events(&sent.metadata.unwrap().log_messages);
+    let event = ViewKeyRevoked::try_from_slice(&events[0][8..]).unwrap();
+    assert_eq!((event.owner, event.view_key), (owner, view_key));
+    assert!(pool.banks.get_account(record).await.unwrap().is_none());
+    assert!(pool.send_result(verify(&pool, commitment, view_key, note)).await.is_err());
+    pool.send(register(&pool, field(b"rotated key"))).await.unwrap();
+}
+
+fn locked_unshield_ix(
+    pool: &TestPool,
+    merkle_root: [u8; 32],
+    nullifier_hash: [u8; 32],
+    recipient: Pubkey,
+    unlock_slot: u64,
+) -> Instruction {
+    let change_commitment = field(b"change");
+    pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, recipient),
+        instruction::Unshield {
+            proof_a: test_proof(&[
+                merkle_root,
+                nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(WITHDRAW_AMOUNT),
+                field_u64(0),
+                change_commitment,
+                field_u64(unlock_slot),
+            ]),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root,
+            change_commitment,
+            unlock_slot,
+        },
+    )
+}
+
+#[tokio::test]
+async fn time_locked_notes_are_spendable_from_their_unlock_slot() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..4u8 {
+        pool.shield(field(&[b"gift".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let merkle_root = pool.current_root().await;
+    let unlock_slot = pool.slot().await + 100;
+    let recipient = Keypair::new().pubkey();
+    let nullifiers: Vec<_> = (0..4u8).map(|i| field(&[b"gift nullifier".as_slice(), &[i]].concat())).collect();
+
+    let spends = [
+        relayed_withdraw_ix(&pool, merkle_root, nullifiers[0], recipient, recipient, 0, unlock_slot),
+        locked_unshield_ix(&pool, merkle_root, nullifiers[1], recipient, unlock_slot),
+        // A locked note spent next to a standard one
+        locked_private_transfer(
+            &pool,
+            merkle_root,
+            [nullifiers[2], nullifiers[3]],
+            [field(b"out0"), field(b"out1")],
+            [0, unlock_slot],
+        ),
+    ];
+
+    // Every spend path refuses the note before its unlock slot
+    for ix in spends.clone() {
+        let err = pool.send_result(ix).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(WhistleError::NoteStillLocked));
+    }
+
+    // The unlock slot is a public input, so the note cannot be claimed as unlocked
+    let mut understated = spends[0].clone();
+    let unlock_offset = understated.data.len() - 8;
+    understated.data[unlock_offset..].copy_from_slice(&0u64.to_le_bytes());
+    let err = pool.send_result(understated).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidProof));
+
+    // From the unlock slot on, the same spends go through
+    pool.warp_slots(100).await;
+    assert!(pool.slot().await >= unlock_slot);
+    for ix in spends {
+        pool.send(ix).await.unwrap();
+    }
+    for nullifier_hash in nullifiers {
+        assert!(is_spent(&mut pool, nullifier_hash).await);
+    }
+    // The unshield pays the protocol fee, the withdraw does not
+    let protocol_fee = WITHDRAW_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    assert_eq!(pool.balance(recipient).await, 2 * WITHDRAW_AMOUNT - protocol_fee);
+}
+
+fn attest_finality_ix(pool: &TestPool, program_data: Pubkey) -> Instruction {
+    pool.ix(
+        accounts::AttestFinality {
+            pool: pda(b"pool"),
+            finality_attestation: finality_attestation_address(),
+            program_data,
+            attester: pool.payer.pubkey(),
+            system_program: system_program::ID,
+        },
+        instruction::AttestFinality {},
+    )
+}
+
+fn finality_attestation_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"finality_attestation", pda(b"pool").as_ref()], &whistle_pool::ID).0
+}
+
+async fn finality_attestation(pool: &mut TestPool) -> FinalityAttestation {
+    let view = pool.ix(
+        accounts::GetFinalityAttestation { pool: pda(b"pool"), finality_attestation: finality_attestation_address() },
+        instruction::GetFinalityAttestation {},
+    );
+    let mut data = pool.view(view).await;
+    data.resize(FinalityAttestation::SIZE - 8, 0);
+    FinalityAttestation::try_from_slice(&data).unwrap()
+}
+
+#[tokio::test]
+async fn finality_attestation_tracks_the_upgrade_authority() {
+    let program_data = program_data_address();
+    let authority = Keypair::new().pubkey();
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.set_account(program_data, program_data_account(1, Some(authority)));
+
+    // Upgradeable: recorded as such
+    pool.send(attest_finality_ix(&pool, program_data)).await.unwrap();
+    let attestation = finality_attestation(&mut pool).await;
+    assert!(!attestation.immutable);
+    assert_eq!(attestation.immutable_since_slot, 0);
+    assert_eq!(attestation.deployed_slot, 1);
+    assert_eq!(attestation.attested_slot, pool.slot().await);
+
+    // Burning the authority and re-attesting updates the record
+    pool.warp_slots(10).await;
+    pool.set_account(program_data, program_data_account(1, None));
+    pool.send(attest_finality_ix(&pool, program_data)).await.unwrap();
+    let burned_slot = pool.slot().await;
+    let attestation = finality_attestation(&mut pool).await;
+    assert!(attestation.immutable);
+    assert_eq!(attestation.immutable_since_slot, burned_slot);
+    assert_eq!(attestation.attested_slot, burned_slot);
+
+    // Later attestations keep the slot it was first seen immutable at
+    pool.warp_slots(10).await;
+    pool.send(attest_finality_ix(&pool, program_data)).await.unwrap();
+    let attestation = finality_attestation(&mut pool).await;
+    assert!(attestation.immutable);
+    assert_eq!(attestation.immutable_since_slot, burned_slot);
+    assert_eq!(attestation.attested_slot, pool.slot().await);
+
+    // A burned ProgramData anywhere but the loader derivation is refused
+    let impostor = Pubkey::new_unique();
+    pool.set_account(impostor, program_data_account(1, None));
+    let err = pool.send_result(attest_finality_ix(&pool, impostor)).await.unwrap_err();
+    assert_eq!(error_code(err), anchor_lang::error::ErrorCode::ConstraintSeeds as u32);
+}
+
+fn transfer_leg(
+    merkle_root: [u8; 32],
+    input_nullifier_hashes: [[u8; 32]; 2],
+    output_commitments: [[u8; 32]; 2],
+) -> TransferLeg {
+    TransferLeg {
+        proof_a: test_proof(&[
+            merkle_root,
+            input_nullifier_hashes[0],
+            input_nullifier_hashes[1],
+            output_commitments[0],
+            output_commitments[1],
+            field_u64(0),
+            field_u64(0),
+        ]),
+        proof_b: [0u8; 128],
+        proof_c: [0u8; 64],
+        input_nullifier_hashes,
+        output_commitments,
+        unlock_slots: [0; 2],
+    }
+}
+
+fn swap_ix(
+    pool: &TestPool,
+    intent: &SwapIntent,
+    maker_leg: &TransferLeg,
+    taker_leg: &TransferLeg,
+    merkle_root: [u8; 32],
+) -> Instruction {
+    pool.ix(
+        accounts::DenominationSwap {
+            transfer: pool.transfer_accounts(&maker_leg.input_nullifier_hashes, &maker_leg.output_commitments),
+            taker_marker_0: nullifier_marker(&taker_leg.input_nullifier_hashes[0]),
+            taker_marker_1: nullifier_marker(&taker_leg.input_nullifier_hashes[1]),
+            taker_output_marker_0: commitment_marker(&taker_leg.output_commitments[0]),
+            taker_output_marker_1: commitment_marker(&taker_leg.output_commitments[1]),
+        },
+        instruction::ExecuteDenominationSwap {
+            intent: intent.clone(),
+            maker_leg: maker_leg.clone(),
+            taker_leg: taker_leg.clone(),
+            merkle_root,
+        },
+    )
+}
+
+#[tokio::test]
+async fn denomination_swap_executes_both_legs_or_neither() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"maker note"), 10 * whistle_pool::DENOM_1_SOL).await.unwrap();
+    pool.shield(field(b"taker note"), SHIELD_AMOUNT).await.unwrap();
+    let before = pool.pool_state().await;
+    let merkle_root = before.current_root;
+
+    // The maker cuts a 0.1 SOL note for the taker out of its 10 SOL note
+    // and receives a 0.05 SOL note cut from the taker's
+    let intent = SwapIntent {
+        from_commitment: field(b"for taker"),
+        from_denomination: whistle_pool::DENOM_01_SOL,
+        to_denomination: whistle_pool::DENOM_005_SOL,
+        to_commitment: field(b"for maker"),
+    };
+    let (maker_nullifier, taker_nullifier) = (field(b"maker nullifier"), field(b"taker nullifier"));
+    let maker_outputs = [intent.from_commitment, field(b"maker change")];
+    let taker_outputs = [intent.to_commitment, field(b"taker change")];
+    let maker_leg = transfer_leg(merkle_root, [maker_nullifier, [0u8; 32]], maker_outputs);
+    let taker_leg = transfer_leg(merkle_root, [taker_nullifier, [0u8; 32]], taker_outputs);
+
+    // Intents must name two different real denominations
+    let same = SwapIntent { to_denomination: intent.from_denomination, ..intent.clone() };
+    let unknown = SwapIntent { to_denomination: 12_345, ..intent.clone() };
+    for bad in [same, unknown] {
+        let err = pool.send_result(swap_ix(&pool, &bad, &maker_leg, &taker_leg, merkle_root)).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(WhistleError::InvalidSwapIntent));
+    }
+
+    // Each leg must create the commitment the intent promises its counterparty
+    let err = pool.send_result(swap_ix(&pool, &intent, &taker_leg, &maker_leg, merkle_root)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::SwapLegMismatch));
+
+    // A bad taker proof fails the swap after the maker's leg ran: nothing lands
+    let mut forged = taker_leg.clone();
+    forged.proof_a[0] ^= 1;
+    let err = pool.send_result(swap_ix(&pool, &intent, &maker_leg, &forged, merkle_root)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidProof));
+    assert_eq!(pool.pool_state().await.next_index, before.next_index);
+    assert!(!is_spent(&mut pool, maker_nullifier).await);
+
+    pool.send(swap_ix(&pool, &intent, &maker_leg, &taker_leg, merkle_root)).await.unwrap();
+    assert!(is_spent(&mut pool, maker_nullifier).await);
+    assert!(is_spent(&mut pool, taker_nullifier).await);
+
+    // Maker outputs first, then the taker's
+    let state = pool.pool_state().await;
+    assert_eq!(state.next_index, before.next_index + 4);
+    let tree_account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let tree = whistle_pool::MerkleTree::try_load_versioned(&tree_account.data).unwrap();
+    let first = (1usize << MERKLE_LEVELS) - 1 + before.next_index as usize;
+    assert_eq!(tree.nodes[first..first + 2], maker_outputs);
+    assert_eq!(tree.nodes[first + 2..first + 4], taker_outputs);
+
+    // Replaying the swap double-spends both notes
+    let err = pool.send_result(swap_ix(&pool, &intent, &maker_leg, &taker_leg, merkle_root)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+}
+
+fn tree_dispute(challenger: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"tree_dispute", challenger.as_ref()], &whistle_pool::ID).0
+}
+
+fn challenge_ix(pool: &TestPool, claimed_correct_root: [u8; 32], disputed_range_start: u64, dispute_bond: u64) -> Instruction {
+    let challenger = pool.payer.pubkey();
+    pool.ix(
+        accounts::ChallengeTreeRoot {
+            pool: pda(b"pool"),
+            tree_dispute: tree_dispute(&challenger),
+            challenger,
+            system_program: system_program::ID,
+        },
+        instruction::ChallengeTreeRoot { claimed_correct_root, disputed_range_start, dispute_bond },
+    )
+}
+
+fn resolve_ix(pool: &TestPool, responder: &Pubkey) -> Instruction {
+    let challenger = pool.payer.pubkey();
+    pool.ix(
+        accounts::ResolveDispute {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            tree_dispute: tree_dispute(&challenger),
+            challenger,
+            responder: *responder,
+        },
+        instruction::ResolveDispute {},
+    )
+}
+
+fn expire_ix(pool: &TestPool) -> Instruction {
+    let challenger = pool.payer.pubkey();
+    pool.ix(
+        accounts::ExpireTreeDispute { tree_dispute: tree_dispute(&challenger), challenger },
+        instruction::ExpireTreeDispute {},
+    )
+}
+
+#[tokio::test]
+async fn tree_disputes_are_defended_or_upheld() {
+    // Two leaf ranges, so the defence climbs through a stored sibling
+    const LEVELS: u8 = 9;
+    let mut pool = TestPool::start(LEVELS).await;
+    for i in 0..2u8 {
+        pool.shield(field(&[b"commitment".as_slice(), &[i]].concat()), SHIELD_AMOUNT).await.unwrap();
+    }
+    let published = pool.current_root().await;
+    let claimed = field(b"claimed root");
+
+    let rejected = [
+        (challenge_ix(&pool, claimed, 0, MIN_DISPUTE_BOND - 1), WhistleError::DisputeBondTooLow),
+        (challenge_ix(&pool, claimed, 1, MIN_DISPUTE_BOND), WhistleError::InvalidDisputeRange),
+        (challenge_ix(&pool, claimed, DISPUTE_RANGE_LEAVES, MIN_DISPUTE_BOND), WhistleError::InvalidDisputeRange),
+        (challenge_ix(&pool, published, 0, MIN_DISPUTE_BOND), WhistleError::DisputeClaimsPublishedRoot),
+    ];
+    for (ix, expected) in rejected {
+        let err = pool.send_result(ix).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(expected));
+    }
+
+    // A consistent tree reproduces the disputed root: the responder takes the bond
+    pool.send(challenge_ix(&pool, claimed, 0, MIN_DISPUTE_BOND)).await.unwrap();
+    let dispute_account = pool.banks.get_account(tree_dispute(&pool.payer.pubkey())).await.unwrap().unwrap();
+    let dispute = TreeDispute::try_deserialize(&mut dispute_account.data.as_slice()).unwrap();
+    assert_eq!((dispute.disputed_root, dispute.claimed_root), (published, claimed));
+    let err = pool.send_result(expire_ix(&pool)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DisputeResponseOpen));
+
+    let responder = Keypair::new();
+    pool.send_signed(resolve_ix(&pool, &responder.pubkey()), &[&responder]).await.unwrap();
+    assert_eq!(pool.balance(responder.pubkey()).await, MIN_DISPUTE_BOND);
+    assert!(pool.banks.get_account(tree_dispute(&pool.payer.pubkey())).await.unwrap().is_none());
+
+    // Overwrite the second leaf without touching its path: the root still
+    // matches the pool, but the range no longer hashes to it
+    let mut tree = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let leaf = 8 + whistle_pool::MERKLE_TREE_HEADER_SIZE + ((1usize << LEVELS) - 1 + 1) * 32;
+    tree.data[leaf..leaf + 32].copy_from_slice(&field(b"corrupted"));
+    pool.set_account(pda(b"merkle_tree"), tree);
+
+    pool.send(challenge_ix(&pool, claimed, 0, MIN_DISPUTE_BOND)).await.unwrap();
+    let err = pool.send_signed(resolve_ix(&pool, &responder.pubkey()), &[&responder]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DisputedRangeMismatch));
+
+    // Undefended past the window, the bond and rent return to the challenger
+    pool.warp_slots(DISPUTE_RESPONSE_SLOTS + 1).await;
+    let err = pool.send_signed(resolve_ix(&pool, &responder.pubkey()), &[&responder]).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DisputeResponseClosed));
+    let before = pool.balance(pool.payer.pubkey()).await;
+    pool.send(expire_ix(&pool)).await.unwrap();
+    let rent = Rent::default().minimum_balance(TreeDispute::SIZE);
+    assert_eq!(pool.balance(pool.payer.pubkey()).await, before + MIN_DISPUTE_BOND + rent - 5_000);
+}
+
+fn batch_withdraw_ix(
+    pool: &TestPool,
+    merkle_root: [u8; 32],
+    nullifiers: [[u8; 32]; 4],
+    recipient: Pubkey,
+    relayer: Pubkey,
+    relayer_fee: u64,
+) -> Instruction {
+    let amounts = [WITHDRAW_AMOUNT; 4];
+    let mut public_inputs = vec![merkle_root];
+    public_inputs.extend_from_slice(&nullifiers);
+    public_inputs.extend(amounts.iter().map(|amount| field_u64(*amount)));
+    public_inputs.push(recipient_field(&recipient));
+    public_inputs.push(field_u64(relayer_fee));
+    pool.ix(
+        accounts::BatchWithdrawZk {
+            unshield: pool.unshield_accounts(&nullifiers[0], &[0u8; 32], recipient, relayer),
+            nullifier_marker_1: nullifier_marker(&nullifiers[1]),
+            nullifier_marker_2: nullifier_marker(&nullifiers[2]),
+            nullifier_marker_3: nullifier_marker(&nullifiers[3]),
+        },
+        instruction::BatchWithdrawZk {
+            proof_a: test_proof(&public_inputs),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifiers,
+            amounts,
+            recipient,
+            relayer_fee,
+            merkle_root,
+        },
+    )
+}
+
+#[tokio::test]
+async fn batch_withdraw_spends_four_notes_with_one_proof() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    for i in 0..4u8 {
+        pool.shield(field(&[b'c', i]), SHIELD_AMOUNT).await.unwrap();
+    }
+    let merkle_root = pool.current_root().await;
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let total = 4 * WITHDRAW_AMOUNT;
+
+    // The fee cap is the sum of the four notes' caps
+    let max_fee = 4 * WITHDRAW_AMOUNT * u64::from(DEFAULT_RELAYER_FEE_CAPS_BPS[1]) / BPS_DENOMINATOR;
+    let nullifiers = [field(b"n0"), field(b"n1"), field(b"n2"), field(b"n3")];
+    let err = pool
+        .send_result(batch_withdraw_ix(&pool, merkle_root, nullifiers, recipient, relayer, max_fee + 1))
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::FeeTooHigh));
+
+    // The same note cannot fill two slots of a batch
+    let repeated = [nullifiers[0], nullifiers[1], nullifiers[2], nullifiers[0]];
+    let err = pool
+        .send_result(batch_withdraw_ix(&pool, merkle_root, repeated, recipient, relayer, 0))
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+
+    let shielded_before = pool.pool_state().await.total_shielded;
+    pool.send(batch_withdraw_ix(&pool, merkle_root, nullifiers, recipient, relayer, max_fee)).await.unwrap();
+
+    let protocol_fee = total * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    assert_eq!(pool.balance(recipient).await, total - max_fee - protocol_fee);
+    assert_eq!(pool.balance(relayer).await, max_fee);
+    assert_eq!(pool.pool_state().await.total_shielded, shielded_before - total);
+    for nullifier_hash in nullifiers {
+        assert!(is_spent(&mut pool, nullifier_hash).await);
+    }
+
+    let err = pool
+        .send_result(batch_withdraw_ix(&pool, merkle_root, nullifiers, recipient, relayer, max_fee))
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+}
+
+/// One batch_unshield note withdrawing WITHDRAW_AMOUNT to `recipient`
+fn batch_unshield_note(
+    merkle_root: [u8; 32],
+    nullifier_hash: [u8; 32],
+    change_commitment: [u8; 32],
+    recipient: &Pubkey,
+    relayer_fee: u64,
+) -> UnshieldParams {
+    UnshieldParams {
+        proof_a: test_proof(&[
+            merkle_root,
+            nullifier_hash,
+            recipient_field(recipient),
+            field_u64(WITHDRAW_AMOUNT),
+            field_u64(relayer_fee),
+            change_commitment,
+            field_u64(0),
+        ]),
+        proof_b: [0u8; 128],
+        proof_c: [0u8; 64],
+        nullifier_hash,
+        withdrawal_amount: WITHDRAW_AMOUNT,
+        relayer_fee,
+        change_commitment,
+    }
+}
+
+/// batch_unshield of `notes`: the first note's markers go in the Unshield
+/// accounts, the others' nullifier markers then change markers follow as
+/// remaining accounts
+fn batch_unshield_ix(
+    pool: &TestPool,
+    notes: &[UnshieldParams],
+    recipient: Pubkey,
+    relayer: Pubkey,
+    merkle_root: [u8; 32],
+) -> Instruction {
+    let (first_nullifier, first_change) =
+        notes.first().map_or(([0u8; 32], [0u8; 32]), |n| (n.nullifier_hash, n.change_commitment));
+    let mut ix = pool.ix(
+        pool.unshield_accounts(&first_nullifier, &first_change, recipient, relayer),
+        instruction::BatchUnshield { proofs: notes.to_vec(), merkle_root },
+    );
+    let rest = notes.iter().skip(1);
+    ix.accounts.extend(rest.clone().map(|n| AccountMeta::new(nullifier_marker(&n.nullifier_hash), false)));
+    ix.accounts.extend(rest.map(|n| AccountMeta::new(commitment_marker(&n.change_commitment), false)));
+    ix
+}
+
+#[tokio::test]
+async fn batch_unshield_withdraws_every_note_or_none() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    pool.shield(field(b"c0"), SHIELD_AMOUNT).await.unwrap();
+    pool.shield(field(b"c1"), SHIELD_AMOUNT).await.unwrap();
+    let merkle_root = pool.current_root().await;
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let fee = WITHDRAW_AMOUNT * u64::from(DEFAULT_RELAYER_FEE_CAPS_BPS[1]) / BPS_DENOMINATOR;
+    let (n0, n1, change) = (field(b"n0"), field(b"n1"), field(b"change"));
+    let with_change = batch_unshield_note(merkle_root, n0, change, &recipient, fee);
+    let without_change = batch_unshield_note(merkle_root, n1, [0u8; 32], &recipient, fee);
+
+    let err = pool.send_result(batch_unshield_ix(&pool, &[], recipient, relayer, merkle_root)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidBatchSize));
+
+    // The same note cannot fill two slots of a batch
+    let repeated = [with_change.clone(), with_change.clone()];
+    let err = pool.send_result(batch_unshield_ix(&pool, &repeated, recipient, relayer, merkle_root)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+
+    // One bad proof rejects the batch before any note is spent
+    let forged = UnshieldParams { proof_a: [1u8; 64], ..without_change.clone() };
+    let batch = [with_change.clone(), forged];
+    let err = pool.send_result(batch_unshield_ix(&pool, &batch, recipient, relayer, merkle_root)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidProof));
+    assert!(!is_spent(&mut pool, n0).await);
+
+    let before = pool.pool_state().await;
+    let batch = [with_change, without_change];
+    pool.send(batch_unshield_ix(&pool, &batch, recipient, relayer, merkle_root)).await.unwrap();
+
+    // One payout each to the recipient and relayer, fees charged per note
+    let protocol_fee = WITHDRAW_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    assert_eq!(pool.balance(recipient).await, 2 * (WITHDRAW_AMOUNT - fee - protocol_fee));
+    assert_eq!(pool.balance(relayer).await, 2 * fee);
+    let after = pool.pool_state().await;
+    assert_eq!(after.total_shielded, before.total_shielded - 2 * WITHDRAW_AMOUNT);
+    assert!(is_spent(&mut pool, n0).await);
+    assert!(is_spent(&mut pool, n1).await);
+
+    // Only the first note had change, inserted at the next leaf
+    assert_eq!(after.next_index, 3);
+    let account = pool.banks.get_account(commitment_marker(&change)).await.unwrap().unwrap();
+    let marker = CommitmentMarker::try_deserialize(&mut &account.data[..]).unwrap();
+    assert_eq!(marker.leaf_index, 2);
+}
+
+fn shield_batch_ix(
+    pool: &TestPool,
+    next_index: u64,
+    commitments: [[u8; 32]; 8],
+    total_amount: u64,
+    proven_total: u64,
+) -> Instruction {
+    let mut public_inputs = commitments.to_vec();
+    public_inputs.push(field_u64(proven_total));
+    pool.batch_shield_ix(
+        next_index,
+        &commitments,
+        instruction::ShieldBatchZk {
+            proof_a: test_proof(&public_inputs),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            commitments,
+            total_amount,
+        },
+    )
+}
+
+#[tokio::test]
+async fn shield_batch_inserts_eight_notes_with_one_proof() {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let first = field(b"single");
+    pool.shield(first, SHIELD_AMOUNT).await.unwrap();
+    let commitments: [[u8; 32]; 8] = std::array::from_fn(|i| field(&[b'b', i as u8]));
+    let total = 8 * SHIELD_AMOUNT;
+
+    // The proof fixes the total the notes add up to
+    let err = pool.send_result(shield_batch_ix(&pool, 1, commitments, total + 1, total)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidProof));
+
+    let before = pool.pool_state().await;
+    let fee_vault = pool.balance(pda(b"fee_vault")).await;
+    pool.send(shield_batch_ix(&pool, 1, commitments, total, total)).await.unwrap();
+
+    let protocol_fee = total * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    let after = pool.pool_state().await;
+    assert_eq!(after.next_index, 9);
+    assert_eq!(after.total_shielded, before.total_shielded + total - protocol_fee);
+    assert_eq!(pool.balance(pda(b"fee_vault")).await, fee_vault + protocol_fee);
+    assert_ne!(after.current_root, before.current_root);
+
+    // Every leaf lands in order, and the leaf page keeps up
+    let mut leaves = vec![first];
+    leaves.extend(commitments);
+    assert_eq!(read_leaves(&mut pool, 0, MAX_LEAF_READ).await, leaves);
+
+    // A spend can prove against the batch's root
+    let nullifier_hash = field(b"batch nullifier");
+    let recipient = Keypair::new().pubkey();
+    let unshield = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &[0u8; 32], recipient, recipient),
+        instruction::Unshield {
+            proof_a: test_proof(&[
+                after.current_root,
+                nullifier_hash,
+                recipient_field(&recipient),
+                field_u64(WITHDRAW_AMOUNT),
+                field_u64(0),
+                [0u8; 32],
+                field_u64(0),
+            ]),
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount: WITHDRAW_AMOUNT,
+            relayer_fee: 0,
+            merkle_root: after.current_root,
+            change_commitment: [0u8; 32],
+            unlock_slot: 0,
+        },
+    );
+    pool.send(unshield).await.unwrap();
+}
+
+#[tokio::test]
+async fn batch_shield_inserts_public_amount_notes() {
+    use anchor_lang::Discriminator;
+
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let first = field(b"single");
+    pool.shield(first, SHIELD_AMOUNT).await.unwrap();
+    let batch_shield = |pool: &TestPool, commitments: Vec<[u8; 32]>, amounts: Vec<u64>| {
+        pool.batch_shield_ix(1, &commitments.clone(), instruction::BatchShield { commitments, amounts })
+    };
+
+    let commitments: Vec<[u8; 32]> = (0..3u8).map(|i| field(&[b'p', i])).collect();
+    let amounts = vec![SHIELD_AMOUNT, 2 * SHIELD_AMOUNT, 3 * SHIELD_AMOUNT];
+    let too_many = vec![field(b"many"); MAX_BATCH_SHIELD + 1];
+    let rejected = [
+        (vec![], vec![], WhistleError::InvalidBatchSize),
+        (commitments.clone(), amounts[..2].to_vec(), WhistleError::InvalidBatchSize),
+        (too_many, vec![SHIELD_AMOUNT; MAX_BATCH_SHIELD + 1], WhistleError::InvalidBatchSize),
+        (commitments.clone(), vec![SHIELD_AMOUNT, MIN_DEPOSIT - 1, SHIELD_AMOUNT], WhistleError::AmountTooSmall),
+        (vec![commitments[0], [0u8; 32]], amounts[..2].to_vec(), WhistleError::ZeroCommitment),
+        (vec![commitments[0], commitments[0]], amounts[..2].to_vec(), WhistleError::DuplicateCommitment),
+        (vec![commitments[0], first], amounts[..2].to_vec(), WhistleError::DuplicateCommitment),
+    ];
+    for (commitments, amounts, error) in rejected {
+        let err = pool.send_result(batch_shield(&pool, commitments, amounts)).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(error));
+    }
+
+    let before = pool.pool_state().await;
+    let fee_vault = pool.balance(pda(b"fee_vault")).await;
+    let sent = pool.send_with_metadata(batch_shield(&pool, commitments.clone(), amounts.clone())).await;
+    sent.result.unwrap();
+
+    // Each note pays the fee a shield of its amount would
+    let fees: u64 = amounts.iter().map(|amount| amount * PROTOCOL_FEE_BPS / BPS_DENOMINATOR).sum();
+    let total: u64 = amounts.iter().sum();
+    let after = pool.pool_state().await;
+    assert_eq!(after.next_index, 4);
+    assert_eq!(after.total_shielded, before.total_shielded + total - fees);
+    assert_eq!(pool.balance(pda(b"fee_vault")).await, fee_vault + fees);
+
+    let mut leaves = vec![first];
+    leaves.extend(&commitments);
+    assert_eq!(read_leaves(&mut pool, 0, MAX_LEAF_READ).await, leaves);
+
+    // The root after each leaf stays provable
+    let history = roots_history(&mut pool).await;
+    for count in 2..=leaves.len() {
+        assert!(history.contains(&padded_tree_root(&leaves[..count], MERKLE_LEVELS)), "root after {count} leaves");
+    }
+
+    // One BatchShielded for the whole batch, no NoteCreated per leaf
+    let events = emitted_events(&sent.metadata.unwrap().log_messages);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0][..8], whistle_pool::BatchShielded::DISCRIMINATOR);
+    let summary = whistle_pool::BatchShielded::try_from_slice(&events[0][8..]).unwrap();
+    assert_eq!(
+        (summary.count, summary.total_amount, summary.first_leaf_index, summary.last_leaf_index),
+        (3, total - fees, 1, 3)
+    );
+}
+
+#[tokio::test]
+async fn capacity_warnings_fire_once_per_threshold() {
+    use anchor_lang::Discriminator;
+
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let capacity = 1u64 << MERKLE_LEVELS;
+
+    // Fill 112 of the 128 leaves eight at a time, below the 90% mark
+    for batch in 0..14u8 {
+        let commitments: [[u8; 32]; 8] = std::array::from_fn(|i| field(&[b'f', batch, i as u8]));
+        let ix = shield_batch_ix(&pool, 8 * batch as u64, commitments, SHIELD_AMOUNT, SHIELD_AMOUNT);
+        let sent = pool.send_with_metadata(ix).await;
+        sent.result.unwrap();
+        let events = emitted_events(&sent.metadata.unwrap().log_messages);
+        assert!(events.iter().all(|event| event[..8] != CapacityWarning::DISCRIMINATOR));
+    }
+
+    // Then one leaf per shield until the tree is full
+    let mut warnings = vec![];
+    for leaf_index in 112..capacity {
+        let ix = pool.ix(
+            pool.shield_accounts(leaf_index, &field(&leaf_index.to_le_bytes())),
+            instruction::Shield { commitment: field(&leaf_index.to_le_bytes()), amount: SHIELD_AMOUNT },
+        );
+        let sent = pool.send_with_metadata(ix).await;
+        sent.result.unwrap();
+        for event in emitted_events(&sent.metadata.unwrap().log_messages) {
+            if event[..8] == CapacityWarning::DISCRIMINATOR {
+                let warning = CapacityWarning::try_from_slice(&event[8..]).unwrap();
+                assert_eq!((warning.next_index, warning.capacity), (leaf_index + 1, capacity));
+                warnings.push((leaf_index, warning.threshold_bps, warning.remaining));
+            }
+        }
+    }
+
+    // 90% of 128 leaves is reached at next_index 116 (115.2 rounded up),
+    // 99% at 127
+    assert_eq!(warnings, vec![(115, 9_000, 12), (126, 9_900, 1)]);
+    let ix = pool.ix(pool.shield_accounts(capacity, &field(b"overflow")), instruction::Shield { commitment: field(b"overflow"), amount: SHIELD_AMOUNT });
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::TreeFull));
+}
+
+#[tokio::test]
+async fn full_pools_migrate_to_a_deeper_tree() {
+    const NEW_LEVELS: u8 = 9;
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let begin = |pool: &TestPool, new_levels: u8| {
+        pool.ix(
+            accounts::BeginMigration {
+                pool: pda(b"pool"),
+                merkle_tree: pda(b"merkle_tree"),
+                migration: pda(b"migration"),
+                payer: pool.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::BeginPoolMigration { new_levels },
+        )
+    };
+    let advance = |pool: &TestPool, batch_size: u32| {
+        pool.ix(
+            accounts::ContinueMigration { pool: pda(b"pool"), merkle_tree: pda(b"merkle_tree"), migration: pda(b"migration") },
+            instruction::ContinueMigration { batch_size },
+        )
+    };
+    let finalize = |pool: &TestPool| {
+        pool.ix(
+            accounts::FinalizeMigration {
+                pool: pda(b"pool"),
+                merkle_tree: pda(b"merkle_tree"),
+                roots_history: pda(b"roots_history"),
+                migration: pda(b"migration"),
+            },
+            instruction::FinalizeMigration {},
+        )
+    };
+
+    let mut leaves = Vec::new();
+    for batch in 0..16u8 {
+        if batch == 1 {
+            let err = pool.send_result(begin(&pool, NEW_LEVELS)).await.unwrap_err();
+            assert_eq!(error_code(err), u32::from(WhistleError::TreeNotFull));
+        }
+        let commitments: [[u8; 32]; 8] = std::array::from_fn(|i| field(&[b'm', batch, i as u8]));
+        pool.send(shield_batch_ix(&pool, 8 * batch as u64, commitments, SHIELD_AMOUNT, SHIELD_AMOUNT)).await.unwrap();
+        leaves.extend(commitments);
+    }
+    for new_levels in [MERKLE_LEVELS, 14] {
+        let err = pool.send_result(begin(&pool, new_levels)).await.unwrap_err();
+        assert_eq!(error_code(err), u32::from(WhistleError::InvalidMerkleLevels));
+    }
+    let old_root = pool.current_root().await;
+    pool.send(begin(&pool, NEW_LEVELS)).await.unwrap();
+
+    // Nothing inserts while the nodes are moving (the pool is still full at
+    // its old depth), and the tree is not yet ready to publish
+    let shield = pool.ix(pool.shield_accounts(128, &field(b"during")), instruction::Shield { commitment: field(b"during"), amount: SHIELD_AMOUNT });
+    let err = pool.send_result(shield).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::TreeFull));
+    let err = pool.send_result(finalize(&pool)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::MigrationIncomplete));
+    let err = pool.send_result(advance(&pool, u32::from(whistle_pool::MAX_REBUILD_NODES) + 1)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidMigrationBatch));
+
+    // One call copies the 128 leaves, four rebuild the 511 internal nodes
+    for _ in 0..5 {
+        pool.send(advance(&pool, u32::from(whistle_pool::MAX_REBUILD_NODES))).await.unwrap();
+    }
+    let sent = pool.send_with_metadata(finalize(&pool)).await;
+    sent.result.unwrap();
+    let events = emitted_events(&sent.metadata.unwrap().log_messages);
+    let event = whistle_pool::PoolMigrated::try_from_slice(&events[0][8..]).unwrap();
+
+    let root = padded_tree_root(&leaves, NEW_LEVELS);
+    assert_ne!(root, old_root);
+    assert_eq!((event.old_levels, event.new_levels, event.leaf_count, event.root), (MERKLE_LEVELS, NEW_LEVELS, 128, root));
+    let state = pool.pool_state().await;
+    assert_eq!((state.merkle_levels, state.next_index, state.current_root), (NEW_LEVELS, 128, root));
+    assert_eq!(roots_history(&mut pool).await.latest(), root);
+    let tree_account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+    let tree = whistle_pool::MerkleTree::try_load_versioned(&tree_account.data).unwrap();
+    let leaf_offset = (1usize << NEW_LEVELS) - 1;
+    assert_eq!(tree.levels_used, NEW_LEVELS);
+    assert_eq!(tree.nodes[leaf_offset..leaf_offset + 128], leaves);
+
+    // Shields resume in the deeper tree; the finished migration takes no
+    // more steps
+    pool.shield(field(b"after"), SHIELD_AMOUNT).await.unwrap();
+    leaves.push(field(b"after"));
+    assert_eq!(pool.current_root().await, padded_tree_root(&leaves, NEW_LEVELS));
+    let err = pool.send_result(advance(&pool, 1)).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NoMigrationInProgress));
+}
+
+async fn token_balance(pool: &mut TestPool, account: Pubkey) -> u64 {
+    let account = pool.banks.get_account(account).await.unwrap().unwrap();
+    spl_token::state::Account::unpack(&account.data).unwrap().amount
+}
+
+/// unshield_token from `mint`'s vault, proven for `proven_mint`
+#[allow(clippy::too_many_arguments)]
+fn unshield_token_ix(
+    pool: &TestPool,
+    next_index: u64,
+    mint: Pubkey,
+    proven_mint: Pubkey,
+    recipient: Pubkey,
+    recipient_token_account: Pubkey,
+    nullifier_hash: [u8; 32],
+    amount: u64,
+    merkle_root: [u8; 32],
+    change_commitment: [u8; 32],
+) -> Instruction {
+    let proof_a = test_proof(&[
+        merkle_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(amount),
+        recipient_field(&proven_mint),
+        change_commitment,
+    ]);
+    pool.ix(
+        accounts::UnshieldToken {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            nullifiers: pda(b"nullifiers"),
+            roots_history: pda(b"roots_history"),
+            mint,
+            token_vault: token_vault(&mint),
+            recipient_token_account,
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+            nullifier_marker: nullifier_marker(&nullifier_hash),
+            payer: pool.payer.pubkey(),
+            leaf_page: leaf_page(whistle_pool::MerkleTreeLeafPage::page_of(next_index)),
+            change_marker: commitment_marker(&change_commitment),
+        },
+        instruction::UnshieldToken {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            amount,
+            merkle_root,
+            change_commitment,
+        },
+    )
+}
+
+#[tokio::test]
+async fn token_notes_shield_and_unshield_per_mint() {
+    const UNIT: u64 = 1_000_000; // 6 decimals
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let payer = pool.payer.pubkey();
+    let usdc = create_mint(&mut pool, 6).await;
+    let other = create_mint(&mut pool, 6).await;
+    let usdc_source = create_token_account(&mut pool, usdc, payer, 10 * UNIT).await;
+    let other_source = create_token_account(&mut pool, other, payer, 10 * UNIT).await;
+
+    // The source account must hold the mint being shielded
+    let ix = shield_token_ix(&pool, 0, usdc, other_source, field(b"usdc note"), 5 * UNIT);
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::MintMismatch));
+
+    // The first shield of each mint creates its vault
+    pool.send(shield_token_ix(&pool, 0, usdc, usdc_source, field(b"usdc note"), 5 * UNIT)).await.unwrap();
+    pool.send(shield_token_ix(&pool, 1, other, other_source, field(b"other note"), 5 * UNIT)).await.unwrap();
+    assert_eq!(token_balance(&mut pool, token_vault(&usdc)).await, 5 * UNIT);
+    assert_eq!(token_balance(&mut pool, token_vault(&other)).await, 5 * UNIT);
+    assert_eq!(token_balance(&mut pool, usdc_source).await, 5 * UNIT);
+    let state = pool.pool_state().await;
+    assert_eq!(state.next_index, 2);
+    assert_eq!(state.total_shielded, 0);
+
+    let recipient = Keypair::new().pubkey();
+    let usdc_destination = create_token_account(&mut pool, usdc, recipient, 0).await;
+    let other_destination = create_token_account(&mut pool, other, recipient, 0).await;
+    let nullifier_hash = field(b"usdc nullifier");
+    let change = field(b"usdc change");
+    let root = pool.current_root().await;
+
+    // A proof for a USDC note cannot drain the other mint's vault
+    let ix = unshield_token_ix(&pool, 2, other, usdc, recipient, other_destination, nullifier_hash, UNIT, root, change);
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidProof));
+
+    // The recipient's token account must hold the note's mint
+    let ix = unshield_token_ix(&pool, 2, usdc, usdc, recipient, other_destination, nullifier_hash, UNIT, root, change);
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::MintMismatch));
+
+    // Withdrawals are whole token units
+    let ix = unshield_token_ix(&pool, 2, usdc, usdc, recipient, usdc_destination, nullifier_hash, UNIT / 2, root, change);
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::InvalidSplDenomination));
+
+    let ix = unshield_token_ix(&pool, 2, usdc, usdc, recipient, usdc_destination, nullifier_hash, UNIT, root, change);
+    pool.send(ix).await.unwrap();
+    assert_eq!(token_balance(&mut pool, usdc_destination).await, UNIT);
+    assert_eq!(token_balance(&mut pool, token_vault(&usdc)).await, 4 * UNIT);
+    assert_eq!(token_balance(&mut pool, token_vault(&other)).await, 5 * UNIT);
+    assert_eq!(pool.pool_state().await.next_index, 3);
+    assert!(pool.banks.get_account(nullifier_marker(&nullifier_hash)).await.unwrap().is_some());
+
+    // Token shields and change notes take their markers and leaf page slots
+    // like SOL notes, so a token commitment cannot be inserted twice
+    assert!(pool.banks.get_account(commitment_marker(&change)).await.unwrap().is_some());
+    assert_eq!(read_leaves(&mut pool, 0, MAX_LEAF_READ).await, vec![field(b"usdc note"), field(b"other note"), change]);
+    let ix = shield_token_ix(&pool, 3, usdc, usdc_source, field(b"usdc note"), UNIT);
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::DuplicateCommitment));
+
+    let ix = unshield_token_ix(&pool, 2, usdc, usdc, recipient, usdc_destination, nullifier_hash, UNIT, root, [0u8; 32]);
+    let err = pool.send_result(ix).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::NullifierAlreadyUsed));
+}
+
+/// A program-owned tree at a valid but non-canonical bump of the tree seed
+fn imposter_tree() -> Pubkey {
+    let canonical = Pubkey::find_program_address(&[b"merkle_tree"], &whistle_pool::ID).1;
+    (0..canonical)
+        .rev()
+        .find_map(|bump| Pubkey::create_program_address(&[b"merkle_tree", &[bump]], &whistle_pool::ID).ok())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn pda_bumps_are_stored_and_imposters_rejected() {
+    let imposter = imposter_tree();
+    let genesis = vec![(imposter, zero_copy_account::<whistle_pool::MerkleTree>(&[]))];
+    let mut pool = TestPool::start_with_accounts(MERKLE_LEVELS, genesis).await;
+
+    let state = pool.pool_state().await;
+    let canonical = |seed: &[u8]| Pubkey::find_program_address(&[seed], &whistle_pool::ID).1;
+    assert_eq!(state.vault_bump, canonical(b"vault"));
+    assert_eq!(state.merkle_tree_bump, canonical(b"merkle_tree"));
+    assert_eq!(state.roots_history_bump, canonical(b"roots_history"));
+    assert_eq!(state.nullifiers_bump, canonical(b"nullifiers"));
+
+    // The stored bump pins the canonical address
+    let merkle_root = pool.current_root().await;
+    let mut transfer = private_transfer(&pool, merkle_root, [field(b"imposter"), [0u8; 32]], [field(b"output"), [0u8; 32]]);
+    transfer.accounts[1].pubkey = imposter;
+    let err = pool.send_result(transfer).await.unwrap_err();
+    assert_eq!(error_code(err), anchor_lang::error::ErrorCode::ConstraintSeeds as u32);
+
+    // A pool from before the bumps were stored reads zeroes and is unusable
+    // until migrated, once
+    let mut account = pool.banks.get_account(pda(b"pool")).await.unwrap().unwrap();
+    let bumps = account.data.len() - 4;
+    account.data[bumps..].fill(0);
+    pool.set_account(pda(b"pool"), account);
+    let err = pool.send_result(pool.ix(pool.shield_accounts(0, &field(b"before migration")), instruction::Shield {
+        commitment: field(b"before migration"),
+        amount: SHIELD_AMOUNT,
+    })).await.unwrap_err();
+    assert_eq!(error_code(err), anchor_lang::error::ErrorCode::ConstraintSeeds as u32);
+
+    let migrate = pool.ix(accounts::MigratePoolBumps { pool: pda(b"pool") }, instruction::MigratePoolBumps {});
+    pool.send(migrate.clone()).await.unwrap();
+    let migrated = pool.pool_state().await;
+    assert_eq!(
+        [migrated.vault_bump, migrated.merkle_tree_bump, migrated.roots_history_bump, migrated.nullifiers_bump],
+        [state.vault_bump, state.merkle_tree_bump, state.roots_history_bump, state.nullifiers_bump]
+    );
+    pool.shield(field(b"after migration"), SHIELD_AMOUNT).await.unwrap();
+
+    pool.warp_slots(1).await;
+    let err = pool.send_result(migrate).await.unwrap_err();
+    assert_eq!(error_code(err), u32::from(WhistleError::PoolBumpsAlreadyStored));
+}
+
+/// Pins the tree event layouts decoded by the SDK (sdk/src/events.ts): slot
+/// then timestamp after each leaf event's other fields, and the timestamp
+/// alone after BatchShielded's
+#[test]
+fn tree_event_layouts_match_sdk_decoders() {
+    let shielded = whistle_pool::Shielded {
+        commitment: [1; 32],
+        leaf_index: 2,
+        amount: 3,
+        protocol_fee: 4,
+        slot: 5,
+        timestamp: -6,
+    };
+    let mut expected = vec![1u8; 32];
+    for value in [2u64, 3, 4, 5] {
+        expected.extend_from_slice(&value.to_le_bytes());
+    }
+    expected.extend_from_slice(&(-6i64).to_le_bytes());
+    assert_eq!(shielded.try_to_vec().unwrap(), expected);
+
+    let change = whistle_pool::ChangeCreated { commitment: [1; 32], leaf_index: 2, slot: 5, timestamp: -6 };
+    let note = whistle_pool::NoteCreated { commitment: [1; 32], leaf_index: 2, slot: 5, timestamp: -6 };
+    let mut expected = vec![1u8; 32];
+    for value in [2u64, 5] {
+        expected.extend_from_slice(&value.to_le_bytes());
+    }
+    expected.extend_from_slice(&(-6i64).to_le_bytes());
+    assert_eq!(change.try_to_vec().unwrap(), expected);
+    assert_eq!(note.try_to_vec().unwrap(), expected);
+
+    let token = whistle_pool::TokenShielded {
+        mint: Pubkey::new_from_array([7; 32]),
+        commitment: [1; 32],
+        leaf_index: 2,
+        amount: 3,
+        slot: 5,
+        timestamp: -6,
+    };
+    let mut expected = [[7u8; 32], [1; 32]].concat();
+    for value in [2u64, 3, 5] {
+        expected.extend_from_slice(&value.to_le_bytes());
+    }
+    expected.extend_from_slice(&(-6i64).to_le_bytes());
+    assert_eq!(token.try_to_vec().unwrap(), expected);
+
+    // decodeBatchShieldedEvent: no slot, just the timestamp
+    let batch = whistle_pool::BatchShielded {
+        count: 1,
+        total_amount: 2,
+        first_leaf_index: 3,
+        last_leaf_index: 4,
+        timestamp: -6,
+    };
+    let mut expected = vec![1u8];
+    for value in [2u64, 3, 4] {
+        expected.extend_from_slice(&value.to_le_bytes());
+    }
+    expected.extend_from_slice(&(-6i64).to_le_bytes());
+    assert_eq!(batch.try_to_vec().unwrap(), expected);
+}
+
+/// Root of `leaves` padded with zero leaves to 2^levels and hashed pairwise,
+/// as the circuits and buildMerkleProof build the tree
+fn padded_tree_root(leaves: &[[u8; 32]], levels: u8) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    level.resize(1 << levels, [0u8; 32]);
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| whistle_pool::poseidon_hash(&[&pair[0], &pair[1]])).collect();
+    }
+    level[0]
+}
+
+#[tokio::test]
+async fn pool_roots_match_a_padded_tree_at_every_depth() {
+    for levels in [7u8, 13] {
+        let mut pool = TestPool::start(levels).await;
+        assert_eq!(pool.current_root().await, padded_tree_root(&[], levels), "depth {levels}");
+
+        let mut leaves = Vec::new();
+        for i in 0..5u8 {
+            let commitment = field(&[b"commitment".as_slice(), &[i]].concat());
+            pool.shield(commitment, SHIELD_AMOUNT).await.unwrap();
+            leaves.push(commitment);
+            assert_eq!(pool.current_root().await, padded_tree_root(&leaves, levels), "depth {levels}, leaf {i}");
+        }
+
+        // The stored siblings of every leaf hash to the same root
+        let root = pool.current_root().await;
+        let tree_account = pool.banks.get_account(pda(b"merkle_tree")).await.unwrap().unwrap();
+        let tree = whistle_pool::MerkleTree::try_load_versioned(&tree_account.data).unwrap();
+        for (leaf_index, leaf) in leaves.iter().enumerate() {
+            let path = whistle_merkle::generate_merkle_path(tree.nodes, leaf_index as u64, levels);
+            assert_eq!(path.root, root);
+            assert_eq!(whistle_merkle::compute_merkle_root(leaf, &path.path_elements, &path.path_indices), root);
+        }
+    }
+}
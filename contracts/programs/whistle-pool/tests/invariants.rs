@@ -0,0 +1,220 @@
+//! Property test: random shield / unshield / private_transfer sequences
+//! must keep every pool invariant. assert_invariants is checked on-chain
+//! after each step, and proptest shrinks a failure to a minimal sequence.
+//!
+//! Proofs go through the test proof backend, so the generator keeps its own
+//! model of unspent notes and only issues operations an honest prover could.
+//!
+//! cargo test -p whistle-pool --features test-harness --test invariants
+
+mod common;
+
+use anchor_client::solana_sdk::signature::Signer;
+use anchor_lang::solana_program::keccak;
+use proptest::prelude::*;
+
+use common::{pda, recipient_field, TestPool};
+use whistle_pool::harness::{field_u64, test_proof};
+use whistle_pool::{DEFAULT_RELAYER_FEE_CAPS_BPS, DENOMINATIONS, MIN_DEPOSIT};
+
+const MERKLE_LEVELS: u8 = 7;
+const MAX_SHIELD: u64 = 2_000_000_000;
+
+/// Deterministic field element, so shrunk sequences replay identically
+fn field(domain: &[u8], counter: u64) -> [u8; 32] {
+    let mut value = keccak::hashv(&[domain, &counter.to_le_bytes()]).to_bytes();
+    value[0] = 0;
+    value
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Shield { amount: u64 },
+    Unshield { note: usize, denomination: usize, fee_bps: u16, change: bool },
+    Transfer { first: usize, second: Option<usize>, split_bps: u16 },
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (MIN_DEPOSIT..=MAX_SHIELD).prop_map(|amount| Op::Shield { amount }),
+        (any::<usize>(), any::<usize>(), 0u16..=10_000, any::<bool>()).prop_map(
+            |(note, denomination, fee_bps, change)| Op::Unshield { note, denomination, fee_bps, change }
+        ),
+        (any::<usize>(), proptest::option::of(any::<usize>()), 0u16..=10_000).prop_map(
+            |(first, second, split_bps)| Op::Transfer { first, second, split_bps }
+        ),
+    ]
+}
+
+/// An unspent note as its owner sees it
+struct Note {
+    value: u64,
+    nullifier_hash: [u8; 32],
+}
+
+struct Harness {
+    pool: TestPool,
+    notes: Vec<Note>,
+    counter: u64,
+}
+
+impl Harness {
+    async fn start() -> Self {
+        Self { pool: TestPool::start(MERKLE_LEVELS).await, notes: Vec::new(), counter: 0 }
+    }
+
+    fn new_note(&mut self, value: u64) -> [u8; 32] {
+        self.counter += 1;
+        self.notes.push(Note { value, nullifier_hash: field(b"nullifier", self.counter) });
+        field(b"commitment", self.counter)
+    }
+
+    /// Apply `op` if the note model allows it; Ok(false) when skipped
+    async fn apply(&mut self, op: &Op) -> std::result::Result<bool, String> {
+        use whistle_pool::instruction;
+
+        match *op {
+            Op::Shield { amount } => {
+                let net = amount - amount * whistle_pool::PROTOCOL_FEE_BPS / whistle_pool::BPS_DENOMINATOR;
+                let commitment = self.new_note(net);
+                self.pool.shield(commitment, amount).await?;
+            }
+
+            Op::Unshield { note, denomination, fee_bps, change } => {
+                if self.notes.is_empty() {
+                    return Ok(false);
+                }
+                let spent = self.notes.swap_remove(note % self.notes.len());
+                let eligible: Vec<(usize, u64)> = DENOMINATIONS.iter().copied()
+                    .enumerate()
+                    .filter(|(_, d)| *d <= spent.value)
+                    .collect();
+                if eligible.is_empty() {
+                    self.notes.push(spent);
+                    return Ok(false);
+                }
+                let (denom_idx, withdrawal_amount) = eligible[denomination % eligible.len()];
+                let cap = withdrawal_amount * DEFAULT_RELAYER_FEE_CAPS_BPS[denom_idx] as u64 / 10_000;
+                let relayer_fee = cap * fee_bps as u64 / 10_000;
+
+                let change_value = spent.value - withdrawal_amount;
+                let change_commitment = if change && change_value > 0 {
+                    self.new_note(change_value)
+                } else {
+                    [0u8; 32]
+                };
+
+                let recipient = self.pool.payer.pubkey();
+                let merkle_root = self.pool.current_root().await;
+                let proof_a = test_proof(&[
+                    merkle_root,
+                    spent.nullifier_hash,
+                    recipient_field(&recipient),
+                    field_u64(withdrawal_amount),
+                    field_u64(relayer_fee),
+                    change_commitment,
+                    field_u64(0),
+                ]);
+
+                let ix = self.pool.ix(
+                    self.pool.unshield_accounts(&spent.nullifier_hash, &change_commitment, recipient, recipient),
+                    instruction::Unshield {
+                        proof_a,
+                        proof_b: [0u8; 128],
+                        proof_c: [0u8; 64],
+                        nullifier_hash: spent.nullifier_hash,
+                        recipient,
+                        withdrawal_amount,
+                        relayer_fee,
+                        merkle_root,
+                        change_commitment,
+                        unlock_slot: 0,
+                    },
+                );
+                self.pool.send(ix).await?;
+            }
+
+            Op::Transfer { first, second, split_bps } => {
+                if self.notes.is_empty() {
+                    return Ok(false);
+                }
+                let a = self.notes.swap_remove(first % self.notes.len());
+                let b = match second {
+                    Some(i) if !self.notes.is_empty() => Some(self.notes.swap_remove(i % self.notes.len())),
+                    _ => None,
+                };
+                let total = a.value + b.as_ref().map_or(0, |n| n.value);
+                let input_nullifier_hashes = [a.nullifier_hash, b.map_or([0u8; 32], |n| n.nullifier_hash)];
+
+                let first_out = total * split_bps as u64 / 10_000;
+                let output_commitments = [self.new_note(first_out), self.new_note(total - first_out)];
+
+                let merkle_root = self.pool.current_root().await;
+                let proof_a = test_proof(&[
+                    merkle_root,
+                    input_nullifier_hashes[0],
+                    input_nullifier_hashes[1],
+                    output_commitments[0],
+                    output_commitments[1],
+                    field_u64(0),
+                    field_u64(0),
+                ]);
+
+                let ix = self.pool.ix(
+                    self.pool.transfer_accounts(&input_nullifier_hashes, &output_commitments),
+                    instruction::PrivateTransfer {
+                        proof_a,
+                        proof_b: [0u8; 128],
+                        proof_c: [0u8; 64],
+                        input_nullifier_hashes,
+                        output_commitments,
+                        merkle_root,
+                        unlock_slots: [0; 2],
+                    },
+                );
+                self.pool.send(ix).await?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Bitmap of violated invariants, read from assert_invariants' return data
+    async fn violations(&mut self) -> u32 {
+        let ix = self.pool.ix(
+            whistle_pool::accounts::AssertInvariants {
+                pool: pda(b"pool"),
+                merkle_tree: pda(b"merkle_tree"),
+                roots_history: pda(b"roots_history"),
+                nullifiers: pda(b"nullifiers"),
+                pool_vault: pda(b"vault"),
+            },
+            whistle_pool::instruction::AssertInvariants {},
+        );
+        let mut data = self.pool.view(ix).await;
+        data.resize(4, 0);
+        u32::from_le_bytes(data[..4].try_into().unwrap())
+    }
+}
+
+async fn run(ops: Vec<Op>) -> std::result::Result<(), TestCaseError> {
+    let mut harness = Harness::start().await;
+    prop_assert_eq!(harness.violations().await, 0, "invariants violated at genesis");
+
+    for (step, op) in ops.iter().enumerate() {
+        let applied = harness.apply(op).await;
+        prop_assert!(applied.is_ok(), "step {} ({:?}) failed: {:?}", step, op, applied);
+        let violations = harness.violations().await;
+        prop_assert_eq!(violations, 0, "step {} ({:?}) violated invariants {:#09b}", step, op, violations);
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn operation_sequences_keep_invariants(ops in proptest::collection::vec(op_strategy(), 1..24)) {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(run(ops))?;
+    }
+}
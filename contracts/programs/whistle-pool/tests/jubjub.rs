@@ -0,0 +1,204 @@
+//! Jubjub-style notes end to end: shield_jubjub binding the note to an
+//! Ed25519 spend key, unshield_jubjub refusing a missing precompile
+//! signature, a signature by another key and a recipient or relayer fee
+//! other than the signed ones, then paying out the signed split. Also
+//! checks that BN254 and Jubjub pools each refuse the other curve's shield
+//! and unshield instructions.
+//!
+//! cargo test -p whistle-pool --features test-harness,jubjub --test jubjub
+
+mod common;
+
+use anchor_client::solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::{instruction::Instruction, keccak, sysvar};
+use solana_program_test::BanksClientError;
+
+use common::{create_mint, create_token_account, ed25519_ix, shield_token_ix, TestPool};
+use whistle_pool::jubjub::{amount_to_scalar, commit_jubjub, JubjubCommitment};
+use whistle_pool::{accounts, instruction, WhistleError, BPS_DENOMINATOR, CURVE_JUBJUB, PROTOCOL_FEE_BPS};
+
+const MERKLE_LEVELS: u8 = 7;
+const SHIELD_AMOUNT: u64 = 100_000_000; // 0.1 SOL
+
+fn field(domain: &[u8]) -> [u8; 32] {
+    let mut value = keccak::hash(domain).to_bytes();
+    value[0] = 0;
+    value
+}
+
+/// Index of the failing instruction and its custom error code
+fn instruction_error(err: BanksClientError) -> (u8, u32) {
+    let BanksClientError::TransactionError(TransactionError::InstructionError(index, InstructionError::Custom(code))) =
+        err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    (index, code)
+}
+
+/// A shielded Jubjub note and its opening
+struct Note {
+    commitment: JubjubCommitment,
+    randomness: [u8; 32],
+    /// What the note holds: the shielded amount less the protocol fee
+    amount: u64,
+    spend_key: Keypair,
+}
+
+impl Note {
+    fn new(seed: u8) -> Self {
+        let amount = SHIELD_AMOUNT - SHIELD_AMOUNT * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+        // Any scalar below the group order
+        let mut randomness = [0u8; 32];
+        randomness[0] = seed;
+        randomness[1] = 0x5a;
+        Self {
+            commitment: commit_jubjub(&amount_to_scalar(amount), &randomness).unwrap(),
+            randomness,
+            amount,
+            spend_key: Keypair::new(),
+        }
+    }
+
+    fn leaf(&self) -> [u8; 32] {
+        self.commitment.leaf(self.amount, &self.spend_key.pubkey().to_bytes())
+    }
+}
+
+async fn shield_jubjub(pool: &mut TestPool, note: &Note) -> Result<(), BanksClientError> {
+    let next_index = pool.pool_state().await.next_index;
+    let ix = pool.ix(
+        pool.shield_accounts(next_index, &note.leaf()),
+        instruction::ShieldJubjub {
+            commitment: note.commitment,
+            spend_pubkey: note.spend_key.pubkey().to_bytes(),
+            amount: SHIELD_AMOUNT,
+        },
+    );
+    pool.send_result(ix).await
+}
+
+fn unshield_jubjub_ix(
+    pool: &TestPool,
+    note: &Note,
+    recipient: Pubkey,
+    relayer: Pubkey,
+    relayer_fee: u64,
+    signature: [u8; 64],
+) -> Instruction {
+    pool.ix(
+        accounts::UnshieldJubjub {
+            unshield: pool.unshield_accounts(&note.commitment.nullifier_hash(), &[0u8; 32], recipient, relayer),
+            instructions: sysvar::instructions::ID,
+        },
+        instruction::UnshieldJubjub {
+            commitment: note.commitment,
+            randomness: note.randomness,
+            amount: note.amount,
+            recipient,
+            relayer_fee,
+            signature,
+        },
+    )
+}
+
+async fn jubjub_pool() -> TestPool {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let mut state = pool.pool_state().await;
+    state.curve = CURVE_JUBJUB;
+    pool.set_pool_state(&state).await;
+    pool
+}
+
+#[tokio::test]
+async fn jubjub_notes_unshield_only_as_their_spend_key_signed() {
+    let mut pool = jubjub_pool().await;
+    let note = Note::new(1);
+    shield_jubjub(&mut pool, &note).await.unwrap();
+
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let relayer_fee = note.amount / 20;
+    let signed = |signer: &Keypair, recipient: Pubkey, relayer_fee: u64| {
+        ed25519_ix(signer, &note.commitment.withdraw_message(&recipient, relayer_fee))
+    };
+    let (precompile, signature) = signed(&note.spend_key, recipient, relayer_fee);
+    let unshield = unshield_jubjub_ix(&pool, &note, recipient, relayer, relayer_fee, signature);
+    let schnorr = u32::from(WhistleError::InvalidSchnorrSignature);
+
+    // Without the precompile instruction
+    let err = pool.send_all(std::slice::from_ref(&unshield)).await.unwrap_err();
+    assert_eq!(instruction_error(err), (0, schnorr));
+
+    // Someone who saw the opening redirects the payout: the spend key's
+    // signature does not cover the new recipient...
+    let thief = Keypair::new();
+    let redirected = unshield_jubjub_ix(&pool, &note, thief.pubkey(), relayer, relayer_fee, signature);
+    let err = pool.send_all(&[precompile.clone(), redirected]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, schnorr));
+
+    // ...and their own signature names a key the note was not shielded to
+    let (thief_precompile, thief_signature) = signed(&thief, thief.pubkey(), relayer_fee);
+    let stolen = unshield_jubjub_ix(&pool, &note, thief.pubkey(), relayer, relayer_fee, thief_signature);
+    let err = pool.send_all(&[thief_precompile, stolen]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::CommitmentNotFound)));
+
+    // A relayer fee other than the signed one
+    let raised = unshield_jubjub_ix(&pool, &note, recipient, relayer, relayer_fee + 1, signature);
+    let err = pool.send_all(&[precompile.clone(), raised]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, schnorr));
+
+    // A signed relayer fee above 10% of the note
+    let excessive = note.amount / 10 + 1;
+    let (excessive_precompile, excessive_signature) = signed(&note.spend_key, recipient, excessive);
+    let ix = unshield_jubjub_ix(&pool, &note, recipient, relayer, excessive, excessive_signature);
+    let err = pool.send_all(&[excessive_precompile, ix]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::FeeTooHigh)));
+
+    // As signed, the note pays out once
+    pool.send_all(&[precompile.clone(), unshield.clone()]).await.unwrap();
+    assert_eq!(pool.balance(recipient).await, note.amount - relayer_fee);
+    assert_eq!(pool.balance(relayer).await, relayer_fee);
+    let err = pool.send_all(&[precompile, unshield]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::NullifierAlreadyUsed)));
+}
+
+#[tokio::test]
+async fn pools_refuse_the_other_curves_notes() {
+    let unsupported = u32::from(WhistleError::UnsupportedCurve);
+
+    // A BN254 pool takes no Jubjub notes and unshields none
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let note = Note::new(2);
+    let err = shield_jubjub(&mut pool, &note).await.unwrap_err();
+    assert_eq!(instruction_error(err), (0, unsupported));
+    let recipient = Keypair::new().pubkey();
+    let (precompile, signature) = ed25519_ix(&note.spend_key, &note.commitment.withdraw_message(&recipient, 0));
+    let unshield = unshield_jubjub_ix(&pool, &note, recipient, Keypair::new().pubkey(), 0, signature);
+    let err = pool.send_all(&[precompile, unshield]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, unsupported));
+
+    // A Jubjub pool takes no BN254 notes through any shield path; its
+    // withdrawal paths are covered by tests/guards.rs
+    let mut pool = jubjub_pool().await;
+    let commitment = field(b"bn254 commitment");
+    let shield = pool.ix(pool.shield_accounts(0, &commitment), instruction::Shield { commitment, amount: SHIELD_AMOUNT });
+    let batch_shield = pool.batch_shield_ix(
+        0,
+        &[commitment],
+        instruction::BatchShield { commitments: vec![commitment], amounts: vec![SHIELD_AMOUNT] },
+    );
+    let mint = create_mint(&mut pool, 0).await;
+    let owner = pool.payer.pubkey();
+    let source = create_token_account(&mut pool, mint, owner, 10).await;
+    let shield_token = shield_token_ix(&pool, 0, mint, source, commitment, 10);
+    for ix in [shield, batch_shield, shield_token] {
+        let err = pool.send_result(ix).await.unwrap_err();
+        assert_eq!(instruction_error(err), (0, unsupported));
+    }
+}
@@ -0,0 +1,167 @@
+//! Profile events from the `profiling` feature: shield, unshield and
+//! private_transfer each report their proof verify, tree insert and
+//! transfer sections, and the sections plus the meter reading carried in
+//! the event account for the compute units the runtime charged. Unshield's
+//! cost outside its sections is held under a ceiling that the four PDA bump
+//! searches it used to run would break on their own.
+//!
+//! Native program-test has no compute meter (sol_remaining_compute_units
+//! reads zero), so this runs the SBF build and is ignored by default:
+//!
+//! cargo build-sbf --features profiling,test-harness
+//! cargo test -p whistle-pool --features profiling,test-harness --test profiling -- --ignored
+
+mod common;
+
+use anchor_client::solana_sdk::signature::{Keypair, Signer};
+use anchor_lang::solana_program::keccak;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::Engine;
+use solana_program_test::BanksTransactionResultWithMetadata;
+
+use common::{recipient_field, TestPool};
+use whistle_pool::harness::{field_u64, test_proof};
+use whistle_pool::profiling::{Profile, PROOF_VERIFY, TRANSFERS, TREE_INSERT};
+use whistle_pool::instruction;
+
+const MERKLE_LEVELS: u8 = 7;
+
+// Compute budget of a transaction with one instruction and no
+// SetComputeUnitLimit
+const DEFAULT_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+
+// Charged after profile_emit! reads the meter: logging the event itself
+// and unwinding back to the runtime
+const EMIT_TAIL_CU: u64 = 2_000;
+
+// Unshield's account validation, deserialization and checks outside the
+// profiled sections. Verifying the vault and data PDAs against the bumps
+// stored in PoolState replaced four find_program_address searches of
+// ~1.5k CU each; re-deriving them again pushes unshield past this. The
+// nullifier marker costs two more searches (checked before and created
+// after the proof) and a create_account CPI.
+const UNSHIELD_UNPROFILED_CU: u64 = 20_000;
+
+fn field(domain: &[u8]) -> [u8; 32] {
+    let mut value = keccak::hash(domain).to_bytes();
+    value[0] = 0;
+    value
+}
+
+/// The Profile event logged by a successful transaction, with the compute
+/// units the runtime charged for it
+fn profile(result: BanksTransactionResultWithMetadata) -> (Profile, u64) {
+    result.result.expect("profiled instruction failed");
+    let metadata = result.metadata.unwrap();
+    let profile = metadata
+        .log_messages
+        .iter()
+        .filter_map(|log| log.strip_prefix("Program data: "))
+        .map(|data| base64::engine::general_purpose::STANDARD.decode(data).unwrap())
+        .find(|data| data[..8] == Profile::DISCRIMINATOR)
+        .map(|data| Profile::try_from_slice(&data[8..]).unwrap())
+        .expect("no Profile event");
+    (profile, metadata.compute_units_consumed)
+}
+
+/// Check that `profile` reports exactly `tags` and reconciles with `consumed`
+fn check_profile(profile: &Profile, consumed: u64, tags: &[u8]) {
+    let reported: Vec<u8> = profile.section_costs.iter().map(|section| section.tag).filter(|&tag| tag != 0).collect();
+    assert_eq!(reported, tags);
+    for section in profile.section_costs.iter().filter(|section| section.tag != 0) {
+        assert!(section.cu > 0, "section {} reported no compute units", section.tag);
+    }
+
+    // Everything up to the event, as read by the program, is the runtime's
+    // total less the tail after it; the sections are part of that
+    let spent_at_emit = DEFAULT_COMPUTE_UNIT_LIMIT - profile.remaining_cu;
+    assert!(spent_at_emit <= consumed && consumed - spent_at_emit <= EMIT_TAIL_CU,
+        "meter read {spent_at_emit} CU at emit, runtime charged {consumed} CU");
+    let sections: u64 = profile.section_costs.iter().map(|section| section.cu).sum();
+    assert!(sections <= spent_at_emit, "sections sum to {sections} CU of {spent_at_emit} CU");
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build: cargo build-sbf --features profiling,test-harness"]
+async fn profiled_instructions_account_for_their_compute_units() {
+    let mut pool = TestPool::start_sbf(MERKLE_LEVELS).await;
+
+    // Shield: fee and vault transfers, then the tree insert and roots history
+    let commitment = field(b"commitment");
+    let shield = pool.ix(
+        pool.shield_accounts(0, &commitment),
+        instruction::Shield { commitment, amount: 3 * whistle_pool::DENOM_1_SOL },
+    );
+    let result = pool.send_with_metadata(shield).await;
+    let (shielded, consumed) = profile(result);
+    check_profile(&shielded, consumed, &[TRANSFERS, TREE_INSERT]);
+
+    // Unshield with change: verify, change insert, payouts
+    let withdrawal_amount = whistle_pool::DENOM_1_SOL;
+    let relayer_fee = withdrawal_amount / 100;
+    let recipient = Keypair::new().pubkey();
+    let relayer = Keypair::new().pubkey();
+    let nullifier_hash = field(b"nullifier");
+    let change_commitment = field(b"change");
+    let merkle_root = pool.current_root().await;
+    let proof_a = test_proof(&[
+        merkle_root,
+        nullifier_hash,
+        recipient_field(&recipient),
+        field_u64(withdrawal_amount),
+        field_u64(relayer_fee),
+        change_commitment,
+        field_u64(0),
+    ]);
+    let unshield = pool.ix(
+        pool.unshield_accounts(&nullifier_hash, &change_commitment, recipient, relayer),
+        instruction::Unshield {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            nullifier_hash,
+            recipient,
+            withdrawal_amount,
+            relayer_fee,
+            merkle_root,
+            change_commitment,
+            unlock_slot: 0,
+        },
+    );
+    let result = pool.send_with_metadata(unshield).await;
+    let (unshielded, consumed) = profile(result);
+    check_profile(&unshielded, consumed, &[PROOF_VERIFY, TREE_INSERT, TRANSFERS]);
+    let sections: u64 = unshielded.section_costs.iter().map(|section| section.cu).sum();
+    let unprofiled = DEFAULT_COMPUTE_UNIT_LIMIT - unshielded.remaining_cu - sections;
+    assert!(unprofiled <= UNSHIELD_UNPROFILED_CU,
+        "unshield spent {unprofiled} CU outside its sections, ceiling {UNSHIELD_UNPROFILED_CU} CU");
+
+    // Private transfer of the change note: verify, then two inserts
+    let merkle_root = pool.current_root().await;
+    let input_nullifier_hashes = [field(b"change nullifier"), [0u8; 32]];
+    let output_commitments = [field(b"output 0"), field(b"output 1")];
+    let proof_a = test_proof(&[
+        merkle_root,
+        input_nullifier_hashes[0],
+        input_nullifier_hashes[1],
+        output_commitments[0],
+        output_commitments[1],
+        field_u64(0),
+        field_u64(0),
+    ]);
+    let transfer = pool.ix(
+        pool.transfer_accounts(&input_nullifier_hashes, &output_commitments),
+        instruction::PrivateTransfer {
+            proof_a,
+            proof_b: [0u8; 128],
+            proof_c: [0u8; 64],
+            input_nullifier_hashes,
+            output_commitments,
+            merkle_root,
+            unlock_slots: [0; 2],
+        },
+    );
+    let result = pool.send_with_metadata(transfer).await;
+    let (transferred, consumed) = profile(result);
+    check_profile(&transferred, consumed, &[PROOF_VERIFY, TREE_INSERT]);
+}
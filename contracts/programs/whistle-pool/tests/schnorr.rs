@@ -0,0 +1,170 @@
+//! The Schnorr withdraw path for small notes: verify_schnorr_withdraw
+//! refusing a pool that has not enabled it, a precompile instruction that
+//! is missing, not the one just before, or verifying data held by another
+//! instruction, a signature by a key other than the note's spend key, and
+//! a signature over another recipient or amount. Then the note pays out
+//! once and its nullifier is spent.
+//!
+//! cargo test -p whistle-pool --features test-harness --test schnorr
+
+mod common;
+
+use anchor_client::solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::{instruction::Instruction, keccak, system_instruction, sysvar};
+use solana_program_test::BanksClientError;
+
+use common::{ed25519_ix, nullifier_marker, pda, TestPool};
+use whistle_pool::{
+    accounts, instruction, schnorr_note_commitment, schnorr_nullifier_hash, WhistleError, BPS_DENOMINATOR,
+    DENOM_001_SOL, DENOM_005_SOL, PROTOCOL_FEE_BPS, SCHNORR_WITHDRAW_DOMAIN,
+};
+
+const MERKLE_LEVELS: u8 = 7;
+const SHIELD_AMOUNT: u64 = 100_000_000; // 0.1 SOL
+
+/// Index of the failing instruction and its custom error code
+fn instruction_error(err: BanksClientError) -> (u8, u32) {
+    let BanksClientError::TransactionError(TransactionError::InstructionError(index, InstructionError::Custom(code))) =
+        err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    (index, code)
+}
+
+/// A Schnorr-path note of `amount` owned by `spend_key`
+struct Note {
+    spend_key: Keypair,
+    amount: u64,
+    commitment: [u8; 32],
+    nullifier_hash: [u8; 32],
+}
+
+impl Note {
+    fn new(amount: u64) -> Self {
+        let spend_key = Keypair::new();
+        let commitment = schnorr_note_commitment(&spend_key.pubkey().to_bytes(), amount);
+        Self { spend_key, amount, commitment, nullifier_hash: schnorr_nullifier_hash(&commitment) }
+    }
+
+    /// What the withdrawal signs
+    fn message(&self, recipient: &Pubkey, amount: u64) -> Vec<u8> {
+        [SCHNORR_WITHDRAW_DOMAIN, &self.nullifier_hash, recipient.as_ref(), &amount.to_le_bytes()].concat()
+    }
+}
+
+fn withdraw_ix(pool: &TestPool, note: &Note, recipient: Pubkey, amount: u64, signature: [u8; 64]) -> Instruction {
+    pool.ix(
+        accounts::SchnorrWithdraw {
+            pool: pda(b"pool"),
+            merkle_tree: pda(b"merkle_tree"),
+            nullifiers: pda(b"nullifiers"),
+            pool_vault: pda(b"vault"),
+            fee_vault: pda(b"fee_vault"),
+            recipient,
+            instructions: sysvar::instructions::ID,
+            system_program: anchor_lang::solana_program::system_program::ID,
+            congestion: pda(b"congestion"),
+            nullifier_marker: nullifier_marker(&note.nullifier_hash),
+            payer: pool.payer.pubkey(),
+        },
+        instruction::VerifySchnorrWithdraw {
+            signature,
+            commitment: note.commitment,
+            nullifier_hash: note.nullifier_hash,
+            recipient,
+            amount,
+        },
+    )
+}
+
+/// A pool with the Schnorr path enabled as `allow`, holding `note` and a
+/// larger note beside it
+async fn pool_with(note: &Note, allow: bool) -> TestPool {
+    let mut pool = TestPool::start(MERKLE_LEVELS).await;
+    let mut state = pool.pool_state().await;
+    state.allow_schnorr_for_small = allow;
+    pool.set_pool_state(&state).await;
+
+    // The shield fee leaves the note's leaf short of its face value, so
+    // another deposit covers the difference in total_shielded
+    let mut other = keccak::hash(b"other note").to_bytes();
+    other[0] = 0;
+    pool.shield(other, SHIELD_AMOUNT).await.unwrap();
+    pool.shield(note.commitment, note.amount).await.unwrap();
+    pool
+}
+
+#[tokio::test]
+async fn schnorr_withdraw_is_refused_unless_enabled() {
+    let note = Note::new(DENOM_001_SOL);
+    let mut pool = pool_with(&note, false).await;
+    let recipient = Keypair::new().pubkey();
+    let (precompile, signature) = ed25519_ix(&note.spend_key, &note.message(&recipient, note.amount));
+    let withdraw = withdraw_ix(&pool, &note, recipient, note.amount, signature);
+    let err = pool.send_all(&[precompile, withdraw]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::SchnorrPathDisabled)));
+}
+
+#[tokio::test]
+async fn schnorr_withdraw_needs_the_spend_keys_signature_just_before_it() {
+    let note = Note::new(DENOM_005_SOL);
+    let mut pool = pool_with(&note, true).await;
+    let recipient = Keypair::new().pubkey();
+    let (precompile, signature) = ed25519_ix(&note.spend_key, &note.message(&recipient, note.amount));
+    let withdraw = withdraw_ix(&pool, &note, recipient, note.amount, signature);
+    let schnorr = u32::from(WhistleError::InvalidSchnorrSignature);
+
+    // Without the precompile instruction
+    let err = pool.send_all(std::slice::from_ref(&withdraw)).await.unwrap_err();
+    assert_eq!(instruction_error(err), (0, schnorr));
+
+    // The precompile two instructions back rather than just before
+    let payer = pool.payer.pubkey();
+    let between = system_instruction::transfer(&payer, &payer, 0);
+    let err = pool.send_all(&[precompile.clone(), between, withdraw.clone()]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (2, schnorr));
+
+    // A precompile whose offsets read the signature, key and message from
+    // instruction 0 by index: valid for the precompile, but the program
+    // only trusts data the instruction carries itself
+    let mut indexed = precompile.clone();
+    for at in [4, 8, 14] {
+        indexed.data[at..at + 2].copy_from_slice(&0u16.to_le_bytes());
+    }
+    let err = pool.send_all(&[indexed, withdraw.clone()]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, schnorr));
+
+    // Another key's valid signature over the same message opens no note
+    // of theirs
+    let thief = Keypair::new();
+    let (thief_precompile, thief_signature) = ed25519_ix(&thief, &note.message(&recipient, note.amount));
+    let stolen = withdraw_ix(&pool, &note, recipient, note.amount, thief_signature);
+    let err = pool.send_all(&[thief_precompile, stolen]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::InvalidCommitment)));
+
+    // The spend key's signature does not cover another recipient...
+    let redirected = withdraw_ix(&pool, &note, thief.pubkey(), note.amount, signature);
+    let err = pool.send_all(&[precompile.clone(), redirected]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, schnorr));
+
+    // ...nor a message signed for another amount
+    let (other_precompile, other_signature) = ed25519_ix(&note.spend_key, &note.message(&recipient, DENOM_001_SOL));
+    let ix = withdraw_ix(&pool, &note, recipient, note.amount, other_signature);
+    let err = pool.send_all(&[other_precompile, ix]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, schnorr));
+
+    // As signed, the note pays out once
+    let shielded = pool.pool_state().await.total_shielded;
+    pool.send_all(&[precompile.clone(), withdraw.clone()]).await.unwrap();
+    let protocol_fee = note.amount * PROTOCOL_FEE_BPS / BPS_DENOMINATOR;
+    assert_eq!(pool.balance(recipient).await, note.amount - protocol_fee);
+    assert_eq!(pool.pool_state().await.total_shielded, shielded - note.amount);
+    let err = pool.send_all(&[precompile, withdraw]).await.unwrap_err();
+    assert_eq!(instruction_error(err), (1, u32::from(WhistleError::NullifierAlreadyUsed)));
+}
@@ -1,11 +1,15 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::alt_bn128::prelude::*;
+use whistle_groth16::compressed::{G1_COMPRESSED_SIZE, G2_COMPRESSED_SIZE};
+use whistle_groth16::{is_valid_g1, is_valid_g2, Groth16Error, Groth16Proof};
+
+pub use whistle_groth16::{PreparedVerificationKey, VerificationKey, PAIRING_INPUT_SIZE, PAIR_SIZE};
 
 declare_id!("C6cKqUzwMdL5Tm9vNsYNjPwZjprthyypywmgne3RkSD4");
 
 /// Whistle Protocol Groth16 Verifier
 /// 
-/// Real zero-knowledge proof verification using Solana's alt_bn128 syscalls.
+/// Real zero-knowledge proof verification using Solana's alt_bn128 syscalls,
+/// through the whistle-groth16 crate shared with whistle-pool.
 /// Verifies Groth16 proofs on the BN254 (alt_bn128) elliptic curve.
 ///
 /// Verification equation:
@@ -28,7 +32,7 @@ pub mod whistle_verifier {
     ) -> Result<bool> {
         require!(public_inputs.len() == 5, VerifierError::InvalidPublicInputCount);
         
-        let vk = get_withdraw_verification_key();
+        let vk = WITHDRAW_PREPARED_KEY;
         
         let result = verify_groth16_proof(
             &proof_a,
@@ -54,7 +58,7 @@ pub mod whistle_verifier {
     ) -> Result<bool> {
         require!(public_inputs.len() == 2, VerifierError::InvalidPublicInputCount);
         
-        let vk = get_deposit_verification_key();
+        let vk = DEPOSIT_PREPARED_KEY;
         
         let result = verify_groth16_proof(
             &proof_a,
@@ -69,243 +73,333 @@ pub mod whistle_verifier {
         msg!("Deposit proof verified successfully");
         Ok(true)
     }
+
+    /// Verify a Groth16 proof of four withdrawals (batch_withdraw circuit)
+    /// 
+    /// Public inputs: [merkleRoot, nullifierHash1..4, amount1..4, recipient,
+    /// relayerFee]. One pairing check covers all four notes. Fails with
+    /// VerifyingKeyNotGenerated until the circuit's key is committed.
+    pub fn verify_batch_withdraw_proof(
+        _ctx: Context<VerifyProof>,
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+        public_inputs: Vec<[u8; 32]>,
+    ) -> Result<bool> {
+        require!(public_inputs.len() == 11, VerifierError::InvalidPublicInputCount);
+        
+        let vk = BATCH_WITHDRAW_PREPARED_KEY;
+        
+        let result = verify_groth16_proof(
+            &proof_a,
+            &proof_b,
+            &proof_c,
+            &public_inputs,
+            &vk,
+        )?;
+        
+        require!(result, VerifierError::ProofVerificationFailed);
+        
+        msg!("Batch withdrawal proof verified successfully");
+        Ok(true)
+    }
+
+    /// Verify a Groth16 proof sent with compressed points
+    /// 
+    /// Each point is a prefix byte (0x02 | sgn0(y), or 0x00 for infinity)
+    /// followed by its x-coordinate: 33 bytes for A and C, 65 for B, 131 in
+    /// all against 256 uncompressed. `circuit` selects the key as in
+    /// validate_verification_key.
+    pub fn verify_compressed_proof(
+        _ctx: Context<VerifyProof>,
+        circuit: u8,
+        proof_a_compressed: [u8; G1_COMPRESSED_SIZE],
+        proof_b_compressed: [u8; G2_COMPRESSED_SIZE],
+        proof_c_compressed: [u8; G1_COMPRESSED_SIZE],
+        public_inputs: Vec<[u8; 32]>,
+    ) -> Result<bool> {
+        let (vk, input_count) = match circuit {
+            CIRCUIT_WITHDRAW => (WITHDRAW_PREPARED_KEY, 5),
+            CIRCUIT_DEPOSIT => (DEPOSIT_PREPARED_KEY, 2),
+            CIRCUIT_BATCH_WITHDRAW => (BATCH_WITHDRAW_PREPARED_KEY, 11),
+            _ => return err!(VerifierError::UnknownCircuit),
+        };
+        require!(public_inputs.len() == input_count, VerifierError::InvalidPublicInputCount);
+        require!(vk_is_generated(&vk.key), VerifierError::VerifyingKeyNotGenerated);
+        
+        let result = Groth16Proof::from_compressed(&proof_a_compressed, &proof_b_compressed, &proof_c_compressed)
+            .and_then(|proof| whistle_groth16::verify_prepared(&vk, &proof, &public_inputs));
+        
+        require!(map_groth16_result(result)?, VerifierError::ProofVerificationFailed);
+        
+        msg!("Compressed proof verified successfully");
+        Ok(true)
+    }
+
+    /// Check that every point of a built-in verification key is on its curve
+    /// 
+    /// A copy-paste error in a VK makes every verification fail (or pass)
+    /// silently; this surfaces it. `circuit` is CIRCUIT_WITHDRAW,
+    /// CIRCUIT_DEPOSIT or CIRCUIT_BATCH_WITHDRAW.
+    pub fn validate_verification_key(
+        _ctx: Context<VerifyProof>,
+        circuit: u8,
+    ) -> Result<VkValidationResult> {
+        let vk = match circuit {
+            CIRCUIT_WITHDRAW => WITHDRAW_VERIFICATION_KEY,
+            CIRCUIT_DEPOSIT => DEPOSIT_VERIFICATION_KEY,
+            CIRCUIT_BATCH_WITHDRAW => BATCH_WITHDRAW_VERIFICATION_KEY,
+            _ => return err!(VerifierError::UnknownCircuit),
+        };
+        require!(vk_is_generated(&vk), VerifierError::VerifyingKeyNotGenerated);
+        
+        let result = validate_vk(&vk);
+        if let Some(component) = result.first_invalid_component() {
+            msg!("Verification key component not on curve: {}", component);
+            return err!(VerifierError::VkValidationFailed);
+        }
+        
+        msg!("Verification key valid");
+        Ok(result)
+    }
 }
 
 #[derive(Accounts)]
 pub struct VerifyProof {}
 
-// ============================================================================
-// VERIFICATION KEY STRUCTURE
-// ============================================================================
+/// Circuit identifiers for validate_verification_key and
+/// verify_compressed_proof
+pub const CIRCUIT_WITHDRAW: u8 = 0;
+pub const CIRCUIT_DEPOSIT: u8 = 1;
+pub const CIRCUIT_BATCH_WITHDRAW: u8 = 2;
+
+/// Per-component result of verification key validation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct VkValidationResult {
+    pub alpha_valid: bool,
+    pub beta_valid: bool,
+    pub gamma_valid: bool,
+    pub delta_valid: bool,
+    pub ic_all_valid: bool,
+}
 
-/// Groth16 Verification Key
-/// Contains the public parameters from trusted setup
-pub struct VerificationKey {
-    /// G1 point: alpha (from trusted setup)
-    pub alpha_g1: [u8; 64],
-    /// G2 point: beta (from trusted setup)
-    pub beta_g2: [u8; 128],
-    /// G2 point: gamma (from trusted setup)
-    pub gamma_g2: [u8; 128],
-    /// G2 point: delta (from trusted setup)
-    pub delta_g2: [u8; 128],
-    /// G1 points: IC (input commitments)
-    /// IC[0] + sum(public_input[i] * IC[i+1])
-    pub ic: Vec<[u8; 64]>,
+impl VkValidationResult {
+    pub fn first_invalid_component(&self) -> Option<&'static str> {
+        if !self.alpha_valid {
+            Some("alpha")
+        } else if !self.beta_valid {
+            Some("beta")
+        } else if !self.gamma_valid {
+            Some("gamma")
+        } else if !self.delta_valid {
+            Some("delta")
+        } else if !self.ic_all_valid {
+            Some("ic")
+        } else {
+            None
+        }
+    }
 }
 
 // ============================================================================
 // GROTH16 VERIFICATION
 // ============================================================================
 
-/// Verify a Groth16 proof using alt_bn128 syscalls
+/// Verify a Groth16 proof (A as the prover output it) with whistle-groth16
 /// 
 /// Verification equation:
 /// e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta) = 1
-fn verify_groth16_proof(
+/// 
+/// Ok(false) means the pairing check failed; an error means a point or
+/// input could not be used at all. A key that has not been generated fails
+/// with VerifyingKeyNotGenerated. A, C and the key's IC points are checked
+/// to be on the curve first, so a malformed one fails with
+/// InvalidCurvePoint instead of inside a syscall. B is left to the pairing
+/// syscall, which rejects it the same way, and vk_x is built from IC
+/// points by syscalls that only return curve points.
+/// 
+/// `vk` is prepared (VerificationKey::prepare) so the constant pairing
+/// pairs are not laid out again on every call.
+pub fn verify_groth16_proof(
     proof_a: &[u8; 64],
     proof_b: &[u8; 128],
     proof_c: &[u8; 64],
     public_inputs: &[[u8; 32]],
-    vk: &VerificationKey,
+    vk: &PreparedVerificationKey,
 ) -> Result<bool> {
-    // Validate IC length matches public inputs + 1
-    require!(
-        vk.ic.len() == public_inputs.len() + 1,
-        VerifierError::InvalidVerificationKey
-    );
-
-    // Step 1: Compute vk_x = IC[0] + sum(public_input[i] * IC[i+1])
-    let vk_x = compute_linear_combination(&vk.ic, public_inputs)?;
-
-    // Step 2: Negate proof point A (for pairing equation)
-    let neg_a = negate_g1_point(proof_a)?;
-
-    // Step 3: Prepare pairing inputs
-    // Pairing check: e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta) = 1
-    let mut pairing_input = Vec::with_capacity(4 * (64 + 128));
-    
-    // Pair 1: e(-A, B)
-    pairing_input.extend_from_slice(&neg_a);
-    pairing_input.extend_from_slice(proof_b);
-    
-    // Pair 2: e(alpha, beta)
-    pairing_input.extend_from_slice(&vk.alpha_g1);
-    pairing_input.extend_from_slice(&vk.beta_g2);
-    
-    // Pair 3: e(vk_x, gamma)
-    pairing_input.extend_from_slice(&vk_x);
-    pairing_input.extend_from_slice(&vk.gamma_g2);
-    
-    // Pair 4: e(C, delta)
-    pairing_input.extend_from_slice(proof_c);
-    pairing_input.extend_from_slice(&vk.delta_g2);
-
-    // Step 4: Execute pairing check
-    let pairing_result = alt_bn128_pairing(&pairing_input)
-        .map_err(|_| error!(VerifierError::PairingFailed))?;
-
-    // Pairing returns 1 (as 32-byte big-endian) if equation holds
-    let one = [
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    ];
+    require!(vk_is_generated(&vk.key), VerifierError::VerifyingKeyNotGenerated);
+    validate_g1_point(proof_a)?;
+    validate_g1_point(proof_c)?;
+    for ic in vk.key.ic {
+        validate_g1_point(ic)?;
+    }
     
-    Ok(pairing_result == one)
+    let result = Groth16Proof::new(proof_a, proof_b, proof_c)
+        .and_then(|proof| whistle_groth16::verify_prepared(vk, &proof, public_inputs));
+    map_groth16_result(result)
 }
 
-/// Compute vk_x = IC[0] + sum(public_input[i] * IC[i+1])
-/// This is a linear combination of G1 points
-fn compute_linear_combination(
-    ic: &[[u8; 64]],
-    public_inputs: &[[u8; 32]],
-) -> Result<[u8; 64]> {
-    // Start with IC[0]
-    let mut result = ic[0];
-
-    // Add public_input[i] * IC[i+1] for each input
-    for (i, input) in public_inputs.iter().enumerate() {
-        // Scalar multiplication: input * IC[i+1]
-        let mut mul_input = Vec::with_capacity(96);
-        mul_input.extend_from_slice(&ic[i + 1]);
-        mul_input.extend_from_slice(input);
-
-        let product = alt_bn128_multiplication(&mul_input)
-            .map_err(|_| error!(VerifierError::ScalarMulFailed))?;
-
-        // Point addition: result + product
-        let mut add_input = Vec::with_capacity(128);
-        add_input.extend_from_slice(&result);
-        add_input.extend_from_slice(&product);
-
-        let sum = alt_bn128_addition(&add_input)
-            .map_err(|_| error!(VerifierError::PointAdditionFailed))?;
-
-        result.copy_from_slice(&sum);
+/// Ok(false) for a proof that does not verify, an error for anything that
+/// stopped verification from running
+fn map_groth16_result(result: core::result::Result<(), Groth16Error>) -> Result<bool> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(Groth16Error::ProofVerificationFailed) => Ok(false),
+        Err(Groth16Error::InvalidPublicInputsLength) => err!(VerifierError::InvalidVerificationKey),
+        Err(Groth16Error::NonCanonicalPublicInput) => err!(VerifierError::NonCanonicalPublicInput),
+        Err(Groth16Error::G1MulFailed) => err!(VerifierError::ScalarMulFailed),
+        Err(Groth16Error::G1AdditionFailed) => err!(VerifierError::PointAdditionFailed),
+        Err(Groth16Error::PairingFailed) => err!(VerifierError::PairingFailed),
+        Err(Groth16Error::FieldSubtractBorrow) => err!(VerifierError::FieldSubtractBorrow),
+        Err(Groth16Error::PointDecompressionFailed) => err!(VerifierError::PointDecompressionFailed),
     }
-
-    Ok(result)
 }
 
-/// Negate a G1 point (flip y-coordinate in the field)
-/// For BN254: -P = (x, p - y) where p is the field modulus
-fn negate_g1_point(point: &[u8; 64]) -> Result<[u8; 64]> {
-    // BN254 field modulus p
-    let p: [u8; 32] = [
-        0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29,
-        0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
-        0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d,
-        0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
-    ];
-
-    let mut result = *point;
-    
-    // y-coordinate is in bytes 32-63
-    let y = &point[32..64];
-    
-    // Compute p - y (big-endian subtraction)
-    let neg_y = field_sub(&p, y)?;
-    result[32..64].copy_from_slice(&neg_y);
+// ============================================================================
+// VERIFICATION KEY VALIDATION
+// ============================================================================
 
-    Ok(result)
+/// Fail with InvalidCurvePoint unless `point` is on y^2 = x^3 + 3 (or is
+/// the point at infinity)
+pub fn validate_g1_point(point: &[u8; 64]) -> Result<()> {
+    require!(is_valid_g1(point), VerifierError::InvalidCurvePoint);
+    Ok(())
 }
 
-/// Subtract two field elements (big-endian): a - b mod p
-fn field_sub(a: &[u8; 32], b: &[u8]) -> Result<[u8; 32]> {
-    let mut result = [0u8; 32];
-    let mut borrow: i16 = 0;
+/// Fail with InvalidCurvePoint unless `point` is on the twist
+/// y^2 = x^3 + 3 / (9 + u) and in its prime-order subgroup
+pub fn validate_g2_point(point: &[u8; 128]) -> Result<()> {
+    require!(is_valid_g2(point), VerifierError::InvalidCurvePoint);
+    Ok(())
+}
 
-    // Subtract from least significant byte
-    for i in (0..32).rev() {
-        let b_val = if i < b.len() { b[i] as i16 } else { 0 };
-        let diff = (a[i] as i16) - b_val - borrow;
-        
-        if diff < 0 {
-            result[i] = (diff + 256) as u8;
-            borrow = 1;
-        } else {
-            result[i] = diff as u8;
-            borrow = 0;
+/// A key whose alpha point is all zeroes has not been generated yet
+/// 
+/// Such a key must never verify. With gamma == delta, as in an all-zero or
+/// generator placeholder, A = alpha, B = beta, C = -vk_x passes for any
+/// public inputs.
+pub const fn vk_is_generated(vk: &VerificationKey) -> bool {
+    let mut i = 0;
+    while i < vk.alpha_g1.len() {
+        if vk.alpha_g1[i] != 0 {
+            return true;
         }
+        i += 1;
     }
+    false
+}
 
-    Ok(result)
+/// Validate every point of a verification key
+pub fn validate_vk(vk: &VerificationKey) -> VkValidationResult {
+    VkValidationResult {
+        alpha_valid: is_valid_g1(&vk.alpha_g1),
+        beta_valid: is_valid_g2(&vk.beta_g2),
+        gamma_valid: is_valid_g2(&vk.gamma_g2),
+        delta_valid: is_valid_g2(&vk.delta_g2),
+        ic_all_valid: !vk.ic.is_empty() && vk.ic.iter().all(is_valid_g1),
+    }
 }
 
 // ============================================================================
 // VERIFICATION KEYS (FROM TRUSTED SETUP)
 // ============================================================================
 
-/// Get verification key for withdrawal circuit
+// NOTE: These are placeholder values
+// In production, replace with actual verification key from:
+// circuits/build/withdraw_verification_key.json
+
+/// IC points of the withdrawal key (6 points for 5 public inputs)
+const WITHDRAW_IC: [[u8; 64]; 6] = [
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+];
+
+/// Verification key for the withdrawal circuit
 /// These values come from the trusted setup ceremony
-fn get_withdraw_verification_key() -> VerificationKey {
-    // NOTE: These are placeholder values
-    // In production, replace with actual verification key from:
-    // circuits/build/withdraw_verification_key.json
-    
-    VerificationKey {
-        // Alpha G1 point
-        alpha_g1: hex_to_g1("0x2d4d9aa7e302d9df41749d5507949d05dbea33fbb16c643b22f599a2be6df2e214bedd503c37ceb061d8ec60209fe345ce89830a19230301f076caff004d1926"),
-        
-        // Beta G2 point  
-        beta_g2: hex_to_g2("0x0967032fcbf776d1afc985f88877f182d38480a653f2decaa9794cbc3bf3060c0e187847ad4c798374d0d6732bf501847dd68bc0e071241e0213bc7fc13db7ab304cfbd1e08a704a99f5e847d93f8c3caafddec46b7a0d379da69a4d112346a71739c1b1a457a8c7313123d24d2f9192f896b7c63eea05a9d57f06547ad0cec8"),
-        
-        // Gamma G2 point
-        gamma_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
-        
-        // Delta G2 point
-        delta_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
-        
-        // IC points (6 points for 5 public inputs)
-        ic: vec![
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-        ],
-    }
-}
-
-/// Get verification key for deposit circuit
-fn get_deposit_verification_key() -> VerificationKey {
-    // Deposit circuit has 2 public inputs
-    VerificationKey {
-        alpha_g1: hex_to_g1("0x2d4d9aa7e302d9df41749d5507949d05dbea33fbb16c643b22f599a2be6df2e214bedd503c37ceb061d8ec60209fe345ce89830a19230301f076caff004d1926"),
-        beta_g2: hex_to_g2("0x0967032fcbf776d1afc985f88877f182d38480a653f2decaa9794cbc3bf3060c0e187847ad4c798374d0d6732bf501847dd68bc0e071241e0213bc7fc13db7ab304cfbd1e08a704a99f5e847d93f8c3caafddec46b7a0d379da69a4d112346a71739c1b1a457a8c7313123d24d2f9192f896b7c63eea05a9d57f06547ad0cec8"),
-        gamma_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
-        delta_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
-        ic: vec![
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-            hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
-        ],
-    }
-}
+const WITHDRAW_VERIFICATION_KEY: VerificationKey<'static> = VerificationKey {
+    alpha_g1: hex_to_g1("0x2d4d9aa7e302d9df41749d5507949d05dbea33fbb16c643b22f599a2be6df2e214bedd503c37ceb061d8ec60209fe345ce89830a19230301f076caff004d1926"),
+    beta_g2: hex_to_g2("0x0967032fcbf776d1afc985f88877f182d38480a653f2decaa9794cbc3bf3060c0e187847ad4c798374d0d6732bf501847dd68bc0e071241e0213bc7fc13db7ab304cfbd1e08a704a99f5e847d93f8c3caafddec46b7a0d379da69a4d112346a71739c1b1a457a8c7313123d24d2f9192f896b7c63eea05a9d57f06547ad0cec8"),
+    gamma_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
+    delta_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
+    ic: &WITHDRAW_IC,
+};
+
+/// The withdrawal key with its pairing input laid out at compile time
+const WITHDRAW_PREPARED_KEY: PreparedVerificationKey<'static> = WITHDRAW_VERIFICATION_KEY.prepare();
+
+/// IC points of the deposit key (3 points for 2 public inputs)
+const DEPOSIT_IC: [[u8; 64]; 3] = [
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+    hex_to_g1("0x0000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000200"),
+];
+
+/// Verification key for the deposit circuit
+const DEPOSIT_VERIFICATION_KEY: VerificationKey<'static> = VerificationKey {
+    alpha_g1: hex_to_g1("0x2d4d9aa7e302d9df41749d5507949d05dbea33fbb16c643b22f599a2be6df2e214bedd503c37ceb061d8ec60209fe345ce89830a19230301f076caff004d1926"),
+    beta_g2: hex_to_g2("0x0967032fcbf776d1afc985f88877f182d38480a653f2decaa9794cbc3bf3060c0e187847ad4c798374d0d6732bf501847dd68bc0e071241e0213bc7fc13db7ab304cfbd1e08a704a99f5e847d93f8c3caafddec46b7a0d379da69a4d112346a71739c1b1a457a8c7313123d24d2f9192f896b7c63eea05a9d57f06547ad0cec8"),
+    gamma_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
+    delta_g2: hex_to_g2("0x198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
+    ic: &DEPOSIT_IC,
+};
+
+/// The deposit key with its pairing input laid out at compile time
+const DEPOSIT_PREPARED_KEY: PreparedVerificationKey<'static> = DEPOSIT_VERIFICATION_KEY.prepare();
+
+/// Verification key for the batch_withdraw circuit (four notes per proof)
+/// 
+/// All zeroes until the batch_withdraw trusted setup is run and its key is
+/// pasted here, as in whistle-pool's groth16.rs; verification fails with
+/// VerifyingKeyNotGenerated until then.
+const BATCH_WITHDRAW_VERIFICATION_KEY: VerificationKey<'static> = VerificationKey {
+    alpha_g1: [0u8; 64],
+    beta_g2: [0u8; 128],
+    gamma_g2: [0u8; 128],
+    delta_g2: [0u8; 128],
+    ic: &[[0u8; 64]; 12],
+};
+
+/// The batch withdrawal key with its pairing input laid out at compile time
+const BATCH_WITHDRAW_PREPARED_KEY: PreparedVerificationKey<'static> = BATCH_WITHDRAW_VERIFICATION_KEY.prepare();
 
 // ============================================================================
 // HELPER FUNCTIONS
 // ============================================================================
 
-/// Convert hex string to G1 point bytes
-fn hex_to_g1(hex: &str) -> [u8; 64] {
-    let hex = hex.trim_start_matches("0x");
-    let mut bytes = [0u8; 64];
-    for i in 0..64 {
-        bytes[i] = u8::from_str_radix(&hex[i*2..i*2+2], 16).unwrap_or(0);
+/// Convert hex string to G1 point bytes (at compile time)
+const fn hex_to_g1(hex: &str) -> [u8; 64] {
+    hex_to_bytes(hex)
+}
+
+/// Convert hex string to G2 point bytes (at compile time)
+const fn hex_to_g2(hex: &str) -> [u8; 128] {
+    hex_to_bytes(hex)
+}
+
+/// Decode the first N bytes of a "0x"-prefixed hex string
+const fn hex_to_bytes<const N: usize>(hex: &str) -> [u8; N] {
+    let hex = hex.as_bytes();
+    let offset = if hex.len() >= 2 && hex[0] == b'0' && hex[1] == b'x' { 2 } else { 0 };
+    let mut bytes = [0u8; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = (hex_digit(hex[offset + 2 * i]) << 4) | hex_digit(hex[offset + 2 * i + 1]);
+        i += 1;
     }
     bytes
 }
 
-/// Convert hex string to G2 point bytes
-fn hex_to_g2(hex: &str) -> [u8; 128] {
-    let hex = hex.trim_start_matches("0x");
-    let mut bytes = [0u8; 128];
-    for i in 0..128 {
-        bytes[i] = u8::from_str_radix(&hex[i*2..i*2+2], 16).unwrap_or(0);
+const fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("invalid hex digit in verification key"),
     }
-    bytes
 }
 
 // ============================================================================
@@ -331,4 +425,25 @@ pub enum VerifierError {
     
     #[msg("Point addition failed")]
     PointAdditionFailed,
+    
+    #[msg("Verification key point is not on its curve")]
+    VkValidationFailed,
+    
+    #[msg("Unknown circuit")]
+    UnknownCircuit,
+    
+    #[msg("Public input is not a canonical field element")]
+    NonCanonicalPublicInput,
+    
+    #[msg("Proof A y-coordinate is above the base field modulus")]
+    FieldSubtractBorrow,
+    
+    #[msg("Compressed proof point does not decompress")]
+    PointDecompressionFailed,
+    
+    #[msg("Proof or verification key point is not on its curve")]
+    InvalidCurvePoint,
+    
+    #[msg("Verification key has not been generated by the trusted setup")]
+    VerifyingKeyNotGenerated,
 }
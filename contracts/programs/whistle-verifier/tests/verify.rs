@@ -0,0 +1,185 @@
+//! Verifier logic as plain Rust tests, without solana-program-test or a
+//! validator. solana-program's alt_bn128 wrappers run on ark-bn254 when not
+//! built for the Solana target, so the pairing, multiplication and addition
+//! the program relies on execute natively here and are checked against
+//! arkworks.
+//!
+//! cargo test -p whistle-verifier
+
+use ark_bn254::{Bn254, Fr, G1Affine};
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_groth16::{Groth16, Proof};
+use ark_relations::lc;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError, Variable};
+use ark_snark::SNARK;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+use whistle_groth16::host::{fr_to_be, g1_to_bytes, g2_to_bytes, OwnedVerificationKey};
+use whistle_groth16::{negate_g1, prepare_inputs};
+use whistle_verifier::{validate_g1_point, validate_g2_point, verify_groth16_proof, VerificationKey, VerifierError};
+
+/// Knowledge of x with x^2 = square and x^3 + x + 5 = out (both public)
+#[derive(Clone)]
+struct Cubic {
+    x: Option<Fr>,
+}
+
+impl ConstraintSynthesizer<Fr> for Cubic {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let square_value = self.x.map(|x| x * x);
+        let cube_value = self.x.map(|x| x * x * x);
+        let out_value = self.x.map(|x| x * x * x + x + Fr::from(5u64));
+
+        let square = cs.new_input_variable(|| square_value.ok_or(SynthesisError::AssignmentMissing))?;
+        let out = cs.new_input_variable(|| out_value.ok_or(SynthesisError::AssignmentMissing))?;
+        let x = cs.new_witness_variable(|| self.x.ok_or(SynthesisError::AssignmentMissing))?;
+        let cube = cs.new_witness_variable(|| cube_value.ok_or(SynthesisError::AssignmentMissing))?;
+
+        cs.enforce_constraint(lc!() + x, lc!() + x, lc!() + square)?;
+        cs.enforce_constraint(lc!() + square, lc!() + x, lc!() + cube)?;
+        cs.enforce_constraint(lc!() + cube + x + (Fr::from(5u64), Variable::One), lc!() + Variable::One, lc!() + out)?;
+        Ok(())
+    }
+}
+
+struct Fixture {
+    ark_vk: ark_groth16::VerifyingKey<Bn254>,
+    vk: OwnedVerificationKey,
+    proof: Proof<Bn254>,
+    inputs: [[u8; 32]; 2],
+}
+
+/// Proof for x = 3: square = 9, out = 35
+fn fixture() -> Fixture {
+    let mut rng = StdRng::seed_from_u64(11);
+    let (pk, ark_vk) = Groth16::<Bn254>::circuit_specific_setup(Cubic { x: None }, &mut rng).unwrap();
+    let proof = Groth16::<Bn254>::prove(&pk, Cubic { x: Some(Fr::from(3u64)) }, &mut rng).unwrap();
+
+    Fixture {
+        vk: OwnedVerificationKey::from(&ark_vk),
+        ark_vk,
+        proof,
+        inputs: [fr_to_be(&Fr::from(9u64)), fr_to_be(&Fr::from(35u64))],
+    }
+}
+
+fn verify(f: &Fixture, proof: &Proof<Bn254>, inputs: &[[u8; 32]]) -> anchor_lang::Result<bool> {
+    verify_groth16_proof(
+        &g1_to_bytes(&proof.a),
+        &g2_to_bytes(&proof.b),
+        &g1_to_bytes(&proof.c),
+        inputs,
+        &f.vk.key().prepare(),
+    )
+}
+
+#[test]
+fn test_verify_valid_groth16_proof() {
+    let f = fixture();
+    assert!(Groth16::<Bn254>::verify(&f.ark_vk, &[Fr::from(9u64), Fr::from(35u64)], &f.proof).unwrap());
+    assert_eq!(verify(&f, &f.proof, &f.inputs), Ok(true));
+}
+
+#[test]
+fn test_verify_invalid_proof_fails() {
+    let f = fixture();
+
+    // Wrong public input
+    let inputs = [fr_to_be(&Fr::from(9u64)), fr_to_be(&Fr::from(36u64))];
+    assert_eq!(verify(&f, &f.proof, &inputs), Ok(false));
+
+    // A proof for another statement (x = 4) against these inputs
+    let mut rng = StdRng::seed_from_u64(12);
+    let (pk, _) = Groth16::<Bn254>::circuit_specific_setup(Cubic { x: None }, &mut rng).unwrap();
+    let other = Groth16::<Bn254>::prove(&pk, Cubic { x: Some(Fr::from(4u64)) }, &mut rng).unwrap();
+    assert_eq!(verify(&f, &other, &f.inputs), Ok(false));
+
+    // Swapped A and C still lie on the curve but do not verify
+    let swapped = Proof { a: f.proof.c, b: f.proof.b, c: f.proof.a };
+    assert_eq!(verify(&f, &swapped, &f.inputs), Ok(false));
+
+    // A C that is not on the curve is rejected before the pairing
+    let mut c = g1_to_bytes(&f.proof.c);
+    c[63] ^= 1;
+    let result = verify_groth16_proof(&g1_to_bytes(&f.proof.a), &g2_to_bytes(&f.proof.b), &c, &f.inputs, &f.vk.key().prepare());
+    assert_eq!(result, Err(VerifierError::InvalidCurvePoint.into()));
+
+    // One input too many for the key
+    let inputs = [f.inputs[0], f.inputs[1], fr_to_be(&Fr::from(1u64))];
+    assert_eq!(verify(&f, &f.proof, &inputs), Err(VerifierError::InvalidVerificationKey.into()));
+}
+
+#[test]
+fn test_compute_linear_combination() {
+    let f = fixture();
+    let pvk = Groth16::<Bn254>::process_vk(&f.ark_vk).unwrap();
+
+    for inputs in [[0u64, 0], [9, 35], [1, u64::MAX]] {
+        let scalars = inputs.map(Fr::from);
+        let expected = Groth16::<Bn254>::prepare_inputs(&pvk, &scalars).unwrap().into_affine();
+        let vk_x = prepare_inputs(&f.vk.key(), &scalars.map(|s| fr_to_be(&s))).unwrap();
+        assert_eq!(vk_x, g1_to_bytes(&expected), "inputs {:?}", inputs);
+    }
+}
+
+#[test]
+fn test_negate_g1_point() {
+    let f = fixture();
+    for point in [f.proof.a, f.proof.c, G1Affine::generator()] {
+        let negated = negate_g1(&g1_to_bytes(&point)).unwrap();
+        assert_eq!(negated, g1_to_bytes(&-point));
+        assert_eq!(negate_g1(&negated).unwrap(), g1_to_bytes(&point));
+    }
+
+    // The point at infinity is its own negation
+    assert_eq!(negate_g1(&[0u8; 64]), Ok([0u8; 64]));
+    assert_eq!(g1_to_bytes(&G1Affine::zero()), [0u8; 64]);
+}
+
+#[test]
+fn test_points_off_the_curve_are_rejected() {
+    let f = fixture();
+    let (a, b, c) = (g1_to_bytes(&f.proof.a), g2_to_bytes(&f.proof.b), g1_to_bytes(&f.proof.c));
+    for point in [a, c, g1_to_bytes(&G1Affine::generator()), [0u8; 64]] {
+        assert_eq!(validate_g1_point(&point), Ok(()));
+    }
+    assert_eq!(validate_g2_point(&b), Ok(()));
+
+    let mut bad_a = a;
+    bad_a[63] ^= 1;
+    assert_eq!(validate_g1_point(&bad_a), Err(VerifierError::InvalidCurvePoint.into()));
+    assert_eq!(verify_groth16_proof(&bad_a, &b, &c, &f.inputs, &f.vk.key().prepare()), Err(VerifierError::InvalidCurvePoint.into()));
+
+    let mut bad_b = b;
+    bad_b[127] ^= 1;
+    assert_eq!(validate_g2_point(&bad_b), Err(VerifierError::InvalidCurvePoint.into()));
+
+    // A key with an IC point off the curve fails every proof the same way
+    let mut ic = f.vk.key().ic.to_vec();
+    ic[1][63] ^= 1;
+    let vk = VerificationKey { ic: &ic, ..f.vk.key() };
+    assert_eq!(verify_groth16_proof(&a, &b, &c, &f.inputs, &vk.prepare()), Err(VerifierError::InvalidCurvePoint.into()));
+}
+
+#[test]
+fn test_ungenerated_key_verifies_nothing() {
+    let f = fixture();
+    let key = f.vk.key();
+
+    // With gamma == delta, A = alpha, B = beta, C = -vk_x passes for any
+    // public inputs: the forgery a placeholder key allows
+    let forgeable = VerificationKey { gamma_g2: key.delta_g2, ..key };
+    let forged_c = negate_g1(&prepare_inputs(&forgeable, &f.inputs).unwrap()).unwrap();
+    let forged = |vk: &VerificationKey| {
+        verify_groth16_proof(&vk.alpha_g1, &vk.beta_g2, &forged_c, &f.inputs, &vk.prepare())
+    };
+    assert_eq!(forged(&forgeable), Ok(true));
+
+    // A key whose alpha is all zeroes has not been through the trusted
+    // setup and is refused before any pairing
+    let placeholder = VerificationKey { alpha_g1: [0u8; 64], ..forgeable };
+    assert_eq!(forged(&placeholder), Err(VerifierError::VerifyingKeyNotGenerated.into()));
+    let proof = (g1_to_bytes(&f.proof.a), g2_to_bytes(&f.proof.b), g1_to_bytes(&f.proof.c));
+    let result = verify_groth16_proof(&proof.0, &proof.1, &proof.2, &f.inputs, &placeholder.prepare());
+    assert_eq!(result, Err(VerifierError::VerifyingKeyNotGenerated.into()));
+}
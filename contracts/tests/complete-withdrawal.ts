@@ -154,12 +154,12 @@ async function main() {
     console.log("\n--- STEP 4: Compute Merkle Path ---");
     
     // For a fresh tree with single leaf at index 0:
-    // On-chain zeros array is initialized to raw [0u8; 32] at each level
+    // every sibling is an empty subtree, padded with Poseidon zero hashes
     const LEVELS = 16;
     const pathElements: bigint[] = [];
     const pathIndices: number[] = [];
     
-    // On-chain uses raw zeros (not hash of zeros)
+    // Empty subtree of the current level (zero leaf at level 0)
     let zeroValue = 0n;
     
     let currentHash = commitment;
@@ -178,7 +178,7 @@ async function main() {
             currentHash = onchainHash(zeroValue, currentHash);
         }
         currentIndex = Math.floor(currentIndex / 2);
-        // On-chain zeros don't change per level - they're all 0
+        zeroValue = onchainHash(zeroValue, zeroValue);
     }
     
     console.log(`Computed root: ${currentHash}`);
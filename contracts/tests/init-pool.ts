@@ -3,6 +3,7 @@
  */
 
 import { 
+  BPF_LOADER_UPGRADEABLE_PROGRAM_ID,
   Connection, 
   Keypair, 
   PublicKey, 
@@ -46,6 +47,11 @@ async function main() {
   const [merkleTreePda] = PublicKey.findProgramAddressSync([Buffer.from("merkle_tree")], POOL_PROGRAM_ID);
   const [rootsHistoryPda] = PublicKey.findProgramAddressSync([Buffer.from("roots_history")], POOL_PROGRAM_ID);
   const [nullifiersPda] = PublicKey.findProgramAddressSync([Buffer.from("nullifiers")], POOL_PROGRAM_ID);
+  const [denominationConfigPda] = PublicKey.findProgramAddressSync([Buffer.from("denomination_config")], POOL_PROGRAM_ID);
+  const [poolStatsPda] = PublicKey.findProgramAddressSync([Buffer.from("pool_stats")], POOL_PROGRAM_ID);
+  const [depositHistogramPda] = PublicKey.findProgramAddressSync([Buffer.from("deposit_histogram")], POOL_PROGRAM_ID);
+  const [congestionPda] = PublicKey.findProgramAddressSync([Buffer.from("congestion")], POOL_PROGRAM_ID);
+  const [programDataPda] = PublicKey.findProgramAddressSync([POOL_PROGRAM_ID.toBuffer()], BPF_LOADER_UPGRADEABLE_PROGRAM_ID);
 
   console.log("\nPDAs:");
   console.log("  Pool:", poolPda.toBase58());
@@ -53,6 +59,10 @@ async function main() {
   console.log("  MerkleTree:", merkleTreePda.toBase58());
   console.log("  RootsHistory:", rootsHistoryPda.toBase58());
   console.log("  Nullifiers:", nullifiersPda.toBase58());
+  console.log("  DenominationConfig:", denominationConfigPda.toBase58());
+  console.log("  PoolStats:", poolStatsPda.toBase58());
+  console.log("  DepositHistogram:", depositHistogramPda.toBase58());
+  console.log("  Congestion:", congestionPda.toBase58());
 
   // Check if already initialized
   const poolAccount = await connection.getAccountInfo(poolPda);
@@ -65,16 +75,26 @@ async function main() {
   console.log("\nInitializing pool...");
 
   // Step 1: Initialize Pool
-  console.log("\n[1/4] Initialize Pool State...");
+  // Must be signed by the program's upgrade authority
+  console.log("\n[1/8] Initialize Pool State...");
   const initDiscrim = getDiscriminator("initialize");
   const merkleLevels = Buffer.alloc(1);
   merkleLevels.writeUInt8(7); // 7 levels = 128 leaves
 
-  const initData = Buffer.concat([initDiscrim, merkleLevels]);
+  // Schnorr withdrawals for small notes: off unless explicitly requested
+  const allowSchnorrForSmall = Buffer.from([process.env.ALLOW_SCHNORR_FOR_SMALL === "1" ? 1 : 0]);
+
+  // Deposit caps in lamports, fixed at genesis (0 = unlimited)
+  const depositCaps = Buffer.alloc(16);
+  depositCaps.writeBigUInt64LE(BigInt(process.env.MAX_DEPOSITS_PER_ADDRESS || "0"), 0);
+  depositCaps.writeBigUInt64LE(BigInt(process.env.MAX_POOL_SHIELDED || "0"), 8);
+
+  const initData = Buffer.concat([initDiscrim, merkleLevels, allowSchnorrForSmall, depositCaps]);
 
   const initIx = new TransactionInstruction({
     keys: [
       { pubkey: poolPda, isSigner: false, isWritable: true },
+      { pubkey: programDataPda, isSigner: false, isWritable: false },
       { pubkey: walletKeypair.publicKey, isSigner: true, isWritable: true },
       { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
     ],
@@ -94,7 +114,7 @@ async function main() {
   await new Promise(r => setTimeout(r, 2000));
 
   // Step 2: Initialize Merkle Tree
-  console.log("\n[2/4] Initialize Merkle Tree...");
+  console.log("\n[2/8] Initialize Merkle Tree...");
   const initMerkleDiscrim = getDiscriminator("init_merkle");
 
   const initMerkleIx = new TransactionInstruction({
@@ -120,7 +140,7 @@ async function main() {
   await new Promise(r => setTimeout(r, 2000));
 
   // Step 3: Initialize Roots History
-  console.log("\n[3/4] Initialize Roots History...");
+  console.log("\n[3/8] Initialize Roots History...");
   const initRootsDiscrim = getDiscriminator("init_roots");
 
   const initRootsIx = new TransactionInstruction({
@@ -146,7 +166,7 @@ async function main() {
   await new Promise(r => setTimeout(r, 2000));
 
   // Step 4: Initialize Nullifiers
-  console.log("\n[4/4] Initialize Nullifiers...");
+  console.log("\n[4/8] Initialize Nullifiers...");
   const initNullifiersDiscrim = getDiscriminator("init_nullifiers");
 
   const initNullifiersIx = new TransactionInstruction({
@@ -169,6 +189,110 @@ async function main() {
     if (e.logs) e.logs.forEach((l: string) => console.log("    ", l));
   }
 
+  await new Promise(r => setTimeout(r, 2000));
+
+  // Step 5: Initialize Denomination Config (relayer fee caps, fixed forever)
+  // Must be signed by the program's upgrade authority
+  console.log("\n[5/8] Initialize Denomination Config...");
+  const initDenomsDiscrim = getDiscriminator("init_denominations");
+  // Caps in bps for 0.01, 0.05, 0.1, 1, 10, 100 SOL
+  const feeCapsBps = [1000, 1000, 500, 300, 100, 100];
+  const feeCapsData = Buffer.alloc(2 * feeCapsBps.length);
+  feeCapsBps.forEach((cap, i) => feeCapsData.writeUInt16LE(cap, i * 2));
+
+  const initDenomsIx = new TransactionInstruction({
+    keys: [
+      { pubkey: poolPda, isSigner: false, isWritable: false },
+      { pubkey: denominationConfigPda, isSigner: false, isWritable: true },
+      { pubkey: programDataPda, isSigner: false, isWritable: false },
+      { pubkey: walletKeypair.publicKey, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: POOL_PROGRAM_ID,
+    data: Buffer.concat([initDenomsDiscrim, feeCapsData]),
+  });
+
+  try {
+    const tx5 = new Transaction().add(initDenomsIx);
+    const sig5 = await sendAndConfirmTransaction(connection, tx5, [walletKeypair]);
+    console.log("  ✅ Denomination config initialized:", sig5);
+  } catch (e: any) {
+    console.log("  Error:", e.message);
+    if (e.logs) e.logs.forEach((l: string) => console.log("    ", l));
+  }
+
+  await new Promise(r => setTimeout(r, 2000));
+
+  // Step 6: Initialize Pool Stats (anonymity metrics)
+  console.log("\n[6/8] Initialize Pool Stats...");
+  const initStatsIx = new TransactionInstruction({
+    keys: [
+      { pubkey: poolPda, isSigner: false, isWritable: false },
+      { pubkey: poolStatsPda, isSigner: false, isWritable: true },
+      { pubkey: walletKeypair.publicKey, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: POOL_PROGRAM_ID,
+    data: getDiscriminator("init_pool_stats"),
+  });
+
+  try {
+    const tx6 = new Transaction().add(initStatsIx);
+    const sig6 = await sendAndConfirmTransaction(connection, tx6, [walletKeypair]);
+    console.log("  ✅ Pool stats initialized:", sig6);
+  } catch (e: any) {
+    console.log("  Error:", e.message);
+    if (e.logs) e.logs.forEach((l: string) => console.log("    ", l));
+  }
+
+  await new Promise(r => setTimeout(r, 2000));
+
+  // Step 7: Initialize Deposit Histogram
+  console.log("\n[7/8] Initialize Deposit Histogram...");
+  const initHistogramIx = new TransactionInstruction({
+    keys: [
+      { pubkey: poolPda, isSigner: false, isWritable: false },
+      { pubkey: depositHistogramPda, isSigner: false, isWritable: true },
+      { pubkey: walletKeypair.publicKey, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: POOL_PROGRAM_ID,
+    data: getDiscriminator("init_deposit_histogram"),
+  });
+
+  try {
+    const tx7 = new Transaction().add(initHistogramIx);
+    const sig7 = await sendAndConfirmTransaction(connection, tx7, [walletKeypair]);
+    console.log("  ✅ Deposit histogram initialized:", sig7);
+  } catch (e: any) {
+    console.log("  Error:", e.message);
+    if (e.logs) e.logs.forEach((l: string) => console.log("    ", l));
+  }
+
+  await new Promise(r => setTimeout(r, 2000));
+
+  // Step 8: Initialize Congestion Stats
+  console.log("\n[8/8] Initialize Congestion Stats...");
+  const initCongestionIx = new TransactionInstruction({
+    keys: [
+      { pubkey: poolPda, isSigner: false, isWritable: false },
+      { pubkey: congestionPda, isSigner: false, isWritable: true },
+      { pubkey: walletKeypair.publicKey, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: POOL_PROGRAM_ID,
+    data: getDiscriminator("init_congestion_stats"),
+  });
+
+  try {
+    const tx8 = new Transaction().add(initCongestionIx);
+    const sig8 = await sendAndConfirmTransaction(connection, tx8, [walletKeypair]);
+    console.log("  ✅ Congestion stats initialized:", sig8);
+  } catch (e: any) {
+    console.log("  Error:", e.message);
+    if (e.logs) e.logs.forEach((l: string) => console.log("    ", l));
+  }
+
   console.log("\n" + "=".repeat(60));
   console.log("INITIALIZATION COMPLETE");
   console.log("=".repeat(60));
@@ -0,0 +1,182 @@
+/**
+ * WHISTLE PROTOCOL - ADDRESS LOOKUP TABLE UNSHIELD TEST
+ *
+ * Runs against a local validator by default (RPC=... to override) with the
+ * pool already initialized.
+ *
+ * This test demonstrates:
+ * 1. Creating a lookup table with every static Whistle address
+ * 2. Verifying the table contents against the current PDA set
+ * 3. Shielding a note, then landing a v0 unshield through the table
+ * 4. Falling back to a legacy transaction when the table is unavailable
+ */
+
+import { Connection, Keypair, LAMPORTS_PER_SOL, PublicKey, Transaction, VersionedTransaction } from "@solana/web3.js";
+import * as crypto from "crypto";
+import * as fs from "fs";
+import * as path from "path";
+// @ts-ignore
+import { groth16 } from "snarkjs";
+// @ts-ignore
+import { buildPoseidon } from "circomlibjs";
+import { WhistleClient } from "../../sdk/src/client";
+import { TransactionBuilder, encodeProof } from "../../sdk/src/transactionBuilder";
+import { decimalStrToBeBytes } from "../../sdk/src/publicInputs";
+import {
+  createWhistleLookupTable,
+  sendBuiltTransaction,
+  verifyWhistleLookupTable,
+} from "../../sdk/src/lookupTable";
+
+// Program ID
+const POOL_PROGRAM_ID = new PublicKey("AMtxCTW99zCBfhukVdN8YvA3AsdSJ7nsgnUdHpth7QTD");
+
+const FIELD_PRIME = BigInt('21888242871839275222246405745257275088548364400416034343698204186575808495617');
+
+const DEPOSIT = BigInt(0.025 * LAMPORTS_PER_SOL);
+const DENOMINATION = BigInt(0.01 * LAMPORTS_PER_SOL);
+
+function randomField(): bigint {
+  return BigInt('0x' + crypto.randomBytes(31).toString('hex')) % FIELD_PRIME;
+}
+
+async function main() {
+  console.log("=".repeat(70));
+  console.log("WHISTLE PROTOCOL - ADDRESS LOOKUP TABLE UNSHIELD TEST");
+  console.log("=".repeat(70));
+
+  const walletPath = process.env.WALLET || "../keys/deploy-wallet.json";
+  const wallet = Keypair.fromSecretKey(
+    new Uint8Array(JSON.parse(fs.readFileSync(walletPath, "utf-8")))
+  );
+  const connection = new Connection(process.env.RPC || "http://127.0.0.1:8899", "confirmed");
+  const client = new WhistleClient({ connection, wallet, programId: POOL_PROGRAM_ID });
+
+  const poseidon = await buildPoseidon();
+  const F = poseidon.F;
+  const hash = (a: bigint, b: bigint) => BigInt(F.toString(poseidon([F.e(a.toString()), F.e(b.toString())])));
+
+  // 1. Create the lookup table
+  console.log("\n1. Creating lookup table...");
+  const lookupTable = await createWhistleLookupTable(connection, wallet, POOL_PROGRAM_ID);
+  console.log(`   ✓ ${lookupTable.toBase58()}`);
+
+  // 2. Verify contents
+  const check = await verifyWhistleLookupTable(connection, lookupTable, POOL_PROGRAM_ID);
+  if (check.missing.length > 0 || check.extra.length > 0) {
+    throw new Error(`Lookup table mismatch: ${check.missing.length} missing, ${check.extra.length} extra`);
+  }
+  console.log("\n2. ✓ Table holds exactly the current Whistle address set");
+
+  // Extended addresses become usable from the next slot
+  const extendedAt = await connection.getSlot();
+  while ((await connection.getSlot()) <= extendedAt) {
+    await new Promise((resolve) => setTimeout(resolve, 400));
+  }
+
+  const builder = new TransactionBuilder(POOL_PROGRAM_ID, lookupTable);
+  const legacyBuilder = new TransactionBuilder(POOL_PROGRAM_ID);
+
+  // 3. Shield a note
+  console.log("\n3. Shielding", Number(DEPOSIT) / LAMPORTS_PER_SOL, "SOL...");
+  const secret = randomField();
+  const nullifier = randomField();
+  const commitment = hash(secret, hash(nullifier, DEPOSIT));
+  const nullifierHash = hash(nullifier, BigInt(0));
+
+  const leafIndex = (await client.getPoolState()).nextIndex;
+  const shieldTx = await builder.compile(
+    connection,
+    wallet.publicKey,
+    builder.shield(wallet.publicKey, decimalStrToBeBytes(commitment), DEPOSIT, BigInt(leafIndex))
+  );
+  console.log(`   ✓ ${await sendBuiltTransaction(connection, shieldTx, [wallet])}`);
+
+  // 4. Prove and unshield through the table
+  console.log("\n4. Unshielding", Number(DENOMINATION) / LAMPORTS_PER_SOL, "SOL with a v0 transaction...");
+  const poolData = (await connection.getAccountInfo(builder.pda("pool")))!.data;
+  const treeData = (await connection.getAccountInfo(builder.pda("merkle_tree")))!.data;
+  const levels = poolData.readUInt8(8);
+  const merkleRoot = poolData.subarray(17, 49);
+
+  const pathElements: string[] = [];
+  const pathIndices: string[] = [];
+  let current = (1 << levels) - 1 + leafIndex;
+  for (let level = 0; level < levels; level++) {
+    const isLeft = current % 2 === 1;
+    const sibling = isLeft ? current + 1 : current - 1;
+    const start = 16 + sibling * 32;
+    pathElements.push(BigInt('0x' + treeData.subarray(start, start + 32).toString('hex')).toString());
+    pathIndices.push(isLeft ? '0' : '1');
+    current = Math.floor((current - 1) / 2);
+  }
+
+  const changeAmount = DEPOSIT - DENOMINATION;
+  const changeSecret = randomField();
+  const changeNullifier = randomField();
+  const changeCommitment = hash(changeSecret, hash(changeNullifier, changeAmount));
+
+  const recipientField = Buffer.alloc(32);
+  wallet.publicKey.toBuffer().copy(recipientField, 1, 0, 31);
+
+  const circuitDir = path.join(__dirname, "../../circuits/build/production/unshield_change");
+  const { proof } = await groth16.fullProve(
+    {
+      merkleRoot: BigInt('0x' + merkleRoot.toString('hex')).toString(),
+      nullifierHash: nullifierHash.toString(),
+      recipient: BigInt('0x' + recipientField.toString('hex')).toString(),
+      withdrawalAmount: DENOMINATION.toString(),
+      relayerFee: "0",
+      changeCommitment: changeCommitment.toString(),
+      secret: secret.toString(),
+      nullifier: nullifier.toString(),
+      noteAmount: DEPOSIT.toString(),
+      pathElements,
+      pathIndices,
+      changeSecret: changeSecret.toString(),
+      changeNullifier: changeNullifier.toString(),
+      changeAmount: changeAmount.toString(),
+    },
+    path.join(circuitDir, "unshield_change_js/unshield_change.wasm"),
+    path.join(circuitDir, "unshield_change_final.zkey")
+  );
+
+  const unshield = builder.unshield({
+    proof: encodeProof(proof),
+    nullifierHash: decimalStrToBeBytes(nullifierHash),
+    recipient: wallet.publicKey,
+    withdrawalAmount: DENOMINATION,
+    relayerFee: BigInt(0),
+    merkleRoot,
+    changeCommitment: decimalStrToBeBytes(changeCommitment),
+  }, wallet.publicKey);
+
+  const v0 = await builder.compile(connection, wallet.publicKey, unshield);
+  if (!(v0 instanceof VersionedTransaction) || v0.message.addressTableLookups.length !== 1) {
+    throw new Error("Expected a v0 transaction using the lookup table");
+  }
+  const legacy = await legacyBuilder.compile(connection, wallet.publicKey, unshield);
+  if (!(legacy instanceof Transaction)) {
+    throw new Error("Expected a legacy transaction without a lookup table");
+  }
+  legacy.sign(wallet);
+  v0.sign([wallet]);
+  console.log(`   v0 ${v0.serialize().length} bytes vs legacy ${legacy.serialize().length} bytes`);
+
+  console.log(`   ✓ ${await sendBuiltTransaction(connection, v0, [wallet])}`);
+
+  const [spent] = await client.checkNullifiersSpent([decimalStrToBeBytes(nullifierHash)]);
+  if (!spent) {
+    throw new Error("Nullifier not marked spent after the v0 unshield");
+  }
+  console.log("   ✓ Nullifier spent");
+
+  console.log("\n" + "=".repeat(70));
+  console.log("ADDRESS LOOKUP TABLE UNSHIELD TEST PASSED");
+  console.log("=".repeat(70));
+}
+
+main().catch((e) => {
+  console.error(e);
+  process.exit(1);
+});
@@ -0,0 +1,272 @@
+/**
+ * WHISTLE PROTOCOL - DEVNET SEEDING TEST
+ *
+ * Requires a pool deployed with the `insecure-devnet` feature.
+ *
+ * This test demonstrates:
+ * 1. Seeding the pool with deterministic notes via devnet_seed_pool
+ * 2. Recomputing the seeded notes with the SDK fixtures module
+ * 3. Checking the recomputed commitments against the on-chain tree
+ * 4. Withdrawing one seeded note with a withdraw_merkle proof
+ */
+
+import {
+  Connection,
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  LAMPORTS_PER_SOL,
+  Transaction,
+  TransactionInstruction,
+  sendAndConfirmTransaction
+} from "@solana/web3.js";
+import * as fs from "fs";
+import * as path from "path";
+// @ts-ignore
+import { groth16 } from "snarkjs";
+import { devnetSeededNotes } from "../../sdk/src/fixtures";
+
+// Program ID
+const POOL_PROGRAM_ID = new PublicKey("AMtxCTW99zCBfhukVdN8YvA3AsdSJ7nsgnUdHpth7QTD");
+
+const BN254_BASE_FIELD = BigInt('21888242871839275222246405745257275088696311157297823662689037894645226208583');
+
+const SEED_COUNT = 6;
+
+// Generate Anchor discriminator
+function getDiscriminator(name: string, namespace = "global"): Buffer {
+  const crypto = require("crypto");
+  return crypto.createHash("sha256")
+    .update(`${namespace}:${name}`)
+    .digest()
+    .slice(0, 8);
+}
+
+// Convert bigint to 32-byte big-endian buffer
+function bigintToBytes32(n: bigint): Buffer {
+  const hex = n.toString(16).padStart(64, '0');
+  return Buffer.from(hex, 'hex');
+}
+
+function bytesToBigintBE(bytes: Buffer): bigint {
+  return BigInt('0x' + bytes.toString('hex'));
+}
+
+// Convert G1 point to 64 bytes with negated y (required by groth16-solana)
+function g1ToBytesNegated(point: string[]): Buffer {
+  const x = BigInt(point[0]);
+  const y = BigInt(point[1]) % BN254_BASE_FIELD;
+  const yNeg = y === 0n ? 0n : BN254_BASE_FIELD - y;
+  return Buffer.concat([bigintToBytes32(x), bigintToBytes32(yNeg)]);
+}
+
+// Convert G1 point to 64 bytes
+function g1ToBytes(point: string[]): Buffer {
+  return Buffer.concat([bigintToBytes32(BigInt(point[0])), bigintToBytes32(BigInt(point[1]))]);
+}
+
+// Convert G2 point to 128 bytes (swapped for Solana)
+function g2ToBytesSwapped(point: string[][]): Buffer {
+  const x0 = bigintToBytes32(BigInt(point[0][0]));
+  const x1 = bigintToBytes32(BigInt(point[0][1]));
+  const y0 = bigintToBytes32(BigInt(point[1][0]));
+  const y1 = bigintToBytes32(BigInt(point[1][1]));
+  return Buffer.concat([x1, x0, y1, y0]);
+}
+
+function u64LE(n: bigint): Buffer {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(n);
+  return buf;
+}
+
+async function main() {
+  console.log("=".repeat(70));
+  console.log("WHISTLE PROTOCOL - DEVNET SEEDING TEST");
+  console.log("=".repeat(70));
+
+  // Load wallet
+  const walletPath = process.env.WALLET || "../keys/deploy-wallet.json";
+  const wallet = Keypair.fromSecretKey(
+    new Uint8Array(JSON.parse(fs.readFileSync(walletPath, "utf-8")))
+  );
+  const connection = new Connection("https://api.devnet.solana.com", "confirmed");
+  console.log(`\nWallet: ${wallet.publicKey.toBase58()}`);
+
+  const pda = (seed: string) => PublicKey.findProgramAddressSync([Buffer.from(seed)], POOL_PROGRAM_ID)[0];
+  const poolPda = pda("pool");
+  const merkleTreePda = pda("merkle_tree");
+  const nullifiersPda = pda("nullifiers");
+  const rootsHistoryPda = pda("roots_history");
+  const poolStatsPda = pda("pool_stats");
+  const depositHistogramPda = pda("deposit_histogram");
+  const denominationConfigPda = pda("denomination_config");
+  const vaultPda = pda("vault");
+  const feeVaultPda = pda("fee_vault");
+
+  // ========================================
+  // STEP 1: SEED THE POOL
+  // ========================================
+  console.log("\n1. Seeding pool with", SEED_COUNT, "notes...");
+  // The seed picks the notes, so their commitment markers can be passed up front
+  const seed = BigInt(Date.now());
+  const nextIndex = Number((await connection.getAccountInfo(poolPda))!.data.readBigUInt64LE(9));
+  const plannedNotes = await devnetSeededNotes(seed, nextIndex, SEED_COUNT);
+  const [depositRecordPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("deposit_record"), wallet.publicKey.toBuffer()],
+    POOL_PROGRAM_ID
+  );
+  const page = Buffer.alloc(4);
+  page.writeUInt32LE(Math.floor(nextIndex / 256));
+  const [leafPagePda] = PublicKey.findProgramAddressSync([Buffer.from("leaf_page"), poolPda.toBuffer(), page], POOL_PROGRAM_ID);
+  const markers = plannedNotes.map(
+    (note) => PublicKey.findProgramAddressSync([Buffer.from("commitment"), bigintToBytes32(note.commitment)], POOL_PROGRAM_ID)[0]
+  );
+  const seedIx = new TransactionInstruction({
+    keys: [
+      { pubkey: poolPda, isSigner: false, isWritable: true },
+      { pubkey: merkleTreePda, isSigner: false, isWritable: true },
+      { pubkey: rootsHistoryPda, isSigner: false, isWritable: true },
+      { pubkey: poolStatsPda, isSigner: false, isWritable: true },
+      { pubkey: depositHistogramPda, isSigner: false, isWritable: true },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: feeVaultPda, isSigner: false, isWritable: true },
+      { pubkey: wallet.publicKey, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: depositRecordPda, isSigner: false, isWritable: true },
+      { pubkey: leafPagePda, isSigner: false, isWritable: true },
+      ...markers.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })),
+    ],
+    programId: POOL_PROGRAM_ID,
+    data: Buffer.concat([getDiscriminator("devnet_seed_pool"), Buffer.from([SEED_COUNT]), u64LE(seed)]),
+  });
+  const seedSig = await sendAndConfirmTransaction(connection, new Transaction().add(seedIx), [wallet]);
+  console.log("   TX:", seedSig);
+
+  // Read the DevnetPoolSeeded event
+  const seedTx = await connection.getTransaction(seedSig, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+  const eventDiscriminator = getDiscriminator("DevnetPoolSeeded", "event");
+  const eventData = (seedTx?.meta?.logMessages || [])
+    .filter((log) => log.startsWith("Program data: "))
+    .map((log) => Buffer.from(log.slice("Program data: ".length), "base64"))
+    .find((data) => data.slice(0, 8).equals(eventDiscriminator));
+  if (!eventData) throw new Error("DevnetPoolSeeded event not found");
+
+  const slot = eventData.readBigUInt64LE(8);
+  const eventSeed = eventData.readBigUInt64LE(16);
+  const firstLeafIndex = Number(eventData.readBigUInt64LE(24));
+  const count = eventData.readUInt8(32);
+  console.log(`   Seeded ${count} notes with seed ${eventSeed} at slot ${slot}, leaves ${firstLeafIndex}..${firstLeafIndex + count - 1}`);
+
+  // ========================================
+  // STEP 2: RECOMPUTE NOTES
+  // ========================================
+  console.log("\n2. Recomputing seeded notes...");
+  const notes = await devnetSeededNotes(eventSeed, firstLeafIndex, count);
+
+  const poolAccount = await connection.getAccountInfo(poolPda);
+  const merkleTreeAccount = await connection.getAccountInfo(merkleTreePda);
+  if (!poolAccount || !merkleTreeAccount) throw new Error("Pool or merkle tree missing");
+
+  const merkleLevels = poolAccount.data.readUInt8(8);
+  const merkleRootBytes = poolAccount.data.slice(17, 49);
+  const nodesOffset = 16; // 8 discriminator + 8 header
+  const totalNodes = (1 << (merkleLevels + 1)) - 1;
+  const readNode = (index: number): Buffer => {
+    if (index < 0 || index >= totalNodes) return Buffer.alloc(32);
+    return merkleTreeAccount.data.slice(nodesOffset + index * 32, nodesOffset + (index + 1) * 32);
+  };
+  const leafOffset = (1 << merkleLevels) - 1;
+
+  // ========================================
+  // STEP 3: CHECK COMMITMENTS
+  // ========================================
+  console.log("\n3. Checking commitments against the tree...");
+  for (const note of notes) {
+    const onChain = bytesToBigintBE(readNode(leafOffset + note.leafIndex));
+    if (onChain !== note.commitment) {
+      throw new Error(`Leaf ${note.leafIndex} does not match the recomputed commitment`);
+    }
+  }
+  console.log(`   ✓ All ${notes.length} recomputed commitments match`);
+
+  // ========================================
+  // STEP 4: WITHDRAW ONE SEEDED NOTE
+  // ========================================
+  const note = notes[0];
+  console.log(`\n4. Withdrawing seeded note ${note.leafIndex} (${Number(note.amount) / LAMPORTS_PER_SOL} SOL)...`);
+
+  let currentIndex = leafOffset + note.leafIndex;
+  const pathElements: bigint[] = [];
+  const pathIndices: number[] = [];
+  for (let level = 0; level < merkleLevels; level++) {
+    const isLeft = currentIndex % 2 === 1;
+    const siblingIndex = isLeft ? currentIndex + 1 : currentIndex - 1;
+    pathElements.push(bytesToBigintBE(readNode(siblingIndex)));
+    pathIndices.push(isLeft ? 0 : 1);
+    currentIndex = Math.floor((currentIndex - 1) / 2);
+  }
+
+  const recipientFieldBuf = Buffer.alloc(32);
+  wallet.publicKey.toBuffer().copy(recipientFieldBuf, 1, 0, 31);
+
+  const circuitInput = {
+    merkleRoot: bytesToBigintBE(merkleRootBytes).toString(),
+    nullifierHash: note.nullifierHash.toString(),
+    recipient: bytesToBigintBE(recipientFieldBuf).toString(),
+    amount: note.amount.toString(),
+    relayerFee: "0",
+    secret: note.secret.toString(),
+    nullifier: note.nullifier.toString(),
+    noteAmount: note.amount.toString(),
+    pathElements: pathElements.map(e => e.toString()),
+    pathIndices: pathIndices.map(i => i.toString()),
+  };
+
+  const circuitDir = path.join(__dirname, "../../circuits/build/production");
+  const wasmPath = path.join(circuitDir, "withdraw_merkle/withdraw_merkle_js/withdraw_merkle.wasm");
+  const zkeyPath = path.join(circuitDir, "withdraw_merkle/withdraw_merkle_final.zkey");
+  const { proof } = await groth16.fullProve(circuitInput, wasmPath, zkeyPath);
+  console.log("   ✓ Proof generated");
+
+  const withdrawData = Buffer.concat([
+    getDiscriminator("withdraw"),
+    g1ToBytesNegated(proof.pi_a),
+    g2ToBytesSwapped(proof.pi_b),
+    g1ToBytes(proof.pi_c),
+    bigintToBytes32(note.nullifierHash),
+    wallet.publicKey.toBuffer(),
+    u64LE(note.amount),
+    u64LE(0n),
+    merkleRootBytes,
+  ]);
+
+  const withdrawIx = new TransactionInstruction({
+    keys: [
+      { pubkey: poolPda, isSigner: false, isWritable: true },
+      { pubkey: merkleTreePda, isSigner: false, isWritable: true },
+      { pubkey: nullifiersPda, isSigner: false, isWritable: true },
+      { pubkey: rootsHistoryPda, isSigner: false, isWritable: true },
+      { pubkey: denominationConfigPda, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: feeVaultPda, isSigner: false, isWritable: true },
+      { pubkey: wallet.publicKey, isSigner: false, isWritable: true }, // recipient
+      { pubkey: wallet.publicKey, isSigner: false, isWritable: true }, // relayer
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: POOL_PROGRAM_ID,
+    data: withdrawData,
+  });
+
+  const withdrawSig = await sendAndConfirmTransaction(connection, new Transaction().add(withdrawIx), [wallet]);
+  console.log("   ✓ Withdrawn:", withdrawSig);
+
+  console.log("\n" + "=".repeat(70));
+  console.log("DEVNET SEEDING TEST PASSED");
+  console.log("=".repeat(70));
+}
+
+main().catch((e) => {
+  console.error(e);
+  process.exit(1);
+});
@@ -0,0 +1,118 @@
+/**
+ * WHISTLE PROTOCOL - NULLIFIER LEDGER EXPORT TEST
+ *
+ * Run after a mixed workload (unshield, withdraw_zk, private transfers...).
+ *
+ * This test demonstrates:
+ * 1. Exporting the spent-nullifier ledger through get_spent_nullifiers
+ * 2. Verifying the report signature
+ * 3. Reconciling the ledger against emitted events:
+ *    - every spend event's nullifier is in the ledger, at the event's slot
+ *    - ledger size = single-nullifier spends + private transfer inputs
+ *    - no nullifier appears twice
+ */
+
+import { Connection, Keypair, PublicKey } from "@solana/web3.js";
+import * as fs from "fs";
+import { createHash, createPublicKey, verify } from "crypto";
+import { WhistleClient } from "../../sdk/src/client";
+
+// Program ID
+const POOL_PROGRAM_ID = new PublicKey("AMtxCTW99zCBfhukVdN8YvA3AsdSJ7nsgnUdHpth7QTD");
+
+// Events whose first field is the spent nullifier hash
+const NULLIFIER_EVENTS = ["Unshielded", "WithdrawnZk", "SchnorrWithdrawn"];
+
+// SPKI DER prefix for a raw Ed25519 public key
+const ED25519_SPKI_PREFIX = Buffer.from("302a300506032b6570032100", "hex");
+
+function eventDiscriminator(name: string): Buffer {
+  return createHash("sha256").update(`event:${name}`).digest().slice(0, 8);
+}
+
+async function main() {
+  console.log("=".repeat(70));
+  console.log("WHISTLE PROTOCOL - NULLIFIER LEDGER EXPORT TEST");
+  console.log("=".repeat(70));
+
+  const walletPath = process.env.WALLET || "../keys/deploy-wallet.json";
+  const wallet = Keypair.fromSecretKey(
+    new Uint8Array(JSON.parse(fs.readFileSync(walletPath, "utf-8")))
+  );
+  const connection = new Connection("https://api.devnet.solana.com", "confirmed");
+  const client = new WhistleClient({ connection, wallet, programId: POOL_PROGRAM_ID });
+
+  // 1. Export
+  console.log("\n1. Exporting ledger...");
+  const report = await client.exportNullifierLedger("json");
+  const ledger: { seq: string; slot: string; nullifier: string }[] = JSON.parse(report.content).nullifiers;
+  fs.writeFileSync("nullifier-ledger.json", report.content);
+  console.log(`   Exported ${ledger.length} spent nullifiers → nullifier-ledger.json`);
+
+  // 2. Signature
+  const publicKey = createPublicKey({
+    key: Buffer.concat([ED25519_SPKI_PREFIX, wallet.publicKey.toBuffer()]),
+    format: "der",
+    type: "spki",
+  });
+  if (!verify(null, Buffer.from(report.content), publicKey, Buffer.from(report.signature, "hex"))) {
+    throw new Error("Report signature does not verify");
+  }
+  console.log(`\n2. ✓ Report signed by ${report.signer}`);
+
+  // 3. Reconcile against events
+  console.log("\n3. Reconciling against emitted events...");
+  const byNullifier = new Map(ledger.map((e) => [e.nullifier, e]));
+  if (byNullifier.size !== ledger.length) throw new Error("Duplicate nullifier in ledger");
+
+  const nullifiersPda = PublicKey.findProgramAddressSync([Buffer.from("nullifiers")], POOL_PROGRAM_ID)[0];
+  const signatures = await connection.getSignaturesForAddress(nullifiersPda, { limit: 1000 });
+
+  const discriminators = NULLIFIER_EVENTS.map((name) => ({ name, disc: eventDiscriminator(name) }));
+  const transferDisc = eventDiscriminator("PrivateTransferCompleted");
+  let singleSpends = 0;
+  let transferInputs = 0;
+
+  for (const { signature, err } of signatures) {
+    if (err) continue;
+    const tx = await connection.getTransaction(signature, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+    const logs = tx?.meta?.logMessages || [];
+
+    for (const log of logs) {
+      if (!log.startsWith("Program data: ")) continue;
+      const data = Buffer.from(log.slice("Program data: ".length), "base64");
+      const disc = data.slice(0, 8);
+
+      if (disc.equals(transferDisc)) {
+        transferInputs += data.readUInt8(8);
+        continue;
+      }
+      const match = discriminators.find((d) => d.disc.equals(disc));
+      if (!match) continue;
+
+      const nullifier = data.slice(8, 40).toString("hex");
+      const entry = byNullifier.get(nullifier);
+      if (!entry) throw new Error(`${match.name} nullifier ${nullifier} missing from ledger`);
+      if (BigInt(entry.slot) !== BigInt(tx!.slot)) {
+        throw new Error(`Nullifier ${nullifier}: ledger slot ${entry.slot}, event slot ${tx!.slot}`);
+      }
+      singleSpends++;
+    }
+  }
+
+  if (singleSpends + transferInputs !== ledger.length) {
+    throw new Error(
+      `Ledger has ${ledger.length} entries, events account for ${singleSpends} + ${transferInputs}`
+    );
+  }
+  console.log(`   ✓ ${singleSpends} withdrawal events + ${transferInputs} transfer inputs = ${ledger.length} ledger entries`);
+
+  console.log("\n" + "=".repeat(70));
+  console.log("NULLIFIER LEDGER EXPORT TEST PASSED");
+  console.log("=".repeat(70));
+}
+
+main().catch((e) => {
+  console.error(e);
+  process.exit(1);
+});
@@ -0,0 +1,209 @@
+/**
+ * WHISTLE PROTOCOL - STAGED PROOF UPLOAD TEST
+ *
+ * This test demonstrates:
+ * 1. Uploading an unshield payload to a ProofStaging PDA in three chunks
+ * 2. Rejecting out-of-order chunks (staged bytes cannot be overwritten)
+ * 3. Executing unshield_from_staged, which closes the staging account
+ * 4. Verifying the session cannot be reused
+ *
+ * Pass STAGED_PAYLOAD=<file> with a hex-encoded UnshieldArgs payload (e.g. the
+ * arguments produced by test-unshield-change.ts) to execute a real withdrawal.
+ * Without it a dummy proof is staged: execution must fail with InvalidProof and
+ * the session is closed with close_proof_staging instead.
+ */
+
+import {
+  Connection,
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+  sendAndConfirmTransaction
+} from "@solana/web3.js";
+import * as fs from "fs";
+
+// Program ID
+const POOL_PROGRAM_ID = new PublicKey("AMtxCTW99zCBfhukVdN8YvA3AsdSJ7nsgnUdHpth7QTD");
+
+const DENOM_001_SOL = 10_000_000n;
+
+// Generate Anchor discriminator
+function getDiscriminator(name: string): Buffer {
+  const crypto = require("crypto");
+  return crypto.createHash("sha256")
+    .update(`global:${name}`)
+    .digest()
+    .slice(0, 8);
+}
+
+function u64LE(n: bigint): Buffer {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(n);
+  return buf;
+}
+
+function pda(seeds: Buffer[]): PublicKey {
+  return PublicKey.findProgramAddressSync(seeds, POOL_PROGRAM_ID)[0];
+}
+
+async function expectFailure(label: string, fn: () => Promise<unknown>, expected?: string) {
+  try {
+    await fn();
+  } catch (e: any) {
+    const logs: string = (e.logs || []).join("\n") + String(e.message || e);
+    if (expected && !logs.includes(expected)) {
+      throw new Error(`${label}: failed with an unexpected error:\n${logs}`);
+    }
+    console.log(`   ✓ ${label} rejected${expected ? ` (${expected})` : ""}`);
+    return;
+  }
+  throw new Error(`${label}: expected failure but the transaction succeeded`);
+}
+
+async function main() {
+  console.log("=".repeat(70));
+  console.log("WHISTLE PROTOCOL - STAGED PROOF UPLOAD TEST");
+  console.log("=".repeat(70));
+
+  // Load wallet
+  const walletPath = process.env.WALLET || "../keys/deploy-wallet.json";
+  const wallet = Keypair.fromSecretKey(
+    new Uint8Array(JSON.parse(fs.readFileSync(walletPath, "utf-8")))
+  );
+  const connection = new Connection("https://api.devnet.solana.com", "confirmed");
+  console.log(`\nWallet: ${wallet.publicKey.toBase58()}`);
+
+  const poolPda = pda([Buffer.from("pool")]);
+  const merkleTreePda = pda([Buffer.from("merkle_tree")]);
+  const nullifiersPda = pda([Buffer.from("nullifiers")]);
+  const rootsHistoryPda = pda([Buffer.from("roots_history")]);
+  const denominationConfigPda = pda([Buffer.from("denomination_config")]);
+  const vaultPda = pda([Buffer.from("vault")]);
+  const feeVaultPda = pda([Buffer.from("fee_vault")]);
+
+  // Build the payload: borsh-encoded UnshieldArgs
+  let payload: Buffer;
+  const realPayload = process.env.STAGED_PAYLOAD;
+  if (realPayload) {
+    payload = Buffer.from(fs.readFileSync(realPayload, "utf-8").trim(), "hex");
+    console.log(`\nUsing staged payload from ${realPayload} (${payload.length} bytes)`);
+  } else {
+    const poolAccount = await connection.getAccountInfo(poolPda);
+    if (!poolAccount) throw new Error("Pool not initialized");
+    const merkleRoot = poolAccount.data.slice(8 + 9, 8 + 41);
+
+    payload = Buffer.concat([
+      Buffer.alloc(64),                 // proof_a (dummy)
+      Buffer.alloc(128),                // proof_b (dummy)
+      Buffer.alloc(64),                 // proof_c (dummy)
+      Keypair.generate().publicKey.toBuffer(), // nullifier_hash
+      wallet.publicKey.toBuffer(),      // recipient
+      u64LE(DENOM_001_SOL),             // withdrawal_amount
+      u64LE(0n),                        // relayer_fee
+      merkleRoot,                       // merkle_root
+      Buffer.alloc(32),                 // change_commitment
+    ]);
+    console.log(`\nUsing dummy unshield payload (${payload.length} bytes)`);
+  }
+  const recipient = new PublicKey(payload.slice(288, 320));
+
+  const sessionId = BigInt(Date.now());
+  const stagingPda = pda([Buffer.from("proof_staging"), wallet.publicKey.toBuffer(), u64LE(sessionId)]);
+  console.log(`Session: ${sessionId} → ${stagingPda.toBase58()}`);
+
+  const stageIx = (offset: number, bytes: Buffer) => {
+    const len = Buffer.alloc(4);
+    len.writeUInt32LE(bytes.length);
+    const off = Buffer.alloc(2);
+    off.writeUInt16LE(offset);
+    return new TransactionInstruction({
+      programId: POOL_PROGRAM_ID,
+      keys: [
+        { pubkey: stagingPda, isSigner: false, isWritable: true },
+        { pubkey: wallet.publicKey, isSigner: true, isWritable: true },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      ],
+      data: Buffer.concat([getDiscriminator("stage_proof_chunk"), u64LE(sessionId), off, len, bytes]),
+    });
+  };
+  const send = (ix: TransactionInstruction) =>
+    sendAndConfirmTransaction(connection, new Transaction().add(ix), [wallet]);
+
+  // 1. Stage in three chunks
+  console.log("\n1. Staging payload in three chunks...");
+  const cuts = [0, Math.floor(payload.length / 3), Math.floor((2 * payload.length) / 3), payload.length];
+  for (let i = 0; i < 3; i++) {
+    if (i === 2) {
+      // 2. A chunk that would overwrite staged bytes is rejected
+      console.log("\n2. Trying to overwrite staged bytes...");
+      await expectFailure("Overlapping chunk", () => send(stageIx(0, payload.slice(0, 8))), "InvalidProofChunkOffset");
+    }
+    const sig = await send(stageIx(cuts[i], payload.slice(cuts[i], cuts[i + 1])));
+    console.log(`   Chunk ${i + 1}: bytes ${cuts[i]}..${cuts[i + 1]} (${sig.slice(0, 16)}...)`);
+  }
+
+  const staging = await connection.getAccountInfo(stagingPda);
+  if (!staging) throw new Error("Staging account missing");
+  const stagedLen = staging.data.readUInt16LE(8 + 32 + 8 + 8);
+  const staged = staging.data.slice(8 + 32 + 8 + 8 + 2, 8 + 32 + 8 + 8 + 2 + stagedLen);
+  if (!staged.equals(payload)) throw new Error("Staged bytes do not match the payload");
+  console.log(`   ✓ Staged ${stagedLen} bytes match the payload`);
+
+  // 3. Execute
+  console.log("\n3. Executing unshield_from_staged...");
+  const executeIx = new TransactionInstruction({
+    programId: POOL_PROGRAM_ID,
+    keys: [
+      { pubkey: stagingPda, isSigner: false, isWritable: true },
+      { pubkey: wallet.publicKey, isSigner: true, isWritable: true },
+      { pubkey: poolPda, isSigner: false, isWritable: true },
+      { pubkey: merkleTreePda, isSigner: false, isWritable: true },
+      { pubkey: nullifiersPda, isSigner: false, isWritable: true },
+      { pubkey: rootsHistoryPda, isSigner: false, isWritable: true },
+      { pubkey: denominationConfigPda, isSigner: false, isWritable: false },
+      { pubkey: vaultPda, isSigner: false, isWritable: true },
+      { pubkey: feeVaultPda, isSigner: false, isWritable: true },
+      { pubkey: recipient, isSigner: false, isWritable: true },
+      { pubkey: wallet.publicKey, isSigner: false, isWritable: true }, // relayer
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    data: Buffer.concat([getDiscriminator("unshield_from_staged"), u64LE(sessionId)]),
+  });
+
+  if (realPayload) {
+    const sig = await send(executeIx);
+    console.log(`   ✓ Unshielded from staged payload (${sig.slice(0, 16)}...)`);
+  } else {
+    await expectFailure("Dummy proof", () => send(executeIx), "InvalidProof");
+
+    const closeIx = new TransactionInstruction({
+      programId: POOL_PROGRAM_ID,
+      keys: [
+        { pubkey: stagingPda, isSigner: false, isWritable: true },
+        { pubkey: wallet.publicKey, isSigner: false, isWritable: true },
+        { pubkey: wallet.publicKey, isSigner: true, isWritable: false },
+      ],
+      data: Buffer.concat([getDiscriminator("close_proof_staging"), u64LE(sessionId)]),
+    });
+    await send(closeIx);
+    console.log("   ✓ Closed staging session with close_proof_staging");
+  }
+
+  // 4. Reuse is impossible: the staging account is gone
+  console.log("\n4. Reusing the session...");
+  if (await connection.getAccountInfo(stagingPda)) {
+    throw new Error("Staging account still exists");
+  }
+  await expectFailure("Reused session", () => send(executeIx), "AccountNotInitialized");
+
+  console.log("\n" + "=".repeat(70));
+  console.log("STAGED PROOF UPLOAD TEST PASSED");
+  console.log("=".repeat(70));
+}
+
+main().catch((e) => {
+  console.error(e);
+  process.exit(1);
+});
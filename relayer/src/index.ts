@@ -180,6 +180,22 @@ const VALID_DENOMINATIONS = [
   100_000_000_000, // 100 SOL
 ];
 
+// Relayer fee caps in basis points, indexed like VALID_DENOMINATIONS
+// (must match the pool's DenominationConfig set at genesis)
+const RELAYER_FEE_CAPS_BPS = [
+  1000, // 0.01 SOL: 10%
+  1000, // 0.05 SOL: 10%
+  500,  // 0.1 SOL:  5%
+  300,  // 1 SOL:    3%
+  100,  // 10 SOL:   1%
+  100,  // 100 SOL:  1%
+];
+
+function maxRelayerFee(amount: number): number {
+  const idx = VALID_DENOMINATIONS.indexOf(amount);
+  return Math.floor((amount * RELAYER_FEE_CAPS_BPS[idx]) / 10_000);
+}
+
 // Relayer wallet
 let relayerKeypair: Keypair;
 
@@ -285,6 +301,7 @@ app.post('/withdraw', async (req, res) => {
       amount,         // Lamports
       fee,            // Relayer fee
       merkleRoot,     // 32 bytes - current merkle root
+      unlockSlot,     // Slot a time-locked note unlocks at (0 or omitted if not locked)
     } = req.body;
 
     const clientIP = getClientIP(req);
@@ -321,9 +338,12 @@ app.post('/withdraw', async (req, res) => {
       return;
     }
 
-    // SECURITY FIX: Validate fee is not too high (max 10%)
-    if (Number(fee) > Number(amount) / 10) {
-      res.status(400).json({ error: 'Relayer fee too high (max 10%)' });
+    // SECURITY FIX: Validate fee against the per-denomination cap
+    if (Number(fee) > maxRelayerFee(Number(amount))) {
+      res.status(400).json({
+        error: 'Relayer fee too high for this denomination',
+        maxFee: maxRelayerFee(Number(amount)),
+      });
       return;
     }
 
@@ -349,13 +369,21 @@ app.post('/withdraw', async (req, res) => {
     
     const feeBuffer = Buffer.alloc(8);
     feeBuffer.writeBigUInt64LE(BigInt(fee || 0));
+    const unlockSlotBuffer = Buffer.alloc(8);
+    unlockSlotBuffer.writeBigUInt64LE(BigInt(unlockSlot || 0));
 
     // Derive PDAs
     const [pool] = PublicKey.findProgramAddressSync([Buffer.from('pool')], PROGRAM_ID);
     const [poolVault] = PublicKey.findProgramAddressSync([Buffer.from('vault')], PROGRAM_ID);
+    const [feeVault] = PublicKey.findProgramAddressSync([Buffer.from('fee_vault')], PROGRAM_ID);
     const [nullifiers] = PublicKey.findProgramAddressSync([Buffer.from('nullifiers')], PROGRAM_ID);
     const [rootsHistory] = PublicKey.findProgramAddressSync([Buffer.from('roots_history')], PROGRAM_ID);
     const [merkleTree] = PublicKey.findProgramAddressSync([Buffer.from('merkle_tree')], PROGRAM_ID);
+    const [denominationConfig] = PublicKey.findProgramAddressSync([Buffer.from('denomination_config')], PROGRAM_ID);
+    const [congestion] = PublicKey.findProgramAddressSync([Buffer.from('congestion')], PROGRAM_ID);
+    const [nullifierMarker] = PublicKey.findProgramAddressSync([Buffer.from('nullifier'), nullifierHashBytes], PROGRAM_ID);
+    // withdraw re-shields no change, so the change marker is the zero commitment's
+    const [changeMarker] = PublicKey.findProgramAddressSync([Buffer.from('commitment'), Buffer.alloc(32)], PROGRAM_ID);
 
     console.log('PDAs:');
     console.log('  Pool:', pool.toBase58());
@@ -364,7 +392,7 @@ app.post('/withdraw', async (req, res) => {
     console.log('  RootsHistory:', rootsHistory.toBase58());
 
     // Build instruction data for 'withdraw' (uses withdraw_merkle circuit)
-    // Format: discriminator + proof_a + proof_b + proof_c + nullifier_hash + recipient + amount + fee + merkle_root
+    // Format: discriminator + proof_a + proof_b + proof_c + nullifier_hash + recipient + amount + fee + merkle_root + unlock_slot
     const instructionData = Buffer.concat([
       WITHDRAW_DISCRIM,          // 8 bytes
       proofA,                    // 64 bytes
@@ -375,6 +403,7 @@ app.post('/withdraw', async (req, res) => {
       amountBuffer,              // 8 bytes
       feeBuffer,                 // 8 bytes
       merkleRootBytes,           // 32 bytes
+      unlockSlotBuffer,          // 8 bytes
     ]);
 
     console.log('Instruction data size:', instructionData.length, 'bytes');
@@ -384,12 +413,18 @@ app.post('/withdraw', async (req, res) => {
       keys: [
         { pubkey: pool, isSigner: false, isWritable: true },
         { pubkey: merkleTree, isSigner: false, isWritable: true },
-        { pubkey: nullifiers, isSigner: false, isWritable: true },
+        { pubkey: nullifiers, isSigner: false, isWritable: false },
         { pubkey: rootsHistory, isSigner: false, isWritable: true },
+        { pubkey: denominationConfig, isSigner: false, isWritable: false },
         { pubkey: poolVault, isSigner: false, isWritable: true },
+        { pubkey: feeVault, isSigner: false, isWritable: true },
         { pubkey: recipientPubkey, isSigner: false, isWritable: true },
         { pubkey: relayerKeypair.publicKey, isSigner: true, isWritable: true },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: congestion, isSigner: false, isWritable: true },
+        { pubkey: nullifierMarker, isSigner: false, isWritable: true }, // created by this spend
+        { pubkey: relayerKeypair.publicKey, isSigner: true, isWritable: true }, // pays the marker's rent
+        { pubkey: changeMarker, isSigner: false, isWritable: true }, // unused without change
       ],
       programId: PROGRAM_ID,
       data: instructionData,
@@ -0,0 +1,436 @@
+#!/usr/bin/env node
+/**
+ * whistle-cli - command line client for the Whistle pool
+ *
+ *   whistle-cli shield --secret <hex> --nullifier <hex> --amount <lamports> [--unlock-slot <slot>]
+ *   whistle-cli unshield --note-json <file> --recipient <pubkey> --denomination <lamports>
+ *   whistle-cli private-transfer --note-in1 <file> --note-in2 <file>
+ *                                --note-out1-secret <hex> --note-out1-nullifier <hex>
+ *   whistle-cli status --pool <pubkey>
+ *
+ * Common options: --rpc <url>, --keypair <file>, --program <pubkey>,
+ * --circuits <dir> (or WHISTLE_CIRCUITS), --lookup-table <pubkey>.
+ *
+ * Every note the CLI creates or spends is recorded in ~/.whistle/notes.json.
+ * Shields above 1 SOL are pre-committed (pre_commit_shield + reveal_shield).
+ * --unlock-slot creates a time-locked note that cannot be spent before that slot.
+ */
+
+import {
+  Connection,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  Transaction,
+} from '@solana/web3.js';
+import * as crypto from 'crypto';
+import * as fs from 'fs';
+import * as os from 'os';
+import * as path from 'path';
+import { parseArgs } from 'util';
+// @ts-ignore
+import { buildPoseidon } from 'circomlibjs';
+// @ts-ignore
+import { groth16 } from 'snarkjs';
+import { DEVNET_RPC, PRE_COMMIT_THRESHOLD } from './core/constants';
+import { POOL_PROGRAM_ID } from './client';
+import { TransactionBuilder, encodeProof } from './transactionBuilder';
+import { decimalStrToBeBytes, isCanonicalFieldElement } from './publicInputs';
+import { sendBuiltTransaction } from './lookupTable';
+
+// BN254 scalar field
+const FIELD_PRIME = BigInt('21888242871839275222246405745257275088548364400416034343698204186575808495617');
+
+const NOTES_PATH = path.join(os.homedir(), '.whistle', 'notes.json');
+
+// Pool account layout (after the 8 byte discriminator)
+const POOL_LEVELS_OFFSET = 8;
+const POOL_NEXT_INDEX_OFFSET = 9;
+const POOL_ROOT_OFFSET = 17;
+// Merkle tree account: discriminator (8) + header (8), then the nodes
+const TREE_NODES_OFFSET = 16;
+
+const USAGE = `usage:
+  whistle-cli shield --secret <hex> --nullifier <hex> --amount <lamports> [--unlock-slot <slot>]
+  whistle-cli unshield --note-json <file> --recipient <pubkey> --denomination <lamports>
+  whistle-cli private-transfer --note-in1 <file> --note-in2 <file> --note-out1-secret <hex> --note-out1-nullifier <hex>
+  whistle-cli status --pool <pubkey>
+
+options: --rpc <url> --keypair <file> --program <pubkey> --circuits <dir> --lookup-table <pubkey>`;
+
+/** A note as stored in notes.json (field elements as 32 byte hex) */
+interface StoredNote {
+  secret: string;
+  nullifier: string;
+  amount: string;
+  commitment: string;
+  nullifierHash: string;
+  leafIndex: number;
+  spent: boolean;
+  /** Slot a time-locked note unlocks at; absent or "0" for standard notes */
+  unlockSlot?: string;
+}
+
+type Poseidon = (...inputs: bigint[]) => bigint;
+
+interface Context {
+  connection: Connection;
+  wallet: Keypair;
+  builder: TransactionBuilder;
+  circuitsDir: string;
+  poseidon: Poseidon;
+}
+
+function toHex(n: bigint): string {
+  return n.toString(16).padStart(64, '0');
+}
+
+function fromHex(hex: string): bigint {
+  const value = BigInt('0x' + hex.replace(/^0x/, ''));
+  if (value >= FIELD_PRIME) {
+    throw new Error(`${hex} is not a field element`);
+  }
+  return value;
+}
+
+function randomField(): bigint {
+  return BigInt('0x' + crypto.randomBytes(31).toString('hex')) % FIELD_PRIME;
+}
+
+function loadNotes(): StoredNote[] {
+  if (!fs.existsSync(NOTES_PATH)) {
+    return [];
+  }
+  return JSON.parse(fs.readFileSync(NOTES_PATH, 'utf-8'));
+}
+
+/** Insert or replace notes (keyed by commitment) in notes.json */
+function saveNotes(updated: StoredNote[]): void {
+  const notes = loadNotes().filter((n) => !updated.some((u) => u.commitment === n.commitment));
+  fs.mkdirSync(path.dirname(NOTES_PATH), { recursive: true });
+  fs.writeFileSync(NOTES_PATH, JSON.stringify([...notes, ...updated], null, 2));
+}
+
+function readNote(file: string): StoredNote {
+  const note: StoredNote = JSON.parse(fs.readFileSync(file, 'utf-8'));
+  if (note.spent) {
+    throw new Error(`Note ${note.commitment} is already spent`);
+  }
+  for (const value of [note.commitment, note.nullifierHash]) {
+    if (!isCanonicalFieldElement(Buffer.from(value, 'hex'))) {
+      throw new Error(`${file}: ${value} is not a big-endian field element (byte-reversed?)`);
+    }
+  }
+  return note;
+}
+
+function makeNote(
+  poseidon: Poseidon,
+  secret: bigint,
+  nullifier: bigint,
+  amount: bigint,
+  unlockSlot: bigint = BigInt(0)
+): StoredNote {
+  // Time-locked notes also commit to their unlock slot (see notes.ts)
+  const inner = poseidon(nullifier, amount);
+  const commitment = unlockSlot === BigInt(0) ? poseidon(secret, inner) : poseidon(secret, inner, unlockSlot);
+  return {
+    secret: toHex(secret),
+    nullifier: toHex(nullifier),
+    amount: amount.toString(),
+    commitment: toHex(commitment),
+    nullifierHash: toHex(poseidon(nullifier, BigInt(0))),
+    leafIndex: -1,
+    spent: false,
+    unlockSlot: unlockSlot.toString(),
+  };
+}
+
+/** Unlock slot of a note, failing early if it is still locked */
+async function unlockSlotOf(ctx: Context, note: StoredNote): Promise<bigint> {
+  const unlockSlot = BigInt(note.unlockSlot || 0);
+  const slot = BigInt(await ctx.connection.getSlot('confirmed'));
+  if (slot < unlockSlot) {
+    throw new Error(`Note ${note.commitment} is time-locked until slot ${unlockSlot} (now ${slot})`);
+  }
+  return unlockSlot;
+}
+
+async function readPool(ctx: Context): Promise<{ levels: number; nextIndex: number; root: Buffer; tree: Buffer }> {
+  const [pool, tree] = await Promise.all([
+    ctx.connection.getAccountInfo(ctx.builder.pda('pool')),
+    ctx.connection.getAccountInfo(ctx.builder.pda('merkle_tree')),
+  ]);
+  if (!pool || !tree) {
+    throw new Error('Pool not initialized');
+  }
+  return {
+    levels: pool.data.readUInt8(POOL_LEVELS_OFFSET),
+    nextIndex: Number(pool.data.readBigUInt64LE(POOL_NEXT_INDEX_OFFSET)),
+    root: pool.data.subarray(POOL_ROOT_OFFSET, POOL_ROOT_OFFSET + 32),
+    tree: tree.data,
+  };
+}
+
+function merklePath(tree: Buffer, levels: number, leafIndex: number): { pathElements: string[]; pathIndices: string[] } {
+  const nodeCount = (tree.length - TREE_NODES_OFFSET) / 32;
+  const readNode = (index: number): bigint => {
+    if (index < 0 || index >= nodeCount) return BigInt(0);
+    const start = TREE_NODES_OFFSET + index * 32;
+    return BigInt('0x' + tree.subarray(start, start + 32).toString('hex'));
+  };
+
+  const pathElements: string[] = [];
+  const pathIndices: string[] = [];
+  let current = (1 << levels) - 1 + leafIndex;
+  for (let level = 0; level < levels; level++) {
+    const isLeft = current % 2 === 1;
+    pathElements.push(readNode(isLeft ? current + 1 : current - 1).toString());
+    pathIndices.push(isLeft ? '0' : '1');
+    current = Math.floor((current - 1) / 2);
+  }
+  return { pathElements, pathIndices };
+}
+
+async function prove(ctx: Context, circuit: string, input: object) {
+  const wasm = path.join(ctx.circuitsDir, circuit, `${circuit}_js`, `${circuit}.wasm`);
+  const zkey = path.join(ctx.circuitsDir, circuit, `${circuit}_final.zkey`);
+  for (const file of [wasm, zkey]) {
+    if (!fs.existsSync(file)) {
+      throw new Error(`Missing circuit artifact ${file}`);
+    }
+  }
+  const { proof } = await groth16.fullProve(input, wasm, zkey);
+  return encodeProof(proof);
+}
+
+async function send(ctx: Context, tx: Transaction): Promise<string> {
+  const built = await ctx.builder.compile(ctx.connection, ctx.wallet.publicKey, tx);
+  return sendBuiltTransaction(ctx.connection, built, [ctx.wallet]);
+}
+
+async function shield(ctx: Context, opts: Record<string, string>): Promise<void> {
+  const amount = BigInt(opts.amount);
+  const unlockSlot = BigInt(opts['unlock-slot'] || 0);
+  const note = makeNote(ctx.poseidon, fromHex(opts.secret), fromHex(opts.nullifier), amount, unlockSlot);
+
+  const commitment = Buffer.from(note.commitment, 'hex');
+  if (amount > PRE_COMMIT_THRESHOLD) {
+    // Large shields commit to the note hash before its amount is public
+    const depositSlot = BigInt(await ctx.connection.getSlot('confirmed'));
+    await send(ctx, ctx.builder.preCommitShield(ctx.wallet.publicKey, commitment, depositSlot));
+  }
+
+  const { nextIndex } = await readPool(ctx);
+  const tx = amount > PRE_COMMIT_THRESHOLD
+    ? ctx.builder.revealShield(ctx.wallet.publicKey, commitment, amount, BigInt(nextIndex))
+    : ctx.builder.shield(ctx.wallet.publicKey, commitment, amount, BigInt(nextIndex));
+  const signature = await send(ctx, tx);
+
+  note.leafIndex = nextIndex;
+  saveNotes([note]);
+  console.log(`Shielded ${Number(amount) / LAMPORTS_PER_SOL} SOL at leaf ${nextIndex}: ${signature}`);
+  console.log(`Commitment ${note.commitment}`);
+  if (unlockSlot > BigInt(0)) {
+    console.log(`Spendable from slot ${unlockSlot}`);
+  }
+}
+
+async function unshield(ctx: Context, opts: Record<string, string>): Promise<void> {
+  const note = readNote(opts['note-json']);
+  const recipient = new PublicKey(opts.recipient);
+  const withdrawalAmount = BigInt(opts.denomination);
+  const relayerFee = BigInt(0);
+  const changeAmount = BigInt(note.amount) - withdrawalAmount - relayerFee;
+  if (changeAmount < BigInt(0)) {
+    throw new Error(`Note holds ${note.amount} lamports, less than ${withdrawalAmount}`);
+  }
+  const unlockSlot = await unlockSlotOf(ctx, note);
+
+  // Independent randomness: nothing about the change note follows from the spent one
+  const change = makeNote(ctx.poseidon, randomField(), randomField(), changeAmount);
+  const changeCommitment = changeAmount > BigInt(0) ? fromHex(change.commitment) : BigInt(0);
+
+  const pool = await readPool(ctx);
+  // Recipient field element: the first 31 pubkey bytes
+  const recipientField = Buffer.alloc(32);
+  recipient.toBuffer().copy(recipientField, 1, 0, 31);
+
+  const proof = await prove(ctx, 'unshield_change', {
+    merkleRoot: BigInt('0x' + pool.root.toString('hex')).toString(),
+    nullifierHash: fromHex(note.nullifierHash).toString(),
+    recipient: BigInt('0x' + recipientField.toString('hex')).toString(),
+    withdrawalAmount: withdrawalAmount.toString(),
+    relayerFee: relayerFee.toString(),
+    changeCommitment: changeCommitment.toString(),
+    unlockSlot: unlockSlot.toString(),
+    secret: fromHex(note.secret).toString(),
+    nullifier: fromHex(note.nullifier).toString(),
+    noteAmount: note.amount,
+    ...merklePath(pool.tree, pool.levels, note.leafIndex),
+    changeSecret: fromHex(change.secret).toString(),
+    changeNullifier: fromHex(change.nullifier).toString(),
+    changeAmount: changeAmount.toString(),
+  });
+
+  const signature = await send(ctx, ctx.builder.unshield({
+    proof,
+    nullifierHash: Buffer.from(note.nullifierHash, 'hex'),
+    recipient,
+    withdrawalAmount,
+    relayerFee,
+    merkleRoot: pool.root,
+    changeCommitment: decimalStrToBeBytes(changeCommitment),
+    unlockSlot,
+  }, ctx.wallet.publicKey));
+
+  const updated = [{ ...note, spent: true }];
+  if (changeAmount > BigInt(0)) {
+    updated.push({ ...change, leafIndex: pool.nextIndex });
+  }
+  saveNotes(updated);
+  console.log(`Unshielded ${Number(withdrawalAmount) / LAMPORTS_PER_SOL} SOL to ${recipient.toBase58()}: ${signature}`);
+  if (changeAmount > BigInt(0)) {
+    console.log(`Change note ${change.commitment} (${changeAmount} lamports) saved to ${NOTES_PATH}`);
+  }
+}
+
+async function privateTransfer(ctx: Context, opts: Record<string, string>): Promise<void> {
+  const inputs = [readNote(opts['note-in1']), readNote(opts['note-in2'])];
+  if (inputs[0].commitment === inputs[1].commitment) {
+    throw new Error('Input notes must be distinct');
+  }
+  const unlockSlots: [bigint, bigint] = [await unlockSlotOf(ctx, inputs[0]), await unlockSlotOf(ctx, inputs[1])];
+
+  // Merge both inputs into output 1; output 2 is the zero note
+  const total = BigInt(inputs[0].amount) + BigInt(inputs[1].amount);
+  const out1 = makeNote(
+    ctx.poseidon,
+    fromHex(opts['note-out1-secret']),
+    fromHex(opts['note-out1-nullifier']),
+    total
+  );
+  const out2 = { secret: randomField(), nullifier: randomField() };
+
+  const pool = await readPool(ctx);
+  const path1 = merklePath(pool.tree, pool.levels, inputs[0].leafIndex);
+  const path2 = merklePath(pool.tree, pool.levels, inputs[1].leafIndex);
+
+  const proof = await prove(ctx, 'private_transfer', {
+    merkleRoot: BigInt('0x' + pool.root.toString('hex')).toString(),
+    inputNullifierHashes: inputs.map((n) => fromHex(n.nullifierHash).toString()),
+    outputCommitments: [fromHex(out1.commitment).toString(), '0'],
+    unlockSlots: unlockSlots.map((slot) => slot.toString()),
+    inSecret1: fromHex(inputs[0].secret).toString(),
+    inNullifier1: fromHex(inputs[0].nullifier).toString(),
+    inAmount1: inputs[0].amount,
+    inPathElements1: path1.pathElements,
+    inPathIndices1: path1.pathIndices,
+    inSecret2: fromHex(inputs[1].secret).toString(),
+    inNullifier2: fromHex(inputs[1].nullifier).toString(),
+    inAmount2: inputs[1].amount,
+    inPathElements2: path2.pathElements,
+    inPathIndices2: path2.pathIndices,
+    outSecret1: fromHex(out1.secret).toString(),
+    outNullifier1: fromHex(out1.nullifier).toString(),
+    outAmount1: total.toString(),
+    outSecret2: out2.secret.toString(),
+    outNullifier2: out2.nullifier.toString(),
+    outAmount2: '0',
+  });
+
+  const signature = await send(ctx, ctx.builder.privateTransfer({
+    proof,
+    inputNullifierHashes: [
+      Buffer.from(inputs[0].nullifierHash, 'hex'),
+      Buffer.from(inputs[1].nullifierHash, 'hex'),
+    ],
+    outputCommitments: [Buffer.from(out1.commitment, 'hex'), Buffer.alloc(32)],
+    merkleRoot: pool.root,
+    unlockSlots,
+  }, ctx.wallet.publicKey));
+
+  saveNotes([
+    ...inputs.map((n) => ({ ...n, spent: true })),
+    { ...out1, leafIndex: pool.nextIndex },
+  ]);
+  console.log(`Transferred ${Number(total) / LAMPORTS_PER_SOL} SOL into ${out1.commitment}: ${signature}`);
+}
+
+async function status(ctx: Context, opts: Record<string, string>): Promise<void> {
+  const account = await ctx.connection.getAccountInfo(new PublicKey(opts.pool));
+  if (!account) {
+    throw new Error(`No account at ${opts.pool}`);
+  }
+
+  const data = account.data.subarray(8);
+  const vaultBalance = await ctx.connection.getBalance(ctx.builder.pda('vault'));
+  const notes = loadNotes().filter((n) => !n.spent);
+  const sol = (offset: number) => (Number(data.readBigUInt64LE(offset)) / LAMPORTS_PER_SOL).toFixed(4);
+
+  console.log(`Pool             ${opts.pool}`);
+  console.log(`Merkle levels    ${data.readUInt8(0)}`);
+  console.log(`Next index       ${data.readBigUInt64LE(1)}`);
+  console.log(`Current root     ${data.subarray(9, 41).toString('hex')}`);
+  console.log(`Total deposits   ${sol(41)} SOL`);
+  console.log(`Total shielded   ${sol(49)} SOL`);
+  console.log(`Fees collected   ${sol(57)} SOL`);
+  console.log(`Vault balance    ${(vaultBalance / LAMPORTS_PER_SOL).toFixed(4)} SOL`);
+  console.log(`Unspent notes    ${notes.length} (${NOTES_PATH})`);
+}
+
+const COMMANDS: Record<string, { required: string[]; run: (ctx: Context, opts: Record<string, string>) => Promise<void> }> = {
+  shield: { required: ['secret', 'nullifier', 'amount'], run: shield },
+  unshield: { required: ['note-json', 'recipient', 'denomination'], run: unshield },
+  'private-transfer': {
+    required: ['note-in1', 'note-in2', 'note-out1-secret', 'note-out1-nullifier'],
+    run: privateTransfer,
+  },
+  status: { required: ['pool'], run: status },
+};
+
+const OPTIONS = [
+  'secret', 'nullifier', 'amount', 'unlock-slot',
+  'note-json', 'recipient', 'denomination',
+  'note-in1', 'note-in2', 'note-out1-secret', 'note-out1-nullifier',
+  'pool', 'rpc', 'keypair', 'program', 'circuits', 'lookup-table',
+];
+
+async function main(): Promise<void> {
+  const { values, positionals } = parseArgs({
+    allowPositionals: true,
+    options: Object.fromEntries(OPTIONS.map((name) => [name, { type: 'string' as const }])),
+  });
+  const opts = values as Record<string, string>;
+
+  const command = COMMANDS[positionals[0]];
+  if (!command || positionals.length !== 1) {
+    console.error(USAGE);
+    process.exit(1);
+  }
+  const missing = command.required.filter((name) => opts[name] === undefined);
+  if (missing.length > 0) {
+    console.error(`${positionals[0]}: missing ${missing.map((m) => `--${m}`).join(', ')}\n\n${USAGE}`);
+    process.exit(1);
+  }
+
+  const keypairPath = opts.keypair || path.join(os.homedir(), '.config', 'solana', 'id.json');
+  const poseidonFn = await buildPoseidon();
+  const F = poseidonFn.F;
+
+  await command.run({
+    connection: new Connection(opts.rpc || DEVNET_RPC, 'confirmed'),
+    wallet: Keypair.fromSecretKey(new Uint8Array(JSON.parse(fs.readFileSync(keypairPath, 'utf-8')))),
+    builder: new TransactionBuilder(
+      opts.program ? new PublicKey(opts.program) : POOL_PROGRAM_ID,
+      opts['lookup-table'] ? new PublicKey(opts['lookup-table']) : undefined
+    ),
+    circuitsDir: opts.circuits || process.env.WHISTLE_CIRCUITS || path.resolve('circuits/build/production'),
+    poseidon: (...inputs) => BigInt(F.toString(poseidonFn(inputs.map((input) => F.e(input.toString()))))),
+  }, opts);
+}
+
+main().catch((e) => {
+  console.error(e instanceof Error ? e.message : e);
+  process.exit(1);
+});
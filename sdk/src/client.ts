@@ -5,7 +5,16 @@ import {
   SystemProgram,
   LAMPORTS_PER_SOL,
   Keypair,
+  TransactionInstruction,
 } from '@solana/web3.js';
+import { createHash, createPrivateKey, sign } from 'crypto';
+import { WITHDRAW_DENOMINATIONS, BPS_DENOMINATOR, LAMPORTS_PER_SIGNATURE } from './core/constants';
+import { MultiRpc } from './multiRpc';
+import { TransactionManager } from './transactionManager';
+import { CongestionInfo, decodeCongestionInfo } from './congestion';
+import { commitmentCompatibilityVectors } from './notes';
+import { selectNotes, SelectionStrategy, SpendableNote, SpendPlan } from './noteSelection';
+import { MerkleProof } from './prover';
 
 export const POOL_PROGRAM_ID = new PublicKey('7H6GXuDXHaErfMgz5xYhDgpZVhUhWUFkhqgbw5iQrUfV');
 export const VERIFIER_PROGRAM_ID = new PublicKey('7vBdkq62GbtXjoJydEEjn996kkr8kcbgrZcGbe7zSj1u');
@@ -14,6 +23,10 @@ export interface WhistleConfig {
   connection: Connection;
   wallet: Keypair;
   programId?: PublicKey;
+  /** Cross-check tree state against several RPC providers */
+  multiRpc?: MultiRpc;
+  /** Submits the client's transactions (default: retries on `connection`) */
+  transactionManager?: TransactionManager;
 }
 
 export interface DepositResult {
@@ -22,6 +35,134 @@ export interface DepositResult {
   leafIndex: number;
 }
 
+export interface SpentNullifier {
+  nullifier: Uint8Array;
+  slot: bigint;
+  /** Position in spend order */
+  seq: bigint;
+}
+
+export interface NullifierLedgerReport {
+  format: 'csv' | 'json';
+  content: string;
+  /** Wallet that signed the report */
+  signer: string;
+  /** Hex Ed25519 signature over `content` */
+  signature: string;
+}
+
+/** Largest page the program returns from get_spent_nullifiers */
+export const MAX_NULLIFIER_PAGE = 16;
+
+/** Largest slice the program returns from get_leaves_page */
+export const MAX_LEAF_READ = 24;
+
+/** Hashes checked per batch_nullifier_status call */
+export const NULLIFIER_STATUS_BATCH = 16;
+
+/** NullifierMarker account: discriminator, nullifier hash, spent slot, bump */
+export const NULLIFIER_MARKER_SIZE = 8 + 32 + 8 + 1;
+
+/** get_reserve_snapshot layout version this SDK decodes */
+export const RESERVE_SNAPSHOT_VERSION = 1;
+
+// Borsh size of ReserveSnapshot: version u8, nine 64-bit fields, root
+const RESERVE_SNAPSHOT_SIZE = 1 + 8 * 9 + 32;
+
+/** Pool state accounts export_state_chunk reads (STATE_* in the program) */
+export const STATE_ACCOUNT_KINDS = {
+  pool: 0,
+  merkleTree: 1,
+  rootsHistory: 2,
+  nullifiers: 3,
+} as const;
+
+/** Bytes written per import_state_chunk transaction (keeps it under the packet limit) */
+export const STATE_IMPORT_CHUNK_SIZE = 800;
+
+/** Raw contents of the pool state accounts, as returned by snapshotPool */
+export type PoolStateSnapshot = Record<keyof typeof STATE_ACCOUNT_KINDS, Uint8Array>;
+
+/** Vault balances and pool ledger totals read in a single slot */
+export interface ReserveSnapshot {
+  version: number;
+  slot: bigint;
+  vaultLamports: bigint;
+  feeVaultLamports: bigint;
+  totalDeposits: bigint;
+  totalShielded: bigint;
+  totalWithdrawn: bigint;
+  totalFeesCollected: bigint;
+  /** Vault balance less total shielded; negative means under-collateralized */
+  surplus: bigint;
+  leafCount: bigint;
+  currentRoot: Uint8Array;
+}
+
+/** Whether the program's upgrade authority was burned, as last attested */
+export interface FinalityAttestation {
+  immutable: boolean;
+  /** ProgramData slot of the program's last deploy */
+  deployedSlot: bigint;
+  /** First attestation that saw no upgrade authority; 0 while upgradeable */
+  immutableSinceSlot: bigint;
+  attestedSlot: bigint;
+}
+
+/** Pool accounting checked against its accounts by verify_reserves */
+export interface ReservesReport {
+  vaultBalance: bigint;
+  shieldedBalance: bigint;
+  /** Vault holds at least the shielded balance */
+  isSolvent: boolean;
+  /** The tree's leaf layer holds exactly next_index commitments */
+  leafCountMatches: boolean;
+  /** The legacy nullifier set records no more spends than there are notes */
+  nullifierCountValid: boolean;
+}
+
+export interface ReserveReport {
+  snapshot: ReserveSnapshot;
+  /** JSON body that was signed */
+  content: string;
+  /** Wallet that signed the report */
+  signer: string;
+  /** Hex Ed25519 signature over `content` */
+  signature: string;
+}
+
+/**
+ * Decode get_reserve_snapshot return data
+ */
+export function decodeReserveSnapshot(raw: Buffer): ReserveSnapshot {
+  // Trailing zero bytes are trimmed from return data
+  const data = Buffer.concat([raw, Buffer.alloc(Math.max(0, RESERVE_SNAPSHOT_SIZE - raw.length))]);
+  const version = data.readUInt8(0);
+  if (version !== RESERVE_SNAPSHOT_VERSION) {
+    throw new Error(`Unsupported reserve snapshot version ${version} (expected ${RESERVE_SNAPSHOT_VERSION})`);
+  }
+  return {
+    version,
+    slot: data.readBigUInt64LE(1),
+    vaultLamports: data.readBigUInt64LE(9),
+    feeVaultLamports: data.readBigUInt64LE(17),
+    totalDeposits: data.readBigUInt64LE(25),
+    totalShielded: data.readBigUInt64LE(33),
+    totalWithdrawn: data.readBigUInt64LE(41),
+    totalFeesCollected: data.readBigUInt64LE(49),
+    surplus: data.readBigInt64LE(57),
+    leafCount: data.readBigUInt64LE(65),
+    currentRoot: new Uint8Array(data.subarray(73, 105)),
+  };
+}
+
+// PKCS#8 DER prefix for a raw Ed25519 private key seed
+const ED25519_PKCS8_PREFIX = Buffer.from('302e020100300506032b657004220420', 'hex');
+
+function instructionDiscriminator(name: string): Buffer {
+  return createHash('sha256').update(`global:${name}`).digest().subarray(0, 8);
+}
+
 export interface WithdrawResult {
   signature: string;
   recipient: PublicKey;
@@ -35,11 +176,15 @@ export class WhistleClient {
   private connection: Connection;
   private wallet: Keypair;
   private programId: PublicKey;
+  private multiRpc?: MultiRpc;
+  private transactions: TransactionManager;
 
   constructor(config: WhistleConfig) {
     this.connection = config.connection;
     this.wallet = config.wallet;
     this.programId = config.programId || POOL_PROGRAM_ID;
+    this.multiRpc = config.multiRpc;
+    this.transactions = config.transactionManager ?? new TransactionManager({ connection: config.connection });
   }
 
   /**
@@ -97,6 +242,97 @@ export class WhistleClient {
     return pda;
   }
 
+  /**
+   * Marker account a spend of `nullifierHash` creates
+   */
+  getNullifierMarkerAddress(nullifierHash: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('nullifier'), Buffer.from(nullifierHash)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Marker account recording the leaf `commitment` was inserted at
+   */
+  getCommitmentMarkerAddress(commitment: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('commitment'), Buffer.from(commitment)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Leaf index of `commitment`, or null if it has no marker (not inserted,
+   * or inserted before commitment markers existed)
+   */
+  async getCommitmentLeafIndex(commitment: Uint8Array): Promise<bigint | null> {
+    const account = await this.connection.getAccountInfo(this.getCommitmentMarkerAddress(commitment));
+    if (!account || !account.owner.equals(this.programId)) {
+      return null;
+    }
+
+    // Layout (after 8 byte discriminator): commitment [u8; 32], leaf_index u64, bump u8
+    return account.data.readBigUInt64LE(8 + 32);
+  }
+
+  /**
+   * Get denomination config PDA address
+   */
+  getDenominationConfigAddress(): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('denomination_config')],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Read the per-denomination relayer fee caps (basis points) fixed at genesis
+   */
+  async getRelayerFeeCaps(): Promise<{ denomination: bigint; capBps: number }[]> {
+    const account = await this.connection.getAccountInfo(this.getDenominationConfigAddress());
+    if (!account) {
+      throw new Error('Denomination config not initialized');
+    }
+
+    // Layout (after 8 byte discriminator): denominations [u64; 6], relayer_fee_caps_bps [u16; 6]
+    const data = account.data.slice(8);
+    return WITHDRAW_DENOMINATIONS.map((_, i) => ({
+      denomination: data.readBigUInt64LE(i * 8),
+      capBps: data.readUInt16LE(48 + i * 2),
+    }));
+  }
+
+  /**
+   * Estimate the maximum relayer fee (lamports) the pool accepts for a withdrawal
+   */
+  async estimateMaxRelayerFee(amountLamports: bigint): Promise<bigint> {
+    const caps = await this.getRelayerFeeCaps();
+    const entry = caps.find(c => c.denomination === amountLamports);
+    if (!entry) {
+      throw new Error('Invalid withdrawal denomination');
+    }
+    return (amountLamports * BigInt(entry.capBps)) / BPS_DENOMINATOR;
+  }
+
+  /**
+   * Plan a withdrawal of `targetAmount` from `notes` (see selectNotes),
+   * costed at the pool's relayer fee caps
+   */
+  async planWithdrawal(notes: SpendableNote[], targetAmount: bigint, strategy: SelectionStrategy): Promise<SpendPlan> {
+    const caps = await this.getRelayerFeeCaps();
+    return selectNotes(notes, targetAmount, strategy, {
+      relayerFee: (denomination) => {
+        const entry = caps.find(c => c.denomination === denomination);
+        return entry ? (denomination * BigInt(entry.capBps)) / BPS_DENOMINATOR : BigInt(0);
+      },
+      transactionFee: LAMPORTS_PER_SIGNATURE,
+    });
+  }
+
   /**
    * Deposit SOL into the privacy pool
    */
@@ -134,11 +370,7 @@ export class WhistleClient {
     });
 
     tx.feePayer = this.wallet.publicKey;
-    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
-    tx.sign(this.wallet);
-
-    const signature = await this.connection.sendRawTransaction(tx.serialize());
-    await this.connection.confirmTransaction(signature, 'confirmed');
+    const signature = await this.transactions.sendAndConfirmWithRetry(tx, [this.wallet]);
 
     return {
       signature,
@@ -187,6 +419,7 @@ export class WhistleClient {
         { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
         { pubkey: this.getNullifiersAddress(), isSigner: false, isWritable: true },
         { pubkey: this.getRootsHistoryAddress(), isSigner: false, isWritable: false },
+        { pubkey: this.getDenominationConfigAddress(), isSigner: false, isWritable: false },
         { pubkey: this.getVaultAddress(), isSigner: false, isWritable: true },
         { pubkey: recipient, isSigner: false, isWritable: true },
         { pubkey: this.wallet.publicKey, isSigner: false, isWritable: true },
@@ -197,11 +430,7 @@ export class WhistleClient {
     });
 
     tx.feePayer = this.wallet.publicKey;
-    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
-    tx.sign(this.wallet);
-
-    const signature = await this.connection.sendRawTransaction(tx.serialize());
-    await this.connection.confirmTransaction(signature, 'confirmed');
+    const signature = await this.transactions.sendAndConfirmWithRetry(tx, [this.wallet]);
 
     return {
       signature,
@@ -218,7 +447,10 @@ export class WhistleClient {
     nextIndex: number;
     totalDeposits: number;
   }> {
-    const poolAccount = await this.connection.getAccountInfo(this.getPoolAddress());
+    // The root feeds proof generation, so require quorum when configured
+    const poolAccount = this.multiRpc
+      ? await this.multiRpc.getAccountInfo(this.getPoolAddress())
+      : await this.connection.getAccountInfo(this.getPoolAddress());
     if (!poolAccount) {
       throw new Error('Pool not initialized');
     }
@@ -237,28 +469,559 @@ export class WhistleClient {
   }
 
   /**
-   * Check if nullifier has been spent
+   * Read one page of the spent-nullifier ledger (simulated, no fee)
    */
-  async isNullifierSpent(nullifierHash: Uint8Array): Promise<boolean> {
-    const nullifiersAccount = await this.connection.getAccountInfo(
-      this.getNullifiersAddress()
+  async getSpentNullifiers(start: bigint, count: number = MAX_NULLIFIER_PAGE): Promise<SpentNullifier[]> {
+    const startBuffer = Buffer.alloc(8);
+    startBuffer.writeBigUInt64LE(start);
+
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [{ pubkey: this.getNullifiersAddress(), isSigner: false, isWritable: false }],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('get_spent_nullifiers'), startBuffer, Buffer.from([count])]),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`get_spent_nullifiers failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+
+    // Return data: Vec<SpentNullifier> = u32 length + (nullifier 32, slot u64, seq u64) entries
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      return [];
+    }
+    const data = Buffer.from(returnData.data[0], 'base64');
+    const len = data.readUInt32LE(0);
+    const entries: SpentNullifier[] = [];
+    for (let i = 0; i < len; i++) {
+      const offset = 4 + i * 48;
+      entries.push({
+        nullifier: new Uint8Array(data.subarray(offset, offset + 32)),
+        slot: data.readBigUInt64LE(offset + 32),
+        seq: data.readBigUInt64LE(offset + 40),
+      });
+    }
+    return entries;
+  }
+
+  /**
+   * Read leaves of leaf page `pageIndex` from offset `start` (simulated, no
+   * fee); only the 8KB page is loaded, not the Merkle tree account
+   *
+   * A short result means the rest of the page is not inserted or not yet
+   * synced (see TransactionBuilder.syncLeafPage).
+   */
+  async getLeavesPage(pageIndex: number, start: number = 0, count: number = MAX_LEAF_READ): Promise<Uint8Array[]> {
+    const page = Buffer.alloc(4);
+    page.writeUInt32LE(pageIndex);
+    const [leafPage] = PublicKey.findProgramAddressSync(
+      [Buffer.from('leaf_page'), this.getPoolAddress().toBuffer(), page],
+      this.programId
+    );
+    const args = Buffer.alloc(3);
+    args.writeUInt16LE(start, 0);
+    args.writeUInt8(count, 2);
+
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
+          { pubkey: leafPage, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('get_leaves_page'), page, args]),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`get_leaves_page failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+
+    // Return data: Vec<[u8; 32]> = u32 length + leaves; trailing zeroes are trimmed
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      return [];
+    }
+    const raw = Buffer.from(returnData.data[0], 'base64');
+    const data = Buffer.concat([raw, Buffer.alloc(Math.max(0, 4 + 32 * MAX_LEAF_READ - raw.length))]);
+    const len = data.readUInt32LE(0);
+    const leaves: Uint8Array[] = [];
+    for (let i = 0; i < len; i++) {
+      leaves.push(new Uint8Array(data.subarray(4 + i * 32, 4 + (i + 1) * 32)));
+    }
+    return leaves;
+  }
+
+  /**
+   * Sibling path of leaf `leafIndex` against the pool's current root
+   * (simulated, no fee); the program reads the Merkle tree account, so
+   * only the ~450-byte path is downloaded
+   */
+  async getMerkleProof(leafIndex: bigint): Promise<MerkleProof & { leafIndex: bigint }> {
+    const leafIndexBuffer = Buffer.alloc(8);
+    leafIndexBuffer.writeBigUInt64LE(leafIndex);
+
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
+          { pubkey: this.getMerkleTreeAddress(), isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('generate_merkle_proof'), leafIndexBuffer]),
+      })
     );
-    if (!nullifiersAccount) {
-      return false;
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err || !simulation.value.returnData) {
+      throw new Error(`generate_merkle_proof failed: ${JSON.stringify(simulation.value.err)}`);
     }
 
-    // Search through nullifier set
-    const data = nullifiersAccount.data.slice(8);
-    const count = data.readUInt16LE(32 * 256);
+    // Return data: MerklePath = u32 length + siblings, u32 length + indices,
+    // root, leaf_index u64; trailing zeroes are trimmed
+    const raw = Buffer.from(simulation.value.returnData.data[0], 'base64');
+    const levels = raw.readUInt32LE(0);
+    const size = 4 + 32 * levels + 4 + levels + 32 + 8;
+    const data = Buffer.concat([raw, Buffer.alloc(Math.max(0, size - raw.length))]);
+    const pathElements: Uint8Array[] = [];
+    for (let i = 0; i < levels; i++) {
+      pathElements.push(new Uint8Array(data.subarray(4 + i * 32, 4 + (i + 1) * 32)));
+    }
+    const indicesOffset = 4 + 32 * levels + 4;
+    const rootOffset = indicesOffset + levels;
+    return {
+      pathElements,
+      pathIndices: Array.from(data.subarray(indicesOffset, rootOffset)),
+      root: new Uint8Array(data.subarray(rootOffset, rootOffset + 32)),
+      leafIndex: data.readBigUInt64LE(rootOffset + 32),
+    };
+  }
 
-    for (let i = 0; i < count; i++) {
-      const stored = data.slice(i * 32, (i + 1) * 32);
-      if (Buffer.from(nullifierHash).equals(stored)) {
-        return true;
+  /**
+   * Nullifiers spent since the legacy set was frozen, read from their
+   * marker accounts and sorted by (slot, hash); `seq` continues after
+   * `legacyCount`
+   */
+  async getNullifierMarkers(legacyCount: bigint): Promise<SpentNullifier[]> {
+    const discriminator = createHash('sha256').update('account:NullifierMarker').digest().subarray(0, 8);
+    const accounts = await this.connection.getProgramAccounts(this.programId, {
+      filters: [{ dataSize: NULLIFIER_MARKER_SIZE }],
+    });
+    const markers = accounts
+      .map(({ account }) => account.data)
+      .filter((data) => data.subarray(0, 8).equals(discriminator))
+      .map((data) => ({ nullifier: new Uint8Array(data.subarray(8, 40)), slot: data.readBigUInt64LE(40) }))
+      .sort((a, b) => (a.slot === b.slot ? Buffer.compare(a.nullifier, b.nullifier) : a.slot < b.slot ? -1 : 1));
+    return markers.map((marker, i) => ({ ...marker, seq: legacyCount + BigInt(i) }));
+  }
+
+  /**
+   * Export the full spent-nullifier ledger as a report signed by the wallet:
+   * the legacy set in spend order, then the nullifier markers
+   */
+  async exportNullifierLedger(format: 'csv' | 'json' = 'csv'): Promise<NullifierLedgerReport> {
+    const entries: SpentNullifier[] = [];
+    for (;;) {
+      const page = await this.getSpentNullifiers(BigInt(entries.length));
+      entries.push(...page);
+      if (page.length < MAX_NULLIFIER_PAGE) {
+        break;
       }
     }
+    entries.push(...(await this.getNullifierMarkers(BigInt(entries.length))));
+
+    const rows = entries.map((e) => ({
+      seq: e.seq.toString(),
+      slot: e.slot.toString(),
+      nullifier: Buffer.from(e.nullifier).toString('hex'),
+    }));
+
+    const content = format === 'json'
+      ? JSON.stringify({ programId: this.programId.toBase58(), nullifiers: rows }, null, 2)
+      : ['seq,slot,nullifier', ...rows.map((r) => `${r.seq},${r.slot},${r.nullifier}`)].join('\n');
+
+    return {
+      format,
+      content,
+      signer: this.wallet.publicKey.toBase58(),
+      signature: this.signReport(content),
+    };
+  }
+
+  /**
+   * Read vault balances and ledger totals in one slot (simulated, no fee)
+   */
+  async getReserveSnapshot(): Promise<ReserveSnapshot> {
+    const [feeVault] = PublicKey.findProgramAddressSync([Buffer.from('fee_vault')], this.programId);
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
+          { pubkey: this.getVaultAddress(), isSigner: false, isWritable: false },
+          { pubkey: feeVault, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('get_reserve_snapshot'),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`get_reserve_snapshot failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('get_reserve_snapshot returned no data');
+    }
+    return decodeReserveSnapshot(Buffer.from(returnData.data[0], 'base64'));
+  }
+
+  /**
+   * Check pool accounting against the vault, tree and nullifier set
+   * (simulated, no fee); poll it to catch accounting drift
+   */
+  async verifyReserves(): Promise<ReservesReport> {
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
+          { pubkey: this.getVaultAddress(), isSigner: false, isWritable: false },
+          { pubkey: this.getMerkleTreeAddress(), isSigner: false, isWritable: false },
+          { pubkey: this.getNullifiersAddress(), isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('verify_reserves'),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`verify_reserves failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+
+    // Two balances (8 each) + three flags; trailing zeroes are trimmed
+    const raw = Buffer.from(simulation.value.returnData?.data[0] ?? '', 'base64');
+    const data = Buffer.concat([raw, Buffer.alloc(Math.max(0, 8 * 2 + 3 - raw.length))]);
+    return {
+      vaultBalance: data.readBigUInt64LE(0),
+      shieldedBalance: data.readBigUInt64LE(8),
+      isSolvent: data[16] !== 0,
+      leafCountMatches: data[17] !== 0,
+      nullifierCountValid: data[18] !== 0,
+    };
+  }
+
+  /**
+   * Read the latest finality attestation (simulated, no fee); null if the
+   * program was never attested (see TransactionBuilder.attestFinality)
+   *
+   * Wallets can show "immutable since slot immutableSinceSlot" when
+   * `immutable` is set.
+   */
+  async getFinalityAttestation(): Promise<FinalityAttestation | null> {
+    const [attestation] = PublicKey.findProgramAddressSync(
+      [Buffer.from('finality_attestation'), this.getPoolAddress().toBuffer()],
+      this.programId
+    );
+    if (!(await this.connection.getAccountInfo(attestation))) {
+      return null;
+    }
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
+          { pubkey: attestation, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('get_finality_attestation'),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`get_finality_attestation failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+
+    // immutable (1) + deployed, since and attested slots (8 each) + bump; trailing zeroes are trimmed
+    const raw = Buffer.from(simulation.value.returnData?.data[0] ?? '', 'base64');
+    const data = Buffer.concat([raw, Buffer.alloc(Math.max(0, 1 + 8 * 3 + 1 - raw.length))]);
+    return {
+      immutable: data[0] !== 0,
+      deployedSlot: data.readBigUInt64LE(1),
+      immutableSinceSlot: data.readBigUInt64LE(9),
+      attestedSlot: data.readBigUInt64LE(17),
+    };
+  }
+
+  /**
+   * Export a proof-of-reserve report signed by the wallet
+   */
+  async exportReserveReport(): Promise<ReserveReport> {
+    const snapshot = await this.getReserveSnapshot();
+    const content = JSON.stringify(
+      {
+        programId: this.programId.toBase58(),
+        ...snapshot,
+        currentRoot: Buffer.from(snapshot.currentRoot).toString('hex'),
+      },
+      (_, value) => (typeof value === 'bigint' ? value.toString() : value),
+      2
+    );
+
+    return {
+      snapshot,
+      content,
+      signer: this.wallet.publicKey.toBase58(),
+      signature: this.signReport(content),
+    };
+  }
+
+  /**
+   * Whether this SDK's Poseidon matches the deployed program's (simulated
+   * verify_commitment_compatibility, no fee)
+   *
+   * False means commitments built here will never be found in the pool;
+   * check before depositing.
+   */
+  async checkCommitmentCompatibility(): Promise<boolean> {
+    const vectors = await commitmentCompatibilityVectors();
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('verify_commitment_compatibility'), ...vectors]),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`verify_commitment_compatibility failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    // Return data: bool. Trailing zero bytes are trimmed, so false is empty.
+    const returnData = simulation.value.returnData;
+    const raw = returnData ? Buffer.from(returnData.data[0], 'base64') : Buffer.alloc(0);
+    return raw.length > 0 && raw[0] === 1;
+  }
+
+  /**
+   * Withdrawal and relayer approval rates for fee quotes (simulated
+   * get_congestion_info, no fee); see suggestFee
+   */
+  async getCongestionInfo(): Promise<CongestionInfo> {
+    const [congestion] = PublicKey.findProgramAddressSync([Buffer.from('congestion')], this.programId);
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [{ pubkey: congestion, isSigner: false, isWritable: false }],
+        programId: this.programId,
+        data: instructionDiscriminator('get_congestion_info'),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`get_congestion_info failed: ${JSON.stringify(simulation.value.err)}`);
+    }
+    const returnData = simulation.value.returnData;
+    if (!returnData) {
+      throw new Error('get_congestion_info returned no data');
+    }
+    return decodeCongestionInfo(Buffer.from(returnData.data[0], 'base64'));
+  }
+
+  /**
+   * Copy the raw pool state accounts via export_state_chunk (simulated, no fee)
+   *
+   * Chunks are read in separate simulations, so snapshot a pool that is not
+   * taking transactions.
+   */
+  async snapshotPool(): Promise<PoolStateSnapshot> {
+    const snapshot = {} as PoolStateSnapshot;
+    for (const [name, kind] of Object.entries(STATE_ACCOUNT_KINDS) as [keyof PoolStateSnapshot, number][]) {
+      const chunks: Buffer[] = [];
+      let offset = 0;
+      for (;;) {
+        const args = Buffer.alloc(5);
+        args.writeUInt8(kind, 0);
+        args.writeUInt32LE(offset, 1);
+        const tx = new Transaction().add(
+          new TransactionInstruction({
+            keys: [{ pubkey: this.getStateAccountAddress(kind), isSigner: false, isWritable: false }],
+            programId: this.programId,
+            data: Buffer.concat([instructionDiscriminator('export_state_chunk'), args]),
+          })
+        );
+        tx.feePayer = this.wallet.publicKey;
+        tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+        const simulation = await this.connection.simulateTransaction(tx);
+        if (simulation.value.err) {
+          throw new Error(`export_state_chunk failed: ${JSON.stringify(simulation.value.err)}`);
+        }
+
+        // Return data: Vec<u8> = u32 length + bytes. Trailing zero bytes are
+        // trimmed, so pad back to the full length.
+        const returnData = simulation.value.returnData;
+        const raw = returnData ? Buffer.from(returnData.data[0], 'base64') : Buffer.alloc(0);
+        const len = Buffer.concat([raw, Buffer.alloc(4)]).readUInt32LE(0);
+        if (len === 0) {
+          break;
+        }
+        chunks.push(Buffer.concat([raw, Buffer.alloc(4 + len)]).subarray(4, 4 + len));
+        offset += len;
+      }
+      snapshot[name] = new Uint8Array(Buffer.concat(chunks));
+    }
+    return snapshot;
+  }
+
+  /**
+   * Write a snapshotPool result into this client's pool with import_state_chunk
+   *
+   * Only test-harness builds (test validators) accept the import. The target
+   * pool must be initialized with the same account sizes. Vault balances are
+   * not copied; fund the vault separately if withdrawals must succeed.
+   */
+  async restorePool(snapshot: PoolStateSnapshot): Promise<void> {
+    for (const [name, kind] of Object.entries(STATE_ACCOUNT_KINDS) as [keyof PoolStateSnapshot, number][]) {
+      const bytes = snapshot[name];
+      for (let offset = 0; offset < bytes.length; offset += STATE_IMPORT_CHUNK_SIZE) {
+        const chunk = Buffer.from(bytes.subarray(offset, offset + STATE_IMPORT_CHUNK_SIZE));
+        const args = Buffer.alloc(9);
+        args.writeUInt8(kind, 0);
+        args.writeUInt32LE(offset, 1);
+        args.writeUInt32LE(chunk.length, 5);
+        const tx = new Transaction().add(
+          new TransactionInstruction({
+            keys: [{ pubkey: this.getStateAccountAddress(kind), isSigner: false, isWritable: true }],
+            programId: this.programId,
+            data: Buffer.concat([instructionDiscriminator('import_state_chunk'), args, chunk]),
+          })
+        );
+        tx.feePayer = this.wallet.publicKey;
+        await this.transactions.sendAndConfirmWithRetry(tx, [this.wallet]);
+      }
+    }
+  }
+
+  private getStateAccountAddress(kind: number): PublicKey {
+    switch (kind) {
+      case STATE_ACCOUNT_KINDS.pool:
+        return this.getPoolAddress();
+      case STATE_ACCOUNT_KINDS.merkleTree:
+        return this.getMerkleTreeAddress();
+      case STATE_ACCOUNT_KINDS.rootsHistory:
+        return this.getRootsHistoryAddress();
+      default:
+        return this.getNullifiersAddress();
+    }
+  }
+
+  /**
+   * Hex Ed25519 signature over `content` with the wallet key
+   */
+  private signReport(content: string): string {
+    const privateKey = createPrivateKey({
+      key: Buffer.concat([ED25519_PKCS8_PREFIX, Buffer.from(this.wallet.secretKey.subarray(0, 32))]),
+      format: 'der',
+      type: 'pkcs8',
+    });
+    return sign(null, Buffer.from(content), privateKey).toString('hex');
+  }
+
+  /**
+   * Check many nullifiers with one simulated call per NULLIFIER_STATUS_BATCH hashes
+   */
+  async checkNullifiersSpent(hashes: Uint8Array[]): Promise<boolean[]> {
+    const spent: boolean[] = [];
+    for (let start = 0; start < hashes.length; start += NULLIFIER_STATUS_BATCH) {
+      const batch = hashes.slice(start, start + NULLIFIER_STATUS_BATCH);
+      // Pad with zero hashes, which are never spent
+      const padded = [...batch, ...Array(NULLIFIER_STATUS_BATCH - batch.length).fill(new Uint8Array(32))];
+
+      // The program also reads each hash's marker, passed in hash order
+      const tx = new Transaction().add(
+        new TransactionInstruction({
+          keys: [
+            { pubkey: this.getNullifiersAddress(), isSigner: false, isWritable: false },
+            ...padded.map((h) => ({ pubkey: this.getNullifierMarkerAddress(h), isSigner: false, isWritable: false })),
+          ],
+          programId: this.programId,
+          data: Buffer.concat([instructionDiscriminator('batch_nullifier_status'), ...padded.map((h) => Buffer.from(h))]),
+        })
+      );
+      tx.feePayer = this.wallet.publicKey;
+      tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+      const simulation = await this.connection.simulateTransaction(tx);
+      if (simulation.value.err) {
+        throw new Error(`batch_nullifier_status failed: ${JSON.stringify(simulation.value.err)}`);
+      }
+
+      // Return data: u16 bitmask, bit i set when hash i is spent. Trailing
+      // zero bytes are trimmed from return data, so pad back to 2 bytes.
+      const returnData = simulation.value.returnData;
+      const raw = returnData ? Buffer.from(returnData.data[0], 'base64') : Buffer.alloc(0);
+      const mask = Buffer.concat([raw, Buffer.alloc(2)]).readUInt16LE(0);
+      batch.forEach((_, i) => spent.push((mask & (1 << i)) !== 0));
+    }
+    return spent;
+  }
+
+  /**
+   * Check if nullifier has been spent, in the legacy set or by its marker
+   */
+  async isNullifierSpent(nullifierHash: Uint8Array): Promise<boolean> {
+    const [spent] = await this.checkNullifiersSpent([nullifierHash]);
+    return spent;
+  }
+
+  /**
+   * Check that a spend could prove against `merkleRoot` now: it is the
+   * current root or still in the roots history
+   */
+  async isKnownRoot(merkleRoot: Uint8Array): Promise<boolean> {
+    const tx = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.getPoolAddress(), isSigner: false, isWritable: false },
+          { pubkey: this.getRootsHistoryAddress(), isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('check_root'), Buffer.from(merkleRoot)]),
+      })
+    );
+    tx.feePayer = this.wallet.publicKey;
+    tx.recentBlockhash = (await this.connection.getLatestBlockhash()).blockhash;
+
+    const simulation = await this.connection.simulateTransaction(tx);
+    if (simulation.value.err) {
+      throw new Error(`check_root failed: ${JSON.stringify(simulation.value.err)}`);
+    }
 
-    return false;
+    // Return data: one bool byte; false is trimmed to nothing
+    const returnData = simulation.value.returnData;
+    const raw = returnData ? Buffer.from(returnData.data[0], 'base64') : Buffer.alloc(0);
+    return raw.length > 0 && raw[0] === 1;
   }
 }
 
@@ -0,0 +1,78 @@
+import { BPS_DENOMINATOR, DEFAULT_RELAYER_FEE_CAPS_BPS, WITHDRAW_DENOMINATIONS } from './core/constants';
+
+/**
+ * Congestion-aware relayer fees
+ *
+ * get_congestion_info reports withdrawals and relayer approvals in the
+ * current and previous window of `windowSlots` slots. suggestFee maps that
+ * load onto a published curve: a quarter of the denomination's fee cap on
+ * an idle pool, rising linearly to the full cap once the load reaches the
+ * pool's `windowTarget`.
+ */
+
+/** get_congestion_info return data */
+export interface CongestionInfo {
+  slot: bigint;
+  withdrawalsThisWindow: number;
+  withdrawalsLastWindow: number;
+  /** Relayer withdrawals approved, each a job still to submit or just submitted */
+  approvalsThisWindow: number;
+  approvalsLastWindow: number;
+  windowSlots: bigint;
+  /** Load at which suggestFee reaches the fee cap */
+  windowTarget: number;
+}
+
+// Borsh size of CongestionInfo: slot, four u32 counts, window_slots, window_target
+const CONGESTION_INFO_SIZE = 8 + 4 * 4 + 8 + 4;
+
+/** Share of the fee cap (in bps of the cap) suggested on an idle pool */
+export const IDLE_FEE_SHARE_BPS = BigInt(2_500);
+
+/**
+ * Decode get_congestion_info return data
+ */
+export function decodeCongestionInfo(raw: Buffer): CongestionInfo {
+  // Trailing zero bytes are trimmed from return data
+  const data = Buffer.concat([raw, Buffer.alloc(Math.max(0, CONGESTION_INFO_SIZE - raw.length))]);
+  return {
+    slot: data.readBigUInt64LE(0),
+    withdrawalsThisWindow: data.readUInt32LE(8),
+    withdrawalsLastWindow: data.readUInt32LE(12),
+    approvalsThisWindow: data.readUInt32LE(16),
+    approvalsLastWindow: data.readUInt32LE(20),
+    windowSlots: data.readBigUInt64LE(24),
+    windowTarget: data.readUInt32LE(32),
+  };
+}
+
+/**
+ * Withdrawals the pool is handling per window: the busier of the current
+ * and previous window (the current one may have just started), plus
+ * approved jobs not yet seen as withdrawals
+ */
+export function congestionLoad(congestion: CongestionInfo): number {
+  const withdrawals = Math.max(congestion.withdrawalsThisWindow, congestion.withdrawalsLastWindow);
+  const pendingApprovals = Math.max(0, congestion.approvalsThisWindow - congestion.withdrawalsThisWindow);
+  return withdrawals + pendingApprovals;
+}
+
+/**
+ * Relayer fee to quote for a withdrawal of `denomination` under `congestion`
+ *
+ * Never decreases as the load grows and never exceeds the default fee cap.
+ * Denominations the pool does not accept quote zero.
+ */
+export function suggestFee(denomination: bigint, congestion: CongestionInfo): bigint {
+  const i = WITHDRAW_DENOMINATIONS.indexOf(denomination as (typeof WITHDRAW_DENOMINATIONS)[number]);
+  if (i < 0) {
+    return BigInt(0);
+  }
+  const cap = (denomination * BigInt(DEFAULT_RELAYER_FEE_CAPS_BPS[i])) / BPS_DENOMINATOR;
+
+  const target = BigInt(Math.max(1, congestion.windowTarget));
+  const load = BigInt(congestionLoad(congestion));
+  const utilizationBps = load >= target ? BPS_DENOMINATOR : (load * BPS_DENOMINATOR) / target;
+  const shareBps = IDLE_FEE_SHARE_BPS + ((BPS_DENOMINATOR - IDLE_FEE_SHARE_BPS) * utilizationBps) / BPS_DENOMINATOR;
+  return (cap * shareBps) / BPS_DENOMINATOR;
+}
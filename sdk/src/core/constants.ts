@@ -20,6 +20,46 @@ export const DEPOSIT_AMOUNTS = {
   HUNDRED_SOL: BigInt(100_000_000_000),
 } as const;
 
+// Withdrawal denominations (in lamports), in on-chain DenominationConfig order
+export const WITHDRAW_DENOMINATIONS = [
+  BigInt(10_000_000),      // 0.01 SOL
+  BigInt(50_000_000),      // 0.05 SOL
+  BigInt(100_000_000),     // 0.1 SOL
+  BigInt(1_000_000_000),   // 1 SOL
+  BigInt(10_000_000_000),  // 10 SOL
+  BigInt(100_000_000_000), // 100 SOL
+] as const;
+
+// Default relayer fee caps (basis points) the pool is initialized with
+export const DEFAULT_RELAYER_FEE_CAPS_BPS = [1000, 1000, 500, 300, 100, 100] as const;
+export const BPS_DENOMINATOR = BigInt(10_000);
+
+// Protocol fee on shields and withdrawals (0.04%), paid to the fee vault
+export const PROTOCOL_FEE_BPS = BigInt(4);
+
+// Shields above PRE_COMMIT_THRESHOLD go through pre_commit_shield /
+// reveal_shield; the stake is refunded on a reveal within the window
+export const PRE_COMMIT_THRESHOLD = BigInt(1_000_000_000); // 1 SOL
+export const PRE_COMMIT_STAKE = BigInt(10_000_000); // 0.01 SOL
+export const PRE_COMMIT_REVEAL_SLOTS = 5;
+
+// Base fee per transaction signature, for self-submitted unshields
+export const LAMPORTS_PER_SIGNATURE = BigInt(5_000);
+
+// Internal Merkle nodes rebuild_root recomputes per call
+export const MAX_REBUILD_NODES = 128;
+
+// Leaf commitments per leaf page account
+export const LEAF_PAGE_SIZE = 256;
+
+// A relayer that leaves an approved withdrawal unsubmitted for
+// RELAYER_EXECUTION_TIMEOUT_SLOTS loses SLASH_AMOUNT of its bond to the user
+export const SLASH_AMOUNT = BigInt(50_000_000); // 0.05 SOL
+export const RELAYER_EXECUTION_TIMEOUT_SLOTS = 300;
+
+// Slots an intent lock holds other submitter nodes off a withdrawal
+export const INTENT_LOCK_TTL_SLOTS = 75;
+
 // Relayer defaults
 export const DEFAULT_RELAYER_FEE = BigInt(10_000_000); // 0.01 SOL
 export const MIN_RELAYER_FEE = BigInt(5_000_000); // 0.005 SOL
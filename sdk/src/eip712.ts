@@ -0,0 +1,139 @@
+import { PublicKey } from '@solana/web3.js';
+import { keccak_256 } from '@noble/hashes/sha3';
+import { POOL_PROGRAM_ID } from './client';
+import type { UnshieldParams } from './transactionBuilder';
+
+/**
+ * EIP-712 authorization of unshields
+ *
+ * For Ethereum-only hardware wallets (e.g. a Ledger on its Ethereum app):
+ * the wallet signs typed data over every public input of the unshield
+ * proof, and unshield_eip712 accepts it once the wallet's address has been
+ * mapped to the recipient with register_eth_address. Must match
+ * programs/whistle-pool/src/eip712.rs.
+ */
+
+export const EIP712_DOMAIN_NAME = 'Whistle Protocol';
+export const EIP712_DOMAIN_VERSION = '1';
+/** Domain chain ids the program builds with (the Solana token-list ids) */
+export const EIP712_CHAIN_ID_MAINNET = BigInt(101);
+export const EIP712_CHAIN_ID_DEVNET = BigInt(103);
+
+const DOMAIN_TYPE = 'EIP712Domain(string name,string version,uint256 chainId,bytes32 salt)';
+const UNSHIELD_TYPE =
+  'Unshield(bytes32 merkleRoot,bytes32 nullifierHash,bytes32 recipient,' +
+  'uint256 withdrawalAmount,uint256 relayerFee,bytes32 changeCommitment,uint256 unlockSlot)';
+const REGISTER_TYPE = 'RegisterEthAddress(bytes32 authorized,uint256 nonce)';
+
+function keccak(...parts: Uint8Array[]): Buffer {
+  return Buffer.from(keccak_256(Buffer.concat(parts)));
+}
+
+function text(value: string): Buffer {
+  return keccak(Buffer.from(value, 'utf8'));
+}
+
+function uint256(value: bigint): Buffer {
+  return Buffer.from(value.toString(16).padStart(64, '0'), 'hex');
+}
+
+function bytes32(value: Uint8Array): Buffer {
+  if (value.length !== 32) {
+    throw new Error(`bytes32 value must be 32 bytes, got ${value.length}`);
+  }
+  return Buffer.from(value);
+}
+
+function typedDataHash(chainId: bigint, programId: PublicKey, structHash: Buffer): Buffer {
+  const domainSeparator = keccak(
+    text(DOMAIN_TYPE),
+    text(EIP712_DOMAIN_NAME),
+    text(EIP712_DOMAIN_VERSION),
+    uint256(chainId),
+    programId.toBuffer()
+  );
+  return keccak(Buffer.from([0x19, 0x01]), domainSeparator, structHash);
+}
+
+/**
+ * Hash an Ethereum wallet signs to authorize the unshield `params`
+ * describes: what eth_signTypedData_v4 signs for unshieldTypedData(params)
+ */
+export function computeEip712Hash(
+  params: UnshieldParams,
+  chainId: bigint,
+  programId: PublicKey = POOL_PROGRAM_ID
+): Buffer {
+  const structHash = keccak(
+    text(UNSHIELD_TYPE),
+    bytes32(params.merkleRoot),
+    bytes32(params.nullifierHash),
+    params.recipient.toBuffer(),
+    uint256(params.withdrawalAmount),
+    uint256(params.relayerFee),
+    bytes32(params.changeCommitment),
+    uint256(params.unlockSlot ?? BigInt(0))
+  );
+  return typedDataHash(chainId, programId, structHash);
+}
+
+/**
+ * Hash an Ethereum wallet signs to map its address to `authorized`;
+ * `nonce` is the mapping's current nonce (0 when it does not exist yet)
+ */
+export function computeRegisterHash(
+  authorized: PublicKey,
+  nonce: bigint,
+  chainId: bigint,
+  programId: PublicKey = POOL_PROGRAM_ID
+): Buffer {
+  const structHash = keccak(text(REGISTER_TYPE), authorized.toBuffer(), uint256(nonce));
+  return typedDataHash(chainId, programId, structHash);
+}
+
+/**
+ * The unshield as eth_signTypedData_v4 input, so the wallet displays the
+ * fields it signs
+ */
+export function unshieldTypedData(
+  params: UnshieldParams,
+  chainId: bigint,
+  programId: PublicKey = POOL_PROGRAM_ID
+) {
+  const hex = (value: Uint8Array) => '0x' + Buffer.from(value).toString('hex');
+  return {
+    domain: {
+      name: EIP712_DOMAIN_NAME,
+      version: EIP712_DOMAIN_VERSION,
+      chainId: chainId.toString(),
+      salt: hex(programId.toBuffer()),
+    },
+    types: {
+      EIP712Domain: [
+        { name: 'name', type: 'string' },
+        { name: 'version', type: 'string' },
+        { name: 'chainId', type: 'uint256' },
+        { name: 'salt', type: 'bytes32' },
+      ],
+      Unshield: [
+        { name: 'merkleRoot', type: 'bytes32' },
+        { name: 'nullifierHash', type: 'bytes32' },
+        { name: 'recipient', type: 'bytes32' },
+        { name: 'withdrawalAmount', type: 'uint256' },
+        { name: 'relayerFee', type: 'uint256' },
+        { name: 'changeCommitment', type: 'bytes32' },
+        { name: 'unlockSlot', type: 'uint256' },
+      ],
+    },
+    primaryType: 'Unshield',
+    message: {
+      merkleRoot: hex(params.merkleRoot),
+      nullifierHash: hex(params.nullifierHash),
+      recipient: hex(params.recipient.toBuffer()),
+      withdrawalAmount: params.withdrawalAmount.toString(),
+      relayerFee: params.relayerFee.toString(),
+      changeCommitment: hex(params.changeCommitment),
+      unlockSlot: (params.unlockSlot ?? BigInt(0)).toString(),
+    },
+  };
+}
@@ -0,0 +1,129 @@
+import { createHash } from 'crypto';
+
+/**
+ * Tree events
+ *
+ * Every leaf the pool inserts is announced by one of Shielded (deposits),
+ * ChangeCreated (unshield change, auction refunds), NoteCreated (private
+ * transfer outputs) or TokenShielded (SPL token deposits). Each carries the slot and unix timestamp
+ * of a single Clock read; order by slot, then leaf index, and keep the
+ * timestamp for display. Batch shields announce their leaves together with
+ * one BatchShielded (decodeBatchShieldedEvent); the commitments are in the
+ * instruction data and the leaf pages. Unshielded events are decoded by
+ * decodeUnshieldedEvent (receipts.ts).
+ */
+
+export type LeafEventKind = 'Shielded' | 'ChangeCreated' | 'NoteCreated' | 'TokenShielded';
+
+export interface LeafEvent {
+  kind: LeafEventKind;
+  commitment: Uint8Array;
+  leafIndex: bigint;
+  /** Net amount shielded (Shielded, TokenShielded) */
+  amount?: bigint;
+  /** Protocol fee taken (Shielded only) */
+  protocolFee?: bigint;
+  /** Token mint (TokenShielded only) */
+  mint?: Uint8Array;
+  slot: bigint;
+  timestamp: bigint;
+}
+
+function eventDiscriminator(name: string): Buffer {
+  return createHash('sha256').update(`event:${name}`).digest().subarray(0, 8);
+}
+
+const LEAF_EVENT_DISCRIMINATORS: [LeafEventKind, Buffer][] = [
+  ['Shielded', eventDiscriminator('Shielded')],
+  ['ChangeCreated', eventDiscriminator('ChangeCreated')],
+  ['NoteCreated', eventDiscriminator('NoteCreated')],
+  ['TokenShielded', eventDiscriminator('TokenShielded')],
+];
+
+/**
+ * Decode a leaf event from a transaction log line's "Program data: <base64>"
+ * payload; null for any other event
+ */
+export function decodeLeafEvent(data: Buffer): LeafEvent | null {
+  const match = LEAF_EVENT_DISCRIMINATORS.find(([, discriminator]) => data.subarray(0, 8).equals(discriminator));
+  if (!match) {
+    return null;
+  }
+  // Shielded: commitment, leaf_index, amount, protocol_fee, slot, timestamp;
+  // TokenShielded: mint, commitment, leaf_index, amount, slot, timestamp;
+  // ChangeCreated / NoteCreated: commitment, leaf_index, slot, timestamp
+  const kind = match[0];
+  if (data.length < (kind === 'Shielded' ? 80 : kind === 'TokenShielded' ? 104 : 64)) {
+    return null;
+  }
+  if (kind === 'TokenShielded') {
+    return {
+      kind,
+      mint: new Uint8Array(data.subarray(8, 40)),
+      commitment: new Uint8Array(data.subarray(40, 72)),
+      leafIndex: data.readBigUInt64LE(72),
+      amount: data.readBigUInt64LE(80),
+      slot: data.readBigUInt64LE(88),
+      timestamp: data.readBigInt64LE(96),
+    };
+  }
+  const commitment = new Uint8Array(data.subarray(8, 40));
+  const leafIndex = data.readBigUInt64LE(40);
+
+  if (kind === 'Shielded') {
+    return {
+      kind,
+      commitment,
+      leafIndex,
+      amount: data.readBigUInt64LE(48),
+      protocolFee: data.readBigUInt64LE(56),
+      slot: data.readBigUInt64LE(64),
+      timestamp: data.readBigInt64LE(72),
+    };
+  }
+
+  return {
+    kind,
+    commitment,
+    leafIndex,
+    slot: data.readBigUInt64LE(48),
+    timestamp: data.readBigInt64LE(56),
+  };
+}
+
+/** A batch_shield or shield_batch_zk batch of leaves firstLeafIndex..=lastLeafIndex */
+export interface BatchShieldedEvent {
+  count: number;
+  /** Net amount the notes hold, after protocol fees */
+  totalAmount: bigint;
+  firstLeafIndex: bigint;
+  lastLeafIndex: bigint;
+  timestamp: bigint;
+}
+
+const BATCH_SHIELDED_DISCRIMINATOR = eventDiscriminator('BatchShielded');
+
+/**
+ * Decode a BatchShielded event from a "Program data: <base64>" payload; null
+ * for any other event
+ */
+export function decodeBatchShieldedEvent(data: Buffer): BatchShieldedEvent | null {
+  // count, total_amount, first_leaf_index, last_leaf_index, timestamp
+  if (data.length < 41 || !data.subarray(0, 8).equals(BATCH_SHIELDED_DISCRIMINATOR)) {
+    return null;
+  }
+  return {
+    count: data.readUInt8(8),
+    totalAmount: data.readBigUInt64LE(9),
+    firstLeafIndex: data.readBigUInt64LE(17),
+    lastLeafIndex: data.readBigUInt64LE(25),
+    timestamp: data.readBigInt64LE(33),
+  };
+}
+
+/** Sort comparator: slot, then leaf index (insertion order within a slot) */
+export function compareLeafEvents(a: LeafEvent, b: LeafEvent): number {
+  if (a.slot !== b.slot) return a.slot < b.slot ? -1 : 1;
+  if (a.leafIndex !== b.leafIndex) return a.leafIndex < b.leafIndex ? -1 : 1;
+  return 0;
+}
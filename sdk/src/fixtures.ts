@@ -0,0 +1,77 @@
+import { createHash } from 'crypto';
+// @ts-ignore
+import { buildPoseidon } from 'circomlibjs';
+
+/**
+ * Devnet fixtures
+ *
+ * Recomputes the notes minted by the pool's `devnet_seed_pool` instruction
+ * (insecure-devnet builds only) so tests can spend them. Must match the
+ * derivation in programs/whistle-pool/src/devnet.rs.
+ */
+
+/** Seeded note amounts, cycled by index (0.01, 0.05, 0.1 SOL) */
+export const DEVNET_SEED_AMOUNTS = [10_000_000n, 50_000_000n, 100_000_000n];
+
+const DEVNET_SECRET_DOMAIN = 'whistle-devnet-secret';
+const DEVNET_NULLIFIER_DOMAIN = 'whistle-devnet-nullifier';
+
+export interface SeededNote {
+  /** Index within the seeding batch */
+  index: number;
+  leafIndex: number;
+  secret: bigint;
+  nullifier: bigint;
+  amount: bigint;
+  commitment: bigint;
+  nullifierHash: bigint;
+}
+
+/**
+ * sha256(domain || seed_le || index) with the top byte cleared, as a field element
+ */
+export function deriveSeedField(domain: string, seed: bigint, index: number): bigint {
+  const seedBytes = Buffer.alloc(8);
+  seedBytes.writeBigUInt64LE(seed);
+
+  const digest = createHash('sha256')
+    .update(Buffer.from(domain))
+    .update(seedBytes)
+    .update(Buffer.from([index]))
+    .digest();
+  digest[0] = 0;
+  return BigInt('0x' + digest.toString('hex'));
+}
+
+/**
+ * Recompute the notes of a `devnet_seed_pool` batch from the seed it was
+ * called with (also in its DevnetPoolSeeded event). Call before seeding to
+ * get the commitments whose markers the instruction takes.
+ */
+export async function devnetSeededNotes(
+  seed: bigint,
+  firstLeafIndex: number,
+  count: number
+): Promise<SeededNote[]> {
+  const poseidon = await buildPoseidon();
+  const F = poseidon.F;
+  const hash = (a: bigint, b: bigint): bigint => BigInt(F.toString(poseidon([F.e(a.toString()), F.e(b.toString())])));
+
+  const notes: SeededNote[] = [];
+  for (let index = 0; index < count; index++) {
+    const secret = deriveSeedField(DEVNET_SECRET_DOMAIN, seed, index);
+    const nullifier = deriveSeedField(DEVNET_NULLIFIER_DOMAIN, seed, index);
+    const amount = DEVNET_SEED_AMOUNTS[index % DEVNET_SEED_AMOUNTS.length];
+
+    notes.push({
+      index,
+      leafIndex: firstLeafIndex + index,
+      secret,
+      nullifier,
+      amount,
+      commitment: hash(secret, hash(nullifier, amount)),
+      nullifierHash: hash(nullifier, 0n),
+    });
+  }
+  return notes;
+}
@@ -4,8 +4,29 @@
  * TypeScript SDK for interacting with the Whistle privacy pool.
  */
 
-export { WhistleClient, POOL_PROGRAM_ID } from './client';
-export type { WhistleConfig, DepositResult, WithdrawResult } from './client';
+export {
+  WhistleClient,
+  POOL_PROGRAM_ID,
+  MAX_NULLIFIER_PAGE,
+  MAX_LEAF_READ,
+  NULLIFIER_STATUS_BATCH,
+  RESERVE_SNAPSHOT_VERSION,
+  STATE_ACCOUNT_KINDS,
+  STATE_IMPORT_CHUNK_SIZE,
+  decodeReserveSnapshot,
+} from './client';
+export type {
+  WhistleConfig,
+  DepositResult,
+  WithdrawResult,
+  SpentNullifier,
+  NullifierLedgerReport,
+  ReserveSnapshot,
+  ReserveReport,
+  ReservesReport,
+  PoolStateSnapshot,
+  FinalityAttestation,
+} from './client';
 
 export {
   generateDepositNote,
@@ -27,6 +48,83 @@ export type {
   MerkleProof,
 } from './prover';
 
+export { TransactionBuilder, encodeProof, preCommitHash } from './transactionBuilder';
+export type {
+  EncodedProof,
+  UnshieldParams,
+  SelfUnshieldParams,
+  SelfSubmittedUnshield,
+  BatchWithdrawParams,
+  BatchUnshieldNote,
+  BatchUnshieldParams,
+  BatchShieldParams,
+  PrivateTransferParams,
+  TransferLeg,
+  DenominationSwapParams,
+} from './transactionBuilder';
+
+export { encodeSwapIntent, signSwapIntent, verifySwapIntent } from './swaps';
+export type { SwapIntent } from './swaps';
+
+export {
+  MEMO_PROGRAM_ID,
+  whistleLookupTableAddresses,
+  createWhistleLookupTable,
+  extendWhistleLookupTable,
+  verifyWhistleLookupTable,
+  loadLookupTable,
+  buildTransaction,
+  sendBuiltTransaction,
+} from './lookupTable';
+export type { LookupTableCheck } from './lookupTable';
+
+export {
+  BN254_SCALAR_MODULUS,
+  fieldLeToBe,
+  fieldBeToLe,
+  decimalStrToBeBytes,
+  isCanonicalFieldElement,
+} from './publicInputs';
+
+export {
+  MultiRpc,
+  RpcDivergenceError,
+  RpcQuorumError,
+  RpcOutlierError,
+  RpcContinuityError,
+} from './multiRpc';
+export type { MultiRpcConfig } from './multiRpc';
+
+export {
+  computeEip712Hash,
+  computeRegisterHash,
+  unshieldTypedData,
+  EIP712_DOMAIN_NAME,
+  EIP712_DOMAIN_VERSION,
+  EIP712_CHAIN_ID_MAINNET,
+  EIP712_CHAIN_ID_DEVNET,
+} from './eip712';
+
+export { TransactionManager, SimulationFailedError } from './transactionManager';
+export type { TransactionManagerConfig } from './transactionManager';
+
+export { decodeUnshieldedEvent, receiptHash, verifyReceipt } from './receipts';
+export type { UnshieldedEvent } from './receipts';
+
+export { decodeCongestionInfo, congestionLoad, suggestFee, IDLE_FEE_SHARE_BPS } from './congestion';
+export type { CongestionInfo } from './congestion';
+
+export { decodeLeafEvent, decodeBatchShieldedEvent, compareLeafEvents } from './events';
+export type { LeafEvent, LeafEventKind, BatchShieldedEvent } from './events';
+
+export { noteCommitment, commitmentCompatibilityVectors, COMMITMENT_COMPATIBILITY_INPUTS } from './notes';
+
+export { selectNotes, splitIntoDenominations, DEPOSIT_EPOCH_SLOTS, DEFAULT_FEE_ESTIMATE } from './noteSelection';
+export type { SelectionStrategy, SpendableNote, SpendStep, SpendPlan, PlanCosts, FeeEstimate } from './noteSelection';
+
+export { devnetSeededNotes, deriveSeedField, DEVNET_SEED_AMOUNTS } from './fixtures';
+export type { SeededNote } from './fixtures';
+
 export * from './core/constants';
 
 export { Connection, PublicKey, Keypair, LAMPORTS_PER_SOL } from '@solana/web3.js';
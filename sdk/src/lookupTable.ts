@@ -0,0 +1,185 @@
+import {
+  AddressLookupTableAccount,
+  AddressLookupTableProgram,
+  Connection,
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+  TransactionMessage,
+  VersionedTransaction,
+  sendAndConfirmTransaction,
+} from '@solana/web3.js';
+import { POOL_PROGRAM_ID, VERIFIER_PROGRAM_ID } from './client';
+
+export const MEMO_PROGRAM_ID = new PublicKey('MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr');
+
+// Addresses per extend instruction (keeps the transaction under the size limit)
+const EXTEND_CHUNK = 20;
+
+/** PDAs with fixed seeds, shared by every pool instruction */
+const STATIC_PDA_SEEDS = [
+  'pool',
+  'vault',
+  'fee_vault',
+  'merkle_tree',
+  'roots_history',
+  'nullifiers',
+  'denomination_config',
+  'pool_stats',
+  'deposit_histogram',
+  'congestion',
+];
+
+export interface LookupTableCheck {
+  /** Expected addresses absent from the table */
+  missing: PublicKey[];
+  /** Table entries that are not part of the current set */
+  extra: PublicKey[];
+}
+
+/**
+ * Addresses a Whistle lookup table should hold: the static pool PDAs plus
+ * the verifier, system and memo programs
+ */
+export function whistleLookupTableAddresses(programId: PublicKey = POOL_PROGRAM_ID): PublicKey[] {
+  return [
+    ...STATIC_PDA_SEEDS.map((seed) => PublicKey.findProgramAddressSync([Buffer.from(seed)], programId)[0]),
+    VERIFIER_PROGRAM_ID,
+    SystemProgram.programId,
+    MEMO_PROGRAM_ID,
+  ];
+}
+
+/**
+ * Add any missing Whistle addresses to `lookupTable`
+ * Returns the number of addresses added.
+ */
+export async function extendWhistleLookupTable(
+  connection: Connection,
+  authority: Keypair,
+  lookupTable: PublicKey,
+  programId: PublicKey = POOL_PROGRAM_ID
+): Promise<number> {
+  const { missing } = await verifyWhistleLookupTable(connection, lookupTable, programId);
+  for (let i = 0; i < missing.length; i += EXTEND_CHUNK) {
+    const tx = new Transaction().add(
+      AddressLookupTableProgram.extendLookupTable({
+        lookupTable,
+        authority: authority.publicKey,
+        payer: authority.publicKey,
+        addresses: missing.slice(i, i + EXTEND_CHUNK),
+      })
+    );
+    await sendAndConfirmTransaction(connection, tx, [authority], { commitment: 'confirmed' });
+  }
+  return missing.length;
+}
+
+/**
+ * Create a lookup table owned by `authority` holding every Whistle address
+ */
+export async function createWhistleLookupTable(
+  connection: Connection,
+  authority: Keypair,
+  programId: PublicKey = POOL_PROGRAM_ID
+): Promise<PublicKey> {
+  const [createIx, lookupTable] = AddressLookupTableProgram.createLookupTable({
+    authority: authority.publicKey,
+    payer: authority.publicKey,
+    recentSlot: await connection.getSlot('finalized'),
+  });
+  await sendAndConfirmTransaction(connection, new Transaction().add(createIx), [authority], {
+    commitment: 'confirmed',
+  });
+
+  await extendWhistleLookupTable(connection, authority, lookupTable, programId);
+  return lookupTable;
+}
+
+/**
+ * Compare a lookup table against the current Whistle address set
+ */
+export async function verifyWhistleLookupTable(
+  connection: Connection,
+  lookupTable: PublicKey,
+  programId: PublicKey = POOL_PROGRAM_ID
+): Promise<LookupTableCheck> {
+  const { value: table } = await connection.getAddressLookupTable(lookupTable);
+  if (!table) {
+    throw new Error(`Lookup table ${lookupTable.toBase58()} not found`);
+  }
+
+  const expected = whistleLookupTableAddresses(programId);
+  const has = (list: PublicKey[], key: PublicKey) => list.some((k) => k.equals(key));
+  return {
+    missing: expected.filter((key) => !has(table.state.addresses, key)),
+    extra: table.state.addresses.filter((key) => !has(expected, key)),
+  };
+}
+
+/**
+ * Load a lookup table if it exists, is active and has been extended
+ */
+export async function loadLookupTable(
+  connection: Connection,
+  lookupTable: PublicKey
+): Promise<AddressLookupTableAccount | null> {
+  const { value: table } = await connection.getAddressLookupTable(lookupTable);
+  if (!table || !table.isActive() || table.state.addresses.length === 0) {
+    return null;
+  }
+  return table;
+}
+
+/**
+ * Compile instructions into a v0 transaction using `lookupTable`, falling
+ * back to a legacy transaction when the table is unavailable
+ * Returned transactions are unsigned.
+ */
+export async function buildTransaction(
+  connection: Connection,
+  payer: PublicKey,
+  instructions: TransactionInstruction[],
+  lookupTable?: PublicKey
+): Promise<VersionedTransaction | Transaction> {
+  const { blockhash } = await connection.getLatestBlockhash();
+  const table = lookupTable ? await loadLookupTable(connection, lookupTable) : null;
+
+  if (!table) {
+    const tx = new Transaction().add(...instructions);
+    tx.feePayer = payer;
+    tx.recentBlockhash = blockhash;
+    return tx;
+  }
+
+  const message = new TransactionMessage({
+    payerKey: payer,
+    recentBlockhash: blockhash,
+    instructions,
+  }).compileToV0Message([table]);
+  return new VersionedTransaction(message);
+}
+
+/**
+ * Sign and send a transaction from buildTransaction
+ */
+export async function sendBuiltTransaction(
+  connection: Connection,
+  tx: VersionedTransaction | Transaction,
+  signers: Keypair[]
+): Promise<string> {
+  if (tx instanceof Transaction) {
+    return sendAndConfirmTransaction(connection, tx, signers, { commitment: 'confirmed' });
+  }
+
+  tx.sign(signers);
+  const { lastValidBlockHeight } = await connection.getLatestBlockhash();
+  const signature = await connection.sendTransaction(tx);
+  await connection.confirmTransaction(
+    { signature, blockhash: tx.message.recentBlockhash, lastValidBlockHeight },
+    'confirmed'
+  );
+  return signature;
+}
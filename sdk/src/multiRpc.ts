@@ -0,0 +1,259 @@
+import {
+  AccountInfo,
+  Commitment,
+  Connection,
+  ConfirmedSignatureInfo,
+  PublicKey,
+} from '@solana/web3.js';
+
+export interface MultiRpcConfig {
+  /** RPC endpoint URLs, or pre-built connections */
+  endpoints: (string | Connection)[];
+  /** Number of endpoints that must agree (defaults to a strict majority) */
+  quorum?: number;
+  commitment?: Commitment;
+}
+
+/**
+ * Base class for cross-check failures
+ */
+export class RpcDivergenceError extends Error {
+  constructor(message: string) {
+    super(message);
+    this.name = 'RpcDivergenceError';
+  }
+}
+
+/**
+ * Fewer than `quorum` endpoints returned the same response
+ */
+export class RpcQuorumError extends RpcDivergenceError {
+  constructor(
+    public readonly what: string,
+    public readonly quorum: number,
+    public readonly largestAgreement: number,
+    public readonly failedEndpoints: string[],
+  ) {
+    super(
+      `No quorum for ${what}: ${largestAgreement}/${quorum} endpoints agree` +
+        (failedEndpoints.length ? ` (unreachable: ${failedEndpoints.join(', ')})` : ''),
+    );
+    this.name = 'RpcQuorumError';
+  }
+}
+
+/**
+ * Quorum was reached but some endpoints returned a different response
+ */
+export class RpcOutlierError extends RpcDivergenceError {
+  constructor(
+    public readonly what: string,
+    public readonly outlierEndpoints: string[],
+  ) {
+    super(`Endpoints disagree with quorum on ${what}: ${outlierEndpoints.join(', ')}`);
+    this.name = 'RpcOutlierError';
+  }
+}
+
+/**
+ * An endpoint's signature history has a gap or reordering relative to quorum
+ */
+export class RpcContinuityError extends RpcDivergenceError {
+  constructor(
+    public readonly endpoint: string,
+    public readonly expected: string,
+    public readonly received: string | undefined,
+  ) {
+    super(`Endpoint ${endpoint} broke history continuity: expected ${expected}, got ${received ?? 'nothing'}`);
+    this.name = 'RpcContinuityError';
+  }
+}
+
+interface EndpointResult<T> {
+  endpoint: string;
+  value?: T;
+  key?: string;
+  error?: unknown;
+}
+
+/**
+ * Reads chain state from several RPC providers and only returns data a
+ * quorum of them agree on.
+ *
+ * A single malicious or buggy provider could otherwise serve a forged tree
+ * and have the wallet prove against a root the chain never had. Outliers
+ * are reported via `onOutlier`; pass `strict` to the read methods to throw
+ * on them instead.
+ */
+export class MultiRpc {
+  private connections: { endpoint: string; connection: Connection }[];
+  private quorum: number;
+
+  /** Called with an RpcOutlierError whenever a non-strict read sees disagreement */
+  onOutlier?: (error: RpcOutlierError) => void;
+
+  constructor(config: MultiRpcConfig) {
+    if (config.endpoints.length === 0) {
+      throw new Error('MultiRpc needs at least one endpoint');
+    }
+
+    this.connections = config.endpoints.map((e) =>
+      typeof e === 'string'
+        ? { endpoint: e, connection: new Connection(e, config.commitment ?? 'confirmed') }
+        : { endpoint: e.rpcEndpoint, connection: e },
+    );
+    this.quorum = config.quorum ?? Math.floor(this.connections.length / 2) + 1;
+
+    if (this.quorum < 1 || this.quorum > this.connections.length) {
+      throw new Error(`Quorum ${this.quorum} is outside 1..${this.connections.length}`);
+    }
+  }
+
+  get endpoints(): string[] {
+    return this.connections.map((c) => c.endpoint);
+  }
+
+  /**
+   * Fetch an account from every endpoint and return the quorum's view
+   */
+  async getAccountInfo(
+    address: PublicKey,
+    strict = false,
+  ): Promise<AccountInfo<Buffer> | null> {
+    return this.agree(
+      `account ${address.toBase58()}`,
+      (c) => c.getAccountInfo(address),
+      (info) => (info ? `${info.owner.toBase58()}:${info.lamports}:${info.data.toString('hex')}` : 'null'),
+      strict,
+    );
+  }
+
+  /**
+   * Fetch a byte range of an account (e.g. the pool's current root) and require agreement
+   */
+  async getAccountSlice(
+    address: PublicKey,
+    offset: number,
+    length: number,
+    strict = false,
+  ): Promise<Buffer | null> {
+    return this.agree(
+      `account ${address.toBase58()}[${offset}..${offset + length}]`,
+      async (c) => {
+        const info = await c.getAccountInfo(address);
+        return info ? Buffer.from(info.data.subarray(offset, offset + length)) : null;
+      },
+      (slice) => (slice ? slice.toString('hex') : 'null'),
+      strict,
+    );
+  }
+
+  /**
+   * Backfill transaction signatures for an address, oldest first
+   *
+   * Every endpoint's history must be a contiguous run of the quorum history:
+   * a provider that drops or reorders a transaction is reported as an outlier.
+   */
+  async getSignatureHistory(
+    address: PublicKey,
+    untilSignature?: string,
+    limit = 1000,
+    strict = false,
+  ): Promise<ConfirmedSignatureInfo[]> {
+    const results = await this.collect((c) =>
+      c.getSignaturesForAddress(address, { until: untilSignature, limit }),
+    );
+    const ok = results.filter((r) => r.value !== undefined);
+
+    if (ok.length < this.quorum) {
+      throw new RpcQuorumError(
+        `signature history of ${address.toBase58()}`,
+        this.quorum,
+        ok.length,
+        results.filter((r) => r.value === undefined).map((r) => r.endpoint),
+      );
+    }
+
+    // Take the longest history confirmed by quorum as the reference: a prefix
+    // agreed on by `quorum` endpoints that is as long as possible.
+    const histories = ok.map((r) => [...r.value!].reverse());
+    const reference: ConfirmedSignatureInfo[] = [];
+    for (let i = 0; ; i++) {
+      const counts = new Map<string, number>();
+      for (const h of histories) {
+        if (h[i] && h.slice(0, i).every((s, j) => s.signature === reference[j]?.signature)) {
+          counts.set(h[i].signature, (counts.get(h[i].signature) ?? 0) + 1);
+        }
+      }
+      const winner = [...counts.entries()].find(([, n]) => n >= this.quorum);
+      if (!winner) break;
+      reference.push(histories.find((h) => h[i]?.signature === winner[0])![i]);
+    }
+
+    // Each endpoint must agree with the reference wherever both have data
+    for (let k = 0; k < ok.length; k++) {
+      const history = histories[k];
+      for (let i = 0; i < Math.min(history.length, reference.length); i++) {
+        if (history[i].signature !== reference[i].signature) {
+          const error = new RpcContinuityError(ok[k].endpoint, reference[i].signature, history[i].signature);
+          if (strict) throw error;
+          this.onOutlier?.(new RpcOutlierError(error.message, [ok[k].endpoint]));
+          break;
+        }
+      }
+    }
+
+    return reference;
+  }
+
+  private async collect<T>(fetch: (c: Connection) => Promise<T>): Promise<EndpointResult<T>[]> {
+    return Promise.all(
+      this.connections.map(async ({ endpoint, connection }) => {
+        try {
+          return { endpoint, value: await fetch(connection) };
+        } catch (error) {
+          return { endpoint, error };
+        }
+      }),
+    );
+  }
+
+  private async agree<T>(
+    what: string,
+    fetch: (c: Connection) => Promise<T>,
+    key: (value: T) => string,
+    strict: boolean,
+  ): Promise<T> {
+    const results = await this.collect(fetch);
+    const groups = new Map<string, EndpointResult<T>[]>();
+
+    for (const r of results) {
+      if (r.error !== undefined) continue;
+      r.key = key(r.value as T);
+      groups.set(r.key, [...(groups.get(r.key) ?? []), r]);
+    }
+
+    const [winningKey, winners] = [...groups.entries()].sort((a, b) => b[1].length - a[1].length)[0] ?? [
+      undefined,
+      [],
+    ];
+
+    if (winners.length < this.quorum) {
+      throw new RpcQuorumError(
+        what,
+        this.quorum,
+        winners.length,
+        results.filter((r) => r.error !== undefined).map((r) => r.endpoint),
+      );
+    }
+
+    const outliers = results.filter((r) => r.error === undefined && r.key !== winningKey).map((r) => r.endpoint);
+    if (outliers.length > 0) {
+      const error = new RpcOutlierError(what, outliers);
+      if (strict) throw error;
+      this.onOutlier?.(error);
+    }
+
+    return winners[0].value as T;
+  }
+}
@@ -0,0 +1,226 @@
+import {
+  BPS_DENOMINATOR,
+  DEFAULT_RELAYER_FEE_CAPS_BPS,
+  LAMPORTS_PER_SIGNATURE,
+  PROTOCOL_FEE_BPS,
+  WITHDRAW_DENOMINATIONS,
+} from './core/constants';
+
+/**
+ * Note selection
+ *
+ * Plans a withdrawal of `targetAmount` from a wallet's notes. Withdrawals
+ * come in fixed denominations, so the target is split greedily into
+ * denominations and each one is paid by an unshield. An unshield spends a
+ * whole note and re-shields the rest as a change note, which later
+ * unshields in the plan draw on first. A denomination larger than every
+ * available note needs consolidate steps before it: private_transfers
+ * merging two notes into one.
+ *
+ * Strategies:
+ * - MinimizeProofs: spend the largest notes first, for the fewest
+ *   consolidations and so the fewest proofs
+ * - MinimizeChange: spend the smallest note that covers each
+ *   denomination, leaving the least value behind in change
+ * - MaximizeAgeDiversity: draw notes from as many deposit epochs
+ *   (DEPOSIT_EPOCH_SLOTS buckets of the shield slot) as possible, so a
+ *   withdrawal does not tie together notes shielded in the same burst
+ */
+
+export type SelectionStrategy = 'MinimizeProofs' | 'MinimizeChange' | 'MaximizeAgeDiversity';
+
+/** Shield slots are bucketed into deposit epochs of this many slots (~1 hour) */
+export const DEPOSIT_EPOCH_SLOTS = BigInt(9_000);
+
+export interface SpendableNote {
+  /** Wallet's identifier for the note, e.g. its commitment in hex */
+  id: string;
+  amount: bigint;
+  /** Slot of the shield that created the note */
+  depositSlot: bigint;
+}
+
+/**
+ * One transaction of a plan. Notes created by the plan get ids
+ * `merged-<n>` and `change-<n>`.
+ */
+export type SpendStep =
+  | { kind: 'consolidate'; inputs: [string, string]; output: string; amount: bigint }
+  | { kind: 'unshield'; note: string; withdrawalAmount: bigint; change: string | null; changeAmount: bigint };
+
+export interface PlanCosts {
+  /** One proof per step */
+  proofs: number;
+  protocolFees: bigint;
+  relayerFees: bigint;
+  networkFees: bigint;
+  total: bigint;
+}
+
+export interface SpendPlan {
+  strategy: SelectionStrategy;
+  targetAmount: bigint;
+  /** Wallet notes the plan spends */
+  selected: string[];
+  steps: SpendStep[];
+  /** Value left in change notes once the plan has run */
+  remainingChange: bigint;
+  costs: PlanCosts;
+}
+
+/** Fee inputs for plan costs (see WhistleClient.planWithdrawal) */
+export interface FeeEstimate {
+  /** Relayer fee charged for a withdrawal of `denomination` */
+  relayerFee(denomination: bigint): bigint;
+  /** Network fee per transaction */
+  transactionFee: bigint;
+}
+
+/** Relayers at the default fee caps, one signature per transaction */
+export const DEFAULT_FEE_ESTIMATE: FeeEstimate = {
+  relayerFee: (denomination) => {
+    const i = WITHDRAW_DENOMINATIONS.indexOf(denomination as (typeof WITHDRAW_DENOMINATIONS)[number]);
+    return (denomination * BigInt(DEFAULT_RELAYER_FEE_CAPS_BPS[i])) / BPS_DENOMINATOR;
+  },
+  transactionFee: LAMPORTS_PER_SIGNATURE,
+};
+
+interface OpenNote {
+  id: string;
+  amount: bigint;
+}
+
+/** `amount` as withdrawal denominations, largest first */
+export function splitIntoDenominations(amount: bigint): bigint[] {
+  const pieces: bigint[] = [];
+  let rest = amount;
+  for (const denomination of [...WITHDRAW_DENOMINATIONS].reverse()) {
+    while (rest >= denomination) {
+      pieces.push(denomination);
+      rest -= denomination;
+    }
+  }
+  if (rest !== BigInt(0) || pieces.length === 0) {
+    throw new Error('Target amount is not a sum of withdrawal denominations');
+  }
+  return pieces;
+}
+
+function depositEpoch(note: SpendableNote): bigint {
+  return note.depositSlot / DEPOSIT_EPOCH_SLOTS;
+}
+
+const byAmountDesc = (a: { amount: bigint }, b: { amount: bigint }) => (a.amount > b.amount ? -1 : a.amount < b.amount ? 1 : 0);
+
+/** Candidate order for `strategy`; age diversity interleaves epochs, largest notes first */
+function orderNotes(notes: SpendableNote[], strategy: SelectionStrategy): SpendableNote[] {
+  const desc = [...notes].sort(byAmountDesc);
+  if (strategy === 'MinimizeProofs') return desc;
+  if (strategy === 'MinimizeChange') return desc.reverse();
+
+  const epochs = new Map<bigint, SpendableNote[]>();
+  for (const note of desc) {
+    const epoch = depositEpoch(note);
+    epochs.set(epoch, [...(epochs.get(epoch) ?? []), note]);
+  }
+  const queues = [...epochs.values()];
+  const ordered: SpendableNote[] = [];
+  for (let round = 0; ordered.length < notes.length; round++) {
+    for (const queue of queues) {
+      if (round < queue.length) ordered.push(queue[round]);
+    }
+  }
+  return ordered;
+}
+
+/**
+ * Plan a withdrawal of `targetAmount` from `notes`
+ *
+ * Every plan covers the target and spends each note at most once; throws
+ * if the notes cannot cover it or it is not a sum of denominations.
+ */
+export function selectNotes(
+  notes: SpendableNote[],
+  targetAmount: bigint,
+  strategy: SelectionStrategy,
+  fees: FeeEstimate = DEFAULT_FEE_ESTIMATE
+): SpendPlan {
+  if (new Set(notes.map((note) => note.id)).size !== notes.length) {
+    throw new Error('Duplicate note ids');
+  }
+  const pieces = splitIntoDenominations(targetAmount);
+  const pending = orderNotes(notes.filter((note) => note.amount > BigInt(0)), strategy);
+  const usedEpochs = new Set<bigint>();
+  const selected: SpendableNote[] = [];
+  const open: OpenNote[] = [];
+  const steps: SpendStep[] = [];
+  let created = 0;
+
+  const take = (note: SpendableNote): OpenNote => {
+    pending.splice(pending.indexOf(note), 1);
+    selected.push(note);
+    usedEpochs.add(depositEpoch(note));
+    const entry = { id: note.id, amount: note.amount };
+    open.push(entry);
+    return entry;
+  };
+
+  // Next wallet note to select: among `candidates`, a fresh epoch first
+  // when diversifying, otherwise the first in strategy order
+  const next = (candidates: SpendableNote[]): SpendableNote | undefined =>
+    (strategy === 'MaximizeAgeDiversity' && candidates.find((note) => !usedEpochs.has(depositEpoch(note)))) ||
+    candidates[0];
+
+  for (const piece of pieces) {
+    // Notes already drawn on come first: spending their change links nothing new
+    const covering = open.filter((note) => note.amount >= piece);
+    let source: OpenNote | undefined =
+      strategy === 'MinimizeChange' ? covering.sort(byAmountDesc)[covering.length - 1] : covering[0];
+
+    if (!source) {
+      const note = next(pending.filter((note) => note.amount >= piece));
+      if (note) source = take(note);
+    }
+
+    // No single note covers the denomination: merge the two largest open
+    // notes, selecting more wallet notes while fewer than two are open
+    while (!source) {
+      if (open.length < 2) {
+        const note = strategy === 'MaximizeAgeDiversity' ? next(pending) : [...pending].sort(byAmountDesc)[0];
+        if (!note) throw new Error('Notes do not cover the target amount');
+        take(note);
+        continue;
+      }
+      open.sort(byAmountDesc);
+      const [a, b] = open.splice(0, 2);
+      const merged = { id: `merged-${++created}`, amount: a.amount + b.amount };
+      steps.push({ kind: 'consolidate', inputs: [a.id, b.id], output: merged.id, amount: merged.amount });
+      open.push(merged);
+      if (merged.amount >= piece) source = merged;
+    }
+
+    open.splice(open.indexOf(source), 1);
+    const changeAmount = source.amount - piece;
+    const change = changeAmount > BigInt(0) ? { id: `change-${++created}`, amount: changeAmount } : null;
+    if (change) open.push(change);
+    steps.push({ kind: 'unshield', note: source.id, withdrawalAmount: piece, change: change?.id ?? null, changeAmount });
+  }
+
+  const protocolFees = pieces.reduce((sum, piece) => sum + (piece * PROTOCOL_FEE_BPS) / BPS_DENOMINATOR, BigInt(0));
+  const relayerFees = pieces.reduce((sum, piece) => sum + fees.relayerFee(piece), BigInt(0));
+  const networkFees = BigInt(steps.length) * fees.transactionFee;
+  return {
+    strategy,
+    targetAmount,
+    selected: selected.map((note) => note.id),
+    steps,
+    remainingChange: selected.reduce((sum, note) => sum + note.amount, BigInt(0)) - targetAmount,
+    costs: {
+      proofs: steps.length,
+      protocolFees,
+      relayerFees,
+      networkFees,
+      total: protocolFees + relayerFees + networkFees,
+    },
+  };
+}
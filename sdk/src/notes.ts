@@ -0,0 +1,58 @@
+// @ts-ignore
+import { buildPoseidon } from 'circomlibjs';
+import { BN254_SCALAR_MODULUS } from './publicInputs';
+
+/**
+ * Note commitments, as opened by the withdraw_merkle, unshield_change and
+ * private_transfer circuits (circuits/lib/note_commitment.circom)
+ *
+ * Standard notes commit to Poseidon(secret, Poseidon(nullifier, amount)).
+ * A time-locked note also commits to the slot it unlocks at:
+ * Poseidon(secret, Poseidon(nullifier, amount), unlockSlot). Every spend
+ * proof takes that slot as a public input and the pool rejects the spend
+ * with NoteStillLocked until it is reached, so a note can be gifted now and
+ * claimed later. Zero means no lock.
+ */
+
+/** Commitment of a note, big-endian; `unlockSlot` of zero is a standard note */
+export async function noteCommitment(
+  secret: bigint,
+  nullifier: bigint,
+  amount: bigint,
+  unlockSlot: bigint = BigInt(0)
+): Promise<Buffer> {
+  const poseidon = await buildPoseidon();
+  const F = poseidon.F;
+  const hash = (...inputs: bigint[]) => BigInt(F.toString(poseidon(inputs.map((input) => F.e(input.toString())))));
+  const inner = hash(nullifier, amount);
+  const commitment = unlockSlot === BigInt(0) ? hash(secret, inner) : hash(secret, inner, unlockSlot);
+  return Buffer.from(commitment.toString(16).padStart(64, '0'), 'hex');
+}
+
+/**
+ * Pairs verify_commitment_compatibility hashes (COMMITMENT_COMPATIBILITY_INPUTS
+ * in the program): 32 zero bytes, 32 0x01 bytes, and r - 1
+ */
+export const COMMITMENT_COMPATIBILITY_INPUTS: [bigint, bigint][] = (() => {
+  const zero = BigInt(0);
+  const ones = BigInt('0x' + '01'.repeat(32));
+  const rMinus1 = BN254_SCALAR_MODULUS - BigInt(1);
+  return [
+    [zero, ones],
+    [ones, zero],
+    [rMinus1, rMinus1],
+  ];
+})();
+
+/**
+ * This SDK's Poseidon hashes of COMMITMENT_COMPATIBILITY_INPUTS, big-endian,
+ * as verify_commitment_compatibility expects them
+ */
+export async function commitmentCompatibilityVectors(): Promise<Buffer[]> {
+  const poseidon = await buildPoseidon();
+  const F = poseidon.F;
+  return COMMITMENT_COMPATIBILITY_INPUTS.map(([left, right]) => {
+    const hash = BigInt(F.toString(poseidon([F.e(left.toString()), F.e(right.toString())])));
+    return Buffer.from(hash.toString(16).padStart(64, '0'), 'hex');
+  });
+}
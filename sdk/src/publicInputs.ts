@@ -0,0 +1,55 @@
+/**
+ * Field element encodings
+ *
+ * The program compares commitments, nullifier hashes and proof inputs as
+ * 32 byte big-endian values. snarkjs prints field elements as decimal strings
+ * and circom witness dumps are little-endian, so convert explicitly.
+ * Mirrors crates/whistle-groth16/src/inputs.rs, shared by the on-chain programs.
+ */
+
+/** BN254 scalar field modulus r */
+export const BN254_SCALAR_MODULUS = BigInt(
+  '21888242871839275222246405745257275088548364400416034343698204186575808495617'
+);
+
+/**
+ * Little-endian field element bytes to big-endian
+ */
+export function fieldLeToBe(le: Uint8Array): Buffer {
+  if (le.length !== 32) {
+    throw new Error(`Field element must be 32 bytes, got ${le.length}`);
+  }
+  return Buffer.from(le).reverse();
+}
+
+/**
+ * Big-endian field element bytes to little-endian
+ */
+export function fieldBeToLe(be: Uint8Array): Buffer {
+  if (be.length !== 32) {
+    throw new Error(`Field element must be 32 bytes, got ${be.length}`);
+  }
+  return Buffer.from(be).reverse();
+}
+
+/**
+ * Parse a snarkjs decimal string (or bigint) into 32 big-endian bytes
+ */
+export function decimalStrToBeBytes(decimal: string | bigint): Buffer {
+  if (typeof decimal === 'string' && !/^[0-9]+$/.test(decimal)) {
+    throw new Error(`Not a decimal field element: ${decimal}`);
+  }
+  const value = BigInt(decimal);
+  if (value < BigInt(0) || value >> BigInt(256) !== BigInt(0)) {
+    throw new Error(`Value does not fit in 32 bytes: ${decimal}`);
+  }
+  return Buffer.from(value.toString(16).padStart(64, '0'), 'hex');
+}
+
+/**
+ * Whether big-endian `be` is below the field modulus (the on-chain check
+ * applied to commitments and nullifier hashes)
+ */
+export function isCanonicalFieldElement(be: Uint8Array): boolean {
+  return BigInt('0x' + Buffer.from(be).toString('hex')) < BN254_SCALAR_MODULUS;
+}
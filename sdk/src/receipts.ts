@@ -0,0 +1,88 @@
+import { createHash } from 'crypto';
+import { PublicKey } from '@solana/web3.js';
+// @ts-ignore
+import { buildPoseidon } from 'circomlibjs';
+
+/**
+ * Withdrawal receipts
+ *
+ * Every `Unshielded` event carries
+ * receipt_hash = Poseidon(nullifierHash, recipient, amount, slot), where
+ * `amount` is what the recipient received. A merchant can confirm a payout
+ * against an order by recomputing it. Must match `receipt_hash` in
+ * programs/whistle-pool/src/lib.rs.
+ */
+
+/** Unshielded event fields, as emitted by the pool */
+export interface UnshieldedEvent {
+  nullifierHash: Uint8Array;
+  withdrawalAmount: bigint;
+  protocolFee: bigint;
+  hasChange: boolean;
+  recipientIsPda: boolean;
+  selfRelayed: boolean;
+  receiptHash: Uint8Array;
+  slot: bigint;
+  timestamp: bigint;
+}
+
+const UNSHIELDED_DISCRIMINATOR = createHash('sha256').update('event:Unshielded').digest().subarray(0, 8);
+
+// 32 byte value as a field element: its first 31 bytes (like the recipient public input)
+function truncatedField(bytes: Uint8Array): bigint {
+  return BigInt('0x' + Buffer.from(bytes.subarray(0, 31)).toString('hex'));
+}
+
+/**
+ * Decode an Unshielded event from a transaction log line's
+ * "Program data: <base64>" payload; null for any other event
+ */
+export function decodeUnshieldedEvent(data: Buffer): UnshieldedEvent | null {
+  if (data.length < 107 || !data.subarray(0, 8).equals(UNSHIELDED_DISCRIMINATOR)) {
+    return null;
+  }
+  return {
+    nullifierHash: new Uint8Array(data.subarray(8, 40)),
+    withdrawalAmount: data.readBigUInt64LE(40),
+    protocolFee: data.readBigUInt64LE(48),
+    hasChange: data[56] !== 0,
+    recipientIsPda: data[57] !== 0,
+    selfRelayed: data[58] !== 0,
+    receiptHash: new Uint8Array(data.subarray(59, 91)),
+    slot: data.readBigUInt64LE(91),
+    timestamp: data.readBigInt64LE(99),
+  };
+}
+
+/**
+ * Poseidon(nullifierHash, recipient, amount, slot), big-endian
+ */
+export async function receiptHash(
+  nullifierHash: Uint8Array,
+  recipient: PublicKey,
+  amount: bigint,
+  slot: bigint
+): Promise<Buffer> {
+  const poseidon = await buildPoseidon();
+  const F = poseidon.F;
+  const inputs = [truncatedField(nullifierHash), truncatedField(recipient.toBytes()), amount, slot];
+  const hash = BigInt(F.toString(poseidon(inputs.map((input) => F.e(input.toString())))));
+  return Buffer.from(hash.toString(16).padStart(64, '0'), 'hex');
+}
+
+/**
+ * Whether `event` is a payout of `expectedAmount` to `expectedRecipient`
+ * with receipt `receipt`
+ */
+export async function verifyReceipt(
+  receipt: Uint8Array,
+  expectedRecipient: PublicKey,
+  expectedAmount: bigint,
+  event: UnshieldedEvent
+): Promise<boolean> {
+  if (!Buffer.from(receipt).equals(Buffer.from(event.receiptHash))) {
+    return false;
+  }
+  const expected = await receiptHash(event.nullifierHash, expectedRecipient, expectedAmount, event.slot);
+  return expected.equals(Buffer.from(receipt));
+}
@@ -0,0 +1,70 @@
+import { createPrivateKey, createPublicKey, sign, verify } from 'crypto';
+import { Keypair, PublicKey } from '@solana/web3.js';
+
+/**
+ * Denomination swap intents
+ *
+ * A maker offers a note worth `fromDenomination` (`fromCommitment`, built
+ * from secrets the taker handed over) for a note worth `toDenomination`
+ * (`toCommitment`, built from the maker's own secrets). The intent is
+ * signed off-chain; execute_denomination_swap lands both parties'
+ * private_transfer legs atomically (see TransactionBuilder.executeDenominationSwap).
+ *
+ * The program cannot see note amounts. Before handing over its leg, each
+ * party checks that the commitment it receives opens to the agreed
+ * denomination under its own secrets. Each leg is also a valid
+ * private_transfer on its own, so whoever assembles the swap could submit
+ * only the leg that pays them: assemble it yourself or trust the assembler.
+ */
+
+export interface SwapIntent {
+  fromCommitment: Uint8Array;
+  fromDenomination: bigint;
+  toDenomination: bigint;
+  toCommitment: Uint8Array;
+}
+
+const SWAP_INTENT_DOMAIN = Buffer.from('whistle-denomination-swap');
+
+// DER prefixes for raw Ed25519 keys (PKCS#8 private seed, SPKI public key)
+const ED25519_PKCS8_PREFIX = Buffer.from('302e020100300506032b657004220420', 'hex');
+const ED25519_SPKI_PREFIX = Buffer.from('302a300506032b6570032100', 'hex');
+
+function u64(value: bigint): Buffer {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(value);
+  return buf;
+}
+
+/** Borsh encoding of the intent, as passed to execute_denomination_swap */
+export function encodeSwapIntent(intent: SwapIntent): Buffer {
+  return Buffer.concat([
+    Buffer.from(intent.fromCommitment),
+    u64(intent.fromDenomination),
+    u64(intent.toDenomination),
+    Buffer.from(intent.toCommitment),
+  ]);
+}
+
+/**
+ * Ed25519 signature over the intent; sign with a key unconnected to the
+ * maker's deposits, since the intent is broadcast
+ */
+export function signSwapIntent(intent: SwapIntent, signer: Keypair): Buffer {
+  const privateKey = createPrivateKey({
+    key: Buffer.concat([ED25519_PKCS8_PREFIX, Buffer.from(signer.secretKey.subarray(0, 32))]),
+    format: 'der',
+    type: 'pkcs8',
+  });
+  return sign(null, Buffer.concat([SWAP_INTENT_DOMAIN, encodeSwapIntent(intent)]), privateKey);
+}
+
+/** Whether `signature` is `maker`'s signature over the intent */
+export function verifySwapIntent(intent: SwapIntent, maker: PublicKey, signature: Uint8Array): boolean {
+  const publicKey = createPublicKey({
+    key: Buffer.concat([ED25519_SPKI_PREFIX, maker.toBuffer()]),
+    format: 'der',
+    type: 'spki',
+  });
+  return verify(null, Buffer.concat([SWAP_INTENT_DOMAIN, encodeSwapIntent(intent)]), publicKey, signature);
+}
@@ -0,0 +1,1035 @@
+import {
+  Connection,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+  VersionedTransaction,
+} from '@solana/web3.js';
+import { createHash } from 'crypto';
+import { POOL_PROGRAM_ID } from './client';
+import { LAMPORTS_PER_SIGNATURE, LEAF_PAGE_SIZE, MAX_REBUILD_NODES } from './core/constants';
+import { decimalStrToBeBytes } from './publicInputs';
+import { buildTransaction } from './lookupTable';
+import { SwapIntent, encodeSwapIntent } from './swaps';
+
+// BN254 base field, for negating proof_a
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new PublicKey('BPFLoaderUpgradeab1e11111111111111111111111');
+
+const BN254_BASE_FIELD = BigInt('21888242871839275222246405745257275088696311157297823662689037894645226208583');
+
+/** Groth16 proof in the byte layout the on-chain verifier expects */
+export interface EncodedProof {
+  proofA: Buffer;
+  proofB: Buffer;
+  proofC: Buffer;
+}
+
+export interface UnshieldParams {
+  proof: EncodedProof;
+  nullifierHash: Uint8Array;
+  recipient: PublicKey;
+  withdrawalAmount: bigint;
+  relayerFee: bigint;
+  merkleRoot: Uint8Array;
+  /**
+   * Zero when the note is spent in full. Derive the change note's secret
+   * and nullifier from fresh randomness, never from the spent note: the
+   * program rejects a commitment equal to `nullifierHash` or to
+   * Poseidon(nullifierHash, nullifierHash) with WeakChangeCommitment, and
+   * any other derivation links the two notes if it ever leaks.
+   */
+  changeCommitment: Uint8Array;
+  /** Slot a time-locked note unlocks at, as committed in the note (defaults to 0, no lock) */
+  unlockSlot?: bigint;
+  /** Account paid the relayer fee (defaults to the recipient) */
+  relayer?: PublicKey;
+}
+
+/**
+ * Four withdrawals to one recipient under one batch_withdraw proof. Every
+ * amount is a withdrawal denomination and every note is a standard note
+ * (no time lock); the relayer fee is taken once from the total.
+ */
+export interface BatchWithdrawParams {
+  proof: EncodedProof;
+  nullifierHashes: [Uint8Array, Uint8Array, Uint8Array, Uint8Array];
+  amounts: [bigint, bigint, bigint, bigint];
+  recipient: PublicKey;
+  relayerFee: bigint;
+  merkleRoot: Uint8Array;
+  /** Account paid the relayer fee (defaults to the recipient) */
+  relayer?: PublicKey;
+}
+
+/** One note of a batch_unshield, proven with its own unshield proof */
+export interface BatchUnshieldNote {
+  proof: EncodedProof;
+  nullifierHash: Uint8Array;
+  withdrawalAmount: bigint;
+  relayerFee: bigint;
+  /** Zero when the note is spent in full (see UnshieldParams.changeCommitment) */
+  changeCommitment: Uint8Array;
+}
+
+/**
+ * Up to four standard notes (no time lock) withdrawn to one recipient
+ * against one root; the relayer fees are paid out together. At 336 bytes
+ * per note, a transaction only fits two notes, with the pool accounts in an
+ * address lookup table.
+ */
+export interface BatchUnshieldParams {
+  notes: BatchUnshieldNote[];
+  recipient: PublicKey;
+  merkleRoot: Uint8Array;
+  /** Account paid the relayer fees (defaults to the recipient) */
+  relayer?: PublicKey;
+}
+
+/**
+ * One deposit split into eight standard notes under one batch_shield proof;
+ * the note amounts sum to `totalAmount`
+ */
+export interface BatchShieldParams {
+  proof: EncodedProof;
+  commitments: Uint8Array[];
+  totalAmount: bigint;
+  depositor: PublicKey;
+  /** The pool's current next_index, where the first note lands */
+  nextIndex: bigint;
+}
+
+/** Unshield submitted by the user: no relayer, no relayer fee */
+export type SelfUnshieldParams = Omit<UnshieldParams, 'relayerFee' | 'relayer'>;
+
+export interface SelfSubmittedUnshield {
+  /** self_unshield transaction; the submitter signs it and pays its fees */
+  transaction: Transaction;
+  /**
+   * Suggested follow-up once the withdrawal lands: the recipient repays the
+   * submitter's fees. It links the two accounts on-chain, so only send it
+   * when that link is acceptable. Null when the submitter is the recipient.
+   */
+  reimbursement: TransactionInstruction | null;
+}
+
+export interface PrivateTransferParams {
+  proof: EncodedProof;
+  inputNullifierHashes: [Uint8Array, Uint8Array];
+  outputCommitments: [Uint8Array, Uint8Array];
+  merkleRoot: Uint8Array;
+  /** Unlock slot of each input note (defaults to [0, 0], no locks) */
+  unlockSlots?: [bigint, bigint];
+}
+
+/** One party's private_transfer inside a denomination swap */
+export type TransferLeg = Omit<PrivateTransferParams, 'merkleRoot'>;
+
+export interface DenominationSwapParams {
+  intent: SwapIntent;
+  /** Creates intent.fromCommitment */
+  makerLeg: TransferLeg;
+  /** Creates intent.toCommitment */
+  takerLeg: TransferLeg;
+  /** Root both legs were proven against */
+  merkleRoot: Uint8Array;
+}
+
+function encodeTransferLeg(leg: TransferLeg): Buffer {
+  return Buffer.concat([
+    leg.proof.proofA,
+    leg.proof.proofB,
+    leg.proof.proofC,
+    ...leg.inputNullifierHashes.map((h) => Buffer.from(h)),
+    ...leg.outputCommitments.map((c) => Buffer.from(c)),
+    ...(leg.unlockSlots ?? [BigInt(0), BigInt(0)]).map(u64),
+  ]);
+}
+
+function encodeBatchUnshieldNote(note: BatchUnshieldNote): Buffer {
+  return Buffer.concat([
+    note.proof.proofA,
+    note.proof.proofB,
+    note.proof.proofC,
+    Buffer.from(note.nullifierHash),
+    u64(note.withdrawalAmount),
+    u64(note.relayerFee),
+    Buffer.from(note.changeCommitment),
+  ]);
+}
+
+function instructionDiscriminator(name: string): Buffer {
+  return createHash('sha256').update(`global:${name}`).digest().subarray(0, 8);
+}
+
+/** sha256 of a commitment, as bound by pre_commit_shield */
+export function preCommitHash(commitment: Uint8Array): Buffer {
+  return createHash('sha256').update(commitment).digest();
+}
+
+function u64(value: bigint): Buffer {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(value);
+  return buf;
+}
+
+/**
+ * Convert a snarkjs Groth16 proof to the on-chain layout
+ * (proof_a negated, proof_b coordinates swapped)
+ */
+export function encodeProof(proof: { pi_a: string[]; pi_b: string[][]; pi_c: string[] }): EncodedProof {
+  const y = BigInt(proof.pi_a[1]) % BN254_BASE_FIELD;
+  const yNeg = y === BigInt(0) ? y : BN254_BASE_FIELD - y;
+  const [[x0, x1], [y0, y1]] = proof.pi_b;
+
+  return {
+    proofA: Buffer.concat([decimalStrToBeBytes(proof.pi_a[0]), decimalStrToBeBytes(yNeg)]),
+    proofB: Buffer.concat([x1, x0, y1, y0].map(decimalStrToBeBytes)),
+    proofC: Buffer.concat([decimalStrToBeBytes(proof.pi_c[0]), decimalStrToBeBytes(proof.pi_c[1])]),
+  };
+}
+
+/**
+ * Builds pool transactions with the program's current account lists
+ */
+export class TransactionBuilder {
+  readonly programId: PublicKey;
+  /** Address lookup table used by `compile` (see lookupTable.ts) */
+  readonly lookupTable?: PublicKey;
+
+  constructor(programId: PublicKey = POOL_PROGRAM_ID, lookupTable?: PublicKey) {
+    this.programId = programId;
+    this.lookupTable = lookupTable;
+  }
+
+  /**
+   * Compile a built transaction to v0 through the lookup table, or to a
+   * legacy transaction when no table is configured or it is unavailable
+   */
+  async compile(connection: Connection, payer: PublicKey, tx: Transaction): Promise<VersionedTransaction | Transaction> {
+    return buildTransaction(connection, payer, tx.instructions, this.lookupTable);
+  }
+
+  /**
+   * Derive a pool PDA from its seed
+   */
+  pda(seed: string): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync([Buffer.from(seed)], this.programId);
+    return pda;
+  }
+
+  /**
+   * Per-depositor record counted against the pool's deposit cap
+   */
+  depositRecordAddress(depositor: PublicKey): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('deposit_record'), depositor.toBuffer()],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Marker account a spend of `nullifierHash` creates
+   */
+  nullifierMarkerAddress(nullifierHash: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('nullifier'), Buffer.from(nullifierHash)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Marker account recording the leaf `commitment` was inserted at
+   */
+  commitmentMarkerAddress(commitment: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('commitment'), Buffer.from(commitment)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Leaf page `pageIndex`, holding leaves from pageIndex * LEAF_PAGE_SIZE
+   */
+  leafPageAddress(pageIndex: number): PublicKey {
+    const page = Buffer.alloc(4);
+    page.writeUInt32LE(pageIndex);
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('leaf_page'), this.pda('pool').toBuffer(), page],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Mapping from an Ethereum address to the account its EIP-712
+   * authorized unshields pay
+   */
+  ethMappingAddress(ethAddress: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('eth_address'), Buffer.from(ethAddress)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Record of whether the program's upgrade authority is burned
+   */
+  finalityAttestationAddress(): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('finality_attestation'), this.pda('pool').toBuffer()],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Attest whether the program is still upgradeable, creating or refreshing
+   * its finality attestation; anyone can send it
+   */
+  attestFinality(attester: PublicKey): Transaction {
+    const [programData] = PublicKey.findProgramAddressSync(
+      [this.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+    );
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pda('pool'), isSigner: false, isWritable: false },
+          { pubkey: this.finalityAttestationAddress(), isSigner: false, isWritable: true },
+          { pubkey: programData, isSigner: false, isWritable: false },
+          { pubkey: attester, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('attest_finality'),
+      })
+    );
+  }
+
+  /**
+   * Open tree root dispute of `challenger` (one per challenger)
+   */
+  treeDisputeAddress(challenger: PublicKey): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('tree_dispute'), challenger.toBuffer()],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Dispute the published root over the 256-leaf range at `rangeStart`,
+   * posting `bond` (at least 0.1 SOL)
+   */
+  challengeTreeRoot(challenger: PublicKey, claimedCorrectRoot: Uint8Array, rangeStart: bigint, bond: bigint): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pda('pool'), isSigner: false, isWritable: false },
+          { pubkey: this.treeDisputeAddress(challenger), isSigner: false, isWritable: true },
+          { pubkey: challenger, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('challenge_tree_root'),
+          Buffer.from(claimedCorrectRoot),
+          u64(rangeStart),
+          u64(bond),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Defend the root disputed by `challenger`, taking its bond; fails unless
+   * the tree still reproduces the disputed root. Hashing a full range needs
+   * ~250k CU, so add a compute budget instruction on large trees.
+   */
+  resolveDispute(challenger: PublicKey, responder: PublicKey): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pda('pool'), isSigner: false, isWritable: false },
+          { pubkey: this.pda('merkle_tree'), isSigner: false, isWritable: false },
+          { pubkey: this.treeDisputeAddress(challenger), isSigner: false, isWritable: true },
+          { pubkey: challenger, isSigner: false, isWritable: true },
+          { pubkey: responder, isSigner: true, isWritable: true },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('resolve_dispute'),
+      })
+    );
+  }
+
+  /**
+   * Return the bond of a dispute left undefended past its response window
+   */
+  expireTreeDispute(challenger: PublicKey): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.treeDisputeAddress(challenger), isSigner: false, isWritable: true },
+          { pubkey: challenger, isSigner: false, isWritable: true },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('expire_tree_dispute'),
+      })
+    );
+  }
+
+  /**
+   * Shield `amount` lamports under `commitment`, landing at leaf `nextIndex`
+   * (the pool's current next_index)
+   */
+  shield(depositor: PublicKey, commitment: Uint8Array, amount: bigint, nextIndex: bigint): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pda('pool'), isSigner: false, isWritable: true },
+          { pubkey: this.pda('merkle_tree'), isSigner: false, isWritable: true },
+          { pubkey: this.pda('roots_history'), isSigner: false, isWritable: true },
+          { pubkey: this.pda('pool_stats'), isSigner: false, isWritable: true },
+          { pubkey: this.pda('deposit_histogram'), isSigner: false, isWritable: true },
+          { pubkey: this.pda('vault'), isSigner: false, isWritable: true },
+          { pubkey: this.pda('fee_vault'), isSigner: false, isWritable: true },
+          { pubkey: depositor, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: this.depositRecordAddress(depositor), isSigner: false, isWritable: true },
+          {
+            pubkey: this.leafPageAddress(Number(nextIndex / BigInt(LEAF_PAGE_SIZE))),
+            isSigner: false,
+            isWritable: true,
+          },
+          { pubkey: this.commitmentMarkerAddress(commitment), isSigner: false, isWritable: true },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('shield'), Buffer.from(commitment), u64(amount)]),
+      })
+    );
+  }
+
+  /**
+   * Shield eight notes with one proof (pools built with the
+   * `batch-shield-zk` feature only; others fail with VerifyingKeyNotGenerated)
+   */
+  shieldBatch(params: BatchShieldParams): Transaction {
+    if (params.commitments.length !== 8) {
+      throw new Error(`shield_batch_zk takes 8 commitments, got ${params.commitments.length}`);
+    }
+    const { proof } = params;
+    const [shieldIx] = this.shield(params.depositor, params.commitments[0], params.totalAmount, params.nextIndex)
+      .instructions;
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          ...shieldIx.keys,
+          ...params.commitments.slice(1).map((commitment) => ({
+            pubkey: this.commitmentMarkerAddress(commitment),
+            isSigner: false,
+            isWritable: true,
+          })),
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('shield_batch_zk'),
+          proof.proofA,
+          proof.proofB,
+          proof.proofC,
+          ...params.commitments.map((commitment) => Buffer.from(commitment)),
+          u64(params.totalAmount),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Pre-commit PDA for `payer` and a commitment hash
+   */
+  preCommitAddress(payer: PublicKey, commitmentHash: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('pre_commit'), payer.toBuffer(), Buffer.from(commitmentHash)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Stake PRE_COMMIT_STAKE behind the hash of `commitment`; reveal with
+   * `revealShield` within PRE_COMMIT_REVEAL_SLOTS of `depositSlot`
+   */
+  preCommitShield(payer: PublicKey, commitment: Uint8Array, depositSlot: bigint): Transaction {
+    const commitmentHash = preCommitHash(commitment);
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.preCommitAddress(payer, commitmentHash), isSigner: false, isWritable: true },
+          { pubkey: payer, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('pre_commit_shield'), commitmentHash, u64(depositSlot)]),
+      })
+    );
+  }
+
+  /**
+   * Shield a pre-committed note, refunding the stake to the depositor
+   */
+  revealShield(depositor: PublicKey, commitment: Uint8Array, amount: bigint, nextIndex: bigint): Transaction {
+    const [shieldIx] = this.shield(depositor, commitment, amount, nextIndex).instructions;
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          {
+            pubkey: this.preCommitAddress(depositor, preCommitHash(commitment)),
+            isSigner: false,
+            isWritable: true,
+          },
+          { pubkey: depositor, isSigner: true, isWritable: true },
+          ...shieldIx.keys,
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('reveal_shield'), Buffer.from(commitment), u64(amount)]),
+      })
+    );
+  }
+
+  /**
+   * Take the stake of a pre-commit whose reveal window has passed
+   */
+  claimExpiredPreCommit(preCommit: PublicKey, payer: PublicKey, claimer: PublicKey): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: preCommit, isSigner: false, isWritable: true },
+          { pubkey: payer, isSigner: false, isWritable: true },
+          { pubkey: claimer, isSigner: true, isWritable: true },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('claim_expired_pre_commit'),
+      })
+    );
+  }
+
+  /**
+   * Accounts of unshield and self_unshield; the program id stands in for an
+   * omitted relayer. `payer` signs and pays the nullifier and change
+   * markers' rent; spends without change pass the zero commitment.
+   */
+  private unshieldKeys(
+    nullifierHash: Uint8Array,
+    changeCommitment: Uint8Array,
+    recipient: PublicKey,
+    relayer: PublicKey | null,
+    payer: PublicKey
+  ) {
+    return [
+      { pubkey: this.pda('pool'), isSigner: false, isWritable: true },
+      { pubkey: this.pda('merkle_tree'), isSigner: false, isWritable: true },
+      { pubkey: this.pda('nullifiers'), isSigner: false, isWritable: false },
+      { pubkey: this.pda('roots_history'), isSigner: false, isWritable: true },
+      { pubkey: this.pda('denomination_config'), isSigner: false, isWritable: false },
+      { pubkey: this.pda('vault'), isSigner: false, isWritable: true },
+      { pubkey: this.pda('fee_vault'), isSigner: false, isWritable: true },
+      { pubkey: recipient, isSigner: false, isWritable: true },
+      relayer
+        ? { pubkey: relayer, isSigner: false, isWritable: true }
+        : { pubkey: this.programId, isSigner: false, isWritable: false },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      { pubkey: this.pda('congestion'), isSigner: false, isWritable: true },
+      { pubkey: this.nullifierMarkerAddress(nullifierHash), isSigner: false, isWritable: true },
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: this.commitmentMarkerAddress(changeCommitment), isSigner: false, isWritable: true },
+    ];
+  }
+
+  /**
+   * Accounts of private_transfer; `payer` signs and pays the input and
+   * output markers' rent
+   */
+  private transferKeys(
+    inputNullifierHashes: [Uint8Array, Uint8Array],
+    outputCommitments: [Uint8Array, Uint8Array],
+    payer: PublicKey
+  ) {
+    return [
+      { pubkey: this.pda('pool'), isSigner: false, isWritable: true },
+      { pubkey: this.pda('merkle_tree'), isSigner: false, isWritable: true },
+      { pubkey: this.pda('nullifiers'), isSigner: false, isWritable: false },
+      { pubkey: this.pda('roots_history'), isSigner: false, isWritable: true },
+      ...inputNullifierHashes.map((hash) => ({
+        pubkey: this.nullifierMarkerAddress(hash),
+        isSigner: false,
+        isWritable: true,
+      })),
+      ...outputCommitments.map((commitment) => ({
+        pubkey: this.commitmentMarkerAddress(commitment),
+        isSigner: false,
+        isWritable: true,
+      })),
+      { pubkey: payer, isSigner: true, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ];
+  }
+
+  /**
+   * Withdraw a fixed denomination from a note, re-shielding the change;
+   * `payer` submits it and pays the nullifier marker's rent
+   */
+  unshield(params: UnshieldParams, payer: PublicKey): Transaction {
+    const { proof } = params;
+    const relayer = params.relayer || params.recipient;
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: this.unshieldKeys(params.nullifierHash, params.changeCommitment, params.recipient, relayer, payer),
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('unshield'),
+          proof.proofA,
+          proof.proofB,
+          proof.proofC,
+          Buffer.from(params.nullifierHash),
+          params.recipient.toBuffer(),
+          u64(params.withdrawalAmount),
+          u64(params.relayerFee),
+          Buffer.from(params.merkleRoot),
+          Buffer.from(params.changeCommitment),
+          u64(params.unlockSlot ?? BigInt(0)),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Map `ethAddress` to `authorized`; `ethSignature` (r || s || v) signs
+   * computeRegisterHash(authorized, nonce) with the mapping's current
+   * nonce. `payer` pays the mapping's rent.
+   */
+  registerEthAddress(
+    payer: PublicKey,
+    ethAddress: Uint8Array,
+    authorized: PublicKey,
+    ethSignature: Uint8Array
+  ): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.ethMappingAddress(ethAddress), isSigner: false, isWritable: true },
+          { pubkey: payer, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('register_eth_address'),
+          Buffer.from(ethAddress),
+          authorized.toBuffer(),
+          Buffer.from(ethSignature),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Unshield authorized by an Ethereum wallet: `ethSignature` signs
+   * computeEip712Hash(params), and `params.recipient` must be the account
+   * `ethAddress` is mapped to. `payer` submits it and pays the nullifier
+   * marker's rent.
+   */
+  unshieldEip712(params: UnshieldParams, ethSignature: Uint8Array, ethAddress: Uint8Array, payer: PublicKey): Transaction {
+    const { proof } = params;
+    const relayer = params.relayer || params.recipient;
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.ethMappingAddress(ethAddress), isSigner: false, isWritable: false },
+          ...this.unshieldKeys(params.nullifierHash, params.changeCommitment, params.recipient, relayer, payer),
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('unshield_eip712'),
+          proof.proofA,
+          proof.proofB,
+          proof.proofC,
+          Buffer.from(params.nullifierHash),
+          params.recipient.toBuffer(),
+          u64(params.withdrawalAmount),
+          u64(params.relayerFee),
+          Buffer.from(params.merkleRoot),
+          Buffer.from(params.changeCommitment),
+          u64(params.unlockSlot ?? BigInt(0)),
+          Buffer.from(ethSignature),
+          Buffer.from(ethAddress),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Withdraw four notes with one proof; `payer` submits it and pays the
+   * four nullifier markers' rent (pools built with the `batch-withdraw-zk`
+   * feature only; others fail with VerifyingKeyNotGenerated)
+   */
+  batchWithdraw(params: BatchWithdrawParams, payer: PublicKey): Transaction {
+    const { proof } = params;
+    const [first, ...rest] = params.nullifierHashes;
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          ...this.unshieldKeys(first, new Uint8Array(32), params.recipient, params.relayer || params.recipient, payer),
+          ...rest.map((hash) => ({ pubkey: this.nullifierMarkerAddress(hash), isSigner: false, isWritable: true })),
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('batch_withdraw_zk'),
+          proof.proofA,
+          proof.proofB,
+          proof.proofC,
+          ...params.nullifierHashes.map((hash) => Buffer.from(hash)),
+          ...params.amounts.map(u64),
+          params.recipient.toBuffer(),
+          u64(params.relayerFee),
+          Buffer.from(params.merkleRoot),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Withdraw several notes, each with its own proof; `payer` submits it and
+   * pays the nullifier and change markers' rent
+   */
+  batchUnshield(params: BatchUnshieldParams, payer: PublicKey): Transaction {
+    const [first, ...rest] = params.notes;
+    const count = Buffer.alloc(4);
+    count.writeUInt32LE(params.notes.length);
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          ...this.unshieldKeys(
+            first.nullifierHash,
+            first.changeCommitment,
+            params.recipient,
+            params.relayer || params.recipient,
+            payer
+          ),
+          ...rest.map((note) => ({
+            pubkey: this.nullifierMarkerAddress(note.nullifierHash),
+            isSigner: false,
+            isWritable: true,
+          })),
+          ...rest.map((note) => ({
+            pubkey: this.commitmentMarkerAddress(note.changeCommitment),
+            isSigner: false,
+            isWritable: true,
+          })),
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('batch_unshield'),
+          count,
+          ...params.notes.map(encodeBatchUnshieldNote),
+          Buffer.from(params.merkleRoot),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Unshield without a relayer, for when relayers refuse the recipient
+   *
+   * The proof must be generated with relayerFee = 0. `submitter` pays the
+   * transaction fees (one signature plus `priorityFee`) and the nullifier
+   * marker's rent; `reimbursement` suggests how the recipient can repay the
+   * fees afterwards.
+   */
+  selfSubmitUnshield(
+    params: SelfUnshieldParams,
+    submitter: PublicKey,
+    priorityFee: bigint = BigInt(0)
+  ): SelfSubmittedUnshield {
+    const { proof } = params;
+    const transaction = new Transaction().add(
+      new TransactionInstruction({
+        keys: this.unshieldKeys(params.nullifierHash, params.changeCommitment, params.recipient, null, submitter),
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('self_unshield'),
+          proof.proofA,
+          proof.proofB,
+          proof.proofC,
+          Buffer.from(params.nullifierHash),
+          params.recipient.toBuffer(),
+          u64(params.withdrawalAmount),
+          Buffer.from(params.merkleRoot),
+          Buffer.from(params.changeCommitment),
+          u64(params.unlockSlot ?? BigInt(0)),
+        ]),
+      })
+    );
+    transaction.feePayer = submitter;
+
+    const reimbursement = submitter.equals(params.recipient)
+      ? null
+      : SystemProgram.transfer({
+          fromPubkey: params.recipient,
+          toPubkey: submitter,
+          lamports: LAMPORTS_PER_SIGNATURE + priorityFee,
+        });
+    return { transaction, reimbursement };
+  }
+
+  /**
+   * rebuild_root calls that recompute every internal Merkle node from the
+   * stored leaves, children first; send them in order
+   *
+   * Repairs a pool whose instructions fail with TreeStateDesync. Anyone
+   * can send them.
+   */
+  rebuildRoot(merkleLevels: number): Transaction[] {
+    const transactions: Transaction[] = [];
+    for (let endNode = 2 ** merkleLevels - 1; endNode > 0; endNode -= MAX_REBUILD_NODES) {
+      const args = Buffer.alloc(6);
+      args.writeUInt32LE(endNode, 0);
+      args.writeUInt16LE(Math.min(endNode, MAX_REBUILD_NODES), 4);
+      transactions.push(
+        new Transaction().add(
+          new TransactionInstruction({
+            keys: [
+              { pubkey: this.pda('pool'), isSigner: false, isWritable: true },
+              { pubkey: this.pda('merkle_tree'), isSigner: false, isWritable: true },
+              { pubkey: this.pda('roots_history'), isSigner: false, isWritable: true },
+            ],
+            programId: this.programId,
+            data: Buffer.concat([instructionDiscriminator('rebuild_root'), args]),
+          })
+        )
+      );
+    }
+    return transactions;
+  }
+
+  /**
+   * Copy leaves inserted outside `shield` (batched shields, change notes,
+   * auction settlements) into leaf page `pageIndex`, creating it if needed
+   */
+  syncLeafPage(payer: PublicKey, pageIndex: number): Transaction {
+    const page = Buffer.alloc(4);
+    page.writeUInt32LE(pageIndex);
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pda('pool'), isSigner: false, isWritable: false },
+          { pubkey: this.pda('merkle_tree'), isSigner: false, isWritable: false },
+          { pubkey: this.leafPageAddress(pageIndex), isSigner: false, isWritable: true },
+          { pubkey: payer, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('sync_leaf_page'), page]),
+      })
+    );
+  }
+
+  /**
+   * Bond posted by a relayer against inactivity slashing
+   */
+  relayerBondAddress(relayer: PublicKey): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('relayer_bond'), relayer.toBuffer()],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Withdrawal a relayer approved, keyed by the note's nullifier hash
+   */
+  pendingWithdrawalAddress(nullifierHash: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('pending_withdrawal'), Buffer.from(nullifierHash)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Post or top up `relayer`'s bond by `amount` lamports
+   */
+  registerRelayer(relayer: PublicKey, amount: bigint): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.relayerBondAddress(relayer), isSigner: false, isWritable: true },
+          { pubkey: relayer, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('register_relayer'), u64(amount)]),
+      })
+    );
+  }
+
+  /**
+   * Record that `relayer` accepted the withdrawal of `nullifierHash`
+   *
+   * Both sign: the user builds the transaction and the relayer co-signs it
+   * when it takes the job. The user's wallet is linked to the withdrawal
+   * once it lands, so use one unconnected to the deposit.
+   */
+  approveRelayerWithdrawal(user: PublicKey, relayer: PublicKey, nullifierHash: Uint8Array): Transaction {
+    const transaction = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pendingWithdrawalAddress(nullifierHash), isSigner: false, isWritable: true },
+          { pubkey: this.relayerBondAddress(relayer), isSigner: false, isWritable: false },
+          { pubkey: this.pda('nullifiers'), isSigner: false, isWritable: false },
+          { pubkey: this.nullifierMarkerAddress(nullifierHash), isSigner: false, isWritable: false },
+          { pubkey: relayer, isSigner: true, isWritable: false },
+          { pubkey: user, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: this.pda('congestion'), isSigner: false, isWritable: true },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('approve_relayer_withdrawal'), Buffer.from(nullifierHash)]),
+      })
+    );
+    transaction.feePayer = user;
+    return transaction;
+  }
+
+  /**
+   * Slash `relayer` for leaving an approved withdrawal unsubmitted past
+   * RELAYER_EXECUTION_TIMEOUT_SLOTS; closes the approval so another
+   * relayer can be approved
+   */
+  proveRelayerInactivity(user: PublicKey, relayer: PublicKey, nullifierHash: Uint8Array): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.pendingWithdrawalAddress(nullifierHash), isSigner: false, isWritable: true },
+          { pubkey: this.relayerBondAddress(relayer), isSigner: false, isWritable: true },
+          { pubkey: this.pda('nullifiers'), isSigner: false, isWritable: false },
+          { pubkey: this.nullifierMarkerAddress(nullifierHash), isSigner: false, isWritable: false },
+          { pubkey: user, isSigner: true, isWritable: true },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('prove_relayer_inactivity'), relayer.toBuffer()]),
+      })
+    );
+  }
+
+  /**
+   * Intent lock a submitter node takes on a nullifier hash
+   */
+  intentLockAddress(nullifierHash: Uint8Array): PublicKey {
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('intent_lock'), Buffer.from(nullifierHash)],
+      this.programId
+    );
+    return pda;
+  }
+
+  /**
+   * Lock `nullifierHash` for `holder` before submitting its withdrawal
+   *
+   * Send it (or simulate it) first: IntentLocked means another node has
+   * the withdrawal, so skip it. The lock lapses after INTENT_LOCK_TTL_SLOTS.
+   */
+  takeIntentLock(holder: PublicKey, nullifierHash: Uint8Array): Transaction {
+    const transaction = new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.intentLockAddress(nullifierHash), isSigner: false, isWritable: true },
+          { pubkey: this.pda('nullifiers'), isSigner: false, isWritable: false },
+          { pubkey: this.nullifierMarkerAddress(nullifierHash), isSigner: false, isWritable: false },
+          { pubkey: holder, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data: Buffer.concat([instructionDiscriminator('take_intent_lock'), Buffer.from(nullifierHash)]),
+      })
+    );
+    transaction.feePayer = holder;
+    return transaction;
+  }
+
+  /**
+   * Close the intent lock on `nullifierHash`, refunding `rentPayer` (the
+   * node that created it); `closer` must hold the lock unless it has lapsed
+   */
+  releaseIntentLock(nullifierHash: Uint8Array, rentPayer: PublicKey, closer: PublicKey): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          { pubkey: this.intentLockAddress(nullifierHash), isSigner: false, isWritable: true },
+          { pubkey: rentPayer, isSigner: false, isWritable: true },
+          { pubkey: closer, isSigner: true, isWritable: false },
+        ],
+        programId: this.programId,
+        data: instructionDiscriminator('release_intent_lock'),
+      })
+    );
+  }
+
+  /**
+   * Spend two notes into two new notes inside the pool; `payer` submits it
+   * and pays the nullifier markers' rent
+   */
+  privateTransfer(params: PrivateTransferParams, payer: PublicKey): Transaction {
+    const { proof } = params;
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: this.transferKeys(params.inputNullifierHashes, params.outputCommitments, payer),
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('private_transfer'),
+          proof.proofA,
+          proof.proofB,
+          proof.proofC,
+          ...params.inputNullifierHashes.map((h) => Buffer.from(h)),
+          ...params.outputCommitments.map((c) => Buffer.from(c)),
+          Buffer.from(params.merkleRoot),
+          ...(params.unlockSlots ?? [BigInt(0), BigInt(0)]).map(u64),
+        ]),
+      })
+    );
+  }
+
+  /**
+   * Land both legs of a denomination swap in one instruction; `payer`
+   * submits it and pays the nullifier markers' rent
+   */
+  executeDenominationSwap(params: DenominationSwapParams, payer: PublicKey): Transaction {
+    return new Transaction().add(
+      new TransactionInstruction({
+        keys: [
+          ...this.transferKeys(params.makerLeg.inputNullifierHashes, params.makerLeg.outputCommitments, payer),
+          ...params.takerLeg.inputNullifierHashes.map((hash) => ({
+            pubkey: this.nullifierMarkerAddress(hash),
+            isSigner: false,
+            isWritable: true,
+          })),
+          ...params.takerLeg.outputCommitments.map((commitment) => ({
+            pubkey: this.commitmentMarkerAddress(commitment),
+            isSigner: false,
+            isWritable: true,
+          })),
+        ],
+        programId: this.programId,
+        data: Buffer.concat([
+          instructionDiscriminator('execute_denomination_swap'),
+          encodeSwapIntent(params.intent),
+          encodeTransferLeg(params.makerLeg),
+          encodeTransferLeg(params.takerLeg),
+          Buffer.from(params.merkleRoot),
+        ]),
+      })
+    );
+  }
+}
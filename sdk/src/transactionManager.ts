@@ -0,0 +1,117 @@
+import {
+  Commitment,
+  Connection,
+  Signer,
+  Transaction,
+  TransactionExpiredBlockheightExceededError,
+  TransactionSignature,
+} from '@solana/web3.js';
+
+/**
+ * Transaction submission with retries for congested periods
+ *
+ * A retry re-signs the transaction on a fresh blockhash, so it is only made
+ * when the previous attempt provably cannot land: the RPC rejected its
+ * blockhash, or the blockhash expired before it confirmed. Any other failure
+ * (a program error, or a transport error after which the transaction may
+ * still land) is thrown, so a shield is never submitted twice.
+ */
+
+export interface TransactionManagerConfig {
+  connection: Connection;
+  /** Commitment to confirm at (default 'confirmed') */
+  commitment?: Commitment;
+  /** Retries after the first attempt (default 3) */
+  maxRetries?: number;
+  /** Delay before the first retry, doubled for each later one (default 500) */
+  baseDelayMs?: number;
+}
+
+/**
+ * The transaction failed in simulation, so its compute units are unknown
+ */
+export class SimulationFailedError extends Error {
+  constructor(
+    public readonly err: unknown,
+    public readonly logs: string[],
+  ) {
+    super(`Simulation failed: ${JSON.stringify(err)}`);
+    this.name = 'SimulationFailedError';
+  }
+}
+
+// RPC error for a blockhash it does not (yet / any longer) know
+function isBlockhashNotFound(error: unknown): boolean {
+  return error instanceof Error && /blockhash not found/i.test(error.message);
+}
+
+function sleep(ms: number): Promise<void> {
+  return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+export class TransactionManager {
+  readonly connection: Connection;
+  readonly commitment: Commitment;
+  readonly maxRetries: number;
+  readonly baseDelayMs: number;
+
+  constructor(config: TransactionManagerConfig) {
+    this.connection = config.connection;
+    this.commitment = config.commitment ?? 'confirmed';
+    this.maxRetries = config.maxRetries ?? 3;
+    this.baseDelayMs = config.baseDelayMs ?? 500;
+  }
+
+  /**
+   * Sign `tx` with `signers` on a fresh blockhash, send it and wait for
+   * confirmation, retrying with exponential backoff while it fails with
+   * BlockhashNotFound or expires unconfirmed
+   *
+   * The fee payer defaults to the first signer.
+   */
+  async sendAndConfirmWithRetry(tx: Transaction, signers: Signer[]): Promise<TransactionSignature> {
+    for (let attempt = 0; ; attempt++) {
+      const { blockhash, lastValidBlockHeight } = await this.connection.getLatestBlockhash(this.commitment);
+      tx.recentBlockhash = blockhash;
+      tx.lastValidBlockHeight = lastValidBlockHeight;
+      tx.feePayer = tx.feePayer ?? signers[0].publicKey;
+      tx.sign(...signers);
+
+      try {
+        const signature = await this.connection.sendRawTransaction(tx.serialize(), {
+          preflightCommitment: this.commitment,
+        });
+        const { value } = await this.connection.confirmTransaction(
+          { signature, blockhash, lastValidBlockHeight },
+          this.commitment
+        );
+        if (value.err) {
+          throw new Error(`Transaction ${signature} failed: ${JSON.stringify(value.err)}`);
+        }
+        return signature;
+      } catch (error) {
+        const retryable = isBlockhashNotFound(error) || error instanceof TransactionExpiredBlockheightExceededError;
+        if (!retryable || attempt >= this.maxRetries) {
+          throw error;
+        }
+        await sleep(this.baseDelayMs * 2 ** attempt);
+      }
+    }
+  }
+
+  /**
+   * Compute units `tx` consumes, from a simulation on the latest blockhash
+   * (signatures are not checked, so `tx` may be unsigned, but it needs its
+   * fee payer)
+   */
+  async estimateComputeUnits(tx: Transaction): Promise<number> {
+    const { value } = await this.connection.simulateTransaction(tx);
+    if (value.err) {
+      throw new SimulationFailedError(value.err, value.logs ?? []);
+    }
+    if (value.unitsConsumed === undefined) {
+      throw new Error('RPC did not report compute units');
+    }
+    return value.unitsConsumed;
+  }
+}
@@ -0,0 +1,178 @@
+import { describe, expect, it, vi } from 'vitest';
+import { AccountInfo, ConfirmedSignatureInfo, Connection, PublicKey } from '@solana/web3.js';
+import { MultiRpc, RpcContinuityError, RpcOutlierError, RpcQuorumError } from '../src/multiRpc';
+
+const ADDRESS = new PublicKey(new Uint8Array(32).fill(7));
+const OWNER = new PublicKey(new Uint8Array(32).fill(9));
+
+function account(data: number[]): AccountInfo<Buffer> {
+  return { data: Buffer.from(data), executable: false, lamports: 1_000_000, owner: OWNER, rentEpoch: 0 };
+}
+
+function signatures(...names: string[]): ConfirmedSignatureInfo[] {
+  // getSignaturesForAddress answers newest first
+  return names.reverse().map((signature, slot) => ({ signature, slot, err: null, memo: null, blockTime: null }));
+}
+
+/** What a fake endpoint answers: a value, or an error for an endpoint that is down */
+type Answer<T> = T | Error;
+
+const DOWN = new Error('fetch failed');
+
+function endpoint(
+  name: string,
+  accountInfo: Answer<AccountInfo<Buffer> | null>,
+  history: Answer<ConfirmedSignatureInfo[]> = [],
+): Connection {
+  const answer = <T>(value: Answer<T>) => (value instanceof Error ? Promise.reject(value) : Promise.resolve(value));
+  return {
+    rpcEndpoint: name,
+    getAccountInfo: vi.fn(() => answer(accountInfo)),
+    getSignaturesForAddress: vi.fn(() => answer(history)),
+  } as unknown as Connection;
+}
+
+describe('MultiRpc failover', () => {
+  it('reads through an unreachable endpoint when the others reach quorum', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [endpoint('a', DOWN), endpoint('b', account([1, 2])), endpoint('c', account([1, 2]))],
+    });
+    const onOutlier = vi.fn();
+    rpc.onOutlier = onOutlier;
+
+    const info = await rpc.getAccountInfo(ADDRESS, true);
+    expect(info?.data).toEqual(Buffer.from([1, 2]));
+    // Being down is not disagreeing
+    expect(onOutlier).not.toHaveBeenCalled();
+  });
+
+  it('falls back to a later endpoint with a quorum of one', async () => {
+    const rpc = new MultiRpc({ endpoints: [endpoint('a', DOWN), endpoint('b', account([3]))], quorum: 1 });
+    expect((await rpc.getAccountInfo(ADDRESS))?.data).toEqual(Buffer.from([3]));
+  });
+
+  it('backfills history past an unreachable endpoint', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [
+        endpoint('a', null, DOWN),
+        endpoint('b', null, signatures('s1', 's2')),
+        endpoint('c', null, signatures('s1', 's2')),
+      ],
+    });
+    const history = await rpc.getSignatureHistory(ADDRESS, undefined, 1000, true);
+    expect(history.map((s) => s.signature)).toEqual(['s1', 's2']);
+  });
+});
+
+describe('MultiRpc quorum disagreement', () => {
+  it('returns the majority view and reports the outlier', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [endpoint('a', account([1])), endpoint('b', account([1])), endpoint('forger', account([6]))],
+    });
+    const onOutlier = vi.fn();
+    rpc.onOutlier = onOutlier;
+
+    expect((await rpc.getAccountInfo(ADDRESS))?.data).toEqual(Buffer.from([1]));
+    expect(onOutlier).toHaveBeenCalledOnce();
+    const [error] = onOutlier.mock.calls[0];
+    expect(error).toBeInstanceOf(RpcOutlierError);
+    expect(error.outlierEndpoints).toEqual(['forger']);
+  });
+
+  it('throws on an outlier in strict mode', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [endpoint('a', account([1])), endpoint('b', account([1])), endpoint('forger', account([6]))],
+    });
+    await expect(rpc.getAccountInfo(ADDRESS, true)).rejects.toBeInstanceOf(RpcOutlierError);
+  });
+
+  it('throws when no answer reaches quorum', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [endpoint('a', account([1])), endpoint('b', account([2])), endpoint('c', account([3]))],
+    });
+    const error = await rpc.getAccountInfo(ADDRESS).catch((e) => e);
+    expect(error).toBeInstanceOf(RpcQuorumError);
+    expect(error.largestAgreement).toBe(1);
+    expect(error.quorum).toBe(2);
+    expect(error.failedEndpoints).toEqual([]);
+  });
+
+  it('throws on an even split below an explicit quorum', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [
+        endpoint('a', account([1])),
+        endpoint('b', account([1])),
+        endpoint('c', account([2])),
+        endpoint('d', account([2])),
+      ],
+      quorum: 3,
+    });
+    await expect(rpc.getAccountInfo(ADDRESS)).rejects.toBeInstanceOf(RpcQuorumError);
+  });
+
+  it('only compares the requested slice', async () => {
+    const rpc = new MultiRpc({
+      endpoints: [endpoint('a', account([1, 2, 3])), endpoint('b', account([1, 2, 9]))],
+      quorum: 2,
+    });
+    expect(await rpc.getAccountSlice(ADDRESS, 0, 2, true)).toEqual(Buffer.from([1, 2]));
+    await expect(rpc.getAccountSlice(ADDRESS, 1, 2, true)).rejects.toBeInstanceOf(RpcQuorumError);
+  });
+
+  it('flags an endpoint that drops a transaction from history', async () => {
+    const endpoints = () => [
+      endpoint('a', null, signatures('s1', 's2', 's3')),
+      endpoint('b', null, signatures('s1', 's2', 's3')),
+      endpoint('gap', null, signatures('s1', 's3')),
+    ];
+
+    const rpc = new MultiRpc({ endpoints: endpoints() });
+    const onOutlier = vi.fn();
+    rpc.onOutlier = onOutlier;
+    const history = await rpc.getSignatureHistory(ADDRESS);
+    expect(history.map((s) => s.signature)).toEqual(['s1', 's2', 's3']);
+    expect(onOutlier).toHaveBeenCalledOnce();
+    expect(onOutlier.mock.calls[0][0].outlierEndpoints).toEqual(['gap']);
+
+    const error = await new MultiRpc({ endpoints: endpoints() })
+      .getSignatureHistory(ADDRESS, undefined, 1000, true)
+      .catch((e) => e);
+    expect(error).toBeInstanceOf(RpcContinuityError);
+    expect(error.endpoint).toBe('gap');
+    expect(error.expected).toBe('s2');
+    expect(error.received).toBe('s3');
+  });
+});
+
+describe('MultiRpc with every endpoint down', () => {
+  const endpoints = () => [endpoint('a', DOWN, DOWN), endpoint('b', DOWN, DOWN), endpoint('c', DOWN, DOWN)];
+
+  it('throws a quorum error naming every endpoint for account reads', async () => {
+    const error = await new MultiRpc({ endpoints: endpoints() }).getAccountInfo(ADDRESS).catch((e) => e);
+    expect(error).toBeInstanceOf(RpcQuorumError);
+    expect(error.largestAgreement).toBe(0);
+    expect(error.failedEndpoints).toEqual(['a', 'b', 'c']);
+  });
+
+  it('throws a quorum error naming every endpoint for history', async () => {
+    const error = await new MultiRpc({ endpoints: endpoints() }).getSignatureHistory(ADDRESS).catch((e) => e);
+    expect(error).toBeInstanceOf(RpcQuorumError);
+    expect(error.failedEndpoints).toEqual(['a', 'b', 'c']);
+  });
+});
+
+describe('MultiRpc configuration', () => {
+  it('rejects an empty endpoint list and a quorum out of range', () => {
+    expect(() => new MultiRpc({ endpoints: [] })).toThrow();
+    expect(() => new MultiRpc({ endpoints: [endpoint('a', null)], quorum: 2 })).toThrow();
+    expect(() => new MultiRpc({ endpoints: [endpoint('a', null)], quorum: 0 })).toThrow();
+  });
+
+  it('defaults to a strict majority', async () => {
+    const split = [account([1]), account([1]), account([2]), account([2])];
+    const rpc = new MultiRpc({ endpoints: split.map((info, i) => endpoint(`e${i}`, info)) });
+    const error = await rpc.getAccountInfo(ADDRESS).catch((e) => e);
+    expect(error).toBeInstanceOf(RpcQuorumError);
+    expect(error.quorum).toBe(3);
+  });
+});
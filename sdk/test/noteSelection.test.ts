@@ -0,0 +1,142 @@
+import { describe, expect, it } from 'vitest';
+import { BPS_DENOMINATOR, PROTOCOL_FEE_BPS, WITHDRAW_DENOMINATIONS } from '../src/core/constants';
+import {
+  DEPOSIT_EPOCH_SLOTS,
+  SelectionStrategy,
+  SpendableNote,
+  selectNotes,
+  splitIntoDenominations,
+} from '../src/noteSelection';
+
+const SOL = BigInt(1_000_000_000);
+const STRATEGIES: SelectionStrategy[] = ['MinimizeProofs', 'MinimizeChange', 'MaximizeAgeDiversity'];
+
+function note(id: string, amount: bigint, depositSlot = BigInt(0)): SpendableNote {
+  return { id, amount, depositSlot };
+}
+
+/** Lamports in `sol` hundredths of a SOL */
+function cents(sol: number): bigint {
+  return (SOL * BigInt(sol)) / BigInt(100);
+}
+
+describe('selectNotes exact match', () => {
+  it.each(STRATEGIES)('spends a note of exactly the target with no change (%s)', (strategy) => {
+    const plan = selectNotes([note('small', cents(10)), note('exact', SOL)], SOL, strategy);
+    expect(plan.selected).toEqual(['exact']);
+    expect(plan.steps).toEqual([
+      { kind: 'unshield', note: 'exact', withdrawalAmount: SOL, change: null, changeAmount: BigInt(0) },
+    ]);
+    expect(plan.remainingChange).toBe(BigInt(0));
+  });
+
+  it('counts one proof and one protocol fee per unshield', () => {
+    const plan = selectNotes([note('a', cents(15))], cents(15), 'MinimizeChange');
+    expect(plan.steps.map((step) => step.kind)).toEqual(['unshield', 'unshield']);
+    expect(plan.costs.proofs).toBe(2);
+    const fee = (amount: bigint) => (amount * PROTOCOL_FEE_BPS) / BPS_DENOMINATOR;
+    expect(plan.costs.protocolFees).toBe(fee(cents(10)) + fee(cents(5)));
+    expect(plan.costs.total).toBe(plan.costs.protocolFees + plan.costs.relayerFees + plan.costs.networkFees);
+  });
+});
+
+describe('selectNotes change minimization', () => {
+  const notes = [note('large', BigInt(10) * SOL), note('mid', cents(120)), note('small', cents(50))];
+
+  it('spends the smallest covering note under MinimizeChange', () => {
+    const plan = selectNotes(notes, SOL, 'MinimizeChange');
+    expect(plan.selected).toEqual(['mid']);
+    expect(plan.remainingChange).toBe(cents(20));
+  });
+
+  it('spends the largest note under MinimizeProofs', () => {
+    const plan = selectNotes(notes, SOL, 'MinimizeProofs');
+    expect(plan.selected).toEqual(['large']);
+    expect(plan.remainingChange).toBe(BigInt(9) * SOL);
+  });
+
+  it('draws later denominations from change before new notes', () => {
+    const plan = selectNotes(notes, cents(110), 'MinimizeChange');
+    expect(plan.selected).toEqual(['mid']);
+    expect(plan.steps).toEqual([
+      { kind: 'unshield', note: 'mid', withdrawalAmount: SOL, change: 'change-1', changeAmount: cents(20) },
+      { kind: 'unshield', note: 'change-1', withdrawalAmount: cents(10), change: 'change-2', changeAmount: cents(10) },
+    ]);
+    expect(plan.remainingChange).toBe(cents(10));
+  });
+
+  it('never leaves more change than MinimizeProofs', () => {
+    for (const target of [cents(5), cents(60), SOL, cents(161), BigInt(10) * SOL]) {
+      const minimized = selectNotes(notes, target, 'MinimizeChange');
+      const proofs = selectNotes(notes, target, 'MinimizeProofs');
+      expect(minimized.remainingChange).toBeLessThanOrEqual(proofs.remainingChange);
+    }
+  });
+
+  it('spreads notes across deposit epochs under MaximizeAgeDiversity', () => {
+    const epoch = (n: number) => DEPOSIT_EPOCH_SLOTS * BigInt(n);
+    const spread = [
+      note('old-a', cents(60), epoch(0)),
+      note('old-b', cents(60), epoch(0) + BigInt(1)),
+      note('new', cents(50), epoch(1)),
+    ];
+    const plan = selectNotes(spread, SOL, 'MaximizeAgeDiversity');
+    expect(plan.selected).toHaveLength(2);
+    expect(plan.selected).toContain('new');
+    expect(plan.steps[0]).toMatchObject({ kind: 'consolidate', amount: cents(110) });
+  });
+});
+
+describe('selectNotes with insufficient funds', () => {
+  it.each(STRATEGIES)('throws when the notes fall short (%s)', (strategy) => {
+    const notes = [note('a', cents(50)), note('b', cents(30))];
+    expect(() => selectNotes(notes, SOL, strategy)).toThrow('Notes do not cover the target amount');
+  });
+
+  it('throws without notes', () => {
+    expect(() => selectNotes([], cents(1), 'MinimizeProofs')).toThrow('Notes do not cover the target amount');
+  });
+
+  it('ignores empty notes', () => {
+    const notes = [note('empty', BigInt(0)), note('a', cents(1))];
+    expect(selectNotes(notes, cents(1), 'MinimizeChange').selected).toEqual(['a']);
+    expect(() => selectNotes([note('empty', BigInt(0))], cents(1), 'MinimizeChange')).toThrow();
+  });
+
+  it('consolidates notes that only cover the target together', () => {
+    const plan = selectNotes([note('a', cents(60)), note('b', cents(40))], SOL, 'MinimizeProofs');
+    expect(plan.steps).toEqual([
+      { kind: 'consolidate', inputs: ['a', 'b'], output: 'merged-1', amount: SOL },
+      { kind: 'unshield', note: 'merged-1', withdrawalAmount: SOL, change: null, changeAmount: BigInt(0) },
+    ]);
+    expect(plan.costs.proofs).toBe(2);
+  });
+});
+
+describe('denomination edge cases', () => {
+  it('splits into denominations largest first', () => {
+    expect(splitIntoDenominations(cents(15))).toEqual([cents(10), cents(5)]);
+    expect(splitIntoDenominations(cents(11116))).toEqual([...WITHDRAW_DENOMINATIONS].reverse());
+    expect(splitIntoDenominations(cents(2))).toEqual([cents(1), cents(1)]);
+  });
+
+  it('rejects amounts that are not a sum of denominations', () => {
+    for (const amount of [BigInt(0), cents(1) - BigInt(1), cents(1) + BigInt(1), cents(5) + BigInt(1)]) {
+      expect(() => splitIntoDenominations(amount)).toThrow('not a sum of withdrawal denominations');
+      expect(() => selectNotes([note('a', BigInt(100) * SOL)], amount, 'MinimizeProofs')).toThrow(
+        'not a sum of withdrawal denominations',
+      );
+    }
+  });
+
+  it('takes a note of exactly the smallest denomination', () => {
+    const plan = selectNotes([note('dust', cents(1))], cents(1), 'MinimizeChange');
+    expect(plan.steps).toEqual([
+      { kind: 'unshield', note: 'dust', withdrawalAmount: cents(1), change: null, changeAmount: BigInt(0) },
+    ]);
+  });
+
+  it('rejects duplicate note ids', () => {
+    expect(() => selectNotes([note('a', SOL), note('a', SOL)], SOL, 'MinimizeProofs')).toThrow('Duplicate note ids');
+  });
+});
@@ -0,0 +1,218 @@
+import { readFileSync } from 'fs';
+import { resolve } from 'path';
+import { createHash } from 'crypto';
+import { describe, expect, it } from 'vitest';
+import { AccountMeta, Keypair, PublicKey, SystemProgram, TransactionInstruction } from '@solana/web3.js';
+import { LEAF_PAGE_SIZE } from '../src/core/constants';
+import { EncodedProof, TransactionBuilder } from '../src/transactionBuilder';
+
+// The program's account structs and handler signatures, read from source
+// the way tests/guards.rs reads the handlers' errors
+const LIB_RS = readFileSync(resolve(__dirname, '../../contracts/programs/whistle-pool/src/lib.rs'), 'utf8');
+
+interface AccountField {
+  name: string;
+  writable: boolean;
+  signer: boolean;
+  /** The seed of a PDA derived from one constant seed */
+  seed?: string;
+}
+
+function withoutComments(source: string): string {
+  return source.replace(/\/\/.*$/gm, '');
+}
+
+/** Fields of `#[derive(Accounts)] pub struct name`, in order */
+function accountFields(name: string): AccountField[] {
+  const struct = LIB_RS.match(new RegExp(`pub struct ${name}<'info> \\{([\\s\\S]*?)\\n\\}`));
+  if (!struct) {
+    throw new Error(`No account struct ${name} in lib.rs`);
+  }
+  const fields = withoutComments(struct[1]).matchAll(/(?:#\[account\(([\s\S]*?)\)\]\s*)?pub (\w+): ([^\n]+),/g);
+  return [...fields].map(([, attributes = '', field, type]) => ({
+    name: field,
+    writable: /\b(mut|init|init_if_needed)\b/.test(attributes),
+    signer: type.startsWith('Signer<'),
+    seed: attributes.match(/seeds = \[b"(\w+)"\]/)?.[1],
+  }));
+}
+
+const PRIMITIVE_SIZES: Record<string, number> = { u8: 1, u16: 2, u32: 4, u64: 8, i64: 8, bool: 1, Pubkey: 32 };
+
+function borshSize(type: string): number {
+  const array = type.match(/^\[(.+); (\d+)\]$/);
+  if (array) {
+    return borshSize(array[1]) * Number(array[2]);
+  }
+  if (!(type in PRIMITIVE_SIZES)) {
+    throw new Error(`No size for argument type ${type}`);
+  }
+  return PRIMITIVE_SIZES[type];
+}
+
+/** Arguments of the `name` handler after its context, with their sizes */
+function handlerArgs(name: string): { name: string; size: number }[] {
+  const handler = LIB_RS.match(new RegExp(`pub fn ${name}\\(([\\s\\S]*?)\\)\\s*->`));
+  if (!handler) {
+    throw new Error(`No handler ${name} in lib.rs`);
+  }
+  return withoutComments(handler[1])
+    .split(',')
+    .map((arg) => arg.trim())
+    .filter((arg) => arg && !arg.startsWith('ctx:'))
+    .map((arg) => {
+      const [argName, type] = arg.split(/:\s*/);
+      return { name: argName, size: borshSize(type.trim()) };
+    });
+}
+
+/**
+ * Check `ix` against the program: one key per struct field, in order, with
+ * the field's signer and writable flags and either its PDA or the address
+ * in `addresses`; then the discriminator and each argument at its offset
+ */
+function expectMatchesProgram(
+  ix: TransactionInstruction,
+  builder: TransactionBuilder,
+  handler: string,
+  struct: string,
+  addresses: Record<string, PublicKey>,
+  args: Record<string, Buffer>,
+) {
+  const fields = accountFields(struct);
+  expect(ix.keys).toHaveLength(fields.length);
+  fields.forEach((field, i) => {
+    const key: AccountMeta = ix.keys[i];
+    const address = field.seed ? builder.pda(field.seed) : addresses[field.name];
+    expect(address, `address of ${struct}.${field.name}`).toBeDefined();
+    expect(key.pubkey.toBase58(), `${struct}.${field.name}`).toBe(address.toBase58());
+    expect(key.isSigner, `${struct}.${field.name} signer`).toBe(field.signer);
+    expect(key.isWritable, `${struct}.${field.name} writable`).toBe(field.writable);
+  });
+
+  const discriminator = createHash('sha256').update(`global:${handler}`).digest().subarray(0, 8);
+  expect(ix.data.subarray(0, 8)).toEqual(discriminator);
+  let offset = 8;
+  for (const arg of handlerArgs(handler)) {
+    expect(args[arg.name], `value of ${handler}(${arg.name})`).toBeDefined();
+    expect(args[arg.name], `${handler}(${arg.name})`).toHaveLength(arg.size);
+    expect(ix.data.subarray(offset, offset + arg.size), `${handler}(${arg.name})`).toEqual(args[arg.name]);
+    offset += arg.size;
+  }
+  expect(ix.data).toHaveLength(offset);
+}
+
+/** `length` bytes counting up from `start`, so no two arguments share bytes */
+function bytes(start: number, length: number): Buffer {
+  return Buffer.from(Array.from({ length }, (_, i) => (start + i) % 256));
+}
+
+function u64(value: bigint): Buffer {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(value);
+  return buf;
+}
+
+const proof: EncodedProof = { proofA: bytes(1, 64), proofB: bytes(2, 128), proofC: bytes(3, 64) };
+const proofArgs = { proof_a: proof.proofA, proof_b: proof.proofB, proof_c: proof.proofC };
+
+describe('TransactionBuilder matches the program', () => {
+  const builder = new TransactionBuilder();
+  const payer = Keypair.generate().publicKey;
+
+  it('shield', () => {
+    const commitment = bytes(10, 32);
+    const amount = BigInt(1_000_000_000);
+    // The second leaf of the third page
+    const nextIndex = BigInt(2 * LEAF_PAGE_SIZE + 1);
+    const [ix] = builder.shield(payer, commitment, amount, nextIndex).instructions;
+
+    expectMatchesProgram(
+      ix,
+      builder,
+      'shield',
+      'Shield',
+      {
+        depositor: payer,
+        system_program: SystemProgram.programId,
+        deposit_record: builder.depositRecordAddress(payer),
+        leaf_page: builder.leafPageAddress(2),
+        commitment_marker: builder.commitmentMarkerAddress(commitment),
+      },
+      { commitment, amount: u64(amount) },
+    );
+  });
+
+  it('unshield', () => {
+    const params = {
+      proof,
+      nullifierHash: bytes(20, 32),
+      recipient: Keypair.generate().publicKey,
+      withdrawalAmount: BigInt(100_000_000),
+      relayerFee: BigInt(1_000_000),
+      merkleRoot: bytes(30, 32),
+      changeCommitment: bytes(40, 32),
+      unlockSlot: BigInt(12_345),
+      relayer: Keypair.generate().publicKey,
+    };
+    const [ix] = builder.unshield(params, payer).instructions;
+
+    expectMatchesProgram(
+      ix,
+      builder,
+      'unshield',
+      'Unshield',
+      {
+        recipient: params.recipient,
+        relayer: params.relayer,
+        system_program: SystemProgram.programId,
+        nullifier_marker: builder.nullifierMarkerAddress(params.nullifierHash),
+        payer,
+        change_marker: builder.commitmentMarkerAddress(params.changeCommitment),
+      },
+      {
+        ...proofArgs,
+        nullifier_hash: Buffer.from(params.nullifierHash),
+        recipient: params.recipient.toBuffer(),
+        withdrawal_amount: u64(params.withdrawalAmount),
+        relayer_fee: u64(params.relayerFee),
+        merkle_root: Buffer.from(params.merkleRoot),
+        change_commitment: Buffer.from(params.changeCommitment),
+        unlock_slot: u64(params.unlockSlot),
+      },
+    );
+  });
+
+  it('private_transfer', () => {
+    const params = {
+      proof,
+      inputNullifierHashes: [bytes(50, 32), bytes(60, 32)] as [Buffer, Buffer],
+      outputCommitments: [bytes(70, 32), bytes(80, 32)] as [Buffer, Buffer],
+      merkleRoot: bytes(90, 32),
+      unlockSlots: [BigInt(7), BigInt(8)] as [bigint, bigint],
+    };
+    const [ix] = builder.privateTransfer(params, payer).instructions;
+
+    expectMatchesProgram(
+      ix,
+      builder,
+      'private_transfer',
+      'PrivateTransfer',
+      {
+        input_marker_0: builder.nullifierMarkerAddress(params.inputNullifierHashes[0]),
+        input_marker_1: builder.nullifierMarkerAddress(params.inputNullifierHashes[1]),
+        output_marker_0: builder.commitmentMarkerAddress(params.outputCommitments[0]),
+        output_marker_1: builder.commitmentMarkerAddress(params.outputCommitments[1]),
+        payer,
+        system_program: SystemProgram.programId,
+      },
+      {
+        ...proofArgs,
+        input_nullifier_hashes: Buffer.concat(params.inputNullifierHashes),
+        output_commitments: Buffer.concat(params.outputCommitments),
+        merkle_root: params.merkleRoot,
+        unlock_slots: Buffer.concat(params.unlockSlots.map(u64)),
+      },
+    );
+  });
+});
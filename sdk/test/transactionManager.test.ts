@@ -0,0 +1,147 @@
+import { describe, expect, it, vi } from 'vitest';
+import {
+  Connection,
+  Keypair,
+  SystemProgram,
+  Transaction,
+  TransactionExpiredBlockheightExceededError,
+} from '@solana/web3.js';
+import { SimulationFailedError, TransactionManager } from '../src/transactionManager';
+
+const payer = Keypair.generate();
+
+function transfer(): Transaction {
+  return new Transaction().add(
+    SystemProgram.transfer({ fromPubkey: payer.publicKey, toPubkey: Keypair.generate().publicKey, lamports: 1 }),
+  );
+}
+
+/**
+ * A fake RPC serving a new blockhash per request. `send` and `confirm`
+ * answer each attempt in turn: a value, or an error to throw.
+ */
+function rpc(send: (string | Error)[], confirm: (object | Error)[]) {
+  const blockhashes: string[] = [];
+  const answer = <T>(answers: (T | Error)[], attempt: number) => {
+    const value = answers[Math.min(attempt, answers.length - 1)];
+    return value instanceof Error ? Promise.reject(value) : Promise.resolve(value);
+  };
+  const connection = {
+    getLatestBlockhash: vi.fn(async () => {
+      blockhashes.push(Keypair.generate().publicKey.toBase58());
+      return { blockhash: blockhashes[blockhashes.length - 1], lastValidBlockHeight: 100 + blockhashes.length };
+    }),
+    sendRawTransaction: vi.fn((_raw: Buffer) => answer(send, connection.sendRawTransaction.mock.calls.length - 1)),
+    confirmTransaction: vi.fn(() => answer(confirm, connection.confirmTransaction.mock.calls.length - 1)),
+    simulateTransaction: vi.fn(),
+  };
+  const manager = new TransactionManager({
+    connection: connection as unknown as Connection,
+    maxRetries: 2,
+    baseDelayMs: 0,
+  });
+  return { connection, blockhashes, manager };
+}
+
+const CONFIRMED = { value: { err: null } };
+const BLOCKHASH_NOT_FOUND = new Error('failed to send transaction: Transaction simulation failed: Blockhash not found');
+
+describe('TransactionManager retries', () => {
+  it('re-signs on a fresh blockhash after BlockhashNotFound', async () => {
+    const { connection, blockhashes, manager } = rpc([BLOCKHASH_NOT_FOUND, 'second'], [CONFIRMED]);
+
+    await expect(manager.sendAndConfirmWithRetry(transfer(), [payer])).resolves.toBe('second');
+    expect(connection.sendRawTransaction).toHaveBeenCalledTimes(2);
+    const sent = connection.sendRawTransaction.mock.calls.map(([raw]) => Transaction.from(raw));
+    expect(sent.map((tx) => tx.recentBlockhash)).toEqual(blockhashes);
+    expect(sent.every((tx) => tx.verifySignatures())).toBe(true);
+    expect(connection.confirmTransaction).toHaveBeenCalledWith(
+      { signature: 'second', blockhash: blockhashes[1], lastValidBlockHeight: 102 },
+      'confirmed',
+    );
+  });
+
+  it('gives up after maxRetries', async () => {
+    const { connection, manager } = rpc([BLOCKHASH_NOT_FOUND], [CONFIRMED]);
+    await expect(manager.sendAndConfirmWithRetry(transfer(), [payer])).rejects.toBe(BLOCKHASH_NOT_FOUND);
+    expect(connection.sendRawTransaction).toHaveBeenCalledTimes(3);
+  });
+
+  it('backs off exponentially between attempts', async () => {
+    vi.useFakeTimers();
+    try {
+      const { connection } = rpc([BLOCKHASH_NOT_FOUND, BLOCKHASH_NOT_FOUND, 'third'], [CONFIRMED]);
+      const manager = new TransactionManager({
+        connection: connection as unknown as Connection,
+        maxRetries: 2,
+        baseDelayMs: 100,
+      });
+      const sent = manager.sendAndConfirmWithRetry(transfer(), [payer]);
+
+      await vi.advanceTimersByTimeAsync(99);
+      expect(connection.sendRawTransaction).toHaveBeenCalledTimes(1);
+      await vi.advanceTimersByTimeAsync(1);
+      expect(connection.sendRawTransaction).toHaveBeenCalledTimes(2);
+      await vi.advanceTimersByTimeAsync(199);
+      expect(connection.sendRawTransaction).toHaveBeenCalledTimes(2);
+      await vi.advanceTimersByTimeAsync(1);
+      await expect(sent).resolves.toBe('third');
+    } finally {
+      vi.useRealTimers();
+    }
+  });
+
+  it('does not resend after a program error', async () => {
+    const failed = { value: { err: { InstructionError: [0, { Custom: 6000 }] } } };
+    const { connection, manager } = rpc(['first'], [failed]);
+    await expect(manager.sendAndConfirmWithRetry(transfer(), [payer])).rejects.toThrow('Transaction first failed');
+    expect(connection.sendRawTransaction).toHaveBeenCalledTimes(1);
+  });
+
+  it('does not resend after a transport error, as the transaction may still land', async () => {
+    const transport = new Error('fetch failed');
+    const { connection, manager } = rpc([transport], [CONFIRMED]);
+    await expect(manager.sendAndConfirmWithRetry(transfer(), [payer])).rejects.toBe(transport);
+    expect(connection.sendRawTransaction).toHaveBeenCalledTimes(1);
+  });
+});
+
+describe('TransactionManager blockhash expiry', () => {
+  it('resends on a new blockhash when the first expires unconfirmed', async () => {
+    const expired = new TransactionExpiredBlockheightExceededError('first');
+    const { connection, blockhashes, manager } = rpc(['first', 'second'], [expired, CONFIRMED]);
+
+    await expect(manager.sendAndConfirmWithRetry(transfer(), [payer])).resolves.toBe('second');
+    expect(connection.getLatestBlockhash).toHaveBeenCalledTimes(2);
+    const [first, second] = connection.sendRawTransaction.mock.calls.map(([raw]) => Transaction.from(raw));
+    expect([first.recentBlockhash, second.recentBlockhash]).toEqual(blockhashes);
+    expect(first.signature).not.toEqual(second.signature);
+  });
+});
+
+describe('TransactionManager confirmation timeout', () => {
+  it('throws the expiry once every attempt has timed out', async () => {
+    const { connection, manager } = rpc(
+      ['first', 'second', 'third'],
+      [new TransactionExpiredBlockheightExceededError('first')],
+    );
+    await expect(manager.sendAndConfirmWithRetry(transfer(), [payer])).rejects.toBeInstanceOf(
+      TransactionExpiredBlockheightExceededError,
+    );
+    expect(connection.sendRawTransaction).toHaveBeenCalledTimes(3);
+    expect(connection.confirmTransaction).toHaveBeenCalledTimes(3);
+  });
+});
+
+describe('TransactionManager compute estimates', () => {
+  it('reports simulated compute units and simulation failures', async () => {
+    const { connection, manager } = rpc([], []);
+    connection.simulateTransaction.mockResolvedValueOnce({ value: { err: null, logs: [], unitsConsumed: 4_321 } });
+    await expect(manager.estimateComputeUnits(transfer())).resolves.toBe(4_321);
+
+    connection.simulateTransaction.mockResolvedValueOnce({ value: { err: 'AccountNotFound', logs: ['log'] } });
+    const error = await manager.estimateComputeUnits(transfer()).catch((e) => e);
+    expect(error).toBeInstanceOf(SimulationFailedError);
+    expect(error.logs).toEqual(['log']);
+  });
+});